@@ -0,0 +1,6 @@
+-- Usernames and emails are unique regardless of case, so "Alice" cannot
+-- register next to "alice". Applying this fails while two existing accounts
+-- differ only in case; rename or remove one of them first.
+
+CREATE UNIQUE INDEX idx_users_username_lower ON users (LOWER(username));
+CREATE UNIQUE INDEX idx_users_email_lower ON users (LOWER(email));
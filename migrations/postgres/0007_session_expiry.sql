@@ -0,0 +1,4 @@
+-- Absolute end of the login a refresh token belongs to; rotation carries it
+-- over, so refreshing cannot extend a session past it. Older tokens have
+-- none and get one at their next refresh
+ALTER TABLE refresh_tokens ADD COLUMN session_expires_at TIMESTAMPTZ;
@@ -0,0 +1,3 @@
+-- Archived items are kept out of the default item list without being
+-- trashed; they stay editable and still count toward the item limit
+ALTER TABLE items ADD COLUMN archived BOOLEAN NOT NULL DEFAULT FALSE;
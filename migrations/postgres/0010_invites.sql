@@ -0,0 +1,11 @@
+-- Single-use codes for registering while signups are invite-only; only a
+-- hash of each code is stored
+
+CREATE TABLE invites (
+    id BIGSERIAL PRIMARY KEY,
+    code_hash TEXT NOT NULL UNIQUE,
+    created_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    used_by BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    used_at TIMESTAMPTZ
+);
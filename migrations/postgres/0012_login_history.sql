@@ -0,0 +1,14 @@
+-- When each account last logged in, and a log of recent logins users can
+-- review on their profile page; old events are pruned as new ones arrive
+
+ALTER TABLE users ADD COLUMN last_login_at TIMESTAMPTZ;
+
+CREATE TABLE login_events (
+    id BIGSERIAL PRIMARY KEY,
+    user_id BIGINT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    ip_address TEXT NOT NULL,
+    user_agent TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_login_events_user_id ON login_events(user_id, created_at);
@@ -0,0 +1,8 @@
+-- Secret tokens feed readers send to `/items/feed.xml` in place of a login;
+-- one per user, and only a hash of it is stored
+
+CREATE TABLE feed_tokens (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,12 @@
+-- New email addresses waiting for their owner to follow the confirmation
+-- link; one per user, and an address can be pending for one account only
+
+CREATE TABLE email_changes (
+    user_id BIGINT PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    new_email TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX idx_email_changes_new_email_lower ON email_changes (LOWER(new_email));
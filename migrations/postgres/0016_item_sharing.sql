@@ -0,0 +1,7 @@
+-- Items their owner shares read-only at `/share/{slug}`; the slug is kept
+-- while sharing is off and replaced whenever it is turned off, so old links
+-- never open the item again
+ALTER TABLE items ADD COLUMN shared BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE items ADD COLUMN share_slug TEXT;
+
+CREATE UNIQUE INDEX idx_items_share_slug ON items(share_slug);
@@ -0,0 +1,17 @@
+-- Every change to an item, by kind: who made it, and the item as JSON before
+-- and after. Rows are written in the transaction of the change and outlive
+-- the item, so purged items keep their trail.
+
+CREATE TABLE item_audit (
+    id BIGSERIAL PRIMARY KEY,
+    item_id BIGINT NOT NULL,
+    user_id BIGINT REFERENCES users(id) ON DELETE SET NULL,
+    action TEXT NOT NULL CHECK (action IN (
+        'create', 'update', 'trash', 'restore', 'purge', 'archive', 'unarchive', 'reassign'
+    )),
+    before_json TEXT,
+    after_json TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_item_audit_item_id ON item_audit(item_id, id);
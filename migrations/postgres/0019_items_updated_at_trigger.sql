@@ -0,0 +1,20 @@
+-- Bump items.updated_at on any update that leaves it alone, so write paths
+-- need not remember to. Moving an item in the manual order changes only
+-- position, which is not an edit of the item.
+--
+-- Databases that ran earlier releases may have an older version of the
+-- trigger, created on startup.
+CREATE OR REPLACE FUNCTION items_touch_updated_at() RETURNS trigger AS $$
+BEGIN
+    IF NEW.updated_at IS NOT DISTINCT FROM OLD.updated_at
+        AND NEW.position IS NOT DISTINCT FROM OLD.position THEN
+        NEW.updated_at = CURRENT_TIMESTAMP;
+    END IF;
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS items_touch_updated_at ON items;
+
+CREATE TRIGGER items_touch_updated_at BEFORE UPDATE ON items
+FOR EACH ROW EXECUTE FUNCTION items_touch_updated_at();
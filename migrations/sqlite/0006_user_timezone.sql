@@ -0,0 +1,2 @@
+-- Zone each user's pages show times in; existing users keep UTC
+ALTER TABLE users ADD COLUMN timezone TEXT NOT NULL DEFAULT 'UTC';
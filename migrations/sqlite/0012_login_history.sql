@@ -0,0 +1,15 @@
+-- When each account last logged in, and a log of recent logins users can
+-- review on their profile page; old events are pruned as new ones arrive
+
+ALTER TABLE users ADD COLUMN last_login_at DATETIME;
+
+CREATE TABLE login_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    ip_address TEXT NOT NULL,
+    user_agent TEXT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_login_events_user_id ON login_events(user_id, created_at);
@@ -0,0 +1,9 @@
+-- Secret tokens feed readers send to `/items/feed.xml` in place of a login;
+-- one per user, and only a hash of it is stored
+
+CREATE TABLE feed_tokens (
+    user_id INTEGER PRIMARY KEY,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
@@ -0,0 +1,5 @@
+-- Where each item sits in its owner's manual order, lowest first; items
+-- never placed have none and list before the placed ones, newest first
+ALTER TABLE items ADD COLUMN position INTEGER;
+
+CREATE INDEX idx_items_user_position ON items(user_id, position);
@@ -0,0 +1,13 @@
+-- New email addresses waiting for their owner to follow the confirmation
+-- link; one per user, and an address can be pending for one account only
+
+CREATE TABLE email_changes (
+    user_id INTEGER PRIMARY KEY,
+    new_email TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at DATETIME NOT NULL,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_email_changes_new_email_lower ON email_changes (LOWER(new_email));
@@ -0,0 +1,5 @@
+-- Optional day an item is due; it shows as overdue once that day has passed
+-- in its owner's timezone
+ALTER TABLE items ADD COLUMN due_at DATE;
+
+CREATE INDEX idx_items_user_due_at ON items(user_id, due_at);
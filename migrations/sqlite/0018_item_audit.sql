@@ -0,0 +1,18 @@
+-- Every change to an item, by kind: who made it, and the item as JSON before
+-- and after. Rows are written in the transaction of the change and outlive
+-- the item, so purged items keep their trail.
+
+CREATE TABLE item_audit (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    item_id INTEGER NOT NULL,
+    user_id INTEGER,
+    action TEXT NOT NULL CHECK (action IN (
+        'create', 'update', 'trash', 'restore', 'purge', 'archive', 'unarchive', 'reassign'
+    )),
+    before_json TEXT,
+    after_json TEXT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE SET NULL
+);
+
+CREATE INDEX idx_item_audit_item_id ON item_audit(item_id, id);
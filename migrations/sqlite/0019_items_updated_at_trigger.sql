@@ -0,0 +1,14 @@
+-- Bump items.updated_at after any update that leaves it alone, so write
+-- paths need not remember to. Moving an item in the manual order changes
+-- only position, which is not an edit of the item. SQLite only fires
+-- triggers recursively with recursive_triggers on, and the trigger's own
+-- update changes updated_at, which the WHEN clause skips anyway.
+--
+-- Databases that ran earlier releases may have an older version of the
+-- trigger, created on startup.
+DROP TRIGGER IF EXISTS items_touch_updated_at;
+
+CREATE TRIGGER items_touch_updated_at AFTER UPDATE ON items
+WHEN new.updated_at IS old.updated_at AND new.position IS old.position BEGIN
+    UPDATE items SET updated_at = CURRENT_TIMESTAMP WHERE id = new.id;
+END;
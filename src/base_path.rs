@@ -0,0 +1,83 @@
+/// Path prefix the app is reached under behind a reverse proxy, e.g. `/app`
+///
+/// The proxy strips the prefix before passing requests on, so routes stay
+/// where they are; only URLs sent back to the browser (redirects, links and
+/// cookie paths) need to carry it. Empty when the app is served at `/`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BasePath(String);
+
+/// Why a `BASE_PATH` value was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasePathError(String);
+
+impl std::fmt::Display for BasePathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "BASE_PATH {:?} must be a plain path starting with `/`, e.g. /app",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for BasePathError {}
+
+impl BasePath {
+    /// Parse `BASE_PATH`; trailing slashes are dropped, so `/` and the
+    /// empty string both mean no prefix
+    pub fn parse(value: &str) -> Result<Self, BasePathError> {
+        let path = value.trim().trim_end_matches('/');
+        let plain = path
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '-' | '_' | '.' | '~'));
+        if !path.is_empty() && (!path.starts_with('/') || path.contains("//") || !plain) {
+            return Err(BasePathError(value.to_string()));
+        }
+        Ok(Self(path.to_string()))
+    }
+
+    /// The prefix itself, `""` or e.g. `/app`
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Browser-facing URL of the app's own `path`, which starts with `/`
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.0, path)
+    }
+
+    /// `Path` attribute of the app's cookies
+    pub fn cookie_path(&self) -> &str {
+        if self.0.is_empty() {
+            "/"
+        } else {
+            &self.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_plain_prefixes() {
+        for (value, prefix) in [("", ""), ("/", ""), ("/app", "/app"), (" /app/ ", "/app")] {
+            assert_eq!(BasePath::parse(value).map(|p| p.0), Ok(prefix.to_string()));
+        }
+        for value in ["app", "//evil.example", "/a b", "/app?x=1", "/\"app"] {
+            assert!(BasePath::parse(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn prefixes_urls_and_cookie_paths() {
+        let root = BasePath::default();
+        assert_eq!(root.url("/items"), "/items");
+        assert_eq!(root.cookie_path(), "/");
+
+        let app = BasePath::parse("/app").expect("parse");
+        assert_eq!(app.url("/items?page=2"), "/app/items?page=2");
+        assert_eq!(app.cookie_path(), "/app");
+    }
+}
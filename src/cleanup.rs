@@ -0,0 +1,25 @@
+use chrono::Utc;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+
+use crate::db::Database;
+
+/// Default wait between sweeps for expired tokens: one hour
+pub const DEFAULT_TOKEN_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Delete expired tokens every `period`, for as long as the server runs
+///
+/// The first sweep runs right away. A failed sweep is logged and the next
+/// one tried on schedule, so the database being away never stops the task.
+pub async fn run(db: Database, period: Duration) {
+    let mut ticks = tokio::time::interval(period);
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticks.tick().await;
+        match db.purge_expired_tokens(Utc::now()).await {
+            Ok(0) => tracing::debug!("no expired tokens to purge"),
+            Ok(removed) => tracing::info!(removed, "purged expired tokens"),
+            Err(e) => tracing::error!(error = %e, "failed to purge expired tokens"),
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use http::HeaderMap;
+use rustapi_rs::prelude::*;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+/// Header proxies append each hop's address to
+pub const FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+
+/// Header some proxies set to the single address they saw instead
+pub const REAL_IP_HEADER: &str = "X-Real-IP";
+
+// Peer assumed when the server keeps no socket address with the request,
+// which RustAPI 0.1 does not; its own `ClientIp` assumes the same
+const UNKNOWN_PEER: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// Addresses and CIDR ranges whose forwarding headers are believed, parsed
+/// from `TRUSTED_PROXIES`
+///
+/// Empty by default, so clients cannot claim another address by sending
+/// the headers themselves.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    networks: Vec<(IpAddr, u8)>,
+}
+
+impl TrustedProxies {
+    /// Parse a comma-separated list such as `127.0.0.1, 10.0.0.0/8, ::1`
+    ///
+    /// Entries that are not an address or a range are logged and left out.
+    pub fn parse(list: &str) -> Self {
+        let networks = list
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| {
+                let network = parse_network(entry);
+                if network.is_none() {
+                    tracing::warn!(entry, "ignoring invalid TRUSTED_PROXIES entry");
+                }
+                network
+            })
+            .collect();
+        Self { networks }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.networks.is_empty()
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.networks
+            .iter()
+            .any(|&(network, prefix)| in_network(ip, network, prefix))
+    }
+
+    /// The address of the client behind `peer`
+    ///
+    /// Only a trusted peer's headers count. `X-Forwarded-For` is read from
+    /// the right, skipping trusted hops, so entries an untrusted client put
+    /// in front are never used; without it, `X-Real-IP` is taken instead.
+    pub fn resolve(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        let peer = peer.to_canonical();
+        if !self.contains(peer) {
+            return peer;
+        }
+
+        let hops: Vec<&str> = headers
+            .get_all(FORWARDED_FOR_HEADER)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+        if hops.is_empty() {
+            return headers
+                .get(REAL_IP_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse::<IpAddr>().ok())
+                .map_or(peer, |ip| ip.to_canonical());
+        }
+
+        let mut client = peer;
+        for hop in hops.iter().rev() {
+            let Ok(ip) = hop.parse::<IpAddr>() else {
+                break;
+            };
+            client = ip.to_canonical();
+            if !self.contains(client) {
+                break;
+            }
+        }
+        client
+    }
+}
+
+/// The socket address a request came from, or loopback when the server did
+/// not record one
+pub fn peer_ip(req: &Request) -> IpAddr {
+    req.extensions()
+        .get::<SocketAddr>()
+        .map_or(UNKNOWN_PEER, |addr| addr.ip())
+}
+
+fn parse_network(entry: &str) -> Option<(IpAddr, u8)> {
+    let (address, prefix) = match entry.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix)),
+        None => (entry, None),
+    };
+    let ip = address.trim().parse::<IpAddr>().ok()?.to_canonical();
+    let bits = if ip.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(prefix) => prefix.trim().parse::<u8>().ok()?,
+        None => bits,
+    };
+    (prefix <= bits).then_some((ip, prefix))
+}
+
+fn in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    let (ip, network, bits) = match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            (u32::from(ip) as u128, u32::from(network) as u128, 32)
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => (u128::from(ip), u128::from(network), 128),
+        _ => return false,
+    };
+    let shift = bits - u32::from(prefix);
+    shift >= bits || (ip >> shift) == (network >> shift)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(
+                http::HeaderName::from_bytes(name.as_bytes()).expect("header name"),
+                value.parse().expect("header value"),
+            );
+        }
+        headers
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().expect("ip")
+    }
+
+    #[test]
+    fn parses_addresses_and_ranges() {
+        let trusted = TrustedProxies::parse(" 127.0.0.1, 10.0.0.0/8 ,fd00::/8, bogus, 1.2.3.4/33");
+        for address in ["127.0.0.1", "10.20.30.40", "fd12::1", "::ffff:10.0.0.1"] {
+            assert!(trusted.contains(ip(address)), "{}", address);
+        }
+        for address in ["127.0.0.2", "11.0.0.1", "fe80::1", "1.2.3.4"] {
+            assert!(!trusted.contains(ip(address)), "{}", address);
+        }
+        assert!(TrustedProxies::parse("").is_empty());
+    }
+
+    #[test]
+    fn trusted_proxies_pass_on_the_client_address() {
+        let trusted = TrustedProxies::parse("10.0.0.0/8");
+        let proxy = ip("10.0.0.2");
+
+        // A spoofed first entry is skipped: the proxy appended the real one
+        let forwarded = headers(&[(FORWARDED_FOR_HEADER, "6.6.6.6, 203.0.113.9, 10.0.0.5")]);
+        assert_eq!(trusted.resolve(proxy, &forwarded), ip("203.0.113.9"));
+
+        // Separate header lines count as one list
+        let forwarded = headers(&[
+            (FORWARDED_FOR_HEADER, "198.51.100.1"),
+            (FORWARDED_FOR_HEADER, "10.0.0.5"),
+        ]);
+        assert_eq!(trusted.resolve(proxy, &forwarded), ip("198.51.100.1"));
+
+        let real_ip = headers(&[(REAL_IP_HEADER, "2001:db8::7")]);
+        assert_eq!(trusted.resolve(proxy, &real_ip), ip("2001:db8::7"));
+
+        // Nothing usable leaves the proxy itself
+        let garbage = headers(&[(FORWARDED_FOR_HEADER, "unknown")]);
+        assert_eq!(trusted.resolve(proxy, &garbage), proxy);
+        assert_eq!(trusted.resolve(proxy, &HeaderMap::new()), proxy);
+    }
+
+    #[test]
+    fn untrusted_peers_cannot_claim_another_address() {
+        let spoofed = headers(&[
+            (FORWARDED_FOR_HEADER, "203.0.113.9"),
+            (REAL_IP_HEADER, "203.0.113.9"),
+        ]);
+        let client = ip("198.51.100.20");
+
+        assert_eq!(TrustedProxies::default().resolve(client, &spoofed), client);
+        assert_eq!(
+            TrustedProxies::parse("10.0.0.0/8").resolve(client, &spoofed),
+            client
+        );
+    }
+}
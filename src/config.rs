@@ -0,0 +1,603 @@
+use jsonwebtoken::Algorithm;
+use std::{fmt, str::FromStr, sync::Arc, time::Duration};
+
+use crate::{
+    base_path::BasePath,
+    cleanup,
+    client_ip::TrustedProxies,
+    cookies::{CookieConfig, SameSite},
+    db::{self, PoolConfig},
+    extractors, feed,
+    handlers::api_items,
+    jwt::{self, JwtKeyError, JwtKeys},
+    maintenance,
+    models::{self, ItemLimits, RegistrationMode},
+    passwords::{self, PasswordConfig},
+    pwned, rate_limit,
+    security_headers::{self, SecurityHeaders},
+    seed::SeedConfig,
+    shutdown, static_cache, strength, sudo,
+    timeout::{self, RequestTimeouts},
+    tokens::{self, SessionConfig},
+    uploads::{self, UploadConfig},
+    webauthn,
+};
+
+/// `JWT_SECRET` when it is unset; only accepted outside production
+pub const DEFAULT_JWT_SECRET: &str = "your-super-secret-key-change-in-production";
+
+/// Shortest `JWT_SECRET` accepted in production, in bytes
+pub const MIN_JWT_SECRET_LENGTH: usize = 32;
+
+/// Where the app runs, from `APP_ENV`
+///
+/// Production refuses to start on the insecure defaults development falls
+/// back to, such as the placeholder `JWT_SECRET`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Environment {
+    #[default]
+    Development,
+    Production,
+}
+
+impl Environment {
+    /// Parse an `APP_ENV` value, ignoring case
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "development" | "dev" => Some(Environment::Development),
+            "production" | "prod" => Some(Environment::Production),
+            _ => None,
+        }
+    }
+}
+
+/// One environment variable that was missing or could not be used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    pub var: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.var, self.message)
+    }
+}
+
+/// Every problem found in the environment, so they can all be fixed before
+/// the next start
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigErrors(pub Vec<ConfigError>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration")?;
+        for error in &self.0 {
+            write!(f, "\n  {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+/// Settings read from the environment once at startup
+///
+/// Unset or blank variables take their default. A variable that is set but
+/// cannot be parsed is an error rather than falling back to the default, so
+/// a typo does not quietly change how the server behaves.
+#[derive(Clone)]
+pub struct Config {
+    pub environment: Environment,
+    pub database_url: String,
+    pub host: String,
+    pub port: u16,
+    /// Signs access tokens under `jwt_key_id`, and the CSRF and flash cookies
+    pub jwt_secret: String,
+    pub jwt_algorithm: Algorithm,
+    pub jwt_key_id: String,
+    /// `(kid, secret)` pairs from `JWT_PREVIOUS_KEYS` that still verify
+    pub jwt_previous_keys: Vec<(String, String)>,
+    pub login_max_attempts: u32,
+    pub login_window: Duration,
+    pub login_delay_step: Duration,
+    pub login_delay_max: Duration,
+    pub uploads: UploadConfig,
+    pub max_form_bytes: usize,
+    pub max_items_per_user: Option<i64>,
+    pub max_batch_items: usize,
+    /// Argon2id settings for new password hashes, from `ARGON2_*`; shared
+    /// by clones since the dummy hash is computed once
+    pub passwords: Arc<PasswordConfig>,
+    pub min_password_score: u8,
+    /// `COOKIE_SECURE` and `COOKIE_SAMESITE`, scoped to `base_path`
+    pub cookies: CookieConfig,
+    pub session: SessionConfig,
+    pub pool: PoolConfig,
+    pub template_autoreload: bool,
+    pub invitation_ttl_secs: i64,
+    pub maintenance_mode: bool,
+    pub maintenance_retry_after_secs: u64,
+    pub trusted_proxies: TrustedProxies,
+    pub registration: RegistrationMode,
+    pub pwned_password_check: bool,
+    pub pwned_password_api_url: String,
+    pub pwned_password_timeout: Duration,
+    pub webauthn_rp_id: String,
+    pub webauthn_origin: String,
+    pub login_history_retention_days: i64,
+    pub sudo_ttl_secs: i64,
+    /// Without a trailing slash, and including any `BASE_PATH`
+    pub public_url: String,
+    pub base_path: BasePath,
+    pub request_timeouts: RequestTimeouts,
+    pub item_limits: ItemLimits,
+    pub static_max_age_secs: u64,
+    pub security_headers: SecurityHeaders,
+    pub shutdown_timeout: Duration,
+    /// Wait between sweeps for expired tokens; zero turns them off
+    pub token_cleanup_interval: Duration,
+    /// Demo account for `cargo run -- seed`
+    pub seed: SeedConfig,
+}
+
+impl Config {
+    /// Read the process environment
+    pub fn from_env() -> Result<Self, ConfigErrors> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// Read each variable through `lookup`, reporting every problem at once
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigErrors> {
+        let mut env = Env {
+            lookup,
+            errors: Vec::new(),
+        };
+
+        let environment = env
+            .parse_with("APP_ENV", "development or production", Environment::parse)
+            .unwrap_or_default();
+        let jwt_secret = match env.var("JWT_SECRET") {
+            Some(secret) => secret,
+            None if environment == Environment::Production => {
+                env.error("JWT_SECRET", "must be set in production");
+                String::new()
+            }
+            None => DEFAULT_JWT_SECRET.to_string(),
+        };
+        if environment == Environment::Production && !jwt_secret.is_empty() {
+            if jwt_secret == DEFAULT_JWT_SECRET {
+                env.error("JWT_SECRET", "must not be the example value in production");
+            } else if jwt_secret.len() < MIN_JWT_SECRET_LENGTH {
+                env.error(
+                    "JWT_SECRET",
+                    format!(
+                        "must be at least {} bytes in production",
+                        MIN_JWT_SECRET_LENGTH
+                    ),
+                );
+            }
+        }
+        let jwt_algorithm = env
+            .parse_with("JWT_ALGORITHM", "HS256, HS384 or HS512", |name| {
+                name.to_uppercase().parse::<Algorithm>().ok()
+            })
+            .unwrap_or(jwt::DEFAULT_ALGORITHM);
+        let jwt_key_id = env.string("JWT_KEY_ID", jwt::DEFAULT_KEY_ID);
+        let jwt_previous_keys =
+            match JwtKeys::parse_previous(&env.var("JWT_PREVIOUS_KEYS").unwrap_or_default()) {
+                Ok(keys) => keys,
+                Err(e) => {
+                    env.error("JWT_PREVIOUS_KEYS", e.to_string());
+                    Vec::new()
+                }
+            };
+
+        let upload_dir = env.string("UPLOAD_DIR", "uploads");
+        let max_upload_bytes = env.number("MAX_UPLOAD_BYTES", uploads::DEFAULT_MAX_UPLOAD_BYTES);
+        let uploads = match env.var("UPLOAD_ALLOWED_TYPES") {
+            Some(types) => UploadConfig::new(
+                upload_dir,
+                max_upload_bytes,
+                &types.split(',').collect::<Vec<_>>(),
+            ),
+            None => UploadConfig::new(upload_dir, max_upload_bytes, uploads::DEFAULT_ALLOWED_TYPES),
+        };
+
+        let min_password_score = env
+            .parse_with(
+                "PASSWORD_MIN_SCORE",
+                &format!("a score from 0 to {}", strength::MAX_SCORE),
+                |v| {
+                    v.parse::<u8>()
+                        .ok()
+                        .filter(|score| *score <= strength::MAX_SCORE)
+                },
+            )
+            .unwrap_or(strength::DEFAULT_MIN_SCORE);
+        let cookie_same_site = env
+            .parse_with("COOKIE_SAMESITE", "Strict, Lax or None", SameSite::parse)
+            .unwrap_or(SameSite::Lax);
+        let session = SessionConfig::new(
+            env.number("SESSION_TTL_SECS", tokens::SESSION_TTL_SECS),
+            env.number("REMEMBER_ME_TTL_SECS", tokens::REMEMBER_ME_TTL_SECS),
+            env.number("SESSION_SLIDING_WINDOW_SECS", tokens::SLIDING_WINDOW_SECS),
+            env.number(
+                "SESSION_MAX_LIFETIME_SECS",
+                tokens::MAX_SESSION_LIFETIME_SECS,
+            ),
+        );
+        let pool = PoolConfig {
+            max_connections: env.number("DB_MAX_CONNECTIONS", db::DEFAULT_MAX_CONNECTIONS),
+            acquire_timeout: env.secs("DB_ACQUIRE_TIMEOUT_SECS", db::DEFAULT_ACQUIRE_TIMEOUT),
+            idle_timeout: env.secs("DB_IDLE_TIMEOUT_SECS", db::DEFAULT_IDLE_TIMEOUT),
+            busy_timeout: env.millis("SQLITE_BUSY_TIMEOUT_MS", db::DEFAULT_BUSY_TIMEOUT),
+            write_retries: env.number("SQLITE_WRITE_RETRIES", db::DEFAULT_WRITE_RETRIES),
+        };
+        let registration = RegistrationMode::new(
+            env.flag("REGISTRATION_ENABLED", true),
+            env.flag("REGISTRATION_INVITE_ONLY", false),
+        );
+        let base_path = match BasePath::parse(&env.var("BASE_PATH").unwrap_or_default()) {
+            Ok(base_path) => base_path,
+            Err(e) => {
+                env.error("BASE_PATH", e.to_string());
+                BasePath::default()
+            }
+        };
+        let passwords = match PasswordConfig::new(
+            env.number("ARGON2_MEMORY_KIB", passwords::DEFAULT_MEMORY_KIB),
+            env.number("ARGON2_ITERATIONS", passwords::DEFAULT_ITERATIONS),
+            env.number("ARGON2_PARALLELISM", passwords::DEFAULT_PARALLELISM),
+        ) {
+            Ok(passwords) => passwords,
+            Err(e) => {
+                env.error(
+                    "ARGON2_MEMORY_KIB",
+                    format!("invalid Argon2 parameters: {}", e),
+                );
+                PasswordConfig::default()
+            }
+        };
+        let public_url = env
+            .string("PUBLIC_URL", feed::DEFAULT_PUBLIC_URL)
+            .trim_end_matches('/')
+            .to_string();
+        let security_headers = SecurityHeaders {
+            enabled: env.flag("SECURITY_HEADERS", true),
+            content_security_policy: env.header(
+                "CONTENT_SECURITY_POLICY",
+                security_headers::DEFAULT_CONTENT_SECURITY_POLICY,
+            ),
+            frame_options: env.header("X_FRAME_OPTIONS", security_headers::DEFAULT_FRAME_OPTIONS),
+            referrer_policy: env
+                .header("REFERRER_POLICY", security_headers::DEFAULT_REFERRER_POLICY),
+            hsts_max_age_secs: env.number(
+                "HSTS_MAX_AGE_SECS",
+                security_headers::DEFAULT_HSTS_MAX_AGE_SECS,
+            ),
+            https: public_url.starts_with("https://"),
+        };
+        let defaults = SeedConfig::default();
+
+        let config = Config {
+            environment,
+            database_url: env.string("DATABASE_URL", "sqlite:data.db?mode=rwc"),
+            host: env.string("SERVER_HOST", "127.0.0.1"),
+            port: env.parse("SERVER_PORT", "a port number").unwrap_or(8080),
+            jwt_secret,
+            jwt_algorithm,
+            jwt_key_id,
+            jwt_previous_keys,
+            login_max_attempts: env.number("LOGIN_MAX_ATTEMPTS", 5),
+            login_window: env.secs("LOGIN_WINDOW_SECS", Duration::from_secs(900)),
+            login_delay_step: env.millis("LOGIN_DELAY_STEP_MS", rate_limit::DEFAULT_DELAY_STEP),
+            login_delay_max: env.millis("LOGIN_DELAY_MAX_MS", rate_limit::DEFAULT_MAX_DELAY),
+            uploads,
+            max_form_bytes: env.number("MAX_FORM_BYTES", extractors::DEFAULT_MAX_FORM_BYTES),
+            max_items_per_user: env.parse("MAX_ITEMS_PER_USER", "a whole number"),
+            max_batch_items: env.number("MAX_BATCH_ITEMS", api_items::DEFAULT_MAX_BATCH_ITEMS),
+            passwords: Arc::new(passwords),
+            min_password_score,
+            cookies: CookieConfig::new(env.flag("COOKIE_SECURE", true), cookie_same_site)
+                .with_path(base_path.cookie_path()),
+            session,
+            pool,
+            template_autoreload: env.flag("TEMPLATE_AUTORELOAD", false),
+            invitation_ttl_secs: env.number("INVITATION_TTL_SECS", tokens::INVITATION_TTL_SECS),
+            maintenance_mode: env.flag("MAINTENANCE_MODE", false),
+            maintenance_retry_after_secs: env.number(
+                "MAINTENANCE_RETRY_AFTER_SECS",
+                maintenance::DEFAULT_RETRY_AFTER_SECS,
+            ),
+            trusted_proxies: env
+                .var("TRUSTED_PROXIES")
+                .map(|list| TrustedProxies::parse(&list))
+                .unwrap_or_default(),
+            registration,
+            pwned_password_check: env.flag("PWNED_PASSWORD_CHECK", false),
+            pwned_password_api_url: env.string("PWNED_PASSWORD_API_URL", pwned::DEFAULT_API_URL),
+            pwned_password_timeout: env.millis("PWNED_PASSWORD_TIMEOUT_MS", pwned::DEFAULT_TIMEOUT),
+            webauthn_rp_id: env.string("WEBAUTHN_RP_ID", webauthn::DEFAULT_RP_ID),
+            webauthn_origin: env.string("WEBAUTHN_ORIGIN", webauthn::DEFAULT_ORIGIN),
+            login_history_retention_days: env.number(
+                "LOGIN_HISTORY_RETENTION_DAYS",
+                models::DEFAULT_LOGIN_HISTORY_RETENTION_DAYS,
+            ),
+            sudo_ttl_secs: env.number("SUDO_TTL_SECS", sudo::DEFAULT_SUDO_TTL_SECS),
+            public_url,
+            base_path,
+            request_timeouts: RequestTimeouts {
+                default: env.secs("REQUEST_TIMEOUT_SECS", timeout::DEFAULT_REQUEST_TIMEOUT),
+                long: env.secs(
+                    "LONG_REQUEST_TIMEOUT_SECS",
+                    timeout::DEFAULT_LONG_REQUEST_TIMEOUT,
+                ),
+            },
+            item_limits: ItemLimits {
+                title: env.number("MAX_TITLE_LENGTH", models::DEFAULT_MAX_TITLE_LENGTH),
+                description: env.number(
+                    "MAX_DESCRIPTION_LENGTH",
+                    models::DEFAULT_MAX_DESCRIPTION_LENGTH,
+                ),
+            },
+            static_max_age_secs: env.number(
+                "STATIC_MAX_AGE_SECS",
+                static_cache::DEFAULT_STATIC_MAX_AGE_SECS,
+            ),
+            security_headers,
+            shutdown_timeout: env.secs("SHUTDOWN_TIMEOUT_SECS", shutdown::DEFAULT_SHUTDOWN_TIMEOUT),
+            token_cleanup_interval: env.secs(
+                "TOKEN_CLEANUP_INTERVAL_SECS",
+                cleanup::DEFAULT_TOKEN_CLEANUP_INTERVAL,
+            ),
+            seed: SeedConfig {
+                username: env.string("SEED_USERNAME", &defaults.username),
+                email: env.string("SEED_EMAIL", &defaults.email),
+                password: env.string("SEED_PASSWORD", &defaults.password),
+            },
+        };
+
+        // Settings that only fail once combined are checked the way startup
+        // builds them
+        if let Err(e) = config.jwt_keys() {
+            let var = match e {
+                JwtKeyError::UnsupportedAlgorithm(_) => "JWT_ALGORITHM",
+                _ => "JWT_PREVIOUS_KEYS",
+            };
+            env.error(var, e.to_string());
+        }
+
+        if env.errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigErrors(env.errors))
+        }
+    }
+
+    /// Keys access tokens are signed and verified with
+    pub fn jwt_keys(&self) -> Result<JwtKeys, JwtKeyError> {
+        JwtKeys::with_rotation(
+            self.jwt_algorithm,
+            &self.jwt_key_id,
+            &self.jwt_secret,
+            &self.jwt_previous_keys,
+        )
+    }
+}
+
+// Reads variables through `lookup`, collecting a `ConfigError` for each one
+// that is set but cannot be used; callers then carry on with the default
+struct Env<F> {
+    lookup: F,
+    errors: Vec<ConfigError>,
+}
+
+impl<F: Fn(&str) -> Option<String>> Env<F> {
+    // Blank values count as unset
+    fn var(&self, name: &str) -> Option<String> {
+        (self.lookup)(name).filter(|value| !value.trim().is_empty())
+    }
+
+    fn string(&self, name: &str, default: &str) -> String {
+        self.var(name).unwrap_or_else(|| default.to_string())
+    }
+
+    fn error(&mut self, var: &'static str, message: impl Into<String>) {
+        self.errors.push(ConfigError {
+            var,
+            message: message.into(),
+        });
+    }
+
+    // `None` when unset or unparsable; the latter is recorded as an error
+    fn parse_with<T>(
+        &mut self,
+        name: &'static str,
+        expected: &str,
+        parse: impl FnOnce(&str) -> Option<T>,
+    ) -> Option<T> {
+        let value = self.var(name)?;
+        let parsed = parse(value.trim());
+        if parsed.is_none() {
+            self.error(name, format!("{:?} is not {}", value, expected));
+        }
+        parsed
+    }
+
+    fn parse<T: FromStr>(&mut self, name: &'static str, expected: &str) -> Option<T> {
+        self.parse_with(name, expected, |value| value.parse().ok())
+    }
+
+    fn number<T: FromStr>(&mut self, name: &'static str, default: T) -> T {
+        self.parse(name, "a whole number").unwrap_or(default)
+    }
+
+    fn flag(&mut self, name: &'static str, default: bool) -> bool {
+        self.parse(name, "true or false").unwrap_or(default)
+    }
+
+    fn secs(&mut self, name: &'static str, default: Duration) -> Duration {
+        self.parse(name, "a number of seconds")
+            .map(Duration::from_secs)
+            .unwrap_or(default)
+    }
+
+    // A header's value; `None` when set to `off`, so it is not sent
+    fn header(&mut self, name: &'static str, default: &str) -> Option<String> {
+        let value = self.string(name, default).trim().to_string();
+        if value.eq_ignore_ascii_case(security_headers::OFF) {
+            return None;
+        }
+        if http::HeaderValue::from_str(&value).is_err() {
+            self.error(name, format!("{:?} is not a valid header value", value));
+            return Some(default.to_string());
+        }
+        Some(value)
+    }
+
+    fn millis(&mut self, name: &'static str, default: Duration) -> Duration {
+        self.parse(name, "a number of milliseconds")
+            .map(Duration::from_millis)
+            .unwrap_or(default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn load(vars: &[(&str, &str)]) -> Result<Config, ConfigErrors> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Config::from_lookup(|name| vars.get(name).cloned())
+    }
+
+    // Names of the variables with a problem, in the order they were read
+    fn vars(errors: &ConfigErrors) -> Vec<&'static str> {
+        errors.0.iter().map(|error| error.var).collect()
+    }
+
+    #[test]
+    fn empty_environment_uses_the_development_defaults() {
+        let config = load(&[]).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(config.environment, Environment::Development);
+        assert_eq!(config.jwt_secret, DEFAULT_JWT_SECRET);
+        assert_eq!(config.port, 8080);
+        assert_eq!(config.pool.max_connections, db::DEFAULT_MAX_CONNECTIONS);
+        assert_eq!(config.request_timeouts, RequestTimeouts::default());
+        assert_eq!(config.item_limits, ItemLimits::default());
+        assert_eq!(config.registration, RegistrationMode::Open);
+        assert_eq!(config.max_items_per_user, None);
+        assert_eq!(config.public_url, feed::DEFAULT_PUBLIC_URL);
+    }
+
+    #[test]
+    fn parses_a_valid_production_environment() {
+        let config = load(&[
+            ("APP_ENV", "Production"),
+            ("JWT_SECRET", "0123456789abcdef0123456789abcdef"),
+            ("JWT_ALGORITHM", "hs512"),
+            ("JWT_PREVIOUS_KEYS", "old:secret"),
+            ("SERVER_PORT", "3000"),
+            ("REQUEST_TIMEOUT_SECS", "10"),
+            ("SQLITE_BUSY_TIMEOUT_MS", "250"),
+            ("MAX_ITEMS_PER_USER", "50"),
+            ("COOKIE_SAMESITE", "strict"),
+            ("REGISTRATION_INVITE_ONLY", "true"),
+            ("BASE_PATH", "/app/"),
+            ("PUBLIC_URL", "https://example.com/app/"),
+            ("MAX_FORM_BYTES", ""),
+        ])
+        .unwrap_or_else(|e| panic!("{}", e));
+
+        assert_eq!(config.environment, Environment::Production);
+        assert_eq!(config.jwt_algorithm, Algorithm::HS512);
+        assert_eq!(
+            config.jwt_previous_keys,
+            vec![("old".to_string(), "secret".to_string())]
+        );
+        assert_eq!(config.port, 3000);
+        assert_eq!(config.request_timeouts.default, Duration::from_secs(10));
+        assert_eq!(config.pool.busy_timeout, Duration::from_millis(250));
+        assert_eq!(config.max_items_per_user, Some(50));
+        assert_eq!(config.cookies.same_site, SameSite::Strict);
+        assert_eq!(config.cookies.path, "/app");
+        assert_eq!(config.registration, RegistrationMode::InviteOnly);
+        assert_eq!(config.base_path.as_str(), "/app");
+        assert_eq!(config.public_url, "https://example.com/app");
+        assert_eq!(config.max_form_bytes, extractors::DEFAULT_MAX_FORM_BYTES);
+        assert!(config.jwt_keys().is_ok());
+    }
+
+    #[test]
+    fn reports_every_problem_at_once() {
+        let errors = load(&[
+            ("APP_ENV", "production"),
+            ("SERVER_PORT", "eighty"),
+            ("LOGIN_MAX_ATTEMPTS", "-1"),
+            ("COOKIE_SECURE", "yes"),
+            ("BASE_PATH", "app"),
+            ("PASSWORD_MIN_SCORE", "9"),
+            ("JWT_PREVIOUS_KEYS", "no-secret"),
+        ])
+        .err()
+        .expect("invalid config");
+
+        assert_eq!(
+            vars(&errors),
+            vec![
+                "JWT_SECRET",
+                "JWT_PREVIOUS_KEYS",
+                "PASSWORD_MIN_SCORE",
+                "BASE_PATH",
+                "SERVER_PORT",
+                "LOGIN_MAX_ATTEMPTS",
+                "COOKIE_SECURE",
+            ]
+        );
+        let message = errors.to_string();
+        assert!(message.starts_with("invalid configuration\n"));
+        assert!(message.contains("JWT_SECRET: must be set in production"));
+        assert!(message.contains("SERVER_PORT: \"eighty\" is not a port number"));
+    }
+
+    #[test]
+    fn production_refuses_weak_jwt_secrets() {
+        for secret in [DEFAULT_JWT_SECRET, "short"] {
+            let errors = load(&[("APP_ENV", "production"), ("JWT_SECRET", secret)])
+                .err()
+                .expect("weak secret refused");
+            assert_eq!(vars(&errors), vec!["JWT_SECRET"]);
+        }
+        assert!(load(&[("JWT_SECRET", "short")]).is_ok());
+    }
+
+    #[test]
+    fn security_headers_can_be_changed_or_turned_off() {
+        let config = load(&[]).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(config.security_headers, SecurityHeaders::default());
+
+        let config = load(&[
+            ("CONTENT_SECURITY_POLICY", " default-src 'none' "),
+            ("X_FRAME_OPTIONS", "OFF"),
+            ("PUBLIC_URL", "https://example.com"),
+        ])
+        .unwrap_or_else(|e| panic!("{}", e));
+        let headers = &config.security_headers;
+        assert_eq!(
+            headers.content_security_policy.as_deref(),
+            Some("default-src 'none'")
+        );
+        assert_eq!(headers.frame_options, None);
+        assert!(headers.https);
+
+        let errors = load(&[("REFERRER_POLICY", "no-referrer\nX-Injected: 1")])
+            .err()
+            .expect("invalid header refused");
+        assert_eq!(vars(&errors), vec!["REFERRER_POLICY"]);
+    }
+}
@@ -0,0 +1,121 @@
+/// Which cross-site requests a cookie is sent with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl SameSite {
+    /// Parse a `COOKIE_SAMESITE` value, ignoring case
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "strict" => Some(SameSite::Strict),
+            "lax" => Some(SameSite::Lax),
+            "none" => Some(SameSite::None),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+/// Attributes of every cookie the app sets
+#[derive(Debug, Clone)]
+pub struct CookieConfig {
+    /// Only send cookies over HTTPS
+    pub secure: bool,
+    pub same_site: SameSite,
+    /// `Path` the cookies are scoped to; `/` unless the app runs under a
+    /// `BasePath`
+    pub path: String,
+}
+
+impl CookieConfig {
+    pub fn new(secure: bool, same_site: SameSite) -> Self {
+        Self {
+            secure,
+            same_site,
+            path: "/".to_string(),
+        }
+    }
+
+    /// The same attributes with the cookies scoped to `path`
+    pub fn with_path(mut self, path: &str) -> Self {
+        self.path = path.to_string();
+        self
+    }
+
+    /// `Set-Cookie` value for an `HttpOnly` cookie on `path`; without a
+    /// `max_age` it lasts for the browser session
+    ///
+    /// `SameSite=None` always gets `Secure`, since browsers reject it otherwise.
+    pub fn header(&self, name: &str, value: &str, max_age: Option<i64>) -> String {
+        let mut cookie = format!(
+            "{}={}; Path={}; HttpOnly; SameSite={}",
+            name,
+            value,
+            self.path,
+            self.same_site.as_str()
+        );
+        if self.secure || self.same_site == SameSite::None {
+            cookie.push_str("; Secure");
+        }
+        if let Some(max_age) = max_age {
+            cookie.push_str(&format!("; Max-Age={}", max_age));
+        }
+        cookie
+    }
+
+    /// `Set-Cookie` value deleting a cookie set by `header`
+    pub fn removal(&self, name: &str) -> String {
+        self.header(name, "", Some(0))
+    }
+}
+
+impl Default for CookieConfig {
+    fn default() -> Self {
+        Self::new(true, SameSite::Lax)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_site_parses_any_case() {
+        assert_eq!(SameSite::parse("Strict"), Some(SameSite::Strict));
+        assert_eq!(SameSite::parse(" lax "), Some(SameSite::Lax));
+        assert_eq!(SameSite::parse("NONE"), Some(SameSite::None));
+        assert_eq!(SameSite::parse("sometimes"), None);
+    }
+
+    #[test]
+    fn header_reflects_configured_flags() {
+        assert_eq!(
+            CookieConfig::default().header("token", "abc", Some(60)),
+            "token=abc; Path=/; HttpOnly; SameSite=Lax; Secure; Max-Age=60"
+        );
+        assert_eq!(
+            CookieConfig::new(false, SameSite::Strict).header("csrf", "abc", None),
+            "csrf=abc; Path=/; HttpOnly; SameSite=Strict"
+        );
+        assert_eq!(
+            CookieConfig::new(false, SameSite::None).removal("token"),
+            "token=; Path=/; HttpOnly; SameSite=None; Secure; Max-Age=0"
+        );
+        assert_eq!(
+            CookieConfig::new(false, SameSite::Lax)
+                .with_path("/app")
+                .header("token", "abc", None),
+            "token=abc; Path=/app; HttpOnly; SameSite=Lax"
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use hmac::{Hmac, Mac};
+use rustapi_rs::Cookies;
+use sha2::Sha256;
+
+use crate::cookies::CookieConfig;
+
+/// Cookie holding the signed per-session CSRF token
+pub const CSRF_COOKIE: &str = "csrf";
+
+/// Form field every state-changing form must submit
+pub const CSRF_FIELD: &str = "_csrf";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Generate a fresh random token
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Sign a token as `<token>.<hmac>` for storage in the cookie
+pub fn sign_token(secret: &str, token: &str) -> String {
+    format!("{}.{}", token, hex::encode(signature(secret, token)))
+}
+
+/// Return the token from a signed cookie value if the signature checks out
+pub fn verify_signed(secret: &str, value: &str) -> Option<String> {
+    let (token, sig) = value.rsplit_once('.')?;
+    let sig = hex::decode(sig).ok()?;
+
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).ok()?;
+    mac.update(token.as_bytes());
+    mac.verify_slice(&sig).ok()?;
+
+    Some(token.to_string())
+}
+
+/// Read and verify the CSRF token from the request cookies
+pub fn token_from_cookies(secret: &str, cookies: &Cookies) -> Option<String> {
+    let cookie = cookies.get(CSRF_COOKIE)?;
+    verify_signed(secret, cookie.value())
+}
+
+/// `Set-Cookie` value storing a signed token for the browser session
+pub fn cookie_header(config: &CookieConfig, secret: &str, token: &str) -> String {
+    config.header(CSRF_COOKIE, &sign_token(secret, token), None)
+}
+
+/// Compare a submitted token with the expected one in constant time
+pub fn tokens_match(expected: &str, submitted: &str) -> bool {
+    expected.len() == submitted.len()
+        && expected
+            .bytes()
+            .zip(submitted.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn signature(secret: &str, token: &str) -> Vec<u8> {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(token.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signed_token_round_trips() {
+        let token = generate_token();
+        let signed = sign_token("secret", &token);
+        assert_eq!(verify_signed("secret", &signed), Some(token));
+    }
+
+    #[test]
+    fn tampered_signature_is_rejected() {
+        let signed = sign_token("secret", "token");
+        assert_eq!(verify_signed("other-secret", &signed), None);
+        assert_eq!(verify_signed("secret", "forged.deadbeef"), None);
+        assert_eq!(verify_signed("secret", "no-signature"), None);
+    }
+
+    #[test]
+    fn tokens_match_compares_exactly() {
+        assert!(tokens_match("abc", "abc"));
+        assert!(!tokens_match("abc", "abd"));
+        assert!(!tokens_match("abc", "ab"));
+    }
+}
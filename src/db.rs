@@ -1,310 +1,5537 @@
-use sqlx::{Pool, Sqlite, SqlitePool};
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use futures_util::future::BoxFuture;
+use sqlx::migrate::Migrator;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+use sqlx::{PgPool, SqlitePool};
 use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
 
-use crate::models::{CreateItem, Item, User};
+use std::collections::{HashMap, HashSet};
+
+use crate::models::{
+    ApiKey, Attachment, AuditAction, Comment, CreateAttachment, CreateItem, DayCount, Invite, Item,
+    ItemAudit, ItemCursor, ItemFilter, ItemInvitation, ItemLimits, ItemSort, LoginEvent, Passkey,
+    RefreshToken, SharedItem, SortColumn, User,
+};
+
+/// Connection pool for the backend selected by the `DATABASE_URL` scheme
+#[derive(Clone)]
+pub enum DbPool {
+    Sqlite(SqlitePool),
+    Postgres(PgPool),
+}
+
+/// Run the same query body against whichever backend the pool wraps.
+///
+/// Queries use `$N` placeholders, which both SQLite and Postgres accept.
+macro_rules! with_pool {
+    ($pool:expr, $p:ident => $body:expr) => {
+        match $pool {
+            DbPool::Sqlite($p) => $body,
+            DbPool::Postgres($p) => $body,
+        }
+    };
+}
+
+/// Default number of pooled connections
+pub const DEFAULT_MAX_CONNECTIONS: u32 = 10;
+
+/// Default wait for a free connection: 30 seconds
+pub const DEFAULT_ACQUIRE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default lifetime of an unused connection: 10 minutes
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Default wait on a locked SQLite database: 5 seconds
+pub const DEFAULT_BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default number of times a write is retried after SQLite reports the
+/// database busy
+pub const DEFAULT_WRITE_RETRIES: u32 = 3;
+
+/// Pause before the first retry of a busy write; doubled for each one after
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(10);
+
+/// Connection pool limits
+#[derive(Debug, Clone)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    /// How long a query waits for a free connection before failing
+    pub acquire_timeout: Duration,
+    /// How long an unused connection stays open
+    pub idle_timeout: Duration,
+    /// How long SQLite retries a locked database before reporting it busy;
+    /// ignored by Postgres
+    pub busy_timeout: Duration,
+    /// How many more times a write is attempted once `busy_timeout` has run
+    /// out; ignored by Postgres
+    pub write_retries: u32,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            acquire_timeout: DEFAULT_ACQUIRE_TIMEOUT,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            busy_timeout: DEFAULT_BUSY_TIMEOUT,
+            write_retries: DEFAULT_WRITE_RETRIES,
+        }
+    }
+}
+
+/// What `Database::reassign_item` did
+#[derive(Debug)]
+pub enum ReassignOutcome {
+    /// The item now belongs to the new owner
+    Moved(Item),
+    ItemNotFound,
+    /// No user has the requested id, so nothing was changed
+    UserNotFound,
+}
+
+/// Which of an account's unique fields another account already holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserConflict {
+    Username,
+    Email,
+}
+
+impl UserConflict {
+    /// The conflict behind a unique violation on `users`, telling the two
+    /// apart by the constraint or index named in the error; `None` for any
+    /// other error
+    pub fn of(error: &sqlx::Error) -> Option<Self> {
+        let error = error
+            .as_database_error()
+            .filter(|e| e.is_unique_violation())?;
+        // Postgres names the constraint; SQLite only puts the column or index
+        // in the message
+        let source = error
+            .constraint()
+            .map(str::to_string)
+            .unwrap_or_else(|| error.message().to_string());
+        if source.contains("username") {
+            Some(UserConflict::Username)
+        } else if source.contains("email") {
+            Some(UserConflict::Email)
+        } else {
+            None
+        }
+    }
+
+    /// Form field the conflict is reported on
+    pub fn field(self) -> &'static str {
+        match self {
+            UserConflict::Username => "username",
+            UserConflict::Email => "email",
+        }
+    }
+
+    /// Key of the message the conflict is reported with; see `i18n`
+    pub fn message_key(self) -> &'static str {
+        match self {
+            UserConflict::Username => "auth.username_taken",
+            UserConflict::Email => "auth.email_taken",
+        }
+    }
+}
 
 /// Database connection pool wrapper
 #[derive(Clone)]
 pub struct Database {
-    pub pool: Pool<Sqlite>,
+    pub pool: DbPool,
+    write_retries: u32,
+    /// Whether `items_fts` exists; false on Postgres and on SQLite builds
+    /// without FTS5
+    full_text: bool,
+    /// Longest title and description an item is saved with
+    item_limits: ItemLimits,
+}
+
+/// An open transaction on whichever backend the pool wraps
+///
+/// Dropping it without calling `commit` rolls every statement back.
+pub enum DbTransaction {
+    Sqlite(sqlx::Transaction<'static, sqlx::Sqlite>),
+    Postgres(sqlx::Transaction<'static, sqlx::Postgres>),
+}
+
+/// Like `with_pool!`, but runs the query body on the transaction's connection
+macro_rules! with_tx {
+    ($tx:expr, $c:ident => $body:expr) => {
+        match $tx {
+            DbTransaction::Sqlite(tx) => {
+                let $c = &mut **tx;
+                $body
+            }
+            DbTransaction::Postgres(tx) => {
+                let $c = &mut **tx;
+                $body
+            }
+        }
+    };
 }
 
 impl Database {
     /// Create a new database connection and run migrations
     pub async fn new(database_url: &str) -> Result<Self, sqlx::Error> {
-        // Ensure database file exists
-        let db_path = database_url.replace("sqlite:", "").replace("?mode=rwc", "");
-        if !Path::new(&db_path).exists() {
-            std::fs::File::create(&db_path).ok();
-        }
+        Self::connect(database_url, &PoolConfig::default()).await
+    }
 
-        let pool = SqlitePool::connect(database_url).await?;
+    /// Like `new`, with explicit pool limits
+    ///
+    /// SQLite connections use WAL journaling, so readers do not block the
+    /// writer, and wait `busy_timeout` for a lock instead of failing at once.
+    pub async fn connect(database_url: &str, config: &PoolConfig) -> Result<Self, sqlx::Error> {
+        let pool = if is_postgres_url(database_url) {
+            DbPool::Postgres(
+                PgPoolOptions::new()
+                    .max_connections(config.max_connections)
+                    .acquire_timeout(config.acquire_timeout)
+                    .idle_timeout(config.idle_timeout)
+                    .connect(database_url)
+                    .await?,
+            )
+        } else {
+            // Ensure database file exists
+            let db_path = database_url.replace("sqlite:", "").replace("?mode=rwc", "");
+            if !Path::new(&db_path).exists() {
+                std::fs::File::create(&db_path).ok();
+            }
+
+            let options = SqliteConnectOptions::from_str(database_url)?
+                .journal_mode(SqliteJournalMode::Wal)
+                .busy_timeout(config.busy_timeout);
+            DbPool::Sqlite(
+                SqlitePoolOptions::new()
+                    .max_connections(config.max_connections)
+                    .acquire_timeout(config.acquire_timeout)
+                    .idle_timeout(config.idle_timeout)
+                    .connect_with(options)
+                    .await?,
+            )
+        };
 
-        let db = Self { pool };
+        let mut db = Self {
+            pool,
+            write_retries: config.write_retries,
+            full_text: false,
+            item_limits: ItemLimits::default(),
+        };
         db.run_migrations().await?;
 
         Ok(db)
     }
 
-    /// Run SQL migrations
-    async fn run_migrations(&self) -> Result<(), sqlx::Error> {
-        // Create tables directly
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS users (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                username TEXT NOT NULL UNIQUE,
-                email TEXT NOT NULL UNIQUE,
-                password_hash TEXT NOT NULL,
-                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
-            )
-            "#,
+    /// Refuse to save items longer than `limits`, in place of the defaults
+    pub fn with_item_limits(mut self, limits: ItemLimits) -> Self {
+        self.item_limits = limits;
+        self
+    }
+
+    // Items over the limits are refused with `InvalidArgument` before
+    // anything is written, so no caller can get past the form validation
+    fn check_item_limits(&self, title: &str, description: Option<&str>) -> Result<(), sqlx::Error> {
+        self.item_limits
+            .check(title, description)
+            .map_err(|errors| {
+                let messages: Vec<String> = errors
+                    .fields
+                    .values()
+                    .flatten()
+                    .map(|rule| rule.interpolate_message())
+                    .collect();
+                sqlx::Error::InvalidArgument(messages.join("; "))
+            })
+    }
+
+    /// Close every pooled connection, waiting for checked-out ones to return
+    pub async fn close(&self) {
+        with_pool!(&self.pool, pool => pool.close().await)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        with_pool!(&self.pool, pool => pool.is_closed())
+    }
+
+    /// Apply pending migrations from `migrations/sqlite` or `migrations/postgres`
+    async fn run_migrations(&mut self) -> Result<(), sqlx::Error> {
+        match &self.pool {
+            DbPool::Sqlite(pool) => SQLITE_MIGRATOR.run(pool).await?,
+            DbPool::Postgres(pool) => POSTGRES_MIGRATOR.run(pool).await?,
+        }
+
+        // Columns added before versioned migrations existed; databases created
+        // since already have them from the initial migration
+        self.ensure_column("users", "failed_attempts", "INTEGER NOT NULL DEFAULT 0")
+            .await?;
+        self.ensure_column("users", "token_version", "INTEGER NOT NULL DEFAULT 0")
+            .await?;
+        // Accounts created before email verification existed count as verified
+        self.ensure_column("users", "verified", "BOOLEAN NOT NULL DEFAULT TRUE")
+            .await?;
+        self.ensure_column("users", "role", "TEXT NOT NULL DEFAULT 'user'")
+            .await?;
+        let timestamp = match &self.pool {
+            DbPool::Sqlite(_) => "DATETIME",
+            DbPool::Postgres(_) => "TIMESTAMPTZ",
+        };
+        self.ensure_column("users", "locked_until", timestamp)
+            .await?;
+        self.ensure_column("items", "deleted_at", timestamp).await?;
+        // Refresh tokens issued before "remember me" had 30-day cookies
+        self.ensure_column(
+            "refresh_tokens",
+            "remember",
+            "BOOLEAN NOT NULL DEFAULT TRUE",
         )
-        .execute(&self.pool)
         .await?;
+        self.full_text = self.ensure_full_text_index().await?;
 
-        sqlx::query(
-            r#"
-            CREATE TABLE IF NOT EXISTS items (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                user_id INTEGER NOT NULL,
-                title TEXT NOT NULL,
-                description TEXT,
-                created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-                updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
-                FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
-            )
-            "#,
+        Ok(())
+    }
+
+    /// Create the SQLite `items_fts` index over item titles and descriptions,
+    /// and the triggers that keep it in step with `items`
+    ///
+    /// Not a versioned migration because it needs FTS5: returns false, and
+    /// searches fall back to `LIKE`, on Postgres or when SQLite was built
+    /// without it. Items written before the index existed are indexed once.
+    async fn ensure_full_text_index(&self) -> Result<bool, sqlx::Error> {
+        let DbPool::Sqlite(pool) = &self.pool else {
+            return Ok(false);
+        };
+
+        let mut tx = pool.begin().await?;
+        let (exists,): (bool,) = sqlx::query_as(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'items_fts')",
         )
-        .execute(&self.pool)
+        .fetch_one(&mut *tx)
         .await?;
+        if !exists {
+            let created = sqlx::query(
+                "CREATE VIRTUAL TABLE items_fts USING fts5(title, description, content = 'items', content_rowid = 'id')",
+            )
+            .execute(&mut *tx)
+            .await;
+            match created {
+                Ok(_) => {}
+                Err(e) if e.to_string().contains("no such module: fts5") => {
+                    tracing::warn!("SQLite was built without FTS5; item search uses LIKE");
+                    return Ok(false);
+                }
+                Err(e) => return Err(e),
+            }
+            sqlx::query("INSERT INTO items_fts (items_fts) VALUES ('rebuild')")
+                .execute(&mut *tx)
+                .await?;
+        }
+        for trigger in FULL_TEXT_TRIGGERS {
+            sqlx::query(trigger).execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
 
-        // Create indexes
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_items_user_id ON items(user_id)")
-            .execute(&self.pool)
-            .await?;
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_users_username ON users(username)")
-            .execute(&self.pool)
-            .await?;
-        sqlx::query("CREATE INDEX IF NOT EXISTS idx_users_email ON users(email)")
-            .execute(&self.pool)
-            .await?;
+        Ok(true)
+    }
+
+    /// Add a column to an existing table unless it is already present
+    async fn ensure_column(
+        &self,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> Result<(), sqlx::Error> {
+        match &self.pool {
+            DbPool::Sqlite(pool) => {
+                let (count,): (i64,) =
+                    sqlx::query_as("SELECT COUNT(*) FROM pragma_table_info($1) WHERE name = $2")
+                        .bind(table)
+                        .bind(column)
+                        .fetch_one(pool)
+                        .await?;
+                if count == 0 {
+                    sqlx::query(&format!(
+                        "ALTER TABLE {} ADD COLUMN {} {}",
+                        table, column, definition
+                    ))
+                    .execute(pool)
+                    .await?;
+                }
+            }
+            DbPool::Postgres(pool) => {
+                sqlx::query(&format!(
+                    "ALTER TABLE {} ADD COLUMN IF NOT EXISTS {} {}",
+                    table, column, definition
+                ))
+                .execute(pool)
+                .await?;
+            }
+        }
 
         Ok(())
     }
 
+    // ==================== Transactions ====================
+
+    /// Start a transaction; statements run through it are only saved once
+    /// it is committed
+    pub async fn begin(&self) -> Result<DbTransaction, sqlx::Error> {
+        Ok(match &self.pool {
+            DbPool::Sqlite(pool) => DbTransaction::Sqlite(pool.begin().await?),
+            DbPool::Postgres(pool) => DbTransaction::Postgres(pool.begin().await?),
+        })
+    }
+
+    /// Run `f` in a transaction, committing if it returns `Ok` and rolling
+    /// every write back if it returns `Err`
+    ///
+    /// The closure returns a boxed future so it can borrow the transaction:
+    /// `db.with_transaction(|tx| Box::pin(async move { ... })).await`
+    pub async fn with_transaction<T, F>(&self, f: F) -> Result<T, sqlx::Error>
+    where
+        F: for<'t> FnOnce(&'t mut DbTransaction) -> BoxFuture<'t, Result<T, sqlx::Error>>,
+    {
+        let mut tx = self.begin().await?;
+        match f(&mut tx).await {
+            Ok(value) => {
+                tx.commit().await?;
+                Ok(value)
+            }
+            Err(e) => {
+                tx.rollback().await?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Run the write `op`, running it again with exponential backoff while
+    /// SQLite reports the database busy, up to `write_retries` more times
+    ///
+    /// `op` must be safe to repeat: a busy error means nothing it did was
+    /// committed.
+    async fn retry_busy<T, F, Fut>(&self, mut op: F) -> Result<T, sqlx::Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T, sqlx::Error>>,
+    {
+        let mut delay = RETRY_BASE_DELAY;
+        for attempt in 0.. {
+            match op().await {
+                Err(e) if attempt < self.write_retries && is_busy(&e) => {
+                    tracing::warn!(attempt = attempt + 1, error = %e, "database busy, retrying write");
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+        unreachable!("the retry loop only ends by returning")
+    }
+
     // ==================== User Operations ====================
 
     /// Create a new user
+    ///
+    /// Fails with a unique violation when the username or email address is
+    /// taken, ignoring case; `UserConflict::of` tells which.
     pub async fn create_user(
         &self,
         username: &str,
         email: &str,
         password_hash: &str,
     ) -> Result<User, sqlx::Error> {
-        let user = sqlx::query_as::<_, User>(
-            r#"
-            INSERT INTO users (username, email, password_hash)
-            VALUES (?, ?, ?)
-            RETURNING id, username, email, password_hash, created_at
-            "#,
-        )
-        .bind(username)
-        .bind(email)
-        .bind(password_hash)
-        .fetch_one(&self.pool)
-        .await?;
-
-        Ok(user)
+        self.retry_busy(|| async move {
+            let mut tx = self.begin().await?;
+            let user = tx.create_user(username, email, password_hash).await?;
+            tx.commit().await?;
+            Ok(user)
+        })
+        .await
     }
 
-    /// Find user by username
+    /// Find user by username, ignoring case
     pub async fn find_user_by_username(&self, username: &str) -> Result<Option<User>, sqlx::Error> {
-        let user = sqlx::query_as::<_, User>(
-            "SELECT id, username, email, password_hash, created_at FROM users WHERE username = ?",
-        )
-        .bind(username)
-        .fetch_optional(&self.pool)
-        .await?;
+        let user = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, User>(
+                r#"
+                SELECT id, username, email, password_hash, failed_attempts, locked_until, token_version,
+                    verified, role, timezone, created_at, last_login_at
+                FROM users
+                WHERE LOWER(username) = LOWER($1)
+                "#,
+            )
+            .bind(username)
+            .fetch_optional(pool)
+            .await
+        })?;
 
         Ok(user)
     }
 
     /// Find user by ID
     pub async fn find_user_by_id(&self, id: i64) -> Result<Option<User>, sqlx::Error> {
-        let user = sqlx::query_as::<_, User>(
-            "SELECT id, username, email, password_hash, created_at FROM users WHERE id = ?",
-        )
-        .bind(id)
-        .fetch_optional(&self.pool)
-        .await?;
+        let user = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, User>(
+                r#"
+                SELECT id, username, email, password_hash, failed_attempts, locked_until, token_version,
+                    verified, role, timezone, created_at, last_login_at
+                FROM users
+                WHERE id = $1
+                "#,
+            )
+            .bind(id)
+            .fetch_optional(pool)
+            .await
+        })?;
 
         Ok(user)
     }
 
-    /// Check if username exists
+    /// Check if username exists, ignoring case
     pub async fn username_exists(&self, username: &str) -> Result<bool, sqlx::Error> {
-        let result: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users WHERE username = ?")
-            .bind(username)
-            .fetch_one(&self.pool)
-            .await?;
+        let result: (i64,) = with_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT COUNT(*) FROM users WHERE LOWER(username) = LOWER($1)")
+                .bind(username)
+                .fetch_one(pool)
+                .await
+        })?;
 
         Ok(result.0 > 0)
     }
 
-    /// Check if email exists
+    /// Check if email exists, ignoring case
     pub async fn email_exists(&self, email: &str) -> Result<bool, sqlx::Error> {
-        let result: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users WHERE email = ?")
-            .bind(email)
-            .fetch_one(&self.pool)
-            .await?;
+        let result: (i64,) = with_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT COUNT(*) FROM users WHERE LOWER(email) = LOWER($1)")
+                .bind(email)
+                .fetch_one(pool)
+                .await
+        })?;
 
         Ok(result.0 > 0)
     }
 
-    // ==================== Item Operations ====================
+    /// Increment a user's failed login counter and return the new count
+    pub async fn record_failed_attempt(&self, user_id: i64) -> Result<i32, sqlx::Error> {
+        let result: (i32,) = with_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                r#"
+                UPDATE users
+                SET failed_attempts = failed_attempts + 1
+                WHERE id = $1
+                RETURNING failed_attempts
+                "#,
+            )
+            .bind(user_id)
+            .fetch_one(pool)
+            .await
+        })?;
 
-    /// Create a new item
-    pub async fn create_item(&self, item: CreateItem) -> Result<Item, sqlx::Error> {
-        let created = sqlx::query_as::<_, Item>(
-            r#"
-            INSERT INTO items (user_id, title, description)
-            VALUES (?, ?, ?)
-            RETURNING id, user_id, title, description, created_at, updated_at
-            "#,
-        )
-        .bind(item.user_id)
-        .bind(&item.title)
-        .bind(&item.description)
-        .fetch_one(&self.pool)
-        .await?;
+        Ok(result.0)
+    }
 
-        Ok(created)
+    /// Lock a user's account until the given time
+    pub async fn lock_user_until(
+        &self,
+        user_id: i64,
+        until: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        with_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE users SET locked_until = $1 WHERE id = $2")
+                .bind(until)
+                .bind(user_id)
+                .execute(pool)
+                .await
+                .map(|_| ())
+        })?;
+
+        Ok(())
     }
 
-    /// Get all items for a user
-    pub async fn get_user_items(&self, user_id: i64) -> Result<Vec<Item>, sqlx::Error> {
-        let items = sqlx::query_as::<_, Item>(
-            r#"
-            SELECT id, user_id, title, description, created_at, updated_at
-            FROM items
-            WHERE user_id = ?
-            ORDER BY created_at DESC
-            "#,
-        )
-        .bind(user_id)
-        .fetch_all(&self.pool)
-        .await?;
+    /// Replace a user's password hash
+    ///
+    /// Sessions are left alone, since the password itself is unchanged when
+    /// a hash is upgraded to stronger settings.
+    pub async fn update_user_password(
+        &self,
+        user_id: i64,
+        password_hash: &str,
+    ) -> Result<(), sqlx::Error> {
+        with_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE users SET password_hash = $1 WHERE id = $2")
+                .bind(password_hash)
+                .bind(user_id)
+                .execute(pool)
+                .await
+                .map(|_| ())
+        })?;
 
-        Ok(items)
+        Ok(())
     }
 
-    /// Get a single item by ID (must belong to user)
-    pub async fn get_item(&self, id: i64, user_id: i64) -> Result<Option<Item>, sqlx::Error> {
-        let item = sqlx::query_as::<_, Item>(
-            r#"
-            SELECT id, user_id, title, description, created_at, updated_at
-            FROM items
-            WHERE id = ? AND user_id = ?
-            "#,
-        )
-        .bind(id)
-        .bind(user_id)
-        .fetch_optional(&self.pool)
-        .await?;
+    /// Clear a user's failed login counter and any lock
+    pub async fn reset_failed_attempts(&self, user_id: i64) -> Result<(), sqlx::Error> {
+        with_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE users SET failed_attempts = 0, locked_until = NULL WHERE id = $1")
+                .bind(user_id)
+                .execute(pool)
+                .await
+                .map(|_| ())
+        })?;
 
-        Ok(item)
+        Ok(())
     }
 
-    /// Update an item
-    pub async fn update_item(
+    /// Increment a user's token version, invalidating every issued access token
+    pub async fn bump_token_version(&self, user_id: i64) -> Result<i32, sqlx::Error> {
+        let result: (i32,) = with_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                r#"
+                UPDATE users
+                SET token_version = token_version + 1
+                WHERE id = $1
+                RETURNING token_version
+                "#,
+            )
+            .bind(user_id)
+            .fetch_one(pool)
+            .await
+        })?;
+
+        Ok(result.0)
+    }
+
+    /// Update a user's username and timezone, returning the updated user
+    ///
+    /// The email address only changes through `update_user_email`, once the
+    /// new one is confirmed.
+    pub async fn update_user_profile(
         &self,
-        id: i64,
         user_id: i64,
-        title: &str,
-        description: Option<&str>,
-    ) -> Result<Option<Item>, sqlx::Error> {
-        let item = sqlx::query_as::<_, Item>(
-            r#"
-            UPDATE items
-            SET title = ?, description = ?, updated_at = CURRENT_TIMESTAMP
-            WHERE id = ? AND user_id = ?
-            RETURNING id, user_id, title, description, created_at, updated_at
-            "#,
-        )
-        .bind(title)
-        .bind(description)
-        .bind(id)
-        .bind(user_id)
-        .fetch_optional(&self.pool)
-        .await?;
+        username: &str,
+        timezone: &str,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let user = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, User>(
+                r#"
+                UPDATE users
+                SET username = $1, timezone = $2
+                WHERE id = $3
+                RETURNING id, username, email, password_hash, failed_attempts, locked_until, token_version,
+                    verified, role, timezone, created_at, last_login_at
+                "#,
+            )
+            .bind(username)
+            .bind(timezone)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await
+        })?;
 
-        Ok(item)
+        Ok(user)
     }
 
-    /// Delete an item
-    pub async fn delete_item(&self, id: i64, user_id: i64) -> Result<bool, sqlx::Error> {
-        let result = sqlx::query("DELETE FROM items WHERE id = ? AND user_id = ?")
-            .bind(id)
+    /// Switch a user's email address, once the new one is confirmed; returns
+    /// false if the user is gone
+    ///
+    /// Fails with a unique violation when another account registered the
+    /// address in the meantime.
+    pub async fn update_user_email(&self, user_id: i64, email: &str) -> Result<bool, sqlx::Error> {
+        let rows_affected = with_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE users SET email = $1 WHERE id = $2")
+                .bind(email)
+                .bind(user_id)
+                .execute(pool)
+                .await
+                .map(|result| result.rows_affected())
+        })?;
+
+        Ok(rows_affected > 0)
+    }
+
+    /// Delete a user; their items and tokens go with them through `ON DELETE CASCADE`
+    ///
+    /// Each of their items, trashed or not, is audited as purged first.
+    pub async fn delete_user(&self, user_id: i64) -> Result<bool, sqlx::Error> {
+        let mut tx = self.begin().await?;
+        let items = with_tx!(&mut tx, conn => {
+            sqlx::query_as::<_, Item>(
+                r#"
+                SELECT id, user_id, title, description, created_at, updated_at, deleted_at, archived, due_at
+                FROM items
+                WHERE user_id = $1
+                ORDER BY id
+                "#,
+            )
             .bind(user_id)
-            .execute(&self.pool)
-            .await?;
+            .fetch_all(&mut *conn)
+            .await
+        })?;
+        for item in &items {
+            tx.record_audit(item.id, user_id, AuditAction::Purge, Some(item), None)
+                .await?;
+        }
+        let rows_affected = with_tx!(&mut tx, conn => {
+            sqlx::query("DELETE FROM users WHERE id = $1")
+                .bind(user_id)
+                .execute(&mut *conn)
+                .await
+                .map(|result| result.rows_affected())
+        })?;
+        if rows_affected == 0 {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+        tx.commit().await?;
 
-        Ok(result.rows_affected() > 0)
+        Ok(true)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::Database;
-    use crate::models::CreateItem;
-    use std::path::PathBuf;
-    use std::time::{SystemTime, UNIX_EPOCH};
+    /// List every user, oldest first
+    pub async fn list_all_users(&self) -> Result<Vec<User>, sqlx::Error> {
+        let users = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, User>(
+                r#"
+                SELECT id, username, email, password_hash, failed_attempts, locked_until, token_version,
+                    verified, role, timezone, created_at, last_login_at
+                FROM users
+                ORDER BY id
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        })?;
 
-    async fn setup_test_db() -> (Database, PathBuf) {
-        let mut path = std::env::temp_dir();
-        let nanos = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
-        path.push(format!("basic_crud_ops_test_{}.db", nanos));
+        Ok(users)
+    }
 
-        let url = format!("sqlite:{}?mode=rwc", path.display());
-        let db = Database::new(&url).await.expect("create test db");
-        (db, path)
+    /// Whether any account exists yet
+    pub async fn has_users(&self) -> Result<bool, sqlx::Error> {
+        let (exists,): (bool,) = with_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT EXISTS (SELECT 1 FROM users)")
+                .fetch_one(pool)
+                .await
+        })?;
+
+        Ok(exists)
     }
 
-    fn cleanup_db(path: PathBuf) {
-        let _ = std::fs::remove_file(path);
+    /// Mark a user's email address as verified
+    pub async fn verify_user(&self, user_id: i64) -> Result<bool, sqlx::Error> {
+        let rows_affected = with_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE users SET verified = TRUE WHERE id = $1")
+                .bind(user_id)
+                .execute(pool)
+                .await
+                .map(|result| result.rows_affected())
+        })?;
+
+        Ok(rows_affected > 0)
     }
 
-    #[tokio::test]
-    async fn user_queries_work() {
-        let (db, path) = setup_test_db().await;
+    // ==================== Email Verification Operations ====================
 
-        let user = db
-            .create_user("alice", "alice@example.com", "hash")
+    /// Store a new email verification token hash for a user
+    pub async fn create_verification_token(
+        &self,
+        user_id: i64,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        with_pool!(&self.pool, pool => {
+            sqlx::query(
+                r#"
+                INSERT INTO email_verification_tokens (user_id, token_hash, expires_at)
+                VALUES ($1, $2, $3)
+                "#,
+            )
+            .bind(user_id)
+            .bind(token_hash)
+            .bind(expires_at)
+            .execute(pool)
             .await
-            .expect("create user");
+            .map(|_| ())
+        })?;
 
-        let by_username = db
-            .find_user_by_username("alice")
+        Ok(())
+    }
+
+    /// Consume a verification token, returning its user if it had not expired
+    ///
+    /// The token is deleted either way, so each link works at most once.
+    pub async fn consume_verification_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        let row: Option<(i64, DateTime<Utc>)> = with_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                r#"
+                DELETE FROM email_verification_tokens
+                WHERE token_hash = $1
+                RETURNING user_id, expires_at
+                "#,
+            )
+            .bind(token_hash)
+            .fetch_optional(pool)
             .await
-            .expect("find by username")
-            .expect("user exists");
+        })?;
 
-        assert_eq!(user.id, by_username.id);
+        Ok(row
+            .filter(|(_, expires_at)| *expires_at > Utc::now())
+            .map(|(user_id, _)| user_id))
+    }
 
-        let by_id = db
-            .find_user_by_id(user.id)
-            .await
-            .expect("find by id")
-            .expect("user exists");
+    /// Delete every outstanding verification token of a user
+    pub async fn delete_verification_tokens(&self, user_id: i64) -> Result<(), sqlx::Error> {
+        with_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM email_verification_tokens WHERE user_id = $1")
+                .bind(user_id)
+                .execute(pool)
+                .await
+                .map(|_| ())
+        })?;
 
-        assert_eq!(by_id.username, "alice");
-        assert!(db.username_exists("alice").await.expect("username exists"));
-        assert!(db
-            .email_exists("alice@example.com")
+        Ok(())
+    }
+
+    // ==================== Email Change Operations ====================
+
+    /// Store a user's pending change to `new_email`, replacing the one before
+    ///
+    /// Returns false, storing nothing, while the address belongs to an
+    /// account or is pending for another user; expired changes no longer
+    /// hold theirs.
+    pub async fn create_email_change(
+        &self,
+        user_id: i64,
+        new_email: &str,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tx = self.begin().await?;
+        with_tx!(&mut tx, conn => {
+            sqlx::query("DELETE FROM email_changes WHERE expires_at <= $1")
+                .bind(Utc::now())
+                .execute(&mut *conn)
+                .await
+                .map(|_| ())
+        })?;
+        let (taken,): (i64,) = with_tx!(&mut tx, conn => {
+            sqlx::query_as(
+                r#"
+                SELECT (SELECT COUNT(*) FROM users WHERE LOWER(email) = LOWER($1))
+                    + (SELECT COUNT(*) FROM email_changes
+                       WHERE LOWER(new_email) = LOWER($1) AND user_id <> $2)
+                "#,
+            )
+            .bind(new_email)
+            .bind(user_id)
+            .fetch_one(&mut *conn)
             .await
-            .expect("email exists"));
+        })?;
+        if taken > 0 {
+            return Ok(false);
+        }
+        with_tx!(&mut tx, conn => {
+            sqlx::query(
+                r#"
+                INSERT INTO email_changes (user_id, new_email, token_hash, expires_at)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (user_id)
+                DO UPDATE SET new_email = excluded.new_email, token_hash = excluded.token_hash,
+                    expires_at = excluded.expires_at, created_at = CURRENT_TIMESTAMP
+                "#,
+            )
+            .bind(user_id)
+            .bind(new_email)
+            .bind(token_hash)
+            .bind(expires_at)
+            .execute(&mut *conn)
+            .await
+            .map(|_| ())
+        })?;
+        tx.commit().await?;
 
-        cleanup_db(path);
+        Ok(true)
     }
 
-    #[tokio::test]
-    async fn item_crud_works() {
-        let (db, path) = setup_test_db().await;
+    /// The address a user's unexpired email change would switch to
+    pub async fn pending_email_change(&self, user_id: i64) -> Result<Option<String>, sqlx::Error> {
+        let row: Option<(String,)> = with_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                "SELECT new_email FROM email_changes WHERE user_id = $1 AND expires_at > $2",
+            )
+            .bind(user_id)
+            .bind(Utc::now())
+            .fetch_optional(pool)
+            .await
+        })?;
 
-        let user = db
-            .create_user("bob", "bob@example.com", "hash")
+        Ok(row.map(|(new_email,)| new_email))
+    }
+
+    /// Consume an email change token, returning its user and new address if
+    /// it had not expired
+    ///
+    /// The change is deleted either way, so each link works at most once;
+    /// `update_user_email` then applies it.
+    pub async fn consume_email_change(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<(i64, String)>, sqlx::Error> {
+        let row: Option<(i64, String, DateTime<Utc>)> = with_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                r#"
+                DELETE FROM email_changes
+                WHERE token_hash = $1
+                RETURNING user_id, new_email, expires_at
+                "#,
+            )
+            .bind(token_hash)
+            .fetch_optional(pool)
             .await
-            .expect("create user");
+        })?;
 
-        let created = db
+        Ok(row
+            .filter(|(_, _, expires_at)| *expires_at > Utc::now())
+            .map(|(user_id, new_email, _)| (user_id, new_email)))
+    }
+
+    // ==================== Refresh Token Operations ====================
+
+    /// Store a new refresh token hash for a user
+    pub async fn create_refresh_token(
+        &self,
+        user_id: i64,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+        session_expires_at: DateTime<Utc>,
+        remember: bool,
+    ) -> Result<RefreshToken, sqlx::Error> {
+        let token = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, RefreshToken>(
+                r#"
+                INSERT INTO refresh_tokens (user_id, token_hash, expires_at, session_expires_at, remember)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING id, user_id, token_hash, expires_at, revoked_at, remember,
+                    session_expires_at, created_at
+                "#,
+            )
+            .bind(user_id)
+            .bind(token_hash)
+            .bind(expires_at)
+            .bind(session_expires_at)
+            .bind(remember)
+            .fetch_one(pool)
+            .await
+        })?;
+
+        Ok(token)
+    }
+
+    /// Find a refresh token by its hash, revoked or not
+    pub async fn find_refresh_token(
+        &self,
+        token_hash: &str,
+    ) -> Result<Option<RefreshToken>, sqlx::Error> {
+        let token = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, RefreshToken>(
+                r#"
+                SELECT id, user_id, token_hash, expires_at, revoked_at, remember,
+                    session_expires_at, created_at
+                FROM refresh_tokens
+                WHERE token_hash = $1
+                "#,
+            )
+            .bind(token_hash)
+            .fetch_optional(pool)
+            .await
+        })?;
+
+        Ok(token)
+    }
+
+    /// Revoke a refresh token; returns false if it was already revoked
+    pub async fn revoke_refresh_token(&self, id: i64) -> Result<bool, sqlx::Error> {
+        let rows_affected = with_pool!(&self.pool, pool => {
+            sqlx::query(
+                "UPDATE refresh_tokens SET revoked_at = CURRENT_TIMESTAMP WHERE id = $1 AND revoked_at IS NULL",
+            )
+            .bind(id)
+            .execute(pool)
+            .await
+            .map(|result| result.rows_affected())
+        })?;
+
+        Ok(rows_affected > 0)
+    }
+
+    /// Revoke every active refresh token belonging to a user
+    pub async fn revoke_user_refresh_tokens(&self, user_id: i64) -> Result<u64, sqlx::Error> {
+        let rows_affected = with_pool!(&self.pool, pool => {
+            sqlx::query(
+                "UPDATE refresh_tokens SET revoked_at = CURRENT_TIMESTAMP WHERE user_id = $1 AND revoked_at IS NULL",
+            )
+            .bind(user_id)
+            .execute(pool)
+            .await
+            .map(|result| result.rows_affected())
+        })?;
+
+        Ok(rows_affected)
+    }
+
+    // ==================== Expired Token Cleanup ====================
+
+    /// Delete every token that expired by `now`: email verification and
+    /// email change links, item invitations, and refresh tokens past either
+    /// of their expiries; returns how many rows went
+    ///
+    /// Revoked refresh tokens are kept until they expire, so reuse of one is
+    /// still noticed.
+    pub async fn purge_expired_tokens(&self, now: DateTime<Utc>) -> Result<u64, sqlx::Error> {
+        const PURGES: [&str; 4] = [
+            "DELETE FROM email_verification_tokens WHERE expires_at <= $1",
+            "DELETE FROM email_changes WHERE expires_at <= $1",
+            "DELETE FROM item_invitations WHERE expires_at <= $1",
+            "DELETE FROM refresh_tokens WHERE expires_at <= $1 OR session_expires_at <= $1",
+        ];
+
+        let mut removed = 0;
+        for sql in PURGES {
+            removed += self
+                .retry_busy(|| async move {
+                    with_pool!(&self.pool, pool => {
+                        sqlx::query(sql)
+                            .bind(now)
+                            .execute(pool)
+                            .await
+                            .map(|result| result.rows_affected())
+                    })
+                })
+                .await?;
+        }
+
+        Ok(removed)
+    }
+
+    // ==================== API Key Operations ====================
+
+    /// Store a new API key hash for a user
+    pub async fn create_api_key(
+        &self,
+        user_id: i64,
+        key_hash: &str,
+        name: &str,
+    ) -> Result<ApiKey, sqlx::Error> {
+        let key = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, ApiKey>(
+                r#"
+                INSERT INTO api_keys (user_id, key_hash, name)
+                VALUES ($1, $2, $3)
+                RETURNING id, user_id, key_hash, name, created_at, last_used_at
+                "#,
+            )
+            .bind(user_id)
+            .bind(key_hash)
+            .bind(name)
+            .fetch_one(pool)
+            .await
+        })?;
+
+        Ok(key)
+    }
+
+    /// Get a user's API keys, newest first
+    pub async fn list_api_keys(&self, user_id: i64) -> Result<Vec<ApiKey>, sqlx::Error> {
+        let keys = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, ApiKey>(
+                r#"
+                SELECT id, user_id, key_hash, name, created_at, last_used_at
+                FROM api_keys
+                WHERE user_id = $1
+                ORDER BY created_at DESC, id DESC
+                "#,
+            )
+            .bind(user_id)
+            .fetch_all(pool)
+            .await
+        })?;
+
+        Ok(keys)
+    }
+
+    /// Find an API key by its hash
+    pub async fn find_api_key(&self, key_hash: &str) -> Result<Option<ApiKey>, sqlx::Error> {
+        let key = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, ApiKey>(
+                r#"
+                SELECT id, user_id, key_hash, name, created_at, last_used_at
+                FROM api_keys
+                WHERE key_hash = $1
+                "#,
+            )
+            .bind(key_hash)
+            .fetch_optional(pool)
+            .await
+        })?;
+
+        Ok(key)
+    }
+
+    /// Record that an API key was just used
+    pub async fn touch_api_key(&self, id: i64) -> Result<(), sqlx::Error> {
+        with_pool!(&self.pool, pool => {
+            sqlx::query("UPDATE api_keys SET last_used_at = CURRENT_TIMESTAMP WHERE id = $1")
+                .bind(id)
+                .execute(pool)
+                .await
+                .map(|_| ())
+        })?;
+
+        Ok(())
+    }
+
+    /// Revoke one of a user's API keys; returns false if it does not exist
+    /// or belongs to someone else
+    pub async fn delete_api_key(&self, id: i64, user_id: i64) -> Result<bool, sqlx::Error> {
+        let rows_affected = with_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM api_keys WHERE id = $1 AND user_id = $2")
+                .bind(id)
+                .bind(user_id)
+                .execute(pool)
+                .await
+                .map(|result| result.rows_affected())
+        })?;
+
+        Ok(rows_affected > 0)
+    }
+
+    // ==================== Item Invitation Operations ====================
+
+    /// Store an invitation for `email` to take over an item
+    pub async fn create_invitation(
+        &self,
+        item_id: i64,
+        sender_id: i64,
+        email: &str,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<ItemInvitation, sqlx::Error> {
+        let invitation = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, ItemInvitation>(
+                r#"
+                INSERT INTO item_invitations (item_id, sender_id, email, token_hash, expires_at)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING id, item_id, sender_id, email, token_hash, expires_at, created_at
+                "#,
+            )
+            .bind(item_id)
+            .bind(sender_id)
+            .bind(email)
+            .bind(token_hash)
+            .bind(expires_at)
+            .fetch_one(pool)
+            .await
+        })?;
+
+        Ok(invitation)
+    }
+
+    /// Get the invitations for an item that have not expired, newest first
+    pub async fn list_item_invitations(
+        &self,
+        item_id: i64,
+    ) -> Result<Vec<ItemInvitation>, sqlx::Error> {
+        let invitations = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, ItemInvitation>(
+                r#"
+                SELECT id, item_id, sender_id, email, token_hash, expires_at, created_at
+                FROM item_invitations
+                WHERE item_id = $1 AND expires_at > $2
+                ORDER BY created_at DESC, id DESC
+                "#,
+            )
+            .bind(item_id)
+            .bind(Utc::now())
+            .fetch_all(pool)
+            .await
+        })?;
+
+        Ok(invitations)
+    }
+
+    /// Cancel an invitation, returning the id of its item; `None` if it does
+    /// not exist or someone else sent it
+    pub async fn delete_invitation(
+        &self,
+        id: i64,
+        sender_id: i64,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        let item_id: Option<(i64,)> = with_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                "DELETE FROM item_invitations WHERE id = $1 AND sender_id = $2 RETURNING item_id",
+            )
+            .bind(id)
+            .bind(sender_id)
+            .fetch_optional(pool)
+            .await
+        })?;
+
+        Ok(item_id.map(|(item_id,)| item_id))
+    }
+
+    /// Consume the invitation with `token_hash` if it was sent to `email`
+    ///
+    /// Expired invitations are returned too, and deleted like the rest, so
+    /// callers must check `ItemInvitation::is_active`. An invitation for
+    /// another address is left alone.
+    pub async fn take_invitation(
+        &self,
+        token_hash: &str,
+        email: &str,
+    ) -> Result<Option<ItemInvitation>, sqlx::Error> {
+        let invitation = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, ItemInvitation>(
+                r#"
+                DELETE FROM item_invitations
+                WHERE token_hash = $1 AND LOWER(email) = LOWER($2)
+                RETURNING id, item_id, sender_id, email, token_hash, expires_at, created_at
+                "#,
+            )
+            .bind(token_hash)
+            .bind(email)
+            .fetch_optional(pool)
+            .await
+        })?;
+
+        Ok(invitation)
+    }
+
+    /// Delete every outstanding invitation for an item
+    pub async fn delete_item_invitations(&self, item_id: i64) -> Result<(), sqlx::Error> {
+        with_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM item_invitations WHERE item_id = $1")
+                .bind(item_id)
+                .execute(pool)
+                .await
+                .map(|_| ())
+        })?;
+
+        Ok(())
+    }
+
+    // ==================== Feed Token Operations ====================
+
+    /// Store the hash of a user's feed token, replacing the one before
+    pub async fn set_feed_token(&self, user_id: i64, token_hash: &str) -> Result<(), sqlx::Error> {
+        with_pool!(&self.pool, pool => {
+            sqlx::query(
+                r#"
+                INSERT INTO feed_tokens (user_id, token_hash)
+                VALUES ($1, $2)
+                ON CONFLICT (user_id)
+                DO UPDATE SET token_hash = excluded.token_hash, created_at = CURRENT_TIMESTAMP
+                "#,
+            )
+            .bind(user_id)
+            .bind(token_hash)
+            .execute(pool)
+            .await
+            .map(|_| ())
+        })?;
+
+        Ok(())
+    }
+
+    /// When the user's feed token was created; `None` without one
+    pub async fn feed_token_created_at(
+        &self,
+        user_id: i64,
+    ) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+        let row: Option<(DateTime<Utc>,)> = with_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT created_at FROM feed_tokens WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_optional(pool)
+                .await
+        })?;
+
+        Ok(row.map(|(created_at,)| created_at))
+    }
+
+    /// User whose feed token has the given hash
+    pub async fn find_feed_token_user(&self, token_hash: &str) -> Result<Option<i64>, sqlx::Error> {
+        let row: Option<(i64,)> = with_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT user_id FROM feed_tokens WHERE token_hash = $1")
+                .bind(token_hash)
+                .fetch_optional(pool)
+                .await
+        })?;
+
+        Ok(row.map(|(user_id,)| user_id))
+    }
+
+    /// Turn off a user's feed; returns false if it was not on
+    pub async fn delete_feed_token(&self, user_id: i64) -> Result<bool, sqlx::Error> {
+        let rows_affected = with_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM feed_tokens WHERE user_id = $1")
+                .bind(user_id)
+                .execute(pool)
+                .await
+                .map(|result| result.rows_affected())
+        })?;
+
+        Ok(rows_affected > 0)
+    }
+
+    // ==================== Login History Operations ====================
+
+    /// Record a successful login from `ip_address` and set the user's
+    /// `last_login_at`, first pruning their events from before `keep_since`
+    pub async fn record_login(
+        &self,
+        user_id: i64,
+        ip_address: &str,
+        user_agent: Option<&str>,
+        keep_since: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.begin().await?;
+        tx.record_login(user_id, ip_address, user_agent, keep_since)
+            .await?;
+        tx.commit().await
+    }
+
+    /// A user's `limit` most recent logins, newest first
+    pub async fn recent_logins(
+        &self,
+        user_id: i64,
+        limit: i64,
+    ) -> Result<Vec<LoginEvent>, sqlx::Error> {
+        let events = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, LoginEvent>(
+                r#"
+                SELECT id, user_id, ip_address, user_agent, created_at
+                FROM login_events
+                WHERE user_id = $1
+                ORDER BY created_at DESC, id DESC
+                LIMIT $2
+                "#,
+            )
+            .bind(user_id)
+            .bind(limit)
+            .fetch_all(pool)
+            .await
+        })?;
+
+        Ok(events)
+    }
+
+    // ==================== Passkey Operations ====================
+
+    /// Store a passkey for a user, with the counter it was registered with
+    pub async fn create_passkey(
+        &self,
+        user_id: i64,
+        credential_id: &str,
+        public_key: &str,
+        sign_count: i64,
+        name: &str,
+    ) -> Result<Passkey, sqlx::Error> {
+        let passkey = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Passkey>(
+                r#"
+                INSERT INTO webauthn_credentials (user_id, credential_id, public_key, sign_count, name)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING id, user_id, credential_id, public_key, sign_count, name, created_at, last_used_at
+                "#,
+            )
+            .bind(user_id)
+            .bind(credential_id)
+            .bind(public_key)
+            .bind(sign_count)
+            .bind(name)
+            .fetch_one(pool)
+            .await
+        })?;
+
+        Ok(passkey)
+    }
+
+    /// Get a user's passkeys, newest first
+    pub async fn list_passkeys(&self, user_id: i64) -> Result<Vec<Passkey>, sqlx::Error> {
+        let passkeys = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Passkey>(
+                r#"
+                SELECT id, user_id, credential_id, public_key, sign_count, name, created_at, last_used_at
+                FROM webauthn_credentials
+                WHERE user_id = $1
+                ORDER BY created_at DESC, id DESC
+                "#,
+            )
+            .bind(user_id)
+            .fetch_all(pool)
+            .await
+        })?;
+
+        Ok(passkeys)
+    }
+
+    /// Find a passkey by the credential ID its authenticator sends
+    pub async fn find_passkey(&self, credential_id: &str) -> Result<Option<Passkey>, sqlx::Error> {
+        let passkey = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Passkey>(
+                r#"
+                SELECT id, user_id, credential_id, public_key, sign_count, name, created_at, last_used_at
+                FROM webauthn_credentials
+                WHERE credential_id = $1
+                "#,
+            )
+            .bind(credential_id)
+            .fetch_optional(pool)
+            .await
+        })?;
+
+        Ok(passkey)
+    }
+
+    /// Record a login with a passkey that reported `sign_count`
+    ///
+    /// Authenticators with a counter raise it on every use, so a count that
+    /// did not go up past the stored one means another copy of the key has
+    /// been used; then nothing is recorded and false is returned. Counters
+    /// that stay at 0 belong to authenticators that keep none. The check is
+    /// part of the update, so two logins racing with one count cannot both
+    /// pass it.
+    pub async fn record_passkey_use(&self, id: i64, sign_count: i64) -> Result<bool, sqlx::Error> {
+        let rows_affected = with_pool!(&self.pool, pool => {
+            sqlx::query(
+                r#"
+                UPDATE webauthn_credentials
+                SET sign_count = $1, last_used_at = CURRENT_TIMESTAMP
+                WHERE id = $2 AND (sign_count < $1 OR (sign_count = 0 AND $1 = 0))
+                "#,
+            )
+            .bind(sign_count)
+            .bind(id)
+            .execute(pool)
+            .await
+            .map(|result| result.rows_affected())
+        })?;
+
+        Ok(rows_affected > 0)
+    }
+
+    /// Remove one of a user's passkeys; returns false if it does not exist
+    /// or belongs to someone else
+    pub async fn delete_passkey(&self, id: i64, user_id: i64) -> Result<bool, sqlx::Error> {
+        let rows_affected = with_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM webauthn_credentials WHERE id = $1 AND user_id = $2")
+                .bind(id)
+                .bind(user_id)
+                .execute(pool)
+                .await
+                .map(|result| result.rows_affected())
+        })?;
+
+        Ok(rows_affected > 0)
+    }
+
+    // ==================== Registration Invite Operations ====================
+
+    /// Store a registration invite created by the admin `created_by`
+    pub async fn create_invite(
+        &self,
+        code_hash: &str,
+        created_by: i64,
+    ) -> Result<Invite, sqlx::Error> {
+        let invite = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Invite>(
+                r#"
+                INSERT INTO invites (code_hash, created_by)
+                VALUES ($1, $2)
+                RETURNING id, code_hash, created_by, created_at, used_by, used_at
+                "#,
+            )
+            .bind(code_hash)
+            .bind(created_by)
+            .fetch_one(pool)
+            .await
+        })?;
+
+        Ok(invite)
+    }
+
+    /// Get every registration invite, unused ones first, newest first
+    pub async fn list_invites(&self) -> Result<Vec<Invite>, sqlx::Error> {
+        let invites = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Invite>(
+                r#"
+                SELECT id, code_hash, created_by, created_at, used_by, used_at
+                FROM invites
+                ORDER BY used_at IS NOT NULL, created_at DESC, id DESC
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        })?;
+
+        Ok(invites)
+    }
+
+    /// Whether `code_hash` belongs to an invite nobody has used yet
+    pub async fn invite_is_unused(&self, code_hash: &str) -> Result<bool, sqlx::Error> {
+        let (unused,): (bool,) = with_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                "SELECT EXISTS (SELECT 1 FROM invites WHERE code_hash = $1 AND used_at IS NULL)",
+            )
+            .bind(code_hash)
+            .fetch_one(pool)
+            .await
+        })?;
+
+        Ok(unused)
+    }
+
+    /// Revoke an invite nobody has used yet
+    pub async fn delete_invite(&self, id: i64) -> Result<bool, sqlx::Error> {
+        let rows_affected = with_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM invites WHERE id = $1 AND used_at IS NULL")
+                .bind(id)
+                .execute(pool)
+                .await
+                .map(|result| result.rows_affected())
+        })?;
+
+        Ok(rows_affected > 0)
+    }
+
+    // ==================== Item Operations ====================
+
+    /// Create a new item
+    pub async fn create_item(&self, item: CreateItem) -> Result<Item, sqlx::Error> {
+        self.check_item_limits(&item.title, item.description.as_deref())?;
+        let item = &item;
+        self.retry_busy(|| async move {
+            let mut tx = self.begin().await?;
+            let created = tx.create_item(item.clone()).await?;
+            tx.commit().await?;
+            Ok(created)
+        })
+        .await
+    }
+
+    /// Create an item together with its tags; neither is saved if either fails
+    pub async fn create_item_with_tags(
+        &self,
+        item: CreateItem,
+        tags: Vec<String>,
+    ) -> Result<Item, sqlx::Error> {
+        self.check_item_limits(&item.title, item.description.as_deref())?;
+        let mut tx = self.begin().await?;
+        let mut created = tx.create_item(item).await?;
+        tx.set_item_tags(created.id, &tags).await?;
+        tx.commit().await?;
+        created.tags = tags;
+        Ok(created)
+    }
+
+    /// Create several items with their tags in one transaction; if any of
+    /// them fails, none is saved
+    pub async fn create_items_with_tags(
+        &self,
+        items: Vec<(CreateItem, Vec<String>)>,
+    ) -> Result<Vec<Item>, sqlx::Error> {
+        for (item, _) in &items {
+            self.check_item_limits(&item.title, item.description.as_deref())?;
+        }
+        let mut tx = self.begin().await?;
+        let mut created = Vec::with_capacity(items.len());
+        for (item, tags) in items {
+            let mut item = tx.create_item(item).await?;
+            tx.set_item_tags(item.id, &tags).await?;
+            item.tags = tags;
+            created.push(item);
+        }
+        tx.commit().await?;
+        Ok(created)
+    }
+
+    /// Create an item with its tags like `create_item_with_tags`, unless the
+    /// user already created one with the same idempotency key in the last
+    /// `window_secs`; returns the item's ID and whether it is new
+    ///
+    /// Older keys are forgotten and may be reused. When two requests with the
+    /// same key race, the later one gets the item of the first.
+    pub async fn create_item_once(
+        &self,
+        item: CreateItem,
+        tags: Vec<String>,
+        key: &str,
+        window_secs: i64,
+    ) -> Result<(i64, bool), sqlx::Error> {
+        self.check_item_limits(&item.title, item.description.as_deref())?;
+        let user_id = item.user_id;
+        if let Some(item_id) = self.find_idempotent_item(user_id, key, window_secs).await? {
+            return Ok((item_id, false));
+        }
+
+        let mut tx = self.begin().await?;
+        let created = tx.create_item(item).await?;
+        tx.set_item_tags(created.id, &tags).await?;
+        match tx.record_idempotency_key(user_id, key, created.id).await {
+            Ok(()) => {
+                tx.commit().await?;
+                Ok((created.id, true))
+            }
+            Err(e)
+                if e.as_database_error()
+                    .is_some_and(|e| e.is_unique_violation()) =>
+            {
+                tx.rollback().await?;
+                match self.find_idempotent_item(user_id, key, window_secs).await? {
+                    Some(item_id) => Ok((item_id, false)),
+                    None => Err(e),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Item a user created with an idempotency key in the last
+    /// `window_secs`; an older record of the key is deleted
+    pub async fn find_idempotent_item(
+        &self,
+        user_id: i64,
+        key: &str,
+        window_secs: i64,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        let row: Option<(i64, i64, DateTime<Utc>)> = with_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                r#"
+                SELECT id, item_id, created_at
+                FROM idempotency_keys
+                WHERE user_id = $1 AND idempotency_key = $2
+                "#,
+            )
+            .bind(user_id)
+            .bind(key)
+            .fetch_optional(pool)
+            .await
+        })?;
+
+        let Some((id, item_id, created_at)) = row else {
+            return Ok(None);
+        };
+        if created_at > Utc::now() - chrono::Duration::seconds(window_secs) {
+            return Ok(Some(item_id));
+        }
+
+        with_pool!(&self.pool, pool => {
+            sqlx::query("DELETE FROM idempotency_keys WHERE id = $1")
+                .bind(id)
+                .execute(pool)
+                .await
+                .map(|_| ())
+        })?;
+        Ok(None)
+    }
+
+    /// Get all items for a user
+    pub async fn get_user_items(&self, user_id: i64) -> Result<Vec<Item>, sqlx::Error> {
+        let items = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Item>(
+                r#"
+                SELECT id, user_id, title, description, created_at, updated_at, deleted_at, archived, due_at
+                FROM items
+                WHERE user_id = $1 AND deleted_at IS NULL
+                ORDER BY created_at DESC, id DESC
+                "#,
+            )
+            .bind(user_id)
+            .fetch_all(pool)
+            .await
+        })?;
+
+        Ok(items)
+    }
+
+    /// Get up to `limit` of a user's items, newest first like
+    /// `get_user_items`, starting just after `after`
+    ///
+    /// Ties on `created_at` are broken by `id`, so every item has one place in
+    /// the order and paging visits each exactly once; items added meanwhile
+    /// sort before any cursor and never shift the pages still to come. `tag`
+    /// narrows the list like `get_items_by_tag`.
+    pub async fn get_user_items_after_cursor(
+        &self,
+        user_id: i64,
+        tag: Option<&str>,
+        after: Option<&ItemCursor>,
+        limit: i64,
+    ) -> Result<Vec<Item>, sqlx::Error> {
+        let created_at = self.comparable_timestamp("created_at");
+        let bound = self.comparable_timestamp("$4");
+        let keyset = match after {
+            Some(_) => {
+                format!("AND ({created_at} < {bound} OR ({created_at} = {bound} AND id < $5))")
+            }
+            None => String::new(),
+        };
+        // Only fixed strings are formatted in, never user input
+        let sql = format!(
+            r#"
+            SELECT id, user_id, title, description, created_at, updated_at, deleted_at, archived, due_at
+            FROM items
+            WHERE user_id = $1 AND deleted_at IS NULL
+              AND ($3 IS NULL OR id IN (
+                  SELECT it.item_id FROM item_tags it
+                  JOIN tags t ON t.id = it.tag_id
+                  WHERE t.name = $3
+              ))
+              {keyset}
+            ORDER BY {created_at} DESC, id DESC
+            LIMIT $2
+            "#
+        );
+        let items = with_pool!(&self.pool, pool => {
+            let mut query = sqlx::query_as::<_, Item>(&sql)
+                .bind(user_id)
+                .bind(limit)
+                .bind(tag);
+            if let Some(after) = after {
+                query = query.bind(after.created_at).bind(after.id);
+            }
+            query.fetch_all(pool).await
+        })?;
+
+        Ok(items)
+    }
+
+    /// Get a user's items changed after `since`, oldest change first
+    ///
+    /// Items moved to the trash are included as they are, with `deleted_at`
+    /// set, so sync clients learn of deletions; items purged from the trash
+    /// are gone for good and not reported.
+    pub async fn get_items_updated_since(
+        &self,
+        user_id: i64,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<Item>, sqlx::Error> {
+        let updated_at = self.comparable_timestamp("updated_at");
+        let bound = self.comparable_timestamp("$2");
+        // Only fixed strings are formatted in, never user input
+        let sql = format!(
+            r#"
+            SELECT id, user_id, title, description, created_at, updated_at, deleted_at, archived, due_at
+            FROM items
+            WHERE user_id = $1 AND {updated_at} > {bound}
+            ORDER BY {updated_at} ASC, id ASC
+            "#
+        );
+        let items = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Item>(&sql)
+                .bind(user_id)
+                .bind(since)
+                .fetch_all(pool)
+                .await
+        })?;
+
+        Ok(items)
+    }
+
+    // `expr`, a timestamp column or bound value, in a form that compares and
+    // sorts correctly against the others. SQLite keeps timestamps as text,
+    // written either in its own format or in the one values are bound in, so
+    // both are put in one first.
+    fn comparable_timestamp(&self, expr: &str) -> String {
+        match &self.pool {
+            DbPool::Sqlite(_) => format!("strftime('%Y-%m-%d %H:%M:%f', {})", expr),
+            DbPool::Postgres(_) => expr.to_string(),
+        }
+    }
+
+    /// Get one page of a user's active items in the given order
+    pub async fn get_user_items_sorted(
+        &self,
+        user_id: i64,
+        sort: ItemSort,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Item>, sqlx::Error> {
+        // `order_by` only yields fixed strings, never user input
+        let sql = format!(
+            r#"
+            SELECT id, user_id, title, description, created_at, updated_at, deleted_at, archived, due_at
+            FROM items
+            WHERE user_id = $1 AND deleted_at IS NULL AND archived = FALSE
+            ORDER BY {}
+            LIMIT $2 OFFSET $3
+            "#,
+            sort.order_by()
+        );
+        let items = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Item>(&sql)
+                .bind(user_id)
+                .bind(limit)
+                .bind(offset)
+                .fetch_all(pool)
+                .await
+        })?;
+
+        Ok(items)
+    }
+
+    /// Get one page of a user's items matching `filter`
+    ///
+    /// The keyword matches title or description case-insensitively, with `%`
+    /// and `_` in it matching literally; the tag must match exactly. A
+    /// `full_text` filter matches each word of the keyword, or a word it
+    /// starts, through `items_fts` instead, and can be sorted by relevance.
+    pub async fn search_user_items(
+        &self,
+        user_id: i64,
+        filter: &ItemFilter<'_>,
+        sort: ItemSort,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Item>, sqlx::Error> {
+        let (source, keyword, ranked) = self.search_source(filter);
+        let order_by = match sort.column {
+            SortColumn::Relevance if ranked => "matches.rank ASC, id ASC",
+            _ => sort.order_by(),
+        };
+        // `source` and `order_by` only hold fixed strings, never user input
+        let sql = format!(
+            r#"
+            SELECT id, user_id, title, description, created_at, updated_at, deleted_at, archived, due_at
+            {}
+            ORDER BY {}
+            LIMIT $4 OFFSET $5
+            "#,
+            source, order_by
+        );
+        let items = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Item>(&sql)
+                .bind(user_id)
+                .bind(&keyword)
+                .bind(filter.tag)
+                .bind(limit)
+                .bind(offset)
+                .fetch_all(pool)
+                .await
+        })?;
+
+        Ok(items)
+    }
+
+    /// Count a user's items matching `filter`, as returned by `search_user_items`
+    pub async fn count_search_user_items(
+        &self,
+        user_id: i64,
+        filter: &ItemFilter<'_>,
+    ) -> Result<i64, sqlx::Error> {
+        let (source, keyword, _) = self.search_source(filter);
+        let sql = format!("SELECT COUNT(*) {}", source);
+        let result: (i64,) = with_pool!(&self.pool, pool => {
+            sqlx::query_as(&sql)
+                .bind(user_id)
+                .bind(&keyword)
+                .bind(filter.tag)
+                .fetch_one(pool)
+                .await
+        })?;
+
+        Ok(result.0)
+    }
+
+    /// Every item of a user matching the words of `query`, best match first
+    ///
+    /// Ranked by `items_fts`; without it, this is a `LIKE` search, newest
+    /// first.
+    pub async fn fts_search_items(
+        &self,
+        user_id: i64,
+        query: &str,
+    ) -> Result<Vec<Item>, sqlx::Error> {
+        let filter = ItemFilter {
+            query: Some(query),
+            full_text: true,
+            ..Default::default()
+        };
+        self.search_user_items(user_id, &filter, ItemSort::relevance(), i64::MAX, 0)
+            .await
+    }
+
+    // `FROM ... WHERE ...` of a search for `filter` with the keyword to bind
+    // as `$2`, and whether the rows carry a full-text rank
+    fn search_source(&self, filter: &ItemFilter<'_>) -> (String, Option<String>, bool) {
+        let fts_query = filter
+            .query
+            .filter(|_| filter.full_text && self.full_text)
+            .and_then(fts_query);
+        match fts_query {
+            Some(fts_query) => (
+                format!(
+                    r#"
+                    FROM items
+                    JOIN (SELECT rowid AS match_id, rank FROM items_fts WHERE items_fts MATCH $2) AS matches
+                        ON matches.match_id = items.id
+                    WHERE user_id = $1 AND deleted_at IS NULL AND {} AND {}
+                    "#,
+                    TAG_CONDITION,
+                    archived_condition(filter)
+                ),
+                Some(fts_query),
+                true,
+            ),
+            None => (
+                format!(
+                    "FROM items WHERE user_id = $1 AND deleted_at IS NULL AND {} AND {} AND {}",
+                    KEYWORD_CONDITION,
+                    TAG_CONDITION,
+                    archived_condition(filter)
+                ),
+                filter.query.map(like_pattern),
+                false,
+            ),
+        }
+    }
+
+    /// Count all items belonging to a user, excluding trashed ones
+    pub async fn count_user_items(&self, user_id: i64) -> Result<i64, sqlx::Error> {
+        let result: (i64,) = with_pool!(&self.pool, pool => {
+            sqlx::query_as("SELECT COUNT(*) FROM items WHERE user_id = $1 AND deleted_at IS NULL")
+                .bind(user_id)
+                .fetch_one(pool)
+                .await
+        })?;
+
+        Ok(result.0)
+    }
+
+    /// Items a user created on each of the last `days` days, oldest first
+    ///
+    /// Days run midnight to midnight in `tz` and end with today; days
+    /// without items count 0. Trashed items do not count.
+    pub async fn item_counts_by_day(
+        &self,
+        user_id: i64,
+        days: i64,
+        tz: Tz,
+    ) -> Result<Vec<DayCount>, sqlx::Error> {
+        let today = Utc::now().with_timezone(&tz).date_naive();
+        let first = today - chrono::Duration::days(days - 1);
+        // A day early, so no zone offset can push items out of the range
+        let since = Utc::now() - chrono::Duration::days(days + 1);
+
+        let created: Vec<(DateTime<Utc>,)> = with_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                r#"
+                SELECT created_at
+                FROM items
+                WHERE user_id = $1 AND deleted_at IS NULL AND created_at >= $2
+                "#,
+            )
+            .bind(user_id)
+            .bind(since)
+            .fetch_all(pool)
+            .await
+        })?;
+
+        let mut counts: Vec<DayCount> = first
+            .iter_days()
+            .take(days.max(0) as usize)
+            .map(|day| DayCount { day, count: 0 })
+            .collect();
+        for (created_at,) in created {
+            let day = created_at.with_timezone(&tz).date_naive();
+            if let Ok(index) = usize::try_from((day - first).num_days()) {
+                if let Some(bucket) = counts.get_mut(index) {
+                    bucket.count += 1;
+                }
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Get every item in the system that is not trashed, newest first
+    pub async fn list_all_items(&self) -> Result<Vec<Item>, sqlx::Error> {
+        let items = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Item>(
+                r#"
+                SELECT id, user_id, title, description, created_at, updated_at, deleted_at, archived, due_at
+                FROM items
+                WHERE deleted_at IS NULL
+                ORDER BY created_at DESC, id DESC
+                "#,
+            )
+            .fetch_all(pool)
+            .await
+        })?;
+
+        Ok(items)
+    }
+
+    /// Get a single item by ID (must belong to user and not be trashed)
+    pub async fn get_item(&self, id: i64, user_id: i64) -> Result<Option<Item>, sqlx::Error> {
+        let item = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Item>(
+                r#"
+                SELECT id, user_id, title, description, created_at, updated_at, deleted_at, archived, due_at
+                FROM items
+                WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
+                "#,
+            )
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await
+        })?;
+
+        Ok(item)
+    }
+
+    /// Update an item
+    pub async fn update_item(
+        &self,
+        id: i64,
+        user_id: i64,
+        title: &str,
+        description: Option<&str>,
+        due_at: Option<NaiveDate>,
+    ) -> Result<Option<Item>, sqlx::Error> {
+        self.check_item_limits(title, description)?;
+        self.retry_busy(|| async move {
+            let mut tx = self.begin().await?;
+            let item = tx
+                .update_item(id, user_id, title, description, due_at)
+                .await?;
+            tx.commit().await?;
+            Ok(item)
+        })
+        .await
+    }
+
+    /// Update an item and replace its tags as one change
+    pub async fn update_item_with_tags(
+        &self,
+        id: i64,
+        user_id: i64,
+        title: &str,
+        description: Option<&str>,
+        due_at: Option<NaiveDate>,
+        tags: Vec<String>,
+    ) -> Result<Option<Item>, sqlx::Error> {
+        self.check_item_limits(title, description)?;
+        let mut tx = self.begin().await?;
+        let Some(mut item) = tx
+            .update_item(id, user_id, title, description, due_at)
+            .await?
+        else {
+            return Ok(None);
+        };
+        tx.set_item_tags(item.id, &tags).await?;
+        tx.commit().await?;
+        item.tags = tags;
+        Ok(Some(item))
+    }
+
+    /// Move an item to the trash; it stays restorable until purged
+    pub async fn delete_item(&self, id: i64, user_id: i64) -> Result<bool, sqlx::Error> {
+        self.retry_busy(|| async move {
+            let mut tx = self.begin().await?;
+            if tx.trash_items(user_id, &[id]).await? == 0 {
+                tx.rollback().await?;
+                return Ok(false);
+            }
+            tx.commit().await?;
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Move several items to the trash at once, all or nothing
+    ///
+    /// If any id is not one of the user's live items, nothing is changed and
+    /// `0` is returned; otherwise the number of trashed items.
+    pub async fn delete_items(&self, user_id: i64, ids: &[i64]) -> Result<u64, sqlx::Error> {
+        let mut tx = self.begin().await?;
+        let trashed = tx.trash_items(user_id, ids).await?;
+        if trashed == 0 {
+            tx.rollback().await?;
+        } else {
+            tx.commit().await?;
+        }
+        Ok(trashed)
+    }
+
+    /// Archive several items at once, all or nothing
+    ///
+    /// If any id is not one of the user's live items, nothing is changed and
+    /// `0` is returned; otherwise the number of selected items.
+    pub async fn archive_items(&self, user_id: i64, ids: &[i64]) -> Result<u64, sqlx::Error> {
+        let mut tx = self.begin().await?;
+        let archived = tx.set_items_archived(user_id, ids, true).await?;
+        if archived == 0 {
+            tx.rollback().await?;
+        } else {
+            tx.commit().await?;
+        }
+        Ok(archived)
+    }
+
+    /// Put a user's items `ids` first in their manual order, in the order
+    /// given, followed by the rest of their items as they were
+    ///
+    /// Every live item of the user gets a position, numbered from 0 without
+    /// gaps, so items never placed before keep their place after the listed
+    /// ones. Returns false, changing nothing, when an id is listed twice or
+    /// is not one of the user's items outside the trash.
+    pub async fn reorder_items(&self, user_id: i64, ids: &[i64]) -> Result<bool, sqlx::Error> {
+        let mut tx = self.begin().await?;
+        // `order_by` only yields fixed strings, never user input
+        let sql = format!(
+            "SELECT id, position FROM items WHERE user_id = $1 AND deleted_at IS NULL ORDER BY {}",
+            ItemSort::manual().order_by()
+        );
+        let current: Vec<(i64, Option<i64>)> = with_tx!(&mut tx, conn => {
+            sqlx::query_as(&sql)
+                .bind(user_id)
+                .fetch_all(&mut *conn)
+                .await
+        })?;
+
+        let listed: HashSet<i64> = ids.iter().copied().collect();
+        if listed.len() != ids.len() || !ids.iter().all(|id| current.iter().any(|(c, _)| c == id)) {
+            return Ok(false);
+        }
+        let order = ids.iter().copied().chain(
+            current
+                .iter()
+                .map(|(id, _)| *id)
+                .filter(|id| !listed.contains(id)),
+        );
+
+        for (position, id) in order.enumerate() {
+            let position = position as i64;
+            if current.contains(&(id, Some(position))) {
+                continue;
+            }
+            with_tx!(&mut tx, conn => {
+                sqlx::query("UPDATE items SET position = $1 WHERE id = $2 AND user_id = $3")
+                    .bind(position)
+                    .bind(id)
+                    .bind(user_id)
+                    .execute(&mut *conn)
+                    .await
+                    .map(|_| ())
+            })?;
+        }
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    /// Get a user's trashed items, most recently deleted first
+    pub async fn get_trashed_items(&self, user_id: i64) -> Result<Vec<Item>, sqlx::Error> {
+        let items = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Item>(
+                r#"
+                SELECT id, user_id, title, description, created_at, updated_at, deleted_at, archived, due_at
+                FROM items
+                WHERE user_id = $1 AND deleted_at IS NOT NULL
+                ORDER BY deleted_at DESC, id DESC
+                "#,
+            )
+            .bind(user_id)
+            .fetch_all(pool)
+            .await
+        })?;
+
+        Ok(items)
+    }
+
+    /// Give an item, live or trashed, to another user
+    ///
+    /// Its tags, comments and attachments follow it, but not its share link:
+    /// the new owner decides whether to share it again. Not scoped to an
+    /// owner, so only admin handlers and accepted invitations may call it.
+    pub async fn reassign_item(
+        &self,
+        item_id: i64,
+        new_user_id: i64,
+        actor_id: i64,
+    ) -> Result<ReassignOutcome, sqlx::Error> {
+        if self.find_user_by_id(new_user_id).await?.is_none() {
+            return Ok(ReassignOutcome::UserNotFound);
+        }
+
+        self.retry_busy(|| async move {
+            let mut tx = self.begin().await?;
+            let Some(before) = tx.find_item(item_id).await? else {
+                tx.rollback().await?;
+                return Ok(ReassignOutcome::ItemNotFound);
+            };
+            with_tx!(&mut tx, conn => {
+                sqlx::query(
+                    "UPDATE items SET user_id = $1, shared = FALSE, share_slug = NULL WHERE id = $2",
+                )
+                .bind(new_user_id)
+                .bind(item_id)
+                .execute(&mut *conn)
+                .await
+                .map(|_| ())
+            })?;
+            let Some(after) = tx.find_item(item_id).await? else {
+                tx.rollback().await?;
+                return Ok(ReassignOutcome::ItemNotFound);
+            };
+            tx.record_audit(
+                item_id,
+                actor_id,
+                AuditAction::Reassign,
+                Some(&before),
+                Some(&after),
+            )
+            .await?;
+            tx.commit().await?;
+            Ok(ReassignOutcome::Moved(after))
+        })
+        .await
+    }
+
+    /// Take an item back out of the trash
+    pub async fn restore_item(&self, id: i64, user_id: i64) -> Result<bool, sqlx::Error> {
+        let mut tx = self.begin().await?;
+        let before = match tx.find_item(id).await? {
+            Some(item) if item.user_id == user_id && item.deleted_at.is_some() => item,
+            _ => {
+                tx.rollback().await?;
+                return Ok(false);
+            }
+        };
+        let rows_affected = with_tx!(&mut tx, conn => {
+            sqlx::query(
+                r#"
+                UPDATE items
+                SET deleted_at = NULL
+                WHERE id = $1 AND user_id = $2 AND deleted_at IS NOT NULL
+                "#,
+            )
+            .bind(id)
+            .bind(user_id)
+            .execute(&mut *conn)
+            .await
+            .map(|result| result.rows_affected())
+        })?;
+        if rows_affected == 0 {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+        let after = tx.find_item(id).await?;
+        tx.record_audit(
+            id,
+            user_id,
+            AuditAction::Restore,
+            Some(&before),
+            after.as_ref(),
+        )
+        .await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    /// Archive or unarchive one of a user's live items
+    ///
+    /// Returns false when the user has no such item outside the trash.
+    pub async fn set_item_archived(
+        &self,
+        id: i64,
+        user_id: i64,
+        archived: bool,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tx = self.begin().await?;
+        if tx.set_items_archived(user_id, &[id], archived).await? == 0 {
+            tx.rollback().await?;
+            return Ok(false);
+        }
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    /// Open one of a user's live items at `/share/{slug}`
+    ///
+    /// An item shared before gets its last slug back, and `slug` is only used
+    /// for one that has none. Returns the slug in use, or `None` when the
+    /// user has no such item outside the trash.
+    pub async fn share_item(
+        &self,
+        id: i64,
+        user_id: i64,
+        slug: &str,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let row: Option<(String,)> = with_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                r#"
+                UPDATE items
+                SET shared = TRUE, share_slug = COALESCE(share_slug, $3)
+                WHERE id = $1 AND user_id = $2 AND deleted_at IS NULL
+                RETURNING share_slug
+                "#,
+            )
+            .bind(id)
+            .bind(user_id)
+            .bind(slug)
+            .fetch_optional(pool)
+            .await
+        })?;
+
+        Ok(row.map(|(share_slug,)| share_slug))
+    }
+
+    /// Stop sharing one of a user's items, replacing its slug with
+    /// `new_slug` so the old link stays dead if it is shared again
+    ///
+    /// Returns false when the user has no such shared item.
+    pub async fn unshare_item(
+        &self,
+        id: i64,
+        user_id: i64,
+        new_slug: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let rows_affected = with_pool!(&self.pool, pool => {
+            sqlx::query(
+                r#"
+                UPDATE items
+                SET shared = FALSE, share_slug = $3
+                WHERE id = $1 AND user_id = $2 AND shared = TRUE
+                "#,
+            )
+            .bind(id)
+            .bind(user_id)
+            .bind(new_slug)
+            .execute(pool)
+            .await
+            .map(|result| result.rows_affected())
+        })?;
+
+        Ok(rows_affected > 0)
+    }
+
+    /// Slug one of a user's items is shared under; `None` while it is not
+    /// shared
+    pub async fn item_share_slug(
+        &self,
+        id: i64,
+        user_id: i64,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let row: Option<(String,)> = with_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                "SELECT share_slug FROM items WHERE id = $1 AND user_id = $2 AND shared = TRUE",
+            )
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await
+        })?;
+
+        Ok(row.map(|(share_slug,)| share_slug))
+    }
+
+    /// The user's live shared items, newest first
+    pub async fn list_shared_items(&self, user_id: i64) -> Result<Vec<SharedItem>, sqlx::Error> {
+        let items = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, SharedItem>(
+                r#"
+                SELECT id, title, share_slug
+                FROM items
+                WHERE user_id = $1 AND shared = TRUE AND deleted_at IS NULL
+                ORDER BY id DESC
+                "#,
+            )
+            .bind(user_id)
+            .fetch_all(pool)
+            .await
+        })?;
+
+        Ok(items)
+    }
+
+    /// The live item shared under `slug`, whoever owns it
+    pub async fn find_shared_item(&self, slug: &str) -> Result<Option<Item>, sqlx::Error> {
+        let item = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Item>(
+                r#"
+                SELECT id, user_id, title, description, created_at, updated_at, deleted_at, archived, due_at
+                FROM items
+                WHERE share_slug = $1 AND shared = TRUE AND deleted_at IS NULL
+                "#,
+            )
+            .bind(slug)
+            .fetch_optional(pool)
+            .await
+        })?;
+
+        Ok(item)
+    }
+
+    /// Permanently remove a trashed item; live items must be trashed first
+    ///
+    /// Its audit trail stays behind.
+    pub async fn purge_item(&self, id: i64, user_id: i64) -> Result<bool, sqlx::Error> {
+        let mut tx = self.begin().await?;
+        let Some(item) = tx.find_item(id).await? else {
+            return Ok(false);
+        };
+        let rows_affected = with_tx!(&mut tx, conn => {
+            sqlx::query(
+                "DELETE FROM items WHERE id = $1 AND user_id = $2 AND deleted_at IS NOT NULL",
+            )
+            .bind(id)
+            .bind(user_id)
+            .execute(&mut *conn)
+            .await
+            .map(|result| result.rows_affected())
+        })?;
+        if rows_affected == 0 {
+            return Ok(false);
+        }
+        tx.record_audit(id, user_id, AuditAction::Purge, Some(&item), None)
+            .await?;
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    // ==================== Item Audit Operations ====================
+
+    /// Every recorded change to an item, oldest first, whether or not the
+    /// item still exists
+    pub async fn list_item_audit(&self, item_id: i64) -> Result<Vec<ItemAudit>, sqlx::Error> {
+        let entries = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, ItemAudit>(
+                r#"
+                SELECT id, item_id, user_id, action, before_json, after_json, created_at
+                FROM item_audit
+                WHERE item_id = $1
+                ORDER BY id
+                "#,
+            )
+            .bind(item_id)
+            .fetch_all(pool)
+            .await
+        })?;
+
+        Ok(entries)
+    }
+
+    // ==================== Tag Operations ====================
+
+    /// Replace an item's tags, creating any tag names not seen before
+    pub async fn set_item_tags(&self, item_id: i64, tags: &[String]) -> Result<(), sqlx::Error> {
+        let mut tx = self.begin().await?;
+        tx.set_item_tags(item_id, tags).await?;
+        tx.commit().await
+    }
+
+    /// Get an item's tag names, sorted
+    pub async fn get_item_tags(&self, item_id: i64) -> Result<Vec<String>, sqlx::Error> {
+        let tags: Vec<(String,)> = with_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                r#"
+                SELECT t.name
+                FROM item_tags it
+                JOIN tags t ON t.id = it.tag_id
+                WHERE it.item_id = $1
+                ORDER BY t.name
+                "#,
+            )
+            .bind(item_id)
+            .fetch_all(pool)
+            .await
+        })?;
+
+        Ok(tags.into_iter().map(|(name,)| name).collect())
+    }
+
+    /// Fill in `tags` for each item with a single query
+    pub async fn load_item_tags(&self, items: &mut [Item]) -> Result<(), sqlx::Error> {
+        if items.is_empty() {
+            return Ok(());
+        }
+
+        let sql = format!(
+            r#"
+            SELECT it.item_id, t.name
+            FROM item_tags it
+            JOIN tags t ON t.id = it.tag_id
+            WHERE it.item_id IN ({})
+            ORDER BY t.name
+            "#,
+            placeholders(1, items.len())
+        );
+        let rows: Vec<(i64, String)> = with_pool!(&self.pool, pool => {
+            let mut query = sqlx::query_as(&sql);
+            for item in items.iter() {
+                query = query.bind(item.id);
+            }
+            query.fetch_all(pool).await
+        })?;
+
+        let mut by_item: HashMap<i64, Vec<String>> = HashMap::new();
+        for (item_id, name) in rows {
+            by_item.entry(item_id).or_default().push(name);
+        }
+        for item in items.iter_mut() {
+            item.tags = by_item.remove(&item.id).unwrap_or_default();
+        }
+
+        Ok(())
+    }
+
+    /// Get all of a user's items carrying `tag`, newest first
+    pub async fn get_items_by_tag(
+        &self,
+        user_id: i64,
+        tag: &str,
+    ) -> Result<Vec<Item>, sqlx::Error> {
+        let items = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Item>(
+                r#"
+                SELECT i.id, i.user_id, i.title, i.description, i.created_at, i.updated_at, i.deleted_at, i.archived, i.due_at
+                FROM items i
+                JOIN item_tags it ON it.item_id = i.id
+                JOIN tags t ON t.id = it.tag_id
+                WHERE i.user_id = $1 AND i.deleted_at IS NULL AND t.name = $2
+                ORDER BY i.created_at DESC, i.id DESC
+                "#,
+            )
+            .bind(user_id)
+            .bind(tag)
+            .fetch_all(pool)
+            .await
+        })?;
+
+        Ok(items)
+    }
+
+    /// Get a user's active items due before `today`, most overdue first
+    ///
+    /// `today` is the user's own date, so an item counts as overdue only once
+    /// its due day has ended where they are. Archived items are left out.
+    pub async fn get_overdue_items(
+        &self,
+        user_id: i64,
+        today: NaiveDate,
+    ) -> Result<Vec<Item>, sqlx::Error> {
+        let items = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Item>(
+                r#"
+                SELECT id, user_id, title, description, created_at, updated_at, deleted_at, archived, due_at
+                FROM items
+                WHERE user_id = $1 AND deleted_at IS NULL AND archived = FALSE
+                  AND due_at IS NOT NULL AND due_at < $2
+                ORDER BY due_at ASC, id ASC
+                "#,
+            )
+            .bind(user_id)
+            .bind(today)
+            .fetch_all(pool)
+            .await
+        })?;
+
+        Ok(items)
+    }
+
+    // ==================== Attachment Operations ====================
+
+    /// Record an uploaded file against an item
+    pub async fn create_attachment(
+        &self,
+        attachment: CreateAttachment,
+    ) -> Result<Attachment, sqlx::Error> {
+        let created = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Attachment>(
+                r#"
+                INSERT INTO attachments (item_id, filename, stored_name, content_type, size)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING id, item_id, filename, stored_name, content_type, size, created_at
+                "#,
+            )
+            .bind(attachment.item_id)
+            .bind(&attachment.filename)
+            .bind(&attachment.stored_name)
+            .bind(&attachment.content_type)
+            .bind(attachment.size)
+            .fetch_one(pool)
+            .await
+        })?;
+
+        Ok(created)
+    }
+
+    /// Get an item's attachments, oldest first
+    pub async fn get_item_attachments(&self, item_id: i64) -> Result<Vec<Attachment>, sqlx::Error> {
+        let attachments = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Attachment>(
+                r#"
+                SELECT id, item_id, filename, stored_name, content_type, size, created_at
+                FROM attachments
+                WHERE item_id = $1
+                ORDER BY created_at ASC, id ASC
+                "#,
+            )
+            .bind(item_id)
+            .fetch_all(pool)
+            .await
+        })?;
+
+        Ok(attachments)
+    }
+
+    /// Get an attachment on one of the user's live items
+    pub async fn get_user_attachment(
+        &self,
+        id: i64,
+        user_id: i64,
+    ) -> Result<Option<Attachment>, sqlx::Error> {
+        let attachment = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Attachment>(
+                r#"
+                SELECT a.id, a.item_id, a.filename, a.stored_name, a.content_type, a.size, a.created_at
+                FROM attachments a
+                JOIN items i ON i.id = a.item_id
+                WHERE a.id = $1 AND i.user_id = $2 AND i.deleted_at IS NULL
+                "#,
+            )
+            .bind(id)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await
+        })?;
+
+        Ok(attachment)
+    }
+
+    // ==================== Comment Operations ====================
+
+    /// Add a comment to an item
+    pub async fn add_comment(
+        &self,
+        item_id: i64,
+        user_id: i64,
+        body: &str,
+    ) -> Result<Comment, sqlx::Error> {
+        let comment = with_pool!(&self.pool, pool => {
+            let (id,): (i64,) = sqlx::query_as(
+                "INSERT INTO comments (item_id, user_id, body) VALUES ($1, $2, $3) RETURNING id",
+            )
+            .bind(item_id)
+            .bind(user_id)
+            .bind(body)
+            .fetch_one(pool)
+            .await?;
+
+            sqlx::query_as::<_, Comment>(
+                r#"
+                SELECT c.id, c.item_id, c.user_id, u.username, c.body, c.created_at
+                FROM comments c
+                JOIN users u ON u.id = c.user_id
+                WHERE c.id = $1
+                "#,
+            )
+            .bind(id)
+            .fetch_one(pool)
+            .await
+        })?;
+
+        Ok(comment)
+    }
+
+    /// Get one page of an item's comments, oldest first
+    ///
+    /// `query` matches the body case-insensitively, like item searches.
+    pub async fn get_item_comments_paged(
+        &self,
+        item_id: i64,
+        query: Option<&str>,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Comment>, sqlx::Error> {
+        let pattern = query.map(like_pattern);
+        let comments = with_pool!(&self.pool, pool => {
+            sqlx::query_as::<_, Comment>(
+                r#"
+                SELECT c.id, c.item_id, c.user_id, u.username, c.body, c.created_at
+                FROM comments c
+                JOIN users u ON u.id = c.user_id
+                WHERE c.item_id = $1 AND ($2 IS NULL OR LOWER(c.body) LIKE $2 ESCAPE '\')
+                ORDER BY c.created_at ASC, c.id ASC
+                LIMIT $3 OFFSET $4
+                "#,
+            )
+            .bind(item_id)
+            .bind(&pattern)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(pool)
+            .await
+        })?;
+
+        Ok(comments)
+    }
+
+    /// Count an item's comments matching `query`, as returned by
+    /// `get_item_comments_paged`
+    pub async fn count_item_comments(
+        &self,
+        item_id: i64,
+        query: Option<&str>,
+    ) -> Result<i64, sqlx::Error> {
+        let pattern = query.map(like_pattern);
+        let result: (i64,) = with_pool!(&self.pool, pool => {
+            sqlx::query_as(
+                r#"
+                SELECT COUNT(*) FROM comments
+                WHERE item_id = $1 AND ($2 IS NULL OR LOWER(body) LIKE $2 ESCAPE '\')
+                "#,
+            )
+            .bind(item_id)
+            .bind(&pattern)
+            .fetch_one(pool)
+            .await
+        })?;
+
+        Ok(result.0)
+    }
+}
+
+impl DbTransaction {
+    /// Save everything run through the transaction
+    pub async fn commit(self) -> Result<(), sqlx::Error> {
+        match self {
+            DbTransaction::Sqlite(tx) => tx.commit().await,
+            DbTransaction::Postgres(tx) => tx.commit().await,
+        }
+    }
+
+    /// Discard everything run through the transaction
+    pub async fn rollback(self) -> Result<(), sqlx::Error> {
+        match self {
+            DbTransaction::Sqlite(tx) => tx.rollback().await,
+            DbTransaction::Postgres(tx) => tx.rollback().await,
+        }
+    }
+
+    /// Create a new user
+    pub async fn create_user(
+        &mut self,
+        username: &str,
+        email: &str,
+        password_hash: &str,
+    ) -> Result<User, sqlx::Error> {
+        let user = with_tx!(self, conn => {
+            sqlx::query_as::<_, User>(
+                r#"
+                INSERT INTO users (username, email, password_hash, verified, role)
+                VALUES (
+                    $1, $2, $3, FALSE,
+                    -- The first account becomes the administrator
+                    CASE WHEN EXISTS (SELECT 1 FROM users) THEN 'user' ELSE 'admin' END
+                )
+                RETURNING id, username, email, password_hash, failed_attempts, locked_until, token_version,
+                    verified, role, timezone, created_at, last_login_at
+                "#,
+            )
+            .bind(username)
+            .bind(email)
+            .bind(password_hash)
+            .fetch_one(&mut *conn)
+            .await
+        })?;
+
+        Ok(user)
+    }
+
+    /// Record a successful login; see `Database::record_login`
+    pub async fn record_login(
+        &mut self,
+        user_id: i64,
+        ip_address: &str,
+        user_agent: Option<&str>,
+        keep_since: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        // Stored as bound rather than by the column default, so SQLite
+        // compares it against `keep_since` in the same text format
+        let now = Utc::now();
+        with_tx!(self, conn => {
+            sqlx::query("DELETE FROM login_events WHERE user_id = $1 AND created_at < $2")
+                .bind(user_id)
+                .bind(keep_since)
+                .execute(&mut *conn)
+                .await
+                .map(|_| ())
+        })?;
+        with_tx!(self, conn => {
+            sqlx::query(
+                r#"
+                INSERT INTO login_events (user_id, ip_address, user_agent, created_at)
+                VALUES ($1, $2, $3, $4)
+                "#,
+            )
+            .bind(user_id)
+            .bind(ip_address)
+            .bind(user_agent)
+            .bind(now)
+            .execute(&mut *conn)
+            .await
+            .map(|_| ())
+        })?;
+        with_tx!(self, conn => {
+            sqlx::query("UPDATE users SET last_login_at = $1 WHERE id = $2")
+                .bind(now)
+                .bind(user_id)
+                .execute(&mut *conn)
+                .await
+                .map(|_| ())
+        })?;
+
+        Ok(())
+    }
+
+    /// Mark the unused invite with `code_hash` as used by `user_id`
+    ///
+    /// Fails with `RowNotFound` when there is no such invite or it was used
+    /// already, so a registration that loses the race rolls back.
+    pub async fn redeem_invite(
+        &mut self,
+        code_hash: &str,
+        user_id: i64,
+    ) -> Result<(), sqlx::Error> {
+        let rows_affected = with_tx!(self, conn => {
+            sqlx::query(
+                r#"
+                UPDATE invites
+                SET used_by = $2, used_at = CURRENT_TIMESTAMP
+                WHERE code_hash = $1 AND used_at IS NULL
+                "#,
+            )
+            .bind(code_hash)
+            .bind(user_id)
+            .execute(&mut *conn)
+            .await
+            .map(|result| result.rows_affected())
+        })?;
+
+        if rows_affected == 0 {
+            return Err(sqlx::Error::RowNotFound);
+        }
+        Ok(())
+    }
+
+    /// Create a new item
+    pub async fn create_item(&mut self, item: CreateItem) -> Result<Item, sqlx::Error> {
+        let created = with_tx!(self, conn => {
+            sqlx::query_as::<_, Item>(
+                r#"
+                INSERT INTO items (user_id, title, description, due_at)
+                VALUES ($1, $2, $3, $4)
+                RETURNING id, user_id, title, description, created_at, updated_at, deleted_at, archived, due_at
+                "#,
+            )
+            .bind(item.user_id)
+            .bind(&item.title)
+            .bind(&item.description)
+            .bind(item.due_at)
+            .fetch_one(&mut *conn)
+            .await
+        })?;
+        self.record_audit(
+            created.id,
+            created.user_id,
+            AuditAction::Create,
+            None,
+            Some(&created),
+        )
+        .await?;
+
+        Ok(created)
+    }
+
+    /// Update an item
+    pub async fn update_item(
+        &mut self,
+        id: i64,
+        user_id: i64,
+        title: &str,
+        description: Option<&str>,
+        due_at: Option<NaiveDate>,
+    ) -> Result<Option<Item>, sqlx::Error> {
+        let Some(before) = self.find_item(id).await? else {
+            return Ok(None);
+        };
+        let rows_affected = with_tx!(self, conn => {
+            sqlx::query(
+                r#"
+                UPDATE items
+                SET title = $1, description = $2, due_at = $3
+                WHERE id = $4 AND user_id = $5 AND deleted_at IS NULL
+                "#,
+            )
+                .bind(title)
+                .bind(description)
+                .bind(due_at)
+                .bind(id)
+                .bind(user_id)
+                .execute(&mut *conn)
+                .await
+                .map(|result| result.rows_affected())
+        })?;
+        if rows_affected == 0 {
+            return Ok(None);
+        }
+
+        // Read back rather than `RETURNING`, which misses the `updated_at`
+        // SQLite's trigger sets
+        let item = self.find_item(id).await?;
+        self.record_audit(
+            id,
+            user_id,
+            AuditAction::Update,
+            Some(&before),
+            item.as_ref(),
+        )
+        .await?;
+
+        Ok(item)
+    }
+
+    /// Move a user's items `ids` to the trash; see `Database::delete_items`
+    ///
+    /// Returns `0` when any id is not one of the user's live items, and the
+    /// transaction must then be rolled back, as some may have been trashed.
+    pub async fn trash_items(&mut self, user_id: i64, ids: &[i64]) -> Result<u64, sqlx::Error> {
+        let mut ids = ids.to_vec();
+        ids.sort_unstable();
+        ids.dedup();
+        let mut trashed = Vec::with_capacity(ids.len());
+        for &id in &ids {
+            match self.find_item(id).await? {
+                Some(item) if item.user_id == user_id && item.deleted_at.is_none() => {
+                    trashed.push(item)
+                }
+                _ => return Ok(0),
+            }
+        }
+        if trashed.is_empty() {
+            return Ok(0);
+        }
+
+        // Checked again in the statement, in case another request trashed
+        // one of them since
+        let sql = format!(
+            r#"
+            UPDATE items
+            SET deleted_at = CURRENT_TIMESTAMP
+            WHERE user_id = $1 AND deleted_at IS NULL AND id IN ({})
+            "#,
+            placeholders(2, ids.len())
+        );
+        let rows_affected = with_tx!(self, conn => {
+            let mut query = sqlx::query(&sql).bind(user_id);
+            for id in &ids {
+                query = query.bind(id);
+            }
+            query
+                .execute(&mut *conn)
+                .await
+                .map(|result| result.rows_affected())
+        })?;
+        if rows_affected != ids.len() as u64 {
+            return Ok(0);
+        }
+        for before in &trashed {
+            let after = self.find_item(before.id).await?;
+            self.record_audit(
+                before.id,
+                user_id,
+                AuditAction::Trash,
+                Some(before),
+                after.as_ref(),
+            )
+            .await?;
+        }
+
+        Ok(trashed.len() as u64)
+    }
+
+    /// Archive or unarchive some of a user's live items, recording each
+    ///
+    /// Returns `0` when any id is not one of the user's live items, and the
+    /// transaction must then be rolled back, as some may have changed.
+    /// Items already in the wanted state count as changed but are not
+    /// audited.
+    pub async fn set_items_archived(
+        &mut self,
+        user_id: i64,
+        ids: &[i64],
+        archived: bool,
+    ) -> Result<u64, sqlx::Error> {
+        let mut ids = ids.to_vec();
+        ids.sort_unstable();
+        ids.dedup();
+        let mut changed = Vec::with_capacity(ids.len());
+        for &id in &ids {
+            match self.find_item(id).await? {
+                Some(item) if item.user_id == user_id && item.deleted_at.is_none() => {
+                    changed.push(item)
+                }
+                _ => return Ok(0),
+            }
+        }
+        if changed.is_empty() {
+            return Ok(0);
+        }
+
+        // Checked again in the statement, in case another request trashed
+        // one of them since
+        let sql = format!(
+            r#"
+            UPDATE items
+            SET archived = $2
+            WHERE user_id = $1 AND deleted_at IS NULL AND id IN ({})
+            "#,
+            placeholders(3, ids.len())
+        );
+        let rows_affected = with_tx!(self, conn => {
+            let mut query = sqlx::query(&sql).bind(user_id).bind(archived);
+            for id in &ids {
+                query = query.bind(id);
+            }
+            query
+                .execute(&mut *conn)
+                .await
+                .map(|result| result.rows_affected())
+        })?;
+        if rows_affected != ids.len() as u64 {
+            return Ok(0);
+        }
+        let action = if archived {
+            AuditAction::Archive
+        } else {
+            AuditAction::Unarchive
+        };
+        for before in changed.iter().filter(|item| item.archived != archived) {
+            let after = self.find_item(before.id).await?;
+            self.record_audit(before.id, user_id, action, Some(before), after.as_ref())
+                .await?;
+        }
+
+        Ok(changed.len() as u64)
+    }
+
+    /// Any item by ID, trashed or not, whoever owns it
+    async fn find_item(&mut self, id: i64) -> Result<Option<Item>, sqlx::Error> {
+        with_tx!(self, conn => {
+            sqlx::query_as::<_, Item>(
+                r#"
+                SELECT id, user_id, title, description, created_at, updated_at, deleted_at, archived, due_at
+                FROM items
+                WHERE id = $1
+                "#,
+            )
+                .bind(id)
+                .fetch_optional(&mut *conn)
+                .await
+        })
+    }
+
+    /// Record that `user_id` made a change to an item, with the item as it
+    /// was `before` and is `after`
+    ///
+    /// Item writes call this in their own transaction, so a change is only
+    /// saved along with its audit row: if the row cannot be written, the
+    /// change fails too.
+    pub async fn record_audit(
+        &mut self,
+        item_id: i64,
+        user_id: i64,
+        action: AuditAction,
+        before: Option<&Item>,
+        after: Option<&Item>,
+    ) -> Result<(), sqlx::Error> {
+        let before = before.map(audit_snapshot).transpose()?;
+        let after = after.map(audit_snapshot).transpose()?;
+        with_tx!(self, conn => {
+            sqlx::query(
+                r#"
+                INSERT INTO item_audit (item_id, user_id, action, before_json, after_json)
+                VALUES ($1, $2, $3, $4, $5)
+                "#,
+            )
+            .bind(item_id)
+            .bind(user_id)
+            .bind(action.as_str())
+            .bind(&before)
+            .bind(&after)
+            .execute(&mut *conn)
+            .await
+            .map(|_| ())
+        })
+    }
+
+    /// Remember that a user's idempotency key created `item_id`; fails with a
+    /// unique violation if the key is already recorded
+    pub async fn record_idempotency_key(
+        &mut self,
+        user_id: i64,
+        key: &str,
+        item_id: i64,
+    ) -> Result<(), sqlx::Error> {
+        with_tx!(self, conn => {
+            sqlx::query(
+                "INSERT INTO idempotency_keys (user_id, idempotency_key, item_id) VALUES ($1, $2, $3)",
+            )
+            .bind(user_id)
+            .bind(key)
+            .bind(item_id)
+            .execute(&mut *conn)
+            .await
+            .map(|_| ())
+        })
+    }
+
+    /// Replace an item's tags, creating any tag names not seen before
+    pub async fn set_item_tags(
+        &mut self,
+        item_id: i64,
+        tags: &[String],
+    ) -> Result<(), sqlx::Error> {
+        with_tx!(self, conn => {
+            sqlx::query("DELETE FROM item_tags WHERE item_id = $1")
+                .bind(item_id)
+                .execute(&mut *conn)
+                .await
+                .map(|_| ())
+        })?;
+
+        for tag in tags {
+            with_tx!(self, conn => {
+                sqlx::query("INSERT INTO tags (name) VALUES ($1) ON CONFLICT (name) DO NOTHING")
+                    .bind(tag)
+                    .execute(&mut *conn)
+                    .await
+                    .map(|_| ())
+            })?;
+            with_tx!(self, conn => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO item_tags (item_id, tag_id)
+                    SELECT $1, id FROM tags WHERE name = $2
+                    ON CONFLICT DO NOTHING
+                    "#,
+                )
+                .bind(item_id)
+                .bind(tag)
+                .execute(&mut *conn)
+                .await
+                .map(|_| ())
+            })?;
+        }
+
+        Ok(())
+    }
+}
+
+/// An item as `item_audit` keeps it: its own columns, without the tags
+fn audit_snapshot(item: &Item) -> Result<String, sqlx::Error> {
+    let mut value = serde_json::to_value(item).map_err(|e| sqlx::Error::Encode(Box::new(e)))?;
+    if let Some(fields) = value.as_object_mut() {
+        fields.remove("tags");
+    }
+    Ok(value.to_string())
+}
+
+/// `WHERE` condition of `LIKE` item searches; `$2` is the optional pattern
+const KEYWORD_CONDITION: &str = r#"
+    ($2 IS NULL OR LOWER(title) LIKE $2 ESCAPE '\' OR LOWER(description) LIKE $2 ESCAPE '\')
+"#;
+
+/// `WHERE` condition of an item search picking active or archived items
+fn archived_condition(filter: &ItemFilter<'_>) -> &'static str {
+    if filter.archived {
+        "archived = TRUE"
+    } else {
+        "archived = FALSE"
+    }
+}
+
+/// `WHERE` condition of every item search; `$3` is the optional tag name
+const TAG_CONDITION: &str = r#"
+    ($3 IS NULL OR id IN (
+        SELECT it.item_id FROM item_tags it JOIN tags t ON t.id = it.tag_id WHERE t.name = $3
+    ))
+"#;
+
+/// Triggers mirroring every change to `items` into the external-content
+/// `items_fts` index, which only learns of rows through them
+const FULL_TEXT_TRIGGERS: [&str; 3] = [
+    r#"
+    CREATE TRIGGER IF NOT EXISTS items_fts_insert AFTER INSERT ON items BEGIN
+        INSERT INTO items_fts (rowid, title, description)
+        VALUES (new.id, new.title, new.description);
+    END
+    "#,
+    r#"
+    CREATE TRIGGER IF NOT EXISTS items_fts_delete AFTER DELETE ON items BEGIN
+        INSERT INTO items_fts (items_fts, rowid, title, description)
+        VALUES ('delete', old.id, old.title, old.description);
+    END
+    "#,
+    r#"
+    CREATE TRIGGER IF NOT EXISTS items_fts_update AFTER UPDATE OF title, description ON items BEGIN
+        INSERT INTO items_fts (items_fts, rowid, title, description)
+        VALUES ('delete', old.id, old.title, old.description);
+        INSERT INTO items_fts (rowid, title, description)
+        VALUES (new.id, new.title, new.description);
+    END
+    "#,
+];
+
+/// Comma-separated `$N` placeholders for an `IN (...)` list of `count` values
+fn placeholders(start: usize, count: usize) -> String {
+    (start..start + count)
+        .map(|i| format!("${}", i))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Build a `LIKE ... ESCAPE '\'` pattern matching `query` anywhere, with
+/// wildcards in the user input escaped
+fn like_pattern(query: &str) -> String {
+    let escaped = query
+        .to_lowercase()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// FTS5 query matching every word of `query` as a prefix, with each word
+/// quoted so operators and punctuation in it are taken literally
+///
+/// `None` when no word holds a letter or digit, as FTS5 would index none of
+/// it; such keywords are left to `LIKE`.
+fn fts_query(query: &str) -> Option<String> {
+    let words: Vec<String> = query
+        .split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
+
+/// Whether SQLite gave up waiting for a lock: `SQLITE_BUSY` or
+/// `SQLITE_LOCKED`, including their extended codes
+fn is_busy(error: &sqlx::Error) -> bool {
+    error
+        .as_database_error()
+        .filter(|e| e.try_downcast_ref::<sqlx::sqlite::SqliteError>().is_some())
+        .and_then(|e| e.code())
+        .and_then(|code| code.parse::<i32>().ok())
+        .is_some_and(|code| matches!(code & 0xff, 5 | 6))
+}
+
+/// Whether a connection URL selects the Postgres backend
+fn is_postgres_url(database_url: &str) -> bool {
+    database_url.starts_with("postgres:") || database_url.starts_with("postgresql:")
+}
+
+/// Versioned schema changes, applied in order and recorded in `_sqlx_migrations`
+static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+
+// SQLite stores `CURRENT_TIMESTAMP` as UTC text, which decodes into the same
+// `DateTime<Utc>` fields as Postgres `TIMESTAMPTZ`.
+static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+#[cfg(test)]
+mod tests {
+    use super::{Database, DbPool, PoolConfig, ReassignOutcome, UserConflict, SQLITE_MIGRATOR};
+    use crate::models::{
+        CreateAttachment, CreateItem, Item, ItemCursor, ItemFilter, ItemLimits, ItemSort,
+    };
+    use chrono::Utc;
+    use chrono_tz::Tz;
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    async fn setup_test_db() -> (Database, PathBuf) {
+        let mut path = std::env::temp_dir();
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        path.push(format!("basic_crud_ops_test_{}.db", nanos));
+
+        let url = format!("sqlite:{}?mode=rwc", path.display());
+        let db = Database::new(&url).await.expect("create test db");
+        (db, path)
+    }
+
+    fn cleanup_db(path: PathBuf) {
+        for suffix in ["-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+        let _ = std::fs::remove_file(path);
+    }
+
+    fn keyword(query: &str) -> ItemFilter<'_> {
+        ItemFilter {
+            query: Some(query),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn migrations_are_recorded_and_rerun_cleanly() {
+        let (mut db, path) = setup_test_db().await;
+        db.run_migrations().await.expect("run migrations again");
+        assert!(db.full_text);
+
+        let DbPool::Sqlite(pool) = &db.pool else {
+            panic!("expected a SQLite pool");
+        };
+        let applied: Vec<(i64,)> =
+            sqlx::query_as("SELECT version FROM _sqlx_migrations WHERE success ORDER BY version")
+                .fetch_all(pool)
+                .await
+                .expect("applied migrations");
+        let expected: Vec<(i64,)> = SQLITE_MIGRATOR.iter().map(|m| (m.version,)).collect();
+        assert_eq!(applied, expected);
+        assert_eq!(applied.first(), Some(&(1,)));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn pool_honors_configured_limits() {
+        let (_, path) = setup_test_db().await;
+        let url = format!("sqlite:{}?mode=rwc", path.display());
+        let config = PoolConfig {
+            max_connections: 1,
+            acquire_timeout: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let db = Database::connect(&url, &config).await.expect("connect");
+        let DbPool::Sqlite(pool) = &db.pool else {
+            panic!("expected a SQLite pool");
+        };
+
+        let held = pool.acquire().await.expect("first connection");
+        assert!(matches!(
+            pool.acquire().await,
+            Err(sqlx::Error::PoolTimedOut)
+        ));
+        drop(held);
+
+        let (mode,): (String,) = sqlx::query_as("PRAGMA journal_mode")
+            .fetch_one(pool)
+            .await
+            .expect("journal mode");
+        assert_eq!(mode, "wal");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn busy_writes_are_retried_until_the_lock_clears() {
+        let (holder, path) = setup_test_db().await;
+        let url = format!("sqlite:{}?mode=rwc", path.display());
+        let config = |write_retries| PoolConfig {
+            busy_timeout: Duration::from_millis(10),
+            write_retries,
+            ..Default::default()
+        };
+        let impatient = Database::connect(&url, &config(0)).await.expect("connect");
+        let patient = Database::connect(&url, &config(5)).await.expect("connect");
+        let user = holder
+            .create_user("busy", "busy@example.com", "hash")
+            .await
+            .expect("create user");
+        let new_item = |title: &str| CreateItem {
+            user_id: user.id,
+            title: title.to_string(),
+            description: None,
+            due_at: None,
+        };
+
+        let mut lock = holder.begin().await.expect("begin");
+        lock.create_item(new_item("Holding the lock"))
+            .await
+            .expect("take the write lock");
+        let error = impatient
+            .create_item(new_item("Too soon"))
+            .await
+            .expect_err("database busy");
+        assert!(super::is_busy(&error));
+
+        // Released while the retries are still backing off
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            lock.commit().await.expect("release the lock");
+        });
+        let item = patient
+            .create_item(new_item("Eventually"))
+            .await
+            .expect("retried write");
+        release.await.expect("release task");
+        assert_eq!(item.title, "Eventually");
+        assert_eq!(
+            holder.get_user_items(user.id).await.expect("items").len(),
+            2
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn user_queries_work() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("alice", "alice@example.com", "hash")
+            .await
+            .expect("create user");
+
+        let by_username = db
+            .find_user_by_username("alice")
+            .await
+            .expect("find by username")
+            .expect("user exists");
+
+        assert_eq!(user.id, by_username.id);
+
+        let by_id = db
+            .find_user_by_id(user.id)
+            .await
+            .expect("find by id")
+            .expect("user exists");
+
+        assert_eq!(by_id.username, "alice");
+        assert!(db.username_exists("alice").await.expect("username exists"));
+        assert!(db
+            .email_exists("alice@example.com")
+            .await
+            .expect("email exists"));
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn usernames_and_emails_are_unique_ignoring_case() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("Alice", "Alice@Example.com", "hash")
+            .await
+            .expect("create user");
+
+        let found = db
+            .find_user_by_username("aLICE")
+            .await
+            .expect("find by username")
+            .expect("user exists");
+        assert_eq!(found.id, user.id);
+        assert!(db.username_exists("ALICE").await.expect("username exists"));
+        assert!(db
+            .email_exists("alice@example.COM")
+            .await
+            .expect("email exists"));
+
+        for (username, email) in [
+            ("alice", "other@example.com"),
+            ("other", "alice@example.com"),
+        ] {
+            let err = db
+                .create_user(username, email, "hash")
+                .await
+                .expect_err("case-only duplicate");
+            assert!(err
+                .as_database_error()
+                .is_some_and(|e| e.is_unique_violation()));
+            let expected = if username == "alice" {
+                UserConflict::Username
+            } else {
+                UserConflict::Email
+            };
+            assert_eq!(UserConflict::of(&err), Some(expected));
+        }
+        assert_eq!(UserConflict::of(&sqlx::Error::RowNotFound), None);
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn failed_attempts_increment_lock_and_reset() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("frank", "frank@example.com", "hash")
+            .await
+            .expect("create user");
+        assert_eq!(user.failed_attempts, 0);
+        assert!(user.locked_until.is_none());
+
+        assert_eq!(db.record_failed_attempt(user.id).await.expect("record"), 1);
+        assert_eq!(db.record_failed_attempt(user.id).await.expect("record"), 2);
+
+        let until = chrono::Utc::now() + chrono::Duration::minutes(5);
+        db.lock_user_until(user.id, until).await.expect("lock");
+
+        let locked = db
+            .find_user_by_id(user.id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert_eq!(locked.failed_attempts, 2);
+        assert_eq!(
+            locked.locked_until.map(|t| t.timestamp()),
+            Some(until.timestamp())
+        );
+
+        db.reset_failed_attempts(user.id).await.expect("reset");
+        let reset = db
+            .find_user_by_id(user.id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert_eq!(reset.failed_attempts, 0);
+        assert!(reset.locked_until.is_none());
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn migrations_add_columns_to_existing_tables() {
+        let (db, path) = setup_test_db().await;
+        let url = format!("sqlite:{}?mode=rwc", path.display());
+        drop(db);
+
+        // Recreate the users and items tables as they looked in the first
+        // release, before migrations were versioned
+        let pool = sqlx::SqlitePool::connect(&url).await.expect("connect");
+        for statement in [
+            "DROP TABLE _sqlx_migrations",
+            "DROP TABLE email_changes",
+            "DROP TABLE feed_tokens",
+            "DROP TABLE login_events",
+            "DROP TABLE webauthn_credentials",
+            "DROP TABLE invites",
+            "DROP TABLE item_invitations",
+            "DROP TABLE api_keys",
+            "DROP TABLE idempotency_keys",
+            "DROP TABLE comments",
+            "DROP TABLE item_audit",
+            "DROP TABLE item_tags",
+            "DROP TABLE email_verification_tokens",
+            "DROP TABLE refresh_tokens",
+            "DROP TABLE items",
+            "DROP TABLE items_fts",
+            "DROP TABLE users",
+            "CREATE TABLE users (id INTEGER PRIMARY KEY AUTOINCREMENT, username TEXT NOT NULL UNIQUE, email TEXT NOT NULL UNIQUE, password_hash TEXT NOT NULL, created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP)",
+            "INSERT INTO users (username, email, password_hash) VALUES ('old', 'old@example.com', 'hash')",
+            "CREATE TABLE items (id INTEGER PRIMARY KEY AUTOINCREMENT, user_id INTEGER NOT NULL, title TEXT NOT NULL, description TEXT, created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP, updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP, FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE)",
+            "INSERT INTO items (user_id, title) SELECT id, 'Old item' FROM users",
+        ] {
+            sqlx::query(statement).execute(&pool).await.expect("legacy schema");
+        }
+        pool.close().await;
+
+        let db = Database::new(&url).await.expect("migrate");
+        let user = db
+            .find_user_by_username("old")
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert_eq!(user.failed_attempts, 0);
+        assert!(user.locked_until.is_none());
+        assert_eq!(user.token_version, 0);
+        assert!(user.verified);
+        assert_eq!(user.role, "user");
+
+        let items = db.get_user_items(user.id).await.expect("items");
+        assert_eq!(items.len(), 1);
+        assert!(items[0].deleted_at.is_none());
+        // Items from before the full-text index are indexed when it is built
+        let found = db.fts_search_items(user.id, "old").await.expect("search");
+        assert_eq!(found.len(), 1);
+        let DbPool::Sqlite(pool) = &db.pool else {
+            panic!("expected a SQLite pool");
+        };
+        let triggers: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'trigger' AND name = 'items_touch_updated_at'",
+        )
+        .fetch_one(pool)
+        .await
+        .expect("count triggers");
+        assert_eq!(triggers, 1);
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn verification_tokens_verify_once() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("iris", "iris@example.com", "hash")
+            .await
+            .expect("create user");
+        assert!(!user.verified);
+
+        let expires_at = chrono::Utc::now() + chrono::Duration::hours(1);
+        db.create_verification_token(user.id, "verify-hash", expires_at)
+            .await
+            .expect("create token");
+
+        let consumed = db
+            .consume_verification_token("verify-hash")
+            .await
+            .expect("consume");
+        assert_eq!(consumed, Some(user.id));
+        assert!(db.verify_user(user.id).await.expect("verify"));
+
+        let again = db
+            .consume_verification_token("verify-hash")
+            .await
+            .expect("consume again");
+        assert_eq!(again, None);
+
+        let verified = db
+            .find_user_by_id(user.id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert!(verified.verified);
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn expired_verification_token_is_rejected() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("jack", "jack@example.com", "hash")
+            .await
+            .expect("create user");
+        let expires_at = chrono::Utc::now() - chrono::Duration::minutes(1);
+        db.create_verification_token(user.id, "old-hash", expires_at)
+            .await
+            .expect("create token");
+
+        let consumed = db
+            .consume_verification_token("old-hash")
+            .await
+            .expect("consume");
+        assert_eq!(consumed, None);
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn item_changes_are_audited() {
+        let (db, path) = setup_test_db().await;
+        let user = db
+            .create_user("auditor", "auditor@example.com", "hash")
+            .await
+            .expect("create user");
+        let snapshot = |json: &Option<String>| -> serde_json::Value {
+            serde_json::from_str(json.as_deref().expect("snapshot")).expect("snapshot JSON")
+        };
+
+        let item = db
+            .create_item(CreateItem {
+                user_id: user.id,
+                title: "Draft".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        db.update_item(item.id, user.id, "Final", Some("Done"), None)
+            .await
+            .expect("update item")
+            .expect("item exists");
+        assert!(db.delete_item(item.id, user.id).await.expect("trash item"));
+        assert!(db.purge_item(item.id, user.id).await.expect("purge item"));
+
+        // The trail outlives the item
+        let trail = db.list_item_audit(item.id).await.expect("audit trail");
+        let actions: Vec<&str> = trail.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(actions, ["create", "update", "trash", "purge"]);
+        assert!(trail.iter().all(|e| e.user_id == Some(user.id)));
+
+        let [created, updated, trashed, purged] = &trail[..] else {
+            unreachable!("four entries")
+        };
+        assert_eq!(created.before_json, None);
+        assert_eq!(snapshot(&created.after_json)["title"], "Draft");
+        assert!(snapshot(&created.after_json).get("tags").is_none());
+        assert_eq!(snapshot(&updated.before_json)["title"], "Draft");
+        assert_eq!(snapshot(&updated.after_json)["title"], "Final");
+        assert_eq!(snapshot(&updated.after_json)["description"], "Done");
+        assert!(snapshot(&trashed.before_json)["deleted_at"].is_null());
+        assert!(!snapshot(&trashed.after_json)["deleted_at"].is_null());
+        assert_eq!(snapshot(&purged.before_json)["title"], "Final");
+        assert_eq!(purged.after_json, None);
+
+        // Batch trashing records each item; refused batches record nothing
+        let ids = [
+            db.create_item(CreateItem {
+                user_id: user.id,
+                title: "One".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item")
+            .id,
+            db.create_item(CreateItem {
+                user_id: user.id,
+                title: "Two".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item")
+            .id,
+        ];
+        assert_eq!(
+            db.delete_items(user.id, &[ids[0], 9999])
+                .await
+                .expect("delete items"),
+            0
+        );
+        assert_eq!(
+            db.delete_items(user.id, &ids).await.expect("delete items"),
+            2
+        );
+        for id in ids {
+            let actions: Vec<String> = db
+                .list_item_audit(id)
+                .await
+                .expect("audit trail")
+                .into_iter()
+                .map(|e| e.action)
+                .collect();
+            assert_eq!(actions, ["create", "trash"]);
+        }
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn each_item_mutation_records_its_own_action() {
+        let (db, path) = setup_test_db().await;
+        let owner = db
+            .create_user("owner", "owner@example.com", "hash")
+            .await
+            .expect("create user");
+        let admin = db
+            .create_user("admin", "admin@example.com", "hash")
+            .await
+            .expect("create user");
+        let item = db
+            .create_item(CreateItem {
+                user_id: owner.id,
+                title: "Tracked".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        assert!(db.delete_item(item.id, owner.id).await.expect("trash"));
+        assert!(db.restore_item(item.id, owner.id).await.expect("restore"));
+        assert!(db
+            .set_item_archived(item.id, owner.id, true)
+            .await
+            .expect("archive"));
+        // Already archived: nothing changes, so nothing is recorded
+        assert!(db
+            .set_item_archived(item.id, owner.id, true)
+            .await
+            .expect("archive"));
+        assert!(db
+            .set_item_archived(item.id, owner.id, false)
+            .await
+            .expect("unarchive"));
+        // Refused changes record nothing
+        assert!(!db.restore_item(item.id, owner.id).await.expect("restore"));
+        assert!(!db
+            .set_item_archived(item.id, admin.id, true)
+            .await
+            .expect("archive"));
+        assert!(matches!(
+            db.reassign_item(item.id, admin.id, admin.id).await,
+            Ok(ReassignOutcome::Moved(_))
+        ));
+        assert!(db.delete_user(admin.id).await.expect("delete user"));
+
+        let trail = db.list_item_audit(item.id).await.expect("audit trail");
+        let actions: Vec<(&str, Option<i64>)> = trail
+            .iter()
+            .map(|e| (e.action.as_str(), e.user_id))
+            .collect();
+        assert_eq!(
+            actions,
+            [
+                ("create", Some(owner.id)),
+                ("trash", Some(owner.id)),
+                ("restore", Some(owner.id)),
+                ("archive", Some(owner.id)),
+                ("unarchive", Some(owner.id)),
+                // The admin's account is gone, so who did it is too
+                ("reassign", None),
+                ("purge", None),
+            ]
+        );
+        let snapshot = |json: &Option<String>| -> serde_json::Value {
+            serde_json::from_str(json.as_deref().expect("snapshot")).expect("snapshot JSON")
+        };
+        let reassigned = &trail[5];
+        assert_eq!(snapshot(&reassigned.before_json)["user_id"], owner.id);
+        assert_eq!(snapshot(&reassigned.after_json)["user_id"], admin.id);
+        assert_eq!(trail[6].after_json, None);
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn item_changes_fail_when_their_audit_row_does() {
+        let (db, path) = setup_test_db().await;
+        let user = db
+            .create_user("strict", "strict@example.com", "hash")
+            .await
+            .expect("create user");
+        let item = db
+            .create_item(CreateItem {
+                user_id: user.id,
+                title: "Kept".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        let DbPool::Sqlite(pool) = &db.pool else {
+            panic!("expected a SQLite pool");
+        };
+        sqlx::query("DROP TABLE item_audit")
+            .execute(pool)
+            .await
+            .expect("drop audit table");
+
+        db.update_item(item.id, user.id, "Changed", None, None)
+            .await
+            .expect_err("audit row cannot be written");
+        db.delete_item(item.id, user.id)
+            .await
+            .expect_err("audit row cannot be written");
+        let stored = db
+            .get_item(item.id, user.id)
+            .await
+            .expect("get item")
+            .expect("item still live");
+        assert_eq!(stored.title, "Kept");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn purging_expired_tokens_keeps_live_ones() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("kai", "kai@example.com", "hash")
+            .await
+            .expect("create user");
+        let item = db
+            .create_item(CreateItem {
+                user_id: user.id,
+                title: "Handed over".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        let now = Utc::now();
+        let past = now - chrono::Duration::minutes(1);
+        let future = now + chrono::Duration::hours(1);
+
+        for (hash, expires_at) in [("old-verify", past), ("new-verify", future)] {
+            db.create_verification_token(user.id, hash, expires_at)
+                .await
+                .expect("create verification token");
+        }
+        for (hash, expires_at, session_expires_at) in [
+            ("old-refresh", past, future),
+            ("ended-session", future, past),
+            ("new-refresh", future, future),
+        ] {
+            db.create_refresh_token(user.id, hash, expires_at, session_expires_at, false)
+                .await
+                .expect("create refresh token");
+        }
+        for (hash, expires_at) in [("old-invite", past), ("new-invite", future)] {
+            db.create_invitation(item.id, user.id, "lee@example.com", hash, expires_at)
+                .await
+                .expect("create invitation");
+        }
+
+        assert_eq!(db.purge_expired_tokens(now).await.expect("purge"), 4);
+        assert_eq!(db.purge_expired_tokens(now).await.expect("purge again"), 0);
+
+        for hash in ["old-refresh", "ended-session"] {
+            assert!(db
+                .find_refresh_token(hash)
+                .await
+                .expect("find refresh token")
+                .is_none());
+        }
+        assert!(db
+            .find_refresh_token("new-refresh")
+            .await
+            .expect("find refresh token")
+            .is_some());
+        assert_eq!(
+            db.consume_verification_token("new-verify")
+                .await
+                .expect("consume"),
+            Some(user.id)
+        );
+        let DbPool::Sqlite(pool) = &db.pool else {
+            panic!("expected a SQLite pool");
+        };
+        let invitations: Vec<(String,)> = sqlx::query_as("SELECT token_hash FROM item_invitations")
+            .fetch_all(pool)
+            .await
+            .expect("list invitations");
+        assert_eq!(invitations, [("new-invite".to_string(),)]);
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn first_user_is_admin_and_listings_cover_everyone() {
+        let (db, path) = setup_test_db().await;
+
+        let first = db
+            .create_user("root", "root@example.com", "hash")
+            .await
+            .expect("create user");
+        let second = db
+            .create_user("kim", "kim@example.com", "hash")
+            .await
+            .expect("create user");
+        assert_eq!(first.role, "admin");
+        assert_eq!(second.role, "user");
+
+        for user_id in [first.id, second.id] {
+            db.create_item(CreateItem {
+                user_id,
+                title: "Item".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        }
+
+        let users = db.list_all_users().await.expect("list users");
+        assert_eq!(
+            users.iter().map(|u| u.id).collect::<Vec<_>>(),
+            vec![first.id, second.id]
+        );
+        assert_eq!(db.list_all_items().await.expect("list items").len(), 2);
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn update_user_profile_changes_fields_and_enforces_uniqueness() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("lou", "lou@example.com", "hash")
+            .await
+            .expect("create user");
+        db.create_user("max", "max@example.com", "hash")
+            .await
+            .expect("create user");
+
+        let updated = db
+            .update_user_profile(user.id, "louis", "Europe/Paris")
+            .await
+            .expect("update")
+            .expect("user exists");
+        assert_eq!(updated.username, "louis");
+        assert_eq!(updated.email, "lou@example.com");
+        assert_eq!(updated.timezone, "Europe/Paris");
+
+        let err = db
+            .update_user_profile(user.id, "max", "UTC")
+            .await
+            .expect_err("duplicate username");
+        assert!(err
+            .as_database_error()
+            .is_some_and(|e| e.is_unique_violation()));
+
+        assert!(db
+            .update_user_profile(9999, "ghost", "UTC")
+            .await
+            .expect("update")
+            .is_none());
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn email_changes_wait_for_confirmation() {
+        let (db, path) = setup_test_db().await;
+        run_email_change(&db, "nell").await;
+        cleanup_db(path);
+    }
+
+    // A pending change leaves the old address in place until its token is
+    // consumed, and holds the new one against other users
+    async fn run_email_change(db: &Database, name: &str) {
+        let user = db
+            .create_user(name, &format!("{}@example.com", name), "hash")
+            .await
+            .expect("create user");
+        let other_name = format!("{}_other", name);
+        let other_email = format!("{}@example.com", other_name);
+        let other = db
+            .create_user(&other_name, &other_email, "hash")
+            .await
+            .expect("create user");
+        let new_email = format!("{}_new@example.com", name);
+        let token_hash = format!("{}-change-hash", name);
+        let expires_at = Utc::now() + chrono::Duration::hours(1);
+
+        assert!(db
+            .create_email_change(user.id, &new_email, &token_hash, expires_at)
+            .await
+            .expect("create"));
+        assert_eq!(
+            db.pending_email_change(user.id).await.expect("pending"),
+            Some(new_email.clone())
+        );
+        let stored = db
+            .find_user_by_id(user.id)
+            .await
+            .expect("find")
+            .expect("user");
+        assert_eq!(stored.email, format!("{}@example.com", name));
+
+        // Neither a registered nor another user's pending address is free
+        let other_hash = format!("{}-other-hash", name);
+        for (user_id, email) in [(other.id, new_email.to_uppercase()), (user.id, other_email)] {
+            assert!(!db
+                .create_email_change(user_id, &email, &other_hash, expires_at)
+                .await
+                .expect("create"));
+        }
+        assert_eq!(
+            db.pending_email_change(other.id).await.expect("pending"),
+            None
+        );
+
+        assert_eq!(
+            db.consume_email_change(&token_hash).await.expect("consume"),
+            Some((user.id, new_email.clone()))
+        );
+        assert_eq!(
+            db.consume_email_change(&token_hash).await.expect("consume"),
+            None
+        );
+        assert!(db
+            .update_user_email(user.id, &new_email)
+            .await
+            .expect("update"));
+        assert!(db
+            .update_user_email(other.id, &new_email.to_uppercase())
+            .await
+            .expect_err("taken")
+            .as_database_error()
+            .is_some_and(|e| e.is_unique_violation()));
+    }
+
+    #[tokio::test]
+    async fn expired_email_changes_free_their_address() {
+        let (db, path) = setup_test_db().await;
+        let user = db
+            .create_user("pia", "pia@example.com", "hash")
+            .await
+            .expect("create user");
+        let other = db
+            .create_user("rex", "rex@example.com", "hash")
+            .await
+            .expect("create user");
+        let expired = Utc::now() - chrono::Duration::seconds(1);
+
+        assert!(db
+            .create_email_change(user.id, "free@example.com", "old-hash", expired)
+            .await
+            .expect("create"));
+        assert_eq!(
+            db.pending_email_change(user.id).await.expect("pending"),
+            None
+        );
+        assert!(db
+            .create_email_change(
+                other.id,
+                "free@example.com",
+                "new-hash",
+                Utc::now() + chrono::Duration::hours(1)
+            )
+            .await
+            .expect("create"));
+        assert_eq!(
+            db.consume_email_change("old-hash").await.expect("consume"),
+            None
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn delete_user_cascades_to_items() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("ned", "ned@example.com", "hash")
+            .await
+            .expect("create user");
+        let item = db
+            .create_item(CreateItem {
+                user_id: user.id,
+                title: "Doomed".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+
+        assert!(db.delete_user(user.id).await.expect("delete"));
+        assert!(db.find_user_by_id(user.id).await.expect("find").is_none());
+        assert!(db.get_item(item.id, user.id).await.expect("get").is_none());
+        assert!(!db.delete_user(user.id).await.expect("delete again"));
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn search_matches_title_and_description() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("sue", "sue@example.com", "hash")
+            .await
+            .expect("create user");
+        for (title, description) in [
+            ("Grocery list", None),
+            ("Weekend", Some("Buy GROCERIES")),
+            ("Unrelated", Some("Nothing here")),
+        ] {
+            db.create_item(CreateItem {
+                user_id: user.id,
+                title: title.to_string(),
+                description: description.map(str::to_string),
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        }
+
+        let found = db
+            .search_user_items(user.id, &keyword("grocer"), ItemSort::default(), 10, 0)
+            .await
+            .expect("search");
+        let titles: Vec<_> = found.iter().map(|i| i.title.as_str()).collect();
+        assert_eq!(titles, vec!["Weekend", "Grocery list"]);
+        assert_eq!(
+            db.count_search_user_items(user.id, &keyword("grocer"))
+                .await
+                .expect("count"),
+            2
+        );
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn search_escapes_like_wildcards() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("ted", "ted@example.com", "hash")
+            .await
+            .expect("create user");
+        for title in [
+            "100% done",
+            "1000 done",
+            "snake_case",
+            "snakeXcase",
+            r"back\slash",
+        ] {
+            db.create_item(CreateItem {
+                user_id: user.id,
+                title: title.to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        }
+
+        let titles = |items: Vec<crate::models::Item>| {
+            items.into_iter().map(|i| i.title).collect::<Vec<_>>()
+        };
+        let percent = db
+            .search_user_items(user.id, &keyword("0%"), ItemSort::default(), 10, 0)
+            .await
+            .expect("search");
+        assert_eq!(titles(percent), vec!["100% done"]);
+        let underscore = db
+            .search_user_items(user.id, &keyword("e_c"), ItemSort::default(), 10, 0)
+            .await
+            .expect("search");
+        assert_eq!(titles(underscore), vec!["snake_case"]);
+        let backslash = db
+            .search_user_items(user.id, &keyword(r"k\s"), ItemSort::default(), 10, 0)
+            .await
+            .expect("search");
+        assert_eq!(titles(backslash), vec![r"back\slash"]);
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn full_text_search_ranks_better_matches_first() {
+        let (db, path) = setup_test_db().await;
+        let user = db
+            .create_user("ruth", "ruth@example.com", "hash")
+            .await
+            .expect("create user");
+        for (title, description) in [
+            ("Weekend", Some("Call the garden centre")),
+            ("Garden", Some("Garden beds, garden hose and garden gloves")),
+            ("Groceries", Some("Milk and eggs")),
+            ("Garden party", None),
+        ] {
+            db.create_item(CreateItem {
+                user_id: user.id,
+                title: title.to_string(),
+                description: description.map(str::to_string),
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        }
+
+        let titles = |items: Vec<crate::models::Item>| {
+            items.into_iter().map(|i| i.title).collect::<Vec<_>>()
+        };
+        let found = db
+            .fts_search_items(user.id, "garden")
+            .await
+            .expect("search");
+        assert_eq!(titles(found), vec!["Garden", "Garden party", "Weekend"]);
+        // Every word must match, each as a word prefix
+        let found = db
+            .fts_search_items(user.id, "gard GLOVE")
+            .await
+            .expect("search");
+        assert_eq!(titles(found), vec!["Garden"]);
+        // FTS5 operators and quotes are plain text
+        let found = db
+            .fts_search_items(user.id, "milk OR \"eggs")
+            .await
+            .expect("search");
+        assert!(found.is_empty());
+
+        let filter = ItemFilter {
+            query: Some("garden"),
+            full_text: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            db.count_search_user_items(user.id, &filter)
+                .await
+                .expect("count"),
+            3
+        );
+        let page = db
+            .search_user_items(user.id, &filter, ItemSort::relevance(), 1, 1)
+            .await
+            .expect("search");
+        assert_eq!(titles(page), vec!["Garden party"]);
+
+        // Without the index the same search is a newest-first `LIKE` match
+        let mut db = db;
+        db.full_text = false;
+        let found = db
+            .fts_search_items(user.id, "garden")
+            .await
+            .expect("search");
+        assert_eq!(titles(found), vec!["Garden party", "Garden", "Weekend"]);
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn full_text_index_follows_item_changes() {
+        let (db, path) = setup_test_db().await;
+        let user = db
+            .create_user("walt", "walt@example.com", "hash")
+            .await
+            .expect("create user");
+        let item = db
+            .create_item(CreateItem {
+                user_id: user.id,
+                title: "Paint fence".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        let search = |query: &'static str| {
+            let db = db.clone();
+            async move {
+                db.fts_search_items(user.id, query)
+                    .await
+                    .expect("search")
+                    .len()
+            }
+        };
+        assert_eq!(search("fence").await, 1);
+
+        db.update_item(item.id, user.id, "Mow lawn", Some("Front and back"), None)
+            .await
+            .expect("update item")
+            .expect("item exists");
+        assert_eq!(search("fence").await, 0);
+        assert_eq!(search("lawn").await, 1);
+        assert_eq!(search("back").await, 1);
+
+        // Trashed items stay indexed but are not returned; purged ones leave
+        db.delete_item(item.id, user.id).await.expect("trash item");
+        assert_eq!(search("lawn").await, 0);
+        db.restore_item(item.id, user.id)
+            .await
+            .expect("restore item");
+        assert_eq!(search("lawn").await, 1);
+        db.delete_item(item.id, user.id).await.expect("trash item");
+        assert!(db.purge_item(item.id, user.id).await.expect("purge item"));
+        let DbPool::Sqlite(pool) = &db.pool else {
+            panic!("expected a SQLite pool");
+        };
+        let (indexed,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM items_fts WHERE items_fts MATCH 'lawn'")
+                .fetch_one(pool)
+                .await
+                .expect("count indexed");
+        assert_eq!(indexed, 0);
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn any_item_update_advances_updated_at() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("ursula", "ursula@example.com", "hash")
+            .await
+            .expect("create user");
+        let item = db
+            .create_item(CreateItem {
+                user_id: user.id,
+                title: "Fixed title".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        let DbPool::Sqlite(pool) = &db.pool else {
+            panic!("expected a SQLite pool");
+        };
+        let long_ago = Utc::now() - chrono::Duration::days(30);
+        let backdate = || async {
+            sqlx::query("UPDATE items SET updated_at = $1 WHERE id = $2")
+                .bind(long_ago)
+                .bind(item.id)
+                .execute(pool)
+                .await
+                .expect("backdate");
+        };
+
+        // An explicit timestamp is kept
+        backdate().await;
+        let stored = db
+            .get_item(item.id, user.id)
+            .await
+            .expect("get")
+            .expect("item");
+        assert!(stored.updated_at < long_ago + chrono::Duration::days(1));
+
+        let updated = db
+            .update_item(
+                item.id,
+                user.id,
+                "Fixed title",
+                Some("Only this changed"),
+                None,
+            )
+            .await
+            .expect("update")
+            .expect("item");
+        assert!(updated.updated_at > long_ago + chrono::Duration::days(29));
+        let stored = db
+            .get_item(item.id, user.id)
+            .await
+            .expect("get")
+            .expect("item");
+        assert_eq!(stored.updated_at, updated.updated_at);
+
+        // Writes that never mention the column bump it too
+        backdate().await;
+        sqlx::query("UPDATE items SET description = NULL WHERE id = $1")
+            .bind(item.id)
+            .execute(pool)
+            .await
+            .expect("raw update");
+        let stored = db
+            .get_item(item.id, user.id)
+            .await
+            .expect("get")
+            .expect("item");
+        assert!(stored.updated_at > long_ago + chrono::Duration::days(29));
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn cursor_pages_visit_each_item_once() {
+        let (db, path) = setup_test_db().await;
+        let user = db
+            .create_user("cora", "cora@example.com", "hash")
+            .await
+            .expect("create user");
+        let add = |title: &str| {
+            db.create_item(CreateItem {
+                user_id: user.id,
+                title: title.to_string(),
+                description: None,
+                due_at: None,
+            })
+        };
+
+        // Several items share a timestamp, and some are stored in the bound
+        // format rather than SQLite's own
+        let base = chrono::Utc::now() - chrono::Duration::hours(1);
+        let DbPool::Sqlite(pool) = &db.pool else {
+            panic!("expected a SQLite pool");
+        };
+        let mut created = Vec::new();
+        for n in 0..7 {
+            let item = add(&format!("item {}", n)).await.expect("create item");
+            if n < 5 {
+                sqlx::query("UPDATE items SET created_at = $1 WHERE id = $2")
+                    .bind(base + chrono::Duration::milliseconds(500 * (n / 2)))
+                    .bind(item.id)
+                    .execute(pool)
+                    .await
+                    .expect("set created_at");
+            }
+            created.push(item.id);
+        }
+        let mut seen = Vec::new();
+        let mut after = None;
+        loop {
+            let page = db
+                .get_user_items_after_cursor(user.id, None, after.as_ref(), 3)
+                .await
+                .expect("page");
+            let Some(last) = page.last() else { break };
+            after = Some(ItemCursor::after(last));
+            seen.extend(page.iter().map(|i| i.id));
+            // Added between pages: newer than the cursor, so never visited
+            add("late").await.expect("create item");
+        }
+
+        // Newest first, and the later of two items created together first
+        created.reverse();
+        assert_eq!(seen, created);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn items_updated_since_include_trashed_ones() {
+        let (db, path) = setup_test_db().await;
+        let user = db
+            .create_user("sid", "sid@example.com", "hash")
+            .await
+            .expect("create user");
+        let other = db
+            .create_user("oda", "oda@example.com", "hash")
+            .await
+            .expect("create user");
+        let DbPool::Sqlite(pool) = &db.pool else {
+            panic!("expected a SQLite pool");
+        };
+        let mut ids = Vec::new();
+        for (owner, title) in [
+            (user.id, "kept"),
+            (user.id, "edited"),
+            (user.id, "trashed"),
+            (other.id, "theirs"),
+        ] {
+            let item = db
+                .create_item(CreateItem {
+                    user_id: owner,
+                    title: title.to_string(),
+                    description: None,
+                    due_at: None,
+                })
+                .await
+                .expect("create item");
+            ids.push(item.id);
+        }
+        // Everything last changed well before the cutoff
+        sqlx::query("UPDATE items SET updated_at = $1")
+            .bind(Utc::now() - chrono::Duration::hours(2))
+            .execute(pool)
+            .await
+            .expect("age items");
+        let cutoff = Utc::now() - chrono::Duration::hours(1);
+
+        db.update_item(ids[1], user.id, "edited again", None, None)
+            .await
+            .expect("update item");
+        assert!(db.delete_item(ids[2], user.id).await.expect("trash item"));
+        db.update_item(ids[3], other.id, "theirs again", None, None)
+            .await
+            .expect("update item");
+
+        let changed = db
+            .get_items_updated_since(user.id, cutoff)
+            .await
+            .expect("changed items");
+        let summary: Vec<(i64, bool)> = changed
+            .iter()
+            .map(|i| (i.id, i.deleted_at.is_some()))
+            .collect();
+        assert_eq!(summary, [(ids[1], false), (ids[2], true)]);
+        assert!(db
+            .get_items_updated_since(user.id, Utc::now() + chrono::Duration::hours(1))
+            .await
+            .expect("changed items")
+            .is_empty());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn sorted_items_follow_each_column() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("uma", "uma@example.com", "hash")
+            .await
+            .expect("create user");
+
+        // (title, created, updated) with distinct orders per column
+        let base = chrono::Utc::now();
+        let at = |minutes: i64| base + chrono::Duration::minutes(minutes);
+        for (title, created, updated) in [("banana", 1, 5), ("Apple", 2, 3), ("cherry", 3, 4)] {
+            let item = db
+                .create_item(CreateItem {
+                    user_id: user.id,
+                    title: title.to_string(),
+                    description: None,
+                    due_at: None,
+                })
+                .await
+                .expect("create item");
+            let DbPool::Sqlite(pool) = &db.pool else {
+                unreachable!("tests use SQLite")
+            };
+            sqlx::query("UPDATE items SET created_at = $1, updated_at = $2 WHERE id = $3")
+                .bind(at(created))
+                .bind(at(updated))
+                .bind(item.id)
+                .execute(pool)
+                .await
+                .expect("set timestamps");
+        }
+
+        let titles = |sort: &str, order: &str| {
+            let db = db.clone();
+            let sort = ItemSort::from_params(Some(sort), Some(order));
+            async move {
+                db.get_user_items_sorted(user.id, sort, 10, 0)
+                    .await
+                    .expect("sorted items")
+                    .into_iter()
+                    .map(|i| i.title)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        assert_eq!(titles("title", "asc").await, ["Apple", "banana", "cherry"]);
+        assert_eq!(titles("title", "desc").await, ["cherry", "banana", "Apple"]);
+        assert_eq!(
+            titles("created_at", "asc").await,
+            ["banana", "Apple", "cherry"]
+        );
+        assert_eq!(
+            titles("created_at", "desc").await,
+            ["cherry", "Apple", "banana"]
+        );
+        assert_eq!(
+            titles("updated_at", "asc").await,
+            ["Apple", "cherry", "banana"]
+        );
+        assert_eq!(
+            titles("updated_at", "desc").await,
+            ["banana", "cherry", "Apple"]
+        );
+        // Unknown columns fall back to newest first
+        assert_eq!(
+            titles("id; DROP TABLE items", "asc").await,
+            ["cherry", "Apple", "banana"]
+        );
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn bump_token_version_increments() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("hal", "hal@example.com", "hash")
+            .await
+            .expect("create user");
+        assert_eq!(user.token_version, 0);
+        assert_eq!(db.bump_token_version(user.id).await.expect("bump"), 1);
+        assert_eq!(db.bump_token_version(user.id).await.expect("bump"), 2);
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_keys_are_found_by_hash_and_revoked_by_owner() {
+        let (db, path) = setup_test_db().await;
+        let owner = db
+            .create_user("keys", "keys@example.com", "hash")
+            .await
+            .expect("create user");
+        let other = db
+            .create_user("other", "other@example.com", "hash")
+            .await
+            .expect("create user");
+
+        let key = db
+            .create_api_key(owner.id, "key-hash", "CI")
+            .await
+            .expect("create key");
+        assert!(key.last_used_at.is_none());
+        db.touch_api_key(key.id).await.expect("touch key");
+        let found = db
+            .find_api_key("key-hash")
+            .await
+            .expect("find key")
+            .expect("key exists");
+        assert_eq!(found.user_id, owner.id);
+        assert!(found.last_used_at.is_some());
+        assert_eq!(db.list_api_keys(owner.id).await.expect("list").len(), 1);
+        assert!(db.list_api_keys(other.id).await.expect("list").is_empty());
+
+        assert!(!db.delete_api_key(key.id, other.id).await.expect("delete"));
+        assert!(db.delete_api_key(key.id, owner.id).await.expect("delete"));
+        assert!(db
+            .find_api_key("key-hash")
+            .await
+            .expect("find key")
+            .is_none());
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn passkey_counters_only_move_forward() {
+        let (db, path) = setup_test_db().await;
+        let user = db
+            .create_user("keys", "keys@example.com", "hash")
+            .await
+            .expect("create user");
+
+        let counting = db
+            .create_passkey(user.id, "counting", "key", 5, "Phone")
+            .await
+            .expect("create passkey");
+        assert!(!db.record_passkey_use(counting.id, 5).await.expect("use"));
+        assert!(!db.record_passkey_use(counting.id, 0).await.expect("use"));
+        assert!(db.record_passkey_use(counting.id, 6).await.expect("use"));
+        let found = db
+            .find_passkey("counting")
+            .await
+            .expect("find passkey")
+            .expect("passkey exists");
+        assert_eq!(found.sign_count, 6);
+        assert!(found.last_used_at.is_some());
+
+        // Authenticators without a counter always report 0
+        let counterless = db
+            .create_passkey(user.id, "counterless", "key", 0, "Key")
+            .await
+            .expect("create passkey");
+        assert!(db.record_passkey_use(counterless.id, 0).await.expect("use"));
+        assert!(db.record_passkey_use(counterless.id, 0).await.expect("use"));
+
+        // Credential IDs are unique, and passkeys go with their account
+        assert!(db
+            .create_passkey(user.id, "counting", "key", 0, "Copy")
+            .await
+            .is_err());
+        assert_eq!(db.list_passkeys(user.id).await.expect("list").len(), 2);
+        db.delete_user(user.id).await.expect("delete user");
+        assert!(db
+            .find_passkey("counting")
+            .await
+            .expect("find passkey")
+            .is_none());
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn each_user_has_at_most_one_feed_token() {
+        let (db, path) = setup_test_db().await;
+        let user = db
+            .create_user("reader", "reader@example.com", "hash")
+            .await
+            .expect("create user");
+        assert!(db
+            .feed_token_created_at(user.id)
+            .await
+            .expect("created at")
+            .is_none());
+
+        db.set_feed_token(user.id, "first")
+            .await
+            .expect("set token");
+        db.set_feed_token(user.id, "second")
+            .await
+            .expect("reset token");
+        assert_eq!(db.find_feed_token_user("first").await.expect("find"), None);
+        assert_eq!(
+            db.find_feed_token_user("second").await.expect("find"),
+            Some(user.id)
+        );
+        assert!(db
+            .feed_token_created_at(user.id)
+            .await
+            .expect("created at")
+            .is_some());
+
+        assert!(db.delete_feed_token(user.id).await.expect("delete"));
+        assert!(!db.delete_feed_token(user.id).await.expect("delete again"));
+        assert_eq!(db.find_feed_token_user("second").await.expect("find"), None);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn login_history_is_pruned_past_the_retention() {
+        let (db, path) = setup_test_db().await;
+        let user = db
+            .create_user("seen", "seen@example.com", "hash")
+            .await
+            .expect("create user");
+        let long_ago = Utc::now() - chrono::Duration::days(90);
+
+        db.record_login(user.id, "203.0.113.1", None, long_ago)
+            .await
+            .expect("record login");
+        db.record_login(user.id, "203.0.113.2", Some("curl/8.5"), long_ago)
+            .await
+            .expect("record login");
+        let logins = db.recent_logins(user.id, 10).await.expect("recent logins");
+        let ips: Vec<&str> = logins.iter().map(|e| e.ip_address.as_str()).collect();
+        assert_eq!(ips, ["203.0.113.2", "203.0.113.1"]);
+        assert_eq!(logins[0].user_agent.as_deref(), Some("curl/8.5"));
+        assert_eq!(db.recent_logins(user.id, 1).await.expect("recent").len(), 1);
+
+        // Keeping nothing from before now leaves only the new login
+        db.record_login(
+            user.id,
+            "203.0.113.3",
+            None,
+            Utc::now() + chrono::Duration::seconds(1),
+        )
+        .await
+        .expect("record login");
+        let logins = db.recent_logins(user.id, 10).await.expect("recent logins");
+        assert_eq!(logins.len(), 1);
+        assert_eq!(logins[0].ip_address, "203.0.113.3");
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn item_counts_by_day_buckets_items_and_fills_gaps() {
+        let (db, path) = setup_test_db().await;
+        let user = db
+            .create_user("days", "days@example.com", "hash")
+            .await
+            .expect("create user");
+        let DbPool::Sqlite(pool) = &db.pool else {
+            unreachable!("tests use SQLite")
+        };
+
+        // Whole days back from now: two items 2 days ago, one today, and one
+        // before the range
+        let now = Utc::now();
+        for days_ago in [2, 2, 0, 45] {
+            let item = db
+                .create_item(CreateItem {
+                    user_id: user.id,
+                    title: format!("{} days ago", days_ago),
+                    description: None,
+                    due_at: None,
+                })
+                .await
+                .expect("create item");
+            sqlx::query("UPDATE items SET created_at = $1 WHERE id = $2")
+                .bind(now - chrono::Duration::days(days_ago))
+                .bind(item.id)
+                .execute(pool)
+                .await
+                .expect("set created_at");
+        }
+
+        let days = db
+            .item_counts_by_day(user.id, 7, Tz::UTC)
+            .await
+            .expect("counts");
+        let today = now.date_naive();
+        assert_eq!(
+            days.iter().map(|d| d.day).collect::<Vec<_>>(),
+            (0..7)
+                .rev()
+                .map(|n| today - chrono::Duration::days(n))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            days.iter().map(|d| d.count).collect::<Vec<_>>(),
+            [0, 0, 0, 0, 2, 0, 1]
+        );
+
+        // Another user's items and trashed ones do not count
+        let other = db
+            .create_user("nodays", "nodays@example.com", "hash")
+            .await
+            .expect("create user");
+        let trashed = db
+            .create_item(CreateItem {
+                user_id: other.id,
+                title: "Trashed".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        db.delete_item(trashed.id, other.id).await.expect("trash");
+        let days = db
+            .item_counts_by_day(other.id, 7, Tz::UTC)
+            .await
+            .expect("counts");
+        assert!(days.iter().all(|d| d.count == 0));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn idempotency_keys_expire_after_the_window() {
+        let (db, path) = setup_test_db().await;
+        let user = db
+            .create_user("idem", "idem@example.com", "hash")
+            .await
+            .expect("create user");
+        let item = || CreateItem {
+            user_id: user.id,
+            title: "Once".to_string(),
+            description: None,
+            due_at: None,
+        };
+
+        let (first, new) = db
+            .create_item_once(item(), vec![], "key", 3600)
+            .await
+            .expect("create");
+        assert!(new);
+        let (again, new) = db
+            .create_item_once(item(), vec![], "key", 3600)
+            .await
+            .expect("create again");
+        assert_eq!((again, new), (first, false));
+
+        // Outside the window the old record is dropped and the key reused
+        let (later, new) = db
+            .create_item_once(item(), vec![], "key", -1)
+            .await
+            .expect("create later");
+        assert!(new);
+        assert_ne!(later, first);
+        assert_eq!(
+            db.find_idempotent_item(user.id, "key", 3600)
+                .await
+                .expect("find"),
+            Some(later)
+        );
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn refresh_tokens_can_be_revoked_once() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("gus", "gus@example.com", "hash")
+            .await
+            .expect("create user");
+        let expires_at = chrono::Utc::now() + chrono::Duration::days(1);
+
+        let first = db
+            .create_refresh_token(user.id, "hash-1", expires_at, expires_at, true)
+            .await
+            .expect("create token");
+        db.create_refresh_token(user.id, "hash-2", expires_at, expires_at, false)
+            .await
+            .expect("create token");
+
+        let found = db
+            .find_refresh_token("hash-1")
+            .await
+            .expect("find token")
+            .expect("token exists");
+        assert_eq!(found.id, first.id);
+        assert!(found.remember);
+        assert_eq!(
+            found.session_expires_at.map(|at| at.timestamp()),
+            Some(expires_at.timestamp())
+        );
+        assert!(found.is_active());
+
+        assert!(db.revoke_refresh_token(first.id).await.expect("revoke"));
+        assert!(!db
+            .revoke_refresh_token(first.id)
+            .await
+            .expect("revoke again"));
+        let revoked = db
+            .find_refresh_token("hash-1")
+            .await
+            .expect("find token")
+            .expect("token exists");
+        assert!(!revoked.is_active());
+
+        assert_eq!(
+            db.revoke_user_refresh_tokens(user.id)
+                .await
+                .expect("revoke all"),
+            1
+        );
+        assert!(db
+            .find_refresh_token("missing")
+            .await
+            .expect("find")
+            .is_none());
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn item_tags_are_shared_and_replaced() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("tess", "tess@example.com", "hash")
+            .await
+            .expect("create user");
+        let new_item = |title: &str| CreateItem {
+            user_id: user.id,
+            title: title.to_string(),
+            description: None,
+            due_at: None,
+        };
+        let first = db.create_item(new_item("First")).await.expect("create");
+        let second = db.create_item(new_item("Second")).await.expect("create");
+        let tags = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+
+        db.set_item_tags(first.id, &tags(&["rust", "web"]))
+            .await
+            .expect("set tags");
+        db.set_item_tags(second.id, &tags(&["rust"]))
+            .await
+            .expect("set tags");
+        assert_eq!(
+            db.get_item_tags(first.id).await.expect("tags"),
+            ["rust", "web"]
+        );
+
+        // Tag names are stored once no matter how many items use them
+        let DbPool::Sqlite(pool) = &db.pool else {
+            unreachable!("tests use SQLite")
+        };
+        let (tag_rows,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM tags")
+            .fetch_one(pool)
+            .await
+            .expect("count tags");
+        assert_eq!(tag_rows, 2);
+
+        db.set_item_tags(first.id, &tags(&["web", "ops"]))
+            .await
+            .expect("replace tags");
+        assert_eq!(
+            db.get_item_tags(first.id).await.expect("tags"),
+            ["ops", "web"]
+        );
+
+        let rust_items = db.get_items_by_tag(user.id, "rust").await.expect("by tag");
+        assert_eq!(rust_items.len(), 1);
+        assert_eq!(rust_items[0].id, second.id);
+
+        let mut items = db.get_user_items(user.id).await.expect("items");
+        db.load_item_tags(&mut items).await.expect("load tags");
+        assert_eq!(items[0].tags, ["rust"]);
+        assert_eq!(items[1].tags, ["ops", "web"]);
+
+        let filter = ItemFilter {
+            query: Some("first"),
+            tag: Some("web"),
+            ..Default::default()
+        };
+        let found = db
+            .search_user_items(user.id, &filter, ItemSort::default(), 10, 0)
+            .await
+            .expect("search");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].id, first.id);
+        let filter = ItemFilter {
+            query: Some("second"),
+            tag: Some("web"),
+            ..Default::default()
+        };
+        assert_eq!(
+            db.count_search_user_items(user.id, &filter)
+                .await
+                .expect("count"),
+            0
+        );
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn deleted_items_move_to_trash_until_purged() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("tom", "tom@example.com", "hash")
+            .await
+            .expect("create user");
+        let item = db
+            .create_item(CreateItem {
+                user_id: user.id,
+                title: "Oops".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+
+        // Only live items can be purged
+        assert!(!db.purge_item(item.id, user.id).await.expect("purge live"));
+
+        assert!(db.delete_item(item.id, user.id).await.expect("delete"));
+        assert!(db.get_item(item.id, user.id).await.expect("get").is_none());
+        assert!(db.get_user_items(user.id).await.expect("items").is_empty());
+        assert_eq!(db.count_user_items(user.id).await.expect("count"), 0);
+        assert!(db
+            .update_item(item.id, user.id, "Edited", None, None)
+            .await
+            .expect("update")
+            .is_none());
+
+        let trash = db.get_trashed_items(user.id).await.expect("trash");
+        assert_eq!(trash.len(), 1);
+        assert!(trash[0].deleted_at.is_some());
+
+        assert!(db.restore_item(item.id, user.id).await.expect("restore"));
+        assert!(!db
+            .restore_item(item.id, user.id)
+            .await
+            .expect("restore again"));
+        assert!(db.get_item(item.id, user.id).await.expect("get").is_some());
+
+        assert!(db.delete_item(item.id, user.id).await.expect("delete"));
+        assert!(db.purge_item(item.id, user.id).await.expect("purge"));
+        assert!(db
+            .get_trashed_items(user.id)
+            .await
+            .expect("trash")
+            .is_empty());
+        assert!(!db
+            .restore_item(item.id, user.id)
+            .await
+            .expect("restore purged"));
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn invites_are_redeemed_once() {
+        let (db, path) = setup_test_db().await;
+        assert!(!db.has_users().await.expect("has users"));
+        let admin = db
+            .create_user("admin", "admin@example.com", "hash")
+            .await
+            .expect("create admin");
+        assert!(db.has_users().await.expect("has users"));
+        let invite = db
+            .create_invite("code-hash", admin.id)
+            .await
+            .expect("create invite");
+        let revoked = db
+            .create_invite("revoked-hash", admin.id)
+            .await
+            .expect("create invite");
+        assert!(db.delete_invite(revoked.id).await.expect("delete invite"));
+        assert!(!db
+            .invite_is_unused("revoked-hash")
+            .await
+            .expect("invite is unused"));
+
+        let redeem = |username: &'static str| {
+            db.with_transaction(move |tx| {
+                Box::pin(async move {
+                    let user = tx
+                        .create_user(username, &format!("{}@example.com", username), "hash")
+                        .await?;
+                    tx.redeem_invite("code-hash", user.id).await?;
+                    Ok(user)
+                })
+            })
+        };
+        let first = redeem("first").await.expect("redeem");
+        assert!(matches!(
+            redeem("second").await,
+            Err(sqlx::Error::RowNotFound)
+        ));
+        // The account that lost the race was not kept
+        assert!(db
+            .find_user_by_username("second")
+            .await
+            .expect("find user")
+            .is_none());
+
+        let invites = db.list_invites().await.expect("list invites");
+        assert_eq!(invites.len(), 1);
+        assert_eq!(
+            (invites[0].id, invites[0].used_by),
+            (invite.id, Some(first.id))
+        );
+        assert!(invites[0].used_at.is_some());
+        // Used invites stay as a record and cannot be revoked
+        assert!(!db.delete_invite(invite.id).await.expect("delete invite"));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn archived_items_are_only_listed_when_asked_for() {
+        let (db, path) = setup_test_db().await;
+        let user = db
+            .create_user("arlo", "arlo@example.com", "hash")
+            .await
+            .expect("create user");
+        let mut ids = vec![];
+        for title in ["Old report", "New report"] {
+            let item = db
+                .create_item(CreateItem {
+                    user_id: user.id,
+                    title: title.to_string(),
+                    description: None,
+                    due_at: None,
+                })
+                .await
+                .expect("create item");
+            ids.push(item.id);
+        }
+
+        assert!(db
+            .set_item_archived(ids[0], user.id, true)
+            .await
+            .expect("archive"));
+        assert!(!db
+            .set_item_archived(ids[0], user.id + 1, true)
+            .await
+            .expect("archive foreign item"));
+
+        let titles = |items: Vec<Item>| -> Vec<String> {
+            items.into_iter().map(|item| item.title).collect()
+        };
+        let sorted = db
+            .get_user_items_sorted(user.id, ItemSort::default(), 10, 0)
+            .await
+            .expect("active items");
+        assert_eq!(titles(sorted), ["New report"]);
+        let archived = ItemFilter {
+            archived: true,
+            ..Default::default()
+        };
+        let found = db
+            .search_user_items(user.id, &archived, ItemSort::default(), 10, 0)
+            .await
+            .expect("archived items");
+        assert_eq!(titles(found), ["Old report"]);
+        let search = ItemFilter {
+            query: Some("report"),
+            ..archived
+        };
+        assert_eq!(
+            db.count_search_user_items(user.id, &search)
+                .await
+                .expect("count archived"),
+            1
+        );
+
+        assert!(db
+            .set_item_archived(ids[0], user.id, false)
+            .await
+            .expect("unarchive"));
+        assert_eq!(
+            db.count_search_user_items(user.id, &ItemFilter::default())
+                .await
+                .expect("count active"),
+            2
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn reassigned_items_belong_to_the_new_owner() {
+        let (db, path) = setup_test_db().await;
+
+        let old = db
+            .create_user("olga", "olga@example.com", "hash")
+            .await
+            .expect("create user");
+        let new = db
+            .create_user("nils", "nils@example.com", "hash")
+            .await
+            .expect("create user");
+        let item = db
+            .create_item(CreateItem {
+                user_id: old.id,
+                title: "Handover".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+
+        match db
+            .reassign_item(item.id, new.id, new.id)
+            .await
+            .expect("reassign")
+        {
+            ReassignOutcome::Moved(moved) => assert_eq!(moved.user_id, new.id),
+            other => panic!("expected a move, got {:?}", other),
+        }
+        assert!(db.get_item(item.id, old.id).await.expect("get").is_none());
+        assert!(db.get_user_items(old.id).await.expect("items").is_empty());
+        assert!(db.get_item(item.id, new.id).await.expect("get").is_some());
+        assert_eq!(db.count_user_items(new.id).await.expect("count"), 1);
+
+        // A missing user or item changes nothing
+        assert!(matches!(
+            db.reassign_item(item.id, new.id + 100, new.id).await,
+            Ok(ReassignOutcome::UserNotFound)
+        ));
+        assert!(db.get_item(item.id, new.id).await.expect("get").is_some());
+        assert!(matches!(
+            db.reassign_item(item.id + 100, old.id, old.id).await,
+            Ok(ReassignOutcome::ItemNotFound)
+        ));
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn batch_create_is_all_or_nothing() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("bea", "bea@example.com", "hash")
+            .await
+            .expect("create user");
+        let item = |user_id: i64, title: &str| CreateItem {
+            user_id,
+            title: title.to_string(),
+            description: None,
+            due_at: None,
+        };
+
+        let created = db
+            .create_items_with_tags(vec![
+                (item(user.id, "First"), vec!["batch".to_string()]),
+                (item(user.id, "Second"), vec![]),
+            ])
+            .await
+            .expect("create batch");
+        assert_eq!(
+            created.iter().map(|i| i.title.as_str()).collect::<Vec<_>>(),
+            ["First", "Second"]
+        );
+        assert_eq!(created[0].tags, ["batch"]);
+        assert_ne!(created[0].id, created[1].id);
+
+        // The missing owner fails the second insert, taking the first with it
+        db.create_items_with_tags(vec![
+            (item(user.id, "Third"), vec![]),
+            (item(user.id + 100, "Orphan"), vec![]),
+        ])
+        .await
+        .expect_err("foreign key violation");
+        assert_eq!(db.count_user_items(user.id).await.expect("count"), 2);
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn bulk_delete_is_all_or_nothing() {
+        let (db, path) = setup_test_db().await;
+
+        let owner = db
+            .create_user("vic", "vic@example.com", "hash")
+            .await
+            .expect("create user");
+        let other = db
+            .create_user("walt", "walt@example.com", "hash")
+            .await
+            .expect("create user");
+        let mut ids = Vec::new();
+        for user_id in [owner.id, owner.id, owner.id, other.id] {
+            let item = db
+                .create_item(CreateItem {
+                    user_id,
+                    title: "Bulk".to_string(),
+                    description: None,
+                    due_at: None,
+                })
+                .await
+                .expect("create item");
+            ids.push(item.id);
+        }
+
+        // One foreign id rejects the whole batch
+        assert_eq!(
+            db.delete_items(owner.id, &[ids[0], ids[3]])
+                .await
+                .expect("delete"),
+            0
+        );
+        assert_eq!(db.count_user_items(owner.id).await.expect("count"), 3);
+        assert_eq!(db.count_user_items(other.id).await.expect("count"), 1);
+
+        assert_eq!(
+            db.delete_items(owner.id, &[ids[0], ids[1], ids[0]])
+                .await
+                .expect("delete"),
+            2
+        );
+        assert_eq!(db.count_user_items(owner.id).await.expect("count"), 1);
+
+        // Already-trashed ids also reject the batch
+        assert_eq!(
+            db.delete_items(owner.id, &[ids[1], ids[2]])
+                .await
+                .expect("delete"),
+            0
+        );
+        assert_eq!(db.count_user_items(owner.id).await.expect("count"), 1);
+
+        cleanup_db(path);
+    }
+
+    /// Item CRUD suite shared by every backend under test
+    /// Fail a transaction after two writes and check neither was kept
+    async fn run_rolled_back_transaction(db: &Database, username: &str) {
+        let owner = db
+            .create_user(username, &format!("{}@example.com", username), "hash")
+            .await
+            .expect("create user");
+        let (owner_id, second) = (owner.id, format!("{}_second", username));
+
+        let second_name = second.clone();
+        let result: Result<(), sqlx::Error> = db
+            .with_transaction(move |tx| {
+                Box::pin(async move {
+                    tx.create_user(
+                        &second_name,
+                        &format!("{}@example.com", second_name),
+                        "hash",
+                    )
+                    .await?;
+                    tx.create_item(CreateItem {
+                        user_id: owner_id,
+                        title: "Never saved".to_string(),
+                        description: None,
+                        due_at: None,
+                    })
+                    .await?;
+                    Err(sqlx::Error::Protocol("forced failure".to_string()))
+                })
+            })
+            .await;
+        assert!(result.is_err());
+
+        assert!(db
+            .find_user_by_username(&second)
+            .await
+            .expect("find user")
+            .is_none());
+        assert!(db
+            .get_user_items(owner_id)
+            .await
+            .expect("list items")
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_transaction_rolls_back_every_write() {
+        let (db, path) = setup_test_db().await;
+        run_rolled_back_transaction(&db, "tx_owner").await;
+        cleanup_db(path);
+    }
+
+    async fn run_reorder(db: &Database, username: &str) {
+        let owner = db
+            .create_user(username, &format!("{}@example.com", username), "hash")
+            .await
+            .expect("create user");
+        let other = db
+            .create_user(
+                &format!("{}_other", username),
+                &format!("{}_other@example.com", username),
+                "hash",
+            )
+            .await
+            .expect("create other user");
+        let mut ids = Vec::new();
+        for title in ["a", "b", "c", "d"] {
+            let item = db
+                .create_item(CreateItem {
+                    user_id: owner.id,
+                    title: title.to_string(),
+                    description: None,
+                    due_at: None,
+                })
+                .await
+                .expect("create item");
+            ids.push(item.id);
+        }
+        let foreign = db
+            .create_item(CreateItem {
+                user_id: other.id,
+                title: "theirs".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create foreign item");
+        let titles = |db: &Database| {
+            let db = db.clone();
+            async move {
+                db.get_user_items_sorted(owner.id, ItemSort::default(), 10, 0)
+                    .await
+                    .expect("list")
+                    .into_iter()
+                    .map(|item| item.title)
+                    .collect::<Vec<_>>()
+            }
+        };
+        // Never reordered: newest first
+        assert_eq!(titles(db).await, ["d", "c", "b", "a"]);
+        let before = db
+            .get_item(ids[0], owner.id)
+            .await
+            .expect("get")
+            .expect("item exists");
+
+        // Listed items go first; the rest keep their order after them
+        assert!(db
+            .reorder_items(owner.id, &[ids[0], ids[2]])
+            .await
+            .expect("reorder"));
+        assert_eq!(titles(db).await, ["a", "c", "d", "b"]);
+        let after = db
+            .get_item(ids[0], owner.id)
+            .await
+            .expect("get")
+            .expect("item exists");
+        assert_eq!(after.updated_at, before.updated_at);
+
+        // Another user's item, a trashed one or a repeat changes nothing
+        db.delete_item(ids[3], owner.id).await.expect("trash");
+        for rejected in [
+            vec![ids[1], foreign.id],
+            vec![ids[1], ids[3]],
+            vec![ids[1], ids[1]],
+        ] {
+            assert!(!db
+                .reorder_items(owner.id, &rejected)
+                .await
+                .expect("reorder"));
+        }
+        assert_eq!(titles(db).await, ["a", "c", "b"]);
+
+        // A new item, never placed, lists first
+        db.create_item(CreateItem {
+            user_id: owner.id,
+            title: "e".to_string(),
+            description: None,
+            due_at: None,
+        })
+        .await
+        .expect("create item");
+        assert_eq!(titles(db).await, ["e", "a", "c", "b"]);
+    }
+
+    async fn run_item_crud(db: &Database, username: &str) {
+        let user = db
+            .create_user(username, &format!("{}@example.com", username), "hash")
+            .await
+            .expect("create user");
+
+        let created = db
             .create_item(CreateItem {
                 user_id: user.id,
                 title: "First".to_string(),
                 description: Some("Desc".to_string()),
+                due_at: None,
             })
             .await
             .expect("create item");
@@ -313,6 +5540,33 @@ mod tests {
         assert_eq!(items.len(), 1);
         assert_eq!(items[0].title, "First");
 
+        let found = db
+            .search_user_items(user.id, &keyword("FIR"), ItemSort::default(), 10, 0)
+            .await
+            .expect("search items");
+        assert_eq!(found.len(), 1);
+
+        db.set_item_tags(created.id, &["alpha".to_string(), "beta".to_string()])
+            .await
+            .expect("set tags");
+        let tags = ItemFilter {
+            tag: Some("beta"),
+            ..Default::default()
+        };
+        assert_eq!(
+            db.count_search_user_items(user.id, &tags)
+                .await
+                .expect("count tagged"),
+            1
+        );
+        assert_eq!(
+            db.get_items_by_tag(user.id, "alpha")
+                .await
+                .expect("tagged items")
+                .len(),
+            1
+        );
+
         let fetched = db
             .get_item(created.id, user.id)
             .await
@@ -321,18 +5575,256 @@ mod tests {
         assert_eq!(fetched.description.as_deref(), Some("Desc"));
 
         let updated = db
-            .update_item(created.id, user.id, "Updated", Some("New"))
+            .update_item(created.id, user.id, "Updated", Some("New"), None)
             .await
             .expect("update item")
             .expect("updated item");
         assert_eq!(updated.title, "Updated");
 
+        let attachment = db
+            .create_attachment(CreateAttachment {
+                item_id: created.id,
+                filename: "notes.txt".to_string(),
+                stored_name: format!("{}-notes", username),
+                content_type: "text/plain".to_string(),
+                size: 5,
+            })
+            .await
+            .expect("create attachment");
+        assert_eq!(attachment.size, 5);
+        assert_eq!(
+            db.get_item_attachments(created.id)
+                .await
+                .expect("list attachments")
+                .len(),
+            1
+        );
+        assert!(db
+            .get_user_attachment(attachment.id, user.id)
+            .await
+            .expect("get attachment")
+            .is_some());
+
+        for body in ["First note", "Second note", "100% done"] {
+            db.add_comment(created.id, user.id, body)
+                .await
+                .expect("add comment");
+        }
+        let comments = db
+            .get_item_comments_paged(created.id, None, 2, 0)
+            .await
+            .expect("list comments");
+        assert_eq!(comments.len(), 2);
+        assert_eq!(comments[0].body, "First note");
+        assert_eq!(comments[0].username, username);
+        let rest = db
+            .get_item_comments_paged(created.id, None, 2, 2)
+            .await
+            .expect("list comments");
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].body, "100% done");
+        assert_eq!(
+            db.count_item_comments(created.id, Some("NOTE"))
+                .await
+                .expect("count comments"),
+            2
+        );
+        assert_eq!(
+            db.count_item_comments(created.id, Some("0%"))
+                .await
+                .expect("count comments"),
+            1
+        );
+
         let deleted = db
             .delete_item(created.id, user.id)
             .await
             .expect("delete item");
         assert!(deleted);
+        assert_eq!(db.get_trashed_items(user.id).await.expect("trash").len(), 1);
+        // Attachments of trashed items are not served
+        assert!(db
+            .get_user_attachment(attachment.id, user.id)
+            .await
+            .expect("get attachment")
+            .is_none());
+        assert!(db.purge_item(created.id, user.id).await.expect("purge"));
+        // Purging the item drops its attachment rows
+        assert!(db
+            .get_item_attachments(created.id)
+            .await
+            .expect("list attachments")
+            .is_empty());
+        assert_eq!(
+            db.count_item_comments(created.id, None)
+                .await
+                .expect("count comments"),
+            0
+        );
+
+        let bulk = db
+            .create_item(CreateItem {
+                user_id: user.id,
+                title: "Bulk".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        assert_eq!(db.delete_items(user.id, &[bulk.id]).await.expect("bulk"), 1);
+    }
+
+    #[tokio::test]
+    async fn item_crud_works() {
+        let (db, path) = setup_test_db().await;
+        run_item_crud(&db, "bob").await;
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn item_writes_refuse_values_over_the_limits() {
+        let (db, path) = setup_test_db().await;
+        let db = db.with_item_limits(ItemLimits {
+            title: 5,
+            description: 5,
+        });
+        let user = db
+            .create_user("lena", "lena@example.com", "hash")
+            .await
+            .expect("create user");
+        let item = |title: &str, description: &str| CreateItem {
+            user_id: user.id,
+            title: title.to_string(),
+            description: Some(description.to_string()),
+            due_at: None,
+        };
+
+        // Five characters, though ten bytes
+        let created = db
+            .create_item(item("Exact", "ééééé"))
+            .await
+            .expect("at the limits");
+        for result in [
+            db.create_item(item("Longer", "")).await.map(|_| ()),
+            db.create_item_with_tags(item("Fits", "Longer"), vec![])
+                .await
+                .map(|_| ()),
+            db.create_items_with_tags(vec![
+                (item("Fits", ""), vec![]),
+                (item("Longer", ""), vec![]),
+            ])
+            .await
+            .map(|_| ()),
+            db.update_item(created.id, user.id, "Longer", None, None)
+                .await
+                .map(|_| ()),
+            db.update_item_with_tags(created.id, user.id, "Fits", Some("Longer"), None, vec![])
+                .await
+                .map(|_| ()),
+        ] {
+            assert!(matches!(result, Err(sqlx::Error::InvalidArgument(_))));
+        }
+
+        let items = db.get_user_items(user.id).await.expect("list items");
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].title, "Exact");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn reordering_sets_the_manual_order() {
+        let (db, path) = setup_test_db().await;
+        run_reorder(&db, "rory").await;
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn item_timestamps_order_chronologically() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("dana", "dana@example.com", "hash")
+            .await
+            .expect("create user");
+
+        let new_item = |title: &str| CreateItem {
+            user_id: user.id,
+            title: title.to_string(),
+            description: None,
+            due_at: None,
+        };
+
+        let earlier = db.create_item(new_item("Earlier")).await.expect("create");
+        // CURRENT_TIMESTAMP has second precision in SQLite
+        tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+        let later = db.create_item(new_item("Later")).await.expect("create");
+
+        assert!(later.created_at > earlier.created_at);
+
+        let items = db.get_user_items(user.id).await.expect("list items");
+        assert_eq!(items[0].id, later.id);
+        assert_eq!(items[1].id, earlier.id);
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn paged_items_follow_offset() {
+        let (db, path) = setup_test_db().await;
+
+        let user = db
+            .create_user("erin", "erin@example.com", "hash")
+            .await
+            .expect("create user");
+
+        for i in 0..5 {
+            db.create_item(CreateItem {
+                user_id: user.id,
+                title: format!("Item {}", i),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        }
+
+        assert_eq!(db.count_user_items(user.id).await.expect("count"), 5);
+
+        let first = db
+            .get_user_items_sorted(user.id, ItemSort::default(), 2, 0)
+            .await
+            .expect("first page");
+        let last = db
+            .get_user_items_sorted(user.id, ItemSort::default(), 2, 4)
+            .await
+            .expect("last page");
+
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[0].title, "Item 4");
+        assert_eq!(last.len(), 1);
+        assert_eq!(last[0].title, "Item 0");
 
         cleanup_db(path);
     }
+
+    #[tokio::test]
+    async fn item_crud_works_on_postgres() {
+        // Only runs when a Postgres database is provided, e.g.
+        // TEST_DATABASE_URL=postgres://postgres@localhost/crud_test
+        let Ok(url) = std::env::var("TEST_DATABASE_URL") else {
+            return;
+        };
+
+        let db = Database::new(&url).await.expect("connect to postgres");
+        assert!(matches!(db.pool, DbPool::Postgres(_)));
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos();
+        run_item_crud(&db, &format!("pg_user_{}", nanos)).await;
+        run_reorder(&db, &format!("pg_reorder_{}", nanos)).await;
+        run_email_change(&db, &format!("pg_email_{}", nanos)).await;
+        run_rolled_back_transaction(&db, &format!("pg_tx_{}", nanos)).await;
+    }
 }
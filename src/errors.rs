@@ -0,0 +1,187 @@
+use rustapi_rs::prelude::*;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::validation::FieldErrors;
+
+/// A JSON error, sent as `{"error": {"code", "message", "details"}}`
+///
+/// `code` is stable for clients to match on, while `message` is meant for
+/// people and may be reworded. `details` is left out unless an error has
+/// more to say, such as the fields that failed validation.
+#[derive(Debug, Clone, Serialize)]
+pub struct ApiErrorResponse {
+    #[serde(skip)]
+    pub status: StatusCode,
+    pub code: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<Value>,
+}
+
+impl ApiErrorResponse {
+    /// An error with the usual message for `code`
+    pub fn new(status: StatusCode, code: &str) -> Self {
+        Self {
+            status,
+            code: code.to_string(),
+            message: message_for(status, code).to_string(),
+            details: None,
+        }
+    }
+
+    /// 404 `not_found`
+    pub fn not_found() -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found")
+    }
+
+    /// 422 `validation_failed`, listing each field's messages under
+    /// `details.fields`
+    pub fn validation(fields: &FieldErrors) -> Self {
+        Self::new(StatusCode::UNPROCESSABLE_ENTITY, "validation_failed")
+            .with_details(json!({ "fields": fields }))
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// The whole JSON body, envelope included
+    pub fn body(&self) -> Value {
+        json!({ "error": self })
+    }
+}
+
+/// Stable codes for database failures; the error itself goes to the log,
+/// never to the client
+impl From<&sqlx::Error> for ApiErrorResponse {
+    fn from(error: &sqlx::Error) -> Self {
+        match error {
+            sqlx::Error::RowNotFound => Self::not_found(),
+            sqlx::Error::Database(e) if e.is_unique_violation() => {
+                Self::new(StatusCode::CONFLICT, "conflict")
+            }
+            // What `Database` answers for an item over the length limits
+            sqlx::Error::InvalidArgument(_) => {
+                Self::new(StatusCode::UNPROCESSABLE_ENTITY, "validation_failed")
+            }
+            sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed | sqlx::Error::Io(_) => {
+                Self::new(StatusCode::SERVICE_UNAVAILABLE, "database_unavailable")
+            }
+            _ => Self::new(StatusCode::INTERNAL_SERVER_ERROR, "database_error"),
+        }
+    }
+}
+
+impl IntoResponse for ApiErrorResponse {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.body())).into_response()
+    }
+}
+
+// What each code tells people; unknown codes fall back to the status text
+fn message_for(status: StatusCode, code: &str) -> &'static str {
+    match code {
+        "unauthorized" => "Authentication is required",
+        "not_found" => "The requested resource does not exist",
+        "validation_failed" => "The request contains invalid fields",
+        "conflict" => "The request conflicts with existing data",
+        "database_error" => "A database error occurred. Please try again.",
+        "database_unavailable" => "The database is temporarily unavailable",
+        "internal_error" => "An unexpected error occurred. Please try again.",
+        "item_limit_reached" => "This account has reached its item limit",
+        "batch_too_large" => "The batch contains too many items",
+        "invalid_cursor" => "The pagination cursor is not valid",
+        "invalid_since" => "`since` must be an RFC 3339 timestamp",
+        "invalid_credentials" => "Invalid username or password",
+        "account_locked" => "This account is temporarily locked",
+        "too_many_attempts" => "Too many failed login attempts. Please try again later.",
+        "email_unverified" => "The email address has not been verified",
+        "token_error" => "The access token could not be issued",
+        "maintenance" => "The service is down for maintenance",
+        "malformed_passkey"
+        | "invalid_challenge"
+        | "invalid_origin"
+        | "invalid_rp_id"
+        | "user_not_present"
+        | "unsupported_algorithm"
+        | "invalid_signature"
+        | "unknown_passkey" => "The passkey could not be verified",
+        "cloned_passkey" => {
+            "This passkey's signature counter went backwards; it may have been copied"
+        }
+        "too_many_challenges" => {
+            "Too many passkey requests are in progress. Please try again later."
+        }
+        _ => status.canonical_reason().unwrap_or("Error"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{body_string, cleanup_db, setup_test_state};
+
+    async fn json_body(response: Response) -> Value {
+        serde_json::from_str(&body_string(response).await).expect("JSON body")
+    }
+
+    #[tokio::test]
+    async fn not_found_uses_the_envelope() {
+        let response = ApiErrorResponse::not_found().into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            json_body(response).await,
+            json!({ "error": {
+                "code": "not_found",
+                "message": "The requested resource does not exist",
+            } })
+        );
+
+        let response = ApiErrorResponse::from(&sqlx::Error::RowNotFound).into_response();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(json_body(response).await["error"]["code"], "not_found");
+    }
+
+    #[tokio::test]
+    async fn validation_errors_list_their_fields() {
+        let fields =
+            FieldErrors::from([("title".to_string(), vec!["Title is required".to_string()])]);
+        let response = ApiErrorResponse::validation(&fields).into_response();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            json_body(response).await,
+            json!({ "error": {
+                "code": "validation_failed",
+                "message": "The request contains invalid fields",
+                "details": { "fields": { "title": ["Title is required"] } },
+            } })
+        );
+    }
+
+    #[tokio::test]
+    async fn database_failures_map_to_stable_codes() {
+        let (state, path) = setup_test_state().await;
+        state
+            .db
+            .create_user("taken", "taken@example.com", "hash")
+            .await
+            .expect("create user");
+        let duplicate = state
+            .db
+            .create_user("taken", "other@example.com", "hash")
+            .await
+            .expect_err("duplicate username");
+        let error = ApiErrorResponse::from(&duplicate);
+        assert_eq!(
+            (error.status, error.code.as_str()),
+            (StatusCode::CONFLICT, "conflict")
+        );
+
+        let code = |error: sqlx::Error| ApiErrorResponse::from(&error).code;
+        assert_eq!(code(sqlx::Error::PoolTimedOut), "database_unavailable");
+        assert_eq!(code(sqlx::Error::Protocol("bad".into())), "database_error");
+        cleanup_db(path);
+    }
+}
@@ -0,0 +1,71 @@
+use rustapi_rs::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// `Cache-Control` of conditional JSON responses: they belong to one user,
+/// and clients must revalidate before reusing them
+pub const CACHE_CONTROL: &str = "private, no-cache";
+
+/// Strong ETag of a JSON body: a hash of its serialized form, so it changes
+/// whenever any field does, `updated_at` included
+pub fn etag_of<T: Serialize>(value: &T) -> String {
+    let json = serde_json::to_vec(value).unwrap_or_default();
+    format!("\"{}\"", &hex::encode(Sha256::digest(json))[..32])
+}
+
+/// Whether an `If-None-Match` header value names `etag`
+///
+/// Accepts `*` and comma-separated lists; weak tags (`W/"..."`) match their
+/// strong counterpart, as RFC 9110 asks for this header.
+pub fn matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
+
+/// `304 Not Modified` when the client already holds the current version of
+/// `value`, otherwise `value` as JSON; both carry its ETag
+pub fn conditional_json<T: Serialize>(value: T, if_none_match: Option<&str>) -> Response {
+    let etag = etag_of(&value);
+    let mut response = if if_none_match.is_some_and(|header| matches(header, &etag)) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        Json(value).into_response()
+    };
+
+    for (name, value) in [("ETag", etag.as_str()), ("Cache-Control", CACHE_CONTROL)] {
+        if let Ok(value) = value.parse() {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn etag_follows_the_body() {
+        let etag = etag_of(&json!({ "id": 1, "updated_at": "2024-01-01T00:00:00Z" }));
+        assert!(etag.starts_with('"') && etag.ends_with('"'));
+        assert_eq!(
+            etag,
+            etag_of(&json!({ "id": 1, "updated_at": "2024-01-01T00:00:00Z" }))
+        );
+        assert_ne!(
+            etag,
+            etag_of(&json!({ "id": 1, "updated_at": "2024-01-01T00:00:01Z" }))
+        );
+    }
+
+    #[test]
+    fn matches_lists_weak_tags_and_wildcards() {
+        assert!(matches("\"abc\"", "\"abc\""));
+        assert!(matches("\"old\", W/\"abc\"", "\"abc\""));
+        assert!(matches("*", "\"abc\""));
+        assert!(!matches("\"old\"", "\"abc\""));
+        assert!(!matches("abc", "\"abc\""));
+    }
+}
@@ -0,0 +1,164 @@
+use futures_util::Stream;
+use rustapi_rs::prelude::SseEvent;
+use serde_json::json;
+use std::{convert::Infallible, time::Duration};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Events a slow subscriber may fall behind by before it misses some
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// How often an idle stream gets a comment, so proxies keep it open
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// What happened to an item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemEventKind {
+    Created,
+    Updated,
+    /// Moved to the trash
+    Deleted,
+}
+
+impl ItemEventKind {
+    /// The SSE event name, also sent as `kind`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ItemEventKind::Created => "created",
+            ItemEventKind::Updated => "updated",
+            ItemEventKind::Deleted => "deleted",
+        }
+    }
+}
+
+/// A change to one of a user's items
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemEvent {
+    /// Owner of the item; only their own streams see the event
+    pub user_id: i64,
+    pub item_id: i64,
+    pub kind: ItemEventKind,
+}
+
+/// Item changes for `GET /items/events`, published by handlers after each
+/// successful write
+///
+/// Every stream subscribes to all events and keeps its own user's, so a
+/// publish with nobody listening costs nothing but the clone.
+#[derive(Debug)]
+pub struct ItemEvents {
+    sender: broadcast::Sender<ItemEvent>,
+}
+
+impl Default for ItemEvents {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl ItemEvents {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self { sender }
+    }
+
+    pub fn publish(&self, user_id: i64, item_id: i64, kind: ItemEventKind) {
+        // Fails only when no stream is open, which is fine
+        let _ = self.sender.send(ItemEvent {
+            user_id,
+            item_id,
+            kind,
+        });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ItemEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Streams open right now
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+
+    /// `user_id`'s events from now on, each named after its kind with
+    /// `{"item_id", "kind"}` as data
+    ///
+    /// A stream that falls more than the channel's capacity behind gets one
+    /// `lagged` event with the number it missed instead. Dropping the stream,
+    /// as the server does once the client disconnects, unsubscribes it.
+    pub fn stream_for(
+        &self,
+        user_id: i64,
+    ) -> impl Stream<Item = Result<SseEvent, Infallible>> + Send + 'static {
+        futures_util::stream::unfold(self.subscribe(), move |mut receiver| async move {
+            loop {
+                let event = match receiver.recv().await {
+                    Ok(event) if event.user_id == user_id => sse_event(&event),
+                    Ok(_) => continue,
+                    Err(RecvError::Lagged(missed)) => {
+                        SseEvent::new(missed.to_string()).event("lagged")
+                    }
+                    Err(RecvError::Closed) => return None,
+                };
+                return Some((Ok(event), receiver));
+            }
+        })
+    }
+}
+
+fn sse_event(event: &ItemEvent) -> SseEvent {
+    let data = json!({ "item_id": event.item_id, "kind": event.kind.as_str() });
+    SseEvent::new(data.to_string()).event(event.kind.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+
+    #[tokio::test]
+    async fn subscribers_get_events_published_after_they_joined() {
+        let events = ItemEvents::default();
+        events.publish(1, 1, ItemEventKind::Created);
+
+        let mut receiver = events.subscribe();
+        assert_eq!(events.subscriber_count(), 1);
+        events.publish(2, 7, ItemEventKind::Deleted);
+        assert_eq!(
+            receiver.recv().await.expect("event"),
+            ItemEvent {
+                user_id: 2,
+                item_id: 7,
+                kind: ItemEventKind::Deleted,
+            }
+        );
+        assert!(receiver.try_recv().is_err());
+
+        drop(receiver);
+        assert_eq!(events.subscriber_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn streams_only_carry_their_users_events() {
+        let events = ItemEvents::new(2);
+        let mut stream = Box::pin(events.stream_for(1));
+        // Nothing has happened yet
+        assert!(futures_util::poll!(stream.next()).is_pending());
+
+        events.publish(2, 5, ItemEventKind::Created);
+        events.publish(1, 6, ItemEventKind::Updated);
+        let event = stream.next().await.expect("event").expect("ok");
+        assert_eq!(
+            event.to_sse_string(),
+            "event: updated\ndata: {\"item_id\":6,\"kind\":\"updated\"}\n\n"
+        );
+
+        for item_id in 7..10 {
+            events.publish(1, item_id, ItemEventKind::Deleted);
+        }
+        let event = stream.next().await.expect("event").expect("ok");
+        assert_eq!(event.to_sse_string(), "event: lagged\ndata: 1\n\n");
+
+        drop(stream);
+        assert_eq!(events.subscriber_count(), 0);
+    }
+}
@@ -1,9 +1,49 @@
-use rustapi_openapi::{Operation, OperationModifier};
-use rustapi_rs::{ApiError, Cookies, FromRequest, Request, Result};
+use bytes::Bytes;
+use rustapi_openapi::{
+    schema::{RustApiSchema, SchemaCtx},
+    MediaType, Operation, OperationModifier, Parameter, RequestBody,
+};
+use rustapi_rs::{
+    ApiError, Cookies, FromRequest, FromRequestParts, Request, Response, Result, StatusCode,
+};
 use serde::de::DeserializeOwned;
+use std::{collections::BTreeMap, marker::PhantomData, net::IpAddr};
+
+use crate::{
+    client_ip,
+    csrf::{self, CSRF_FIELD},
+    flash::Flash,
+    middleware::{access_token, account_from_token, api_key, get_current_user, user_from_api_key},
+    models::{Role, User, UserInfo, MAX_USER_AGENT_LENGTH},
+    openapi,
+    route_limits::RouteLimits,
+    sudo, tokens,
+    validation::{field_errors, FieldErrors, ValidateConfigured},
+    AppState,
+};
+
+/// Header API clients send an idempotency key in
+pub const IDEMPOTENCY_HEADER: &str = "Idempotency-Key";
+
+/// Form field carrying the idempotency key of the new item form
+pub const IDEMPOTENCY_FIELD: &str = "idempotency_key";
+
+/// Longest idempotency key accepted
+pub const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+/// How long a used idempotency key keeps returning its first result
+pub const IDEMPOTENCY_WINDOW_SECS: i64 = 24 * 60 * 60;
+
+/// Largest URL-encoded or JSON body accepted by `Form<T>` and `JsonBody<T>`
+/// unless `MAX_FORM_BYTES` overrides it
+pub const DEFAULT_MAX_FORM_BYTES: usize = 256 * 1024;
 
 /// Custom Form extractor for URL-encoded form data
 /// Similar to Axum's Form extractor but works with RustAPI
+///
+/// Bodies over the route's `RouteLimits::max_body_bytes`, which is
+/// `Config::max_form_bytes` unless the route overrides it, are rejected
+/// with 413 before parsing.
 pub struct Form<T>(pub T);
 
 impl<T> std::ops::Deref for Form<T> {
@@ -16,13 +56,8 @@ impl<T> std::ops::Deref for Form<T> {
 
 impl<T: DeserializeOwned + Send + 'static> FromRequest for Form<T> {
     async fn from_request(req: &mut Request) -> Result<Self> {
-        // Ensure the body is loaded
-        req.load_body().await?;
-
-        // Get the body bytes
-        let body_bytes = req
-            .take_body()
-            .ok_or_else(|| ApiError::internal("Body already consumed"))?;
+        let limit = RouteLimits::of(&app_state(req)?, req).max_body_bytes;
+        let body_bytes = take_body_within(req, limit).await?;
 
         // Parse as URL-encoded form data
         let form: T = serde_urlencoded::from_bytes(&body_bytes)
@@ -32,10 +67,241 @@ impl<T: DeserializeOwned + Send + 'static> FromRequest for Form<T> {
     }
 }
 
-impl<T> OperationModifier for Form<T> {
+impl<T: RustApiSchema> OperationModifier for Form<T> {
+    fn update_operation(op: &mut Operation) {
+        op.request_body = Some(request_body::<T>("application/x-www-form-urlencoded"));
+    }
+}
+
+/// `Form<T>` that also runs the form's `#[validate(...)]` rules and the
+/// checks against the configured limits
+///
+/// Holds the form when every rule passes, or the per-field messages ready to
+/// render when one fails; a body that doesn't parse is still a 400.
+pub struct ValidatedForm<T>(pub std::result::Result<T, FieldErrors>);
+
+impl<T: DeserializeOwned + ValidateConfigured + Send + 'static> FromRequest for ValidatedForm<T> {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        let state = app_state(req)?;
+        let Form(form) = Form::<T>::from_request(req).await?;
+
+        Ok(ValidatedForm(match form.validate_configured(&state) {
+            Ok(()) => Ok(form),
+            Err(errors) => Err(field_errors(&errors)),
+        }))
+    }
+}
+
+impl<T: RustApiSchema> OperationModifier for ValidatedForm<T> {
+    fn update_operation(op: &mut Operation) {
+        Form::<T>::update_operation(op);
+    }
+}
+
+/// Query string extractor, parsed with the same `serde_urlencoded` rules as
+/// `Form<T>`
+///
+/// A missing required parameter or a value of the wrong type is a 400 that
+/// names the problem.
+pub struct Query<T>(pub T);
+
+impl<T> std::ops::Deref for Query<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + Send + 'static> FromRequestParts for Query<T> {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        let query = req.query_string().unwrap_or_default();
+
+        let value: T = serde_urlencoded::from_str(query)
+            .map_err(|e| ApiError::bad_request(format!("Invalid query parameters: {}", e)))?;
+
+        Ok(Query(value))
+    }
+}
+
+impl<T: RustApiSchema> OperationModifier for Query<T> {
+    fn update_operation(op: &mut Operation) {
+        let mut ctx = SchemaCtx::new();
+        let Some(fields) = T::field_schemas(&mut ctx) else {
+            return;
+        };
+        op.parameters
+            .extend(fields.into_iter().map(|(name, schema)| Parameter {
+                name,
+                location: "query".to_string(),
+                description: None,
+                // Every query struct in this app defaults its fields
+                required: false,
+                deprecated: None,
+                schema: Some(schema),
+            }));
+    }
+}
+
+/// JSON body extractor for API handlers
+/// Like `Form<T>`, but parses `application/json` and skips the schema bound of RustAPI's `Json<T>`
+pub struct JsonBody<T>(pub T);
+
+impl<T> std::ops::Deref for JsonBody<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: DeserializeOwned + Send + 'static> FromRequest for JsonBody<T> {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        let limit = RouteLimits::of(&app_state(req)?, req).max_body_bytes;
+        let body_bytes = take_body_within(req, limit).await?;
+
+        let value: T = serde_json::from_slice(&body_bytes)
+            .map_err(|e| ApiError::bad_request(format!("Invalid JSON body: {}", e)))?;
+
+        Ok(JsonBody(value))
+    }
+}
+
+impl<T: RustApiSchema> OperationModifier for JsonBody<T> {
+    fn update_operation(op: &mut Operation) {
+        op.request_body = Some(request_body::<T>("application/json"));
+    }
+}
+
+// Helper function to document a required request body of type `T`; the
+// schema itself is registered in `openapi::document`
+fn request_body<T: RustApiSchema>(content_type: &str) -> RequestBody {
+    let schema = T::schema(&mut SchemaCtx::new());
+    RequestBody {
+        description: None,
+        required: Some(true),
+        content: BTreeMap::from([(
+            content_type.to_string(),
+            MediaType {
+                schema: Some(schema),
+                example: None,
+            },
+        )]),
+    }
+}
+
+/// A file part of a `multipart/form-data` body
+#[derive(Debug, Clone)]
+pub struct UploadedFile {
+    /// Form field the file was sent under
+    pub field: String,
+    /// File name as sent by the browser; never use it as a path
+    pub filename: String,
+    /// Declared MIME type, `application/octet-stream` when missing
+    pub content_type: String,
+    pub bytes: Bytes,
+}
+
+/// Multipart form extractor for file uploads
+///
+/// Buffers a `multipart/form-data` body into its text fields and files,
+/// rejecting any file larger than `UploadConfig::max_bytes` and any body
+/// larger than `UploadConfig::max_body_bytes` with 413. Like
+/// `Form<T>` it consumes the body, so it must come after `CsrfProtected`.
+#[derive(Debug, Default)]
+pub struct MultipartForm {
+    pub fields: Vec<(String, String)>,
+    pub files: Vec<UploadedFile>,
+}
+
+impl MultipartForm {
+    /// Parse a buffered body; `max_file_bytes` bounds every single part
+    pub async fn parse(
+        content_type: &str,
+        body: Bytes,
+        max_file_bytes: Option<usize>,
+    ) -> Result<Self> {
+        let boundary = multer::parse_boundary(content_type)
+            .map_err(|_| ApiError::bad_request("Expected a multipart/form-data body"))?;
+        let mut limit = multer::SizeLimit::new();
+        if let Some(max) = max_file_bytes {
+            limit = limit.per_field(max as u64);
+        }
+        let stream = futures_util::stream::once(async move { Ok::<_, std::io::Error>(body) });
+        let mut multipart = multer::Multipart::with_constraints(
+            stream,
+            boundary,
+            multer::Constraints::new().size_limit(limit),
+        );
+
+        let mut form = MultipartForm::default();
+        while let Some(field) = multipart.next_field().await.map_err(multipart_error)? {
+            let name = field.name().unwrap_or_default().to_string();
+            match field.file_name().map(str::to_string) {
+                Some(filename) => {
+                    let content_type = field
+                        .content_type()
+                        .map(|mime| mime.to_string())
+                        .unwrap_or_else(|| "application/octet-stream".to_string());
+                    let bytes = field.bytes().await.map_err(multipart_error)?;
+                    form.files.push(UploadedFile {
+                        field: name,
+                        filename,
+                        content_type,
+                        bytes,
+                    });
+                }
+                None => {
+                    let value = field.text().await.map_err(multipart_error)?;
+                    form.fields.push((name, value));
+                }
+            }
+        }
+
+        Ok(form)
+    }
+
+    /// First text field with this name
+    pub fn text(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// First file sent under this field name
+    pub fn file(&self, name: &str) -> Option<&UploadedFile> {
+        self.files.iter().find(|file| file.field == name)
+    }
+}
+
+impl FromRequest for MultipartForm {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        let state = app_state(req)?;
+        let content_type = content_type(req);
+
+        let body = take_body_within(req, state.config.uploads.max_body_bytes()).await?;
+
+        MultipartForm::parse(&content_type, body, Some(state.config.uploads.max_bytes)).await
+    }
+}
+
+impl OperationModifier for MultipartForm {
     fn update_operation(_op: &mut Operation) {}
 }
 
+fn multipart_error(error: multer::Error) -> ApiError {
+    match error {
+        multer::Error::FieldSizeExceeded { limit, .. }
+        | multer::Error::StreamSizeExceeded { limit } => ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "payload_too_large",
+            format!("Uploaded files are limited to {} bytes", limit),
+        ),
+        e => ApiError::bad_request(format!("Invalid multipart body: {}", e)),
+    }
+}
+
 /// Wrapper around Cookies to satisfy OperationModifier bound
 pub struct AppCookies(pub Cookies);
 
@@ -57,3 +323,1173 @@ impl FromRequest for AppCookies {
 impl OperationModifier for AppCookies {
     fn update_operation(_op: &mut Operation) {}
 }
+
+/// JWT the request was sent with, from an `Authorization: Bearer` header
+/// or the `token` cookie; `None` for anonymous requests
+///
+/// Handlers that serve both anonymous and logged-in users pass it to
+/// `get_current_user`.
+pub struct AccessToken(pub Option<String>);
+
+impl FromRequestParts for AccessToken {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        Ok(AccessToken(access_token(req)))
+    }
+}
+
+impl OperationModifier for AccessToken {
+    fn update_operation(_op: &mut Operation) {}
+}
+
+/// `If-None-Match` header of a conditional GET, holding the ETags the client
+/// has cached; see `etag::conditional_json`
+pub struct IfNoneMatch(pub Option<String>);
+
+impl FromRequestParts for IfNoneMatch {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        Ok(IfNoneMatch(
+            req.headers()
+                .get("If-None-Match")
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string),
+        ))
+    }
+}
+
+impl OperationModifier for IfNoneMatch {
+    fn update_operation(op: &mut Operation) {
+        op.parameters.push(Parameter {
+            name: "If-None-Match".to_string(),
+            location: "header".to_string(),
+            description: Some(
+                "ETag of a cached copy; answered with 304 while it is current".to_string(),
+            ),
+            required: false,
+            deprecated: None,
+            schema: None,
+        });
+    }
+}
+
+/// Address of the client that sent the request
+///
+/// Forwarding headers are only believed from `Config::trusted_proxies`;
+/// see `TrustedProxies::resolve`.
+pub struct RealIp(pub IpAddr);
+
+impl FromRequestParts for RealIp {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        let state = app_state(req)?;
+        Ok(RealIp(
+            state
+                .config
+                .trusted_proxies
+                .resolve(client_ip::peer_ip(req), req.headers()),
+        ))
+    }
+}
+
+impl OperationModifier for RealIp {
+    fn update_operation(_op: &mut Operation) {}
+}
+
+/// The request's `User-Agent`, cut to `MAX_USER_AGENT_LENGTH` characters;
+/// `None` when it is missing, empty or not text
+pub struct UserAgent(pub Option<String>);
+
+impl FromRequestParts for UserAgent {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        Ok(UserAgent(
+            req.headers()
+                .get(http::header::USER_AGENT)
+                .and_then(|v| v.to_str().ok())
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(|v| v.chars().take(MAX_USER_AGENT_LENGTH).collect()),
+        ))
+    }
+}
+
+impl OperationModifier for UserAgent {
+    fn update_operation(_op: &mut Operation) {}
+}
+
+/// The logged-in user, or `None` for anonymous visitors, for pages that
+/// serve both
+///
+/// Resolved at most once per request: the result is kept in the request
+/// extensions, where `RequireAuth` finds it as well.
+#[derive(Debug, Clone)]
+pub struct CurrentUser(pub Option<UserInfo>);
+
+impl FromRequest for CurrentUser {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        if let Some(current) = req.extensions().get::<CurrentUser>() {
+            return Ok(current.clone());
+        }
+
+        let state = app_state(req)?;
+        let user = get_current_user(&state, &AccessToken::from_request_parts(req)?).await;
+        let current = CurrentUser(user);
+        req.extensions_mut().insert(current.clone());
+        Ok(current)
+    }
+}
+
+impl OperationModifier for CurrentUser {
+    fn update_operation(_op: &mut Operation) {}
+}
+
+/// The user a JSON API request acts for, or `None` when it is not
+/// authenticated
+///
+/// Services authenticate with an `X-API-Key` header. When one is sent it is
+/// the only credential considered, so a revoked key is rejected even next to
+/// a valid session cookie; other requests use the JWT like `CurrentUser`.
+pub struct ApiUser(pub Option<UserInfo>);
+
+impl FromRequest for ApiUser {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        let Some(key) = api_key(req) else {
+            return Ok(ApiUser(CurrentUser::from_request(req).await?.0));
+        };
+        let state = app_state(req)?;
+        Ok(ApiUser(user_from_api_key(&state, &key).await))
+    }
+}
+
+impl OperationModifier for ApiUser {
+    fn update_operation(op: &mut Operation) {
+        openapi::require_api_auth(op);
+    }
+}
+
+/// Extractor for handlers that require a logged-in user
+///
+/// Takes the JWT from an `Authorization: Bearer` header when one is sent,
+/// otherwise from the cookie. Rejects with 401 when it is missing, invalid, or
+/// expired; `LoginRedirectLayer` turns that into a redirect to `/login` for
+/// HTML routes.
+pub struct RequireAuth(pub UserInfo);
+
+impl FromRequest for RequireAuth {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        CurrentUser::from_request(req)
+            .await?
+            .0
+            .map(RequireAuth)
+            .ok_or_else(|| ApiError::unauthorized("Login required"))
+    }
+}
+
+impl OperationModifier for RequireAuth {
+    fn update_operation(op: &mut Operation) {
+        openapi::require_auth(op);
+    }
+}
+
+/// Like `RequireAuth`, but with the user's full `User` record for handlers
+/// that need more than `UserInfo` carries, such as the password hash
+///
+/// The JWT is validated and rejected the same way. Later `CurrentUser` and
+/// `RequireAuth` extractors in the request reuse the record instead of
+/// loading the user again.
+pub struct RequireAccount(pub User);
+
+impl FromRequest for RequireAccount {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        let state = app_state(req)?;
+        let token = AccessToken::from_request_parts(req)?;
+        let Some(user) = account_from_token(&state, token.0.as_deref().unwrap_or_default()).await
+        else {
+            return Err(ApiError::unauthorized("Login required"));
+        };
+
+        req.extensions_mut()
+            .insert(CurrentUser(Some(UserInfo::from(user.clone()))));
+        Ok(RequireAccount(user))
+    }
+}
+
+impl OperationModifier for RequireAccount {
+    fn update_operation(op: &mut Operation) {
+        openapi::require_auth(op);
+    }
+}
+
+/// Sudo mode: whether the current login confirmed its password within the
+/// last `Config::sudo_ttl_secs`
+///
+/// Sensitive handlers start with `check`, which sends users whose
+/// confirmation is missing or has expired to the password prompt first.
+/// Only cookie sessions can be elevated; bearer tokens never are.
+pub struct RequireSudo(pub bool);
+
+impl RequireSudo {
+    /// The redirect to the prompt unless the login is elevated; the prompt
+    /// comes back to `next`, a plain path, afterwards
+    pub fn check(&self, state: &AppState, next: &str) -> Option<Response> {
+        (!self.0).then(|| sudo::prompt(state, next))
+    }
+}
+
+impl FromRequestParts for RequireSudo {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        let state = app_state(req)?;
+        let cookies = Cookies::from_request_parts(req)?;
+        let claims = cookies
+            .get(tokens::ACCESS_COOKIE)
+            .and_then(|token| state.jwt_keys.verify(token.value()));
+        Ok(RequireSudo(claims.is_some_and(|claims| {
+            sudo::is_elevated(&state.config.jwt_secret, &cookies, &claims)
+        })))
+    }
+}
+
+impl OperationModifier for RequireSudo {
+    fn update_operation(_op: &mut Operation) {}
+}
+
+/// Role a `RequireRole` guard demands, as a type so it can be named in handler signatures
+pub trait RoleRequirement {
+    const ROLE: Role;
+}
+
+/// Marker for `RequireRole<Admin>`
+pub struct Admin;
+
+impl RoleRequirement for Admin {
+    const ROLE: Role = Role::Admin;
+}
+
+/// Extractor for handlers restricted to a role, e.g. `RequireRole<Admin>`
+///
+/// Anonymous requests are rejected with 401 like `RequireAuth` (and so
+/// redirected to `/login`); logged-in users without the role get 403.
+pub struct RequireRole<R: RoleRequirement>(pub UserInfo, pub PhantomData<R>);
+
+impl<R: RoleRequirement> FromRequest for RequireRole<R> {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        let RequireAuth(user) = RequireAuth::from_request(req).await?;
+
+        if !user.role.allows(R::ROLE) {
+            return Err(ApiError::forbidden("Insufficient permissions"));
+        }
+
+        Ok(RequireRole(user, PhantomData))
+    }
+}
+
+impl<R: RoleRequirement> OperationModifier for RequireRole<R> {
+    fn update_operation(op: &mut Operation) {
+        openapi::require_auth(op);
+    }
+}
+
+/// CSRF token for the current session, to embed in rendered forms
+///
+/// `CsrfLayer` issues the token (and its cookie) when the request has none.
+#[derive(Debug, Clone)]
+pub struct CsrfToken(pub String);
+
+impl FromRequestParts for CsrfToken {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        if let Some(token) = req.extensions().get::<CsrfToken>() {
+            return Ok(token.clone());
+        }
+
+        let state = app_state(req)?;
+        let cookies = Cookies::from_request_parts(req)?;
+
+        csrf::token_from_cookies(&state.config.jwt_secret, &cookies)
+            .map(CsrfToken)
+            .ok_or_else(|| ApiError::internal("CSRF token missing; is CsrfLayer installed?"))
+    }
+}
+
+impl OperationModifier for CsrfToken {
+    fn update_operation(_op: &mut Operation) {}
+}
+
+/// One-time message left by the redirect that led here, for the page to
+/// show; `FlashLayer` clears the cookie once the page is rendered
+pub struct FlashMessage(pub Option<Flash>);
+
+impl FromRequestParts for FlashMessage {
+    fn from_request_parts(req: &Request) -> Result<Self> {
+        let state = app_state(req)?;
+        let cookies = Cookies::from_request_parts(req)?;
+
+        Ok(FlashMessage(Flash::from_cookies(
+            &state.config.jwt_secret,
+            &cookies,
+        )))
+    }
+}
+
+impl OperationModifier for FlashMessage {
+    fn update_operation(_op: &mut Operation) {}
+}
+
+/// Guard for state-changing form submissions
+///
+/// Checks the `_csrf` form field against the signed cookie and rejects with
+/// 403 before the handler runs. Reads a copy of the body, so it must come
+/// before `Form<T>` or `MultipartForm`. Holds the verified token for
+/// re-rendering the form.
+pub struct CsrfProtected(pub String);
+
+impl FromRequest for CsrfProtected {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        let state = app_state(req)?;
+        let cookies = Cookies::from_request_parts(req)?;
+        let expected = csrf::token_from_cookies(&state.config.jwt_secret, &cookies);
+
+        // Parsed here before the form extractor sees it, so the same body
+        // limit applies
+        let content_type = content_type(req);
+        load_body_within(req, body_limit(&state, req, &content_type)).await?;
+        let body = req
+            .try_clone()
+            .and_then(|mut copy| copy.take_body())
+            .unwrap_or_default();
+        let submitted = if content_type.starts_with("multipart/form-data") {
+            // The upload size is enforced later by `MultipartForm`, so an
+            // oversize file still gets a 413 rather than a CSRF failure
+            MultipartForm::parse(&content_type, body, None)
+                .await
+                .ok()
+                .and_then(|form| form.text(CSRF_FIELD).map(str::to_string))
+        } else {
+            form_field(&body, CSRF_FIELD)
+        };
+
+        match (expected, submitted) {
+            (Some(expected), Some(submitted)) if csrf::tokens_match(&expected, &submitted) => {
+                Ok(CsrfProtected(expected))
+            }
+            _ => Err(ApiError::forbidden("Invalid CSRF token")),
+        }
+    }
+}
+
+impl OperationModifier for CsrfProtected {
+    fn update_operation(_op: &mut Operation) {}
+}
+
+// Helper function to read the request's `Content-Type`, empty when missing
+/// Key identifying one logical create request, so that sending it again
+/// returns the first result instead of creating a duplicate
+///
+/// API clients send an `Idempotency-Key` header; the new item form carries an
+/// `idempotency_key` field instead, which is read without consuming the body.
+/// `None` when neither is sent; keys over `MAX_IDEMPOTENCY_KEY_LENGTH` are
+/// rejected with 400.
+pub struct IdempotencyKey(pub Option<String>);
+
+impl FromRequest for IdempotencyKey {
+    async fn from_request(req: &mut Request) -> Result<Self> {
+        let header = req
+            .headers()
+            .get(IDEMPOTENCY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let key = match header {
+            Some(key) => Some(key),
+            None if content_type(req).starts_with("application/x-www-form-urlencoded") => {
+                let limit = RouteLimits::of(&app_state(req)?, req).max_body_bytes;
+                load_body_within(req, limit).await?;
+                let body = req
+                    .try_clone()
+                    .and_then(|mut copy| copy.take_body())
+                    .unwrap_or_default();
+                form_field(&body, IDEMPOTENCY_FIELD)
+            }
+            None => None,
+        };
+
+        let key = key
+            .map(|key| key.trim().to_string())
+            .filter(|key| !key.is_empty());
+        if key
+            .as_ref()
+            .is_some_and(|key| key.len() > MAX_IDEMPOTENCY_KEY_LENGTH)
+        {
+            return Err(ApiError::bad_request(format!(
+                "Idempotency keys are limited to {} characters",
+                MAX_IDEMPOTENCY_KEY_LENGTH
+            )));
+        }
+        Ok(IdempotencyKey(key))
+    }
+}
+
+impl OperationModifier for IdempotencyKey {
+    fn update_operation(op: &mut Operation) {
+        op.parameters.push(Parameter {
+            name: IDEMPOTENCY_HEADER.to_string(),
+            location: "header".to_string(),
+            description: Some(
+                "Repeating a request with the same key returns the item it created".to_string(),
+            ),
+            required: false,
+            deprecated: None,
+            schema: None,
+        });
+    }
+}
+
+// Value of one field of a URL-encoded body
+fn form_field(body: &[u8], name: &str) -> Option<String> {
+    serde_urlencoded::from_bytes::<Vec<(String, String)>>(body)
+        .ok()?
+        .into_iter()
+        .find(|(field, _)| field == name)
+        .map(|(_, value)| value)
+}
+
+fn content_type(req: &Request) -> String {
+    req.headers()
+        .get("Content-Type")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string()
+}
+
+// Body limit of the extractor that will parse a body of this type
+fn body_limit(state: &AppState, req: &Request, content_type: &str) -> usize {
+    if content_type.starts_with("multipart/form-data") {
+        state.config.uploads.max_body_bytes()
+    } else {
+        RouteLimits::of(state, req).max_body_bytes
+    }
+}
+
+// Buffer the body, answering 413 once it is known to exceed `limit`. A
+// declared `Content-Length` is checked before anything is read; a chunked
+// body is still capped by the server-wide limit set in `main`
+async fn load_body_within(req: &mut Request, limit: usize) -> Result<()> {
+    let declared = req
+        .headers()
+        .get("Content-Length")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if declared.is_some_and(|len| len > limit) {
+        return Err(body_too_large(limit));
+    }
+
+    req.load_body().await?;
+    let buffered = req
+        .try_clone()
+        .and_then(|mut copy| copy.take_body())
+        .map_or(0, |body| body.len());
+    if buffered > limit {
+        return Err(body_too_large(limit));
+    }
+    Ok(())
+}
+
+// Like `load_body_within`, then take the body out of the request
+async fn take_body_within(req: &mut Request, limit: usize) -> Result<Bytes> {
+    load_body_within(req, limit).await?;
+    req.take_body()
+        .ok_or_else(|| ApiError::internal("Body already consumed"))
+}
+
+fn body_too_large(limit: usize) -> ApiError {
+    ApiError::new(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        "payload_too_large",
+        format!("Request bodies are limited to {} bytes", limit),
+    )
+}
+
+// Helper function to fetch the shared state registered with `.state()`
+fn app_state(req: &Request) -> Result<AppState> {
+    req.state()
+        .get::<AppState>()
+        .cloned()
+        .ok_or_else(|| ApiError::internal("AppState not configured"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client_ip::TrustedProxies;
+    use crate::models::ItemForm;
+    use crate::test_utils::{
+        cleanup_db, multipart_request, request_with_state, setup_test_state, token_for_user,
+        TEST_MAX_UPLOAD_BYTES,
+    };
+    use rustapi_rs::StatusCode;
+    use std::sync::Arc;
+
+    #[derive(Debug, serde::Deserialize)]
+    struct SearchQuery {
+        q: String,
+        page: Option<i64>,
+    }
+
+    #[tokio::test]
+    async fn query_reads_required_and_optional_params() {
+        let (state, path) = setup_test_state().await;
+        let query = |uri: &str| {
+            let req = request_with_state(&state, http::Request::get(uri), "");
+            Query::<SearchQuery>::from_request_parts(&req)
+        };
+
+        let Query(search) = query("/search?q=milk+tea&page=2").expect("valid query");
+        assert_eq!(search.q, "milk tea");
+        assert_eq!(search.page, Some(2));
+
+        let Query(search) = query("/search?q=eggs").expect("valid query");
+        assert_eq!(search.page, None);
+
+        let missing = query("/search?page=1").err().expect("missing q");
+        assert_eq!(missing.status, StatusCode::BAD_REQUEST);
+        assert!(missing.message.contains("missing field `q`"));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn query_rejects_values_of_the_wrong_type() {
+        let (state, path) = setup_test_state().await;
+        let req = request_with_state(&state, http::Request::get("/search?q=x&page=two"), "");
+
+        let rejection = Query::<SearchQuery>::from_request_parts(&req)
+            .err()
+            .expect("bad page");
+        assert_eq!(rejection.status, StatusCode::BAD_REQUEST);
+        assert!(rejection.message.starts_with("Invalid query parameters:"));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn validated_form_separates_valid_and_invalid_input() {
+        let (state, path) = setup_test_state().await;
+        let extract = |body: &'static str| {
+            let mut req = request_with_state(&state, http::Request::post("/items"), body);
+            async move { ValidatedForm::<ItemForm>::from_request(&mut req).await }
+        };
+
+        let ValidatedForm(valid) = extract("title=Groceries&tags=errands")
+            .await
+            .expect("form parses");
+        assert_eq!(valid.expect("valid form").title, "Groceries");
+
+        let ValidatedForm(invalid) = extract("title=").await.expect("form parses");
+        let errors = invalid.expect_err("validation errors");
+        assert_eq!(errors["title"], ["Title is required"]);
+
+        let rejection = extract("tags=missing-title").await.err();
+        assert_eq!(rejection.map(|e| e.status), Some(StatusCode::BAD_REQUEST));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_are_rejected_before_parsing() {
+        let (mut state, path) = setup_test_state().await;
+        Arc::make_mut(&mut state.config).max_form_bytes = 16;
+        let title = "title=Groceries";
+
+        let mut req = request_with_state(&state, http::Request::post("/items"), title);
+        let Form(form) = Form::<ItemForm>::from_request(&mut req)
+            .await
+            .expect("body within the limit");
+        assert_eq!(form.title, "Groceries");
+
+        let mut req =
+            request_with_state(&state, http::Request::post("/items"), "title=Groceries!!");
+        let rejection = Form::<ItemForm>::from_request(&mut req).await.err();
+        assert_eq!(
+            rejection.map(|e| e.status),
+            Some(StatusCode::PAYLOAD_TOO_LARGE)
+        );
+
+        // A declared length is enough; the body is never read
+        let mut req = request_with_state(
+            &state,
+            http::Request::post("/items").header("Content-Length", "4096"),
+            title,
+        );
+        let rejection = Form::<ItemForm>::from_request(&mut req).await.err();
+        assert_eq!(
+            rejection.map(|e| e.status),
+            Some(StatusCode::PAYLOAD_TOO_LARGE)
+        );
+
+        // `CsrfProtected` reads the body first and applies the same limit
+        let mut req =
+            request_with_state(&state, http::Request::post("/items"), "title=Groceries!!");
+        let rejection = CsrfProtected::from_request(&mut req).await.err();
+        assert_eq!(
+            rejection.map(|e| e.status),
+            Some(StatusCode::PAYLOAD_TOO_LARGE)
+        );
+
+        let mut req = request_with_state(
+            &state,
+            http::Request::post("/api/items"),
+            r#"{"title":"Groceries"}"#,
+        );
+        let rejection = JsonBody::<serde_json::Value>::from_request(&mut req)
+            .await
+            .err();
+        assert_eq!(
+            rejection.map(|e| e.status),
+            Some(StatusCode::PAYLOAD_TOO_LARGE)
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn routes_can_override_the_body_limit() {
+        use std::collections::HashMap;
+
+        let (state, path) = setup_test_state().await;
+        let body = format!("username=ann&password={}", "x".repeat(32 * 1024));
+        assert!(body.len() < state.config.max_form_bytes);
+
+        let mut req = request_with_state(&state, http::Request::post("/login"), &body);
+        let rejection = Form::<HashMap<String, String>>::from_request(&mut req)
+            .await
+            .err();
+        assert_eq!(
+            rejection.map(|e| e.status),
+            Some(StatusCode::PAYLOAD_TOO_LARGE)
+        );
+
+        // Other routes keep the configured limit
+        let mut req = request_with_state(&state, http::Request::post("/items"), &body);
+        assert!(Form::<HashMap<String, String>>::from_request(&mut req)
+            .await
+            .is_ok());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn require_auth_accepts_valid_token() {
+        let (state, path) = setup_test_state().await;
+        let user = state
+            .db
+            .create_user("authed", "authed@example.com", "hash")
+            .await
+            .expect("create user");
+        let token = token_for_user(&state.config.jwt_secret, user.id, &user.username, 3600);
+
+        let mut req = request_with_state(
+            &state,
+            http::Request::get("/items").header("Cookie", format!("token={}", token)),
+            "",
+        );
+        let RequireAuth(info) = RequireAuth::from_request(&mut req)
+            .await
+            .expect("authenticated");
+        assert_eq!(info.id, user.id);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn require_auth_rejects_missing_token() {
+        let (state, path) = setup_test_state().await;
+        let mut req = request_with_state(&state, http::Request::get("/items"), "");
+
+        let err = RequireAuth::from_request(&mut req)
+            .await
+            .err()
+            .expect("rejected");
+        assert_eq!(err.status, StatusCode::UNAUTHORIZED);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn require_auth_rejects_expired_token() {
+        let (state, path) = setup_test_state().await;
+        let user = state
+            .db
+            .create_user("expired", "expired@example.com", "hash")
+            .await
+            .expect("create user");
+        // Past the default 60s validation leeway
+        let token = token_for_user(&state.config.jwt_secret, user.id, &user.username, -3600);
+
+        let mut req = request_with_state(
+            &state,
+            http::Request::get("/items").header("Cookie", format!("token={}", token)),
+            "",
+        );
+        let err = RequireAuth::from_request(&mut req)
+            .await
+            .err()
+            .expect("rejected");
+        assert_eq!(err.status, StatusCode::UNAUTHORIZED);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn current_user_is_resolved_once_per_request() {
+        let (state, path) = setup_test_state().await;
+        let user = state
+            .db
+            .create_user("cached", "cached@example.com", "hash")
+            .await
+            .expect("create user");
+        let token = token_for_user(&state.config.jwt_secret, user.id, &user.username, 3600);
+        let request = || {
+            request_with_state(
+                &state,
+                http::Request::get("/items").header("Cookie", format!("token={}", token)),
+                "",
+            )
+        };
+
+        let mut req = request();
+        let CurrentUser(current) = CurrentUser::from_request(&mut req).await.expect("extract");
+        assert_eq!(current.map(|u| u.id), Some(user.id));
+        state.db.delete_user(user.id).await.expect("delete user");
+
+        // The same request reuses the user it already looked up
+        assert!(RequireAuth::from_request(&mut req).await.is_ok());
+        let err = RequireAuth::from_request(&mut request())
+            .await
+            .err()
+            .expect("rejected");
+        assert_eq!(err.status, StatusCode::UNAUTHORIZED);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn require_account_loads_the_full_user() {
+        let (state, path) = setup_test_state().await;
+        let user = state
+            .db
+            .create_user("full", "full@example.com", "stored-hash")
+            .await
+            .expect("create user");
+        let request = |token: &str| {
+            request_with_state(
+                &state,
+                http::Request::get("/account").header("Cookie", format!("token={}", token)),
+                "",
+            )
+        };
+
+        let token = token_for_user(&state.config.jwt_secret, user.id, &user.username, 3600);
+        let mut req = request(&token);
+        let RequireAccount(account) = RequireAccount::from_request(&mut req)
+            .await
+            .expect("extract");
+        assert_eq!(account.id, user.id);
+        assert_eq!(account.password_hash, "stored-hash");
+        assert_eq!(account.created_at, user.created_at);
+
+        // The rest of the request sees the same user without another lookup
+        state.db.delete_user(user.id).await.expect("delete user");
+        let RequireAuth(info) = RequireAuth::from_request(&mut req).await.expect("extract");
+        assert_eq!(info.id, user.id);
+
+        for token in ["not-a-jwt", "", &token] {
+            let err = RequireAccount::from_request(&mut request(token))
+                .await
+                .err()
+                .expect("rejected");
+            assert_eq!(err.status, StatusCode::UNAUTHORIZED);
+        }
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_user_authenticates_with_an_api_key() {
+        let (state, path) = setup_test_state().await;
+        let user = state
+            .db
+            .create_user("service", "service@example.com", "hash")
+            .await
+            .expect("create user");
+        let key = crate::tokens::generate_token();
+        let stored = state
+            .db
+            .create_api_key(user.id, &crate::tokens::hash_token(&key), "CI")
+            .await
+            .expect("create key");
+        let request = |key: &str| {
+            request_with_state(
+                &state,
+                http::Request::get("/api/items").header("X-API-Key", key),
+                "",
+            )
+        };
+
+        let ApiUser(found) = ApiUser::from_request(&mut request(&key))
+            .await
+            .expect("extract");
+        assert_eq!(found.map(|u| u.id), Some(user.id));
+        let used = state
+            .db
+            .find_api_key(&stored.key_hash)
+            .await
+            .expect("find key")
+            .expect("key exists");
+        assert!(used.last_used_at.is_some());
+
+        let ApiUser(found) = ApiUser::from_request(&mut request("wrong-key"))
+            .await
+            .expect("extract");
+        assert!(found.is_none());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_user_rejects_a_revoked_key_despite_a_session() {
+        let (state, path) = setup_test_state().await;
+        let user = state
+            .db
+            .create_user("revoked", "revoked@example.com", "hash")
+            .await
+            .expect("create user");
+        let stored = state
+            .db
+            .create_api_key(user.id, &crate::tokens::hash_token("old-key"), "CI")
+            .await
+            .expect("create key");
+        assert!(state
+            .db
+            .delete_api_key(stored.id, user.id)
+            .await
+            .expect("revoke key"));
+        let token = token_for_user(&state.config.jwt_secret, user.id, &user.username, 3600);
+
+        let mut req = request_with_state(
+            &state,
+            http::Request::get("/api/items")
+                .header("X-API-Key", "old-key")
+                .header("Cookie", format!("token={}", token)),
+            "",
+        );
+        let ApiUser(found) = ApiUser::from_request(&mut req).await.expect("extract");
+        assert!(found.is_none());
+
+        // Without a key the session still works
+        let mut req = request_with_state(
+            &state,
+            http::Request::get("/api/items").header("Cookie", format!("token={}", token)),
+            "",
+        );
+        let ApiUser(found) = ApiUser::from_request(&mut req).await.expect("extract");
+        assert_eq!(found.map(|u| u.id), Some(user.id));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn idempotency_key_comes_from_the_header_or_the_form() {
+        let (state, path) = setup_test_state().await;
+        let key = |builder| async {
+            let mut req = request_with_state(&state, builder, "title=x&idempotency_key=+form-key+");
+            let key = IdempotencyKey::from_request(&mut req).await.map(|k| k.0);
+            // The form itself still parses afterwards
+            let form = Form::<ItemForm>::from_request(&mut req).await;
+            (key, form.is_ok())
+        };
+        let post = || {
+            http::Request::post("/items")
+                .header("Content-Type", "application/x-www-form-urlencoded")
+        };
+
+        let (found, parsed) = key(post()).await;
+        assert_eq!(found.expect("key"), Some("form-key".to_string()));
+        assert!(parsed);
+        let (found, _) = key(post().header("Idempotency-Key", "header-key")).await;
+        assert_eq!(found.expect("key"), Some("header-key".to_string()));
+        let (found, _) = key(post().header("Idempotency-Key", "x".repeat(256))).await;
+        assert_eq!(found.expect_err("rejected").status, StatusCode::BAD_REQUEST);
+
+        let mut req = request_with_state(&state, http::Request::post("/items"), "");
+        let IdempotencyKey(found) = IdempotencyKey::from_request(&mut req)
+            .await
+            .expect("extract");
+        assert!(found.is_none());
+        cleanup_db(path);
+    }
+
+    fn authed_request(state: &AppState, user_id: i64, username: &str) -> Request {
+        let token = token_for_user(&state.config.jwt_secret, user_id, username, 3600);
+        request_with_state(
+            state,
+            http::Request::get("/admin/users").header("Cookie", format!("token={}", token)),
+            "",
+        )
+    }
+
+    #[tokio::test]
+    async fn require_role_accepts_admin_and_rejects_user() {
+        let (state, path) = setup_test_state().await;
+        // The first account is seeded as admin
+        let admin = state
+            .db
+            .create_user("boss", "boss@example.com", "hash")
+            .await
+            .expect("create user");
+        let user = state
+            .db
+            .create_user("staff", "staff@example.com", "hash")
+            .await
+            .expect("create user");
+
+        let mut req = authed_request(&state, admin.id, &admin.username);
+        let RequireRole(info, _) = RequireRole::<Admin>::from_request(&mut req)
+            .await
+            .expect("admin allowed");
+        assert_eq!(info.role, Role::Admin);
+
+        let mut req = authed_request(&state, user.id, &user.username);
+        let err = RequireRole::<Admin>::from_request(&mut req)
+            .await
+            .err()
+            .expect("user rejected");
+        assert_eq!(err.status, StatusCode::FORBIDDEN);
+
+        let mut req = request_with_state(&state, http::Request::get("/admin/users"), "");
+        let err = RequireRole::<Admin>::from_request(&mut req)
+            .await
+            .err()
+            .expect("anonymous rejected");
+        assert_eq!(err.status, StatusCode::UNAUTHORIZED);
+        cleanup_db(path);
+    }
+
+    fn csrf_request(state: &AppState, cookie_token: &str, body: &str) -> Request {
+        request_with_state(
+            state,
+            http::Request::post("/items").header(
+                "Cookie",
+                format!(
+                    "{}={}",
+                    csrf::CSRF_COOKIE,
+                    csrf::sign_token(&state.config.jwt_secret, cookie_token)
+                ),
+            ),
+            body,
+        )
+    }
+
+    #[tokio::test]
+    async fn csrf_protected_accepts_matching_token() {
+        let (state, path) = setup_test_state().await;
+        let mut req = csrf_request(&state, "good-token", "title=Hi&_csrf=good-token");
+
+        let CsrfProtected(token) = CsrfProtected::from_request(&mut req)
+            .await
+            .expect("csrf accepted");
+        assert_eq!(token, "good-token");
+
+        // The form body is still available to the next extractor
+        let Form(fields) = Form::<Vec<(String, String)>>::from_request(&mut req)
+            .await
+            .expect("form still readable");
+        assert!(fields.contains(&("title".to_string(), "Hi".to_string())));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn csrf_protected_rejects_missing_token() {
+        let (state, path) = setup_test_state().await;
+        let mut req = csrf_request(&state, "good-token", "title=Hi");
+
+        let err = CsrfProtected::from_request(&mut req)
+            .await
+            .err()
+            .expect("rejected");
+        assert_eq!(err.status, StatusCode::FORBIDDEN);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn csrf_protected_rejects_forged_token() {
+        let (state, path) = setup_test_state().await;
+        let mut req = csrf_request(&state, "good-token", "title=Hi&_csrf=forged-token");
+        let err = CsrfProtected::from_request(&mut req)
+            .await
+            .err()
+            .expect("rejected");
+        assert_eq!(err.status, StatusCode::FORBIDDEN);
+
+        // A cookie signed with another key is not trusted either
+        let mut req = request_with_state(
+            &state,
+            http::Request::post("/items").header(
+                "Cookie",
+                format!(
+                    "{}={}",
+                    csrf::CSRF_COOKIE,
+                    csrf::sign_token("attacker-key", "forged-token")
+                ),
+            ),
+            "_csrf=forged-token",
+        );
+        let err = CsrfProtected::from_request(&mut req)
+            .await
+            .err()
+            .expect("rejected");
+        assert_eq!(err.status, StatusCode::FORBIDDEN);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn multipart_form_exposes_fields_and_file() {
+        let (state, path) = setup_test_state().await;
+        // Not valid UTF-8, so the bytes must come through untouched
+        let png = [0x89, b'P', b'N', b'G', 0xff, 0x00, b'\r', b'\n'];
+        let mut req = multipart_request(
+            &state,
+            http::Request::post("/items/1/attachments"),
+            &[("note", "hello")],
+            ("file", "pixel.png", "image/png", &png),
+        );
+
+        let form = MultipartForm::from_request(&mut req)
+            .await
+            .expect("multipart parsed");
+        assert_eq!(form.text("note"), Some("hello"));
+        let file = form.file("file").expect("file part");
+        assert_eq!(file.filename, "pixel.png");
+        assert_eq!(file.content_type, "image/png");
+        assert_eq!(file.bytes.as_ref(), &png);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn multipart_form_rejects_oversize_file() {
+        let (state, path) = setup_test_state().await;
+        let big = vec![b'a'; TEST_MAX_UPLOAD_BYTES + 1];
+        let mut req = multipart_request(
+            &state,
+            http::Request::post("/items/1/attachments"),
+            &[],
+            ("file", "big.txt", "text/plain", &big),
+        );
+
+        let err = MultipartForm::from_request(&mut req)
+            .await
+            .expect_err("rejected");
+        assert_eq!(err.status, StatusCode::PAYLOAD_TOO_LARGE);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn csrf_protected_reads_multipart_token() {
+        let (state, path) = setup_test_state().await;
+        let cookie = format!(
+            "{}={}",
+            csrf::CSRF_COOKIE,
+            csrf::sign_token(&state.config.jwt_secret, "good-token")
+        );
+        let big = vec![b'a'; TEST_MAX_UPLOAD_BYTES + 1];
+
+        let mut req = multipart_request(
+            &state,
+            http::Request::post("/items/1/attachments").header("Cookie", &cookie),
+            &[(CSRF_FIELD, "good-token")],
+            ("file", "big.txt", "text/plain", &big),
+        );
+        CsrfProtected::from_request(&mut req)
+            .await
+            .expect("csrf accepted");
+        // The size limit is left to `MultipartForm`
+        let err = MultipartForm::from_request(&mut req)
+            .await
+            .expect_err("rejected");
+        assert_eq!(err.status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        let mut req = multipart_request(
+            &state,
+            http::Request::post("/items/1/attachments").header("Cookie", &cookie),
+            &[(CSRF_FIELD, "forged-token")],
+            ("file", "notes.txt", "text/plain", b"hi"),
+        );
+        let err = CsrfProtected::from_request(&mut req)
+            .await
+            .err()
+            .expect("rejected");
+        assert_eq!(err.status, StatusCode::FORBIDDEN);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn real_ip_only_believes_trusted_proxies() {
+        let (mut state, path) = setup_test_state().await;
+        let from = |state: &AppState, peer: &str| {
+            let peer: std::net::SocketAddr = peer.parse().expect("socket address");
+            let req = request_with_state(
+                state,
+                http::Request::get("/")
+                    .header(client_ip::FORWARDED_FOR_HEADER, "203.0.113.9")
+                    .extension(peer),
+                "",
+            );
+            RealIp::from_request_parts(&req).expect("real ip").0
+        };
+
+        // No proxy is trusted by default, so the header is ignored
+        assert_eq!(from(&state, "10.0.0.2:5000").to_string(), "10.0.0.2");
+
+        Arc::make_mut(&mut state.config).trusted_proxies = TrustedProxies::parse("10.0.0.0/8");
+        assert_eq!(from(&state, "10.0.0.2:5000").to_string(), "203.0.113.9");
+        assert_eq!(
+            from(&state, "198.51.100.20:5000").to_string(),
+            "198.51.100.20"
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn user_agent_is_trimmed_and_capped() {
+        let (state, path) = setup_test_state().await;
+        let agent = |value: Option<&str>| {
+            let mut builder = http::Request::get("/");
+            if let Some(value) = value {
+                builder = builder.header("User-Agent", value);
+            }
+            let req = request_with_state(&state, builder, "");
+            UserAgent::from_request_parts(&req).expect("user agent").0
+        };
+
+        assert_eq!(agent(Some(" curl/8.5 ")).as_deref(), Some("curl/8.5"));
+        assert_eq!(agent(Some(" ")), None);
+        assert_eq!(agent(None), None);
+        let long = "x".repeat(MAX_USER_AGENT_LENGTH + 10);
+        assert_eq!(
+            agent(Some(&long)).map(|v| v.len()),
+            Some(MAX_USER_AGENT_LENGTH)
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn require_sudo_reads_the_elevation_of_the_cookie_session() {
+        let (state, path) = setup_test_state().await;
+        let elevated = |cookie: &str| {
+            let req =
+                request_with_state(&state, http::Request::get("/").header("Cookie", cookie), "");
+            RequireSudo::from_request_parts(&req).expect("sudo").0
+        };
+        let token = token_for_user(&state.config.jwt_secret, 1, "alice", 600);
+        let claims = state.jwt_keys.verify(&token).expect("claims");
+        let value = |ttl_secs| {
+            let cookie = sudo::cookie(
+                &state.config.cookies,
+                &state.config.jwt_secret,
+                &claims,
+                ttl_secs,
+            );
+            cookie.split(';').next().expect("pair").to_string()
+        };
+
+        assert!(elevated(&format!("token={}; {}", token, value(60))));
+        assert!(!elevated(&format!("token={}; {}", token, value(-1))));
+        assert!(!elevated(&format!("token={}", token)));
+        assert!(!elevated(&value(60)));
+        assert!(RequireSudo(false).check(&state, "/profile").is_some());
+        assert!(RequireSudo(true).check(&state, "/profile").is_none());
+        cleanup_db(path);
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt::Write;
+
+use crate::models::Item;
+
+/// Newest items a feed lists
+pub const FEED_ITEMS: i64 = 50;
+
+/// Address the site is reached at when `PUBLIC_URL` is unset; feed links
+/// are built from it
+pub const DEFAULT_PUBLIC_URL: &str = "http://localhost:8080";
+
+/// `Content-Type` of the feed
+pub const CONTENT_TYPE: &str = "application/rss+xml; charset=utf-8";
+
+/// `text` escaped for XML text and attribute values
+///
+/// Control characters XML 1.0 does not allow, even escaped, are dropped.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() && c < '\u{80}' => {}
+            '\u{FFFE}' | '\u{FFFF}' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// RSS 2.0 document listing `items`, newest first, for `username`
+///
+/// Links point at the item pages under `public_url`; dates are RFC 822, as
+/// RSS wants.
+pub fn rss(public_url: &str, username: &str, items: &[Item]) -> String {
+    let base = public_url.trim_end_matches('/');
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<rss version=\"2.0\">\n<channel>\n");
+    let _ = writeln!(xml, "<title>{}&apos;s items</title>", escape(username));
+    let _ = writeln!(xml, "<link>{}/items</link>", escape(base));
+    let _ = writeln!(
+        xml,
+        "<description>Recent items of {}</description>",
+        escape(username)
+    );
+    if let Some(updated) = items.iter().map(|item| item.updated_at).max() {
+        let _ = writeln!(
+            xml,
+            "<lastBuildDate>{}</lastBuildDate>",
+            updated.to_rfc2822()
+        );
+    }
+
+    for item in items {
+        let link = escape(&format!("{}/items/{}", base, item.id));
+        xml.push_str("<item>\n");
+        let _ = writeln!(xml, "<title>{}</title>", escape(&item.title));
+        let _ = writeln!(xml, "<link>{}</link>", link);
+        let _ = writeln!(xml, "<guid isPermaLink=\"true\">{}</guid>", link);
+        if let Some(description) = item.description.as_deref().filter(|d| !d.is_empty()) {
+            let _ = writeln!(xml, "<description>{}</description>", escape(description));
+        }
+        for tag in &item.tags {
+            let _ = writeln!(xml, "<category>{}</category>", escape(tag));
+        }
+        let _ = writeln!(xml, "<pubDate>{}</pubDate>", item.created_at.to_rfc2822());
+        xml.push_str("</item>\n");
+    }
+
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::assert_well_formed;
+    use chrono::{TimeZone, Utc};
+
+    fn item(id: i64, title: &str, description: Option<&str>) -> Item {
+        let created_at = Utc.with_ymd_and_hms(2026, 7, 1, 10, 52, 37).unwrap();
+        Item {
+            id,
+            user_id: 1,
+            title: title.to_string(),
+            description: description.map(str::to_string),
+            created_at,
+            updated_at: created_at,
+            deleted_at: None,
+            archived: false,
+            due_at: None,
+            tags: vec!["r&d".to_string()],
+        }
+    }
+
+    #[test]
+    fn escapes_markup_and_drops_invalid_characters() {
+        assert_eq!(
+            escape("<b>Tom & \"Jerry's\"</b>\u{0}\u{1b}\n"),
+            "&lt;b&gt;Tom &amp; &quot;Jerry&apos;s&quot;&lt;/b&gt;\n"
+        );
+    }
+
+    #[test]
+    fn feed_is_well_formed_rss() {
+        let items = vec![
+            item(2, "Fish & <Chips>", Some("</description><script>\u{7}")),
+            item(1, "Plain", None),
+        ];
+        let xml = rss("https://example.com/", "o'hara", &items);
+        assert_well_formed(&xml);
+
+        assert!(xml.contains("<rss version=\"2.0\">"));
+        assert!(xml.contains("<title>o&apos;hara&apos;s items</title>"));
+        assert!(xml.contains("<title>Fish &amp; &lt;Chips&gt;</title>"));
+        assert!(xml.contains("<link>https://example.com/items/2</link>"));
+        assert!(xml.contains("<description>&lt;/description&gt;&lt;script&gt;</description>"));
+        assert!(xml.contains("<category>r&amp;d</category>"));
+        assert!(xml.contains("<pubDate>Wed, 1 Jul 2026 10:52:37 +0000</pubDate>"));
+        assert_eq!(xml.matches("<item>").count(), 2);
+        assert!(xml.find("items/2").unwrap() < xml.find("items/1").unwrap());
+    }
+
+    #[test]
+    fn empty_feed_is_well_formed() {
+        let xml = rss(DEFAULT_PUBLIC_URL, "alice", &[]);
+        assert_well_formed(&xml);
+        assert!(!xml.contains("<item>"));
+        assert!(!xml.contains("lastBuildDate"));
+    }
+}
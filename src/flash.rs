@@ -0,0 +1,125 @@
+use rustapi_rs::prelude::*;
+use serde::{Deserialize, Serialize};
+use tera::Context;
+
+use crate::{cookies::CookieConfig, csrf, responses, AppState};
+
+/// Cookie carrying a one-time message to the page a redirect leads to
+pub const FLASH_COOKIE: &str = "flash";
+
+/// Lifetime of the flash cookie; the page it is meant for normally loads
+/// right after the redirect
+pub const FLASH_MAX_AGE_SECS: i64 = 60;
+
+/// Shown when a database call fails on the way to a redirect
+pub const DATABASE_ERROR: &str = "An error occurred. Please try again.";
+
+/// Shown when the item in the URL is not one of the user's
+pub const ITEM_NOT_FOUND: &str = "Item not found";
+
+/// Which alert a flash is shown in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlashKind {
+    Success,
+    Error,
+}
+
+/// A message for the next page the browser renders
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Flash {
+    pub kind: FlashKind,
+    pub message: String,
+}
+
+impl Flash {
+    pub fn success(message: impl Into<String>) -> Self {
+        Self {
+            kind: FlashKind::Success,
+            message: message.into(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self {
+            kind: FlashKind::Error,
+            message: message.into(),
+        }
+    }
+
+    /// Add the message to a page's context as `success` or `error`, which
+    /// the templates' alert blocks read
+    pub fn insert_into(&self, context: &mut Context) {
+        let key = match self.kind {
+            FlashKind::Success => "success",
+            FlashKind::Error => "error",
+        };
+        context.insert(key, &self.message);
+    }
+
+    /// Signed cookie value; the JSON is hex-encoded so it is cookie-safe
+    pub fn encode(&self, secret: &str) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        csrf::sign_token(secret, &hex::encode(json))
+    }
+
+    /// The flash in a cookie value from `encode`, if the signature checks out
+    pub fn decode(secret: &str, value: &str) -> Option<Self> {
+        let json = hex::decode(csrf::verify_signed(secret, value)?).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+
+    /// Read and verify the flash from the request cookies
+    pub fn from_cookies(secret: &str, cookies: &Cookies) -> Option<Self> {
+        Self::decode(secret, cookies.get(FLASH_COOKIE)?.value())
+    }
+}
+
+/// Redirect to the app's own `location`, showing `flash` on the page it
+/// leads to
+pub fn redirect(state: &AppState, location: &str, flash: Flash) -> Response {
+    let cookie = state.config.cookies.header(
+        FLASH_COOKIE,
+        &flash.encode(&state.config.jwt_secret),
+        Some(FLASH_MAX_AGE_SECS),
+    );
+    responses::redirect(
+        StatusCode::FOUND,
+        &state.config.base_path.url(location),
+        &[("Set-Cookie", &cookie)],
+    )
+}
+
+/// `Set-Cookie` value clearing the flash once it has been shown
+pub fn removal(config: &CookieConfig) -> String {
+    config.removal(FLASH_COOKIE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flash_round_trips_through_the_cookie() {
+        let flash = Flash::success("Item \"created\"; 100% done");
+        let value = flash.encode("secret");
+        assert!(value.chars().all(|c| c.is_ascii_hexdigit() || c == '.'));
+        assert_eq!(Flash::decode("secret", &value), Some(flash));
+
+        assert_eq!(Flash::decode("other-secret", &value), None);
+        let forged = csrf::sign_token("other-secret", &hex::encode(br#"{"kind":"error"}"#));
+        assert_eq!(Flash::decode("secret", &forged), None);
+        assert_eq!(Flash::decode("secret", "garbage"), None);
+    }
+
+    #[test]
+    fn insert_into_fills_the_matching_alert() {
+        let mut context = Context::new();
+        Flash::error(ITEM_NOT_FOUND).insert_into(&mut context);
+        assert_eq!(
+            context.get("error").and_then(|v| v.as_str()),
+            Some(ITEM_NOT_FOUND)
+        );
+        assert!(context.get("success").is_none());
+    }
+}
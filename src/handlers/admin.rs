@@ -0,0 +1,607 @@
+use rustapi_rs::prelude::*;
+use tera::Context;
+
+use crate::{
+    db::ReassignOutcome,
+    extractors::{Admin, CsrfProtected, CsrfToken, FlashMessage, Form, RequireRole},
+    flash::{self, Flash, DATABASE_ERROR, ITEM_NOT_FOUND},
+    models::{MaintenanceForm, ReassignItemForm, UserInfo},
+    templates::render,
+    tokens, AppState,
+};
+
+/// Flash shown when an item would move to a user that does not exist
+const USER_NOT_FOUND: &str = "That user does not exist";
+
+/// List every registered user (admin only)
+#[rustapi_rs::get("/admin/users")]
+pub async fn list_users(
+    State(state): State<AppState>,
+    RequireRole(admin, _): RequireRole<Admin>,
+    CsrfToken(csrf_token): CsrfToken,
+    FlashMessage(flash): FlashMessage,
+) -> Response {
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+    context.insert("maintenance", &state.maintenance.is_enabled());
+    if let Some(flash) = flash {
+        flash.insert_into(&mut context);
+    }
+
+    let users: Vec<UserInfo> = match state.db.list_all_users().await {
+        Ok(users) => users.into_iter().map(UserInfo::from).collect(),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", "Failed to load users");
+            vec![]
+        }
+    };
+    context.insert("users", &users);
+
+    render(&state, "admin/users.html", Some(&admin), context)
+}
+
+/// List every item across all users (admin only)
+#[rustapi_rs::get("/admin/items")]
+pub async fn list_items(
+    State(state): State<AppState>,
+    RequireRole(admin, _): RequireRole<Admin>,
+    CsrfToken(csrf_token): CsrfToken,
+    FlashMessage(flash): FlashMessage,
+) -> Response {
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+    if let Some(flash) = flash {
+        flash.insert_into(&mut context);
+    }
+
+    let items = match state.db.list_all_items().await {
+        Ok(items) => items,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", "Failed to load items");
+            vec![]
+        }
+    };
+    // Owners a row's item can move to
+    let users: Vec<UserInfo> = match state.db.list_all_users().await {
+        Ok(users) => users.into_iter().map(UserInfo::from).collect(),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", "Failed to load items");
+            vec![]
+        }
+    };
+    context.insert("items", &items);
+    context.insert("users", &users);
+
+    render(&state, "admin/items.html", Some(&admin), context)
+}
+
+/// Give an item to another user (admin only)
+///
+/// Afterwards the item shows up in the new owner's lists and no longer in
+/// the old owner's.
+#[rustapi_rs::post("/admin/items/{id}/reassign")]
+pub async fn reassign_item(
+    State(state): State<AppState>,
+    RequireRole(admin, _): RequireRole<Admin>,
+    _csrf: CsrfProtected,
+    Path(id): Path<i64>,
+    Form(form): Form<ReassignItemForm>,
+) -> Response {
+    match state.db.reassign_item(id, form.user_id, admin.id).await {
+        Ok(ReassignOutcome::Moved(item)) => {
+            tracing::info!(
+                admin_id = admin.id,
+                item_id = item.id,
+                user_id = item.user_id,
+                "item reassigned"
+            );
+            flash::redirect(&state, "/admin/items", Flash::success("Item reassigned"))
+        }
+        Ok(ReassignOutcome::ItemNotFound) => {
+            flash::redirect(&state, "/admin/items", Flash::error(ITEM_NOT_FOUND))
+        }
+        Ok(ReassignOutcome::UserNotFound) => {
+            flash::redirect(&state, "/admin/items", Flash::error(USER_NOT_FOUND))
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            flash::redirect(&state, "/admin/items", Flash::error(DATABASE_ERROR))
+        }
+    }
+}
+
+/// Every recorded create, update and delete of one item, oldest first
+/// (admin only)
+///
+/// Purged items keep their history, so it is found by ID even once the item
+/// is gone.
+#[rustapi_rs::get("/admin/items/{id}/audit")]
+pub async fn item_audit(
+    State(state): State<AppState>,
+    RequireRole(admin, _): RequireRole<Admin>,
+    Path(id): Path<i64>,
+) -> Response {
+    let mut context = Context::new();
+    context.insert("item_id", &id);
+
+    let entries = match state.db.list_item_audit(id).await {
+        Ok(entries) => entries,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", "Failed to load the item's history");
+            vec![]
+        }
+    };
+    context.insert("entries", &entries);
+
+    render(&state, "admin/audit.html", Some(&admin), context)
+}
+
+/// Take the app down for maintenance or bring it back up (admin only)
+///
+/// Lasts until toggled again or the server restarts, which goes back to
+/// `MAINTENANCE_MODE`.
+#[rustapi_rs::post("/admin/maintenance")]
+pub async fn set_maintenance(
+    State(state): State<AppState>,
+    RequireRole(admin, _): RequireRole<Admin>,
+    _csrf: CsrfProtected,
+    Form(form): Form<MaintenanceForm>,
+) -> Response {
+    state.maintenance.set_enabled(form.enabled);
+    tracing::info!(
+        admin_id = admin.id,
+        enabled = form.enabled,
+        "maintenance mode changed"
+    );
+    let message = if form.enabled {
+        "Maintenance mode is on"
+    } else {
+        "Maintenance mode is off"
+    };
+    flash::redirect(&state, "/admin/users", Flash::success(message))
+}
+
+/// List registration invites (admin only)
+#[rustapi_rs::get("/admin/invites")]
+pub async fn list_invites(
+    State(state): State<AppState>,
+    RequireRole(admin, _): RequireRole<Admin>,
+    CsrfToken(csrf_token): CsrfToken,
+    FlashMessage(flash): FlashMessage,
+) -> Response {
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+    if let Some(flash) = flash {
+        flash.insert_into(&mut context);
+    }
+
+    render_invites(&state, &admin, context).await
+}
+
+/// Create a registration invite (admin only); the code is shown on the page
+/// this once, and only its hash is kept
+#[rustapi_rs::post("/admin/invites")]
+pub async fn create_invite(
+    State(state): State<AppState>,
+    RequireRole(admin, _): RequireRole<Admin>,
+    CsrfProtected(csrf_token): CsrfProtected,
+) -> Response {
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+
+    let code = tokens::generate_token();
+    match state
+        .db
+        .create_invite(&tokens::hash_token(&code), admin.id)
+        .await
+    {
+        Ok(invite) => {
+            tracing::info!(admin_id = admin.id, invite_id = invite.id, "invite created");
+            context.insert("new_invite_code", &code);
+            context.insert(
+                "success",
+                "Invite created. Copy the code now; it will not be shown again.",
+            );
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", "An error occurred. Please try again.");
+        }
+    }
+    render_invites(&state, &admin, context).await
+}
+
+/// Revoke a registration invite nobody has used yet (admin only)
+#[rustapi_rs::post("/admin/invites/{id}/revoke")]
+pub async fn revoke_invite(
+    State(state): State<AppState>,
+    RequireRole(admin, _): RequireRole<Admin>,
+    _csrf: CsrfProtected,
+    Path(id): Path<i64>,
+) -> Response {
+    match state.db.delete_invite(id).await {
+        Ok(true) => {
+            tracing::info!(admin_id = admin.id, invite_id = id, "invite revoked");
+            flash::redirect(&state, "/admin/invites", Flash::success("Invite revoked"))
+        }
+        Ok(false) => flash::redirect(
+            &state,
+            "/admin/invites",
+            Flash::error("Invite not found or already used"),
+        ),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            flash::redirect(&state, "/admin/invites", Flash::error(DATABASE_ERROR))
+        }
+    }
+}
+
+async fn render_invites(state: &AppState, admin: &UserInfo, mut context: Context) -> Response {
+    let invites = match state.db.list_invites().await {
+        Ok(invites) => invites,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", "Failed to load invites");
+            vec![]
+        }
+    };
+    context.insert("invites", &invites);
+
+    render(state, "admin/invites.html", Some(admin), context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CreateItem;
+    use crate::test_utils::{
+        body_string, cleanup_db, flash_from, header_value, request_with_state, setup_test_state,
+        test_csrf, test_csrf_token, token_for_user,
+    };
+    use rustapi_rs::FromRequest;
+
+    async fn admin_guard(state: &AppState, path: &str, user_id: i64) -> Result<RequireRole<Admin>> {
+        let user = state
+            .db
+            .find_user_by_id(user_id)
+            .await
+            .expect("find user")
+            .expect("user exists");
+        let token = token_for_user(&state.config.jwt_secret, user.id, &user.username, 3600);
+        let mut req = request_with_state(
+            state,
+            http::Request::get(path).header("Cookie", format!("token={}", token)),
+            "",
+        );
+        RequireRole::<Admin>::from_request(&mut req).await
+    }
+
+    #[tokio::test]
+    async fn admin_routes_reject_normal_user() {
+        let (state, path) = setup_test_state().await;
+        state
+            .db
+            .create_user("admin", "admin@example.com", "hash")
+            .await
+            .expect("create admin");
+        let user = state
+            .db
+            .create_user("user", "user@example.com", "hash")
+            .await
+            .expect("create user");
+
+        for route in [
+            "/admin/users",
+            "/admin/items",
+            "/admin/items/1/reassign",
+            "/admin/items/1/audit",
+            "/admin/invites",
+        ] {
+            let rejection = admin_guard(&state, route, user.id).await.err();
+            assert_eq!(rejection.map(|e| e.status), Some(StatusCode::FORBIDDEN));
+        }
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn admin_sees_all_users_and_items() {
+        let (state, path) = setup_test_state().await;
+        let admin = state
+            .db
+            .create_user("admin", "admin@example.com", "hash")
+            .await
+            .expect("create admin");
+        let user = state
+            .db
+            .create_user("user", "user@example.com", "hash")
+            .await
+            .expect("create user");
+        state
+            .db
+            .create_item(CreateItem {
+                user_id: user.id,
+                title: "Someone else's item".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+
+        let guard = admin_guard(&state, "/admin/users", admin.id)
+            .await
+            .expect("admin allowed");
+        let response = list_users(
+            State(state.clone()),
+            guard,
+            test_csrf_token(),
+            FlashMessage(None),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            body_string(response).await,
+            "ADMIN USERS 2 maintenance=false"
+        );
+
+        let guard = admin_guard(&state, "/admin/items", admin.id)
+            .await
+            .expect("admin allowed");
+        let response = list_items(
+            State(state.clone()),
+            guard,
+            test_csrf_token(),
+            FlashMessage(None),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "ADMIN ITEMS 1");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn admin_sees_an_items_history() {
+        let (state, path) = setup_test_state().await;
+        let admin = state
+            .db
+            .create_user("admin", "admin@example.com", "hash")
+            .await
+            .expect("create admin");
+        let user = state
+            .db
+            .create_user("user", "user@example.com", "hash")
+            .await
+            .expect("create user");
+        let item = state
+            .db
+            .create_item(CreateItem {
+                user_id: user.id,
+                title: "Tracked".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        state
+            .db
+            .update_item(item.id, user.id, "Tracked again", None, None)
+            .await
+            .expect("update item");
+        state
+            .db
+            .delete_item(item.id, user.id)
+            .await
+            .expect("trash item");
+
+        let guard = admin_guard(&state, "/admin/items/1/audit", admin.id)
+            .await
+            .expect("admin allowed");
+        let response = item_audit(State(state.clone()), guard, Path(item.id)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            body_string(response).await,
+            format!("ADMIN AUDIT {} create,update,trash,", item.id)
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn reassign_item_moves_it_to_the_new_owner() {
+        let (state, path) = setup_test_state().await;
+        let admin = state
+            .db
+            .create_user("admin", "admin@example.com", "hash")
+            .await
+            .expect("create admin");
+        let old = state
+            .db
+            .create_user("old", "old@example.com", "hash")
+            .await
+            .expect("create user");
+        let new = state
+            .db
+            .create_user("new", "new@example.com", "hash")
+            .await
+            .expect("create user");
+        let item = state
+            .db
+            .create_item(CreateItem {
+                user_id: old.id,
+                title: "Handover".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        let reassign = |item_id: i64, user_id: i64| {
+            let state = state.clone();
+            async move {
+                let guard = admin_guard(&state, "/admin/items/1/reassign", admin.id)
+                    .await
+                    .expect("admin allowed");
+                reassign_item(
+                    State(state.clone()),
+                    guard,
+                    test_csrf(),
+                    Path(item_id),
+                    Form(ReassignItemForm { user_id }),
+                )
+                .await
+            }
+        };
+
+        let response = reassign(item.id, new.id).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            header_value(&response, "Location").as_deref(),
+            Some("/admin/items")
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("Item reassigned"))
+        );
+        assert!(state
+            .db
+            .get_item(item.id, old.id)
+            .await
+            .expect("get")
+            .is_none());
+        assert!(state
+            .db
+            .get_item(item.id, new.id)
+            .await
+            .expect("get")
+            .is_some());
+
+        // Unknown users and items are reported and change nothing
+        let response = reassign(item.id, new.id + 100).await;
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(USER_NOT_FOUND))
+        );
+        assert!(state
+            .db
+            .get_item(item.id, new.id)
+            .await
+            .expect("get")
+            .is_some());
+
+        let response = reassign(item.id + 100, old.id).await;
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(ITEM_NOT_FOUND))
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn invites_are_shown_once_and_can_be_revoked() {
+        let (state, path) = setup_test_state().await;
+        let admin = state
+            .db
+            .create_user("admin", "admin@example.com", "hash")
+            .await
+            .expect("create admin");
+        let guard = || admin_guard(&state, "/admin/invites", admin.id);
+
+        let response = create_invite(
+            State(state.clone()),
+            guard().await.expect("admin allowed"),
+            test_csrf(),
+        )
+        .await;
+        let body = body_string(response).await;
+        let code = body
+            .split_once("new_invite_code=")
+            .map(|(_, code)| code.to_string())
+            .expect("invite code shown");
+        assert!(!code.is_empty());
+        assert!(state
+            .db
+            .invite_is_unused(&tokens::hash_token(&code))
+            .await
+            .expect("invite is unused"));
+
+        // Listing never shows the code again
+        let response = list_invites(
+            State(state.clone()),
+            guard().await.expect("admin allowed"),
+            test_csrf_token(),
+            FlashMessage(None),
+        )
+        .await;
+        assert_eq!(
+            body_string(response).await,
+            "ADMIN INVITES 1 new_invite_code="
+        );
+
+        let invite_id = state.db.list_invites().await.expect("list invites")[0].id;
+        let revoke = || async {
+            revoke_invite(
+                State(state.clone()),
+                guard().await.expect("admin allowed"),
+                test_csrf(),
+                Path(invite_id),
+            )
+            .await
+        };
+        let response = revoke().await;
+        assert_eq!(
+            header_value(&response, "Location").as_deref(),
+            Some("/admin/invites")
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("Invite revoked"))
+        );
+        let response = revoke().await;
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error("Invite not found or already used"))
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn set_maintenance_toggles_the_mode() {
+        let (state, path) = setup_test_state().await;
+        let admin = state
+            .db
+            .create_user("admin", "admin@example.com", "hash")
+            .await
+            .expect("create admin");
+        let toggle = |enabled: bool| {
+            let state = state.clone();
+            async move {
+                let guard = admin_guard(&state, "/admin/maintenance", admin.id)
+                    .await
+                    .expect("admin allowed");
+                set_maintenance(
+                    State(state.clone()),
+                    guard,
+                    test_csrf(),
+                    Form(MaintenanceForm { enabled }),
+                )
+                .await
+            }
+        };
+
+        let response = toggle(true).await;
+        assert_eq!(
+            header_value(&response, "Location").as_deref(),
+            Some("/admin/users")
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("Maintenance mode is on"))
+        );
+        assert!(state.maintenance.is_enabled());
+
+        toggle(false).await;
+        assert!(!state.maintenance.is_enabled());
+        cleanup_db(path);
+    }
+}
@@ -0,0 +1,1246 @@
+use chrono::{DateTime, Utc};
+use rustapi_rs::prelude::*;
+use serde_json::json;
+
+use crate::{
+    errors::ApiErrorResponse,
+    etag::conditional_json,
+    events::ItemEventKind,
+    extractors::{ApiUser, IdempotencyKey, IfNoneMatch, JsonBody, Query, IDEMPOTENCY_WINDOW_SECS},
+    handlers::items::remaining_items,
+    models::{
+        normalize_tag, ApiItemsQuery, CreateItem, Item, ItemCursor, ItemForm, DEFAULT_PER_PAGE,
+        MAX_PER_PAGE,
+    },
+    validation::{field_errors, FieldErrors, ValidateConfigured},
+    AppState,
+};
+
+/// Most items `POST /api/items/batch` accepts at once unless
+/// `MAX_BATCH_ITEMS` overrides it
+pub const DEFAULT_MAX_BATCH_ITEMS: usize = 100;
+
+/// List all items for the current user as JSON, optionally only those with `?tag=`
+///
+/// With `?limit=` or `?cursor=` the list comes a page at a time, newest first,
+/// as `{"items": [...], "next_cursor": ...}`; passing `next_cursor` back as
+/// `?cursor=` fetches the page after, until it is `null`. With `?since=` only
+/// what changed after that time is returned; see `list_changes`.
+#[rustapi_rs::get("/api/items")]
+pub async fn api_list_items(
+    State(state): State<AppState>,
+    ApiUser(user): ApiUser,
+    Query(query): Query<ApiItemsQuery>,
+) -> Response {
+    let Some(user) = user else {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    };
+
+    if let Some(since) = query.since.as_deref() {
+        return list_changes(&state, user.id, since).await;
+    }
+    let tag = query.tag.as_deref().and_then(normalize_tag);
+    if query.paged() {
+        return list_items_page(&state, user.id, tag.as_deref(), &query).await;
+    }
+
+    let items = match tag {
+        Some(tag) => state.db.get_items_by_tag(user.id, &tag).await,
+        None => state.db.get_user_items(user.id).await,
+    };
+    let items = match items {
+        Ok(mut items) => state.db.load_item_tags(&mut items).await.map(|()| items),
+        Err(e) => Err(e),
+    };
+
+    match items {
+        Ok(items) => Json(items).into_response(),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            ApiErrorResponse::from(&e).into_response()
+        }
+    }
+}
+
+/// Get a single item as JSON
+///
+/// The response carries an `ETag`; sending it back in `If-None-Match` gets a
+/// bodiless 304 while the item is unchanged.
+#[rustapi_rs::get("/api/items/{id}")]
+pub async fn api_get_item(
+    State(state): State<AppState>,
+    ApiUser(user): ApiUser,
+    IfNoneMatch(if_none_match): IfNoneMatch,
+    Path(id): Path<i64>,
+) -> Response {
+    let Some(user) = user else {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    };
+
+    match load_item(&state, id, user.id).await {
+        Ok(Some(item)) => conditional_json(item, if_none_match.as_deref()),
+        Ok(None) => json_error(StatusCode::NOT_FOUND, "not_found"),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            ApiErrorResponse::from(&e).into_response()
+        }
+    }
+}
+
+/// Create an item from a JSON body
+///
+/// With an `Idempotency-Key` header, retrying a request returns the item the
+/// first attempt created instead of creating another.
+#[rustapi_rs::post("/api/items")]
+pub async fn api_create_item(
+    State(state): State<AppState>,
+    ApiUser(user): ApiUser,
+    IdempotencyKey(key): IdempotencyKey,
+    JsonBody(form): JsonBody<ItemForm>,
+) -> Response {
+    let Some(user) = user else {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    };
+
+    if let Some(response) = invalid_item(&state, &form) {
+        return response;
+    }
+    if let Some(key) = key.as_deref() {
+        match state
+            .db
+            .find_idempotent_item(user.id, key, IDEMPOTENCY_WINDOW_SECS)
+            .await
+        {
+            Ok(Some(id)) => return created_item(&state, id, user.id).await,
+            Ok(None) => {}
+            Err(e) => {
+                tracing::error!(error = %e, "database error");
+                return ApiErrorResponse::from(&e).into_response();
+            }
+        }
+    }
+    match remaining_items(&state, user.id).await {
+        Ok(Some(0)) => return json_error(StatusCode::FORBIDDEN, "item_limit_reached"),
+        Ok(_) => {}
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return ApiErrorResponse::from(&e).into_response();
+        }
+    }
+
+    let tags = form.tag_list();
+    let due_at = form.due_date().unwrap_or_default();
+    let create_item = CreateItem {
+        user_id: user.id,
+        title: form.title.trim().to_string(),
+        description: form
+            .description
+            .map(|d| d.trim().to_string())
+            .filter(|d| !d.is_empty()),
+        due_at,
+    };
+
+    if let Some(key) = key.as_deref() {
+        return match state
+            .db
+            .create_item_once(create_item, tags, key, IDEMPOTENCY_WINDOW_SECS)
+            .await
+        {
+            Ok((id, new)) => {
+                if new {
+                    state.metrics.record_item_operation("create");
+                    state
+                        .item_events
+                        .publish(user.id, id, ItemEventKind::Created);
+                }
+                created_item(&state, id, user.id).await
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "database error");
+                ApiErrorResponse::from(&e).into_response()
+            }
+        };
+    }
+
+    match state.db.create_item_with_tags(create_item, tags).await {
+        Ok(item) => {
+            state.metrics.record_item_operation("create");
+            state
+                .item_events
+                .publish(user.id, item.id, ItemEventKind::Created);
+            (StatusCode::CREATED, Json(item)).into_response()
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            ApiErrorResponse::from(&e).into_response()
+        }
+    }
+}
+
+/// Check a JSON item body the way `POST /api/items` would, without creating
+/// anything
+///
+/// Answers `{"valid": true}`, or 422 with the same field errors a create
+/// gets. The item limit is not checked.
+#[rustapi_rs::post("/api/items/validate")]
+pub async fn api_validate_item(
+    State(state): State<AppState>,
+    ApiUser(user): ApiUser,
+    JsonBody(form): JsonBody<ItemForm>,
+) -> Response {
+    if user.is_none() {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+    invalid_item(&state, &form).unwrap_or_else(|| Json(json!({ "valid": true })).into_response())
+}
+
+/// Check a JSON array of item bodies the way `POST /api/items` checks each,
+/// without creating anything, so an import can be checked before it is run
+///
+/// Answers a parallel array of `{"index", "valid", "errors"}`, with each
+/// record's field errors as `POST /api/items` reports them, or `{}` for a
+/// valid one. Arrays of any length are checked, within the body size limit;
+/// the item limit is not.
+#[rustapi_rs::post("/api/items/validate-batch")]
+pub async fn api_validate_items(
+    State(state): State<AppState>,
+    ApiUser(user): ApiUser,
+    JsonBody(forms): JsonBody<Vec<ItemForm>>,
+) -> Response {
+    if user.is_none() {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    }
+    let results: Vec<_> = forms
+        .iter()
+        .enumerate()
+        .map(|(index, form)| {
+            let errors = match form.validate_configured(&state) {
+                Ok(()) => FieldErrors::new(),
+                Err(errors) => field_errors(&errors),
+            };
+            json!({ "index": index, "valid": errors.is_empty(), "errors": errors })
+        })
+        .collect();
+    Json(results).into_response()
+}
+
+// The 422 a create answers for an invalid body, shared with the validate
+// endpoint so the two cannot drift apart
+fn invalid_item(state: &AppState, form: &ItemForm) -> Option<Response> {
+    form.validate_configured(state)
+        .err()
+        .map(|errors| ApiErrorResponse::validation(&field_errors(&errors)).into_response())
+}
+
+/// Create several items from a JSON array of item bodies
+///
+/// Each is validated like `POST /api/items`, and all are created in one
+/// transaction: one invalid entry, or a batch that would go over
+/// `MAX_ITEMS_PER_USER`, creates nothing. Answers with the created items in
+/// request order. Has the long timeout; see `route_limits::ROUTE_OVERRIDES`.
+#[rustapi_rs::post("/api/items/batch")]
+pub async fn api_create_items(
+    State(state): State<AppState>,
+    ApiUser(user): ApiUser,
+    JsonBody(forms): JsonBody<Vec<ItemForm>>,
+) -> Response {
+    let Some(user) = user else {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    };
+
+    if forms.len() > state.config.max_batch_items {
+        return json_error(StatusCode::PAYLOAD_TOO_LARGE, "batch_too_large");
+    }
+    let invalid = forms
+        .iter()
+        .enumerate()
+        .find_map(|(index, form)| form.validate_configured(&state).err().map(|e| (index, e)));
+    if let Some((index, errors)) = invalid {
+        return ApiErrorResponse::new(StatusCode::UNPROCESSABLE_ENTITY, "validation_failed")
+            .with_details(json!({ "index": index, "fields": field_errors(&errors) }))
+            .into_response();
+    }
+    match remaining_items(&state, user.id).await {
+        Ok(Some(remaining)) if remaining < forms.len() as i64 => {
+            return json_error(StatusCode::FORBIDDEN, "item_limit_reached")
+        }
+        Ok(_) => {}
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return ApiErrorResponse::from(&e).into_response();
+        }
+    }
+
+    let items = forms
+        .into_iter()
+        .map(|form| {
+            let tags = form.tag_list();
+            let due_at = form.due_date().unwrap_or_default();
+            let item = CreateItem {
+                user_id: user.id,
+                title: form.title.trim().to_string(),
+                description: form
+                    .description
+                    .map(|d| d.trim().to_string())
+                    .filter(|d| !d.is_empty()),
+                due_at,
+            };
+            (item, tags)
+        })
+        .collect();
+
+    match state.db.create_items_with_tags(items).await {
+        Ok(items) => {
+            for item in &items {
+                state.metrics.record_item_operation("create");
+                state
+                    .item_events
+                    .publish(user.id, item.id, ItemEventKind::Created);
+            }
+            (StatusCode::CREATED, Json(items)).into_response()
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            ApiErrorResponse::from(&e).into_response()
+        }
+    }
+}
+
+/// Replace an item's fields from a JSON body
+#[rustapi_rs::put("/api/items/{id}")]
+pub async fn api_update_item(
+    State(state): State<AppState>,
+    ApiUser(user): ApiUser,
+    Path(id): Path<i64>,
+    JsonBody(form): JsonBody<ItemForm>,
+) -> Response {
+    let Some(user) = user else {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    };
+
+    if let Some(response) = invalid_item(&state, &form) {
+        return response;
+    }
+
+    let description = form
+        .description
+        .as_deref()
+        .map(|d| d.trim())
+        .filter(|d| !d.is_empty());
+
+    let updated = state
+        .db
+        .update_item_with_tags(
+            id,
+            user.id,
+            form.title.trim(),
+            description,
+            form.due_date().unwrap_or_default(),
+            form.tag_list(),
+        )
+        .await;
+
+    match updated {
+        Ok(Some(item)) => {
+            state.metrics.record_item_operation("update");
+            state
+                .item_events
+                .publish(user.id, item.id, ItemEventKind::Updated);
+            Json(item).into_response()
+        }
+        Ok(None) => json_error(StatusCode::NOT_FOUND, "not_found"),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            ApiErrorResponse::from(&e).into_response()
+        }
+    }
+}
+
+/// Delete an item
+#[rustapi_rs::delete("/api/items/{id}")]
+pub async fn api_delete_item(
+    State(state): State<AppState>,
+    ApiUser(user): ApiUser,
+    Path(id): Path<i64>,
+) -> Response {
+    let Some(user) = user else {
+        return json_error(StatusCode::UNAUTHORIZED, "unauthorized");
+    };
+
+    match state.db.delete_item(id, user.id).await {
+        Ok(true) => {
+            state.metrics.record_item_operation("delete");
+            state
+                .item_events
+                .publish(user.id, id, ItemEventKind::Deleted);
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => json_error(StatusCode::NOT_FOUND, "not_found"),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            ApiErrorResponse::from(&e).into_response()
+        }
+    }
+}
+
+// One of the user's items with its tags
+pub(crate) async fn load_item(
+    state: &AppState,
+    id: i64,
+    user_id: i64,
+) -> Result<Option<Item>, sqlx::Error> {
+    match state.db.get_item(id, user_id).await? {
+        Some(mut item) => {
+            item.tags = state.db.get_item_tags(item.id).await?;
+            Ok(Some(item))
+        }
+        None => Ok(None),
+    }
+}
+
+// `201 Created` with an item an idempotent create made, now or earlier
+async fn created_item(state: &AppState, id: i64, user_id: i64) -> Response {
+    match load_item(state, id, user_id).await {
+        Ok(Some(item)) => (StatusCode::CREATED, Json(item)).into_response(),
+        // Deleted since it was created
+        Ok(None) => json_error(StatusCode::NOT_FOUND, "not_found"),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            ApiErrorResponse::from(&e).into_response()
+        }
+    }
+}
+
+// `status` with the `ApiErrorResponse` envelope for `code`
+pub(crate) fn json_error(status: StatusCode, code: &str) -> Response {
+    ApiErrorResponse::new(status, code).into_response()
+}
+
+/// The user's items changed after `since`, for clients keeping a copy in sync
+///
+/// Answers `{"items": [...], "deleted": [{"id", "deleted_at"}]}`: items added
+/// or edited since, oldest change first, and tombstones for those moved to
+/// the trash, which a later restore brings back among `items`. Other query
+/// parameters are ignored; every change is returned at once.
+async fn list_changes(state: &AppState, user_id: i64, since: &str) -> Response {
+    let Ok(since) = DateTime::parse_from_rfc3339(since.trim()) else {
+        return json_error(StatusCode::BAD_REQUEST, "invalid_since");
+    };
+
+    let changed = match state
+        .db
+        .get_items_updated_since(user_id, since.with_timezone(&Utc))
+        .await
+    {
+        Ok(changed) => changed,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return ApiErrorResponse::from(&e).into_response();
+        }
+    };
+    let (deleted, mut items): (Vec<Item>, Vec<Item>) = changed
+        .into_iter()
+        .partition(|item| item.deleted_at.is_some());
+    if let Err(e) = state.db.load_item_tags(&mut items).await {
+        tracing::error!(error = %e, "database error");
+        return ApiErrorResponse::from(&e).into_response();
+    }
+
+    let deleted: Vec<_> = deleted
+        .iter()
+        .map(|item| json!({ "id": item.id, "deleted_at": item.deleted_at }))
+        .collect();
+    Json(json!({ "items": items, "deleted": deleted })).into_response()
+}
+
+/// One page of `api_list_items`, with the cursor for the next if there is one
+async fn list_items_page(
+    state: &AppState,
+    user_id: i64,
+    tag: Option<&str>,
+    query: &ApiItemsQuery,
+) -> Response {
+    let after = match query.cursor.as_deref() {
+        Some(token) => match ItemCursor::decode(token) {
+            Some(cursor) => Some(cursor),
+            None => return json_error(StatusCode::BAD_REQUEST, "invalid_cursor"),
+        },
+        None => None,
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PER_PAGE)
+        .clamp(1, MAX_PER_PAGE);
+
+    // One past the page tells whether another follows
+    let items = match state
+        .db
+        .get_user_items_after_cursor(user_id, tag, after.as_ref(), limit + 1)
+        .await
+    {
+        Ok(mut items) => state.db.load_item_tags(&mut items).await.map(|()| items),
+        Err(e) => Err(e),
+    };
+
+    match items {
+        Ok(mut items) => {
+            let next_cursor = if items.len() as i64 > limit {
+                items.truncate(limit as usize);
+                items.last().map(|item| ItemCursor::after(item).encode())
+            } else {
+                None
+            };
+            Json(json!({ "items": items, "next_cursor": next_cursor })).into_response()
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            ApiErrorResponse::from(&e).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::DbPool;
+    use crate::models::UserInfo;
+    use crate::models::{Item, ItemLimits};
+    use crate::test_utils::{body_string, cleanup_db, header_value, setup_test_state};
+    use rustapi_rs::Path;
+    use std::sync::Arc;
+
+    async fn setup_user(state: &AppState) -> (i64, ApiUser) {
+        let user = state
+            .db
+            .create_user("api", "api@example.com", "hash")
+            .await
+            .expect("create user");
+        (user.id, ApiUser(Some(UserInfo::from(user))))
+    }
+
+    async fn json_body(response: Response) -> serde_json::Value {
+        serde_json::from_str(&body_string(response).await).expect("json body")
+    }
+
+    #[tokio::test]
+    async fn api_list_items_requires_auth() {
+        let (state, path) = setup_test_state().await;
+        let response = api_list_items(
+            State(state.clone()),
+            ApiUser(None),
+            Query(ApiItemsQuery::default()),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(json_body(response).await["error"]["code"], "unauthorized");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_create_item_returns_created_item() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+
+        let response = api_create_item(
+            State(state.clone()),
+            auth,
+            IdempotencyKey(None),
+            JsonBody(ItemForm {
+                title: "  Api item ".to_string(),
+                description: Some("From JSON".to_string()),
+                tags: String::new(),
+                due_at: None,
+            }),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let item: Item = serde_json::from_str(&body_string(response).await).expect("item body");
+        assert_eq!(item.title, "Api item");
+        assert_eq!(item.user_id, user_id);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_create_item_applies_the_configured_limits() {
+        let (mut state, path) = setup_test_state().await;
+        Arc::make_mut(&mut state.config).item_limits = ItemLimits {
+            title: 10,
+            description: 20,
+        };
+        let (user_id, auth) = setup_user(&state).await;
+        let create = |title: String, description: String| {
+            api_create_item(
+                State(state.clone()),
+                ApiUser(auth.0.clone()),
+                IdempotencyKey(None),
+                JsonBody(ItemForm {
+                    title,
+                    description: Some(description),
+                    tags: String::new(),
+                    due_at: None,
+                }),
+            )
+        };
+
+        let response = create("t".repeat(10), "d".repeat(20)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = create("t".repeat(11), "d".repeat(20)).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = json_body(response).await;
+        assert_eq!(
+            body["error"]["details"]["fields"]["title"],
+            json!(["Title must be 10 characters or less"])
+        );
+
+        let response = create("t".repeat(10), "d".repeat(21)).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = json_body(response).await;
+        assert_eq!(
+            body["error"]["details"]["fields"]["description"],
+            json!(["Description must be 20 characters or less"])
+        );
+
+        let items = state.db.get_user_items(user_id).await.expect("items");
+        assert_eq!(items.len(), 1);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_create_item_replays_a_repeated_idempotency_key() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let create = |key: &str| {
+            api_create_item(
+                State(state.clone()),
+                ApiUser(auth.0.clone()),
+                IdempotencyKey(Some(key.to_string())),
+                JsonBody(ItemForm {
+                    title: "Retried".to_string(),
+                    description: None,
+                    tags: "api".to_string(),
+                    due_at: None,
+                }),
+            )
+        };
+
+        let first = create("retry-1").await;
+        assert_eq!(first.status(), StatusCode::CREATED);
+        let first: Item = serde_json::from_str(&body_string(first).await).expect("item body");
+        let again = create("retry-1").await;
+        assert_eq!(again.status(), StatusCode::CREATED);
+        let again: Item = serde_json::from_str(&body_string(again).await).expect("item body");
+        assert_eq!(again.id, first.id);
+        assert_eq!(again.tags, vec!["api".to_string()]);
+
+        // Keys are scoped per user
+        let other = state
+            .db
+            .create_user("other", "other@example.com", "hash")
+            .await
+            .expect("create user");
+        let response = api_create_item(
+            State(state.clone()),
+            ApiUser(Some(UserInfo::from(other))),
+            IdempotencyKey(Some("retry-1".to_string())),
+            JsonBody(ItemForm {
+                title: "Theirs".to_string(),
+                description: None,
+                tags: String::new(),
+                due_at: None,
+            }),
+        )
+        .await;
+        let theirs: Item = serde_json::from_str(&body_string(response).await).expect("item body");
+        assert_ne!(theirs.id, first.id);
+
+        let different = create("retry-2").await;
+        assert_eq!(different.status(), StatusCode::CREATED);
+        let items = state.db.get_user_items(user_id).await.expect("items");
+        assert_eq!(items.len(), 2);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_create_item_stops_at_the_item_limit() {
+        let (mut state, path) = setup_test_state().await;
+        Arc::make_mut(&mut state.config).max_items_per_user = Some(1);
+        let (user_id, auth) = setup_user(&state).await;
+        let create = || {
+            api_create_item(
+                State(state.clone()),
+                ApiUser(auth.0.clone()),
+                IdempotencyKey(None),
+                JsonBody(ItemForm {
+                    title: "Only one".to_string(),
+                    description: None,
+                    tags: String::new(),
+                    due_at: None,
+                }),
+            )
+        };
+
+        assert_eq!(create().await.status(), StatusCode::CREATED);
+        let response = create().await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            json_body(response).await["error"]["code"],
+            "item_limit_reached"
+        );
+        let items = state.db.get_user_items(user_id).await.expect("items");
+        assert_eq!(items.len(), 1);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_create_item_rejects_invalid_body() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+
+        let response = api_create_item(
+            State(state.clone()),
+            auth,
+            IdempotencyKey(None),
+            JsonBody(ItemForm {
+                title: "".to_string(),
+                description: None,
+                tags: String::new(),
+                due_at: None,
+            }),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            json_body(response).await["error"]["code"],
+            "validation_failed"
+        );
+        let items = state.db.get_user_items(user_id).await.expect("items");
+        assert!(items.is_empty());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_validate_item_checks_without_creating() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let form = |title: &str| {
+            JsonBody(ItemForm {
+                title: title.to_string(),
+                description: Some("Desc".to_string()),
+                tags: "one, two".to_string(),
+                due_at: None,
+            })
+        };
+
+        let response = api_validate_item(State(state.clone()), ApiUser(None), form("Valid")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response =
+            api_validate_item(State(state.clone()), ApiUser(auth.0.clone()), form("")).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = json_body(response).await;
+        assert_eq!(body["error"]["code"], "validation_failed");
+        assert!(body["error"]["details"]["fields"]["title"].is_array());
+
+        let response = api_validate_item(State(state.clone()), auth, form("Valid")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await, json!({ "valid": true }));
+        let items = state.db.get_user_items(user_id).await.expect("items");
+        assert!(items.is_empty());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_validate_items_reports_each_record() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let records = || {
+            [
+                ("Fine", None),
+                ("", None),
+                ("Also fine", Some("2030-01-31")),
+                ("Bad date", Some("someday")),
+            ]
+            .into_iter()
+            .map(|(title, due_at)| ItemForm {
+                title: title.to_string(),
+                description: None,
+                tags: String::new(),
+                due_at: due_at.map(str::to_string),
+            })
+            .collect::<Vec<_>>()
+        };
+
+        let response =
+            api_validate_items(State(state.clone()), ApiUser(None), JsonBody(records())).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = api_validate_items(State(state.clone()), auth, JsonBody(records())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let results = json_body(response).await;
+        let results = results.as_array().expect("results");
+        assert_eq!(results.len(), 4);
+        for (index, result) in results.iter().enumerate() {
+            assert_eq!(result["index"], index);
+        }
+        let valid: Vec<bool> = results
+            .iter()
+            .map(|r| r["valid"].as_bool().expect("valid"))
+            .collect();
+        assert_eq!(valid, [true, false, true, false]);
+        assert_eq!(results[0]["errors"], json!({}));
+        assert!(results[1]["errors"]["title"].is_array());
+        assert!(results[3]["errors"]["due_at"].is_array());
+        assert!(results[3]["errors"].get("title").is_none());
+
+        let items = state.db.get_user_items(user_id).await.expect("items");
+        assert!(items.is_empty());
+        cleanup_db(path);
+    }
+
+    fn batch(titles: &[&str]) -> JsonBody<Vec<ItemForm>> {
+        JsonBody(
+            titles
+                .iter()
+                .map(|title| ItemForm {
+                    title: title.to_string(),
+                    description: None,
+                    tags: "batch".to_string(),
+                    due_at: None,
+                })
+                .collect(),
+        )
+    }
+
+    #[tokio::test]
+    async fn api_create_items_creates_the_whole_batch() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+
+        let response =
+            api_create_items(State(state.clone()), auth, batch(&["One", "Two", "Three"])).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let items: Vec<Item> =
+            serde_json::from_str(&body_string(response).await).expect("items body");
+        assert_eq!(
+            items.iter().map(|i| i.title.as_str()).collect::<Vec<_>>(),
+            ["One", "Two", "Three"]
+        );
+        assert!(items
+            .iter()
+            .all(|i| i.user_id == user_id && i.tags == ["batch"]));
+        let stored = state.db.get_user_items(user_id).await.expect("items");
+        assert_eq!(stored.len(), 3);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_create_items_rejects_the_batch_if_any_item_is_invalid() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+
+        let response = api_create_items(State(state.clone()), auth, batch(&["Fine", ""])).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = json_body(response).await;
+        assert_eq!(body["error"]["code"], "validation_failed");
+        assert_eq!(body["error"]["details"]["index"], 1);
+        assert_eq!(
+            body["error"]["details"]["fields"]["title"][0],
+            "Title is required"
+        );
+        let items = state.db.get_user_items(user_id).await.expect("items");
+        assert!(items.is_empty());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_create_items_caps_the_batch_size() {
+        let (mut state, path) = setup_test_state().await;
+        Arc::make_mut(&mut state.config).max_batch_items = 2;
+        let (user_id, auth) = setup_user(&state).await;
+
+        let response = api_create_items(
+            State(state.clone()),
+            ApiUser(auth.0.clone()),
+            batch(&["A", "B", "C"]),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            json_body(response).await["error"]["code"],
+            "batch_too_large"
+        );
+        assert!(state
+            .db
+            .get_user_items(user_id)
+            .await
+            .expect("items")
+            .is_empty());
+
+        let response = api_create_items(State(state.clone()), auth, batch(&["A", "B"])).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_create_items_stops_at_the_item_limit() {
+        let (mut state, path) = setup_test_state().await;
+        Arc::make_mut(&mut state.config).max_items_per_user = Some(2);
+        let (user_id, auth) = setup_user(&state).await;
+
+        let response = api_create_items(State(state.clone()), auth, batch(&["A", "B", "C"])).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(state
+            .db
+            .get_user_items(user_id)
+            .await
+            .expect("items")
+            .is_empty());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_get_item_returns_not_found() {
+        let (state, path) = setup_test_state().await;
+        let (_user_id, auth) = setup_user(&state).await;
+
+        let response = api_get_item(State(state.clone()), auth, IfNoneMatch(None), Path(999)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(json_body(response).await["error"]["code"], "not_found");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_list_and_get_return_items() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let created = state
+            .db
+            .create_item(CreateItem {
+                user_id,
+                title: "Listed".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+
+        let response = api_list_items(
+            State(state.clone()),
+            ApiUser(auth.0.clone()),
+            Query(ApiItemsQuery::default()),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let items = json_body(response).await;
+        assert_eq!(items.as_array().map(Vec::len), Some(1));
+        assert_eq!(items[0]["title"], "Listed");
+
+        let response = api_get_item(
+            State(state.clone()),
+            auth,
+            IfNoneMatch(None),
+            Path(created.id),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await["id"], created.id);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_get_item_answers_conditional_requests() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let created = state
+            .db
+            .create_item(CreateItem {
+                user_id,
+                title: "Cached".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        let get = |if_none_match: Option<&str>| {
+            api_get_item(
+                State(state.clone()),
+                ApiUser(auth.0.clone()),
+                IfNoneMatch(if_none_match.map(str::to_string)),
+                Path(created.id),
+            )
+        };
+
+        let response = get(None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            header_value(&response, "Cache-Control"),
+            Some("private, no-cache".to_string())
+        );
+        let etag = header_value(&response, "ETag").expect("etag");
+
+        let response = get(Some(&etag)).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(header_value(&response, "ETag"), Some(etag.clone()));
+        assert_eq!(body_string(response).await, "");
+
+        state
+            .db
+            .update_item_with_tags(created.id, user_id, "Changed", None, None, vec![])
+            .await
+            .expect("update item");
+        let response = get(Some(&etag)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let fresh = header_value(&response, "ETag").expect("etag");
+        assert_ne!(fresh, etag);
+        assert_eq!(json_body(response).await["title"], "Changed");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_list_items_filters_by_tag() {
+        let (state, path) = setup_test_state().await;
+        let (_user_id, auth) = setup_user(&state).await;
+        for (title, tags) in [("Tagged", "Rust, web"), ("Plain", "")] {
+            let response = api_create_item(
+                State(state.clone()),
+                ApiUser(auth.0.clone()),
+                IdempotencyKey(None),
+                JsonBody(ItemForm {
+                    title: title.to_string(),
+                    description: None,
+                    tags: tags.to_string(),
+                    due_at: None,
+                }),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        let response = api_list_items(
+            State(state.clone()),
+            auth,
+            Query(ApiItemsQuery {
+                tag: Some("rust".to_string()),
+                ..Default::default()
+            }),
+        )
+        .await;
+        let items = json_body(response).await;
+        assert_eq!(items.as_array().map(Vec::len), Some(1));
+        assert_eq!(items[0]["title"], "Tagged");
+        assert_eq!(items[0]["tags"], json!(["rust", "web"]));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_list_items_pages_with_cursors() {
+        let (state, path) = setup_test_state().await;
+        let (_user_id, auth) = setup_user(&state).await;
+        let create = |title: String, tags: &str| {
+            api_create_item(
+                State(state.clone()),
+                ApiUser(auth.0.clone()),
+                IdempotencyKey(None),
+                JsonBody(ItemForm {
+                    title,
+                    description: None,
+                    tags: tags.to_string(),
+                    due_at: None,
+                }),
+            )
+        };
+        for n in 0..5 {
+            let tags = if n % 2 == 0 { "even" } else { "" };
+            let response = create(format!("Item {}", n), tags).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+        let page = |cursor: Option<String>, tag: Option<&str>| {
+            api_list_items(
+                State(state.clone()),
+                ApiUser(auth.0.clone()),
+                Query(ApiItemsQuery {
+                    tag: tag.map(str::to_string),
+                    cursor,
+                    limit: Some(2),
+                    ..Default::default()
+                }),
+            )
+        };
+
+        let mut titles = Vec::new();
+        let mut cursor = None;
+        loop {
+            let response = page(cursor, None).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = json_body(response).await;
+            let items = body["items"].as_array().expect("items").clone();
+            assert!(items.len() <= 2);
+            titles.extend(
+                items
+                    .iter()
+                    .map(|i| i["title"].as_str().unwrap().to_string()),
+            );
+            cursor = body["next_cursor"].as_str().map(str::to_string);
+            if cursor.is_none() {
+                break;
+            }
+            create("Late".to_string(), "").await;
+        }
+        assert_eq!(titles, ["Item 4", "Item 3", "Item 2", "Item 1", "Item 0"]);
+
+        let body = json_body(page(None, Some("even")).await).await;
+        assert_eq!(body["items"][0]["title"], "Item 4");
+        assert_eq!(body["items"][1]["title"], "Item 2");
+        let body = json_body(
+            page(
+                body["next_cursor"].as_str().map(str::to_string),
+                Some("even"),
+            )
+            .await,
+        )
+        .await;
+        assert_eq!(body["items"][0]["title"], "Item 0");
+        assert_eq!(body["items"][0]["tags"], json!(["even"]));
+        assert_eq!(body["next_cursor"], json!(null));
+
+        let response = page(Some("garbage".to_string()), None).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(json_body(response).await["error"]["code"], "invalid_cursor");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_list_items_since_returns_changes_and_deletions() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let mut ids = Vec::new();
+        for title in ["Untouched", "Edited", "Trashed"] {
+            let item = state
+                .db
+                .create_item(CreateItem {
+                    user_id,
+                    title: title.to_string(),
+                    description: None,
+                    due_at: None,
+                })
+                .await
+                .expect("create item");
+            ids.push(item.id);
+        }
+        let DbPool::Sqlite(pool) = &state.db.pool else {
+            panic!("expected a SQLite pool");
+        };
+        sqlx::query("UPDATE items SET updated_at = $1")
+            .bind(Utc::now() - chrono::Duration::hours(2))
+            .execute(pool)
+            .await
+            .expect("age items");
+        let cutoff = Utc::now() - chrono::Duration::hours(1);
+        state
+            .db
+            .update_item_with_tags(
+                ids[1],
+                user_id,
+                "Edited again",
+                None,
+                None,
+                vec!["sync".to_string()],
+            )
+            .await
+            .expect("update item");
+        assert!(state
+            .db
+            .delete_item(ids[2], user_id)
+            .await
+            .expect("trash item"));
+
+        let since = |since: String| {
+            api_list_items(
+                State(state.clone()),
+                ApiUser(auth.0.clone()),
+                Query(ApiItemsQuery {
+                    since: Some(since),
+                    ..Default::default()
+                }),
+            )
+        };
+        let response = since(cutoff.to_rfc3339()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!(body["items"].as_array().map(Vec::len), Some(1));
+        assert_eq!(body["items"][0]["id"], ids[1]);
+        assert_eq!(body["items"][0]["tags"], json!(["sync"]));
+        assert_eq!(body["deleted"].as_array().map(Vec::len), Some(1));
+        assert_eq!(body["deleted"][0]["id"], ids[2]);
+        assert!(body["deleted"][0]["deleted_at"].is_string());
+
+        let response = since("yesterday".to_string()).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(json_body(response).await["error"]["code"], "invalid_since");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_update_item_returns_updated_item() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let created = state
+            .db
+            .create_item(CreateItem {
+                user_id,
+                title: "Before".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+
+        let response = api_update_item(
+            State(state.clone()),
+            auth,
+            Path(created.id),
+            JsonBody(ItemForm {
+                title: "After".to_string(),
+                description: None,
+                tags: String::new(),
+                due_at: None,
+            }),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await["title"], "After");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_delete_item_returns_no_content() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let created = state
+            .db
+            .create_item(CreateItem {
+                user_id,
+                title: "Delete me".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+
+        let response = api_delete_item(
+            State(state.clone()),
+            ApiUser(auth.0.clone()),
+            Path(created.id),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let response = api_delete_item(State(state.clone()), auth, Path(created.id)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        cleanup_db(path);
+    }
+}
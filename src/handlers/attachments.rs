@@ -0,0 +1,333 @@
+use rustapi_core::ResponseBody;
+use rustapi_rs::prelude::*;
+use tera::Context;
+
+use crate::{
+    extractors::{CsrfProtected, CsrfToken, FlashMessage, MultipartForm, RequireAuth},
+    flash::{self, Flash, DATABASE_ERROR, ITEM_NOT_FOUND},
+    models::{sanitize_filename, CreateAttachment},
+    templates::render,
+    uploads::mime_essence,
+    AppState,
+};
+
+/// Form field the upload form sends the file under
+const FILE_FIELD: &str = "file";
+
+/// List an item's attachments, with the upload form
+#[rustapi_rs::get("/items/{id}/attachments")]
+pub async fn list_attachments(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    CsrfToken(csrf_token): CsrfToken,
+    FlashMessage(flash): FlashMessage,
+    Path(id): Path<i64>,
+) -> Response {
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+    if let Some(flash) = flash {
+        flash.insert_into(&mut context);
+    }
+
+    let item = match state.db.get_item(id, user.id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return flash::redirect(&state, "/items", Flash::error(ITEM_NOT_FOUND)),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return flash::redirect(&state, "/items", Flash::error(DATABASE_ERROR));
+        }
+    };
+
+    let attachments = match state.db.get_item_attachments(item.id).await {
+        Ok(attachments) => attachments,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", "Failed to load attachments");
+            vec![]
+        }
+    };
+
+    context.insert("item", &item);
+    context.insert("attachments", &attachments);
+    context.insert("max_upload_bytes", &state.config.uploads.max_bytes);
+    context.insert("allowed_types", &state.config.uploads.allowed_types);
+
+    render(&state, "items/attachments.html", Some(&user), context)
+}
+
+/// Upload a file and attach it to an item
+///
+/// The file is stored under a random name in the upload directory; its
+/// declared content type must be on the allowlist.
+#[rustapi_rs::post("/items/{id}/attachments")]
+pub async fn upload_attachment(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    Path(id): Path<i64>,
+    _csrf: CsrfProtected,
+    form: MultipartForm,
+) -> Response {
+    let page = format!("/items/{}/attachments", id);
+
+    match state.db.get_item(id, user.id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return flash::redirect(&state, "/items", Flash::error(ITEM_NOT_FOUND)),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return flash::redirect(&state, "/items", Flash::error(DATABASE_ERROR));
+        }
+    }
+
+    // Browsers send an empty part when no file was chosen
+    let Some(file) = form.file(FILE_FIELD).filter(|f| !f.bytes.is_empty()) else {
+        return flash::redirect(&state, &page, Flash::error("Choose a file to upload"));
+    };
+    if !state.config.uploads.allows(&file.content_type) {
+        return flash::redirect(
+            &state,
+            &page,
+            Flash::error("Files of this type cannot be attached"),
+        );
+    }
+
+    let stored_name = match state.config.uploads.store(&file.bytes).await {
+        Ok(name) => name,
+        Err(e) => {
+            tracing::error!(error = %e, "upload storage error");
+            return flash::redirect(&state, &page, Flash::error("Failed to store the file"));
+        }
+    };
+
+    let created = state
+        .db
+        .create_attachment(CreateAttachment {
+            item_id: id,
+            filename: sanitize_filename(&file.filename),
+            stored_name: stored_name.clone(),
+            content_type: mime_essence(&file.content_type),
+            size: file.bytes.len() as i64,
+        })
+        .await;
+
+    match created {
+        Ok(_) => {
+            state.metrics.record_item_operation("attach");
+            flash::redirect(&state, &page, Flash::success("File attached"))
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            if let Err(e) = state.config.uploads.remove(&stored_name).await {
+                tracing::error!(error = %e, "upload storage error");
+            }
+            flash::redirect(&state, &page, Flash::error(DATABASE_ERROR))
+        }
+    }
+}
+
+/// Download an attachment of one of the user's items
+///
+/// Always served as a download with `nosniff`, so an uploaded file is never
+/// rendered as a page on this origin.
+#[rustapi_rs::get("/attachments/{id}")]
+pub async fn download_attachment(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    Path(id): Path<i64>,
+) -> Response {
+    let attachment = match state.db.get_user_attachment(id, user.id).await {
+        Ok(Some(attachment)) => attachment,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Attachment not found").into_response(),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response();
+        }
+    };
+
+    let bytes = match state.config.uploads.read(&attachment.stored_name).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            tracing::error!(error = %e, "upload storage error");
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Attachment unavailable").into_response();
+        }
+    };
+
+    let mut response = Response::new(ResponseBody::from(bytes));
+    let headers = response.headers_mut();
+    if let Ok(value) = attachment.content_type.parse() {
+        headers.insert("Content-Type", value);
+    }
+    // Header values must be ASCII; `sanitize_filename` already removed quotes
+    let ascii_name: String = attachment
+        .filename
+        .chars()
+        .map(|c| if c.is_ascii() { c } else { '_' })
+        .collect();
+    if let Ok(value) = format!("attachment; filename=\"{}\"", ascii_name).parse() {
+        headers.insert("Content-Disposition", value);
+    }
+    if let Ok(value) = "nosniff".parse() {
+        headers.insert("X-Content-Type-Options", value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extractors::UploadedFile;
+    use crate::models::{CreateItem, UserInfo};
+    use crate::test_utils::{
+        body_string, cleanup_db, flash_from, header_value, setup_test_state, test_csrf,
+        test_csrf_token,
+    };
+
+    async fn setup_item(state: &AppState) -> (i64, UserInfo) {
+        let user = state
+            .db
+            .create_user("uploader", "uploader@example.com", "hash")
+            .await
+            .expect("create user");
+        let item = state
+            .db
+            .create_item(CreateItem {
+                user_id: user.id,
+                title: "With files".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        (item.id, UserInfo::from(user))
+    }
+
+    fn upload(filename: &str, content_type: &str, bytes: &'static [u8]) -> MultipartForm {
+        MultipartForm {
+            fields: vec![],
+            files: vec![UploadedFile {
+                field: FILE_FIELD.to_string(),
+                filename: filename.to_string(),
+                content_type: content_type.to_string(),
+                bytes: bytes::Bytes::from_static(bytes),
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn upload_stores_file_and_lists_it() {
+        let (state, path) = setup_test_state().await;
+        let (item_id, user) = setup_item(&state).await;
+
+        let response = upload_attachment(
+            State(state.clone()),
+            RequireAuth(user.clone()),
+            Path(item_id),
+            test_csrf(),
+            upload("../notes.txt", "text/plain; charset=utf-8", b"hello"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some(format!("/items/{}/attachments", item_id))
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("File attached"))
+        );
+
+        let attachments = state
+            .db
+            .get_item_attachments(item_id)
+            .await
+            .expect("list attachments");
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].filename, "notes.txt");
+        assert_eq!(attachments[0].content_type, "text/plain");
+        assert_eq!(attachments[0].size, 5);
+        assert_ne!(attachments[0].stored_name, "notes.txt");
+
+        let response = list_attachments(
+            State(state.clone()),
+            RequireAuth(user.clone()),
+            test_csrf_token(),
+            FlashMessage(None),
+            Path(item_id),
+        )
+        .await;
+        assert_eq!(body_string(response).await, "ITEM ATTACHMENTS 1");
+
+        let response = download_attachment(
+            State(state.clone()),
+            RequireAuth(user),
+            Path(attachments[0].id),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            header_value(&response, "Content-Disposition"),
+            Some("attachment; filename=\"notes.txt\"".to_string())
+        );
+        assert_eq!(body_string(response).await, "hello");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn upload_rejects_disallowed_content_type() {
+        let (state, path) = setup_test_state().await;
+        let (item_id, user) = setup_item(&state).await;
+
+        let response = upload_attachment(
+            State(state.clone()),
+            RequireAuth(user),
+            Path(item_id),
+            test_csrf(),
+            upload("page.html", "text/html", b"<script>alert(1)</script>"),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some(format!("/items/{}/attachments", item_id))
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error("Files of this type cannot be attached"))
+        );
+        assert!(state
+            .db
+            .get_item_attachments(item_id)
+            .await
+            .expect("list attachments")
+            .is_empty());
+        assert!(!state.config.uploads.dir.exists());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn upload_rejects_foreign_item() {
+        let (state, path) = setup_test_state().await;
+        let (item_id, _) = setup_item(&state).await;
+        let other = state
+            .db
+            .create_user("other", "other@example.com", "hash")
+            .await
+            .expect("create user");
+
+        let response = upload_attachment(
+            State(state.clone()),
+            RequireAuth(UserInfo::from(other)),
+            Path(item_id),
+            test_csrf(),
+            upload("notes.txt", "text/plain", b"hello"),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(ITEM_NOT_FOUND))
+        );
+        cleanup_db(path);
+    }
+}
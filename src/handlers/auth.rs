@@ -1,254 +1,917 @@
-use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
-    Argon2,
-};
-use jsonwebtoken::{encode, EncodingKey, Header};
+use chrono::{DateTime, Utc};
 use rustapi_rs::prelude::*;
-use rustapi_rs::ResponseBody;
+use std::net::IpAddr;
 use tera::Context;
 
 use crate::{
-    extractors::Form,
-    models::{Claims, LoginForm, RegisterForm, UserInfo},
+    db::UserConflict,
+    errors::ApiErrorResponse,
+    extractors::{
+        AppCookies, CsrfProtected, CsrfToken, Form, JsonBody, Query, RealIp, RequireAuth, UserAgent,
+    },
+    flash::{self, Flash},
+    handlers::api_items::json_error,
+    i18n::{INVALID_FORM, TRY_AGAIN},
+    models::{
+        normalize_email, ApiLoginForm, ApiToken, CreateItem, LoginForm, LoginPageQuery,
+        RegisterForm, RegistrationMode, ResendVerificationForm, Role, User, VerifyQuery,
+    },
+    passwords,
+    responses::{redirect_to, redirect_with_cookies, with_cookies},
+    strength,
+    templates::render,
+    tokens,
+    validation::{field_errors, FieldErrors},
     AppState,
 };
 
+/// Failed password attempts before an account is locked
+const LOCKOUT_THRESHOLD: i32 = 5;
+/// Lock duration for the first lockout; doubles with every further failure
+const LOCKOUT_BASE_SECS: i64 = 60;
+/// Upper bound for the lockout backoff
+const LOCKOUT_MAX_SECS: i64 = 60 * 60;
+/// Message key of the error for passwords found by `PwnedPasswords`
+const PASSWORD_PWNED: &str = "auth.password_pwned";
+/// Message key of the error for invite codes that are unknown, revoked or
+/// already used
+const INVITE_INVALID: &str = "auth.invite_invalid";
+/// Message key of the error for locked accounts
+const LOCKED_MESSAGE: &str = "auth.account_locked";
+
 /// Show login page
 #[rustapi_rs::get("/login")]
-pub async fn show_login(State(state): State<AppState>) -> Response {
+pub async fn show_login(
+    State(state): State<AppState>,
+    CsrfToken(csrf_token): CsrfToken,
+    Query(query): Query<LoginPageQuery>,
+) -> Response {
     let mut context = Context::new();
-    context.insert("user", &None::<UserInfo>);
+    context.insert("csrf_token", &csrf_token);
 
-    match state.tera.render("auth/login.html", &context) {
-        Ok(html) => Html(html).into_response(),
-        Err(e) => {
-            eprintln!("Template error: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, "Template error").into_response()
-        }
+    if query.registered == Some(true) {
+        context.insert(
+            "success",
+            "Account created. Check your email for a verification link.",
+        );
+    } else if query.verified == Some(true) {
+        context.insert("success", "Email verified. You can now log in.");
+    } else if query.resent == Some(true) {
+        context.insert(
+            "success",
+            "If that account is awaiting verification, a new link has been sent.",
+        );
+    } else if query.verify_failed == Some(true) {
+        context.insert("error", &state.messages.t("auth.verification_link_invalid"));
     }
+
+    render_login(&state, context)
 }
 
 /// Handle login form submission
+///
+/// Bodies are capped at `route_limits::AUTH_MAX_BODY_BYTES`.
 #[rustapi_rs::post("/login")]
-pub async fn handle_login(State(state): State<AppState>, Form(form): Form<LoginForm>) -> Response {
+pub async fn handle_login(
+    State(state): State<AppState>,
+    RealIp(client_ip): RealIp,
+    UserAgent(user_agent): UserAgent,
+    CsrfProtected(csrf_token): CsrfProtected,
+    Form(form): Form<LoginForm>,
+) -> Response {
     let mut context = Context::new();
-    context.insert("user", &None::<UserInfo>);
+    context.insert("csrf_token", &csrf_token);
     context.insert("username", &form.username);
 
+    // Refuse while the username is rate limited
+    if let Err(retry_after) = state.login_limiter.check(&form.username) {
+        state.metrics.record_login("throttled");
+        let minutes = retry_after.as_secs().div_ceil(60).max(1);
+        let key = if minutes == 1 {
+            "auth.login_throttled.one"
+        } else {
+            "auth.login_throttled.other"
+        };
+        context.insert(
+            "error",
+            &state
+                .messages
+                .t_with(key, &[("minutes", &minutes.to_string())]),
+        );
+        let mut response = render_login(&state, context);
+        *response.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+        return response;
+    }
+
     // Find user
     let user = match state.db.find_user_by_username(&form.username).await {
         Ok(Some(user)) => user,
         Ok(None) => {
-            context.insert("error", "Invalid username or password");
-            return render_login(&state.tera, &context);
+            state.config.passwords.verify_dummy(&form.password);
+            state.login_limiter.record_failure(&form.username);
+            tracing::warn!(username = %form.username, client_ip = %client_ip, "failed login");
+            state.login_throttle.fail(&form.username).await;
+            state.metrics.record_login("invalid_credentials");
+            context.insert("error", &state.messages.t("auth.invalid_credentials"));
+            return render_login(&state, context);
         }
         Err(e) => {
-            eprintln!("Database error: {}", e);
-            context.insert("error", "An error occurred. Please try again.");
-            return render_login(&state.tera, &context);
+            tracing::error!(error = %e, "database error");
+            context.insert("error", &state.messages.t(TRY_AGAIN));
+            return render_login(&state, context);
         }
     };
 
+    // Refuse locked accounts before looking at the password, so the response
+    // does not reveal whether it was correct
+    if user
+        .locked_until
+        .is_some_and(|until| until > chrono::Utc::now())
+    {
+        state.metrics.record_login("locked");
+        context.insert("error", &state.messages.t(LOCKED_MESSAGE));
+        return render_login(&state, context);
+    }
+
     // Verify password
-    let parsed_hash = match PasswordHash::new(&user.password_hash) {
-        Ok(hash) => hash,
-        Err(_) => {
-            context.insert("error", "An error occurred. Please try again.");
-            return render_login(&state.tera, &context);
-        }
-    };
+    if !passwords::is_known_format(&user.password_hash) {
+        context.insert("error", &state.messages.t(TRY_AGAIN));
+        return render_login(&state, context);
+    }
 
-    if Argon2::default()
-        .verify_password(form.password.as_bytes(), &parsed_hash)
-        .is_err()
+    if !state
+        .config
+        .passwords
+        .verify(&user.password_hash, &form.password)
     {
-        context.insert("error", "Invalid username or password");
-        return render_login(&state.tera, &context);
+        state.login_limiter.record_failure(&form.username);
+        tracing::warn!(username = %form.username, client_ip = %client_ip, "failed login");
+        let locked = register_failed_attempt(&state, &user).await;
+        state.login_throttle.fail(&form.username).await;
+        state.metrics.record_login(if locked {
+            "locked"
+        } else {
+            "invalid_credentials"
+        });
+        context.insert(
+            "error",
+            &state.messages.t(if locked {
+                LOCKED_MESSAGE
+            } else {
+                "auth.invalid_credentials"
+            }),
+        );
+        return render_login(&state, context);
     }
 
-    // Create JWT token
-    let now = chrono::Utc::now().timestamp();
-    let claims = Claims {
-        sub: user.id,
-        username: user.username.clone(),
-        exp: now + 86400, // 24 hours
-        iat: now,
-    };
+    clear_failed_attempts(&state, &form.username, &user).await;
+    upgrade_password_hash(&state, &user, &form.password).await;
 
-    let token = match encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(state.jwt_secret.as_bytes()),
-    ) {
-        Ok(t) => t,
+    // The password was right, but the email address must be confirmed first
+    if !user.verified {
+        state.metrics.record_login("unverified");
+        context.insert("error", &state.messages.t("auth.email_unverified"));
+        context.insert("unverified", &true);
+        return render_login(&state, context);
+    }
+
+    // Issue the access/refresh token pair and redirect
+    let session_expires_at = state.config.session.session_end();
+    match issue_session(&state, &user, form.remember, session_expires_at).await {
+        Some(cookies) => {
+            state.metrics.record_login("success");
+            record_login_event(&state, user.id, client_ip, user_agent.as_deref()).await;
+            redirect_with_cookies(&state, "/items", &cookies)
+        }
+        None => {
+            context.insert("error", &state.messages.t(TRY_AGAIN));
+            render_login(&state, context)
+        }
+    }
+}
+
+/// Exchange a username and password for a bearer access token
+///
+/// For SPA and mobile clients that cannot follow the cookie flow; they send
+/// the token back as `Authorization: Bearer <token>` until `expires_at`. The
+/// rate limit, lockout and body cap of `/login` apply here too.
+#[rustapi_rs::post("/api/login")]
+pub async fn api_login(
+    State(state): State<AppState>,
+    RealIp(client_ip): RealIp,
+    UserAgent(user_agent): UserAgent,
+    JsonBody(form): JsonBody<ApiLoginForm>,
+) -> Response {
+    if state.login_limiter.check(&form.username).is_err() {
+        state.metrics.record_login("throttled");
+        return json_error(StatusCode::TOO_MANY_REQUESTS, "too_many_attempts");
+    }
+
+    let user = match state.db.find_user_by_username(&form.username).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            state.config.passwords.verify_dummy(&form.password);
+            state.login_limiter.record_failure(&form.username);
+            tracing::warn!(username = %form.username, client_ip = %client_ip, "failed login");
+            state.login_throttle.fail(&form.username).await;
+            state.metrics.record_login("invalid_credentials");
+            return json_error(StatusCode::UNAUTHORIZED, "invalid_credentials");
+        }
         Err(e) => {
-            eprintln!("JWT error: {}", e);
-            context.insert("error", "An error occurred. Please try again.");
-            return render_login(&state.tera, &context);
+            tracing::error!(error = %e, "database error");
+            return ApiErrorResponse::from(&e).into_response();
         }
     };
 
-    // Set cookie and redirect
-    let cookie = format!(
-        "token={}; Path=/; HttpOnly; SameSite=Strict; Max-Age=86400",
-        token
-    );
+    if user
+        .locked_until
+        .is_some_and(|until| until > chrono::Utc::now())
+    {
+        state.metrics.record_login("locked");
+        return json_error(StatusCode::FORBIDDEN, "account_locked");
+    }
+
+    if !state
+        .config
+        .passwords
+        .verify(&user.password_hash, &form.password)
+    {
+        state.login_limiter.record_failure(&form.username);
+        tracing::warn!(username = %form.username, client_ip = %client_ip, "failed login");
+        if register_failed_attempt(&state, &user).await {
+            state.metrics.record_login("locked");
+            return json_error(StatusCode::FORBIDDEN, "account_locked");
+        }
+        state.metrics.record_login("invalid_credentials");
+        return json_error(StatusCode::UNAUTHORIZED, "invalid_credentials");
+    }
 
-    redirect_with_cookie("/items", &cookie)
+    clear_failed_attempts(&state, &form.username, &user).await;
+    upgrade_password_hash(&state, &user, &form.password).await;
+
+    if !user.verified {
+        state.metrics.record_login("unverified");
+        return json_error(StatusCode::FORBIDDEN, "email_unverified");
+    }
+
+    // Bearer tokens are not renewed; the login ends with the token
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(tokens::ACCESS_TOKEN_TTL_SECS);
+    match tokens::create_access_token(
+        &state.jwt_keys,
+        user.id,
+        &user.username,
+        user.token_version,
+        Role::parse(&user.role),
+        expires_at.timestamp(),
+    ) {
+        Ok(token) => {
+            state.metrics.record_login("success");
+            record_login_event(&state, user.id, client_ip, user_agent.as_deref()).await;
+            Json(ApiToken { token, expires_at }).into_response()
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "failed to sign access token");
+            json_error(StatusCode::INTERNAL_SERVER_ERROR, "token_error")
+        }
+    }
 }
 
 /// Show registration page
 #[rustapi_rs::get("/register")]
-pub async fn show_register(State(state): State<AppState>) -> Response {
+pub async fn show_register(
+    State(state): State<AppState>,
+    CsrfToken(csrf_token): CsrfToken,
+) -> Response {
     let mut context = Context::new();
-    context.insert("user", &None::<UserInfo>);
+    context.insert("csrf_token", &csrf_token);
 
-    match state.tera.render("auth/register.html", &context) {
-        Ok(html) => Html(html).into_response(),
+    match registration_mode(&state).await {
+        Ok(RegistrationMode::Closed) => registration_closed(&state, context),
+        Ok(mode) => {
+            context.insert("invite_required", &(mode == RegistrationMode::InviteOnly));
+            render_register(&state, context)
+        }
         Err(e) => {
-            eprintln!("Template error: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, "Template error").into_response()
+            tracing::error!(error = %e, "database error");
+            context.insert("error", &state.messages.t(TRY_AGAIN));
+            render_register(&state, context)
         }
     }
 }
 
 /// Handle registration form submission
+///
+/// Bodies are capped at `route_limits::AUTH_MAX_BODY_BYTES`.
 #[rustapi_rs::post("/register")]
 pub async fn handle_register(
     State(state): State<AppState>,
+    CsrfProtected(csrf_token): CsrfProtected,
     Form(form): Form<RegisterForm>,
 ) -> Response {
     let mut context = Context::new();
-    context.insert("user", &None::<UserInfo>);
+    context.insert("csrf_token", &csrf_token);
     context.insert("username", &form.username);
     context.insert("email", &form.email);
+    context.insert("invite_code", &form.invite_code);
+
+    let mode = match registration_mode(&state).await {
+        Ok(mode) => mode,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", &state.messages.t(TRY_AGAIN));
+            return render_register(&state, context);
+        }
+    };
+    if mode == RegistrationMode::Closed {
+        state.metrics.record_registration("rejected");
+        return registration_closed(&state, context);
+    }
+    context.insert("invite_required", &(mode == RegistrationMode::InviteOnly));
 
     // Validate form
     if form.username.len() < 3 {
-        context.insert("error", "Username must be at least 3 characters");
-        return render_register(&state.tera, &context);
+        state.metrics.record_registration("rejected");
+        context.insert("error", &state.messages.t("auth.username_too_short"));
+        return render_register(&state, context);
     }
 
-    if form.password.len() < 6 {
-        context.insert("error", "Password must be at least 6 characters");
-        return render_register(&state.tera, &context);
+    if form.password.chars().count() < strength::MIN_PASSWORD_LENGTH {
+        state.metrics.record_registration("rejected");
+        context.insert(
+            "error",
+            &state.messages.t_with(
+                "auth.password_too_short",
+                &[("min", &strength::MIN_PASSWORD_LENGTH.to_string())],
+            ),
+        );
+        return render_register(&state, context);
+    }
+
+    let estimate = strength::estimate(&form.password, &[&form.username, &form.email]);
+    if estimate.score < state.config.min_password_score {
+        state.metrics.record_registration("rejected");
+        let mut error = state.messages.t("auth.password_too_weak");
+        if let Some(feedback) = estimate.feedback {
+            error = format!("{} {}", error, feedback);
+        }
+        context.insert("error", &error);
+        return render_register(&state, context);
     }
 
     if form.password != form.confirm_password {
-        context.insert("error", "Passwords do not match");
-        return render_register(&state.tera, &context);
+        state.metrics.record_registration("rejected");
+        context.insert("error", &state.messages.t("auth.passwords_mismatch"));
+        return render_register(&state, context);
+    }
+
+    if let Err(validation_errors) = form.validate() {
+        state.metrics.record_registration("rejected");
+        context.insert("error", &state.messages.t(INVALID_FORM));
+        context.insert("errors", &field_errors(&validation_errors));
+        return render_register(&state, context);
+    }
+
+    if let Some(pwned) = &state.pwned_passwords {
+        if pwned.is_pwned(&form.password).await {
+            state.metrics.record_registration("rejected");
+            context.insert("error", &state.messages.t(PASSWORD_PWNED));
+            return render_register(&state, context);
+        }
     }
 
+    // The code is only redeemed along with the new account below, which
+    // fails if another registration used it in the meantime
+    let invite_hash = if mode == RegistrationMode::InviteOnly {
+        let code = form.invite_code.trim();
+        if code.is_empty() {
+            state.metrics.record_registration("rejected");
+            context.insert("error", &state.messages.t("auth.invite_required"));
+            return render_register(&state, context);
+        }
+        let code_hash = tokens::hash_token(code);
+        match state.db.invite_is_unused(&code_hash).await {
+            Ok(true) => Some(code_hash),
+            Ok(false) => {
+                state.metrics.record_registration("rejected");
+                context.insert("error", &state.messages.t(INVITE_INVALID));
+                return render_register(&state, context);
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "database error");
+                context.insert("error", &state.messages.t(TRY_AGAIN));
+                return render_register(&state, context);
+            }
+        }
+    } else {
+        None
+    };
+
     // Check if username exists
     match state.db.username_exists(&form.username).await {
-        Ok(true) => {
-            context.insert("error", "Username is already taken");
-            return render_register(&state.tera, &context);
-        }
+        Ok(true) => return account_taken(&state, context, UserConflict::Username),
         Err(e) => {
-            eprintln!("Database error: {}", e);
-            context.insert("error", "An error occurred. Please try again.");
-            return render_register(&state.tera, &context);
+            tracing::error!(error = %e, "database error");
+            context.insert("error", &state.messages.t(TRY_AGAIN));
+            return render_register(&state, context);
         }
         _ => {}
     }
 
     // Check if email exists
     match state.db.email_exists(&form.email).await {
-        Ok(true) => {
-            context.insert("error", "Email is already registered");
-            return render_register(&state.tera, &context);
-        }
+        Ok(true) => return account_taken(&state, context, UserConflict::Email),
         Err(e) => {
-            eprintln!("Database error: {}", e);
-            context.insert("error", "An error occurred. Please try again.");
-            return render_register(&state.tera, &context);
+            tracing::error!(error = %e, "database error");
+            context.insert("error", &state.messages.t(TRY_AGAIN));
+            return render_register(&state, context);
         }
         _ => {}
     }
 
     // Hash password
-    let salt = SaltString::generate(&mut OsRng);
-    let password_hash = match Argon2::default().hash_password(form.password.as_bytes(), &salt) {
-        Ok(hash) => hash.to_string(),
+    let password_hash = match state.config.passwords.hash(&form.password) {
+        Ok(hash) => hash,
         Err(e) => {
-            eprintln!("Password hash error: {}", e);
-            context.insert("error", "An error occurred. Please try again.");
-            return render_register(&state.tera, &context);
+            tracing::error!(error = %e, "password hash error");
+            context.insert("error", &state.messages.t(TRY_AGAIN));
+            return render_register(&state, context);
         }
     };
 
-    // Create user
-    if let Err(e) = state
+    // Create the user and their welcome item and use up the invite; nothing
+    // is kept if any of it fails
+    let (username, email) = (form.username.clone(), normalize_email(&form.email));
+    let invited = invite_hash.is_some();
+    let created = state
+        .db
+        .with_transaction(move |tx| {
+            Box::pin(async move {
+                let user = tx.create_user(&username, &email, &password_hash).await?;
+                tx.create_item(CreateItem::welcome(user.id)).await?;
+                if let Some(code_hash) = invite_hash {
+                    tx.redeem_invite(&code_hash, user.id).await?;
+                }
+                Ok(user)
+            })
+        })
+        .await;
+    let user = match created {
+        Ok(user) => user,
+        Err(sqlx::Error::RowNotFound) if invited => {
+            state.metrics.record_registration("rejected");
+            context.insert("error", &state.messages.t(INVITE_INVALID));
+            return render_register(&state, context);
+        }
+        // Someone else signed up with the name or address since the checks
+        // above
+        Err(e) => match UserConflict::of(&e) {
+            Some(conflict) => return account_taken(&state, context, conflict),
+            None => {
+                tracing::error!(error = %e, "database error");
+                context.insert("error", &state.messages.t(TRY_AGAIN));
+                return render_register(&state, context);
+            }
+        },
+    };
+
+    // The account is usable once the emailed link is followed; a failure here
+    // can be recovered through the resend form
+    send_verification_email(&state, &user).await;
+    state.metrics.record_registration("success");
+
+    // Redirect to login with success message
+    redirect_to(&state, "/login?registered=true")
+}
+
+/// Confirm an email address from a verification link
+#[rustapi_rs::get("/verify")]
+pub async fn verify_email(
+    State(state): State<AppState>,
+    Query(query): Query<VerifyQuery>,
+) -> Response {
+    let user_id = match state
+        .db
+        .consume_verification_token(&tokens::hash_token(&query.token))
+        .await
+    {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return redirect_to(&state, "/login?verify_failed=true"),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return redirect_to(&state, "/login?verify_failed=true");
+        }
+    };
+
+    match state.db.verify_user(user_id).await {
+        Ok(true) => redirect_to(&state, "/login?verified=true"),
+        Ok(false) => redirect_to(&state, "/login?verify_failed=true"),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            redirect_to(&state, "/login?verify_failed=true")
+        }
+    }
+}
+
+/// Send a fresh verification link to an unverified account
+///
+/// Always answers the same way, so it cannot be used to probe usernames.
+#[rustapi_rs::post("/verify/resend")]
+pub async fn resend_verification(
+    State(state): State<AppState>,
+    _csrf: CsrfProtected,
+    Form(form): Form<ResendVerificationForm>,
+) -> Response {
+    match state.db.find_user_by_username(&form.username).await {
+        Ok(Some(user)) if !user.verified => {
+            if let Err(e) = state.db.delete_verification_tokens(user.id).await {
+                tracing::error!(error = %e, "database error");
+            }
+            send_verification_email(&state, &user).await;
+        }
+        Ok(_) => {}
+        Err(e) => tracing::error!(error = %e, "database error"),
+    }
+
+    redirect_to(&state, "/login?resent=true")
+}
+
+/// Exchange the refresh cookie for a new access token
+///
+/// Refresh tokens are single use: each one is revoked and replaced by a new
+/// one. Presenting a token that was already rotated revokes every session of
+/// its user, since that means it was copied. Both cookies are `SameSite=Lax`
+/// unless `COOKIE_SAMESITE` says otherwise, so cross-site POSTs never carry them.
+#[rustapi_rs::post("/refresh")]
+pub async fn handle_refresh(
+    State(state): State<AppState>,
+    AppCookies(cookies): AppCookies,
+) -> Response {
+    let Some(token) = tokens::refresh_token_from_cookies(&cookies) else {
+        return session_expired(&state);
+    };
+
+    let stored = match state
         .db
-        .create_user(&form.username, &form.email, &password_hash)
+        .find_refresh_token(&tokens::hash_token(&token))
         .await
     {
-        eprintln!("Database error: {}", e);
-        context.insert("error", "An error occurred. Please try again.");
-        return render_register(&state.tera, &context);
+        Ok(Some(stored)) => stored,
+        Ok(None) => return session_expired(&state),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    if stored.revoked_at.is_some() {
+        return revoke_all_sessions(&state, stored.user_id).await;
+    }
+    if !stored.is_active() {
+        return session_expired(&state);
     }
 
-    // Redirect to login with success message
-    Redirect::to("/login?registered=true").into_response()
+    // Rotate: only the request that actually revokes the token may continue
+    match state.db.revoke_refresh_token(stored.id).await {
+        Ok(true) => {}
+        Ok(false) => return revoke_all_sessions(&state, stored.user_id).await,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    }
+
+    let user = match state.db.find_user_by_id(stored.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return session_expired(&state),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    // Rotation keeps the login's end; tokens from before it was recorded
+    // start counting now
+    let session_expires_at = stored
+        .session_expires_at
+        .unwrap_or_else(|| state.config.session.session_end());
+    match issue_session(&state, &user, stored.remember, session_expires_at).await {
+        Some(cookies) => with_cookies(StatusCode::NO_CONTENT.into_response(), &cookies),
+        None => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
 }
 
 /// Handle logout
 #[rustapi_rs::post("/logout")]
-pub async fn handle_logout() -> Response {
-    let cookie = "token=; Path=/; HttpOnly; SameSite=Strict; Max-Age=0";
-    redirect_with_cookie("/", cookie)
+pub async fn handle_logout(
+    State(state): State<AppState>,
+    _csrf: CsrfProtected,
+    AppCookies(cookies): AppCookies,
+) -> Response {
+    // Revoke the refresh token so it cannot outlive the session
+    if let Some(token) = tokens::refresh_token_from_cookies(&cookies) {
+        let hash = tokens::hash_token(&token);
+        if let Ok(Some(stored)) = state.db.find_refresh_token(&hash).await {
+            if let Err(e) = state.db.revoke_refresh_token(stored.id).await {
+                tracing::error!(error = %e, "database error");
+            }
+        }
+    }
+
+    redirect_with_cookies(
+        &state,
+        "/",
+        &tokens::clear_session_cookies(&state.config.cookies),
+    )
 }
 
-// Helper function to redirect with a Set-Cookie header
-fn redirect_with_cookie(location: &str, cookie: &str) -> Response {
-    let mut response = Response::new(ResponseBody::empty());
-    *response.status_mut() = StatusCode::SEE_OTHER;
+// Count a bad password and lock the account once the threshold is reached.
+// Returns whether the account is now locked.
+async fn register_failed_attempt(state: &AppState, user: &User) -> bool {
+    let attempts = match state.db.record_failed_attempt(user.id).await {
+        Ok(attempts) => attempts,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return false;
+        }
+    };
+
+    let Some(duration) = lockout_duration(attempts) else {
+        return false;
+    };
 
-    if let Ok(value) = location.parse() {
-        response.headers_mut().insert("Location", value);
+    let until = chrono::Utc::now() + duration;
+    if let Err(e) = state.db.lock_user_until(user.id, until).await {
+        tracing::error!(error = %e, "database error");
+        return false;
     }
 
-    if let Ok(value) = cookie.parse() {
-        response.headers_mut().insert("Set-Cookie", value);
+    true
+}
+
+// Lockout backoff for a failure count: none below the threshold, then
+// doubling from the base duration up to the maximum
+fn lockout_duration(attempts: i32) -> Option<chrono::Duration> {
+    if attempts < LOCKOUT_THRESHOLD {
+        return None;
     }
 
-    response
+    let doublings = (attempts - LOCKOUT_THRESHOLD).min(16) as u32;
+    let secs = LOCKOUT_BASE_SECS
+        .saturating_mul(1 << doublings)
+        .min(LOCKOUT_MAX_SECS);
+    Some(chrono::Duration::seconds(secs))
 }
 
-// Helper functions
-fn render_login(tera: &tera::Tera, context: &Context) -> Response {
-    match tera.render("auth/login.html", context) {
-        Ok(html) => Html(html).into_response(),
+/// Log out every session of the current user
+///
+/// Bumping the token version invalidates all outstanding access tokens, and
+/// revoking the refresh tokens stops them from being renewed.
+#[rustapi_rs::post("/logout-all")]
+pub async fn handle_logout_all(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    _csrf: CsrfProtected,
+) -> Response {
+    if let Err(e) = state.db.bump_token_version(user.id).await {
+        tracing::error!(error = %e, "database error");
+        return flash::redirect(&state, "/items", Flash::error(state.messages.t(TRY_AGAIN)));
+    }
+    if let Err(e) = state.db.revoke_user_refresh_tokens(user.id).await {
+        tracing::error!(error = %e, "database error");
+    }
+
+    redirect_with_cookies(
+        &state,
+        "/",
+        &tokens::clear_session_cookies(&state.config.cookies),
+    )
+}
+
+// Store a verification token and deliver its link. No mail transport is
+// configured in this template, so the link is written to the server log.
+async fn send_verification_email(state: &AppState, user: &User) -> Option<String> {
+    let token = tokens::generate_token();
+    let expires_at =
+        chrono::Utc::now() + chrono::Duration::seconds(tokens::VERIFICATION_TOKEN_TTL_SECS);
+
+    if let Err(e) = state
+        .db
+        .create_verification_token(user.id, &tokens::hash_token(&token), expires_at)
+        .await
+    {
+        tracing::error!(error = %e, "database error");
+        return None;
+    }
+
+    tracing::info!(
+        email = %user.email,
+        "verification link: {}",
+        state.config.base_path.url(&format!("/verify?token={}", token))
+    );
+    Some(token)
+}
+
+// A correct password ends the rate-limit window and any failed-attempt streak
+async fn clear_failed_attempts(state: &AppState, username: &str, user: &User) {
+    state.login_limiter.reset(username);
+    state.login_throttle.reset(username);
+    if user.failed_attempts > 0 || user.locked_until.is_some() {
+        if let Err(e) = state.db.reset_failed_attempts(user.id).await {
+            tracing::error!(error = %e, "database error");
+        }
+    }
+}
+
+// Once the password is known to be right, replace a bcrypt hash or one made
+// with weaker Argon2 settings; on failure the old hash simply stays in use
+async fn upgrade_password_hash(state: &AppState, user: &User, password: &str) {
+    if !state.config.passwords.needs_rehash(&user.password_hash) {
+        return;
+    }
+    let hash = match state.config.passwords.hash(password) {
+        Ok(hash) => hash,
         Err(e) => {
-            eprintln!("Template error: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, "Template error").into_response()
+            tracing::error!(error = %e, "password hash error");
+            return;
         }
+    };
+    match state.db.update_user_password(user.id, &hash).await {
+        Ok(()) => tracing::info!(user_id = user.id, "upgraded password hash"),
+        Err(e) => tracing::error!(error = %e, "database error"),
+    }
+}
+
+// Add a successful login to the user's history, pruning events past the
+// retention; a failure is only logged and never fails the login itself
+pub(crate) async fn record_login_event(
+    state: &AppState,
+    user_id: i64,
+    client_ip: IpAddr,
+    user_agent: Option<&str>,
+) {
+    let keep_since = Utc::now() - chrono::Duration::days(state.config.login_history_retention_days);
+    if let Err(e) = state
+        .db
+        .record_login(user_id, &client_ip.to_string(), user_agent, keep_since)
+        .await
+    {
+        tracing::error!(error = %e, "database error");
     }
 }
 
-fn render_register(tera: &tera::Tera, context: &Context) -> Response {
-    match tera.render("auth/register.html", context) {
-        Ok(html) => Html(html).into_response(),
+// Create an access token and a stored refresh token, returned as Set-Cookie values.
+// The refresh cookie outlives the browser session only when `remember` is set,
+// and then expires together with the stored token. Neither outlives the login,
+// which ends at `session_expires_at`.
+pub(crate) async fn issue_session(
+    state: &AppState,
+    user: &User,
+    remember: bool,
+    session_expires_at: DateTime<Utc>,
+) -> Option<[String; 2]> {
+    let access_token = match tokens::create_access_token(
+        &state.jwt_keys,
+        user.id,
+        &user.username,
+        user.token_version,
+        Role::parse(&user.role),
+        session_expires_at.timestamp(),
+    ) {
+        Ok(token) => token,
         Err(e) => {
-            eprintln!("Template error: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, "Template error").into_response()
+            tracing::error!(error = %e, "failed to sign access token");
+            return None;
         }
+    };
+
+    let refresh_token = tokens::generate_token();
+    let now = Utc::now();
+    let ttl_secs = state
+        .config
+        .session
+        .refresh_ttl_secs(remember)
+        .min((session_expires_at - now).num_seconds());
+    let expires_at = now + chrono::Duration::seconds(ttl_secs);
+    if let Err(e) = state
+        .db
+        .create_refresh_token(
+            user.id,
+            &tokens::hash_token(&refresh_token),
+            expires_at,
+            session_expires_at,
+            remember,
+        )
+        .await
+    {
+        tracing::error!(error = %e, "database error");
+        return None;
+    }
+
+    Some([
+        tokens::access_cookie(&state.config.cookies, &access_token),
+        tokens::refresh_cookie(
+            &state.config.cookies,
+            &refresh_token,
+            remember.then_some(ttl_secs),
+        ),
+    ])
+}
+
+// Refresh token reuse: end every session of the user
+async fn revoke_all_sessions(state: &AppState, user_id: i64) -> Response {
+    if let Err(e) = state.db.revoke_user_refresh_tokens(user_id).await {
+        tracing::error!(error = %e, "database error");
+    }
+    session_expired(state)
+}
+
+// 401 that also clears the session cookies
+fn session_expired(state: &AppState) -> Response {
+    with_cookies(
+        StatusCode::UNAUTHORIZED.into_response(),
+        &tokens::clear_session_cookies(&state.config.cookies),
+    )
+}
+
+// Helper functions
+fn render_login(state: &AppState, context: Context) -> Response {
+    render(state, "auth/login.html", None, context)
+}
+
+fn render_register(state: &AppState, context: Context) -> Response {
+    render(state, "auth/register.html", None, context)
+}
+
+/// The registration mode in effect: the configured one once an account
+/// exists, but open until then so the first account, the administrator, can
+/// be created
+async fn registration_mode(state: &AppState) -> Result<RegistrationMode, sqlx::Error> {
+    if state.config.registration == RegistrationMode::Open || state.db.has_users().await? {
+        return Ok(state.config.registration);
     }
+    Ok(RegistrationMode::Open)
+}
+
+// The registration form again, with the conflict on its field
+fn account_taken(state: &AppState, mut context: Context, conflict: UserConflict) -> Response {
+    state.metrics.record_registration("rejected");
+    let errors = FieldErrors::from([(
+        conflict.field().to_string(),
+        vec![state.messages.t(conflict.message_key())],
+    )]);
+    context.insert("error", &state.messages.t(INVALID_FORM));
+    context.insert("errors", &errors);
+    render_register(state, context)
+}
+
+fn registration_closed(state: &AppState, mut context: Context) -> Response {
+    context.insert("closed", &true);
+    let mut response = render_register(state, context);
+    *response.status_mut() = StatusCode::FORBIDDEN;
+    response
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test_utils::{cleanup_db, header_value, setup_test_state};
+    use crate::extractors::AccessToken;
+    use crate::middleware::get_current_user;
+    use crate::test_utils::{
+        access_token_for_user, body_string, cleanup_db, cookies_from, empty_cookies, header_value,
+        request_with_state, set_cookie_value, setup_test_state, test_client_ip, test_csrf,
+        test_csrf_token,
+    };
     use crate::{
+        base_path::BasePath,
+        cookies::{CookieConfig, SameSite},
+        db::DbPool,
         extractors::Form,
-        models::{LoginForm, RegisterForm},
+        models::{LoginForm, RegisterForm, UserInfo},
+        passwords::PasswordConfig,
+        rate_limit::{LoginRateLimiter, LoginThrottle},
     };
     use argon2::{
         password_hash::{rand_core::OsRng, PasswordHasher, SaltString},
         Argon2,
     };
+    use rustapi_rs::FromRequest;
+    use std::{sync::Arc, time::Duration};
+
+    // Users created directly in the database skip the emailed link
+    async fn create_verified_user(
+        state: &AppState,
+        username: &str,
+        email: &str,
+        password_hash: &str,
+    ) -> User {
+        let user = state
+            .db
+            .create_user(username, email, password_hash)
+            .await
+            .expect("create user");
+        state.db.verify_user(user.id).await.expect("verify user");
+        user
+    }
 
     fn hash_password(password: &str) -> String {
         let salt = SaltString::generate(&mut OsRng);
@@ -261,7 +924,12 @@ mod tests {
     #[tokio::test]
     async fn show_login_returns_ok() {
         let (state, path) = setup_test_state().await;
-        let response = show_login(State(state.clone())).await;
+        let response = show_login(
+            State(state.clone()),
+            test_csrf_token(),
+            Query(LoginPageQuery::default()),
+        )
+        .await;
         assert_eq!(response.status(), StatusCode::OK);
         cleanup_db(path);
     }
@@ -269,7 +937,7 @@ mod tests {
     #[tokio::test]
     async fn show_register_returns_ok() {
         let (state, path) = setup_test_state().await;
-        let response = show_register(State(state.clone())).await;
+        let response = show_register(State(state.clone()), test_csrf_token()).await;
         assert_eq!(response.status(), StatusCode::OK);
         cleanup_db(path);
     }
@@ -282,9 +950,10 @@ mod tests {
             email: "bad@example.com".to_string(),
             password: "short".to_string(),
             confirm_password: "mismatch".to_string(),
+            invite_code: String::new(),
         };
 
-        let response = handle_register(State(state.clone()), Form(form)).await;
+        let response = handle_register(State(state.clone()), test_csrf(), Form(form)).await;
         assert_eq!(response.status(), StatusCode::OK);
 
         let exists = state
@@ -297,69 +966,421 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn handle_register_success_redirects() {
+    async fn handle_register_rejects_malformed_email() {
         let (state, path) = setup_test_state().await;
         let form = RegisterForm {
-            username: "alice".to_string(),
-            email: "alice@example.com".to_string(),
-            password: "password123".to_string(),
-            confirm_password: "password123".to_string(),
+            username: "mallory".to_string(),
+            email: "not-an-email".to_string(),
+            password: "plum tree lantern".to_string(),
+            confirm_password: "plum tree lantern".to_string(),
+            invite_code: String::new(),
         };
 
-        let response = handle_register(State(state.clone()), Form(form)).await;
-        assert_eq!(response.status(), StatusCode::FOUND);
-        assert_eq!(
-            header_value(&response, "Location"),
-            Some("/login?registered=true".to_string())
-        );
-
-        let exists = state
+        let response = handle_register(State(state.clone()), test_csrf(), Form(form)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_string(response)
+            .await
+            .contains("errors.email=Enter a valid email address"));
+        assert!(state
             .db
-            .username_exists("alice")
+            .list_all_users()
             .await
-            .expect("username exists");
-        assert!(exists);
+            .expect("list users")
+            .is_empty());
         cleanup_db(path);
     }
 
     #[tokio::test]
-    async fn handle_login_invalid_password_renders_form() {
-        let (state, path) = setup_test_state().await;
-        let hash = hash_password("correct-password");
-        state
-            .db
-            .create_user("bob", "bob@example.com", &hash)
+    async fn handle_register_rejects_easily_guessed_passwords() {
+        let (mut state, path) = setup_test_state().await;
+        let register = |state: &AppState, password: &str| {
+            handle_register(
+                State(state.clone()),
+                test_csrf(),
+                Form(RegisterForm {
+                    username: "walter".to_string(),
+                    email: "walter@example.com".to_string(),
+                    password: password.to_string(),
+                    confirm_password: password.to_string(),
+                    invite_code: String::new(),
+                }),
+            )
+        };
+
+        let response = register(&state, "sunshine1").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_string(response)
             .await
-            .expect("create user");
+            .contains("Password is too easy to guess. This is a very common password."));
+        let response = register(&state, "Walter123").await;
+        assert!(body_string(response)
+            .await
+            .contains("Avoid using your username or email address"));
+        assert!(!state.db.username_exists("walter").await.expect("exists"));
 
-        let response = handle_login(
+        // The required score is configurable; the length floor is not
+        Arc::make_mut(&mut state.config).min_password_score = 0;
+        let response = register(&state, "abc").await;
+        assert!(body_string(response)
+            .await
+            .contains("Password must be at least 6 characters"));
+        let response = register(&state, "sunshine1").await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_register_rejects_breached_passwords() {
+        use crate::pwned::PwnedPasswords;
+        use crate::test_utils::FakeRangeSource;
+        use sha1::{Digest, Sha1};
+
+        let (mut state, path) = setup_test_state().await;
+        let digest = hex::encode_upper(Sha1::digest(b"plum tree lantern"));
+        let range = format!("{}:42\r\n", &digest[5..]);
+        state.pwned_passwords = Some(Arc::new(PwnedPasswords::new(FakeRangeSource::answering(
+            &range,
+        ))));
+        let response = handle_register(
             State(state.clone()),
-            Form(LoginForm {
-                username: "bob".to_string(),
-                password: "wrong".to_string(),
-            }),
+            test_csrf(),
+            register_with_invite("alice", ""),
         )
         .await;
-
         assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_string(response)
+            .await
+            .contains("This password has appeared in a data breach"));
+        assert!(!state.db.username_exists("alice").await.expect("exists"));
+
+        // An unreachable API lets the signup through
+        state.pwned_passwords = Some(Arc::new(PwnedPasswords::new(FakeRangeSource::failing())));
+        let response = handle_register(
+            State(state.clone()),
+            test_csrf(),
+            register_with_invite("alice", ""),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
         cleanup_db(path);
     }
 
+    #[test]
+    fn register_form_accepts_valid_email() {
+        let form = RegisterForm {
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            password: "plum tree lantern".to_string(),
+            confirm_password: "plum tree lantern".to_string(),
+            invite_code: String::new(),
+        };
+        assert!(form.validate().is_ok());
+    }
+
     #[tokio::test]
-    async fn handle_login_sets_cookie_and_redirects() {
+    async fn handle_register_success_redirects() {
         let (state, path) = setup_test_state().await;
-        let hash = hash_password("secret");
+        let form = RegisterForm {
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            password: "plum tree lantern".to_string(),
+            confirm_password: "plum tree lantern".to_string(),
+            invite_code: String::new(),
+        };
+
+        let response = handle_register(State(state.clone()), test_csrf(), Form(form)).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/login?registered=true".to_string())
+        );
+
+        let exists = state
+            .db
+            .username_exists("alice")
+            .await
+            .expect("username exists");
+        assert!(exists);
+        cleanup_db(path);
+    }
+
+    fn register_with_invite(username: &str, invite_code: &str) -> Form<RegisterForm> {
+        Form(RegisterForm {
+            username: username.to_string(),
+            email: format!("{}@example.com", username),
+            password: "plum tree lantern".to_string(),
+            confirm_password: "plum tree lantern".to_string(),
+            invite_code: invite_code.to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn closed_registration_still_lets_the_first_account_in() {
+        let (mut state, path) = setup_test_state().await;
+        Arc::make_mut(&mut state.config).registration = RegistrationMode::Closed;
+
+        // With nobody to invite anyone, the administrator can still sign up
+        let response = show_register(State(state.clone()), test_csrf_token()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = handle_register(
+            State(state.clone()),
+            test_csrf(),
+            register_with_invite("founder", ""),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+
+        let response = show_register(State(state.clone()), test_csrf_token()).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(body_string(response).await.contains("closed=true"));
+        let response = handle_register(
+            State(state.clone()),
+            test_csrf(),
+            register_with_invite("latecomer", ""),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(!state
+            .db
+            .username_exists("latecomer")
+            .await
+            .expect("username exists"));
+
+        // Existing accounts log in as before
+        create_verified_user(
+            &state,
+            "carol",
+            "carol@example.com",
+            &hash_password("secret"),
+        )
+        .await;
+        let response = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("carol", "secret"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn invite_only_registration_takes_each_code_once() {
+        let (mut state, path) = setup_test_state().await;
+        Arc::make_mut(&mut state.config).registration = RegistrationMode::InviteOnly;
+        let admin = state
+            .db
+            .create_user("admin", "admin@example.com", "hash")
+            .await
+            .expect("create admin");
         state
             .db
-            .create_user("carol", "carol@example.com", &hash)
+            .create_invite(&tokens::hash_token("welcome-code"), admin.id)
             .await
-            .expect("create user");
+            .expect("create invite");
+
+        let response = show_register(State(state.clone()), test_csrf_token()).await;
+        assert!(body_string(response).await.contains("invite_required=true"));
+
+        for (code, error) in [
+            ("", "An invite code is required to register"),
+            (
+                "wrong-code",
+                "This invite code is invalid or has already been used",
+            ),
+        ] {
+            let response = handle_register(
+                State(state.clone()),
+                test_csrf(),
+                register_with_invite("alice", code),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert!(body_string(response).await.contains(error));
+        }
+
+        let response = handle_register(
+            State(state.clone()),
+            test_csrf(),
+            register_with_invite("alice", " welcome-code "),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let alice = state
+            .db
+            .find_user_by_username("alice")
+            .await
+            .expect("find")
+            .expect("user exists");
+        let invites = state.db.list_invites().await.expect("list invites");
+        assert_eq!(invites[0].used_by, Some(alice.id));
+
+        let response = handle_register(
+            State(state.clone()),
+            test_csrf(),
+            register_with_invite("bob", "welcome-code"),
+        )
+        .await;
+        assert!(body_string(response)
+            .await
+            .contains("This invite code is invalid or has already been used"));
+        assert!(!state
+            .db
+            .username_exists("bob")
+            .await
+            .expect("username exists"));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_register_ignores_case_when_checking_duplicates() {
+        let (state, path) = setup_test_state().await;
+        let register = |username: &str, email: &str| {
+            Form(RegisterForm {
+                username: username.to_string(),
+                email: email.to_string(),
+                password: "plum tree lantern".to_string(),
+                confirm_password: "plum tree lantern".to_string(),
+                invite_code: String::new(),
+            })
+        };
+
+        let response = handle_register(
+            State(state.clone()),
+            test_csrf(),
+            register("alice", "Alice@Example.com"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let stored = state
+            .db
+            .find_user_by_username("alice")
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert_eq!(stored.email, "alice@example.com");
+
+        let response = handle_register(
+            State(state.clone()),
+            test_csrf(),
+            register("Alice", "other@example.com"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_string(response)
+            .await
+            .contains("Username is already taken"));
+
+        let response = handle_register(
+            State(state.clone()),
+            test_csrf(),
+            register("bob", "ALICE@example.com"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_string(response)
+            .await
+            .contains("Email is already registered"));
+
+        assert_eq!(state.db.list_all_users().await.expect("list").len(), 1);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_register_reports_a_lost_race_as_the_field_taken() {
+        let (state, path) = setup_test_state().await;
+        // Another registration claims the name or address between the
+        // handler's checks and its insert
+        let DbPool::Sqlite(pool) = &state.db.pool else {
+            unreachable!("tests run on SQLite")
+        };
+        sqlx::query(
+            "CREATE TRIGGER steal_username BEFORE INSERT ON users \
+             WHEN NEW.username = 'racer' BEGIN \
+             INSERT INTO users (username, email, password_hash) \
+             VALUES (NEW.username, 'first-' || NEW.email, 'hash'); END",
+        )
+        .execute(pool)
+        .await
+        .expect("create trigger");
+        sqlx::query(
+            "CREATE TRIGGER steal_email BEFORE INSERT ON users \
+             WHEN NEW.email = 'race@example.com' BEGIN \
+             INSERT INTO users (username, email, password_hash) \
+             VALUES ('first-' || NEW.username, NEW.email, 'hash'); END",
+        )
+        .execute(pool)
+        .await
+        .expect("create trigger");
+        let register = |username: &str, email: &str| {
+            handle_register(
+                State(state.clone()),
+                test_csrf(),
+                Form(RegisterForm {
+                    username: username.to_string(),
+                    email: email.to_string(),
+                    password: "plum tree lantern".to_string(),
+                    confirm_password: "plum tree lantern".to_string(),
+                    invite_code: String::new(),
+                }),
+            )
+        };
+
+        let response = register("racer", "racer@example.com").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_string(response)
+            .await
+            .contains("errors.username=Username is already taken"));
+
+        let response = register("slowpoke", "race@example.com").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_string(response)
+            .await
+            .contains("errors.email=Email is already registered"));
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_login_invalid_password_renders_form() {
+        let (state, path) = setup_test_state().await;
+        let hash = hash_password("correct-password");
+        create_verified_user(&state, "bob", "bob@example.com", &hash).await;
+
+        let response = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            Form(LoginForm {
+                username: "bob".to_string(),
+                password: "wrong".to_string(),
+                remember: false,
+            }),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_login_sets_cookie_and_redirects() {
+        let (state, path) = setup_test_state().await;
+        let hash = hash_password("secret");
+        create_verified_user(&state, "carol", "carol@example.com", &hash).await;
 
         let response = handle_login(
             State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
             Form(LoginForm {
                 username: "carol".to_string(),
                 password: "secret".to_string(),
+                remember: false,
             }),
         )
         .await;
@@ -371,17 +1392,1078 @@ mod tests {
         );
         let set_cookie = header_value(&response, "Set-Cookie").unwrap_or_default();
         assert!(set_cookie.contains("token="));
+        assert!(set_cookie.contains(&format!("Max-Age={}", tokens::ACCESS_TOKEN_TTL_SECS)));
+        assert!(set_cookie.contains("SameSite=Lax; Secure"));
+        assert!(set_cookie_value(&response, tokens::REFRESH_COOKIE).is_some());
         cleanup_db(path);
     }
 
     #[tokio::test]
-    async fn handle_logout_clears_cookie() {
-        let (_state, path) = setup_test_state().await;
-        let response = handle_logout().await;
+    async fn handle_login_stays_under_the_base_path() {
+        let (mut state, path) = setup_test_state().await;
+        let config = Arc::make_mut(&mut state.config);
+        config.base_path = BasePath::parse("/app").expect("base path");
+        config.cookies = config.cookies.clone().with_path("/app");
+        let hash = hash_password("secret");
+        create_verified_user(&state, "cleo", "cleo@example.com", &hash).await;
+
+        let response = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            Form(LoginForm {
+                username: "cleo".to_string(),
+                password: "secret".to_string(),
+                remember: false,
+            }),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/app/items".to_string())
+        );
+        let set_cookie = header_value(&response, "Set-Cookie").unwrap_or_default();
+        assert!(set_cookie.contains("; Path=/app;"), "{}", set_cookie);
+
+        let response = handle_logout(State(state.clone()), test_csrf(), empty_cookies()).await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/app/".to_string())
+        );
+        let set_cookie = header_value(&response, "Set-Cookie").unwrap_or_default();
+        assert!(
+            set_cookie.starts_with("token=; Path=/app;"),
+            "{}",
+            set_cookie
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn successful_logins_are_recorded() {
+        let (state, path) = setup_test_state().await;
+        let hash = hash_password("secret");
+        let user = create_verified_user(&state, "dora", "dora@example.com", &hash).await;
+        assert!(user.last_login_at.is_none());
+
+        // Failed attempts leave no trace in the history
+        handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(Some("Firefox".to_string())),
+            test_csrf(),
+            login_form("dora", "wrong"),
+        )
+        .await;
+        assert!(state
+            .db
+            .recent_logins(user.id, 10)
+            .await
+            .expect("logins")
+            .is_empty());
+
+        let response = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(Some("Firefox".to_string())),
+            test_csrf(),
+            login_form("dora", "secret"),
+        )
+        .await;
         assert_eq!(response.status(), StatusCode::SEE_OTHER);
-        assert_eq!(header_value(&response, "Location"), Some("/".to_string()));
+
+        let logins = state.db.recent_logins(user.id, 10).await.expect("logins");
+        assert_eq!(logins.len(), 1);
+        assert_eq!(logins[0].ip_address, "127.0.0.1");
+        assert_eq!(logins[0].user_agent.as_deref(), Some("Firefox"));
+        let user = state
+            .db
+            .find_user_by_id(user.id)
+            .await
+            .expect("find user")
+            .expect("user exists");
+        assert!(user.last_login_at.is_some());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn session_cookies_follow_cookie_config() {
+        let (mut state, path) = setup_test_state().await;
+        Arc::make_mut(&mut state.config).cookies = CookieConfig::new(false, SameSite::Strict);
+        let hash = hash_password("secret");
+        create_verified_user(&state, "erin", "erin@example.com", &hash).await;
+
+        let response = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("erin", "secret"),
+        )
+        .await;
+        let login_cookies: Vec<_> = response.headers().get_all("Set-Cookie").iter().collect();
+        assert_eq!(login_cookies.len(), 2);
+        for cookie in login_cookies {
+            let cookie = cookie.to_str().expect("ascii cookie");
+            assert!(cookie.contains("SameSite=Strict"), "{}", cookie);
+            assert!(!cookie.contains("Secure"), "{}", cookie);
+        }
+
+        let response = handle_logout(State(state.clone()), test_csrf(), empty_cookies()).await;
         let set_cookie = header_value(&response, "Set-Cookie").unwrap_or_default();
-        assert!(set_cookie.contains("Max-Age=0"));
+        assert_eq!(
+            set_cookie,
+            "token=; Path=/; HttpOnly; SameSite=Strict; Max-Age=0"
+        );
+        cleanup_db(path);
+    }
+
+    fn login_form(username: &str, password: &str) -> Form<LoginForm> {
+        Form(LoginForm {
+            username: username.to_string(),
+            password: password.to_string(),
+            remember: false,
+        })
+    }
+
+    #[tokio::test]
+    async fn handle_login_accepts_and_upgrades_bcrypt_hashes() {
+        let (state, path) = setup_test_state().await;
+        let legacy = bcrypt::hash("secret", 4).expect("hash password");
+        let user = create_verified_user(&state, "brad", "brad@example.com", &legacy).await;
+
+        let response = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("brad", "secret"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let stored = state
+            .db
+            .find_user_by_id(user.id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert!(stored.password_hash.starts_with("$argon2id$"));
+        assert!(!state.config.passwords.needs_rehash(&stored.password_hash));
+        assert!(state
+            .config
+            .passwords
+            .verify(&stored.password_hash, "secret"));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_login_upgrades_weak_password_hashes() {
+        let (state, path) = setup_test_state().await;
+        let weak = PasswordConfig::new(1024, 1, 1)
+            .expect("valid params")
+            .hash("secret")
+            .expect("hash password");
+        let user = create_verified_user(&state, "wendy", "wendy@example.com", &weak).await;
+        assert!(state.config.passwords.needs_rehash(&weak));
+
+        let response = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("wendy", "secret"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let stored = state
+            .db
+            .find_user_by_id(user.id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert_ne!(stored.password_hash, weak);
+        assert!(!state.config.passwords.needs_rehash(&stored.password_hash));
+        assert!(state
+            .config
+            .passwords
+            .verify(&stored.password_hash, "secret"));
+
+        // The upgraded hash keeps working, and is not rewritten again
+        let response = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("wendy", "secret"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let again = state
+            .db
+            .find_user_by_id(user.id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert_eq!(again.password_hash, stored.password_hash);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_login_keeps_hash_after_wrong_password() {
+        let (state, path) = setup_test_state().await;
+        let weak = PasswordConfig::new(1024, 1, 1)
+            .expect("valid params")
+            .hash("secret")
+            .expect("hash password");
+        let user = create_verified_user(&state, "xena", "xena@example.com", &weak).await;
+
+        handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("xena", "wrong"),
+        )
+        .await;
+        let stored = state
+            .db
+            .find_user_by_id(user.id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert_eq!(stored.password_hash, weak);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_login_ignores_username_case() {
+        let (state, path) = setup_test_state().await;
+        let hash = hash_password("secret");
+        create_verified_user(&state, "Dana", "dana@example.com", &hash).await;
+
+        let response = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("dANA", "secret"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items".to_string())
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_login_counts_attempts_by_outcome() {
+        let (state, path) = setup_test_state().await;
+        let hash = hash_password("secret");
+        create_verified_user(&state, "mia", "mia@example.com", &hash).await;
+
+        handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("mia", "nope"),
+        )
+        .await;
+        handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("mia", "secret"),
+        )
+        .await;
+
+        let text = state.metrics.encode();
+        assert!(text.contains(r#"login_attempts_total{outcome="invalid_credentials"} 1"#));
+        assert!(text.contains(r#"login_attempts_total{outcome="success"} 1"#));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_login_rate_limits_repeated_failures() {
+        let (state, path) = setup_test_state().await;
+        let hash = hash_password("correct-password");
+        create_verified_user(&state, "dave", "dave@example.com", &hash).await;
+
+        for _ in 0..5 {
+            let response = handle_login(
+                State(state.clone()),
+                test_client_ip(),
+                UserAgent(None),
+                test_csrf(),
+                login_form("dave", "wrong"),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        // Even the correct password is refused while limited
+        let response = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("dave", "correct-password"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(header_value(&response, "Set-Cookie").is_none());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_login_success_resets_rate_limit() {
+        let (state, path) = setup_test_state().await;
+        let hash = hash_password("secret");
+        create_verified_user(&state, "erin", "erin@example.com", &hash).await;
+
+        for _ in 0..4 {
+            handle_login(
+                State(state.clone()),
+                test_client_ip(),
+                UserAgent(None),
+                test_csrf(),
+                login_form("erin", "wrong"),
+            )
+            .await;
+        }
+        let response = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("erin", "secret"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        // Counter starts over, so four more failures are still allowed
+        for _ in 0..4 {
+            let response = handle_login(
+                State(state.clone()),
+                test_client_ip(),
+                UserAgent(None),
+                test_csrf(),
+                login_form("erin", "wrong"),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        assert!(state.login_limiter.check("erin").is_ok());
+        cleanup_db(path);
+    }
+
+    #[test]
+    fn lockout_duration_grows_to_cap() {
+        assert_eq!(lockout_duration(LOCKOUT_THRESHOLD - 1), None);
+        assert_eq!(
+            lockout_duration(LOCKOUT_THRESHOLD),
+            Some(chrono::Duration::seconds(LOCKOUT_BASE_SECS))
+        );
+        assert_eq!(
+            lockout_duration(LOCKOUT_THRESHOLD + 1),
+            Some(chrono::Duration::seconds(LOCKOUT_BASE_SECS * 2))
+        );
+        assert_eq!(
+            lockout_duration(LOCKOUT_THRESHOLD + 40),
+            Some(chrono::Duration::seconds(LOCKOUT_MAX_SECS))
+        );
+    }
+
+    #[tokio::test]
+    async fn handle_login_increments_failed_attempts() {
+        let (state, path) = setup_test_state().await;
+        let user =
+            create_verified_user(&state, "gina", "gina@example.com", &hash_password("secret"))
+                .await;
+
+        handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("gina", "wrong"),
+        )
+        .await;
+        handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("gina", "wrong"),
+        )
+        .await;
+
+        let stored = state
+            .db
+            .find_user_by_id(user.id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert_eq!(stored.failed_attempts, 2);
+        assert!(stored.locked_until.is_none());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_login_locks_account_after_threshold() {
+        let (mut state, path) = setup_test_state().await;
+        // Keep the per-username rate limiter out of the way
+        state.login_limiter = Arc::new(LoginRateLimiter::new(100, Duration::from_secs(900)));
+        let user =
+            create_verified_user(&state, "hank", "hank@example.com", &hash_password("secret"))
+                .await;
+
+        for _ in 0..LOCKOUT_THRESHOLD {
+            handle_login(
+                State(state.clone()),
+                test_client_ip(),
+                UserAgent(None),
+                test_csrf(),
+                login_form("hank", "wrong"),
+            )
+            .await;
+        }
+
+        let stored = state
+            .db
+            .find_user_by_id(user.id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert!(stored.locked_until.is_some());
+
+        // The correct password gets the same locked message and no session
+        let wrong = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("hank", "wrong"),
+        )
+        .await;
+        let right = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("hank", "secret"),
+        )
+        .await;
+        assert_eq!(wrong.status(), StatusCode::OK);
+        assert_eq!(right.status(), StatusCode::OK);
+        assert!(header_value(&right, "Set-Cookie").is_none());
+        assert_eq!(body_string(wrong).await, body_string(right).await);
+
+        // Attempts while locked are not counted
+        let stored = state
+            .db
+            .find_user_by_id(user.id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert_eq!(stored.failed_attempts, LOCKOUT_THRESHOLD);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_login_success_resets_failed_attempts() {
+        let (state, path) = setup_test_state().await;
+        let user =
+            create_verified_user(&state, "ivy", "ivy@example.com", &hash_password("secret")).await;
+
+        handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("ivy", "wrong"),
+        )
+        .await;
+        let response = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("ivy", "secret"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let stored = state
+            .db
+            .find_user_by_id(user.id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert_eq!(stored.failed_attempts, 0);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn failed_logins_wait_longer_each_time() {
+        let (mut state, path) = setup_test_state().await;
+        let delays = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = delays.clone();
+        state.login_throttle = Arc::new(
+            LoginThrottle::new(
+                Duration::from_millis(100),
+                Duration::from_millis(300),
+                Duration::from_secs(900),
+            )
+            .with_sleep(move |delay| {
+                recorded.lock().unwrap().push(delay.as_millis());
+                Box::pin(async {})
+            }),
+        );
+        create_verified_user(&state, "lena", "lena@example.com", &hash_password("secret")).await;
+        let login = |username: &str, password: &str| {
+            handle_login(
+                State(state.clone()),
+                test_client_ip(),
+                UserAgent(None),
+                test_csrf(),
+                login_form(username, password),
+            )
+        };
+
+        for _ in 0..3 {
+            login("lena", "wrong").await;
+        }
+        login("nobody", "wrong").await;
+        assert_eq!(*delays.lock().unwrap(), [100, 200, 300, 100]);
+
+        // Success adds no delay and starts the count over
+        assert_eq!(
+            login("lena", "secret").await.status(),
+            StatusCode::SEE_OTHER
+        );
+        assert_eq!(delays.lock().unwrap().len(), 4);
+        login("lena", "wrong").await;
+        assert_eq!(delays.lock().unwrap()[4], 100);
+        cleanup_db(path);
+    }
+
+    // Log a fresh user in and return the refresh token it was issued
+    async fn login_refresh_token(state: &AppState, username: &str) -> String {
+        create_verified_user(
+            state,
+            username,
+            &format!("{}@example.com", username),
+            &hash_password("secret"),
+        )
+        .await;
+
+        let response = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form(username, "secret"),
+        )
+        .await;
+        set_cookie_value(&response, tokens::REFRESH_COOKIE).expect("refresh cookie")
+    }
+
+    fn refresh_cookies(token: &str) -> AppCookies {
+        cookies_from(&[(tokens::REFRESH_COOKIE, token)])
+    }
+
+    // Full `Set-Cookie` header of the refresh cookie
+    fn refresh_set_cookie(response: &Response) -> String {
+        response
+            .headers()
+            .get_all("Set-Cookie")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .find(|v| v.starts_with(&format!("{}=", tokens::REFRESH_COOKIE)))
+            .expect("refresh cookie")
+            .to_string()
+    }
+
+    // Seconds until the stored refresh token expires
+    async fn stored_refresh_ttl(state: &AppState, token: &str) -> (i64, bool) {
+        let stored = state
+            .db
+            .find_refresh_token(&tokens::hash_token(token))
+            .await
+            .expect("find token")
+            .expect("token exists");
+        (
+            (stored.expires_at - chrono::Utc::now()).num_seconds(),
+            stored.remember,
+        )
+    }
+
+    #[tokio::test]
+    async fn remember_me_extends_the_refresh_cookie() {
+        let (mut state, path) = setup_test_state().await;
+        Arc::make_mut(&mut state.config).session = tokens::SessionConfig::new(
+            3600,
+            30 * 24 * 3600,
+            tokens::SLIDING_WINDOW_SECS,
+            60 * 24 * 3600,
+        );
+        create_verified_user(&state, "rita", "rita@example.com", &hash_password("secret")).await;
+
+        let mut form = login_form("rita", "secret");
+        form.0.remember = true;
+        let response = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            form,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert!(refresh_set_cookie(&response).ends_with(&format!("; Max-Age={}", 30 * 24 * 3600)));
+
+        let token = set_cookie_value(&response, tokens::REFRESH_COOKIE).expect("refresh cookie");
+        let (ttl, remember) = stored_refresh_ttl(&state, &token).await;
+        assert!(remember);
+        assert!((30 * 24 * 3600 - 5..=30 * 24 * 3600).contains(&ttl));
+
+        // A rotated token keeps the choice
+        let response = handle_refresh(State(state.clone()), refresh_cookies(&token)).await;
+        assert!(refresh_set_cookie(&response).contains("Max-Age="));
+        let rotated = set_cookie_value(&response, tokens::REFRESH_COOKIE).expect("refresh cookie");
+        assert!(stored_refresh_ttl(&state, &rotated).await.1);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn login_without_remember_me_uses_a_session_cookie() {
+        let (mut state, path) = setup_test_state().await;
+        Arc::make_mut(&mut state.config).session = tokens::SessionConfig::new(
+            3600,
+            30 * 24 * 3600,
+            tokens::SLIDING_WINDOW_SECS,
+            tokens::MAX_SESSION_LIFETIME_SECS,
+        );
+        let token = login_refresh_token(&state, "sam").await;
+
+        let (ttl, remember) = stored_refresh_ttl(&state, &token).await;
+        assert!(!remember);
+        assert!((3600 - 5..=3600).contains(&ttl));
+
+        let response = handle_refresh(State(state.clone()), refresh_cookies(&token)).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let set_cookie = refresh_set_cookie(&response);
+        assert!(!set_cookie.contains("Max-Age"), "{}", set_cookie);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn refresh_never_extends_the_session_limit() {
+        let (mut state, path) = setup_test_state().await;
+        Arc::make_mut(&mut state.config).session =
+            tokens::SessionConfig::new(3600, 30 * 24 * 3600, tokens::SLIDING_WINDOW_SECS, 120);
+        let token = login_refresh_token(&state, "cap").await;
+        let (ttl, _) = stored_refresh_ttl(&state, &token).await;
+        assert!((115..=120).contains(&ttl));
+
+        // Rotation keeps the end the login started with
+        let response = handle_refresh(State(state.clone()), refresh_cookies(&token)).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let rotated = set_cookie_value(&response, tokens::REFRESH_COOKIE).expect("refresh cookie");
+        let (ttl, _) = stored_refresh_ttl(&state, &rotated).await;
+        assert!((115..=120).contains(&ttl));
+
+        // Once the limit passes, the user has to log in again
+        Arc::make_mut(&mut state.config).session =
+            tokens::SessionConfig::new(3600, 30 * 24 * 3600, tokens::SLIDING_WINDOW_SECS, 0);
+        let token = login_refresh_token(&state, "capped").await;
+        let response = handle_refresh(State(state.clone()), refresh_cookies(&token)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_refresh_issues_new_tokens() {
+        let (state, path) = setup_test_state().await;
+        let refresh_token = login_refresh_token(&state, "jane").await;
+
+        let response = handle_refresh(State(state.clone()), refresh_cookies(&refresh_token)).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let access = set_cookie_value(&response, tokens::ACCESS_COOKIE).expect("access cookie");
+        let user = get_current_user(&state, &AccessToken(Some(access.clone())))
+            .await
+            .expect("access token is valid");
+        assert_eq!(user.username, "jane");
+
+        let rotated = set_cookie_value(&response, tokens::REFRESH_COOKIE).expect("refresh cookie");
+        assert_ne!(rotated, refresh_token);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_refresh_rotation_rejects_reused_token() {
+        let (state, path) = setup_test_state().await;
+        let original = login_refresh_token(&state, "kyle").await;
+
+        let response = handle_refresh(State(state.clone()), refresh_cookies(&original)).await;
+        let rotated = set_cookie_value(&response, tokens::REFRESH_COOKIE).expect("refresh cookie");
+
+        // The old token no longer works, and replaying it also kills the new one
+        let replay = handle_refresh(State(state.clone()), refresh_cookies(&original)).await;
+        assert_eq!(replay.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            set_cookie_value(&replay, tokens::REFRESH_COOKIE),
+            Some(String::new())
+        );
+
+        let response = handle_refresh(State(state.clone()), refresh_cookies(&rotated)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_refresh_rejects_missing_or_unknown_token() {
+        let (state, path) = setup_test_state().await;
+
+        let response = handle_refresh(State(state.clone()), empty_cookies()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = handle_refresh(State(state.clone()), refresh_cookies("unknown")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_logout_revokes_refresh_token() {
+        let (state, path) = setup_test_state().await;
+        let refresh_token = login_refresh_token(&state, "lena").await;
+
+        let response = handle_logout(
+            State(state.clone()),
+            test_csrf(),
+            refresh_cookies(&refresh_token),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let response = handle_refresh(State(state.clone()), refresh_cookies(&refresh_token)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_logout_all_invalidates_existing_tokens() {
+        let (state, path) = setup_test_state().await;
+        let refresh_token = login_refresh_token(&state, "mona").await;
+        let user = state
+            .db
+            .find_user_by_username("mona")
+            .await
+            .expect("find")
+            .expect("user exists");
+
+        let token = access_token_for_user(&state.config.jwt_secret, user.id, "mona");
+        assert!(get_current_user(&state, &token).await.is_some());
+
+        let response = handle_logout_all(
+            State(state.clone()),
+            RequireAuth(UserInfo::from(user)),
+            test_csrf(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        // The old access token is rejected and the refresh token cannot renew it
+        assert!(get_current_user(&state, &token).await.is_none());
+        let response = handle_refresh(State(state.clone()), refresh_cookies(&refresh_token)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        // A fresh login embeds the new version and works again
+        let response = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("mona", "secret"),
+        )
+        .await;
+        let access = set_cookie_value(&response, tokens::ACCESS_COOKIE).expect("access cookie");
+        assert!(get_current_user(&state, &AccessToken(Some(access.clone())))
+            .await
+            .is_some());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_register_creates_unverified_user_with_token() {
+        let (state, path) = setup_test_state().await;
+        let form = RegisterForm {
+            username: "nina".to_string(),
+            email: "nina@example.com".to_string(),
+            password: "plum tree lantern".to_string(),
+            confirm_password: "plum tree lantern".to_string(),
+            invite_code: String::new(),
+        };
+
+        handle_register(State(state.clone()), test_csrf(), Form(form)).await;
+
+        let user = state
+            .db
+            .find_user_by_username("nina")
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert!(!user.verified);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_register_creates_welcome_item() {
+        let (state, path) = setup_test_state().await;
+        let form = RegisterForm {
+            username: "nell".to_string(),
+            email: "nell@example.com".to_string(),
+            password: "plum tree lantern".to_string(),
+            confirm_password: "plum tree lantern".to_string(),
+            invite_code: String::new(),
+        };
+
+        handle_register(State(state.clone()), test_csrf(), Form(form)).await;
+
+        let user = state
+            .db
+            .find_user_by_username("nell")
+            .await
+            .expect("find")
+            .expect("user exists");
+        let items = state.db.get_user_items(user.id).await.expect("list items");
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].title, CreateItem::welcome(user.id).title);
+        cleanup_db(path);
+    }
+
+    fn api_login_form(username: &str, password: &str) -> JsonBody<ApiLoginForm> {
+        JsonBody(ApiLoginForm {
+            username: username.to_string(),
+            password: password.to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn api_login_returns_bearer_token() {
+        let (state, path) = setup_test_state().await;
+        let hash = hash_password("secret");
+        create_verified_user(&state, "api_user", "api_user@example.com", &hash).await;
+
+        let response = api_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            api_login_form("api_user", "secret"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value =
+            serde_json::from_str(&body_string(response).await).expect("json body");
+        let token = body["token"].as_str().expect("token");
+        assert!(body["expires_at"].is_string());
+
+        let mut req = request_with_state(
+            &state,
+            http::Request::get("/api/items").header("Authorization", format!("Bearer {}", token)),
+            "",
+        );
+        let RequireAuth(user) = RequireAuth::from_request(&mut req)
+            .await
+            .expect("bearer token accepted");
+        assert_eq!(user.username, "api_user");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_login_rejects_wrong_password() {
+        let (state, path) = setup_test_state().await;
+        let hash = hash_password("secret");
+        create_verified_user(&state, "api_user", "api_user@example.com", &hash).await;
+
+        let response = api_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            api_login_form("api_user", "wrong"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value =
+            serde_json::from_str(&body_string(response).await).expect("JSON body");
+        assert_eq!(body["error"]["code"], "invalid_credentials");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn api_login_rejects_unknown_user() {
+        let (state, path) = setup_test_state().await;
+
+        let response = api_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            api_login_form("nobody", "secret"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value =
+            serde_json::from_str(&body_string(response).await).expect("JSON body");
+        assert_eq!(body["error"]["code"], "invalid_credentials");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_login_rejects_unverified_account() {
+        let (state, path) = setup_test_state().await;
+        state
+            .db
+            .create_user("oscar", "oscar@example.com", &hash_password("secret"))
+            .await
+            .expect("create user");
+
+        let response = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("oscar", "secret"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(header_value(&response, "Set-Cookie").is_none());
+        assert!(body_string(response)
+            .await
+            .contains("Please verify your email address"));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn verify_email_marks_user_verified_and_allows_login() {
+        let (state, path) = setup_test_state().await;
+        let user = state
+            .db
+            .create_user("pia", "pia@example.com", &hash_password("secret"))
+            .await
+            .expect("create user");
+        let token = send_verification_email(&state, &user)
+            .await
+            .expect("verification token");
+
+        let response = verify_email(State(state.clone()), Query(VerifyQuery { token })).await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/login?verified=true".to_string())
+        );
+
+        let response = handle_login(
+            State(state.clone()),
+            test_client_ip(),
+            UserAgent(None),
+            test_csrf(),
+            login_form("pia", "secret"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn verify_email_rejects_expired_token() {
+        let (state, path) = setup_test_state().await;
+        let user = state
+            .db
+            .create_user("quinn", "quinn@example.com", &hash_password("secret"))
+            .await
+            .expect("create user");
+        let expires_at = chrono::Utc::now() - chrono::Duration::minutes(1);
+        state
+            .db
+            .create_verification_token(user.id, &tokens::hash_token("stale"), expires_at)
+            .await
+            .expect("create token");
+
+        let response = verify_email(
+            State(state.clone()),
+            Query(VerifyQuery {
+                token: "stale".to_string(),
+            }),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/login?verify_failed=true".to_string())
+        );
+
+        let stored = state
+            .db
+            .find_user_by_id(user.id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert!(!stored.verified);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn resend_verification_replaces_old_link() {
+        let (state, path) = setup_test_state().await;
+        let user = state
+            .db
+            .create_user("rita", "rita@example.com", &hash_password("secret"))
+            .await
+            .expect("create user");
+        let old_token = send_verification_email(&state, &user)
+            .await
+            .expect("verification token");
+
+        let response = resend_verification(
+            State(state.clone()),
+            test_csrf(),
+            Form(ResendVerificationForm {
+                username: "rita".to_string(),
+            }),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/login?resent=true".to_string())
+        );
+
+        let consumed = state
+            .db
+            .consume_verification_token(&tokens::hash_token(&old_token))
+            .await
+            .expect("consume");
+        assert_eq!(consumed, None);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn handle_logout_clears_cookie() {
+        let (state, path) = setup_test_state().await;
+        let response = handle_logout(State(state.clone()), test_csrf(), empty_cookies()).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(header_value(&response, "Location"), Some("/".to_string()));
+        let set_cookie = header_value(&response, "Set-Cookie").unwrap_or_default();
+        assert!(set_cookie.contains("Max-Age=0"));
+        assert_eq!(
+            set_cookie_value(&response, tokens::REFRESH_COOKIE),
+            Some(String::new())
+        );
         cleanup_db(path);
     }
 }
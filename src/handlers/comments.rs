@@ -0,0 +1,334 @@
+use rustapi_rs::prelude::*;
+use tera::Context;
+
+use crate::{
+    extractors::{CsrfProtected, CsrfToken, Form, Query, RequireAuth},
+    flash::{self, Flash, DATABASE_ERROR, ITEM_NOT_FOUND},
+    models::{CommentForm, CommentsQuery, Item, Pagination, UserInfo, MAX_COMMENT_LENGTH},
+    responses::redirect_to,
+    templates::render,
+    validation::{field_errors, INVALID_FORM_MESSAGE},
+    AppState,
+};
+
+/// List an item's comments one page at a time, with the comment form
+#[rustapi_rs::get("/items/{id}/comments")]
+pub async fn list_comments(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    CsrfToken(csrf_token): CsrfToken,
+    Path(id): Path<i64>,
+    Query(query): Query<CommentsQuery>,
+) -> Response {
+    let item = match find_item(&state, id, &user).await {
+        Ok(item) => item,
+        Err(response) => return response,
+    };
+
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+
+    render_comments(&state, &user, context, &item, &query).await
+}
+
+/// Add a comment to one of the user's items
+#[rustapi_rs::post("/items/{id}/comments")]
+pub async fn create_comment(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    Path(id): Path<i64>,
+    CsrfProtected(csrf_token): CsrfProtected,
+    Form(form): Form<CommentForm>,
+) -> Response {
+    let item = match find_item(&state, id, &user).await {
+        Ok(item) => item,
+        Err(response) => return response,
+    };
+
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+
+    let form = form.trimmed();
+    if let Err(validation_errors) = form.validate() {
+        context.insert("error", INVALID_FORM_MESSAGE);
+        context.insert("errors", &field_errors(&validation_errors));
+        context.insert("body", &form.body);
+        return render_comments(&state, &user, context, &item, &CommentsQuery::default()).await;
+    }
+
+    match state.db.add_comment(item.id, user.id, &form.body).await {
+        Ok(_) => {
+            state.metrics.record_item_operation("comment");
+            redirect_to(
+                &state,
+                &format!("/items/{}/comments?success=created", item.id),
+            )
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", "Failed to add comment");
+            context.insert("body", &form.body);
+            render_comments(&state, &user, context, &item, &CommentsQuery::default()).await
+        }
+    }
+}
+
+// Comments are only visible to, and only accepted from, the item's owner
+async fn find_item(state: &AppState, id: i64, user: &UserInfo) -> Result<Item, Response> {
+    match state.db.get_item(id, user.id).await {
+        Ok(Some(item)) => Ok(item),
+        Ok(None) => Err(flash::redirect(
+            state,
+            "/items",
+            Flash::error(ITEM_NOT_FOUND),
+        )),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            Err(flash::redirect(
+                state,
+                "/items",
+                Flash::error(DATABASE_ERROR),
+            ))
+        }
+    }
+}
+
+// Load the requested page of comments and render the comments page
+async fn render_comments(
+    state: &AppState,
+    user: &UserInfo,
+    mut context: Context,
+    item: &Item,
+    query: &CommentsQuery,
+) -> Response {
+    let search = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty());
+    context.insert("q", search.unwrap_or_default());
+
+    let total = match state.db.count_item_comments(item.id, search).await {
+        Ok(total) => total,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", "Failed to load comments");
+            0
+        }
+    };
+    let pagination = Pagination::new(query.page, query.per_page, total);
+
+    let comments = match state
+        .db
+        .get_item_comments_paged(item.id, search, pagination.limit(), pagination.offset())
+        .await
+    {
+        Ok(comments) => comments,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", "Failed to load comments");
+            vec![]
+        }
+    };
+
+    context.insert("item", item);
+    context.insert("comments", &comments);
+    context.insert("pagination", &pagination);
+    context.insert("total_pages", &pagination.total_pages);
+    context.insert("max_comment_length", &MAX_COMMENT_LENGTH);
+
+    render(state, "items/comments.html", Some(user), context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CreateItem;
+    use crate::test_utils::{
+        body_string, cleanup_db, flash_from, header_value, setup_test_state, test_csrf,
+        test_csrf_token,
+    };
+
+    async fn setup_item(state: &AppState) -> (i64, UserInfo) {
+        let user = state
+            .db
+            .create_user("commenter", "commenter@example.com", "hash")
+            .await
+            .expect("create user");
+        let item = state
+            .db
+            .create_item(CreateItem {
+                user_id: user.id,
+                title: "Discussed".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        (item.id, UserInfo::from(user))
+    }
+
+    fn comment(body: &str) -> Form<CommentForm> {
+        Form(CommentForm {
+            body: body.to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn create_comment_stores_trimmed_body() {
+        let (state, path) = setup_test_state().await;
+        let (item_id, user) = setup_item(&state).await;
+
+        let response = create_comment(
+            State(state.clone()),
+            RequireAuth(user.clone()),
+            Path(item_id),
+            test_csrf(),
+            comment("  Needs milk too \n"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some(format!("/items/{}/comments?success=created", item_id))
+        );
+
+        let comments = state
+            .db
+            .get_item_comments_paged(item_id, None, 10, 0)
+            .await
+            .expect("list comments");
+        assert_eq!(comments.len(), 1);
+        assert_eq!(comments[0].body, "Needs milk too");
+        assert_eq!(comments[0].user_id, user.id);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn create_comment_rejects_blank_body() {
+        let (state, path) = setup_test_state().await;
+        let (item_id, user) = setup_item(&state).await;
+
+        let response = create_comment(
+            State(state.clone()),
+            RequireAuth(user),
+            Path(item_id),
+            test_csrf(),
+            comment("   "),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_string(response)
+            .await
+            .contains("errors.body=Comment is required"));
+        assert_eq!(
+            state
+                .db
+                .count_item_comments(item_id, None)
+                .await
+                .expect("count comments"),
+            0
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn list_comments_pages_and_filters() {
+        let (state, path) = setup_test_state().await;
+        let (item_id, user) = setup_item(&state).await;
+        for i in 0..5 {
+            let body = if i % 2 == 0 { "Even note" } else { "Odd note" };
+            state
+                .db
+                .add_comment(item_id, user.id, body)
+                .await
+                .expect("add comment");
+        }
+
+        let response = list_comments(
+            State(state.clone()),
+            RequireAuth(user.clone()),
+            test_csrf_token(),
+            Path(item_id),
+            Query(CommentsQuery {
+                page: Some(3),
+                per_page: Some(2),
+                q: None,
+            }),
+        )
+        .await;
+        assert_eq!(
+            body_string(response).await,
+            "ITEM COMMENTS page 3 of 3 q= comments=1 error= errors.body="
+        );
+
+        let response = list_comments(
+            State(state.clone()),
+            RequireAuth(user),
+            test_csrf_token(),
+            Path(item_id),
+            Query(CommentsQuery {
+                q: Some(" odd ".to_string()),
+                ..Default::default()
+            }),
+        )
+        .await;
+        assert_eq!(
+            body_string(response).await,
+            "ITEM COMMENTS page 1 of 1 q=odd comments=2 error= errors.body="
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn comments_are_limited_to_the_item_owner() {
+        let (state, path) = setup_test_state().await;
+        let (item_id, _) = setup_item(&state).await;
+        let other = UserInfo::from(
+            state
+                .db
+                .create_user("other", "other@example.com", "hash")
+                .await
+                .expect("create user"),
+        );
+
+        let response = create_comment(
+            State(state.clone()),
+            RequireAuth(other.clone()),
+            Path(item_id),
+            test_csrf(),
+            comment("Not mine"),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(ITEM_NOT_FOUND))
+        );
+        assert_eq!(
+            state
+                .db
+                .count_item_comments(item_id, None)
+                .await
+                .expect("count comments"),
+            0
+        );
+
+        let response = list_comments(
+            State(state.clone()),
+            RequireAuth(other),
+            test_csrf_token(),
+            Path(item_id),
+            Query(CommentsQuery::default()),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(ITEM_NOT_FOUND))
+        );
+        cleanup_db(path);
+    }
+}
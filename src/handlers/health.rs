@@ -0,0 +1,23 @@
+use rustapi_rs::prelude::*;
+
+/// Liveness check for load balancers and orchestrators
+///
+/// Answers 200 as long as the server is accepting requests, maintenance mode
+/// included.
+#[rustapi_rs::get("/healthz")]
+pub async fn healthz() -> Response {
+    "ok".into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::body_string;
+
+    #[tokio::test]
+    async fn healthz_answers_ok() {
+        let response = healthz().await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "ok");
+    }
+}
@@ -1,39 +1,67 @@
 use rustapi_rs::prelude::*;
 use tera::Context;
 
-use crate::{extractors::AppCookies, middleware::get_current_user, models::UserInfo, AppState};
+use crate::{
+    extractors::{CsrfToken, CurrentUser},
+    models::{Item, ItemSort},
+    templates::render,
+    AppState,
+};
 
-/// Home page handler
+/// Home page handler; works with or without a logged-in user
+///
+/// A logged-in user also gets `item_count`, their items outside the trash,
+/// and `latest_item`, the newest active one; anonymous visitors cost no
+/// query.
 #[rustapi_rs::get("/")]
-pub async fn home(State(state): State<AppState>, cookies: AppCookies) -> Response {
+pub async fn home(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+    CsrfToken(csrf_token): CsrfToken,
+) -> Response {
     let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
 
-    // Try to get current user (optional)
-    if let Some(user) = get_current_user(&state, &cookies).await {
-        context.insert("user", &Some(&user));
-    } else {
-        context.insert("user", &None::<UserInfo>);
-    }
-
-    match state.tera.render("index.html", &context) {
-        Ok(html) => Html(html).into_response(),
-        Err(e) => {
-            eprintln!("Template error: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, "Template error").into_response()
+    if let Some(user) = &user {
+        // The page still renders without the summary if it fails to load
+        match item_summary(&state, user.id).await {
+            Ok((item_count, latest_item)) => {
+                context.insert("item_count", &item_count);
+                context.insert("latest_item", &latest_item);
+            }
+            Err(e) => tracing::error!(error = %e, "database error"),
         }
     }
+
+    render(&state, "index.html", user.as_ref(), context)
+}
+
+async fn item_summary(state: &AppState, user_id: i64) -> Result<(i64, Option<Item>), sqlx::Error> {
+    let count = state.db.count_user_items(user_id).await?;
+    let latest = state
+        .db
+        .get_user_items_sorted(user_id, ItemSort::newest(), 1, 0)
+        .await?
+        .pop();
+    Ok((count, latest))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::test_utils::{cleanup_db, cookies_for_user, empty_cookies, setup_test_state};
+    use crate::models::{CreateItem, UserInfo};
+    use crate::test_utils::{
+        body_string, cleanup_db, request_with_state, setup_test_state, test_csrf_token,
+        token_for_user,
+    };
+    use rustapi_rs::FromRequest;
 
     #[tokio::test]
     async fn home_returns_ok_for_anonymous() {
         let (state, path) = setup_test_state().await;
-        let response = home(State(state.clone()), empty_cookies()).await;
+        let response = home(State(state.clone()), CurrentUser(None), test_csrf_token()).await;
         assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "HOME anonymous");
         cleanup_db(path);
     }
 
@@ -45,9 +73,65 @@ mod tests {
             .create_user("viewer", "viewer@example.com", "hash")
             .await
             .expect("create user");
-        let cookies = cookies_for_user(&state.jwt_secret, user.id, &user.username);
-        let response = home(State(state.clone()), cookies).await;
+        let token = token_for_user(&state.config.jwt_secret, user.id, &user.username, 3600);
+        let mut req = request_with_state(
+            &state,
+            http::Request::get("/").header("Cookie", format!("token={}", token)),
+            "",
+        );
+        let current = CurrentUser::from_request(&mut req)
+            .await
+            .expect("current user");
+
+        let response = home(State(state.clone()), current, test_csrf_token()).await;
         assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "HOME viewer items=0");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn home_summarizes_the_users_items() {
+        let (state, path) = setup_test_state().await;
+        let user = state
+            .db
+            .create_user("viewer", "viewer@example.com", "hash")
+            .await
+            .expect("create user");
+        let other = state
+            .db
+            .create_user("other", "other@example.com", "hash")
+            .await
+            .expect("create user");
+        for (user_id, title) in [
+            (user.id, "First"),
+            (user.id, "Second"),
+            (other.id, "Theirs"),
+        ] {
+            state
+                .db
+                .create_item(CreateItem {
+                    user_id,
+                    title: title.to_string(),
+                    description: None,
+                    due_at: None,
+                })
+                .await
+                .expect("create item");
+        }
+
+        let response = home(
+            State(state.clone()),
+            CurrentUser(Some(UserInfo::from(user))),
+            test_csrf_token(),
+        )
+        .await;
+        assert_eq!(
+            body_string(response).await,
+            "HOME viewer items=2 latest=Second"
+        );
+
+        let response = home(State(state.clone()), CurrentUser(None), test_csrf_token()).await;
+        assert_eq!(body_string(response).await, "HOME anonymous");
         cleanup_db(path);
     }
 }
@@ -0,0 +1,399 @@
+use rustapi_rs::prelude::*;
+
+use crate::{
+    db::ReassignOutcome,
+    extractors::{CsrfProtected, Form, Query, RequireAuth},
+    flash::{self, Flash, DATABASE_ERROR, ITEM_NOT_FOUND},
+    models::{normalize_email, InvitationForm, InvitationQuery},
+    tokens,
+    validation::{field_errors, INVALID_FORM_MESSAGE},
+    AppState,
+};
+
+/// Flash shown for links that are unknown, expired, cancelled, meant for
+/// another account, or whose item has since changed hands
+const INVITATION_INVALID: &str = "This invitation is invalid or has expired";
+
+/// Invite the user with an email address to take over one of the current
+/// user's items
+///
+/// The answer is the same whether or not anyone has that address, so the
+/// form cannot be used to find out who is registered. The link is valid for
+/// `Config::invitation_ttl_secs`.
+#[rustapi_rs::post("/items/{id}/invitations")]
+pub async fn create_invitation(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    _csrf: CsrfProtected,
+    Path(id): Path<i64>,
+    Form(form): Form<InvitationForm>,
+) -> Response {
+    let item_path = format!("/items/{}", id);
+    let form = InvitationForm {
+        email: normalize_email(&form.email),
+    };
+    if let Err(validation_errors) = form.validate() {
+        let message = field_errors(&validation_errors)
+            .remove("email")
+            .and_then(|messages| messages.into_iter().next())
+            .unwrap_or_else(|| INVALID_FORM_MESSAGE.to_string());
+        return flash::redirect(&state, &item_path, Flash::error(message));
+    }
+    if form.email == normalize_email(&user.email) {
+        return flash::redirect(
+            &state,
+            &item_path,
+            Flash::error("You already own this item"),
+        );
+    }
+
+    match state.db.get_item(id, user.id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return flash::redirect(&state, "/items", Flash::error(ITEM_NOT_FOUND)),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return flash::redirect(&state, &item_path, Flash::error(DATABASE_ERROR));
+        }
+    }
+
+    let token = tokens::generate_token();
+    let expires_at =
+        chrono::Utc::now() + chrono::Duration::seconds(state.config.invitation_ttl_secs);
+    if let Err(e) = state
+        .db
+        .create_invitation(
+            id,
+            user.id,
+            &form.email,
+            &tokens::hash_token(&token),
+            expires_at,
+        )
+        .await
+    {
+        tracing::error!(error = %e, "database error");
+        return flash::redirect(&state, &item_path, Flash::error(DATABASE_ERROR));
+    }
+
+    // No mail transport is configured in this template, so the link is
+    // written to the server log
+    tracing::info!(
+        email = %form.email,
+        item_id = id,
+        "invitation link: {}",
+        state
+            .config
+            .base_path
+            .url(&format!("/invitations/accept?token={}", token))
+    );
+    flash::redirect(
+        &state,
+        &item_path,
+        Flash::success(format!("Invitation sent to {}", form.email)),
+    )
+}
+
+/// Withdraw an invitation the current user sent
+#[rustapi_rs::post("/invitations/{id}/cancel")]
+pub async fn cancel_invitation(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    _csrf: CsrfProtected,
+    Path(id): Path<i64>,
+) -> Response {
+    match state.db.delete_invitation(id, user.id).await {
+        Ok(Some(item_id)) => flash::redirect(
+            &state,
+            &format!("/items/{}", item_id),
+            Flash::success("Invitation cancelled"),
+        ),
+        Ok(None) => flash::redirect(&state, "/items", Flash::error("Invitation not found")),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            flash::redirect(&state, "/items", Flash::error(DATABASE_ERROR))
+        }
+    }
+}
+
+/// Take over an item from an invitation link
+///
+/// Only the account with the invited email address can accept, and only
+/// while the sender still owns the item. The item then moves with its tags,
+/// comments and attachments, and its other invitations are dropped.
+#[rustapi_rs::get("/invitations/accept")]
+pub async fn accept_invitation(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    Query(query): Query<InvitationQuery>,
+) -> Response {
+    let invalid = || flash::redirect(&state, "/items", Flash::error(INVITATION_INVALID));
+    let database_error = |e: sqlx::Error| {
+        tracing::error!(error = %e, "database error");
+        flash::redirect(&state, "/items", Flash::error(DATABASE_ERROR))
+    };
+
+    let invitation = match state
+        .db
+        .take_invitation(&tokens::hash_token(&query.token), &user.email)
+        .await
+    {
+        Ok(Some(invitation)) if invitation.is_active() => invitation,
+        Ok(_) => return invalid(),
+        Err(e) => return database_error(e),
+    };
+    match state
+        .db
+        .get_item(invitation.item_id, invitation.sender_id)
+        .await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => return invalid(),
+        Err(e) => return database_error(e),
+    }
+
+    let item = match state
+        .db
+        .reassign_item(invitation.item_id, user.id, user.id)
+        .await
+    {
+        Ok(ReassignOutcome::Moved(item)) => item,
+        Ok(ReassignOutcome::ItemNotFound | ReassignOutcome::UserNotFound) => return invalid(),
+        Err(e) => return database_error(e),
+    };
+    if let Err(e) = state.db.delete_item_invitations(item.id).await {
+        tracing::warn!(error = %e, "failed to drop the item's other invitations");
+    }
+    tracing::info!(
+        item_id = item.id,
+        from_user_id = invitation.sender_id,
+        to_user_id = user.id,
+        "item handed over"
+    );
+    flash::redirect(
+        &state,
+        &format!("/items/{}", item.id),
+        Flash::success("The item is now yours"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CreateItem, Item, UserInfo};
+    use crate::test_utils::{cleanup_db, flash_from, header_value, setup_test_state, test_csrf};
+
+    async fn setup(state: &AppState) -> (UserInfo, UserInfo, Item) {
+        let sender = state
+            .db
+            .create_user("sender", "sender@example.com", "hash")
+            .await
+            .expect("create user");
+        let invitee = state
+            .db
+            .create_user("invitee", "invitee@example.com", "hash")
+            .await
+            .expect("create user");
+        let item = state
+            .db
+            .create_item(CreateItem {
+                user_id: sender.id,
+                title: "Handover".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+        (UserInfo::from(sender), UserInfo::from(invitee), item)
+    }
+
+    async fn invite(state: &AppState, sender: &UserInfo, item_id: i64, email: &str) -> Response {
+        create_invitation(
+            State(state.clone()),
+            RequireAuth(sender.clone()),
+            test_csrf(),
+            Path(item_id),
+            Form(InvitationForm {
+                email: email.to_string(),
+            }),
+        )
+        .await
+    }
+
+    // Store an invitation directly, returning its link token
+    async fn stored_invitation(
+        state: &AppState,
+        sender: &UserInfo,
+        item_id: i64,
+        email: &str,
+        ttl_secs: i64,
+    ) -> String {
+        let token = tokens::generate_token();
+        state
+            .db
+            .create_invitation(
+                item_id,
+                sender.id,
+                email,
+                &tokens::hash_token(&token),
+                chrono::Utc::now() + chrono::Duration::seconds(ttl_secs),
+            )
+            .await
+            .expect("create invitation");
+        token
+    }
+
+    async fn accept(state: &AppState, user: &UserInfo, token: &str) -> Response {
+        accept_invitation(
+            State(state.clone()),
+            RequireAuth(user.clone()),
+            Query(InvitationQuery {
+                token: token.to_string(),
+            }),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn create_invitation_answers_the_same_for_unknown_emails() {
+        let (state, path) = setup_test_state().await;
+        let (sender, _, item) = setup(&state).await;
+
+        for email in [" Invitee@Example.com ", "nobody@example.com"] {
+            let response = invite(&state, &sender, item.id, email).await;
+            assert_eq!(
+                header_value(&response, "Location"),
+                Some(format!("/items/{}", item.id))
+            );
+            let expected = format!("Invitation sent to {}", normalize_email(email));
+            assert_eq!(
+                flash_from(&state, &response),
+                Some(Flash::success(&expected))
+            );
+        }
+        let invitations = state
+            .db
+            .list_item_invitations(item.id)
+            .await
+            .expect("list invitations");
+        let emails: Vec<&str> = invitations.iter().map(|i| i.email.as_str()).collect();
+        assert_eq!(emails, ["nobody@example.com", "invitee@example.com"]);
+
+        // Bad addresses and the owner's own are refused
+        for (email, message) in [
+            ("not-an-email", "Enter a valid email address"),
+            ("sender@example.com", "You already own this item"),
+        ] {
+            let response = invite(&state, &sender, item.id, email).await;
+            assert_eq!(flash_from(&state, &response), Some(Flash::error(message)));
+        }
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn accepting_an_invitation_reassigns_the_item() {
+        let (state, path) = setup_test_state().await;
+        let (sender, invitee, item) = setup(&state).await;
+        let token = stored_invitation(&state, &sender, item.id, &invitee.email, 3600).await;
+        let other = stored_invitation(&state, &sender, item.id, "x@example.com", 3600).await;
+
+        // Nobody else can use the link, and trying leaves it intact
+        let response = accept(&state, &sender, &token).await;
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(INVITATION_INVALID))
+        );
+
+        let response = accept(&state, &invitee, &token).await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some(format!("/items/{}", item.id))
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("The item is now yours"))
+        );
+        assert!(state
+            .db
+            .get_item(item.id, invitee.id)
+            .await
+            .expect("get")
+            .is_some());
+        assert!(state
+            .db
+            .get_item(item.id, sender.id)
+            .await
+            .expect("get")
+            .is_none());
+
+        // The link works once, and the item's other invitations are gone
+        let response = accept(&state, &invitee, &token).await;
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(INVITATION_INVALID))
+        );
+        assert!(state
+            .db
+            .take_invitation(&tokens::hash_token(&other), "x@example.com")
+            .await
+            .expect("take invitation")
+            .is_none());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn expired_and_cancelled_invitations_cannot_be_accepted() {
+        let (state, path) = setup_test_state().await;
+        let (sender, invitee, item) = setup(&state).await;
+
+        let expired = stored_invitation(&state, &sender, item.id, &invitee.email, -60).await;
+        let response = accept(&state, &invitee, &expired).await;
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(INVITATION_INVALID))
+        );
+        assert!(state
+            .db
+            .list_item_invitations(item.id)
+            .await
+            .expect("list invitations")
+            .is_empty());
+
+        let token = stored_invitation(&state, &sender, item.id, &invitee.email, 3600).await;
+        let invitation = state
+            .db
+            .list_item_invitations(item.id)
+            .await
+            .expect("list invitations")
+            .remove(0);
+        let cancel = |user: &UserInfo| {
+            cancel_invitation(
+                State(state.clone()),
+                RequireAuth(user.clone()),
+                test_csrf(),
+                Path(invitation.id),
+            )
+        };
+        let response = cancel(&invitee).await;
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error("Invitation not found"))
+        );
+        let response = cancel(&sender).await;
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("Invitation cancelled"))
+        );
+
+        let response = accept(&state, &invitee, &token).await;
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(INVITATION_INVALID))
+        );
+        assert!(state
+            .db
+            .get_item(item.id, sender.id)
+            .await
+            .expect("get")
+            .is_some());
+        cleanup_db(path);
+    }
+}
@@ -1,79 +1,241 @@
+use chrono::{NaiveDate, Utc};
+use chrono_tz::Tz;
 use rustapi_rs::prelude::*;
+use serde_json::json;
 use tera::Context;
 
 use crate::{
-    extractors::{AppCookies, Form},
-    middleware::get_current_user,
-    models::{CreateItem, ItemForm},
+    errors::ApiErrorResponse,
+    events::{self, ItemEventKind},
+    extractors::{
+        CsrfProtected, CsrfToken, FlashMessage, Form, IdempotencyKey, MultipartForm, Query,
+        RequireAuth, ValidatedForm, IDEMPOTENCY_WINDOW_SECS,
+    },
+    feed,
+    flash::{self, Flash},
+    handlers::{api_items::load_item, shares},
+    i18n::{INVALID_FORM, ITEM_NOT_FOUND, TRY_AGAIN},
+    models::{
+        normalize_tag, BulkAction, BulkItemsForm, CreateItem, FeedQuery, ImportItem, ItemFilter,
+        ItemForm, ItemSort, ListItemsQuery, Pagination, ReorderItemsForm, UserInfo,
+    },
+    negotiate::{with_json, with_json_error},
+    templates::render,
+    tokens,
+    validation::{field_errors, FieldErrors, ValidateConfigured},
     AppState,
 };
 
-/// List all items for the current user
+/// List the current user's items, one page at a time
+///
+/// Archived items are left out unless `?archived=1` asks for them alone.
+/// Clients preferring JSON get `{"items": [...], "pagination": {...}}`.
 #[rustapi_rs::get("/items")]
-pub async fn list_items(State(state): State<AppState>, cookies: AppCookies) -> Response {
+pub async fn list_items(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    CsrfToken(csrf_token): CsrfToken,
+    FlashMessage(flash): FlashMessage,
+    Query(query): Query<ListItemsQuery>,
+) -> Response {
     let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+    if let Some(flash) = flash {
+        flash.insert_into(&mut context);
+    }
+
+    // Page errors are shown above the list; JSON clients get a 500 instead
+    let mut failed = false;
+
+    let search = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty());
+    context.insert("q", search.unwrap_or_default());
+
+    let tag = query.tag.as_deref().and_then(normalize_tag);
+    context.insert("tag", tag.as_deref().unwrap_or_default());
+
+    let archived = query.archived();
+    context.insert("archived", &archived);
+
+    let filter = ItemFilter {
+        query: search,
+        tag: tag.as_deref(),
+        full_text: true,
+        archived,
+    };
+
+    // Searches rank by relevance unless another order was picked
+    let sort = match (search, query.sort.as_deref()) {
+        (Some(_), None) => ItemSort::relevance(),
+        (None, Some("relevance")) => ItemSort::default(),
+        (_, sort) => ItemSort::from_params(sort, query.order.as_deref()),
+    };
+    context.insert("sort", sort.column.as_str());
+    context.insert("order", sort.order_str());
 
-    // Get current user from JWT
-    let user = match get_current_user(&state, &cookies).await {
-        Some(u) => u,
-        None => return Redirect::to("/login").into_response(),
+    let total = match state.db.count_search_user_items(user.id, &filter).await {
+        Ok(total) => total,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", &state.messages.t("items.load_failed"));
+            failed = true;
+            0
+        }
     };
 
-    context.insert("user", &Some(&user));
+    let pagination = Pagination::new(query.page, query.per_page, total);
 
-    let items = match state.db.get_user_items(user.id).await {
-        Ok(items) => items,
+    let items = if filter.is_empty() {
+        state
+            .db
+            .get_user_items_sorted(user.id, sort, pagination.limit(), pagination.offset())
+            .await
+    } else {
+        state
+            .db
+            .search_user_items(
+                user.id,
+                &filter,
+                sort,
+                pagination.limit(),
+                pagination.offset(),
+            )
+            .await
+    };
+    let items = match items {
+        Ok(mut items) => match state.db.load_item_tags(&mut items).await {
+            Ok(()) => items,
+            Err(e) => {
+                tracing::error!(error = %e, "database error");
+                context.insert("error", &state.messages.t("items.load_tags_failed"));
+                failed = true;
+                items
+            }
+        },
         Err(e) => {
-            eprintln!("Database error: {}", e);
-            context.insert("error", "Failed to load items");
+            tracing::error!(error = %e, "database error");
+            context.insert("error", &state.messages.t("items.load_failed"));
+            failed = true;
             vec![]
         }
     };
 
+    let today = today_for(&user);
+    let overdue_ids: Vec<i64> = items
+        .iter()
+        .filter(|item| item.due_at.is_some_and(|due| due < today))
+        .map(|item| item.id)
+        .collect();
+
     context.insert("items", &items);
+    context.insert("overdue_ids", &overdue_ids);
+    context.insert("pagination", &pagination);
+    context.insert("total_pages", &pagination.total_pages);
 
-    render_template(&state, "items/list.html", &context)
+    let response = render(&state, "items/list.html", Some(&user), context);
+    if failed {
+        return with_json_error(
+            response,
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "database_error",
+        );
+    }
+    with_json(
+        response,
+        StatusCode::OK,
+        &json!({ "items": items, "pagination": pagination }),
+    )
 }
 
 /// Show form to create a new item
 #[rustapi_rs::get("/items/new")]
-pub async fn new_item_form(State(state): State<AppState>, cookies: AppCookies) -> Response {
-    let user = match get_current_user(&state, &cookies).await {
-        Some(u) => u,
-        None => return Redirect::to("/login").into_response(),
-    };
-
+pub async fn new_item_form(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    CsrfToken(csrf_token): CsrfToken,
+) -> Response {
     let mut context = Context::new();
-    context.insert("user", &Some(&user));
+    context.insert("csrf_token", &csrf_token);
     context.insert("item", &None::<()>);
+    context.insert("idempotency_key", &tokens::generate_token());
 
-    render_template(&state, "items/form.html", &context)
+    render(&state, "items/form.html", Some(&user), context)
 }
 
 /// Create a new item
+///
+/// Submitting the form twice creates one item: the form carries an
+/// idempotency key, and a key already used gets the first answer again.
+/// Clients preferring JSON get the item with a 201 instead of the redirect.
+/// Takes the global timeout and body limit; an entry in
+/// `route_limits::ROUTE_OVERRIDES` would change them.
 #[rustapi_rs::post("/items")]
 pub async fn create_item(
     State(state): State<AppState>,
-    cookies: AppCookies,
-    Form(form): Form<ItemForm>,
+    RequireAuth(user): RequireAuth,
+    CsrfProtected(csrf_token): CsrfProtected,
+    IdempotencyKey(key): IdempotencyKey,
+    ValidatedForm(form): ValidatedForm<ItemForm>,
 ) -> Response {
-    let user = match get_current_user(&state, &cookies).await {
-        Some(u) => u,
-        None => return Redirect::to("/login").into_response(),
-    };
-
     let mut context = Context::new();
-    context.insert("user", &Some(&user));
+    context.insert("csrf_token", &csrf_token);
+    // Re-rendered forms keep their key, so fixing an error and resubmitting
+    // is still one request
+    context.insert(
+        "idempotency_key",
+        &key.clone().unwrap_or_else(tokens::generate_token),
+    );
 
-    // Validate
-    if let Err(validation_errors) = form.validate() {
-        let error_msg = format!("Validation error: {:?}", validation_errors);
+    let form = match form {
+        Ok(form) => form,
+        Err(errors) => {
+            context.insert("error", &state.messages.t(INVALID_FORM));
+            context.insert("errors", &errors);
+            context.insert("item", &None::<()>);
+            return invalid_form(
+                render(&state, "items/form.html", Some(&user), context),
+                &errors,
+            );
+        }
+    };
+
+    if let Some(key) = key.as_deref() {
+        match state
+            .db
+            .find_idempotent_item(user.id, key, IDEMPOTENCY_WINDOW_SECS)
+            .await
+        {
+            Ok(Some(id)) => return created(&state, id, user.id).await,
+            Ok(None) => {}
+            Err(e) => {
+                tracing::error!(error = %e, "database error");
+                context.insert("error", &state.messages.t("items.create_failed"));
+                context.insert("item", &None::<()>);
+                return create_failed(&state, &user, context);
+            }
+        }
+    }
 
-        context.insert("error", &error_msg);
-        context.insert("item", &None::<()>);
-        return render_template(&state, "items/form.html", &context);
+    match remaining_items(&state, user.id).await {
+        Ok(Some(0)) => {
+            context.insert("error", &item_limit_message(&state));
+            context.insert("item", &None::<()>);
+            return with_json_error(
+                render(&state, "items/form.html", Some(&user), context),
+                StatusCode::FORBIDDEN,
+                "item_limit_reached",
+            );
+        }
+        Ok(_) => {}
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", &state.messages.t("items.create_failed"));
+            context.insert("item", &None::<()>);
+            return create_failed(&state, &user, context);
+        }
     }
 
+    let tags = form.tag_list();
+    let due_at = form.due_date().unwrap_or_default();
     let create_item = CreateItem {
         user_id: user.id,
         title: form.title.trim().to_string(),
@@ -81,76 +243,179 @@ pub async fn create_item(
             .description
             .map(|d| d.trim().to_string())
             .filter(|d| !d.is_empty()),
+        due_at,
     };
 
-    match state.db.create_item(create_item).await {
-        Ok(_) => Redirect::to("/items?success=created").into_response(),
+    let outcome = match key.as_deref() {
+        Some(key) => {
+            state
+                .db
+                .create_item_once(create_item, tags, key, IDEMPOTENCY_WINDOW_SECS)
+                .await
+        }
+        None => state
+            .db
+            .create_item_with_tags(create_item, tags)
+            .await
+            .map(|item| (item.id, true)),
+    };
+
+    match outcome {
+        Ok((id, new)) => {
+            if new {
+                state.metrics.record_item_operation("create");
+                state
+                    .item_events
+                    .publish(user.id, id, ItemEventKind::Created);
+            }
+            created(&state, id, user.id).await
+        }
         Err(e) => {
-            eprintln!("Database error: {}", e);
-            context.insert("error", "Failed to create item");
+            tracing::error!(error = %e, "database error");
+            context.insert("error", &state.messages.t("items.create_failed"));
             context.insert("item", &None::<()>);
-            render_template(&state, "items/form.html", &context)
+            create_failed(&state, &user, context)
+        }
+    }
+}
+
+/// Show one item with its tags, attachments, first page of comments,
+/// pending invitations and share link
+#[rustapi_rs::get("/items/{id}")]
+pub async fn show_item(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    CsrfToken(csrf_token): CsrfToken,
+    Path(id): Path<i64>,
+    FlashMessage(flash): FlashMessage,
+) -> Response {
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+    if let Some(flash) = flash {
+        flash.insert_into(&mut context);
+    }
+
+    let mut item = match state.db.get_item(id, user.id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return item_not_found(&state),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return database_error(&state);
         }
+    };
+
+    let loaded = async {
+        item.tags = state.db.get_item_tags(item.id).await?;
+        let attachments = state.db.get_item_attachments(item.id).await?;
+        let comment_count = state.db.count_item_comments(item.id, None).await?;
+        let pagination = Pagination::new(None, None, comment_count);
+        let comments = state
+            .db
+            .get_item_comments_paged(item.id, None, pagination.limit(), pagination.offset())
+            .await?;
+        let invitations = state.db.list_item_invitations(item.id).await?;
+        let share_slug = state.db.item_share_slug(item.id, user.id).await?;
+        Ok::<_, sqlx::Error>((
+            attachments,
+            comment_count,
+            comments,
+            invitations,
+            share_slug,
+        ))
     }
+    .await;
+    let (attachments, comment_count, comments, invitations, share_slug) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return database_error(&state);
+        }
+    };
+
+    context.insert("item", &item);
+    context.insert("attachments", &attachments);
+    context.insert("comments", &comments);
+    context.insert("comment_count", &comment_count);
+    context.insert("invitations", &invitations);
+    let share_url = share_slug.map(|slug| shares::share_url(&state, &slug));
+    context.insert("share_url", &share_url);
+
+    let response = render(&state, "items/detail.html", Some(&user), context);
+    with_json(
+        response,
+        StatusCode::OK,
+        &json!({
+            "item": item,
+            "attachments": attachments,
+            "comments": comments,
+            "comment_count": comment_count,
+            "invitations": invitations,
+            "share_url": share_url,
+        }),
+    )
 }
 
 /// Show form to edit an item
 #[rustapi_rs::get("/items/{id}/edit")]
 pub async fn edit_item_form(
     State(state): State<AppState>,
-    cookies: AppCookies,
+    RequireAuth(user): RequireAuth,
+    CsrfToken(csrf_token): CsrfToken,
     Path(id): Path<i64>,
 ) -> Response {
-    let user = match get_current_user(&state, &cookies).await {
-        Some(u) => u,
-        None => return Redirect::to("/login").into_response(),
-    };
-
     let mut context = Context::new();
-    context.insert("user", &Some(&user));
+    context.insert("csrf_token", &csrf_token);
 
-    let item = match state.db.get_item(id, user.id).await {
+    let mut item = match state.db.get_item(id, user.id).await {
         Ok(Some(item)) => item,
         Ok(None) => {
-            return Redirect::to("/items?error=not_found").into_response();
+            return item_not_found(&state);
         }
         Err(e) => {
-            eprintln!("Database error: {}", e);
-            return Redirect::to("/items?error=database").into_response();
+            tracing::error!(error = %e, "database error");
+            return database_error(&state);
         }
     };
 
+    match state.db.get_item_tags(item.id).await {
+        Ok(tags) => item.tags = tags,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return database_error(&state);
+        }
+    }
+
     context.insert("item", &Some(&item));
 
-    render_template(&state, "items/form.html", &context)
+    let response = render(&state, "items/form.html", Some(&user), context);
+    with_json(response, StatusCode::OK, &item)
 }
 
-/// Update an item
+/// Update an item; clients preferring JSON get it back instead of the redirect
 #[rustapi_rs::post("/items/{id}")]
 pub async fn update_item(
     State(state): State<AppState>,
-    cookies: AppCookies,
+    RequireAuth(user): RequireAuth,
     Path(id): Path<i64>,
+    CsrfProtected(csrf_token): CsrfProtected,
     Form(form): Form<ItemForm>,
 ) -> Response {
-    let user = match get_current_user(&state, &cookies).await {
-        Some(u) => u,
-        None => return Redirect::to("/login").into_response(),
-    };
-
     let mut context = Context::new();
-    context.insert("user", &Some(&user));
+    context.insert("csrf_token", &csrf_token);
 
     // Validate
-    if let Err(validation_errors) = form.validate() {
+    if let Err(validation_errors) = form.validate_configured(&state) {
         if let Ok(Some(item)) = state.db.get_item(id, user.id).await {
             context.insert("item", &Some(&item));
         }
 
-        let error_msg = format!("Validation error: {:?}", validation_errors);
-
-        context.insert("error", &error_msg);
-        return render_template(&state, "items/form.html", &context);
+        let errors = field_errors(&validation_errors);
+        context.insert("error", &state.messages.t(INVALID_FORM));
+        context.insert("errors", &errors);
+        return invalid_form(
+            render(&state, "items/form.html", Some(&user), context),
+            &errors,
+        );
     }
 
     let description = form
@@ -159,209 +424,2399 @@ pub async fn update_item(
         .map(|d| d.trim())
         .filter(|d| !d.is_empty());
 
-    match state
+    let updated = state
         .db
-        .update_item(id, user.id, form.title.trim(), description)
-        .await
-    {
-        Ok(Some(_)) => Redirect::to("/items?success=updated").into_response(),
-        Ok(None) => Redirect::to("/items?error=not_found").into_response(),
+        .update_item_with_tags(
+            id,
+            user.id,
+            form.title.trim(),
+            description,
+            form.due_date().unwrap_or_default(),
+            form.tag_list(),
+        )
+        .await;
+
+    match updated {
+        Ok(Some(item)) => {
+            state.metrics.record_item_operation("update");
+            state
+                .item_events
+                .publish(user.id, item.id, ItemEventKind::Updated);
+            let response = flash::redirect(&state, "/items", Flash::success("Item updated"));
+            with_json(response, StatusCode::OK, &item)
+        }
+        Ok(None) => item_not_found(&state),
         Err(e) => {
-            eprintln!("Database error: {}", e);
+            tracing::error!(error = %e, "database error");
             if let Ok(Some(item)) = state.db.get_item(id, user.id).await {
                 context.insert("item", &Some(&item));
             }
-            context.insert("error", "Failed to update item");
-            render_template(&state, "items/form.html", &context)
+            context.insert("error", &state.messages.t("items.update_failed"));
+            with_json_error(
+                render(&state, "items/form.html", Some(&user), context),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database_error",
+            )
         }
     }
 }
 
-/// Delete an item
+/// Move an item to the trash
 #[rustapi_rs::post("/items/{id}/delete")]
 pub async fn delete_item(
     State(state): State<AppState>,
-    cookies: AppCookies,
+    RequireAuth(user): RequireAuth,
+    _csrf: CsrfProtected,
     Path(id): Path<i64>,
 ) -> Response {
-    let user = match get_current_user(&state, &cookies).await {
-        Some(u) => u,
-        None => return Redirect::to("/login").into_response(),
-    };
-
     match state.db.delete_item(id, user.id).await {
-        Ok(true) => Redirect::to("/items?success=deleted").into_response(),
-        Ok(false) => Redirect::to("/items?error=not_found").into_response(),
+        Ok(true) => {
+            state.metrics.record_item_operation("delete");
+            state
+                .item_events
+                .publish(user.id, id, ItemEventKind::Deleted);
+            flash::redirect(&state, "/items", Flash::success("Item moved to the trash"))
+        }
+        Ok(false) => flash::redirect(
+            &state,
+            "/items",
+            Flash::error(state.messages.t(ITEM_NOT_FOUND)),
+        ),
         Err(e) => {
-            eprintln!("Database error: {}", e);
-            Redirect::to("/items?error=database").into_response()
+            tracing::error!(error = %e, "database error");
+            flash::redirect(&state, "/items", Flash::error(state.messages.t(TRY_AGAIN)))
         }
     }
 }
 
-// Helper function to render templates
-fn render_template(state: &AppState, template: &str, context: &Context) -> Response {
-    match state.tera.render(template, context) {
-        Ok(html) => Html(html).into_response(),
+/// Create a copy of an item, with the same description and tags and fresh
+/// timestamps, titled "Copy of ..."
+#[rustapi_rs::post("/items/{id}/duplicate")]
+pub async fn duplicate_item(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    _csrf: CsrfProtected,
+    Path(id): Path<i64>,
+) -> Response {
+    let item = match state.db.get_item(id, user.id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => {
+            return flash::redirect(
+                &state,
+                "/items",
+                Flash::error(state.messages.t(ITEM_NOT_FOUND)),
+            )
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return flash::redirect(&state, "/items", Flash::error(state.messages.t(TRY_AGAIN)));
+        }
+    };
+    match remaining_items(&state, user.id).await {
+        Ok(Some(0)) => {
+            return flash::redirect(&state, "/items", Flash::error(item_limit_message(&state)))
+        }
+        Ok(_) => {}
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return flash::redirect(&state, "/items", Flash::error(state.messages.t(TRY_AGAIN)));
+        }
+    }
+
+    let copy = CreateItem {
+        user_id: user.id,
+        title: item.copy_title(state.config.item_limits.title),
+        description: item.description.clone(),
+        due_at: item.due_at,
+    };
+    let created = match state.db.get_item_tags(item.id).await {
+        Ok(tags) => state.db.create_item_with_tags(copy, tags).await,
+        Err(e) => Err(e),
+    };
+    match created {
+        Ok(copy) => {
+            state.metrics.record_item_operation("duplicate");
+            state
+                .item_events
+                .publish(user.id, copy.id, ItemEventKind::Created);
+            flash::redirect(&state, "/items", Flash::success("Item duplicated"))
+        }
         Err(e) => {
-            eprintln!("Template error: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, "Template error").into_response()
+            tracing::error!(error = %e, "database error");
+            flash::redirect(&state, "/items", Flash::error(state.messages.t(TRY_AGAIN)))
         }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::test_utils::{
-        cleanup_db, cookies_for_user, empty_cookies, header_value, setup_test_state,
+/// Apply one action to several selected items
+///
+/// The whole operation is rejected if any selected id is not one of the
+/// user's items, rather than silently skipping it.
+#[rustapi_rs::post("/items/bulk")]
+pub async fn bulk_items(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    _csrf: CsrfProtected,
+    Form(form): Form<BulkItemsForm>,
+) -> Response {
+    let Some(action) = BulkAction::parse(&form.action) else {
+        return flash::redirect(
+            &state,
+            "/items",
+            Flash::error(state.messages.t("items.unknown_bulk_action")),
+        );
+    };
+    if form.ids.is_empty() {
+        return flash::redirect(
+            &state,
+            "/items",
+            Flash::error(state.messages.t("items.select_items")),
+        );
+    }
+
+    let (result, operation, event, not_found) = match action {
+        BulkAction::Delete => (
+            state.db.delete_items(user.id, &form.ids).await,
+            "bulk_delete",
+            ItemEventKind::Deleted,
+            "items.bulk_not_found",
+        ),
+        BulkAction::Archive => (
+            state.db.archive_items(user.id, &form.ids).await,
+            "bulk_archive",
+            ItemEventKind::Updated,
+            "items.bulk_archive_not_found",
+        ),
     };
-    use rustapi_rs::Path;
 
-    async fn setup_user(state: &AppState) -> (i64, AppCookies) {
-        let user = state
-            .db
-            .create_user("user", "user@example.com", "hash")
-            .await
-            .expect("create user");
-        let cookies = cookies_for_user(&state.jwt_secret, user.id, &user.username);
-        (user.id, cookies)
+    match result {
+        Ok(0) => flash::redirect(&state, "/items", Flash::error(state.messages.t(not_found))),
+        Ok(count) => {
+            state.metrics.record_item_operation(operation);
+            for &id in &form.ids {
+                state.item_events.publish(user.id, id, event);
+            }
+            let message = match action {
+                BulkAction::Delete => format!("Moved {} to the trash", item_count(count as i64)),
+                BulkAction::Archive => format!("Archived {}", item_count(count as i64)),
+            };
+            flash::redirect(&state, "/items", Flash::success(message))
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            flash::redirect(&state, "/items", Flash::error(state.messages.t(TRY_AGAIN)))
+        }
     }
+}
 
-    #[tokio::test]
-    async fn list_items_requires_auth() {
-        let (state, path) = setup_test_state().await;
-        let response = list_items(State(state.clone()), empty_cookies()).await;
-        assert_eq!(response.status(), StatusCode::FOUND);
-        assert_eq!(
-            header_value(&response, "Location"),
-            Some("/login".to_string())
+/// Save the manual order of the current user's items
+///
+/// `ids` lists items in their new order, ahead of the user's other items;
+/// see `Database::reorder_items`. The whole order is rejected if any id is
+/// not one of the user's items.
+#[rustapi_rs::post("/items/reorder")]
+pub async fn reorder_items(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    _csrf: CsrfProtected,
+    Form(form): Form<ReorderItemsForm>,
+) -> Response {
+    if form.ids.is_empty() {
+        let response = flash::redirect(
+            &state,
+            "/items",
+            Flash::error(state.messages.t("items.nothing_to_reorder")),
         );
-        cleanup_db(path);
+        return with_json_error(response, StatusCode::BAD_REQUEST, "validation_failed");
     }
 
-    #[tokio::test]
-    async fn list_items_returns_ok_for_authenticated_user() {
-        let (state, path) = setup_test_state().await;
-        let (user_id, cookies) = setup_user(&state).await;
-        state
-            .db
-            .create_item(CreateItem {
-                user_id,
-                title: "Item".to_string(),
-                description: None,
-            })
-            .await
-            .expect("create item");
+    match state.db.reorder_items(user.id, &form.ids).await {
+        Ok(true) => {
+            state.metrics.record_item_operation("reorder");
+            let response = flash::redirect(&state, "/items", Flash::success("Order saved"));
+            with_json(response, StatusCode::OK, &json!({ "ids": form.ids }))
+        }
+        Ok(false) => item_not_found(&state),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            database_error(&state)
+        }
+    }
+}
 
-        let response = list_items(State(state.clone()), cookies).await;
-        assert_eq!(response.status(), StatusCode::OK);
-        cleanup_db(path);
+/// Stream changes to the current user's items as server-sent events
+///
+/// Items created, updated, archived or moved to the trash through the item
+/// pages or the JSON API each send a `created`, `updated` or `deleted`
+/// event; see `ItemEvents::stream_for`. The stream stays open until the
+/// client goes away.
+#[rustapi_rs::get("/items/events")]
+pub async fn item_events(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+) -> Response {
+    Sse::new(state.item_events.stream_for(user.id))
+        .keep_alive(KeepAlive::new().interval(events::KEEP_ALIVE_INTERVAL))
+        .into_response()
+}
+
+/// List the current user's trashed items
+#[rustapi_rs::get("/items/trash")]
+pub async fn show_trash(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    CsrfToken(csrf_token): CsrfToken,
+    FlashMessage(flash): FlashMessage,
+) -> Response {
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+    if let Some(flash) = flash {
+        flash.insert_into(&mut context);
     }
 
-    #[tokio::test]
-    async fn create_item_validates_title() {
-        let (state, path) = setup_test_state().await;
-        let (user_id, cookies) = setup_user(&state).await;
+    let items = match state.db.get_trashed_items(user.id).await {
+        Ok(items) => items,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", &state.messages.t("items.load_trash_failed"));
+            vec![]
+        }
+    };
+    context.insert("items", &items);
 
-        let response = create_item(
-            State(state.clone()),
-            cookies,
-            Form(ItemForm {
-                title: "".to_string(),
-                description: None,
-            }),
-        )
-        .await;
+    render(&state, "items/trash.html", Some(&user), context)
+}
 
-        assert_eq!(response.status(), StatusCode::OK);
-        let items = state.db.get_user_items(user_id).await.expect("items");
-        assert!(items.is_empty());
-        cleanup_db(path);
-    }
+/// List the current user's items past their due date, most overdue first
+///
+/// Clients preferring JSON get `{"items": [...]}`.
+#[rustapi_rs::get("/items/overdue")]
+pub async fn show_overdue(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    CsrfToken(csrf_token): CsrfToken,
+) -> Response {
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
 
-    #[tokio::test]
-    async fn create_item_redirects_on_success() {
-        let (state, path) = setup_test_state().await;
-        let (_user_id, cookies) = setup_user(&state).await;
+    let items = match state.db.get_overdue_items(user.id, today_for(&user)).await {
+        Ok(items) => items,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", &state.messages.t("items.load_overdue_failed"));
+            let response = render(&state, "items/overdue.html", Some(&user), context);
+            return with_json_error(
+                response,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database_error",
+            );
+        }
+    };
+    context.insert("items", &items);
 
-        let response = create_item(
-            State(state.clone()),
-            cookies,
-            Form(ItemForm {
-                title: "New".to_string(),
-                description: Some("Desc".to_string()),
-            }),
-        )
-        .await;
+    let response = render(&state, "items/overdue.html", Some(&user), context);
+    with_json(response, StatusCode::OK, &json!({ "items": items }))
+}
 
-        assert_eq!(response.status(), StatusCode::FOUND);
-        assert_eq!(
-            header_value(&response, "Location"),
-            Some("/items?success=created".to_string())
-        );
-        cleanup_db(path);
+// Due dates are days in the user's own timezone, so "today" is too
+fn today_for(user: &UserInfo) -> NaiveDate {
+    let tz = user.timezone.parse().unwrap_or(Tz::UTC);
+    Utc::now().with_timezone(&tz).date_naive()
+}
+
+/// Restore a trashed item
+#[rustapi_rs::post("/items/{id}/restore")]
+pub async fn restore_item(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    _csrf: CsrfProtected,
+    Path(id): Path<i64>,
+) -> Response {
+    match state.db.restore_item(id, user.id).await {
+        Ok(true) => {
+            state.metrics.record_item_operation("restore");
+            flash::redirect(&state, "/items/trash", Flash::success("Item restored"))
+        }
+        Ok(false) => flash::redirect(
+            &state,
+            "/items/trash",
+            Flash::error(state.messages.t(ITEM_NOT_FOUND)),
+        ),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            flash::redirect(
+                &state,
+                "/items/trash",
+                Flash::error(state.messages.t(TRY_AGAIN)),
+            )
+        }
     }
+}
 
-    #[tokio::test]
-    async fn edit_item_form_redirects_when_missing() {
-        let (state, path) = setup_test_state().await;
-        let (_user_id, cookies) = setup_user(&state).await;
+/// Archive an item, taking it off the default item list
+#[rustapi_rs::post("/items/{id}/archive")]
+pub async fn archive_item(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    _csrf: CsrfProtected,
+    Path(id): Path<i64>,
+) -> Response {
+    set_archived(&state, &user, id, true).await
+}
 
-        let response = edit_item_form(State(state.clone()), cookies, Path(999)).await;
-        assert_eq!(response.status(), StatusCode::FOUND);
-        assert_eq!(
-            header_value(&response, "Location"),
-            Some("/items?error=not_found".to_string())
-        );
-        cleanup_db(path);
+/// Move an archived item back to the default item list
+#[rustapi_rs::post("/items/{id}/unarchive")]
+pub async fn unarchive_item(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    _csrf: CsrfProtected,
+    Path(id): Path<i64>,
+) -> Response {
+    set_archived(&state, &user, id, false).await
+}
+
+// Redirects to the list the item just left
+async fn set_archived(state: &AppState, user: &UserInfo, id: i64, archived: bool) -> Response {
+    let (from, operation, message) = if archived {
+        ("/items", "archive", "Item archived")
+    } else {
+        (
+            "/items?archived=1",
+            "unarchive",
+            "Item moved back to your items",
+        )
+    };
+    match state.db.set_item_archived(id, user.id, archived).await {
+        Ok(true) => {
+            state.metrics.record_item_operation(operation);
+            state
+                .item_events
+                .publish(user.id, id, ItemEventKind::Updated);
+            flash::redirect(state, from, Flash::success(message))
+        }
+        Ok(false) => flash::redirect(state, from, Flash::error(state.messages.t(ITEM_NOT_FOUND))),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            flash::redirect(state, from, Flash::error(state.messages.t(TRY_AGAIN)))
+        }
     }
+}
 
-    #[tokio::test]
-    async fn update_item_redirects_when_missing() {
-        let (state, path) = setup_test_state().await;
-        let (_user_id, cookies) = setup_user(&state).await;
+/// Permanently delete a trashed item, along with its attachment files
+#[rustapi_rs::post("/items/{id}/purge")]
+pub async fn purge_item(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    _csrf: CsrfProtected,
+    Path(id): Path<i64>,
+) -> Response {
+    // Attachment rows go with the item, so find the files first
+    let attachments = match state.db.get_item_attachments(id).await {
+        Ok(attachments) => attachments,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return flash::redirect(
+                &state,
+                "/items/trash",
+                Flash::error(state.messages.t(TRY_AGAIN)),
+            );
+        }
+    };
+
+    match state.db.purge_item(id, user.id).await {
+        Ok(true) => {
+            for attachment in attachments {
+                if let Err(e) = state.config.uploads.remove(&attachment.stored_name).await {
+                    tracing::error!(error = %e, "upload storage error");
+                }
+            }
+            state.metrics.record_item_operation("purge");
+            flash::redirect(
+                &state,
+                "/items/trash",
+                Flash::success("Item deleted permanently"),
+            )
+        }
+        Ok(false) => flash::redirect(
+            &state,
+            "/items/trash",
+            Flash::error(state.messages.t(ITEM_NOT_FOUND)),
+        ),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            flash::redirect(
+                &state,
+                "/items/trash",
+                Flash::error(state.messages.t(TRY_AGAIN)),
+            )
+        }
+    }
+}
+
+/// Download all of the current user's items as a JSON array
+///
+/// Has the long timeout; see `route_limits::ROUTE_OVERRIDES`.
+#[rustapi_rs::get("/items/export.json")]
+pub async fn export_items(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+) -> Response {
+    let items = match state.db.get_user_items(user.id).await {
+        Ok(mut items) => state.db.load_item_tags(&mut items).await.map(|()| items),
+        Err(e) => Err(e),
+    };
+
+    match items {
+        Ok(items) => {
+            let mut response = Json(items).into_response();
+            if let Ok(value) = "attachment; filename=\"items.json\"".parse() {
+                response.headers_mut().insert("Content-Disposition", value);
+            }
+            response
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to export items").into_response()
+        }
+    }
+}
+
+/// RSS feed of a user's newest items, for feed readers
+///
+/// Feed readers cannot log in, so the feed is opened with the user's feed
+/// token, from the profile page, in `?token=`. Unknown tokens get a 404
+/// rather than the login page.
+#[rustapi_rs::get("/items/feed.xml")]
+pub async fn item_feed(State(state): State<AppState>, Query(query): Query<FeedQuery>) -> Response {
+    let Some(token) = query.token.filter(|token| !token.is_empty()) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let loaded = async {
+        let Some(user_id) = state
+            .db
+            .find_feed_token_user(&tokens::hash_token(&token))
+            .await?
+        else {
+            return Ok(None);
+        };
+        let Some(user) = state.db.find_user_by_id(user_id).await? else {
+            return Ok(None);
+        };
+        let mut items = state
+            .db
+            .get_user_items_sorted(user.id, ItemSort::newest(), feed::FEED_ITEMS, 0)
+            .await?;
+        state.db.load_item_tags(&mut items).await?;
+        Ok::<_, sqlx::Error>(Some((user, items)))
+    };
+
+    match loaded.await {
+        Ok(Some((user, items))) => {
+            let mut response =
+                feed::rss(&state.config.public_url, &user.username, &items).into_response();
+            if let Ok(value) = feed::CONTENT_TYPE.parse() {
+                response.headers_mut().insert("Content-Type", value);
+            }
+            response
+        }
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Create items from an uploaded JSON array, as produced by the export
+///
+/// Every record is validated like the item form and always created under
+/// the current user; records that fail, or would go over
+/// `MAX_ITEMS_PER_USER`, are skipped and counted. Has the long timeout; see
+/// `route_limits::ROUTE_OVERRIDES`.
+#[rustapi_rs::post("/items/import")]
+pub async fn import_items(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    _csrf: CsrfProtected,
+    form: MultipartForm,
+) -> Response {
+    let Some(file) = form.file("file").filter(|f| !f.bytes.is_empty()) else {
+        return flash::redirect(
+            &state,
+            "/items",
+            Flash::error(state.messages.t("items.import_no_file")),
+        );
+    };
+    let records: Vec<serde_json::Value> = match serde_json::from_slice(&file.bytes) {
+        Ok(records) => records,
+        Err(_) => {
+            return flash::redirect(
+                &state,
+                "/items",
+                Flash::error(state.messages.t("items.import_not_json")),
+            )
+        }
+    };
+
+    let mut remaining = match remaining_items(&state, user.id).await {
+        Ok(remaining) => remaining,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return flash::redirect(&state, "/items", Flash::error(state.messages.t(TRY_AGAIN)));
+        }
+    };
+    let (mut imported, mut failed) = (0, 0);
+    for record in records {
+        if remaining == Some(0) {
+            failed += 1;
+            continue;
+        }
+        let Ok(form) = serde_json::from_value::<ImportItem>(record).map(ImportItem::into_form)
+        else {
+            failed += 1;
+            continue;
+        };
+        if form.validate_configured(&state).is_err() {
+            failed += 1;
+            continue;
+        }
+
+        let created = state
+            .db
+            .create_item_with_tags(
+                CreateItem {
+                    user_id: user.id,
+                    title: form.title.trim().to_string(),
+                    description: form
+                        .description
+                        .as_deref()
+                        .map(str::trim)
+                        .filter(|d| !d.is_empty())
+                        .map(str::to_string),
+                    due_at: form.due_date().unwrap_or_default(),
+                },
+                form.tag_list(),
+            )
+            .await;
+        match created {
+            Ok(item) => {
+                state.metrics.record_item_operation("import");
+                state
+                    .item_events
+                    .publish(user.id, item.id, ItemEventKind::Created);
+                imported += 1;
+                remaining = remaining.map(|n| n - 1);
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "database error");
+                failed += 1;
+            }
+        }
+    }
+
+    let (imported_count, skipped_count) = (imported.to_string(), failed.to_string());
+    let args = [
+        ("imported", imported_count.as_str()),
+        ("skipped", skipped_count.as_str()),
+    ];
+    let flash = match (failed, imported) {
+        (0, 1) => Flash::success(state.messages.t_with("items.imported.one", &args)),
+        (0, _) => Flash::success(state.messages.t_with("items.imported.other", &args)),
+        (_, 1) => Flash::error(state.messages.t_with("items.import_skipped.one", &args)),
+        (_, _) => Flash::error(state.messages.t_with("items.import_skipped.other", &args)),
+    };
+    flash::redirect(&state, "/items", flash)
+}
+
+/// How many more items the user may create under `MAX_ITEMS_PER_USER`;
+/// `None` when there is no limit
+///
+/// Trashed items do not count. Two requests racing at the limit can both
+/// pass the check, so the limit may be overshot by a concurrent create.
+pub(crate) async fn remaining_items(
+    state: &AppState,
+    user_id: i64,
+) -> Result<Option<i64>, sqlx::Error> {
+    let Some(max) = state.config.max_items_per_user else {
+        return Ok(None);
+    };
+    let count = state.db.count_user_items(user_id).await?;
+    Ok(Some((max - count).max(0)))
+}
+
+/// Message shown in place of the item form's result at the limit
+fn item_limit_message(state: &AppState) -> String {
+    let max = state.config.max_items_per_user.unwrap_or_default();
+    let key = if max == 1 {
+        "items.limit_reached.one"
+    } else {
+        "items.limit_reached.other"
+    };
+    state.messages.t_with(key, &[("max", &max.to_string())])
+}
+
+// Back to the list with an error; JSON clients get a 404
+fn item_not_found(state: &AppState) -> Response {
+    let response = flash::redirect(
+        state,
+        "/items",
+        Flash::error(state.messages.t(ITEM_NOT_FOUND)),
+    );
+    with_json_error(response, StatusCode::NOT_FOUND, "not_found")
+}
+
+// Back to the list after a failed query; JSON clients get a 500
+fn database_error(state: &AppState) -> Response {
+    let response = flash::redirect(state, "/items", Flash::error(state.messages.t(TRY_AGAIN)));
+    with_json_error(
+        response,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "database_error",
+    )
+}
+
+// A form re-rendered with field errors; JSON clients get a 422 with the same
+// messages
+fn invalid_form(response: Response, errors: &FieldErrors) -> Response {
+    with_json(
+        response,
+        StatusCode::UNPROCESSABLE_ENTITY,
+        &ApiErrorResponse::validation(errors).body(),
+    )
+}
+
+// The new-item form after a failed query; JSON clients get a 500
+fn create_failed(state: &AppState, user: &UserInfo, context: Context) -> Response {
+    let response = render(state, "items/form.html", Some(user), context);
+    with_json_error(
+        response,
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "database_error",
+    )
+}
+
+// The redirect after a create, now or by an earlier request with the same
+// idempotency key; JSON clients get a 201 with the item
+async fn created(state: &AppState, id: i64, user_id: i64) -> Response {
+    let response = flash::redirect(state, "/items", Flash::success("Item created"));
+    match load_item(state, id, user_id).await {
+        Ok(Some(item)) => with_json(response, StatusCode::CREATED, &item),
+        // Deleted since it was created
+        Ok(None) => with_json_error(response, StatusCode::NOT_FOUND, "not_found"),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            with_json_error(
+                response,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database_error",
+            )
+        }
+    }
+}
+
+// "1 item", "3 items"
+fn item_count(count: i64) -> String {
+    format!("{} {}", count, if count == 1 { "item" } else { "items" })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_path::BasePath;
+    use crate::extractors::UploadedFile;
+    use crate::flash::{FLASH_COOKIE, ITEM_NOT_FOUND};
+    use crate::models::CreateAttachment;
+    use crate::negotiate::negotiate;
+    use crate::test_utils::{
+        assert_well_formed, body_string, cleanup_db, flash_from, header_value, request_with_state,
+        set_cookie_value, setup_page_state, setup_test_state, test_csrf, test_csrf_token,
+    };
+    use rustapi_rs::{FromRequest, FromRequestParts, Path};
+    use std::sync::Arc;
+
+    async fn setup_user(state: &AppState) -> (i64, RequireAuth) {
+        let user = state
+            .db
+            .create_user("user", "user@example.com", "hash")
+            .await
+            .expect("create user");
+        (user.id, RequireAuth(UserInfo::from(user)))
+    }
+
+    #[tokio::test]
+    async fn list_items_requires_auth() {
+        let (state, path) = setup_test_state().await;
+        let mut req = request_with_state(&state, http::Request::get("/items"), "");
+        let rejection = RequireAuth::from_request(&mut req).await.err();
+        assert_eq!(rejection.map(|e| e.status), Some(StatusCode::UNAUTHORIZED));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn list_items_returns_ok_for_authenticated_user() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        state
+            .db
+            .create_item(CreateItem {
+                user_id,
+                title: "Item".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+
+        let response = list_items(
+            State(state.clone()),
+            auth,
+            test_csrf_token(),
+            FlashMessage(None),
+            Query(ListItemsQuery::default()),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn list_items_clamps_out_of_range_page() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        state
+            .db
+            .create_item(CreateItem {
+                user_id,
+                title: "Only".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+
+        let response = list_items(
+            State(state.clone()),
+            auth,
+            test_csrf_token(),
+            FlashMessage(None),
+            Query(ListItemsQuery {
+                page: Some(50),
+                per_page: Some(500),
+                ..Default::default()
+            }),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_string(response).await.contains("page 1 of 1"));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn list_items_filters_by_search_query() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        for title in ["Buy milk", "Walk dog", "Buy bread"] {
+            state
+                .db
+                .create_item(CreateItem {
+                    user_id,
+                    title: title.to_string(),
+                    description: None,
+                    due_at: None,
+                })
+                .await
+                .expect("create item");
+        }
+
+        let response = list_items(
+            State(state.clone()),
+            auth,
+            test_csrf_token(),
+            FlashMessage(None),
+            Query(ListItemsQuery {
+                q: Some("  buy ".to_string()),
+                ..Default::default()
+            }),
+        )
+        .await;
+        assert!(body_string(response).await.contains("q=buy items=2 "));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn list_items_exposes_active_sort() {
+        let (state, path) = setup_test_state().await;
+        let (_user_id, auth) = setup_user(&state).await;
+
+        let response = list_items(
+            State(state.clone()),
+            auth,
+            test_csrf_token(),
+            FlashMessage(None),
+            Query(ListItemsQuery {
+                sort: Some("title".to_string()),
+                order: Some("desc".to_string()),
+                ..Default::default()
+            }),
+        )
+        .await;
+        assert!(body_string(response)
+            .await
+            .ends_with("sort=title order=desc"));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn list_items_ranks_searches_unless_sorted() {
+        let (state, path) = setup_test_state().await;
+        let (_user_id, auth) = setup_user(&state).await;
+        let list = |query: ListItemsQuery| {
+            let state = state.clone();
+            let auth = RequireAuth(auth.0.clone());
+            async move {
+                body_string(
+                    list_items(
+                        State(state),
+                        auth,
+                        test_csrf_token(),
+                        FlashMessage(None),
+                        Query(query),
+                    )
+                    .await,
+                )
+                .await
+            }
+        };
+        let param = |value: &str| Some(value.to_string());
+
+        let body = list(ListItemsQuery {
+            q: param("milk"),
+            ..Default::default()
+        })
+        .await;
+        assert!(body.ends_with("sort=relevance order=asc"));
+
+        let body = list(ListItemsQuery {
+            q: param("milk"),
+            sort: param("title"),
+            ..Default::default()
+        })
+        .await;
+        assert!(body.ends_with("sort=title order=asc"));
+
+        // Without a keyword there is nothing to rank
+        let body = list(ListItemsQuery {
+            sort: param("relevance"),
+            ..Default::default()
+        })
+        .await;
+        assert!(body.ends_with("sort=position order=asc"));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn create_item_validates_title() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+
+        let mut req = request_with_state(
+            &state,
+            http::Request::post("/items"),
+            "title=&description=&tags=",
+        );
+        let form = ValidatedForm::<ItemForm>::from_request(&mut req)
+            .await
+            .expect("form parses");
+        let response = create_item(
+            State(state.clone()),
+            auth,
+            test_csrf(),
+            IdempotencyKey(None),
+            form,
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            body_string(response).await,
+            "ITEMS FORM errors.title=Title is required error=Please correct the highlighted fields"
+        );
+        let items = state.db.get_user_items(user_id).await.expect("items");
+        assert!(items.is_empty());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn create_item_redirects_on_success() {
+        let (state, path) = setup_test_state().await;
+        let (_user_id, auth) = setup_user(&state).await;
+
+        let response = create_item(
+            State(state.clone()),
+            auth,
+            test_csrf(),
+            IdempotencyKey(None),
+            ValidatedForm(Ok(ItemForm {
+                title: "New".to_string(),
+                description: Some("Desc".to_string()),
+                tags: String::new(),
+                due_at: None,
+            })),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("Item created"))
+        );
+        assert!(state
+            .metrics
+            .encode()
+            .contains(r#"item_operations_total{operation="create"} 1"#));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn item_redirects_keep_the_base_path() {
+        let (mut state, path) = setup_test_state().await;
+        let config = Arc::make_mut(&mut state.config);
+        config.base_path = BasePath::parse("/app").expect("base path");
+        config.cookies = config.cookies.clone().with_path("/app");
+        let (_user_id, auth) = setup_user(&state).await;
+
+        let response = create_item(
+            State(state.clone()),
+            auth,
+            test_csrf(),
+            IdempotencyKey(None),
+            ValidatedForm(Ok(ItemForm {
+                title: "New".to_string(),
+                description: None,
+                tags: String::new(),
+                due_at: None,
+            })),
+        )
+        .await;
+
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/app/items".to_string())
+        );
+        let set_cookie = header_value(&response, "Set-Cookie").unwrap_or_default();
+        assert!(set_cookie.contains("; Path=/app;"), "{}", set_cookie);
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("Item created"))
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn item_writes_reach_subscribed_streams() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let mut events = state.item_events.subscribe();
+
+        create_item(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            test_csrf(),
+            IdempotencyKey(None),
+            ValidatedForm(Ok(item_form("Live"))),
+        )
+        .await;
+        let event = events.try_recv().expect("created event");
+        assert_eq!(
+            (event.user_id, event.kind),
+            (user_id, ItemEventKind::Created)
+        );
+
+        delete_item(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            test_csrf(),
+            Path(event.item_id),
+        )
+        .await;
+        let deleted = events.try_recv().expect("deleted event");
+        assert_eq!(
+            (deleted.item_id, deleted.kind),
+            (event.item_id, ItemEventKind::Deleted)
+        );
+
+        // Failed writes send nothing
+        delete_item(State(state.clone()), auth, test_csrf(), Path(event.item_id)).await;
+        assert!(events.try_recv().is_err());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn item_events_opens_an_event_stream() {
+        let (state, path) = setup_test_state().await;
+        let (_user_id, auth) = setup_user(&state).await;
+
+        let response = item_events(State(state.clone()), auth).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            header_value(&response, "Content-Type").as_deref(),
+            Some("text/event-stream")
+        );
+        assert_eq!(state.item_events.subscriber_count(), 1);
+
+        // Closing the connection drops the body and with it the subscription
+        drop(response);
+        assert_eq!(state.item_events.subscriber_count(), 0);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn create_item_with_a_repeated_idempotency_key_creates_one_item() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let submit = |key: &str| {
+            create_item(
+                State(state.clone()),
+                RequireAuth(auth.0.clone()),
+                test_csrf(),
+                IdempotencyKey(Some(key.to_string())),
+                ValidatedForm(Ok(ItemForm {
+                    title: "Once".to_string(),
+                    description: None,
+                    tags: "a".to_string(),
+                    due_at: None,
+                })),
+            )
+        };
+
+        for key in ["first", "first", "second"] {
+            let response = submit(key).await;
+            assert_eq!(response.status(), StatusCode::FOUND);
+            assert_eq!(
+                flash_from(&state, &response),
+                Some(Flash::success("Item created"))
+            );
+        }
+
+        let items = state.db.get_user_items(user_id).await.expect("items");
+        assert_eq!(items.len(), 2);
+        assert!(state
+            .metrics
+            .encode()
+            .contains(r#"item_operations_total{operation="create"} 2"#));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn new_item_form_carries_an_idempotency_key() {
+        let (state, path) = setup_page_state().await;
+        let (_user_id, auth) = setup_user(&state).await;
+
+        let response = new_item_form(State(state.clone()), auth, test_csrf_token()).await;
+        let body = body_string(response).await;
+        assert!(body.contains(r#"name="idempotency_key" value=""#));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn flash_from_a_redirect_reaches_the_next_page() {
+        let (state, path) = setup_test_state().await;
+        let (_user_id, auth) = setup_user(&state).await;
+        let user = auth.0.clone();
+
+        let response = create_item(
+            State(state.clone()),
+            auth,
+            test_csrf(),
+            IdempotencyKey(None),
+            ValidatedForm(Ok(ItemForm {
+                title: "Flashy".to_string(),
+                description: None,
+                tags: String::new(),
+                due_at: None,
+            })),
+        )
+        .await;
+        let cookie = set_cookie_value(&response, FLASH_COOKIE).expect("flash cookie");
+
+        let list = |cookie: Option<&str>| {
+            let mut builder = http::Request::get("/items");
+            if let Some(cookie) = cookie {
+                builder = builder.header("Cookie", format!("{}={}", FLASH_COOKIE, cookie));
+            }
+            let req = request_with_state(&state, builder, "");
+            let flash = FlashMessage::from_request_parts(&req).expect("read flash");
+            list_items(
+                State(state.clone()),
+                RequireAuth(user.clone()),
+                test_csrf_token(),
+                flash,
+                Query(ListItemsQuery::default()),
+            )
+        };
+        assert!(body_string(list(Some(&cookie)).await)
+            .await
+            .starts_with("ITEMS LIST success=Item created;"));
+        // Once `FlashLayer` has cleared the cookie the message is gone
+        assert!(body_string(list(None).await)
+            .await
+            .starts_with("ITEMS LIST success=;"));
+        // A forged cookie shows nothing
+        let forged = Flash::success("Forged").encode("other-secret");
+        assert!(body_string(list(Some(&forged)).await)
+            .await
+            .starts_with("ITEMS LIST success=;"));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn create_item_stops_at_the_item_limit() {
+        let (mut state, path) = setup_test_state().await;
+        Arc::make_mut(&mut state.config).max_items_per_user = Some(2);
+        let (user_id, auth) = setup_user(&state).await;
+        let create = |title: &str| {
+            create_item(
+                State(state.clone()),
+                RequireAuth(auth.0.clone()),
+                test_csrf(),
+                IdempotencyKey(None),
+                ValidatedForm(Ok(ItemForm {
+                    title: title.to_string(),
+                    description: None,
+                    tags: String::new(),
+                    due_at: None,
+                })),
+            )
+        };
+
+        for title in ["First", "Second"] {
+            assert_eq!(create(title).await.status(), StatusCode::FOUND);
+        }
+        let response = create("Third").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_string(response).await.ends_with(
+            "error=Item limit reached: you can keep at most 2 items. Delete some to add more."
+        ));
+        let items = state.db.get_user_items(user_id).await.expect("items");
+        assert_eq!(items.len(), 2);
+
+        // Trashed items make room
+        state
+            .db
+            .delete_item(items[0].id, user_id)
+            .await
+            .expect("trash item");
+        assert_eq!(create("Third").await.status(), StatusCode::FOUND);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn create_and_update_item_normalize_tags() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+
+        let response = create_item(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            test_csrf(),
+            IdempotencyKey(None),
+            ValidatedForm(Ok(ItemForm {
+                title: "Tagged".to_string(),
+                description: None,
+                tags: " Work, urgent ,work,, ".to_string(),
+                due_at: None,
+            })),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+
+        let item = state.db.get_user_items(user_id).await.expect("items")[0].clone();
+        assert_eq!(
+            state.db.get_item_tags(item.id).await.expect("tags"),
+            ["urgent", "work"]
+        );
+
+        let response = update_item(
+            State(state.clone()),
+            auth,
+            Path(item.id),
+            test_csrf(),
+            Form(ItemForm {
+                title: "Tagged".to_string(),
+                description: None,
+                tags: "Home".to_string(),
+                due_at: None,
+            }),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("Item updated"))
+        );
+        assert_eq!(
+            state.db.get_item_tags(item.id).await.expect("tags"),
+            ["home"]
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn list_items_filters_by_tag() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        for (title, tags) in [
+            ("Report", "work"),
+            ("Groceries", "home"),
+            ("Review", "Work"),
+        ] {
+            let item = state
+                .db
+                .create_item(CreateItem {
+                    user_id,
+                    title: title.to_string(),
+                    description: None,
+                    due_at: None,
+                })
+                .await
+                .expect("create item");
+            state
+                .db
+                .set_item_tags(item.id, &crate::models::normalize_tags(tags))
+                .await
+                .expect("set tags");
+        }
+
+        let response = list_items(
+            State(state.clone()),
+            auth,
+            test_csrf_token(),
+            FlashMessage(None),
+            Query(ListItemsQuery {
+                tag: Some(" WORK ".to_string()),
+                ..Default::default()
+            }),
+        )
+        .await;
+        assert!(body_string(response).await.contains("tag=work q= items=2 "));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn show_item_renders_item_with_comments() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let item = state
+            .db
+            .create_item_with_tags(
+                CreateItem {
+                    user_id,
+                    title: "Detailed".to_string(),
+                    description: Some("The full description".to_string()),
+                    due_at: None,
+                },
+                vec!["home".to_string()],
+            )
+            .await
+            .expect("create item");
+        state
+            .db
+            .add_comment(item.id, user_id, "First comment")
+            .await
+            .expect("add comment");
+
+        let response = show_item(
+            State(state.clone()),
+            auth,
+            test_csrf_token(),
+            Path(item.id),
+            FlashMessage(None),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            body_string(response).await,
+            "ITEM DETAIL Detailed tags=1 attachments=0 comments=1 of 1"
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn show_item_redirects_when_missing() {
+        let (state, path) = setup_test_state().await;
+        let (_user_id, auth) = setup_user(&state).await;
+
+        let response = show_item(
+            State(state.clone()),
+            auth,
+            test_csrf_token(),
+            Path(999),
+            FlashMessage(None),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(ITEM_NOT_FOUND))
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn edit_item_form_redirects_when_missing() {
+        let (state, path) = setup_test_state().await;
+        let (_user_id, auth) = setup_user(&state).await;
+
+        let response =
+            edit_item_form(State(state.clone()), auth, test_csrf_token(), Path(999)).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(ITEM_NOT_FOUND))
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn update_item_redirects_when_missing() {
+        let (state, path) = setup_test_state().await;
+        let (_user_id, auth) = setup_user(&state).await;
 
         let response = update_item(
             State(state.clone()),
-            cookies,
-            Path(999),
-            Form(ItemForm {
-                title: "Title".to_string(),
-                description: None,
-            }),
+            auth,
+            Path(999),
+            test_csrf(),
+            Form(ItemForm {
+                title: "Title".to_string(),
+                description: None,
+                tags: String::new(),
+                due_at: None,
+            }),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(ITEM_NOT_FOUND))
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn delete_item_redirects_on_success() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+
+        let item = state
+            .db
+            .create_item(CreateItem {
+                user_id,
+                title: "Delete".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+
+        let response = delete_item(State(state.clone()), auth, test_csrf(), Path(item.id)).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("Item moved to the trash"))
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn duplicate_item_creates_a_copy() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let original = state
+            .db
+            .create_item_with_tags(
+                CreateItem {
+                    user_id,
+                    title: "Weekly report".to_string(),
+                    description: Some("Send by Friday".to_string()),
+                    due_at: None,
+                },
+                vec!["work".to_string()],
+            )
+            .await
+            .expect("create item");
+
+        let response =
+            duplicate_item(State(state.clone()), auth, test_csrf(), Path(original.id)).await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("Item duplicated"))
+        );
+
+        let items = state.db.get_user_items(user_id).await.expect("items");
+        assert_eq!(items.len(), 2);
+        let copy = &items[0];
+        assert_ne!(copy.id, original.id);
+        assert_eq!(copy.title, "Copy of Weekly report");
+        assert_eq!(copy.description.as_deref(), Some("Send by Friday"));
+        assert!(copy.created_at >= original.created_at);
+        assert_eq!(
+            state.db.get_item_tags(copy.id).await.expect("tags"),
+            ["work"]
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn duplicate_item_redirects_when_missing() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+
+        let response = duplicate_item(State(state.clone()), auth, test_csrf(), Path(999)).await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(ITEM_NOT_FOUND))
+        );
+        assert_eq!(state.db.count_user_items(user_id).await.expect("count"), 0);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn trashed_item_can_be_restored() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let item = state
+            .db
+            .create_item(CreateItem {
+                user_id,
+                title: "Trash me".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+
+        delete_item(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            test_csrf(),
+            Path(item.id),
+        )
+        .await;
+        let response = show_trash(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            test_csrf_token(),
+            FlashMessage(None),
+        )
+        .await;
+        assert_eq!(body_string(response).await, "ITEMS TRASH 1");
+
+        let response = restore_item(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            test_csrf(),
+            Path(item.id),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items/trash".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("Item restored"))
+        );
+        assert!(state
+            .db
+            .get_item(item.id, user_id)
+            .await
+            .expect("get item")
+            .is_some());
+
+        let response = restore_item(State(state.clone()), auth, test_csrf(), Path(item.id)).await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items/trash".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(ITEM_NOT_FOUND))
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn overdue_view_lists_only_items_past_due() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let today = Utc::now().date_naive();
+        let create = |title: &str, due_at: Option<NaiveDate>| {
+            create_item(
+                State(state.clone()),
+                RequireAuth(auth.0.clone()),
+                test_csrf(),
+                IdempotencyKey(None),
+                ValidatedForm(Ok(ItemForm {
+                    title: title.to_string(),
+                    description: None,
+                    tags: String::new(),
+                    due_at: due_at.map(|day| day.to_string()),
+                })),
+            )
+        };
+        create("Past due", Some(today - chrono::Duration::days(3))).await;
+        create("Due later", Some(today + chrono::Duration::days(3))).await;
+        create("No due date", None).await;
+
+        let response = show_overdue(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            test_csrf_token(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "ITEMS OVERDUE Past due,");
+
+        let response = list_items(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            test_csrf_token(),
+            FlashMessage(None),
+            Query(ListItemsQuery::default()),
+        )
+        .await;
+        assert!(body_string(response).await.contains("items=3 overdue=1 "));
+
+        // Archived items are no longer overdue
+        let past_due = state
+            .db
+            .get_overdue_items(user_id, today)
+            .await
+            .expect("overdue items");
+        archive_item(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            test_csrf(),
+            Path(past_due[0].id),
+        )
+        .await;
+        let response = show_overdue(State(state.clone()), auth, test_csrf_token()).await;
+        assert_eq!(body_string(response).await, "ITEMS OVERDUE ");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn create_item_rejects_an_unparseable_due_date() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+
+        let mut req = request_with_state(
+            &state,
+            http::Request::post("/items"),
+            "title=Taxes&description=&tags=&due_at=next+tuesday",
+        );
+        let form = ValidatedForm::<ItemForm>::from_request(&mut req)
+            .await
+            .expect("form parses");
+        let response = create_item(
+            State(state.clone()),
+            auth,
+            test_csrf(),
+            IdempotencyKey(None),
+            form,
         )
         .await;
 
-        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(response.status(), StatusCode::OK);
+        let items = state.db.get_user_items(user_id).await.expect("items");
+        assert!(items.is_empty());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn archived_items_leave_the_default_list() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let mut ids = vec![];
+        for title in ["Done task", "Open task", "Other"] {
+            let item = state
+                .db
+                .create_item(CreateItem {
+                    user_id,
+                    title: title.to_string(),
+                    description: None,
+                    due_at: None,
+                })
+                .await
+                .expect("create item");
+            ids.push(item.id);
+        }
+        let list = |query: ListItemsQuery| {
+            let state = state.clone();
+            let user = auth.0.clone();
+            async move {
+                let response = list_items(
+                    State(state),
+                    RequireAuth(user),
+                    test_csrf_token(),
+                    FlashMessage(None),
+                    Query(query),
+                )
+                .await;
+                body_string(response).await
+            }
+        };
+        let archived = || ListItemsQuery {
+            archived: Some("1".to_string()),
+            ..Default::default()
+        };
+
+        let response = archive_item(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            test_csrf(),
+            Path(ids[0]),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("Item archived"))
+        );
+
+        // Counts and pages follow the view, searches included
+        let active = list(ListItemsQuery {
+            per_page: Some(1),
+            ..Default::default()
+        })
+        .await;
+        assert!(active.contains("page 1 of 2 "), "{}", active);
+        assert!(list(archived()).await.contains("page 1 of 1 "));
+        assert!(list(archived()).await.contains("items=1 "));
+        let search = |archived: Option<String>| ListItemsQuery {
+            q: Some("task".to_string()),
+            archived,
+            ..Default::default()
+        };
+        assert!(list(search(None)).await.contains("items=1 "));
+        assert!(list(search(Some("1".to_string())))
+            .await
+            .contains("items=1 "));
+
+        // Archived items still open, and still count toward the item limit
+        assert!(state
+            .db
+            .get_item(ids[0], user_id)
+            .await
+            .expect("get item")
+            .is_some_and(|item| item.archived));
+        assert_eq!(state.db.count_user_items(user_id).await.expect("count"), 3);
+
+        let response = unarchive_item(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            test_csrf(),
+            Path(ids[0]),
+        )
+        .await;
         assert_eq!(
             header_value(&response, "Location"),
-            Some("/items?error=not_found".to_string())
+            Some("/items?archived=1".to_string())
+        );
+        assert!(list(ListItemsQuery::default()).await.contains("items=3 "));
+        assert!(list(archived()).await.contains("items=0 "));
+
+        // Trashed items cannot be archived
+        delete_item(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            test_csrf(),
+            Path(ids[1]),
+        )
+        .await;
+        let response = archive_item(State(state.clone()), auth, test_csrf(), Path(ids[1])).await;
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(ITEM_NOT_FOUND))
         );
         cleanup_db(path);
     }
 
     #[tokio::test]
-    async fn delete_item_redirects_on_success() {
+    async fn purge_item_removes_trashed_item() {
         let (state, path) = setup_test_state().await;
-        let (user_id, cookies) = setup_user(&state).await;
-
+        let (user_id, auth) = setup_user(&state).await;
         let item = state
             .db
             .create_item(CreateItem {
                 user_id,
-                title: "Delete".to_string(),
+                title: "Gone".to_string(),
                 description: None,
+                due_at: None,
             })
             .await
             .expect("create item");
+        let stored_name = state
+            .config
+            .uploads
+            .store(b"bye")
+            .await
+            .expect("store file");
+        state
+            .db
+            .create_attachment(CreateAttachment {
+                item_id: item.id,
+                filename: "bye.txt".to_string(),
+                stored_name: stored_name.clone(),
+                content_type: "text/plain".to_string(),
+                size: 3,
+            })
+            .await
+            .expect("create attachment");
+        state
+            .db
+            .delete_item(item.id, user_id)
+            .await
+            .expect("trash item");
 
-        let response = delete_item(State(state.clone()), cookies, Path(item.id)).await;
-        assert_eq!(response.status(), StatusCode::FOUND);
+        let response = purge_item(State(state.clone()), auth, test_csrf(), Path(item.id)).await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items/trash".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("Item deleted permanently"))
+        );
+        assert!(state
+            .db
+            .get_trashed_items(user_id)
+            .await
+            .expect("trash")
+            .is_empty());
+        assert!(state.config.uploads.read(&stored_name).await.is_err());
+        cleanup_db(path);
+    }
+
+    fn import_file(bytes: Vec<u8>) -> MultipartForm {
+        MultipartForm {
+            fields: vec![],
+            files: vec![UploadedFile {
+                field: "file".to_string(),
+                filename: "items.json".to_string(),
+                content_type: "application/json".to_string(),
+                bytes: bytes.into(),
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn feed_lists_the_items_of_the_token_owner() {
+        let (state, path) = setup_test_state().await;
+        let (owner_id, owner) = setup_user(&state).await;
+        for title in ["First", "Tom & <Jerry>"] {
+            create_item(
+                State(state.clone()),
+                RequireAuth(owner.0.clone()),
+                test_csrf(),
+                IdempotencyKey(None),
+                ValidatedForm(Ok(ItemForm {
+                    title: title.to_string(),
+                    description: None,
+                    tags: "news".to_string(),
+                    due_at: None,
+                })),
+            )
+            .await;
+        }
+        state
+            .db
+            .set_feed_token(owner_id, &tokens::hash_token("feed-token"))
+            .await
+            .expect("set feed token");
+
+        let feed_for = |token: Option<&str>| {
+            item_feed(
+                State(state.clone()),
+                Query(FeedQuery {
+                    token: token.map(str::to_string),
+                }),
+            )
+        };
+        for token in [None, Some(""), Some("guess")] {
+            assert_eq!(feed_for(token).await.status(), StatusCode::NOT_FOUND);
+        }
+
+        let response = feed_for(Some("feed-token")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            header_value(&response, "Content-Type"),
+            Some(feed::CONTENT_TYPE.to_string())
+        );
+        let xml = body_string(response).await;
+        assert_well_formed(&xml);
+        assert_eq!(xml.matches("<item>").count(), 2);
+        assert!(xml.contains("<title>Tom &amp; &lt;Jerry&gt;</title>"));
+        assert!(xml.contains("<title>First</title>"));
+        assert!(xml.contains("<category>news</category>"));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn export_round_trips_through_import() {
+        let (state, path) = setup_test_state().await;
+        let (owner_id, owner) = setup_user(&state).await;
+        for (title, tags) in [("Alpha", "rust, web"), ("Beta", "")] {
+            create_item(
+                State(state.clone()),
+                RequireAuth(owner.0.clone()),
+                test_csrf(),
+                IdempotencyKey(None),
+                ValidatedForm(Ok(ItemForm {
+                    title: title.to_string(),
+                    description: Some(format!("{} notes", title)),
+                    tags: tags.to_string(),
+                    due_at: None,
+                })),
+            )
+            .await;
+        }
+
+        let response = export_items(State(state.clone()), owner).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            header_value(&response, "Content-Disposition"),
+            Some("attachment; filename=\"items.json\"".to_string())
+        );
+        let mut records: Vec<serde_json::Value> =
+            serde_json::from_str(&body_string(response).await).expect("export is JSON");
+        assert_eq!(records.len(), 2);
+        // Invalid records are skipped, not fatal
+        records.push(serde_json::json!({ "title": "   " }));
+        records.push(serde_json::json!({ "description": "no title" }));
+        records.push(serde_json::json!("not an object"));
+
+        let importer = state
+            .db
+            .create_user("importer", "importer@example.com", "hash")
+            .await
+            .expect("create user");
+        let response = import_items(
+            State(state.clone()),
+            RequireAuth(UserInfo::from(importer.clone())),
+            test_csrf(),
+            import_file(serde_json::to_vec(&records).expect("encode")),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(
+                "Imported 2 items; skipped 3 that were invalid or over the limit"
+            ))
+        );
+
+        // The exported ids and user_id did not touch the original items
+        assert_eq!(state.db.count_user_items(owner_id).await.expect("count"), 2);
+        let mut imported = state
+            .db
+            .get_user_items(importer.id)
+            .await
+            .expect("imported items");
+        state
+            .db
+            .load_item_tags(&mut imported)
+            .await
+            .expect("load tags");
+        let mut copies: Vec<_> = imported
+            .iter()
+            .map(|item| {
+                (
+                    item.title.as_str(),
+                    item.description.as_deref(),
+                    item.tags.clone(),
+                )
+            })
+            .collect();
+        copies.sort();
+        assert_eq!(
+            copies,
+            [
+                (
+                    "Alpha",
+                    Some("Alpha notes"),
+                    vec!["rust".to_string(), "web".to_string()]
+                ),
+                ("Beta", Some("Beta notes"), vec![]),
+            ]
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn import_rejects_non_array_file() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+
+        let response = import_items(
+            State(state.clone()),
+            auth,
+            test_csrf(),
+            import_file(br#"{"title": "Solo"}"#.to_vec()),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error("The file is not a JSON list of items"))
+        );
+        assert_eq!(state.db.count_user_items(user_id).await.expect("count"), 0);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn import_skips_records_over_the_item_limit() {
+        let (mut state, path) = setup_test_state().await;
+        Arc::make_mut(&mut state.config).max_items_per_user = Some(2);
+        let (user_id, auth) = setup_user(&state).await;
+
+        let response = import_items(
+            State(state.clone()),
+            auth,
+            test_csrf(),
+            import_file(br#"[{"title": "A"}, {"title": "B"}, {"title": "C"}]"#.to_vec()),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(
+                "Imported 2 items; skipped 1 that were invalid or over the limit"
+            ))
+        );
+        assert_eq!(state.db.count_user_items(user_id).await.expect("count"), 2);
+        cleanup_db(path);
+    }
+
+    fn bulk_form(ids: &[i64]) -> Form<BulkItemsForm> {
+        Form(BulkItemsForm {
+            ids: ids.to_vec(),
+            action: "delete".to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn bulk_archive_archives_selected_items_all_or_nothing() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let other = state
+            .db
+            .create_user("other", "other@example.com", "hash")
+            .await
+            .expect("create user");
+        let mut ids = Vec::new();
+        for (owner, title) in [(user_id, "One"), (user_id, "Two"), (other.id, "Theirs")] {
+            let item = state
+                .db
+                .create_item(CreateItem {
+                    user_id: owner,
+                    title: title.to_string(),
+                    description: None,
+                    due_at: None,
+                })
+                .await
+                .expect("create item");
+            ids.push(item.id);
+        }
+        let archive = |ids: &[i64]| {
+            Form(BulkItemsForm {
+                ids: ids.to_vec(),
+                action: "archive".to_string(),
+            })
+        };
+
+        let response = bulk_items(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            test_csrf(),
+            archive(&[ids[0], ids[2]]),
+        )
+        .await;
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(
+                "Nothing was archived: some of the selected items were not found"
+            ))
+        );
+        let item = state.db.get_item(ids[0], user_id).await.expect("get");
+        assert!(!item.expect("item exists").archived);
+
+        let response =
+            bulk_items(State(state.clone()), auth, test_csrf(), archive(&ids[..2])).await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("Archived 2 items"))
+        );
+        for &id in &ids[..2] {
+            let item = state.db.get_item(id, user_id).await.expect("get");
+            assert!(item.expect("item exists").archived);
+            let trail = state.db.list_item_audit(id).await.expect("audit trail");
+            assert_eq!(trail.last().map(|e| e.action.as_str()), Some("archive"));
+        }
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn bulk_delete_trashes_selected_items() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let mut ids = Vec::new();
+        for title in ["One", "Two", "Keep"] {
+            let item = state
+                .db
+                .create_item(CreateItem {
+                    user_id,
+                    title: title.to_string(),
+                    description: None,
+                    due_at: None,
+                })
+                .await
+                .expect("create item");
+            ids.push(item.id);
+        }
+
+        let response = bulk_items(
+            State(state.clone()),
+            auth,
+            test_csrf(),
+            bulk_form(&ids[..2]),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/items".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("Moved 2 items to the trash"))
+        );
+        let remaining = state.db.get_user_items(user_id).await.expect("items");
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].title, "Keep");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn reorder_changes_the_list_order_of_own_items_only() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let other = state
+            .db
+            .create_user("other", "other@example.com", "hash")
+            .await
+            .expect("create user");
+        let mut ids = Vec::new();
+        for (owner, title) in [(user_id, "One"), (user_id, "Two"), (other.id, "Theirs")] {
+            let item = state
+                .db
+                .create_item(CreateItem {
+                    user_id: owner,
+                    title: title.to_string(),
+                    description: None,
+                    due_at: None,
+                })
+                .await
+                .expect("create item");
+            ids.push(item.id);
+        }
+        let titles = || async {
+            state
+                .db
+                .get_user_items_sorted(user_id, ItemSort::default(), 10, 0)
+                .await
+                .expect("items")
+                .into_iter()
+                .map(|item| item.title)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(titles().await, ["Two", "One"]);
+
+        let response = reorder_items(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            test_csrf(),
+            Form(ReorderItemsForm {
+                ids: vec![ids[0], ids[1]],
+            }),
+        )
+        .await;
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("Order saved"))
+        );
+        assert_eq!(titles().await, ["One", "Two"]);
+
+        let response = reorder_items(
+            State(state.clone()),
+            auth,
+            test_csrf(),
+            Form(ReorderItemsForm {
+                ids: vec![ids[1], ids[2]],
+            }),
+        )
+        .await;
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(ITEM_NOT_FOUND))
+        );
+        assert_eq!(titles().await, ["One", "Two"]);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn bulk_delete_rejects_foreign_items() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let other = state
+            .db
+            .create_user("other", "other@example.com", "hash")
+            .await
+            .expect("create user");
+        let mut ids = Vec::new();
+        for owner in [user_id, other.id] {
+            let item = state
+                .db
+                .create_item(CreateItem {
+                    user_id: owner,
+                    title: "Mine?".to_string(),
+                    description: None,
+                    due_at: None,
+                })
+                .await
+                .expect("create item");
+            ids.push(item.id);
+        }
+
+        let response = bulk_items(State(state.clone()), auth, test_csrf(), bulk_form(&ids)).await;
         assert_eq!(
             header_value(&response, "Location"),
-            Some("/items?success=deleted".to_string())
+            Some("/items".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(
+                "Nothing was deleted: some of the selected items were not found"
+            ))
+        );
+        assert_eq!(state.db.count_user_items(user_id).await.expect("count"), 1);
+        assert_eq!(state.db.count_user_items(other.id).await.expect("count"), 1);
+        cleanup_db(path);
+    }
+
+    const JSON: Option<&str> = Some("application/json");
+    const HTML: Option<&str> = Some("text/html,application/xhtml+xml,*/*;q=0.8");
+
+    async fn json_body(response: Response) -> serde_json::Value {
+        serde_json::from_str(&body_string(response).await).expect("JSON body")
+    }
+
+    fn item_form(title: &str) -> ItemForm {
+        ItemForm {
+            title: title.to_string(),
+            description: None,
+            tags: "work".to_string(),
+            due_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn item_pages_answer_json_clients() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let item = state
+            .db
+            .create_item_with_tags(
+                CreateItem {
+                    user_id,
+                    title: "Listed".to_string(),
+                    description: None,
+                    due_at: None,
+                },
+                vec!["work".to_string()],
+            )
+            .await
+            .expect("create item");
+        let list = || {
+            list_items(
+                State(state.clone()),
+                RequireAuth(auth.0.clone()),
+                test_csrf_token(),
+                FlashMessage(None),
+                Query(Default::default()),
+            )
+        };
+        let show = |id| {
+            show_item(
+                State(state.clone()),
+                RequireAuth(auth.0.clone()),
+                test_csrf_token(),
+                Path(id),
+                FlashMessage(None),
+            )
+        };
+
+        let response = negotiate(HTML, list().await);
+        assert_eq!(
+            header_value(&response, "Content-Type").as_deref(),
+            Some("text/html; charset=utf-8")
+        );
+        let response = negotiate(JSON, list().await);
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!(body["items"][0]["title"], "Listed");
+        assert_eq!(body["items"][0]["tags"][0], "work");
+        assert_eq!(body["pagination"]["total"], 1);
+
+        let response = negotiate(JSON, show(item.id).await);
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = json_body(response).await;
+        assert_eq!(body["item"]["id"], item.id);
+        assert_eq!(body["comment_count"], 0);
+        let response = negotiate(
+            JSON,
+            edit_item_form(
+                State(state.clone()),
+                RequireAuth(auth.0.clone()),
+                test_csrf_token(),
+                Path(item.id),
+            )
+            .await,
+        );
+        assert_eq!(json_body(response).await["title"], "Listed");
+
+        // A missing item redirects browsers and is a 404 otherwise
+        let response = negotiate(HTML, show(item.id + 1).await);
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(ITEM_NOT_FOUND))
+        );
+        let response = negotiate(JSON, show(item.id + 1).await);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(set_cookie_value(&response, FLASH_COOKIE), None);
+        assert_eq!(json_body(response).await["error"]["code"], "not_found");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn create_and_update_answer_json_clients() {
+        let (state, path) = setup_test_state().await;
+        let (user_id, auth) = setup_user(&state).await;
+        let create = |form| {
+            create_item(
+                State(state.clone()),
+                RequireAuth(auth.0.clone()),
+                test_csrf(),
+                IdempotencyKey(None),
+                ValidatedForm(form),
+            )
+        };
+
+        let response = negotiate(HTML, create(Ok(item_form("Browser"))).await);
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            header_value(&response, "Location").as_deref(),
+            Some("/items")
         );
+        let response = negotiate(JSON, create(Ok(item_form("Script"))).await);
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let created = json_body(response).await;
+        assert_eq!(created["title"], "Script");
+        assert_eq!(created["tags"][0], "work");
+        assert_eq!(state.db.count_user_items(user_id).await.expect("count"), 2);
+
+        let errors: FieldErrors =
+            [("title".to_string(), vec!["Title is required".to_string()])].into();
+        let response = negotiate(HTML, create(Err(errors.clone())).await);
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = negotiate(JSON, create(Err(errors)).await);
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = json_body(response).await;
+        assert_eq!(body["error"]["code"], "validation_failed");
+        assert_eq!(
+            body["error"]["details"]["fields"]["title"][0],
+            "Title is required"
+        );
+
+        let id = created["id"].as_i64().expect("item id");
+        let update = |id, title: &str| {
+            update_item(
+                State(state.clone()),
+                RequireAuth(auth.0.clone()),
+                Path(id),
+                test_csrf(),
+                Form(item_form(title)),
+            )
+        };
+        let response = negotiate(HTML, update(id, "Renamed").await);
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let response = negotiate(JSON, update(id, "Renamed again").await);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(json_body(response).await["title"], "Renamed again");
+
+        let response = negotiate(JSON, update(id, "").await);
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let response = negotiate(JSON, update(id + 100, "Missing").await);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
         cleanup_db(path);
     }
 }
@@ -0,0 +1,39 @@
+use rustapi_rs::prelude::*;
+
+use crate::AppState;
+
+/// Content type of the Prometheus text exposition format
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Export all metrics for Prometheus scraping
+#[rustapi_rs::get("/metrics")]
+pub async fn metrics(State(state): State<AppState>) -> Response {
+    let mut response = state.metrics.encode().into_response();
+    if let Ok(value) = PROMETHEUS_CONTENT_TYPE.parse() {
+        response.headers_mut().insert("Content-Type", value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{body_string, cleanup_db, header_value, setup_test_state};
+
+    #[tokio::test]
+    async fn metrics_uses_prometheus_content_type() {
+        let (state, path) = setup_test_state().await;
+        state.metrics.record_item_operation("create");
+
+        let response = metrics(State(state.clone())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            header_value(&response, "Content-Type"),
+            Some(PROMETHEUS_CONTENT_TYPE.to_string())
+        );
+        assert!(body_string(response)
+            .await
+            .contains(r#"item_operations_total{operation="create"} 1"#));
+        cleanup_db(path);
+    }
+}
@@ -0,0 +1,1229 @@
+use rustapi_rs::prelude::*;
+use tera::Context;
+
+use crate::{
+    extractors::{
+        CsrfProtected, CsrfToken, FlashMessage, Form, Query, RequireAccount, RequireAuth,
+        RequireSudo,
+    },
+    flash::{self, Flash, DATABASE_ERROR},
+    handlers::shares,
+    models::{
+        normalize_email, ApiKeyForm, DeleteAccountForm, ProfileForm, ProfilePageQuery, UserInfo,
+        VerifyQuery, MAX_API_KEY_NAME_LENGTH, RECENT_LOGINS_SHOWN,
+    },
+    responses::{redirect_to, redirect_with_cookies},
+    templates::{is_valid_timezone, render},
+    tokens, AppState,
+};
+
+/// Show the current user's profile
+#[rustapi_rs::get("/profile")]
+pub async fn show_profile(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    CsrfToken(csrf_token): CsrfToken,
+    FlashMessage(flash): FlashMessage,
+    Query(query): Query<ProfilePageQuery>,
+) -> Response {
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+    context.insert("username", &user.username);
+    context.insert("email", &user.email);
+
+    if query.updated == Some(true) {
+        context.insert("success", "Profile updated successfully!");
+    }
+    if let Some(flash) = flash {
+        flash.insert_into(&mut context);
+    }
+
+    render_profile(&state, &user, context).await
+}
+
+/// Update the current user's username, email and timezone
+#[rustapi_rs::post("/profile")]
+pub async fn update_profile(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    sudo: RequireSudo,
+    CsrfProtected(csrf_token): CsrfProtected,
+    Form(form): Form<ProfileForm>,
+) -> Response {
+    let username = form.username.trim();
+    let email = normalize_email(&form.email);
+    let email = email.as_str();
+    let timezone = form
+        .timezone
+        .as_deref()
+        .map_or(user.timezone.as_str(), str::trim);
+
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+    context.insert("username", username);
+    context.insert("email", email);
+    context.insert("selected_timezone", timezone);
+
+    // Validate form
+    if username.len() < 3 {
+        context.insert("error", "Username must be at least 3 characters");
+        return render_profile(&state, &user, context).await;
+    }
+
+    if email.is_empty() {
+        context.insert("error", "Email is required");
+        return render_profile(&state, &user, context).await;
+    }
+
+    if !is_valid_timezone(timezone) {
+        context.insert("error", "Choose a timezone from the list");
+        return render_profile(&state, &user, context).await;
+    }
+
+    // Only values that actually change can collide with another account; a
+    // change of case alone still matches the user's own account
+    if username.to_lowercase() != user.username.to_lowercase() {
+        match state.db.username_exists(username).await {
+            Ok(true) => {
+                context.insert("error", "Username is already taken");
+                return render_profile(&state, &user, context).await;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "database error");
+                context.insert("error", "An error occurred. Please try again.");
+                return render_profile(&state, &user, context).await;
+            }
+            _ => {}
+        }
+    }
+
+    // Whoever controls the email address can reset the account, so moving
+    // it needs the password, and only happens once the new address confirms
+    // it (see `confirm_email_change`)
+    let email_changed = email != user.email.to_lowercase();
+    if email_changed {
+        if let Some(prompt) = sudo.check(&state, "/profile") {
+            return prompt;
+        }
+        match request_email_change(&state, user.id, email).await {
+            Ok(true) => {}
+            Ok(false) => {
+                context.insert("error", "Email is already registered");
+                return render_profile(&state, &user, context).await;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "database error");
+                context.insert("error", "An error occurred. Please try again.");
+                return render_profile(&state, &user, context).await;
+            }
+        }
+    }
+
+    match state
+        .db
+        .update_user_profile(user.id, username, timezone)
+        .await
+    {
+        Ok(Some(_)) if email_changed => flash::redirect(
+            &state,
+            "/profile",
+            Flash::success(format!(
+                "Profile updated. Follow the link sent to {} to start using it.",
+                email
+            )),
+        ),
+        Ok(Some(_)) => redirect_to(&state, "/profile?updated=true"),
+        Ok(None) => redirect_to(&state, "/login"),
+        // Another account claimed the name between the check and the update
+        Err(e)
+            if e.as_database_error()
+                .is_some_and(|db_err| db_err.is_unique_violation()) =>
+        {
+            context.insert("error", "Username or email is already taken");
+            render_profile(&state, &user, context).await
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", "An error occurred. Please try again.");
+            render_profile(&state, &user, context).await
+        }
+    }
+}
+
+/// Switch the account to the new email address a confirmation link was
+/// sent to
+///
+/// The link proves control of the new address, so it works without a login.
+#[rustapi_rs::get("/profile/email/confirm")]
+pub async fn confirm_email_change(
+    State(state): State<AppState>,
+    Query(query): Query<VerifyQuery>,
+) -> Response {
+    let invalid = || {
+        flash::redirect(
+            &state,
+            "/profile",
+            Flash::error("This email confirmation link is invalid or has expired"),
+        )
+    };
+    let (user_id, new_email) = match state
+        .db
+        .consume_email_change(&tokens::hash_token(&query.token))
+        .await
+    {
+        Ok(Some(change)) => change,
+        Ok(None) => return invalid(),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return flash::redirect(&state, "/profile", Flash::error(DATABASE_ERROR));
+        }
+    };
+
+    match state.db.update_user_email(user_id, &new_email).await {
+        Ok(true) => {
+            tracing::info!(user_id, "changed email address");
+            flash::redirect(&state, "/profile", Flash::success("Email address changed"))
+        }
+        Ok(false) => invalid(),
+        // Another account registered the address after the change was asked for
+        Err(e)
+            if e.as_database_error()
+                .is_some_and(|db_err| db_err.is_unique_violation()) =>
+        {
+            flash::redirect(
+                &state,
+                "/profile",
+                Flash::error("That email address has been registered by another account"),
+            )
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            flash::redirect(&state, "/profile", Flash::error(DATABASE_ERROR))
+        }
+    }
+}
+
+/// Permanently delete the current account and all of its items
+#[rustapi_rs::post("/account/delete")]
+pub async fn delete_account(
+    State(state): State<AppState>,
+    RequireAccount(account): RequireAccount,
+    sudo: RequireSudo,
+    CsrfProtected(csrf_token): CsrfProtected,
+    Form(form): Form<DeleteAccountForm>,
+) -> Response {
+    if let Some(prompt) = sudo.check(&state, "/profile") {
+        return prompt;
+    }
+    let user = UserInfo::from(account.clone());
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+    context.insert("username", &user.username);
+    context.insert("email", &user.email);
+
+    if form.confirm.trim() != user.username {
+        context.insert(
+            "error",
+            "Type your username to confirm deleting your account",
+        );
+        return render_profile(&state, &user, context).await;
+    }
+
+    if let Err(e) = state.db.delete_user(user.id).await {
+        tracing::error!(error = %e, "database error");
+        context.insert("error", "An error occurred. Please try again.");
+        return render_profile(&state, &user, context).await;
+    }
+
+    redirect_with_cookies(
+        &state,
+        "/",
+        &tokens::clear_session_cookies(&state.config.cookies),
+    )
+}
+
+/// Create an API key for the current user; the key is shown on the page
+/// this once, and only its hash is kept
+#[rustapi_rs::post("/profile/api-keys")]
+pub async fn create_api_key(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    sudo: RequireSudo,
+    CsrfProtected(csrf_token): CsrfProtected,
+    Form(form): Form<ApiKeyForm>,
+) -> Response {
+    if let Some(prompt) = sudo.check(&state, "/profile") {
+        return prompt;
+    }
+    let name = form.name.trim();
+
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+    context.insert("username", &user.username);
+    context.insert("email", &user.email);
+
+    if name.is_empty() {
+        context.insert("error", "API key name is required");
+        return render_profile(&state, &user, context).await;
+    }
+
+    if name.chars().count() > MAX_API_KEY_NAME_LENGTH {
+        context.insert("api_key_name", name);
+        context.insert(
+            "error",
+            &format!(
+                "API key name must be {} characters or less",
+                MAX_API_KEY_NAME_LENGTH
+            ),
+        );
+        return render_profile(&state, &user, context).await;
+    }
+
+    let key = tokens::generate_token();
+    match state
+        .db
+        .create_api_key(user.id, &tokens::hash_token(&key), name)
+        .await
+    {
+        Ok(_) => {
+            context.insert("new_api_key", &key);
+            context.insert(
+                "success",
+                "API key created. Copy it now; it will not be shown again.",
+            );
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", "An error occurred. Please try again.");
+        }
+    }
+    render_profile(&state, &user, context).await
+}
+
+/// Revoke one of the current user's API keys
+#[rustapi_rs::post("/profile/api-keys/{id}/revoke")]
+pub async fn revoke_api_key(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    _csrf: CsrfProtected,
+    Path(id): Path<i64>,
+) -> Response {
+    match state.db.delete_api_key(id, user.id).await {
+        Ok(true) => flash::redirect(&state, "/profile", Flash::success("API key revoked")),
+        Ok(false) => flash::redirect(&state, "/profile", Flash::error("API key not found")),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            flash::redirect(&state, "/profile", Flash::error(DATABASE_ERROR))
+        }
+    }
+}
+
+/// Turn on the current user's item feed, or give it a new address; the
+/// address is shown on the page this once, and only its token's hash is kept
+#[rustapi_rs::post("/profile/feed-token")]
+pub async fn create_feed_token(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    sudo: RequireSudo,
+    CsrfProtected(csrf_token): CsrfProtected,
+) -> Response {
+    if let Some(prompt) = sudo.check(&state, "/profile") {
+        return prompt;
+    }
+
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+    context.insert("username", &user.username);
+    context.insert("email", &user.email);
+
+    let token = tokens::generate_token();
+    match state
+        .db
+        .set_feed_token(user.id, &tokens::hash_token(&token))
+        .await
+    {
+        Ok(()) => {
+            context.insert(
+                "new_feed_url",
+                &format!("{}/items/feed.xml?token={}", state.config.public_url, token),
+            );
+            context.insert(
+                "success",
+                "Feed address created. Copy it now; it will not be shown again.",
+            );
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", "An error occurred. Please try again.");
+        }
+    }
+    render_profile(&state, &user, context).await
+}
+
+/// Turn off the current user's item feed
+#[rustapi_rs::post("/profile/feed-token/delete")]
+pub async fn delete_feed_token(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    _csrf: CsrfProtected,
+) -> Response {
+    match state.db.delete_feed_token(user.id).await {
+        Ok(true) => flash::redirect(&state, "/profile", Flash::success("Item feed turned off")),
+        Ok(false) => flash::redirect(&state, "/profile", Flash::error("The item feed is not on")),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            flash::redirect(&state, "/profile", Flash::error(DATABASE_ERROR))
+        }
+    }
+}
+
+/// Remove one of the current user's passkeys
+#[rustapi_rs::post("/profile/passkeys/{id}/delete")]
+pub async fn delete_passkey(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    _csrf: CsrfProtected,
+    Path(id): Path<i64>,
+) -> Response {
+    match state.db.delete_passkey(id, user.id).await {
+        Ok(true) => flash::redirect(&state, "/profile", Flash::success("Passkey removed")),
+        Ok(false) => flash::redirect(&state, "/profile", Flash::error("Passkey not found")),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            flash::redirect(&state, "/profile", Flash::error(DATABASE_ERROR))
+        }
+    }
+}
+
+// Store a pending change to `email` and deliver its confirmation link; false
+// while the address is taken. No mail transport is configured in this
+// template, so the link is written to the server log.
+async fn request_email_change(
+    state: &AppState,
+    user_id: i64,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    let token = tokens::generate_token();
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(tokens::EMAIL_CHANGE_TTL_SECS);
+    if !state
+        .db
+        .create_email_change(user_id, email, &tokens::hash_token(&token), expires_at)
+        .await?
+    {
+        return Ok(false);
+    }
+
+    tracing::info!(
+        email = %email,
+        "email change link: {}",
+        state
+            .config
+            .base_path
+            .url(&format!("/profile/email/confirm?token={}", token))
+    );
+    Ok(true)
+}
+
+// Render the profile page for the unchanged user, listing their API keys,
+// feed, shared items, passkeys and the zones the timezone field offers
+async fn render_profile(state: &AppState, user: &UserInfo, mut context: Context) -> Response {
+    if !context.contains_key("selected_timezone") {
+        context.insert("selected_timezone", &user.timezone);
+    }
+    let timezones: Vec<&str> = chrono_tz::TZ_VARIANTS.iter().map(|tz| tz.name()).collect();
+    context.insert("timezones", &timezones);
+
+    match state.db.list_api_keys(user.id).await {
+        Ok(keys) => context.insert("api_keys", &keys),
+        Err(e) => tracing::error!(error = %e, "database error"),
+    }
+    match state.db.pending_email_change(user.id).await {
+        Ok(pending_email) => context.insert("pending_email", &pending_email),
+        Err(e) => tracing::error!(error = %e, "database error"),
+    }
+    match state.db.feed_token_created_at(user.id).await {
+        Ok(created_at) => context.insert("feed_token_created_at", &created_at),
+        Err(e) => tracing::error!(error = %e, "database error"),
+    }
+    match state.db.list_shared_items(user.id).await {
+        Ok(shared_items) => {
+            context.insert("shared_items", &shared_items);
+            context.insert("share_base_url", &shares::share_url(state, ""));
+        }
+        Err(e) => tracing::error!(error = %e, "database error"),
+    }
+    match state.db.list_passkeys(user.id).await {
+        Ok(passkeys) => context.insert("passkeys", &passkeys),
+        Err(e) => tracing::error!(error = %e, "database error"),
+    }
+    match state.db.recent_logins(user.id, RECENT_LOGINS_SHOWN).await {
+        Ok(logins) => context.insert("recent_logins", &logins),
+        Err(e) => tracing::error!(error = %e, "database error"),
+    }
+    render(state, "profile.html", Some(user), context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CreateItem;
+    use crate::test_utils::{
+        body_string, cleanup_db, flash_from, header_value, set_cookie_value, setup_test_state,
+        test_csrf, test_csrf_token,
+    };
+
+    async fn setup_user(state: &AppState, username: &str) -> RequireAuth {
+        let hash = state
+            .config
+            .passwords
+            .hash("secret")
+            .expect("hash password");
+        let user = state
+            .db
+            .create_user(username, &format!("{}@example.com", username), &hash)
+            .await
+            .expect("create user");
+        RequireAuth(UserInfo::from(user))
+    }
+
+    async fn account(state: &AppState, auth: &RequireAuth) -> RequireAccount {
+        let user = state
+            .db
+            .find_user_by_id(auth.0.id)
+            .await
+            .expect("find user")
+            .expect("user exists");
+        RequireAccount(user)
+    }
+
+    fn delete_form(confirm: &str) -> Form<DeleteAccountForm> {
+        Form(DeleteAccountForm {
+            confirm: confirm.to_string(),
+        })
+    }
+
+    fn profile_form(username: &str, email: &str) -> Form<ProfileForm> {
+        Form(ProfileForm {
+            username: username.to_string(),
+            email: email.to_string(),
+            timezone: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn show_profile_renders_current_user() {
+        let (state, path) = setup_test_state().await;
+        let auth = setup_user(&state, "nora").await;
+
+        let response = show_profile(
+            State(state.clone()),
+            auth,
+            test_csrf_token(),
+            FlashMessage(None),
+            Query(ProfilePageQuery::default()),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_string(response).await.starts_with("PROFILE nora"));
+        cleanup_db(path);
+    }
+
+    fn api_key_form(name: &str) -> Form<ApiKeyForm> {
+        Form(ApiKeyForm {
+            name: name.to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn create_api_key_shows_the_key_once_and_stores_its_hash() {
+        let (state, path) = setup_test_state().await;
+        let auth = setup_user(&state, "ivan").await;
+        let user_id = auth.0.id;
+
+        let response = create_api_key(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            RequireSudo(true),
+            test_csrf(),
+            api_key_form("  Billing  "),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_string(response).await;
+        let key = body
+            .split_once("new_api_key=")
+            .and_then(|(_, rest)| rest.split_whitespace().next())
+            .expect("key shown")
+            .to_string();
+        assert_eq!(key.len(), 64);
+        assert!(body.contains("api_keys=Billing,"));
+
+        let stored = state.db.list_api_keys(user_id).await.expect("list keys");
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].key_hash, tokens::hash_token(&key));
+        assert_ne!(stored[0].key_hash, key);
+
+        // Later visits list the key without revealing it
+        let response = show_profile(
+            State(state.clone()),
+            auth,
+            test_csrf_token(),
+            FlashMessage(None),
+            Query(ProfilePageQuery::default()),
+        )
+        .await;
+        let body = body_string(response).await;
+        assert!(body.contains("new_api_key= api_keys=Billing,"));
+        assert!(!body.contains(&key));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn create_api_key_requires_a_name() {
+        let (state, path) = setup_test_state().await;
+        let auth = setup_user(&state, "jude").await;
+        let user_id = auth.0.id;
+
+        for name in [" ".to_string(), "x".repeat(MAX_API_KEY_NAME_LENGTH + 1)] {
+            let response = create_api_key(
+                State(state.clone()),
+                RequireAuth(auth.0.clone()),
+                RequireSudo(true),
+                test_csrf(),
+                api_key_form(&name),
+            )
+            .await;
+            assert!(body_string(response).await.contains("API key name"));
+        }
+        assert!(state
+            .db
+            .list_api_keys(user_id)
+            .await
+            .expect("list keys")
+            .is_empty());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn revoke_api_key_only_removes_own_keys() {
+        let (state, path) = setup_test_state().await;
+        let owner = setup_user(&state, "kira").await;
+        let other = setup_user(&state, "liam").await;
+        let key = state
+            .db
+            .create_api_key(owner.0.id, "key-hash", "CI")
+            .await
+            .expect("create key");
+
+        let response = revoke_api_key(State(state.clone()), other, test_csrf(), Path(key.id)).await;
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error("API key not found"))
+        );
+
+        let response = revoke_api_key(State(state.clone()), owner, test_csrf(), Path(key.id)).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/profile".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("API key revoked"))
+        );
+        assert!(state
+            .db
+            .find_api_key("key-hash")
+            .await
+            .expect("find key")
+            .is_none());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn feed_address_is_shown_once_and_can_be_turned_off() {
+        let (state, path) = setup_test_state().await;
+        let auth = setup_user(&state, "fern").await;
+        let user_id = auth.0.id;
+
+        let response = create_feed_token(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            RequireSudo(false),
+            test_csrf(),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/sudo?next=/profile".to_string())
+        );
+
+        let response = create_feed_token(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            RequireSudo(true),
+            test_csrf(),
+        )
+        .await;
+        let body = body_string(response).await;
+        assert!(body.contains("feed=on"));
+        let token = body
+            .split_once("feed.xml?token=")
+            .map(|(_, rest)| rest.trim())
+            .expect("feed address shown");
+        assert_eq!(
+            state
+                .db
+                .find_feed_token_user(&tokens::hash_token(token))
+                .await
+                .expect("find"),
+            Some(user_id)
+        );
+
+        let response = show_profile(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            test_csrf_token(),
+            FlashMessage(None),
+            Query(ProfilePageQuery::default()),
+        )
+        .await;
+        let body = body_string(response).await;
+        assert!(body.contains("feed=on new_feed_url="));
+        assert!(!body.contains(token));
+
+        let response = delete_feed_token(State(state.clone()), auth, test_csrf()).await;
+        assert_eq!(
+            flash_from(&state, &response).map(|f| f.message),
+            Some("Item feed turned off".to_string())
+        );
+        assert_eq!(
+            state
+                .db
+                .find_feed_token_user(&tokens::hash_token(token))
+                .await
+                .expect("find"),
+            None
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn passkeys_are_listed_and_removed_by_their_owner() {
+        let (state, path) = setup_test_state().await;
+        let owner = setup_user(&state, "mona").await;
+        let other = setup_user(&state, "ned").await;
+        let passkey = state
+            .db
+            .create_passkey(owner.0.id, "credential", "key", 0, "Laptop")
+            .await
+            .expect("create passkey");
+
+        let response = show_profile(
+            State(state.clone()),
+            RequireAuth(owner.0.clone()),
+            test_csrf_token(),
+            FlashMessage(None),
+            Query(ProfilePageQuery::default()),
+        )
+        .await;
+        assert!(body_string(response).await.contains("passkeys=Laptop,"));
+
+        let response =
+            delete_passkey(State(state.clone()), other, test_csrf(), Path(passkey.id)).await;
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error("Passkey not found"))
+        );
+
+        let response =
+            delete_passkey(State(state.clone()), owner, test_csrf(), Path(passkey.id)).await;
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("Passkey removed"))
+        );
+        assert!(state
+            .db
+            .find_passkey("credential")
+            .await
+            .expect("find passkey")
+            .is_none());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn profile_lists_recent_logins() {
+        let (state, path) = setup_test_state().await;
+        let user = setup_user(&state, "orla").await;
+        let keep_since = chrono::Utc::now() - chrono::Duration::days(1);
+        for ip in ["203.0.113.1", "203.0.113.2"] {
+            state
+                .db
+                .record_login(user.0.id, ip, None, keep_since)
+                .await
+                .expect("record login");
+        }
+
+        let response = show_profile(
+            State(state.clone()),
+            user,
+            test_csrf_token(),
+            FlashMessage(None),
+            Query(ProfilePageQuery::default()),
+        )
+        .await;
+        assert!(body_string(response)
+            .await
+            .contains("logins=203.0.113.2,203.0.113.1,"));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn update_profile_saves_changes() {
+        let (state, path) = setup_test_state().await;
+        let auth = setup_user(&state, "otto").await;
+        let user_id = auth.0.id;
+
+        let response = update_profile(
+            State(state.clone()),
+            auth,
+            RequireSudo(true),
+            test_csrf(),
+            profile_form("otto2", "otto2@example.com"),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/profile".to_string())
+        );
+        let flash = flash_from(&state, &response).expect("flash");
+        assert!(flash.message.contains("otto2@example.com"));
+
+        // The new address only takes over once it is confirmed
+        let stored = state
+            .db
+            .find_user_by_id(user_id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert_eq!(stored.username, "otto2");
+        assert_eq!(stored.email, "otto@example.com");
+        assert_eq!(stored.timezone, "UTC");
+        assert_eq!(
+            state
+                .db
+                .pending_email_change(user_id)
+                .await
+                .expect("pending"),
+            Some("otto2@example.com".to_string())
+        );
+        cleanup_db(path);
+    }
+
+    fn confirm_query(token: &str) -> Query<VerifyQuery> {
+        Query(VerifyQuery {
+            token: token.to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn email_change_applies_once_confirmed() {
+        let (state, path) = setup_test_state().await;
+        let auth = setup_user(&state, "ruth").await;
+        let user_id = auth.0.id;
+        let expires_at = chrono::Utc::now() + chrono::Duration::hours(1);
+        state
+            .db
+            .create_email_change(
+                user_id,
+                "ruth.new@example.com",
+                &tokens::hash_token("confirm-token"),
+                expires_at,
+            )
+            .await
+            .expect("create change");
+
+        let response = show_profile(
+            State(state.clone()),
+            auth,
+            test_csrf_token(),
+            FlashMessage(None),
+            Query(ProfilePageQuery::default()),
+        )
+        .await;
+        let body = body_string(response).await;
+        assert!(body.contains("pending_email=ruth.new@example.com"));
+
+        let response = confirm_email_change(State(state.clone()), confirm_query("wrong")).await;
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::error(
+                "This email confirmation link is invalid or has expired"
+            ))
+        );
+
+        let response =
+            confirm_email_change(State(state.clone()), confirm_query("confirm-token")).await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/profile".to_string())
+        );
+        assert_eq!(
+            flash_from(&state, &response),
+            Some(Flash::success("Email address changed"))
+        );
+        let stored = state
+            .db
+            .find_user_by_id(user_id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert_eq!(stored.email, "ruth.new@example.com");
+        assert_eq!(
+            state
+                .db
+                .pending_email_change(user_id)
+                .await
+                .expect("pending"),
+            None
+        );
+
+        // Each link works once
+        let response =
+            confirm_email_change(State(state.clone()), confirm_query("confirm-token")).await;
+        assert!(flash_from(&state, &response).is_some_and(|f| f.message.contains("invalid")));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn email_change_refuses_taken_addresses() {
+        let (state, path) = setup_test_state().await;
+        let first = setup_user(&state, "sven").await;
+        let second = setup_user(&state, "tara").await;
+        let second_id = second.0.id;
+
+        let response = update_profile(
+            State(state.clone()),
+            first,
+            RequireSudo(true),
+            test_csrf(),
+            profile_form("sven", "shared@example.com"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+
+        // Pending for another account counts as taken
+        let response = update_profile(
+            State(state.clone()),
+            second,
+            RequireSudo(true),
+            test_csrf(),
+            profile_form("tara", "SHARED@example.com"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_string(response)
+            .await
+            .contains("Email is already registered"));
+        assert_eq!(
+            state
+                .db
+                .pending_email_change(second_id)
+                .await
+                .expect("pending"),
+            None
+        );
+
+        // An address registered after the change was asked for stays with
+        // its account
+        let expires_at = chrono::Utc::now() + chrono::Duration::hours(1);
+        state
+            .db
+            .create_email_change(
+                second_id,
+                "late@example.com",
+                &tokens::hash_token("late-token"),
+                expires_at,
+            )
+            .await
+            .expect("create change");
+        setup_user(&state, "late").await;
+        let response =
+            confirm_email_change(State(state.clone()), confirm_query("late-token")).await;
+        assert!(flash_from(&state, &response)
+            .is_some_and(|f| f.message.contains("registered by another account")));
+        let stored = state
+            .db
+            .find_user_by_id(second_id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert_eq!(stored.email, "tara@example.com");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn sensitive_changes_need_sudo() {
+        let (state, path) = setup_test_state().await;
+        let auth = setup_user(&state, "vera").await;
+        let user_id = auth.0.id;
+
+        let response = update_profile(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            RequireSudo(false),
+            test_csrf(),
+            profile_form("vera", "elsewhere@example.com"),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/sudo?next=/profile".to_string())
+        );
+        let response = create_api_key(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            RequireSudo(false),
+            test_csrf(),
+            api_key_form("Billing"),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/sudo?next=/profile".to_string())
+        );
+        assert!(state
+            .db
+            .list_api_keys(user_id)
+            .await
+            .expect("list")
+            .is_empty());
+
+        // Renaming the account keeps working without it
+        let response = update_profile(
+            State(state.clone()),
+            auth,
+            RequireSudo(false),
+            test_csrf(),
+            profile_form("vera2", "vera@example.com"),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/profile?updated=true".to_string())
+        );
+        let stored = state
+            .db
+            .find_user_by_id(user_id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert_eq!(stored.username, "vera2");
+        assert_eq!(stored.email, "vera@example.com");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn update_profile_validates_the_timezone() {
+        let (state, path) = setup_test_state().await;
+        let auth = setup_user(&state, "tess").await;
+        let user_id = auth.0.id;
+        let form = |timezone: &str| {
+            Form(ProfileForm {
+                timezone: Some(timezone.to_string()),
+                ..profile_form("tess", "tess@example.com").0
+            })
+        };
+
+        let response = update_profile(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            RequireSudo(true),
+            test_csrf(),
+            form("Mars/Olympus_Mons"),
+        )
+        .await;
+        assert!(body_string(response)
+            .await
+            .contains("Choose a timezone from the list"));
+
+        let response = update_profile(
+            State(state.clone()),
+            auth,
+            RequireSudo(true),
+            test_csrf(),
+            form("America/New_York"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let stored = state
+            .db
+            .find_user_by_id(user_id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert_eq!(stored.timezone, "America/New_York");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn update_profile_allows_changing_case_only() {
+        let (state, path) = setup_test_state().await;
+        let auth = setup_user(&state, "quinn").await;
+        let user_id = auth.0.id;
+
+        let response = update_profile(
+            State(state.clone()),
+            auth,
+            RequireSudo(true),
+            test_csrf(),
+            profile_form("Quinn", "QUINN@example.com"),
+        )
+        .await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/profile?updated=true".to_string())
+        );
+
+        let stored = state
+            .db
+            .find_user_by_id(user_id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert_eq!(stored.username, "Quinn");
+        assert_eq!(stored.email, "quinn@example.com");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn update_profile_rejects_collisions() {
+        let (state, path) = setup_test_state().await;
+        setup_user(&state, "taken").await;
+        let auth = setup_user(&state, "paul").await;
+        let user_id = auth.0.id;
+
+        let response = update_profile(
+            State(state.clone()),
+            RequireAuth(auth.0.clone()),
+            RequireSudo(true),
+            test_csrf(),
+            profile_form("taken", "paul@example.com"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_string(response)
+            .await
+            .contains("Username is already taken"));
+
+        let response = update_profile(
+            State(state.clone()),
+            auth,
+            RequireSudo(true),
+            test_csrf(),
+            profile_form("paul", "taken@example.com"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_string(response)
+            .await
+            .contains("Email is already registered"));
+
+        let stored = state
+            .db
+            .find_user_by_id(user_id)
+            .await
+            .expect("find")
+            .expect("user exists");
+        assert_eq!(stored.username, "paul");
+        assert_eq!(stored.email, "paul@example.com");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn delete_account_removes_user_items_and_cookie() {
+        let (state, path) = setup_test_state().await;
+        let auth = setup_user(&state, "rosa").await;
+        let user_id = auth.0.id;
+        let item = state
+            .db
+            .create_item(CreateItem {
+                user_id,
+                title: "Item".to_string(),
+                description: None,
+                due_at: None,
+            })
+            .await
+            .expect("create item");
+
+        let response = delete_account(
+            State(state.clone()),
+            account(&state, &auth).await,
+            RequireSudo(true),
+            test_csrf(),
+            delete_form("rosa"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(header_value(&response, "Location"), Some("/".to_string()));
+        assert_eq!(
+            set_cookie_value(&response, tokens::ACCESS_COOKIE),
+            Some(String::new())
+        );
+
+        assert!(state
+            .db
+            .find_user_by_id(user_id)
+            .await
+            .expect("find")
+            .is_none());
+        assert!(state
+            .db
+            .get_item(item.id, user_id)
+            .await
+            .expect("get")
+            .is_none());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn delete_account_requires_confirmation_and_sudo() {
+        let (state, path) = setup_test_state().await;
+        let auth = setup_user(&state, "sam").await;
+        let user_id = auth.0.id;
+
+        let response = delete_account(
+            State(state.clone()),
+            account(&state, &auth).await,
+            RequireSudo(true),
+            test_csrf(),
+            delete_form("not-sam"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_string(response).await.contains("Type your username"));
+
+        // Without a recent password confirmation it asks for one first
+        let response = delete_account(
+            State(state.clone()),
+            account(&state, &auth).await,
+            RequireSudo(false),
+            test_csrf(),
+            delete_form("sam"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/sudo?next=/profile".to_string())
+        );
+
+        assert!(state
+            .db
+            .find_user_by_id(user_id)
+            .await
+            .expect("find")
+            .is_some());
+        cleanup_db(path);
+    }
+}
@@ -0,0 +1,263 @@
+use rustapi_rs::prelude::*;
+use tera::Context;
+
+use crate::{
+    extractors::{CsrfProtected, CurrentUser, RequireAuth},
+    flash::{self, Flash, DATABASE_ERROR, ITEM_NOT_FOUND},
+    templates::render,
+    tokens, AppState,
+};
+
+/// Address anyone can open an item shared under `slug` at
+pub fn share_url(state: &AppState, slug: &str) -> String {
+    format!("{}/share/{}", state.config.public_url, slug)
+}
+
+/// Open one of the current user's items read-only to anyone with its link
+///
+/// The link is listed on the profile page, which this returns to.
+#[rustapi_rs::post("/profile/shared-items/{id}")]
+pub async fn share_item(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    _csrf: CsrfProtected,
+    Path(id): Path<i64>,
+) -> Response {
+    match state
+        .db
+        .share_item(id, user.id, &tokens::generate_token())
+        .await
+    {
+        Ok(Some(_)) => flash::redirect(
+            &state,
+            "/profile",
+            Flash::success("Item shared. Anyone with its link can now view it."),
+        ),
+        Ok(None) => flash::redirect(&state, "/items", Flash::error(ITEM_NOT_FOUND)),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            flash::redirect(&state, "/profile", Flash::error(DATABASE_ERROR))
+        }
+    }
+}
+
+/// Stop sharing one of the current user's items
+///
+/// The item gets a new slug, so its old link stays dead even if it is
+/// shared again.
+#[rustapi_rs::post("/profile/shared-items/{id}/delete")]
+pub async fn unshare_item(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    _csrf: CsrfProtected,
+    Path(id): Path<i64>,
+) -> Response {
+    match state
+        .db
+        .unshare_item(id, user.id, &tokens::generate_token())
+        .await
+    {
+        Ok(true) => flash::redirect(&state, "/profile", Flash::success("Item no longer shared")),
+        Ok(false) => flash::redirect(&state, "/profile", Flash::error("That item is not shared")),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            flash::redirect(&state, "/profile", Flash::error(DATABASE_ERROR))
+        }
+    }
+}
+
+/// Read-only view of a shared item, open without logging in
+///
+/// Only the item's title, description, tags and dates are shown, never who
+/// owns it. Unknown slugs and items no longer shared get a 404.
+#[rustapi_rs::get("/share/{slug}")]
+pub async fn show_shared_item(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+    Path(slug): Path<String>,
+) -> Response {
+    let loaded = async {
+        let Some(mut item) = state.db.find_shared_item(&slug).await? else {
+            return Ok(None);
+        };
+        item.tags = state.db.get_item_tags(item.id).await?;
+        Ok::<_, sqlx::Error>(Some(item))
+    };
+
+    match loaded.await {
+        Ok(Some(item)) => {
+            let mut context = Context::new();
+            context.insert("title", &item.title);
+            context.insert("description", &item.description);
+            context.insert("tags", &item.tags);
+            context.insert("created_at", &item.created_at);
+            context.insert("updated_at", &item.updated_at);
+            render(&state, "items/shared.html", user.as_ref(), context)
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Item not found").into_response(),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CreateItem, Item, UserInfo};
+    use crate::test_utils::{body_string, cleanup_db, flash_from, setup_test_state, test_csrf};
+
+    async fn setup(state: &AppState) -> (UserInfo, Item) {
+        let owner = state
+            .db
+            .create_user("owner", "owner@example.com", "hash")
+            .await
+            .expect("create user");
+        let item = state
+            .db
+            .create_item_with_tags(
+                CreateItem {
+                    user_id: owner.id,
+                    title: "Recipe".to_string(),
+                    description: Some("Flour & water".to_string()),
+                    due_at: None,
+                },
+                vec!["baking".to_string()],
+            )
+            .await
+            .expect("create item");
+        (UserInfo::from(owner), item)
+    }
+
+    async fn view(state: &AppState, slug: &str) -> Response {
+        show_shared_item(
+            State(state.clone()),
+            CurrentUser(None),
+            Path(slug.to_string()),
+        )
+        .await
+    }
+
+    async fn shared_slug(state: &AppState, owner: &UserInfo, item_id: i64) -> Option<String> {
+        state
+            .db
+            .item_share_slug(item_id, owner.id)
+            .await
+            .expect("share slug")
+    }
+
+    #[tokio::test]
+    async fn shared_item_is_viewable_by_slug_without_the_owner() {
+        let (state, path) = setup_test_state().await;
+        let (owner, item) = setup(&state).await;
+
+        let response = share_item(
+            State(state.clone()),
+            RequireAuth(owner.clone()),
+            test_csrf(),
+            Path(item.id),
+        )
+        .await;
+        assert_eq!(
+            flash_from(&state, &response).map(|f| f.message),
+            Some("Item shared. Anyone with its link can now view it.".to_string())
+        );
+        let slug = shared_slug(&state, &owner, item.id)
+            .await
+            .expect("item shared");
+
+        let response = view(&state, &slug).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = body_string(response).await;
+        assert!(body.contains("SHARED ITEM Recipe"));
+        assert!(body.contains("tags=baking,"));
+        assert!(!body.contains("owner@example.com"));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn items_not_shared_are_not_found() {
+        let (state, path) = setup_test_state().await;
+        let (owner, item) = setup(&state).await;
+        assert_eq!(
+            view(&state, "no-such-slug").await.status(),
+            StatusCode::NOT_FOUND
+        );
+
+        state
+            .db
+            .share_item(item.id, owner.id, "first-slug")
+            .await
+            .expect("share");
+        assert_eq!(view(&state, "first-slug").await.status(), StatusCode::OK);
+
+        let response = unshare_item(
+            State(state.clone()),
+            RequireAuth(owner.clone()),
+            test_csrf(),
+            Path(item.id),
+        )
+        .await;
+        assert_eq!(
+            flash_from(&state, &response).map(|f| f.message),
+            Some("Item no longer shared".to_string())
+        );
+        assert_eq!(
+            view(&state, "first-slug").await.status(),
+            StatusCode::NOT_FOUND
+        );
+
+        // Sharing again opens a new link, not the old one
+        share_item(
+            State(state.clone()),
+            RequireAuth(owner.clone()),
+            test_csrf(),
+            Path(item.id),
+        )
+        .await;
+        let slug = shared_slug(&state, &owner, item.id)
+            .await
+            .expect("item shared");
+        assert_ne!(slug, "first-slug");
+        assert_eq!(
+            view(&state, "first-slug").await.status(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(view(&state, &slug).await.status(), StatusCode::OK);
+
+        // Trashed items are not shown either
+        state
+            .db
+            .delete_item(item.id, owner.id)
+            .await
+            .expect("trash item");
+        assert_eq!(view(&state, &slug).await.status(), StatusCode::NOT_FOUND);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn only_the_owner_can_share_an_item() {
+        let (state, path) = setup_test_state().await;
+        let (owner, item) = setup(&state).await;
+        let other = state
+            .db
+            .create_user("other", "other@example.com", "hash")
+            .await
+            .expect("create user");
+
+        let response = share_item(
+            State(state.clone()),
+            RequireAuth(UserInfo::from(other)),
+            test_csrf(),
+            Path(item.id),
+        )
+        .await;
+        assert_eq!(
+            flash_from(&state, &response).map(|f| f.message),
+            Some(ITEM_NOT_FOUND.to_string())
+        );
+        assert_eq!(shared_slug(&state, &owner, item.id).await, None);
+        cleanup_db(path);
+    }
+}
@@ -0,0 +1,112 @@
+use chrono_tz::Tz;
+use rustapi_rs::prelude::*;
+use serde_json::json;
+use tera::Context;
+
+use crate::{
+    extractors::{CsrfToken, RequireAuth},
+    negotiate::{with_json, with_json_error},
+    templates::render,
+    AppState,
+};
+
+/// Days the stats page covers, today included
+pub const STATS_DAYS: i64 = 30;
+
+/// Items the current user created per day over the last `STATS_DAYS` days
+///
+/// Days follow the user's timezone. Clients preferring JSON get
+/// `{"days": [{"day", "count"}, ...], "total": n}`.
+#[rustapi_rs::get("/stats")]
+pub async fn show_stats(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    CsrfToken(csrf_token): CsrfToken,
+) -> Response {
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+
+    let tz = user.timezone.parse().unwrap_or(Tz::UTC);
+    let days = match state.db.item_counts_by_day(user.id, STATS_DAYS, tz).await {
+        Ok(days) => days,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            context.insert("error", "Failed to load stats");
+            let response = render(&state, "stats.html", Some(&user), context);
+            return with_json_error(
+                response,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "database_error",
+            );
+        }
+    };
+    let total: i64 = days.iter().map(|day| day.count).sum();
+    let max_count = days.iter().map(|day| day.count).max().unwrap_or_default();
+
+    context.insert("days", &days);
+    context.insert("total", &total);
+    context.insert("max_count", &max_count);
+
+    let response = render(&state, "stats.html", Some(&user), context);
+    with_json(
+        response,
+        StatusCode::OK,
+        &json!({ "days": days, "total": total }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{CreateItem, UserInfo};
+    use crate::negotiate::negotiate;
+    use crate::test_utils::{body_string, cleanup_db, setup_test_state, test_csrf_token};
+
+    #[tokio::test]
+    async fn show_stats_counts_only_the_users_items() {
+        let (state, path) = setup_test_state().await;
+        let user = state
+            .db
+            .create_user("stats", "stats@example.com", "hash")
+            .await
+            .expect("create user");
+        let other = state
+            .db
+            .create_user("other", "other@example.com", "hash")
+            .await
+            .expect("create user");
+        for (user_id, title) in [(user.id, "Mine"), (user.id, "Also mine"), (other.id, "Not")] {
+            state
+                .db
+                .create_item(CreateItem {
+                    user_id,
+                    title: title.to_string(),
+                    description: None,
+                    due_at: None,
+                })
+                .await
+                .expect("create item");
+        }
+        let user = UserInfo::from(user);
+        let stats = || {
+            show_stats(
+                State(state.clone()),
+                RequireAuth(user.clone()),
+                test_csrf_token(),
+            )
+        };
+
+        let response = stats().await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_string(response).await, "STATS total=2 days=30");
+
+        let response = negotiate(Some("application/json"), stats().await);
+        let body: serde_json::Value =
+            serde_json::from_str(&body_string(response).await).expect("JSON body");
+        assert_eq!(body["total"], 2);
+        let days = body["days"].as_array().expect("days");
+        assert_eq!(days.len(), STATS_DAYS as usize);
+        assert_eq!(days[days.len() - 1]["count"], 2);
+        cleanup_db(path);
+    }
+}
@@ -0,0 +1,173 @@
+use rustapi_rs::prelude::*;
+use tera::Context;
+
+use crate::{
+    extractors::{AppCookies, CsrfProtected, CsrfToken, Form, Query, RequireAccount, RequireAuth},
+    models::{SudoForm, SudoQuery, UserInfo},
+    responses::redirect_with_cookies,
+    sudo,
+    templates::render,
+    tokens, AppState,
+};
+
+/// Ask for the password again before a sensitive action
+#[rustapi_rs::get("/sudo")]
+pub async fn show_sudo(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    CsrfToken(csrf_token): CsrfToken,
+    Query(query): Query<SudoQuery>,
+) -> Response {
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+    context.insert("next", sudo::safe_next(query.next.as_deref()));
+    render(&state, "auth/sudo.html", Some(&user), context)
+}
+
+/// Check the password and elevate the login for `Config::sudo_ttl_secs`,
+/// then return to `next`
+///
+/// Wrong passwords count against the login rate limit of the username, so
+/// the prompt cannot be used to guess it.
+#[rustapi_rs::post("/sudo")]
+pub async fn confirm_sudo(
+    State(state): State<AppState>,
+    RequireAccount(account): RequireAccount,
+    AppCookies(cookies): AppCookies,
+    CsrfProtected(csrf_token): CsrfProtected,
+    Form(form): Form<SudoForm>,
+) -> Response {
+    let next = sudo::safe_next(form.next.as_deref());
+    let user = UserInfo::from(account.clone());
+    let mut context = Context::new();
+    context.insert("csrf_token", &csrf_token);
+    context.insert("next", next);
+
+    if state.login_limiter.check(&account.username).is_err() {
+        context.insert(
+            "error",
+            "Too many incorrect passwords. Please try again later.",
+        );
+        let mut response = render(&state, "auth/sudo.html", Some(&user), context);
+        *response.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+        return response;
+    }
+
+    if !state
+        .config
+        .passwords
+        .verify(&account.password_hash, &form.password)
+    {
+        state.login_limiter.record_failure(&account.username);
+        state.login_throttle.fail(&account.username).await;
+        context.insert("error", "Incorrect password");
+        return render(&state, "auth/sudo.html", Some(&user), context);
+    }
+
+    // Bearer tokens come from API clients, which have no use for the cookie
+    let Some(claims) = cookies
+        .get(tokens::ACCESS_COOKIE)
+        .and_then(|token| state.jwt_keys.verify(token.value()))
+    else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let cookie = sudo::cookie(
+        &state.config.cookies,
+        &state.config.jwt_secret,
+        &claims,
+        state.config.sudo_ttl_secs,
+    );
+    redirect_with_cookies(&state, next, &[cookie])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{
+        body_string, cleanup_db, cookies_from, header_value, set_cookie_value, setup_test_state,
+        test_csrf, test_csrf_token, token_for_user,
+    };
+
+    async fn setup_account(state: &AppState, username: &str) -> (RequireAccount, String) {
+        let hash = state
+            .config
+            .passwords
+            .hash("secret")
+            .expect("hash password");
+        let user = state
+            .db
+            .create_user(username, &format!("{}@example.com", username), &hash)
+            .await
+            .expect("create user");
+        let token = token_for_user(&state.config.jwt_secret, user.id, username, 600);
+        (RequireAccount(user), token)
+    }
+
+    fn sudo_form(password: &str, next: &str) -> Form<SudoForm> {
+        Form(SudoForm {
+            password: password.to_string(),
+            next: Some(next.to_string()),
+        })
+    }
+
+    #[tokio::test]
+    async fn the_prompt_keeps_next_on_the_site() {
+        let (state, path) = setup_test_state().await;
+        let (account, _) = setup_account(&state, "tess").await;
+
+        let response = show_sudo(
+            State(state.clone()),
+            RequireAuth(UserInfo::from(account.0)),
+            test_csrf_token(),
+            Query(SudoQuery {
+                next: Some("https://evil.example".to_string()),
+            }),
+        )
+        .await;
+        let body = body_string(response).await;
+        assert!(body.contains("next=&#x2F;profile"));
+        assert!(!body.contains("evil"));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn the_right_password_elevates_the_login() {
+        let (state, path) = setup_test_state().await;
+        let (account, token) = setup_account(&state, "uma").await;
+        let cookies = cookies_from(&[(tokens::ACCESS_COOKIE, &token)]);
+
+        let response = confirm_sudo(
+            State(state.clone()),
+            RequireAccount(account.0.clone()),
+            cookies_from(&[(tokens::ACCESS_COOKIE, &token)]),
+            test_csrf(),
+            sudo_form("wrong", "/profile"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_string(response).await.contains("Incorrect password"));
+
+        let response = confirm_sudo(
+            State(state.clone()),
+            account,
+            cookies,
+            test_csrf(),
+            sudo_form("secret", "/profile"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/profile".to_string())
+        );
+        let sudo_cookie = set_cookie_value(&response, sudo::SUDO_COOKIE).expect("sudo cookie");
+        let claims = state.jwt_keys.verify(&token).expect("claims");
+        let cookies = cookies_from(&[(sudo::SUDO_COOKIE, &sudo_cookie)]);
+        assert!(sudo::is_elevated(
+            &state.config.jwt_secret,
+            &cookies,
+            &claims
+        ));
+        cleanup_db(path);
+    }
+}
@@ -0,0 +1,427 @@
+use rustapi_rs::prelude::*;
+use serde_json::{json, Value};
+
+use crate::{
+    errors::ApiErrorResponse,
+    extractors::{JsonBody, RealIp, RequireAuth, UserAgent},
+    handlers::{
+        api_items::json_error,
+        auth::{issue_session, record_login_event},
+    },
+    models::{PasskeyAssertion, PasskeyRegistration, MAX_PASSKEY_NAME_LENGTH},
+    responses::with_cookies,
+    validation::FieldErrors,
+    webauthn::{self, Ceremony, WebAuthnError, CHALLENGE_TTL},
+    AppState,
+};
+
+/// Name given to passkeys registered without one
+const DEFAULT_PASSKEY_NAME: &str = "Passkey";
+
+/// Options for `navigator.credentials.create()` to add a passkey to the
+/// current account; binary values are base64url
+///
+/// Neither this nor the finish step takes a CSRF token: the challenge only
+/// reaches the page that asked for it, and the browser signs the page's
+/// origin along with it.
+#[rustapi_rs::post("/webauthn/register/start")]
+pub async fn register_start(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+) -> Response {
+    let passkeys = match state.db.list_passkeys(user.id).await {
+        Ok(passkeys) => passkeys,
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return ApiErrorResponse::from(&e).into_response();
+        }
+    };
+    let Some(challenge) = state
+        .webauthn
+        .challenge(Ceremony::Register { user_id: user.id })
+    else {
+        return json_error(StatusCode::SERVICE_UNAVAILABLE, "too_many_challenges");
+    };
+
+    // Registering the same authenticator twice is refused by the browser
+    let exclude: Vec<Value> = passkeys
+        .iter()
+        .map(|passkey| json!({ "type": "public-key", "id": passkey.credential_id }))
+        .collect();
+    Json(json!({
+        "challenge": challenge,
+        "rp": { "id": state.webauthn.rp_id(), "name": webauthn::RP_NAME },
+        "user": {
+            "id": webauthn::user_handle(user.id),
+            "name": user.username,
+            "displayName": user.username,
+        },
+        "pubKeyCredParams": [{ "type": "public-key", "alg": webauthn::ES256 }],
+        "timeout": CHALLENGE_TTL.as_millis() as u64,
+        "attestation": "none",
+        "authenticatorSelection": {
+            "residentKey": "required",
+            "userVerification": "preferred",
+        },
+        "excludeCredentials": exclude,
+    }))
+    .into_response()
+}
+
+/// Store the passkey the browser created for a `register/start` challenge
+#[rustapi_rs::post("/webauthn/register/finish")]
+pub async fn register_finish(
+    State(state): State<AppState>,
+    RequireAuth(user): RequireAuth,
+    JsonBody(registration): JsonBody<PasskeyRegistration>,
+) -> Response {
+    let name = match registration.name.trim() {
+        "" => DEFAULT_PASSKEY_NAME,
+        name => name,
+    };
+    if name.chars().count() > MAX_PASSKEY_NAME_LENGTH {
+        let message = format!(
+            "Passkey name must be {} characters or less",
+            MAX_PASSKEY_NAME_LENGTH
+        );
+        let fields = FieldErrors::from([("name".to_string(), vec![message])]);
+        return ApiErrorResponse::validation(&fields).into_response();
+    }
+
+    let passkey = match state.webauthn.verify_registration(user.id, &registration) {
+        Ok(passkey) => passkey,
+        Err(e) => {
+            tracing::warn!(
+                user_id = user.id,
+                error = e.code(),
+                "passkey registration refused"
+            );
+            return json_error(StatusCode::BAD_REQUEST, e.code());
+        }
+    };
+
+    match state
+        .db
+        .create_passkey(
+            user.id,
+            &passkey.credential_id,
+            &passkey.public_key,
+            passkey.sign_count,
+            name,
+        )
+        .await
+    {
+        Ok(passkey) => (StatusCode::CREATED, Json(passkey)).into_response(),
+        Err(e)
+            if e.as_database_error()
+                .is_some_and(|db_err| db_err.is_unique_violation()) =>
+        {
+            json_error(StatusCode::CONFLICT, "conflict")
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            ApiErrorResponse::from(&e).into_response()
+        }
+    }
+}
+
+/// Options for `navigator.credentials.get()` to log in with a passkey
+///
+/// No username is asked for: the authenticator offers the passkeys it holds
+/// for this site, and the chosen one names its account.
+#[rustapi_rs::post("/webauthn/login/start")]
+pub async fn login_start(State(state): State<AppState>) -> Response {
+    let Some(challenge) = state.webauthn.challenge(Ceremony::Login) else {
+        return json_error(StatusCode::SERVICE_UNAVAILABLE, "too_many_challenges");
+    };
+    Json(json!({
+        "challenge": challenge,
+        "rpId": state.webauthn.rp_id(),
+        "timeout": CHALLENGE_TTL.as_millis() as u64,
+        "userVerification": "preferred",
+    }))
+    .into_response()
+}
+
+/// Log in with the passkey the browser signed a `login/start` challenge
+/// with, setting the same session cookies as `POST /login`
+///
+/// A signature counter that did not go up means the passkey has been
+/// copied to another authenticator, and the login is refused.
+#[rustapi_rs::post("/webauthn/login/finish")]
+pub async fn login_finish(
+    State(state): State<AppState>,
+    RealIp(client_ip): RealIp,
+    UserAgent(user_agent): UserAgent,
+    JsonBody(assertion): JsonBody<PasskeyAssertion>,
+) -> Response {
+    let passkey = match state.db.find_passkey(&assertion.credential_id).await {
+        Ok(Some(passkey)) => passkey,
+        Ok(None) => {
+            tracing::warn!(client_ip = %client_ip, "passkey login with unknown credential");
+            state.metrics.record_login("invalid_credentials");
+            return json_error(StatusCode::UNAUTHORIZED, "unknown_passkey");
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return ApiErrorResponse::from(&e).into_response();
+        }
+    };
+
+    let sign_count = match state.webauthn.verify_login(&assertion, &passkey.public_key) {
+        Ok(sign_count) => sign_count,
+        Err(e) => {
+            tracing::warn!(
+                user_id = passkey.user_id,
+                client_ip = %client_ip,
+                error = e.code(),
+                "failed passkey login"
+            );
+            state.metrics.record_login("invalid_credentials");
+            let status = if e == WebAuthnError::Malformed {
+                StatusCode::BAD_REQUEST
+            } else {
+                StatusCode::UNAUTHORIZED
+            };
+            return json_error(status, e.code());
+        }
+    };
+
+    match state.db.record_passkey_use(passkey.id, sign_count).await {
+        Ok(true) => {}
+        Ok(false) => {
+            tracing::warn!(
+                user_id = passkey.user_id,
+                passkey_id = passkey.id,
+                stored = passkey.sign_count,
+                reported = sign_count,
+                client_ip = %client_ip,
+                "passkey signature counter did not advance, possible cloned authenticator"
+            );
+            state.metrics.record_login("cloned_passkey");
+            return json_error(StatusCode::UNAUTHORIZED, "cloned_passkey");
+        }
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return ApiErrorResponse::from(&e).into_response();
+        }
+    }
+
+    let user = match state.db.find_user_by_id(passkey.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return json_error(StatusCode::UNAUTHORIZED, "unknown_passkey"),
+        Err(e) => {
+            tracing::error!(error = %e, "database error");
+            return ApiErrorResponse::from(&e).into_response();
+        }
+    };
+    if user
+        .locked_until
+        .is_some_and(|until| until > chrono::Utc::now())
+    {
+        state.metrics.record_login("locked");
+        return json_error(StatusCode::FORBIDDEN, "account_locked");
+    }
+    if !user.verified {
+        state.metrics.record_login("unverified");
+        return json_error(StatusCode::FORBIDDEN, "email_unverified");
+    }
+
+    let session_expires_at = state.config.session.session_end();
+    match issue_session(&state, &user, assertion.remember, session_expires_at).await {
+        Some(cookies) => {
+            state.metrics.record_login("success");
+            record_login_event(&state, user.id, client_ip, user_agent.as_deref()).await;
+            with_cookies(
+                Json(json!({ "redirect": state.config.base_path.url("/items") })).into_response(),
+                &cookies,
+            )
+        }
+        None => json_error(StatusCode::INTERNAL_SERVER_ERROR, "token_error"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::UserInfo;
+    use crate::test_utils::{
+        body_string, cleanup_db, set_cookie_value, setup_test_state, TestAuthenticator,
+    };
+    use crate::tokens::ACCESS_COOKIE;
+
+    async fn json_body(response: Response) -> Value {
+        serde_json::from_str(&body_string(response).await).expect("JSON body")
+    }
+
+    async fn setup_user(state: &AppState, username: &str, verified: bool) -> UserInfo {
+        let user = state
+            .db
+            .create_user(username, &format!("{}@example.com", username), "hash")
+            .await
+            .expect("create user");
+        if verified {
+            state.db.verify_user(user.id).await.expect("verify user");
+        }
+        UserInfo::from(user)
+    }
+
+    async fn start_registration(state: &AppState, user: &UserInfo) -> Value {
+        let response = register_start(State(state.clone()), RequireAuth(user.clone())).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        json_body(response).await
+    }
+
+    async fn register(
+        state: &AppState,
+        user: &UserInfo,
+        authenticator: &TestAuthenticator,
+    ) -> Response {
+        let options = start_registration(state, user).await;
+        let challenge = options["challenge"].as_str().expect("challenge");
+        let registration = authenticator.register(challenge, webauthn::DEFAULT_ORIGIN);
+        register_finish(
+            State(state.clone()),
+            RequireAuth(user.clone()),
+            JsonBody(registration),
+        )
+        .await
+    }
+
+    async fn log_in(state: &AppState, authenticator: &mut TestAuthenticator) -> Response {
+        let options = json_body(login_start(State(state.clone())).await).await;
+        assert_eq!(options["rpId"], webauthn::DEFAULT_RP_ID);
+        let challenge = options["challenge"].as_str().expect("challenge");
+        let assertion = authenticator.login(challenge, webauthn::DEFAULT_ORIGIN);
+        login_finish(
+            State(state.clone()),
+            RealIp("127.0.0.1".parse().expect("ip")),
+            UserAgent(None),
+            JsonBody(assertion),
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn registered_passkeys_log_their_owner_in() {
+        let (state, path) = setup_test_state().await;
+        let user = setup_user(&state, "pia", true).await;
+        let mut authenticator = TestAuthenticator::new(webauthn::DEFAULT_RP_ID);
+
+        let options = start_registration(&state, &user).await;
+        assert_eq!(options["rp"]["id"], webauthn::DEFAULT_RP_ID);
+        assert_eq!(options["user"]["name"], "pia");
+        assert_eq!(options["excludeCredentials"], json!([]));
+
+        let response = register(&state, &user, &authenticator).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(json_body(response).await["name"], DEFAULT_PASSKEY_NAME);
+
+        // The stored passkey is excluded from the next registration
+        let options = start_registration(&state, &user).await;
+        assert_eq!(
+            options["excludeCredentials"][0]["id"],
+            authenticator.credential_id()
+        );
+
+        let response = log_in(&state, &mut authenticator).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let token = set_cookie_value(&response, ACCESS_COOKIE).expect("access cookie");
+        let claims = state.jwt_keys.verify(&token).expect("valid token");
+        assert_eq!(claims.sub, user.id);
+        assert_eq!(json_body(response).await["redirect"], "/items");
+
+        let passkeys = state.db.list_passkeys(user.id).await.expect("list");
+        assert_eq!(passkeys[0].sign_count, 1);
+        assert!(passkeys[0].last_used_at.is_some());
+        let logins = state.db.recent_logins(user.id, 10).await.expect("logins");
+        assert_eq!(logins.len(), 1);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn a_passkey_is_registered_once() {
+        let (state, path) = setup_test_state().await;
+        let user = setup_user(&state, "quin", true).await;
+        let authenticator = TestAuthenticator::new(webauthn::DEFAULT_RP_ID);
+
+        assert_eq!(
+            register(&state, &user, &authenticator).await.status(),
+            StatusCode::CREATED
+        );
+        assert_eq!(
+            register(&state, &user, &authenticator).await.status(),
+            StatusCode::CONFLICT
+        );
+
+        // Nor can a response be replayed for another challenge's user
+        let other = setup_user(&state, "rhea", true).await;
+        let options = start_registration(&state, &user).await;
+        let registration = authenticator.register(
+            options["challenge"].as_str().expect("challenge"),
+            webauthn::DEFAULT_ORIGIN,
+        );
+        let response = register_finish(
+            State(state.clone()),
+            RequireAuth(other.clone()),
+            JsonBody(registration),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            json_body(response).await["error"]["code"],
+            "invalid_challenge"
+        );
+        assert!(state
+            .db
+            .list_passkeys(other.id)
+            .await
+            .expect("list")
+            .is_empty());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn counters_that_go_back_reveal_cloned_passkeys() {
+        let (state, path) = setup_test_state().await;
+        let user = setup_user(&state, "sol", true).await;
+        let mut authenticator = TestAuthenticator::new(webauthn::DEFAULT_RP_ID);
+        register(&state, &user, &authenticator).await;
+
+        authenticator.sign_count = 7;
+        assert_eq!(
+            log_in(&state, &mut authenticator).await.status(),
+            StatusCode::OK
+        );
+
+        // A copy of the key still reporting an older count
+        authenticator.sign_count = 7;
+        let response = log_in(&state, &mut authenticator).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(set_cookie_value(&response, ACCESS_COOKIE).is_none());
+        assert_eq!(json_body(response).await["error"]["code"], "cloned_passkey");
+        let passkeys = state.db.list_passkeys(user.id).await.expect("list");
+        assert_eq!(passkeys[0].sign_count, 7);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn unknown_and_unverified_logins_are_refused() {
+        let (state, path) = setup_test_state().await;
+        let mut stranger = TestAuthenticator::new(webauthn::DEFAULT_RP_ID);
+        let response = log_in(&state, &mut stranger).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            json_body(response).await["error"]["code"],
+            "unknown_passkey"
+        );
+
+        let user = setup_user(&state, "tao", false).await;
+        let mut authenticator = TestAuthenticator::new(webauthn::DEFAULT_RP_ID);
+        register(&state, &user, &authenticator).await;
+        let response = log_in(&state, &mut authenticator).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(set_cookie_value(&response, ACCESS_COOKIE).is_none());
+        cleanup_db(path);
+    }
+}
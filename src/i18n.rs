@@ -0,0 +1,255 @@
+use std::{collections::HashMap, future::Future, path::Path, sync::Arc};
+use tera::{Tera, Value};
+
+/// Directory the message catalogs are read from at startup, one
+/// `<locale>.json` object of message keys to text per locale
+pub const LOCALE_DIR: &str = "locales";
+
+/// Locale every message falls back to, which must have a catalog
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Cookie holding a visitor's chosen locale; it wins over `Accept-Language`
+pub const LOCALE_COOKIE: &str = "lang";
+
+/// Key of the message for a request that failed on the database
+pub const TRY_AGAIN: &str = "common.try_again";
+
+/// Key of the message heading a form's field errors
+pub const INVALID_FORM: &str = "common.invalid_form";
+
+/// Key of the message for an item the user has no access to
+pub const ITEM_NOT_FOUND: &str = "items.not_found";
+
+tokio::task_local! {
+    static LOCALE: String;
+}
+
+/// Run `f` as a request answered in `locale`, which `Messages::t` and the
+/// templates' `t` function then use
+pub async fn scope<F: Future>(locale: String, f: F) -> F::Output {
+    LOCALE.scope(locale, f).await
+}
+
+/// Locale of the request being handled; `DEFAULT_LOCALE` outside of one, as
+/// in handlers called directly from tests
+pub fn current_locale() -> String {
+    LOCALE
+        .try_with(Clone::clone)
+        .unwrap_or_else(|_| DEFAULT_LOCALE.to_string())
+}
+
+/// The message catalogs of every locale the UI is served in
+#[derive(Debug, Default)]
+pub struct Messages {
+    catalogs: HashMap<String, HashMap<String, String>>,
+}
+
+impl Messages {
+    /// Read every `*.json` catalog in `dir`, named after its locale
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut catalogs = HashMap::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(locale) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let catalog: HashMap<String, String> =
+                serde_json::from_str(&std::fs::read_to_string(&path)?)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+            catalogs.insert(locale.to_lowercase(), catalog);
+        }
+        if !catalogs.contains_key(DEFAULT_LOCALE) {
+            return Err(format!("no catalog for the default locale {}", DEFAULT_LOCALE).into());
+        }
+        Ok(Self { catalogs })
+    }
+
+    /// Message `key` in `locale`, falling back to `DEFAULT_LOCALE` and then
+    /// to the key itself
+    pub fn translate<'a>(&'a self, locale: &str, key: &'a str) -> &'a str {
+        [locale, DEFAULT_LOCALE]
+            .iter()
+            .find_map(|locale| self.catalogs.get(*locale)?.get(key))
+            .map_or(key, String::as_str)
+    }
+
+    /// Message `key` in the locale of the request being handled
+    pub fn t(&self, key: &str) -> String {
+        self.translate(&current_locale(), key).to_string()
+    }
+
+    /// Like `t`, with each `{name}` in the message replaced by its value
+    pub fn t_with(&self, key: &str, args: &[(&str, &str)]) -> String {
+        fill(&self.t(key), args.iter().copied())
+    }
+
+    /// Locale to answer a request in: the one chosen in the `lang` cookie,
+    /// else the most preferred `Accept-Language` one with a catalog, else
+    /// `DEFAULT_LOCALE`
+    ///
+    /// A regional tag like `fr-CA` is answered with `fr` when there is no
+    /// catalog for the region itself.
+    pub fn negotiate(&self, cookie: Option<&str>, accept_language: Option<&str>) -> String {
+        let mut wanted: Vec<(&str, f32)> = accept_language
+            .unwrap_or("")
+            .split(',')
+            .filter_map(|part| {
+                let mut params = part.split(';');
+                let tag = params.next()?.trim();
+                let quality = params
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+                (!tag.is_empty() && tag != "*" && quality > 0.0).then_some((tag, quality))
+            })
+            .collect();
+        // Stable, so equally preferred locales keep the client's order
+        wanted.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        cookie
+            .into_iter()
+            .chain(wanted.into_iter().map(|(tag, _)| tag))
+            .flat_map(|tag| {
+                let tag = tag.trim().to_lowercase();
+                let language = tag.split('-').next().unwrap_or_default().to_string();
+                [tag, language]
+            })
+            .find(|locale| self.catalogs.contains_key(locale))
+            .unwrap_or_else(|| DEFAULT_LOCALE.to_string())
+    }
+}
+
+/// Give templates `{{ t(key="nav.login") }}`, the message in the request's
+/// locale
+///
+/// Any other argument fills the `{name}` of the same name, e.g.
+/// `t(key="nav.welcome", username=user.username)`; the result is escaped like
+/// any other value.
+pub fn register(tera: &mut Tera, messages: Arc<Messages>) {
+    tera.register_function("t", move |args: &HashMap<String, Value>| {
+        let key = args
+            .get("key")
+            .and_then(Value::as_str)
+            .ok_or_else(|| tera::Error::msg("t expects a `key` string"))?;
+        let values: Vec<(&str, String)> = args
+            .iter()
+            .filter(|(name, _)| *name != "key")
+            .map(|(name, value)| {
+                let value = match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                (name.as_str(), value)
+            })
+            .collect();
+        Ok(Value::String(fill(
+            &messages.t(key),
+            values.iter().map(|(name, value)| (*name, value.as_str())),
+        )))
+    });
+}
+
+// `message` with each `{name}` replaced by its value
+fn fill<'a>(message: &str, args: impl Iterator<Item = (&'a str, &'a str)>) -> String {
+    args.fold(message.to_string(), |message, (name, value)| {
+        message.replace(&format!("{{{}}}", name), value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tera::Context;
+
+    fn messages() -> Messages {
+        Messages::load(LOCALE_DIR).expect("load catalogs")
+    }
+
+    #[test]
+    fn every_catalog_has_the_keys_of_the_default_one() {
+        let messages = messages();
+        let default = &messages.catalogs[DEFAULT_LOCALE];
+        for (locale, catalog) in &messages.catalogs {
+            let mut missing: Vec<&String> = default
+                .keys()
+                .filter(|k| !catalog.contains_key(*k))
+                .collect();
+            missing.sort();
+            assert!(missing.is_empty(), "{} lacks {:?}", locale, missing);
+        }
+    }
+
+    #[test]
+    fn locale_comes_from_the_cookie_then_accept_language() {
+        let messages = messages();
+        assert_eq!(messages.negotiate(None, None), "en");
+        assert_eq!(
+            messages.negotiate(None, Some("fr-CA,fr;q=0.9,en;q=0.8")),
+            "fr"
+        );
+        assert_eq!(
+            messages.negotiate(None, Some("de, en;q=0.5, fr;q=0.7")),
+            "fr"
+        );
+        assert_eq!(messages.negotiate(None, Some("fr;q=0, en")), "en");
+        assert_eq!(messages.negotiate(None, Some("xx-YY, *")), "en");
+        assert_eq!(messages.negotiate(Some("en"), Some("fr")), "en");
+        assert_eq!(messages.negotiate(Some("FR"), None), "fr");
+        // An unknown cookie value does not hide the header
+        assert_eq!(messages.negotiate(Some("xx"), Some("fr")), "fr");
+    }
+
+    #[tokio::test]
+    async fn messages_fall_back_to_english_and_then_the_key() {
+        let messages = messages();
+        assert_eq!(
+            messages.translate("fr", "auth.invalid_credentials"),
+            "Nom d'utilisateur ou mot de passe incorrect"
+        );
+        assert_eq!(
+            messages.translate("xx", "auth.invalid_credentials"),
+            "Invalid username or password"
+        );
+        assert_eq!(messages.translate("fr", "no.such.key"), "no.such.key");
+
+        assert_eq!(
+            messages.t("auth.invalid_credentials"),
+            "Invalid username or password"
+        );
+        let french = scope("fr".to_string(), async {
+            messages.t_with("auth.password_too_short", &[("min", "12")])
+        })
+        .await;
+        assert_eq!(
+            french,
+            "Le mot de passe doit contenir au moins 12 caractères"
+        );
+    }
+
+    #[tokio::test]
+    async fn templates_translate_in_the_request_locale() {
+        let mut tera = Tera::default();
+        register(&mut tera, Arc::new(messages()));
+        tera.add_raw_template(
+            "nav.html",
+            r#"{{ t(key="nav.login") }} | {{ t(key="nav.welcome", username=name) }}"#,
+        )
+        .expect("add template");
+        let mut context = Context::new();
+        context.insert("name", "<b>ana</b>");
+
+        let english = tera.render("nav.html", &context).expect("render");
+        assert_eq!(english, "Login | Welcome, &lt;b&gt;ana&lt;&#x2F;b&gt;!");
+        let french = scope("fr".to_string(), async {
+            tera.render("nav.html", &context)
+        })
+        .await
+        .expect("render");
+        assert_eq!(
+            french,
+            "Connexion | Bienvenue, &lt;b&gt;ana&lt;&#x2F;b&gt; !"
+        );
+    }
+}
@@ -0,0 +1,245 @@
+use jsonwebtoken::{decode, decode_header, encode, Algorithm, DecodingKey, EncodingKey, Header};
+
+use crate::models::Claims;
+
+/// Signing algorithm when `JWT_ALGORITHM` is unset
+pub const DEFAULT_ALGORITHM: Algorithm = Algorithm::HS256;
+
+/// `kid` of the `JWT_SECRET` key when `JWT_KEY_ID` is unset
+pub const DEFAULT_KEY_ID: &str = "primary";
+
+/// Why a key set could not be built from the configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JwtKeyError {
+    /// Only the HMAC algorithms take a shared secret
+    UnsupportedAlgorithm(String),
+    /// A `JWT_PREVIOUS_KEYS` entry without a `kid:` or a secret
+    InvalidPreviousKey(String),
+    /// Two keys with the same `kid`
+    DuplicateKeyId(String),
+}
+
+impl std::fmt::Display for JwtKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JwtKeyError::UnsupportedAlgorithm(name) => write!(
+                f,
+                "unsupported JWT algorithm {:?}; use HS256, HS384 or HS512",
+                name
+            ),
+            JwtKeyError::InvalidPreviousKey(entry) => {
+                write!(f, "JWT_PREVIOUS_KEYS entry {:?} is not kid:secret", entry)
+            }
+            JwtKeyError::DuplicateKeyId(kid) => write!(f, "JWT key id {:?} is used twice", kid),
+        }
+    }
+}
+
+impl std::error::Error for JwtKeyError {}
+
+struct JwtKey {
+    kid: String,
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+}
+
+impl JwtKey {
+    fn new(kid: &str, secret: &str) -> Self {
+        Self {
+            kid: kid.to_string(),
+            encoding: EncodingKey::from_secret(secret.as_bytes()),
+            decoding: DecodingKey::from_secret(secret.as_bytes()),
+        }
+    }
+}
+
+/// Keys access tokens are signed and verified with
+///
+/// New tokens are signed with one key and name it in their `kid` header.
+/// Earlier keys keep verifying the tokens they signed, so the signing
+/// secret can be rotated without logging everyone out: sign with the new
+/// key, keep the old one in `JWT_PREVIOUS_KEYS` until its tokens have
+/// expired, then drop it.
+pub struct JwtKeys {
+    algorithm: Algorithm,
+    // The signing key comes first
+    keys: Vec<JwtKey>,
+}
+
+impl JwtKeys {
+    /// Sign with `secret` under `kid`, and still verify tokens from each
+    /// `(kid, secret)` in `previous`
+    pub fn with_rotation(
+        algorithm: Algorithm,
+        kid: &str,
+        secret: &str,
+        previous: &[(String, String)],
+    ) -> Result<Self, JwtKeyError> {
+        if !matches!(
+            algorithm,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(JwtKeyError::UnsupportedAlgorithm(format!(
+                "{:?}",
+                algorithm
+            )));
+        }
+        let mut keys = vec![JwtKey::new(kid, secret)];
+        for (kid, secret) in previous {
+            if keys.iter().any(|key| key.kid == *kid) {
+                return Err(JwtKeyError::DuplicateKeyId(kid.clone()));
+            }
+            keys.push(JwtKey::new(kid, secret));
+        }
+        Ok(Self { algorithm, keys })
+    }
+
+    /// Parse `JWT_PREVIOUS_KEYS`: comma-separated `kid:secret` pairs, where
+    /// only the first `:` separates the two
+    pub fn parse_previous(list: &str) -> Result<Vec<(String, String)>, JwtKeyError> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| match entry.split_once(':') {
+                Some((kid, secret)) if !kid.trim().is_empty() && !secret.is_empty() => {
+                    Ok((kid.trim().to_string(), secret.to_string()))
+                }
+                _ => Err(JwtKeyError::InvalidPreviousKey(entry.to_string())),
+            })
+            .collect()
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// `kid` new tokens are signed under
+    pub fn signing_kid(&self) -> &str {
+        &self.keys[0].kid
+    }
+
+    pub fn sign(&self, claims: &Claims) -> Result<String, jsonwebtoken::errors::Error> {
+        let key = &self.keys[0];
+        let mut header = Header::new(self.algorithm);
+        header.kid = Some(key.kid.clone());
+        encode(&header, claims, &key.encoding)
+    }
+
+    /// Claims of a token one of the keys signed, if it has not expired
+    ///
+    /// A token naming a `kid` is only checked against that key, so tokens
+    /// of a retired key fail even before their signature is looked at.
+    /// Tokens without one, issued before keys had ids, are tried against
+    /// each key.
+    pub fn verify(&self, token: &str) -> Option<Claims> {
+        let header = decode_header(token).ok()?;
+        let validation = jsonwebtoken::Validation::new(self.algorithm);
+        let decode_with = |key: &JwtKey| {
+            decode::<Claims>(token, &key.decoding, &validation)
+                .ok()
+                .map(|data| data.claims)
+        };
+        match header.kid {
+            Some(kid) => self
+                .keys
+                .iter()
+                .find(|key| key.kid == kid)
+                .and_then(decode_with),
+            None => self.keys.iter().find_map(decode_with),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Role;
+
+    fn claims() -> Claims {
+        let now = chrono::Utc::now().timestamp();
+        Claims {
+            sub: 7,
+            username: "alice".to_string(),
+            exp: now + 600,
+            iat: now,
+            token_version: 0,
+            role: Role::User,
+            session_exp: now + 600,
+        }
+    }
+
+    fn keys(kid: &str, secret: &str, previous: &str) -> JwtKeys {
+        let previous = JwtKeys::parse_previous(previous).expect("previous keys");
+        JwtKeys::with_rotation(Algorithm::HS256, kid, secret, &previous).expect("keys")
+    }
+
+    #[test]
+    fn tokens_name_their_signing_key() {
+        let keys =
+            JwtKeys::with_rotation(Algorithm::HS512, "2026-10", "secret", &[]).expect("keys");
+        let token = keys.sign(&claims()).expect("sign");
+        let header = decode_header(&token).expect("header");
+        assert_eq!(header.alg, Algorithm::HS512);
+        assert_eq!(header.kid.as_deref(), Some("2026-10"));
+        assert_eq!(keys.verify(&token).map(|c| c.sub), Some(7));
+    }
+
+    #[test]
+    fn previous_keys_verify_until_retired() {
+        let old = keys("old", "old-secret", "");
+        let token = old.sign(&claims()).expect("sign");
+
+        // During the rotation window the old key still verifies
+        let rotating = keys("new", "new-secret", "old:old-secret");
+        assert_eq!(rotating.signing_kid(), "new");
+        assert_eq!(rotating.verify(&token).map(|c| c.sub), Some(7));
+
+        // Once retired it no longer does, nor does another key's secret
+        let retired = keys("new", "new-secret", "");
+        assert!(retired.verify(&token).is_none());
+        let impostor = keys("new", "new-secret", "old:guessed");
+        assert!(impostor.verify(&token).is_none());
+    }
+
+    #[test]
+    fn tokens_without_a_kid_try_every_key() {
+        let legacy = encode(
+            &Header::default(),
+            &claims(),
+            &EncodingKey::from_secret(b"old-secret"),
+        )
+        .expect("encode");
+        assert!(keys("new", "new-secret", "old:old-secret")
+            .verify(&legacy)
+            .is_some());
+        assert!(keys("new", "new-secret", "").verify(&legacy).is_none());
+    }
+
+    #[test]
+    fn rejects_bad_configuration() {
+        assert_eq!(
+            JwtKeys::parse_previous("a:one, b:two:with:colons ,"),
+            Ok(vec![
+                ("a".to_string(), "one".to_string()),
+                ("b".to_string(), "two:with:colons".to_string()),
+            ])
+        );
+        assert!(matches!(
+            JwtKeys::parse_previous("no-secret"),
+            Err(JwtKeyError::InvalidPreviousKey(_))
+        ));
+        assert!(matches!(
+            JwtKeys::with_rotation(Algorithm::RS256, "k", "secret", &[]),
+            Err(JwtKeyError::UnsupportedAlgorithm(_))
+        ));
+        assert!(matches!(
+            JwtKeys::with_rotation(
+                Algorithm::HS256,
+                "k",
+                "secret",
+                &[("k".to_string(), "other".to_string())]
+            ),
+            Err(JwtKeyError::DuplicateKeyId(_))
+        ));
+    }
+}
@@ -1,23 +1,89 @@
+mod base_path;
+mod cleanup;
+mod client_ip;
+mod config;
+mod cookies;
+mod csrf;
 mod db;
+mod errors;
+mod etag;
+mod events;
 mod extractors;
+mod feed;
+mod flash;
 mod handlers;
+mod i18n;
+mod jwt;
+mod maintenance;
+mod metrics;
 mod middleware;
 mod models;
+mod negotiate;
+mod openapi;
+mod passwords;
+mod pwned;
+mod rate_limit;
+mod responses;
+mod route_limits;
+mod security_headers;
+mod seed;
+mod shutdown;
+mod static_cache;
+mod strength;
+mod sudo;
+mod templates;
 #[cfg(test)]
 mod test_utils;
+mod timeout;
+mod tokens;
+mod uploads;
+mod validation;
+mod webauthn;
 
 use rustapi_rs::prelude::*;
 use std::sync::Arc;
-use tera::Tera;
+use tracing_subscriber::EnvFilter;
 
+use config::Config;
 use db::Database;
+use events::ItemEvents;
+use i18n::Messages;
+use jwt::JwtKeys;
+use maintenance::Maintenance;
+use metrics::Metrics;
+use pwned::{HttpRangeSource, PwnedPasswords};
+use rate_limit::{LoginRateLimiter, LoginThrottle};
+use seed::SeedOutcome;
+use shutdown::InFlight;
+use templates::Templates;
+use webauthn::WebAuthn;
 
 /// Application state shared across handlers
 #[derive(Clone)]
 pub struct AppState {
     pub db: Database,
-    pub tera: Arc<Tera>,
-    pub jwt_secret: String,
+    pub templates: Arc<Templates>,
+    /// UI text in every locale; `Messages::t` picks the request's
+    pub messages: Arc<Messages>,
+    /// Sign and verify access tokens, with `config.jwt_secret` as the
+    /// signing key
+    pub jwt_keys: Arc<JwtKeys>,
+    pub login_limiter: Arc<LoginRateLimiter>,
+    pub login_throttle: Arc<LoginThrottle>,
+    pub metrics: Arc<Metrics>,
+    pub in_flight: Arc<InFlight>,
+    /// While enabled, most routes answer 503 to everyone but admins
+    pub maintenance: Arc<Maintenance>,
+    /// Breached password lookups for new passwords; `None` unless enabled
+    pub pwned_passwords: Option<Arc<PwnedPasswords>>,
+    /// Item changes streamed by `GET /items/events`
+    pub item_events: Arc<ItemEvents>,
+    /// Passkey challenges and the relying party they are checked against
+    pub webauthn: Arc<WebAuthn>,
+    /// Everything read from the environment at startup; `jwt_secret` signs
+    /// the CSRF and flash cookies, and `public_url` starts links leaving the
+    /// browser
+    pub config: Arc<Config>,
 }
 
 #[tokio::main]
@@ -25,53 +91,170 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     // Load environment variables
     dotenvy::dotenv().ok();
 
-    let database_url =
-        std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite:data.db?mode=rwc".to_string());
-    let jwt_secret = std::env::var("JWT_SECRET")
-        .unwrap_or_else(|_| "your-super-secret-key-change-in-production".to_string());
-    let host = std::env::var("SERVER_HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
-    let port: u16 = std::env::var("SERVER_PORT")
-        .unwrap_or_else(|_| "8080".to_string())
-        .parse()
-        .unwrap_or(8080);
+    // Log level comes from `RUST_LOG`, e.g. `RUST_LOG=debug` or
+    // `RUST_LOG=info,sqlx=warn`; RustAPI keeps this subscriber
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
 
-    println!("🚀 Starting CRUD App with RustAPI...");
-    println!("📦 Connecting to database...");
+    let config = match Config::from_env() {
+        Ok(config) => Arc::new(config),
+        Err(errors) => {
+            tracing::error!("{}", errors);
+            std::process::exit(1);
+        }
+    };
+    if config.jwt_secret == config::DEFAULT_JWT_SECRET {
+        tracing::warn!(
+            environment = ?config.environment,
+            "JWT_SECRET is the example secret; set a real one before going to production"
+        );
+    }
+    let jwt_keys = config.jwt_keys()?;
+    tracing::info!(
+        algorithm = ?jwt_keys.algorithm(),
+        kid = jwt_keys.signing_kid(),
+        "access tokens configured"
+    );
+    let pool_config = config.pool.clone();
+    let pwned_passwords = if config.pwned_password_check {
+        let source = HttpRangeSource::new(
+            &config.pwned_password_api_url,
+            config.pwned_password_timeout,
+        )?;
+        Some(Arc::new(PwnedPasswords::new(source)))
+    } else {
+        None
+    };
+    // Raise RustAPI's 1 MiB default so attachments up to the limit fit
+    let body_limit = config.uploads.max_body_bytes().max(1024 * 1024);
+
+    tracing::info!("starting CRUD app, connecting to database");
 
     // Initialize database
-    let db = Database::new(&database_url).await?;
-    println!("✅ Database connected and migrations applied");
+    let db = Database::connect(&config.database_url, &pool_config)
+        .await?
+        .with_item_limits(config.item_limits);
+    tracing::info!(
+        max_connections = pool_config.max_connections,
+        acquire_timeout_secs = pool_config.acquire_timeout.as_secs(),
+        idle_timeout_secs = pool_config.idle_timeout.as_secs(),
+        sqlite_busy_timeout_ms = pool_config.busy_timeout.as_millis() as u64,
+        sqlite_write_retries = pool_config.write_retries,
+        "database connected and migrations applied"
+    );
+
+    // `cargo run -- seed` fills the database with demo data instead of serving
+    if std::env::args().nth(1).as_deref() == Some("seed") {
+        let passwords = &config.passwords;
+        let config = &config.seed;
+        match seed::seed(&db, config, passwords).await? {
+            SeedOutcome::Created { items } => println!(
+                "Seeded user '{}' with {} items; log in with password '{}'",
+                config.username, items, config.password
+            ),
+            SeedOutcome::AlreadySeeded => {
+                println!("User '{}' already exists; nothing to seed", config.username)
+            }
+        }
+        return Ok(());
+    }
+
+    let messages = match Messages::load(i18n::LOCALE_DIR) {
+        Ok(messages) => Arc::new(messages),
+        Err(e) => {
+            tracing::error!(error = %e, "failed to load message catalogs");
+            std::process::exit(1);
+        }
+    };
 
     // Initialize Tera templates
-    let tera = match Tera::new("templates/**/*.html") {
-        Ok(t) => Arc::new(t),
+    let templates = match templates::load(templates::TEMPLATE_GLOB).and_then(|t| {
+        templates::check_required(&t, templates::REQUIRED_TEMPLATES)?;
+        Ok(t)
+    }) {
+        Ok(mut t) => {
+            i18n::register(&mut t, messages.clone());
+            Arc::new(Templates::new(t, config.template_autoreload))
+        }
         Err(e) => {
-            eprintln!("Template parsing error: {}", e);
+            tracing::error!(error = %e, "failed to load templates");
             std::process::exit(1);
         }
     };
-    println!("✅ Templates loaded");
+    tracing::info!(autoreload = config.template_autoreload, "templates loaded");
 
-    // Create app state
+    // Create app state; shutdown keeps its own handles to drain and close
+    let in_flight = Arc::new(InFlight::default());
     let state = AppState {
-        db,
-        tera,
-        jwt_secret,
+        db: db.clone(),
+        templates,
+        messages,
+        jwt_keys: Arc::new(jwt_keys),
+        login_limiter: Arc::new(LoginRateLimiter::new(
+            config.login_max_attempts,
+            config.login_window,
+        )),
+        login_throttle: Arc::new(LoginThrottle::new(
+            config.login_delay_step,
+            config.login_delay_max,
+            config.login_window,
+        )),
+        metrics: Arc::new(Metrics::new()),
+        in_flight: in_flight.clone(),
+        maintenance: Arc::new(Maintenance::new(
+            config.maintenance_mode,
+            config.maintenance_retry_after_secs,
+        )),
+        pwned_passwords,
+        item_events: Arc::new(ItemEvents::default()),
+        webauthn: Arc::new(WebAuthn::new(
+            &config.webauthn_rp_id,
+            &config.webauthn_origin,
+        )),
+        config: config.clone(),
     };
 
-    println!("🌐 Server running at http://{}:{}", host, port);
-    println!("📝 Visit http://{}:{} to get started", host, port);
+    if config.token_cleanup_interval.is_zero() {
+        tracing::info!("expired token cleanup disabled");
+    } else {
+        tokio::spawn(cleanup::run(db.clone(), config.token_cleanup_interval));
+    }
+
+    tracing::info!("server running at http://{}:{}", config.host, config.port);
 
-    let addr = format!("{}:{}", host, port);
+    let addr = format!("{}:{}", config.host, config.port);
 
-    // Build and run RustAPI server (auto routes)
-    RustApi::auto()
+    // Build and run RustAPI server (auto routes); the spec and Swagger UI are
+    // mounted by `openapi` so they include our own schemas
+    openapi::mount(RustApi::config().docs_enabled(false).build())
         .state(state)
+        .body_limit(body_limit)
+        .layer(middleware::InFlightLayer)
+        .layer(middleware::RequestLogLayer)
+        .layer(middleware::LocaleLayer)
+        .layer(middleware::SecurityHeadersLayer)
+        .layer(middleware::MetricsLayer)
+        .layer(middleware::TimeoutLayer)
+        .layer(middleware::ErrorPageLayer)
+        .layer(middleware::MaintenanceLayer)
+        .layer(middleware::LoginRedirectLayer)
+        .layer(middleware::SlidingSessionLayer)
+        .layer(middleware::CsrfLayer)
+        .layer(middleware::FlashLayer)
+        .layer(middleware::NegotiateLayer)
+        .layer(middleware::StaticCacheLayer)
         // Static files
         .status_page()
-        .serve_static("/static", "static")
-        .run(&addr)
+        .serve_static_with_config(static_cache::config(config.static_max_age_secs))
+        .run_with_shutdown(&addr, shutdown::signal())
         .await?;
 
+    tracing::info!("stopped accepting connections");
+    shutdown::drain(&in_flight, &db, config.shutdown_timeout).await;
+    tracing::info!("shutdown complete");
+
     Ok(())
 }
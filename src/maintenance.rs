@@ -0,0 +1,62 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// `Retry-After` of maintenance responses unless
+/// `MAINTENANCE_RETRY_AFTER_SECS` overrides it: 5 minutes
+pub const DEFAULT_RETRY_AFTER_SECS: u64 = 300;
+
+/// The liveness check load balancers poll, answered even during maintenance
+pub const HEALTH_PATH: &str = "/healthz";
+
+/// Whether the app is down for maintenance, switchable at runtime
+#[derive(Debug, Default)]
+pub struct Maintenance {
+    enabled: AtomicBool,
+    retry_after_secs: u64,
+}
+
+impl Maintenance {
+    pub fn new(enabled: bool, retry_after_secs: u64) -> Self {
+        Self {
+            enabled: AtomicBool::new(enabled),
+            retry_after_secs,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Seconds clients are told to wait before trying again
+    pub fn retry_after_secs(&self) -> u64 {
+        self.retry_after_secs
+    }
+}
+
+/// Whether `path` stays up during maintenance: the health check, static
+/// assets, and the login page, so administrators can still sign in
+pub fn is_exempt(path: &str) -> bool {
+    path == HEALTH_PATH
+        || path == "/login"
+        || path
+            .strip_prefix(crate::static_cache::STATIC_PREFIX)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_health_static_and_login_are_exempt() {
+        for path in [HEALTH_PATH, "/static/css/style.css", "/login"] {
+            assert!(is_exempt(path), "{}", path);
+        }
+        for path in ["/", "/items", "/api/items", "/healthz/more", "/staticky"] {
+            assert!(!is_exempt(path), "{}", path);
+        }
+    }
+}
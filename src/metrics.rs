@@ -0,0 +1,209 @@
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry, TextEncoder,
+};
+
+use crate::openapi;
+
+/// Latency buckets in seconds, from 5ms to 10s
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Prometheus registry with the application's metrics
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_duration: HistogramVec,
+    item_operations: IntCounterVec,
+    login_attempts: IntCounterVec,
+    registrations: IntCounterVec,
+    routes: Vec<String>,
+}
+
+impl Metrics {
+    /// Create a fresh registry with every metric registered
+    pub fn new() -> Self {
+        let registry = Registry::new();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests by route and status"),
+            &["method", "route", "status"],
+        )
+        .expect("valid metric");
+        let http_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "HTTP request latency by route",
+            )
+            .buckets(LATENCY_BUCKETS.to_vec()),
+            &["method", "route"],
+        )
+        .expect("valid metric");
+        let item_operations = IntCounterVec::new(
+            Opts::new(
+                "item_operations_total",
+                "Successful item writes by operation",
+            ),
+            &["operation"],
+        )
+        .expect("valid metric");
+        let login_attempts = IntCounterVec::new(
+            Opts::new("login_attempts_total", "Login attempts by outcome"),
+            &["outcome"],
+        )
+        .expect("valid metric");
+        let registrations = IntCounterVec::new(
+            Opts::new("registrations_total", "Registration attempts by outcome"),
+            &["outcome"],
+        )
+        .expect("valid metric");
+
+        for collector in [
+            Box::new(http_requests.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(http_duration.clone()),
+            Box::new(item_operations.clone()),
+            Box::new(login_attempts.clone()),
+            Box::new(registrations.clone()),
+        ] {
+            registry.register(collector).expect("unique metric name");
+        }
+
+        Self {
+            registry,
+            http_requests,
+            http_duration,
+            item_operations,
+            login_attempts,
+            registrations,
+            routes: known_routes(),
+        }
+    }
+
+    /// Record one handled request
+    ///
+    /// Requests are labelled with the route template they match, e.g.
+    /// `/items/{id}`, whatever their status; paths no route serves share a
+    /// single `unmatched` route, so probing arbitrary URLs cannot create
+    /// unbounded label values.
+    pub fn observe_request(&self, method: &str, path: &str, status: u16, seconds: f64) {
+        let route = self.route_label(path);
+        self.http_requests
+            .with_label_values(&[method, route, &status.to_string()])
+            .inc();
+        self.http_duration
+            .with_label_values(&[method, route])
+            .observe(seconds);
+    }
+
+    /// The template of the route serving `path`, or `unmatched`
+    ///
+    /// A `{name}` segment matches any one segment; when several templates
+    /// match, the one with the most literal segments wins, as in the router,
+    /// so `/items/trash` is not counted as `/items/{id}`.
+    pub fn route_label(&self, path: &str) -> &str {
+        self.routes
+            .iter()
+            .filter_map(|route| literal_segments(route, path).map(|n| (n, route)))
+            .max_by_key(|(n, _)| *n)
+            .map_or("unmatched", |(_, route)| route.as_str())
+    }
+
+    /// Count a successful item write, e.g. `create` or `delete`
+    pub fn record_item_operation(&self, operation: &str) {
+        self.item_operations.with_label_values(&[operation]).inc();
+    }
+
+    /// Count a login attempt, e.g. `success` or `invalid_credentials`
+    pub fn record_login(&self, outcome: &str) {
+        self.login_attempts.with_label_values(&[outcome]).inc();
+    }
+
+    /// Count a registration attempt, `success` or `rejected`
+    pub fn record_registration(&self, outcome: &str) {
+        self.registrations.with_label_values(&[outcome]).inc();
+    }
+
+    /// Encode every metric in the Prometheus text exposition format
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!(error = %e, "failed to encode metrics");
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Every path template the app serves: the handlers' route macros, plus the
+/// spec and docs `openapi::mount` adds
+fn known_routes() -> Vec<String> {
+    let mut routes: Vec<String> = rustapi_core::collect_auto_routes()
+        .iter()
+        .map(|route| route.path().to_string())
+        .chain([openapi::SPEC_PATH, openapi::DOCS_PATH].map(String::from))
+        .collect();
+    routes.sort();
+    routes.dedup();
+    routes
+}
+
+/// How many literal segments `route` has if it matches `path`
+fn literal_segments(route: &str, path: &str) -> Option<usize> {
+    let mut pattern = route.split('/');
+    let mut segments = path.split('/');
+    let mut literal = 0;
+    loop {
+        match (pattern.next(), segments.next()) {
+            (None, None) => return Some(literal),
+            (Some(p), Some(s)) if p == s => literal += 1,
+            (Some(p), Some(s)) if p.starts_with('{') && !s.is_empty() => {}
+            _ => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn route_label_uses_route_templates() {
+        let metrics = Metrics::new();
+        assert_eq!(metrics.route_label("/items/42/edit"), "/items/{id}/edit");
+        assert_eq!(metrics.route_label("/items/trash"), "/items/trash");
+        assert_eq!(metrics.route_label("/share/b7Kq2x"), "/share/{slug}");
+        assert_eq!(metrics.route_label("/"), "/");
+        assert_eq!(metrics.route_label("/docs"), "/docs");
+        assert_eq!(metrics.route_label("/items/42/nope"), "unmatched");
+        assert_eq!(metrics.route_label("/items/"), "unmatched");
+    }
+
+    #[test]
+    fn encode_includes_recorded_series() {
+        let metrics = Metrics::new();
+        metrics.observe_request("GET", "/items/7", 200, 0.02);
+        metrics.observe_request("GET", "/wp-admin.php", 404, 0.001);
+        // Refused before routing, e.g. during maintenance
+        metrics.observe_request("GET", "/wp-login.php", 503, 0.001);
+        metrics.record_login("success");
+
+        let text = metrics.encode();
+        assert!(text
+            .contains(r#"http_requests_total{method="GET",route="/items/{id}",status="200"} 1"#));
+        assert!(text.contains(
+            r#"http_request_duration_seconds_count{method="GET",route="/items/{id}"} 1"#
+        ));
+        assert!(
+            text.contains(r#"http_requests_total{method="GET",route="unmatched",status="404"} 1"#)
+        );
+        assert!(
+            text.contains(r#"http_requests_total{method="GET",route="unmatched",status="503"} 1"#)
+        );
+        assert!(text.contains(r#"login_attempts_total{outcome="success"} 1"#));
+    }
+}
@@ -1,9 +1,23 @@
-use jsonwebtoken::{decode, DecodingKey, Validation};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use rustapi_rs::middleware::{BoxedNext, MiddlewareLayer};
 use rustapi_rs::prelude::*;
+use rustapi_rs::FromRequestParts;
+use std::{future::Future, pin::Pin, time::Instant};
+use tera::Context;
+use tracing::Instrument;
 
 use crate::{
-    models::{Claims, UserInfo},
-    AppState,
+    client_ip, csrf,
+    extractors::{AccessToken, CsrfToken},
+    flash,
+    handlers::api_items::json_error,
+    i18n, maintenance,
+    models::{Claims, Role, User, UserInfo},
+    negotiate,
+    route_limits::RouteLimits,
+    static_cache,
+    templates::{error_page, render},
+    tokens, AppState,
 };
 
 /// Extract JWT token from cookies
@@ -11,19 +25,1516 @@ fn extract_token_from_cookies(cookies: &Cookies) -> Option<String> {
     cookies.get("token").map(|c| c.value().to_string())
 }
 
-/// Get current user from JWT cookie
-pub async fn get_current_user(state: &AppState, cookies: &Cookies) -> Option<UserInfo> {
-    let token = extract_token_from_cookies(cookies)?;
+/// JWT from an `Authorization: Bearer <token>` header, as sent by API clients
+///
+/// A header that is not valid ASCII or uses another scheme yields `None`.
+fn bearer_token(req: &Request) -> Option<String> {
+    let value = req.headers().get("Authorization")?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    let token = token.trim();
+    (scheme.eq_ignore_ascii_case("bearer") && !token.is_empty()).then(|| token.to_string())
+}
 
-    let claims = decode::<Claims>(
-        &token,
-        &DecodingKey::from_secret(state.jwt_secret.as_bytes()),
-        &Validation::default(),
-    )
-    .ok()?
-    .claims;
+/// JWT a request authenticates with: the bearer header when there is a
+/// usable one, otherwise the `token` cookie
+pub fn access_token(req: &Request) -> Option<String> {
+    bearer_token(req).or_else(|| {
+        Cookies::from_request_parts(req)
+            .ok()
+            .and_then(|cookies| extract_token_from_cookies(&cookies))
+    })
+}
+
+/// API key from the `X-API-Key` header, as sent by other services
+pub fn api_key(req: &Request) -> Option<String> {
+    let key = req
+        .headers()
+        .get(tokens::API_KEY_HEADER)?
+        .to_str()
+        .ok()?
+        .trim();
+    (!key.is_empty()).then(|| key.to_string())
+}
+
+/// Get current user from the request's JWT
+pub async fn get_current_user(state: &AppState, token: &AccessToken) -> Option<UserInfo> {
+    user_from_token(state, token.0.as_deref()?).await
+}
+
+/// User a JWT was issued to, if it is valid and has not been revoked
+pub async fn user_from_token(state: &AppState, token: &str) -> Option<UserInfo> {
+    account_from_token(state, token).await.map(UserInfo::from)
+}
+
+/// Full record of the user a JWT was issued to, validated like
+/// `user_from_token`
+pub async fn account_from_token(state: &AppState, token: &str) -> Option<User> {
+    let claims = state.jwt_keys.verify(token)?;
 
     let user = state.db.find_user_by_id(claims.sub).await.ok()??;
 
+    // Tokens issued before the last "log out everywhere" are no longer valid
+    if user.token_version != claims.token_version {
+        return None;
+    }
+
+    Some(user)
+}
+
+/// User an API key belongs to, recording that the key was used; `None` for
+/// unknown and revoked keys
+pub async fn user_from_api_key(state: &AppState, key: &str) -> Option<UserInfo> {
+    let stored = state
+        .db
+        .find_api_key(&tokens::hash_token(key))
+        .await
+        .ok()??;
+    let user = state.db.find_user_by_id(stored.user_id).await.ok()??;
+
+    if let Err(e) = state.db.touch_api_key(stored.id).await {
+        tracing::warn!(error = %e, "failed to record API key use");
+    }
+
     Some(UserInfo::from(user))
 }
+
+/// Redirects unauthenticated HTML requests to the login page
+///
+/// `RequireAuth` rejects with 401; JSON routes under `/api/`, the `/refresh`
+/// and `/webauthn/` endpoints, which are called from scripts, and clients
+/// whose `Accept` prefers JSON keep that status.
+#[derive(Clone)]
+pub struct LoginRedirectLayer;
+
+impl MiddlewareLayer for LoginRedirectLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let keep_status = req.path().starts_with("/api/")
+            || req.path() == "/refresh"
+            || req.path().starts_with("/webauthn/")
+            || accept(&req).is_some_and(negotiate::prefers_json);
+        let login = req.state().get::<AppState>().map_or_else(
+            || "/login".to_string(),
+            |s| s.config.base_path.url("/login"),
+        );
+
+        Box::pin(async move {
+            let response = next(req).await;
+            if !keep_status && response.status() == StatusCode::UNAUTHORIZED {
+                return Redirect::to(&login).into_response();
+            }
+            response
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Renews the access cookie of an active session before it expires
+///
+/// A request whose cookie token has less than
+/// `SessionConfig::sliding_window_secs` left gets a fresh one with the
+/// response, up to the login's `session_exp`; past that the user logs in
+/// again. Bearer tokens are left alone, as are responses that set the cookie
+/// themselves or reject the token.
+#[derive(Clone)]
+pub struct SlidingSessionLayer;
+
+impl MiddlewareLayer for SlidingSessionLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        Box::pin(async move {
+            let Some(state) = req.state().get::<AppState>().cloned() else {
+                return next(req).await;
+            };
+            let claims = if bearer_token(&req).is_some() {
+                None
+            } else {
+                Cookies::from_request_parts(&req)
+                    .ok()
+                    .and_then(|cookies| extract_token_from_cookies(&cookies))
+                    .and_then(|token| state.jwt_keys.verify(&token))
+            };
+
+            let mut response = next(req).await;
+            let sets_token = response
+                .headers()
+                .get_all("Set-Cookie")
+                .iter()
+                .filter_map(|v| v.to_str().ok())
+                .any(|v| v.starts_with(&format!("{}=", tokens::ACCESS_COOKIE)));
+            if response.status() == StatusCode::UNAUTHORIZED || sets_token {
+                return response;
+            }
+            let renewed = claims.and_then(|claims| renewed_access_token(&state, &claims));
+            if let Some(value) = renewed.and_then(|token| {
+                tokens::access_cookie(&state.config.cookies, &token)
+                    .parse()
+                    .ok()
+            }) {
+                response.headers_mut().append("Set-Cookie", value);
+            }
+            response
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+// A fresh access token for the same login when `claims` is about to expire
+// and a new one would last longer
+fn renewed_access_token(state: &AppState, claims: &Claims) -> Option<String> {
+    let now = chrono::Utc::now().timestamp();
+    if claims.exp - now >= state.config.session.sliding_window_secs {
+        return None;
+    }
+    if (now + tokens::ACCESS_TOKEN_TTL_SECS).min(claims.session_exp) <= claims.exp {
+        return None;
+    }
+    tokens::create_access_token(
+        &state.jwt_keys,
+        claims.sub,
+        &claims.username,
+        claims.token_version,
+        claims.role,
+        claims.session_exp,
+    )
+    .map_err(|e| tracing::error!(error = %e, "failed to sign access token"))
+    .ok()
+}
+
+/// Ensures every visitor has a signed CSRF cookie
+///
+/// When the request carries no valid token a fresh one is generated, handed to
+/// handlers through the `CsrfToken` extractor, and set on the response.
+#[derive(Clone)]
+pub struct CsrfLayer;
+
+impl MiddlewareLayer for CsrfLayer {
+    fn call(
+        &self,
+        mut req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        Box::pin(async move {
+            let Some(state) = req.state().get::<AppState>().cloned() else {
+                return next(req).await;
+            };
+
+            let has_token = Cookies::from_request_parts(&req)
+                .ok()
+                .and_then(|cookies| csrf::token_from_cookies(&state.config.jwt_secret, &cookies))
+                .is_some();
+            if has_token {
+                return next(req).await;
+            }
+
+            let token = csrf::generate_token();
+            req.extensions_mut().insert(CsrfToken(token.clone()));
+
+            let mut response = next(req).await;
+            if let Ok(value) =
+                csrf::cookie_header(&state.config.cookies, &state.config.jwt_secret, &token).parse()
+            {
+                response.headers_mut().append("Set-Cookie", value);
+            }
+            response
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Response header carrying the request's correlation ID
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Logs method, path, status and duration of every request
+///
+/// Each request gets a fresh correlation ID; the handler runs inside a span
+/// carrying it, so every event logged while handling the request includes
+/// it, along with the client's address. The ID is also returned in the
+/// `X-Request-Id` header.
+#[derive(Clone)]
+pub struct RequestLogLayer;
+
+impl MiddlewareLayer for RequestLogLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let request_id = generate_request_id();
+        let client_ip = match req.state().get::<AppState>() {
+            Some(state) => state
+                .config
+                .trusted_proxies
+                .resolve(client_ip::peer_ip(&req), req.headers()),
+            None => client_ip::peer_ip(&req),
+        };
+        let span = tracing::info_span!(
+            "request",
+            request_id = %request_id,
+            client_ip = %client_ip,
+            method = %req.method(),
+            path = %req.path(),
+        );
+
+        Box::pin(
+            async move {
+                let started = Instant::now();
+                let mut response = next(req).await;
+                tracing::info!(
+                    status = response.status().as_u16(),
+                    duration_ms = started.elapsed().as_millis() as u64,
+                    "request completed"
+                );
+                if let Ok(value) = request_id.parse() {
+                    response.headers_mut().insert(REQUEST_ID_HEADER, value);
+                }
+                response
+            }
+            .instrument(span),
+        )
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Short random correlation ID (16 hex characters)
+fn generate_request_id() -> String {
+    let mut bytes = [0u8; 8];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Records the count and latency of every request in `AppState::metrics`
+#[derive(Clone)]
+pub struct MetricsLayer;
+
+impl MiddlewareLayer for MetricsLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let metrics = req.state().get::<AppState>().map(|s| s.metrics.clone());
+        let method = req.method().to_string();
+        let path = req.path().to_string();
+
+        Box::pin(async move {
+            let started = Instant::now();
+            let response = next(req).await;
+            if let Some(metrics) = metrics {
+                metrics.observe_request(
+                    &method,
+                    &path,
+                    response.status().as_u16(),
+                    started.elapsed().as_secs_f64(),
+                );
+            }
+            response
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Clears the flash cookie once an HTML page has been rendered with it
+///
+/// Redirects pass it on untouched (or replace it), so the message survives
+/// until the page it was meant for loads, and is shown there only once.
+#[derive(Clone)]
+pub struct FlashLayer;
+
+impl MiddlewareLayer for FlashLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        Box::pin(async move {
+            let Some(state) = req.state().get::<AppState>().cloned() else {
+                return next(req).await;
+            };
+            let has_flash = Cookies::from_request_parts(&req)
+                .is_ok_and(|cookies| cookies.get(flash::FLASH_COOKIE).is_some());
+
+            let mut response = next(req).await;
+            let rendered = !response.status().is_redirection()
+                && response
+                    .headers()
+                    .get("Content-Type")
+                    .and_then(|v| v.to_str().ok())
+                    .is_some_and(|v| v.starts_with("text/html"));
+            if has_flash && rendered {
+                if let Ok(value) = flash::removal(&state.config.cookies).parse() {
+                    response.headers_mut().append("Set-Cookie", value);
+                }
+            }
+            response
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Sends clients that prefer JSON the JSON a page handler offers in place of
+/// its page or redirect (see `negotiate::with_json`)
+#[derive(Clone)]
+pub struct NegotiateLayer;
+
+impl MiddlewareLayer for NegotiateLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let accept = accept(&req).map(str::to_string);
+
+        Box::pin(async move { negotiate::negotiate(accept.as_deref(), next(req).await) })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Handles each request in its visitor's locale, from the `lang` cookie or
+/// `Accept-Language` (see `i18n::Messages::negotiate`)
+#[derive(Clone)]
+pub struct LocaleLayer;
+
+impl MiddlewareLayer for LocaleLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let Some(state) = req.state().get::<AppState>().cloned() else {
+            return next(req);
+        };
+        let cookie = Cookies::from_request_parts(&req).ok().and_then(|cookies| {
+            cookies
+                .get(i18n::LOCALE_COOKIE)
+                .map(|c| c.value().to_string())
+        });
+        let accept_language = req
+            .headers()
+            .get("Accept-Language")
+            .and_then(|v| v.to_str().ok());
+        let locale = state.messages.negotiate(cookie.as_deref(), accept_language);
+
+        Box::pin(i18n::scope(locale, next(req)))
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+// The `Accept` header, unless it is not valid ASCII
+fn accept(req: &Request) -> Option<&str> {
+    req.headers().get("Accept")?.to_str().ok()
+}
+
+/// Answers 503 with the maintenance page while `AppState::maintenance` is
+/// enabled
+///
+/// Administrators keep using the app as usual, and `maintenance::is_exempt`
+/// routes stay up for everyone. API routes and clients preferring JSON get
+/// `{"error": "maintenance"}`; both forms carry `Retry-After`.
+#[derive(Clone)]
+pub struct MaintenanceLayer;
+
+impl MiddlewareLayer for MaintenanceLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let Some(state) = req.state().get::<AppState>().cloned() else {
+            return next(req);
+        };
+        if !state.maintenance.is_enabled() || maintenance::is_exempt(req.path()) {
+            return next(req);
+        }
+        let token = access_token(&req);
+        let wants_json =
+            req.path().starts_with("/api/") || accept(&req).is_some_and(negotiate::prefers_json);
+
+        Box::pin(async move {
+            if let Some(token) = token {
+                let user = user_from_token(&state, &token).await;
+                if user.is_some_and(|user| user.role.allows(Role::Admin)) {
+                    return next(req).await;
+                }
+            }
+
+            let mut response = if wants_json {
+                json_error(StatusCode::SERVICE_UNAVAILABLE, "maintenance")
+            } else {
+                let mut page = render(&state, "maintenance.html", None, Context::new());
+                *page.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                page
+            };
+            let retry_after = state.maintenance.retry_after_secs().to_string();
+            if let Ok(value) = retry_after.parse() {
+                response.headers_mut().insert("Retry-After", value);
+            }
+            response
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Answers conditional requests for static assets with `304 Not Modified`
+/// and keeps static HTML out of caches (see `static_cache::conditional`)
+#[derive(Clone)]
+pub struct StaticCacheLayer;
+
+impl MiddlewareLayer for StaticCacheLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let is_static = req
+            .uri()
+            .path()
+            .strip_prefix(static_cache::STATIC_PREFIX)
+            .is_some_and(|rest| rest.starts_with('/'));
+        if !is_static {
+            return next(req);
+        }
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let if_none_match = header("If-None-Match");
+        let if_modified_since = header("If-Modified-Since");
+
+        Box::pin(async move {
+            static_cache::conditional(
+                next(req).await,
+                if_none_match.as_deref(),
+                if_modified_since.as_deref(),
+            )
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Adds the configured `SecurityHeaders` to HTML pages, including the error,
+/// timeout and maintenance pages other layers answer with
+#[derive(Clone)]
+pub struct SecurityHeadersLayer;
+
+impl MiddlewareLayer for SecurityHeadersLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let Some(state) = req.state().get::<AppState>().cloned() else {
+            return next(req);
+        };
+        let path = req.path().to_string();
+
+        Box::pin(async move { state.config.security_headers.apply(&path, next(req).await) })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Counts requests in `AppState::in_flight` while they are handled, so
+/// shutdown can wait for them
+#[derive(Clone)]
+pub struct InFlightLayer;
+
+impl MiddlewareLayer for InFlightLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let guard = req.state().get::<AppState>().map(|s| s.in_flight.start());
+
+        Box::pin(async move {
+            let response = next(req).await;
+            drop(guard);
+            response
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Answers 504 when a request is not handled by the deadline of its route
+///
+/// The route's `RouteLimits` are resolved here and left in the request
+/// extensions, where the body extractors find its size limit.
+///
+/// At the deadline the handler's future is dropped, which cancels the
+/// queries it was waiting on and rolls back its open transaction; a
+/// statement SQLite already started still runs to its end. API routes and
+/// clients preferring JSON get `{"error": "timeout"}`.
+#[derive(Clone)]
+pub struct TimeoutLayer;
+
+impl MiddlewareLayer for TimeoutLayer {
+    fn call(
+        &self,
+        mut req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let Some(state) = req.state().get::<AppState>().cloned() else {
+            return next(req);
+        };
+        let limits = RouteLimits::for_route(&state, req.method(), req.path());
+        req.extensions_mut().insert(limits);
+        let deadline = limits.timeout;
+        let wants_json =
+            req.path().starts_with("/api/") || accept(&req).is_some_and(negotiate::prefers_json);
+
+        Box::pin(async move {
+            match tokio::time::timeout(deadline, next(req)).await {
+                Ok(response) => response,
+                Err(_) => {
+                    tracing::warn!(
+                        timeout_ms = deadline.as_millis() as u64,
+                        "request timed out"
+                    );
+                    if wants_json {
+                        json_error(StatusCode::GATEWAY_TIMEOUT, "timeout")
+                    } else {
+                        let mut page = render(&state, "timeout.html", None, Context::new());
+                        *page.status_mut() = StatusCode::GATEWAY_TIMEOUT;
+                        page
+                    }
+                }
+            }
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+/// Replaces bare 404 and 500 answers, such as the one for a route that does
+/// not exist, with the `404.html` and `500.html` pages
+///
+/// API routes and clients preferring JSON get the JSON error envelope
+/// instead. Answers that already are a page, or JSON, are left alone, as are
+/// other statuses.
+#[derive(Clone)]
+pub struct ErrorPageLayer;
+
+impl MiddlewareLayer for ErrorPageLayer {
+    fn call(
+        &self,
+        req: Request,
+        next: BoxedNext,
+    ) -> Pin<Box<dyn Future<Output = Response> + Send + 'static>> {
+        let Some(state) = req.state().get::<AppState>().cloned() else {
+            return next(req);
+        };
+        let token = access_token(&req);
+        let wants_json =
+            req.path().starts_with("/api/") || accept(&req).is_some_and(negotiate::prefers_json);
+
+        Box::pin(async move {
+            let response = next(req).await;
+            let status = response.status();
+            if status != StatusCode::NOT_FOUND && status != StatusCode::INTERNAL_SERVER_ERROR {
+                return response;
+            }
+            let content_type = response
+                .headers()
+                .get("Content-Type")
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("");
+            let is_json = content_type.starts_with("application/json");
+            if wants_json {
+                if is_json {
+                    return response;
+                }
+                let code = if status == StatusCode::NOT_FOUND {
+                    "not_found"
+                } else {
+                    "internal_error"
+                };
+                return json_error(status, code);
+            }
+            if is_json || content_type.starts_with("text/html") {
+                return response;
+            }
+
+            let user = match token {
+                Some(token) => user_from_token(&state, &token).await,
+                None => None,
+            };
+            error_page(&state, status, user.as_ref())
+        })
+    }
+
+    fn clone_box(&self) -> Box<dyn MiddlewareLayer> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flash::{Flash, FLASH_COOKIE};
+    use crate::test_utils::{
+        body_string, cleanup_db, header_value, request_with_state, set_cookie_value,
+        setup_test_state, token_for_user,
+    };
+    use crate::timeout::RequestTimeouts;
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+
+    fn unauthorized_next() -> BoxedNext {
+        Arc::new(|_req: Request| {
+            Box::pin(async { ApiError::unauthorized("Login required").into_response() })
+                as Pin<Box<dyn Future<Output = Response> + Send + 'static>>
+        })
+    }
+
+    #[tokio::test]
+    async fn login_redirect_layer_redirects_html_routes() {
+        let (state, path) = setup_test_state().await;
+        let req = request_with_state(&state, http::Request::get("/items"), "");
+
+        let response = LoginRedirectLayer.call(req, unauthorized_next()).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/login".to_string())
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn login_redirect_layer_keeps_the_base_path() {
+        let (mut state, path) = setup_test_state().await;
+        Arc::make_mut(&mut state.config).base_path =
+            crate::base_path::BasePath::parse("/app").expect("base path");
+        let req = request_with_state(&state, http::Request::get("/items"), "");
+
+        let response = LoginRedirectLayer.call(req, unauthorized_next()).await;
+        assert_eq!(
+            header_value(&response, "Location"),
+            Some("/app/login".to_string())
+        );
+        cleanup_db(path);
+    }
+
+    fn auth_request(
+        state: &AppState,
+        cookie: Option<&str>,
+        authorization: Option<http::HeaderValue>,
+    ) -> Request {
+        let mut builder = http::Request::get("/api/items");
+        if let Some(token) = cookie {
+            builder = builder.header("Cookie", format!("token={}", token));
+        }
+        if let Some(value) = authorization {
+            builder = builder.header("Authorization", value);
+        }
+        request_with_state(state, builder, "")
+    }
+
+    fn bearer(token: &str) -> Option<http::HeaderValue> {
+        http::HeaderValue::from_str(&format!("Bearer {}", token)).ok()
+    }
+
+    #[tokio::test]
+    async fn access_token_prefers_bearer_header_over_cookie() {
+        let (state, path) = setup_test_state().await;
+
+        let cookie_only = auth_request(&state, Some("from-cookie"), None);
+        assert_eq!(access_token(&cookie_only).as_deref(), Some("from-cookie"));
+
+        let header_only = auth_request(&state, None, bearer("from-header"));
+        assert_eq!(access_token(&header_only).as_deref(), Some("from-header"));
+
+        let both = auth_request(&state, Some("from-cookie"), bearer("from-header"));
+        assert_eq!(access_token(&both).as_deref(), Some("from-header"));
+
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn access_token_ignores_malformed_authorization() {
+        let (state, path) = setup_test_state().await;
+        let malformed = [
+            http::HeaderValue::from_static("Bearer"),
+            http::HeaderValue::from_static("Bearer   "),
+            http::HeaderValue::from_static("Basic dXNlcjpwYXNz"),
+            http::HeaderValue::from_bytes(b"Bearer \xff").expect("opaque header"),
+        ];
+
+        for value in malformed {
+            let req = auth_request(&state, None, Some(value.clone()));
+            assert_eq!(access_token(&req), None, "{:?}", value);
+            let req = auth_request(&state, Some("from-cookie"), Some(value));
+            assert_eq!(access_token(&req).as_deref(), Some("from-cookie"));
+        }
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn get_current_user_accepts_a_bearer_token() {
+        let (state, path) = setup_test_state().await;
+        let user = state
+            .db
+            .create_user("bearer", "bearer@example.com", "hash")
+            .await
+            .expect("create user");
+        let token = token_for_user(&state.config.jwt_secret, user.id, &user.username, 3600);
+
+        let req = auth_request(&state, None, bearer(&token));
+        let current = get_current_user(&state, &AccessToken(access_token(&req)))
+            .await
+            .expect("user from header");
+        assert_eq!(current.id, user.id);
+
+        // An invalid header is not rescued by a valid cookie
+        let req = auth_request(&state, Some(&token), bearer("not-a-jwt"));
+        assert!(get_current_user(&state, &AccessToken(access_token(&req)))
+            .await
+            .is_none());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn get_current_user_follows_key_rotation() {
+        use crate::jwt::{JwtKeys, DEFAULT_ALGORITHM};
+
+        let (mut state, path) = setup_test_state().await;
+        let user = state
+            .db
+            .create_user("rotated", "rotated@example.com", "hash")
+            .await
+            .expect("create user");
+        let keys = |kid: &str, secret: &str, previous: &[(&str, &str)]| {
+            let previous: Vec<(String, String)> = previous
+                .iter()
+                .map(|(kid, secret)| (kid.to_string(), secret.to_string()))
+                .collect();
+            Arc::new(
+                JwtKeys::with_rotation(DEFAULT_ALGORITHM, kid, secret, &previous).expect("keys"),
+            )
+        };
+        let now = chrono::Utc::now().timestamp();
+        state.jwt_keys = keys("2026-09", "old-secret", &[]);
+        let token = tokens::create_access_token(
+            &state.jwt_keys,
+            user.id,
+            &user.username,
+            user.token_version,
+            Role::User,
+            now + 3600,
+        )
+        .expect("sign");
+        let current = |state: AppState| {
+            let token = AccessToken(Some(token.clone()));
+            async move { get_current_user(&state, &token).await.map(|u| u.id) }
+        };
+
+        // Signing with a new key keeps the old one's tokens working
+        state.jwt_keys = keys("2026-10", "new-secret", &[("2026-09", "old-secret")]);
+        assert_eq!(current(state.clone()).await, Some(user.id));
+
+        // Until the old key is retired
+        state.jwt_keys = keys("2026-10", "new-secret", &[]);
+        assert_eq!(current(state.clone()).await, None);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn login_redirect_layer_keeps_api_status() {
+        let (state, path) = setup_test_state().await;
+        let req = request_with_state(&state, http::Request::get("/api/items"), "");
+
+        let response = LoginRedirectLayer.call(req, unauthorized_next()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        for path in ["/refresh", "/webauthn/register/start"] {
+            let req = request_with_state(&state, http::Request::post(path), "");
+            let response = LoginRedirectLayer.call(req, unauthorized_next()).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{}", path);
+        }
+
+        let req = request_with_state(
+            &state,
+            http::Request::get("/items").header("Accept", "application/json"),
+            "",
+        );
+        let response = LoginRedirectLayer.call(req, unauthorized_next()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        cleanup_db(path);
+    }
+
+    // Cookie token expiring in `exp_in` seconds, for a login ending in
+    // `session_in`
+    fn session_token(state: &AppState, exp_in: i64, session_in: i64) -> String {
+        let now = chrono::Utc::now().timestamp();
+        let claims = Claims {
+            sub: 1,
+            username: "slider".to_string(),
+            exp: now + exp_in,
+            iat: now,
+            token_version: 0,
+            role: crate::models::Role::User,
+            session_exp: now + session_in,
+        };
+        jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &claims,
+            &jsonwebtoken::EncodingKey::from_secret(state.config.jwt_secret.as_bytes()),
+        )
+        .expect("encode token")
+    }
+
+    // The access token a response sets, and its expiry in seconds from now
+    async fn renewed(state: &AppState, cookie: &str) -> Option<(String, i64)> {
+        let page: BoxedNext = Arc::new(|_req: Request| {
+            Box::pin(async { Html("<p>page</p>").into_response() })
+                as Pin<Box<dyn Future<Output = Response> + Send + 'static>>
+        });
+        let req = request_with_state(
+            state,
+            http::Request::get("/items").header("Cookie", format!("token={}", cookie)),
+            "",
+        );
+        let response = SlidingSessionLayer.call(req, page).await;
+        let token = set_cookie_value(&response, tokens::ACCESS_COOKIE)?;
+        let claims = state.jwt_keys.verify(&token).expect("valid token");
+        Some((token, claims.exp - chrono::Utc::now().timestamp()))
+    }
+
+    #[tokio::test]
+    async fn sliding_session_layer_renews_tokens_about_to_expire() {
+        let (state, path) = setup_test_state().await;
+
+        let (_, expires_in) = renewed(&state, &session_token(&state, 60, 3600))
+            .await
+            .expect("renewed token");
+        assert!(expires_in > tokens::ACCESS_TOKEN_TTL_SECS - 5);
+
+        // Plenty of time left, or no valid token at all
+        assert_eq!(
+            renewed(&state, &session_token(&state, 600, 3600)).await,
+            None
+        );
+        assert_eq!(renewed(&state, "not-a-jwt").await, None);
+
+        // Bearer clients renew their own tokens
+        let req = request_with_state(
+            &state,
+            http::Request::get("/api/items").header(
+                "Authorization",
+                format!("Bearer {}", session_token(&state, 60, 3600)),
+            ),
+            "",
+        );
+        let page: BoxedNext = Arc::new(|_req: Request| {
+            Box::pin(async { Json(serde_json::json!({})).into_response() })
+                as Pin<Box<dyn Future<Output = Response> + Send + 'static>>
+        });
+        let response = SlidingSessionLayer.call(req, page).await;
+        assert_eq!(set_cookie_value(&response, tokens::ACCESS_COOKIE), None);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn sliding_session_layer_stops_at_the_session_limit() {
+        let (state, path) = setup_test_state().await;
+
+        // Renewal runs up to the end of the login, not past it
+        let (token, expires_in) = renewed(&state, &session_token(&state, 60, 120))
+            .await
+            .expect("renewed token");
+        assert!((118..=120).contains(&expires_in));
+        assert_eq!(renewed(&state, &token).await, None);
+
+        // Once the token runs out the login is over
+        let expired = session_token(&state, -120, -120);
+        assert!(user_from_token(&state, &expired).await.is_none());
+        assert_eq!(renewed(&state, &expired).await, None);
+        cleanup_db(path);
+    }
+
+    fn echo_csrf_next() -> BoxedNext {
+        Arc::new(|req: Request| {
+            Box::pin(async move {
+                match CsrfToken::from_request_parts(&req) {
+                    Ok(CsrfToken(token)) => token.into_response(),
+                    Err(e) => e.into_response(),
+                }
+            }) as Pin<Box<dyn Future<Output = Response> + Send + 'static>>
+        })
+    }
+
+    #[tokio::test]
+    async fn csrf_layer_issues_cookie_for_new_visitors() {
+        let (state, path) = setup_test_state().await;
+        let req = request_with_state(&state, http::Request::get("/login"), "");
+
+        let response = CsrfLayer.call(req, echo_csrf_next()).await;
+        let set_cookie = header_value(&response, "Set-Cookie").unwrap_or_default();
+        let token = body_string(response).await;
+
+        assert!(set_cookie.starts_with("csrf="));
+        let value = set_cookie
+            .trim_start_matches("csrf=")
+            .split(';')
+            .next()
+            .unwrap_or_default();
+        assert_eq!(
+            csrf::verify_signed(&state.config.jwt_secret, value),
+            Some(token)
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn csrf_layer_keeps_existing_cookie() {
+        let (state, path) = setup_test_state().await;
+        let signed = csrf::sign_token(&state.config.jwt_secret, "existing");
+        let req = request_with_state(
+            &state,
+            http::Request::get("/login").header("Cookie", format!("csrf={}", signed)),
+            "",
+        );
+
+        let response = CsrfLayer.call(req, echo_csrf_next()).await;
+        assert_eq!(header_value(&response, "Set-Cookie"), None);
+        assert_eq!(body_string(response).await, "existing");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn metrics_layer_counts_requests_by_route_and_status() {
+        let (state, path) = setup_test_state().await;
+
+        for id in [1, 2] {
+            let uri = format!("/items/{}/edit", id);
+            let req = request_with_state(&state, http::Request::get(uri.as_str()), "");
+            let response = MetricsLayer.call(req, unauthorized_next()).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        }
+
+        let text = state.metrics.encode();
+        assert!(text.contains(
+            r#"http_requests_total{method="GET",route="/items/{id}/edit",status="401"} 2"#
+        ));
+        assert!(text.contains(
+            r#"http_request_duration_seconds_count{method="GET",route="/items/{id}/edit"} 2"#
+        ));
+        cleanup_db(path);
+    }
+
+    /// Collects formatted log output for assertions
+    #[derive(Clone, Default)]
+    struct LogBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn request_log_layer_tags_events_with_request_id() {
+        let (state, path) = setup_test_state().await;
+        let logs = LogBuffer::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let next: BoxedNext = Arc::new(|_req: Request| {
+            Box::pin(async {
+                tracing::error!("handler failed");
+                ApiError::internal("boom").into_response()
+            }) as Pin<Box<dyn Future<Output = Response> + Send + 'static>>
+        });
+        let req = request_with_state(&state, http::Request::get("/items"), "");
+        let response = RequestLogLayer.call(req, next).await;
+
+        let request_id = header_value(&response, REQUEST_ID_HEADER).expect("request id header");
+        assert_eq!(request_id.len(), 16);
+
+        let output = String::from_utf8(logs.0.lock().unwrap().clone()).expect("utf8 logs");
+        let line = |needle: &str| {
+            output
+                .lines()
+                .find(|line| line.contains(needle))
+                .unwrap_or_else(|| panic!("no log line with {:?} in {}", needle, output))
+                .to_string()
+        };
+        // The handler's own event and the completion event share the ID
+        let tag = format!("request_id={}", request_id);
+        assert!(line("handler failed").contains(&tag));
+        let completed = line("request completed");
+        assert!(completed.contains(&tag));
+        assert!(completed.contains("path=/items"));
+        assert!(completed.contains("client_ip=127.0.0.1"));
+        assert!(completed.contains("status=500"));
+        cleanup_db(path);
+    }
+
+    fn flash_request(state: &AppState, path: &str) -> Request {
+        let cookie = Flash::success("Saved").encode(&state.config.jwt_secret);
+        request_with_state(
+            state,
+            http::Request::get(path).header("Cookie", format!("{}={}", FLASH_COOKIE, cookie)),
+            "",
+        )
+    }
+
+    #[tokio::test]
+    async fn flash_layer_clears_the_cookie_once_a_page_renders() {
+        let (state, path) = setup_test_state().await;
+        let page: BoxedNext = Arc::new(|_req: Request| {
+            Box::pin(async { Html("<p>page</p>").into_response() })
+                as Pin<Box<dyn Future<Output = Response> + Send + 'static>>
+        });
+
+        let response = FlashLayer
+            .call(flash_request(&state, "/items"), page.clone())
+            .await;
+        assert_eq!(
+            header_value(&response, "Set-Cookie"),
+            Some(format!(
+                "{}=; Path=/; HttpOnly; SameSite=Lax; Secure; Max-Age=0",
+                FLASH_COOKIE
+            ))
+        );
+
+        // Nothing to clear without a flash
+        let req = request_with_state(&state, http::Request::get("/items"), "");
+        let response = FlashLayer.call(req, page).await;
+        assert_eq!(header_value(&response, "Set-Cookie"), None);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn flash_layer_keeps_the_cookie_across_redirects_and_json() {
+        let (state, path) = setup_test_state().await;
+        let redirect: BoxedNext = Arc::new(|_req: Request| {
+            Box::pin(async { Redirect::to("/items").into_response() })
+                as Pin<Box<dyn Future<Output = Response> + Send + 'static>>
+        });
+        let json: BoxedNext = Arc::new(|_req: Request| {
+            Box::pin(async { Json(serde_json::json!({"ok": true})).into_response() })
+                as Pin<Box<dyn Future<Output = Response> + Send + 'static>>
+        });
+
+        let response = FlashLayer
+            .call(flash_request(&state, "/items/1/delete"), redirect)
+            .await;
+        assert_eq!(set_cookie_value(&response, FLASH_COOKIE), None);
+        let response = FlashLayer
+            .call(flash_request(&state, "/api/items"), json)
+            .await;
+        assert_eq!(set_cookie_value(&response, FLASH_COOKIE), None);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn static_cache_layer_answers_conditional_requests() {
+        let (state, path) = setup_test_state().await;
+        let asset: BoxedNext = Arc::new(|_req: Request| {
+            Box::pin(async {
+                rustapi_rs::StaticFile::serve("css/style.css", &static_cache::config(600))
+                    .await
+                    .expect("serve static file")
+            }) as Pin<Box<dyn Future<Output = Response> + Send + 'static>>
+        });
+
+        let req = request_with_state(&state, http::Request::get("/static/css/style.css"), "");
+        let response = StaticCacheLayer.call(req, asset.clone()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = header_value(&response, "ETag").expect("ETag");
+
+        let req = request_with_state(
+            &state,
+            http::Request::get("/static/css/style.css").header("If-None-Match", &etag),
+            "",
+        );
+        let response = StaticCacheLayer.call(req, asset.clone()).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(body_string(response).await, "");
+
+        // Other routes are left alone
+        let req = request_with_state(
+            &state,
+            http::Request::get("/staticky").header("If-None-Match", &etag),
+            "",
+        );
+        let response = StaticCacheLayer.call(req, asset).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn maintenance_layer_answers_503_except_for_health_and_admins() {
+        let (state, path) = setup_test_state().await;
+        let admin = state
+            .db
+            .create_user("admin", "admin@example.com", "hash")
+            .await
+            .expect("create admin");
+        let user = state
+            .db
+            .create_user("user", "user@example.com", "hash")
+            .await
+            .expect("create user");
+        state.maintenance.set_enabled(true);
+        let ok: BoxedNext = Arc::new(|_req: Request| {
+            Box::pin(async { "ok".into_response() })
+                as Pin<Box<dyn Future<Output = Response> + Send + 'static>>
+        });
+        let get = |uri: &str, user_id: Option<i64>| {
+            let mut builder = http::Request::get(uri);
+            if let Some(user_id) = user_id {
+                let token = token_for_user(&state.config.jwt_secret, user_id, "someone", 3600);
+                builder = builder.header("Cookie", format!("token={}", token));
+            }
+            request_with_state(&state, builder, "")
+        };
+
+        let response = MaintenanceLayer.call(get("/items", None), ok.clone()).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            header_value(&response, "Retry-After").as_deref(),
+            Some(state.maintenance.retry_after_secs().to_string().as_str())
+        );
+        assert_eq!(body_string(response).await, "MAINTENANCE");
+
+        let response = MaintenanceLayer
+            .call(get("/api/items", Some(user.id)), ok.clone())
+            .await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert!(header_value(&response, "Retry-After").is_some());
+        let body: serde_json::Value =
+            serde_json::from_str(&body_string(response).await).expect("JSON body");
+        assert_eq!(body["error"]["code"], "maintenance");
+
+        for (uri, user_id) in [
+            (maintenance::HEALTH_PATH, None),
+            ("/static/css/style.css", None),
+            ("/items", Some(admin.id)),
+        ] {
+            let response = MaintenanceLayer.call(get(uri, user_id), ok.clone()).await;
+            assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+        }
+
+        state.maintenance.set_enabled(false);
+        let response = MaintenanceLayer.call(get("/items", None), ok).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        cleanup_db(path);
+    }
+
+    // Handler taking `delay`; `dropped` is set once its future is dropped
+    fn slow_next(delay: std::time::Duration, dropped: Arc<AtomicBool>) -> BoxedNext {
+        struct DropFlag(Arc<AtomicBool>);
+        impl Drop for DropFlag {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+        Arc::new(move |_req: Request| {
+            let flag = DropFlag(dropped.clone());
+            Box::pin(async move {
+                tokio::time::sleep(delay).await;
+                drop(flag);
+                Html("<p>done</p>").into_response()
+            }) as Pin<Box<dyn Future<Output = Response> + Send + 'static>>
+        })
+    }
+
+    #[tokio::test]
+    async fn timeout_layer_answers_504_and_cancels_slow_handlers() {
+        use std::time::Duration;
+
+        let (mut state, path) = setup_test_state().await;
+        Arc::make_mut(&mut state.config).request_timeouts = RequestTimeouts {
+            default: Duration::from_millis(50),
+            long: Duration::from_secs(5),
+        };
+
+        let dropped = Arc::new(AtomicBool::new(false));
+        let req = request_with_state(&state, http::Request::get("/items"), "");
+        let response = TimeoutLayer
+            .call(req, slow_next(Duration::from_secs(10), dropped.clone()))
+            .await;
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert!(dropped.load(Ordering::SeqCst), "slow handler kept running");
+        assert_eq!(body_string(response).await, "TIMEOUT");
+
+        let req = request_with_state(&state, http::Request::get("/api/items"), "");
+        let response = TimeoutLayer
+            .call(req, slow_next(Duration::from_secs(10), Arc::default()))
+            .await;
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert!(body_string(response).await.contains("\"timeout\""));
+
+        // Exports get the long deadline, logins keep the short one
+        let req = request_with_state(&state, http::Request::get("/items/export.json"), "");
+        let response = TimeoutLayer
+            .call(req, slow_next(Duration::from_millis(100), Arc::default()))
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let req = request_with_state(&state, http::Request::post("/login"), "");
+        let response = TimeoutLayer
+            .call(req, slow_next(Duration::from_millis(100), Arc::default()))
+            .await;
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        cleanup_db(path);
+    }
+
+    // What the router answers for a path no handler is registered for
+    fn unrouted_next() -> BoxedNext {
+        Arc::new(|_req: Request| {
+            Box::pin(async { (StatusCode::NOT_FOUND, "Not Found").into_response() })
+                as Pin<Box<dyn Future<Output = Response> + Send + 'static>>
+        })
+    }
+
+    #[tokio::test]
+    async fn error_page_layer_renders_the_404_page_for_unknown_paths() {
+        let (state, path) = setup_test_state().await;
+        let user = state
+            .db
+            .create_user("lost", "lost@example.com", "hash")
+            .await
+            .expect("create user");
+
+        let req = request_with_state(&state, http::Request::get("/no/such/page"), "");
+        let response = ErrorPageLayer.call(req, unrouted_next()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body_string(response).await, "NOT FOUND anonymous");
+
+        // The layout still knows who is logged in
+        let token = token_for_user(&state.config.jwt_secret, user.id, &user.username, 3600);
+        let req = request_with_state(
+            &state,
+            http::Request::get("/no/such/page").header("Cookie", format!("token={}", token)),
+            "",
+        );
+        let response = ErrorPageLayer.call(req, unrouted_next()).await;
+        assert_eq!(body_string(response).await, "NOT FOUND lost");
+
+        let req = request_with_state(&state, http::Request::get("/api/no-such-route"), "");
+        let response = ErrorPageLayer.call(req, unrouted_next()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(body_string(response).await.contains("\"not_found\""));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn error_page_layer_keeps_pages_and_json_errors() {
+        let (state, path) = setup_test_state().await;
+        let page: BoxedNext = Arc::new(|req: Request| {
+            Box::pin(async move {
+                let state = req.state().get::<AppState>().cloned().expect("state");
+                render(&state, "missing.html", None, Context::new())
+            }) as Pin<Box<dyn Future<Output = Response> + Send + 'static>>
+        });
+        let req = request_with_state(&state, http::Request::get("/items"), "");
+        let response = ErrorPageLayer.call(req, page).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body_string(response).await, "ERROR 500 anonymous");
+
+        let json: BoxedNext = Arc::new(|_req: Request| {
+            Box::pin(async { json_error(StatusCode::NOT_FOUND, "item_not_found") })
+                as Pin<Box<dyn Future<Output = Response> + Send + 'static>>
+        });
+        let req = request_with_state(&state, http::Request::get("/api/items/9"), "");
+        let response = ErrorPageLayer.call(req, json).await;
+        assert!(body_string(response).await.contains("\"item_not_found\""));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn security_headers_layer_hardens_rendered_pages() {
+        let (mut state, path) = setup_test_state().await;
+        let policy = "default-src 'self'; img-src https://images.example";
+        let config = Arc::make_mut(&mut state.config);
+        config.security_headers.content_security_policy = Some(policy.to_string());
+        config.security_headers.https = true;
+        let page: BoxedNext = Arc::new(|req: Request| {
+            Box::pin(async move {
+                let state = req.state().get::<AppState>().cloned().expect("state");
+                if req.path().starts_with("/api/") {
+                    return json_error(StatusCode::NOT_FOUND, "not_found");
+                }
+                render(&state, "maintenance.html", None, Context::new())
+            }) as Pin<Box<dyn Future<Output = Response> + Send + 'static>>
+        });
+
+        let req = request_with_state(&state, http::Request::get("/"), "");
+        let response = SecurityHeadersLayer.call(req, page.clone()).await;
+        assert_eq!(
+            header_value(&response, "Content-Security-Policy"),
+            Some(policy.to_string())
+        );
+        assert_eq!(
+            header_value(&response, "X-Content-Type-Options"),
+            Some("nosniff".to_string())
+        );
+        assert_eq!(
+            header_value(&response, "X-Frame-Options"),
+            Some("DENY".to_string())
+        );
+        assert_eq!(
+            header_value(&response, "Referrer-Policy"),
+            Some(crate::security_headers::DEFAULT_REFERRER_POLICY.to_string())
+        );
+        assert!(header_value(&response, "Strict-Transport-Security").is_some());
+
+        // JSON answers are not pages
+        let req = request_with_state(&state, http::Request::get("/api/items/9"), "");
+        let response = SecurityHeadersLayer.call(req, page.clone()).await;
+        assert_eq!(header_value(&response, "Content-Security-Policy"), None);
+
+        Arc::make_mut(&mut state.config).security_headers.enabled = false;
+        let req = request_with_state(&state, http::Request::get("/"), "");
+        let response = SecurityHeadersLayer.call(req, page).await;
+        assert_eq!(header_value(&response, "X-Frame-Options"), None);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn security_headers_layer_lets_the_docs_page_load_its_assets() {
+        let (state, path) = setup_test_state().await;
+        let docs: BoxedNext = Arc::new(|_req: Request| {
+            Box::pin(crate::openapi::swagger_ui())
+                as Pin<Box<dyn Future<Output = Response> + Send + 'static>>
+        });
+
+        let req = request_with_state(&state, http::Request::get(crate::openapi::DOCS_PATH), "");
+        let response = SecurityHeadersLayer.call(req, docs.clone()).await;
+        let policy = header_value(&response, "Content-Security-Policy").expect("policy");
+        let body = body_string(response).await;
+        let directive = |name: &str| {
+            policy
+                .split(';')
+                .map(str::trim)
+                .find(|d| d.starts_with(name))
+                .unwrap_or_default()
+                .to_string()
+        };
+        let assets: Vec<&str> = body
+            .split(['"', '\''])
+            .filter(|part| part.starts_with("https://"))
+            .collect();
+        assert!(!assets.is_empty());
+        for asset in assets {
+            let origin: String = asset.splitn(4, '/').take(3).collect::<Vec<_>>().join("/");
+            let directive = if asset.ends_with(".css") {
+                directive("style-src")
+            } else {
+                directive("script-src")
+            };
+            assert!(
+                directive.contains(&origin),
+                "{} blocks {}",
+                directive,
+                asset
+            );
+        }
+
+        // Other pages keep the configured policy
+        let req = request_with_state(&state, http::Request::get("/docs/other"), "");
+        let response = SecurityHeadersLayer.call(req, docs).await;
+        assert_eq!(
+            header_value(&response, "Content-Security-Policy"),
+            Some(crate::security_headers::DEFAULT_CONTENT_SECURITY_POLICY.to_string())
+        );
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn locale_layer_answers_in_the_accept_language_locale() {
+        let (state, path) = setup_test_state().await;
+        let page: BoxedNext = Arc::new(|req: Request| {
+            Box::pin(async move {
+                let state = req.state().get::<AppState>().cloned().expect("state");
+                state.messages.t("auth.invalid_credentials").into_response()
+            }) as Pin<Box<dyn Future<Output = Response> + Send + 'static>>
+        });
+        let request = |accept_language: Option<&str>, cookie: Option<&str>| {
+            let mut builder = http::Request::get("/login");
+            if let Some(value) = accept_language {
+                builder = builder.header("Accept-Language", value);
+            }
+            if let Some(value) = cookie {
+                builder = builder.header("Cookie", format!("lang={}", value));
+            }
+            request_with_state(&state, builder, "")
+        };
+
+        let response = LocaleLayer
+            .call(request(Some("fr-FR,fr;q=0.9,en;q=0.8"), None), page.clone())
+            .await;
+        assert_eq!(
+            body_string(response).await,
+            "Nom d'utilisateur ou mot de passe incorrect"
+        );
+        let response = LocaleLayer.call(request(None, None), page.clone()).await;
+        assert_eq!(body_string(response).await, "Invalid username or password");
+        // A chosen locale wins over the browser's
+        let response = LocaleLayer
+            .call(request(Some("fr"), Some("en")), page)
+            .await;
+        assert_eq!(body_string(response).await, "Invalid username or password");
+        cleanup_db(path);
+    }
+}
@@ -0,0 +1,64 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Attachment model representing the attachments table
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Attachment {
+    pub id: i64,
+    pub item_id: i64,
+    /// Original file name, for display and downloads only
+    pub filename: String,
+    /// Random name of the file inside the upload directory
+    #[serde(skip_serializing)]
+    pub stored_name: String,
+    pub content_type: String,
+    /// Size in bytes
+    pub size: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Attachment creation data, recorded after the file is stored
+#[derive(Debug)]
+pub struct CreateAttachment {
+    pub item_id: i64,
+    pub filename: String,
+    pub stored_name: String,
+    pub content_type: String,
+    pub size: i64,
+}
+
+/// Reduce a browser-supplied file name to a safe display name
+///
+/// Drops any directory part and control characters and caps the length;
+/// falls back to `file` when nothing is left.
+pub fn sanitize_filename(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = base
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .take(255)
+        .collect();
+    let cleaned = cleaned.trim();
+
+    if cleaned.is_empty() || cleaned.chars().all(|c| c == '.') {
+        "file".to_string()
+    } else {
+        cleaned.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_filename_strips_paths_and_controls() {
+        assert_eq!(sanitize_filename("report.pdf"), "report.pdf");
+        assert_eq!(sanitize_filename("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_filename(r"C:\Users\me\photo.png"), "photo.png");
+        assert_eq!(sanitize_filename("evil\"\r\n.txt"), "evil.txt");
+        assert_eq!(sanitize_filename(".."), "file");
+        assert_eq!(sanitize_filename(""), "file");
+    }
+}
@@ -0,0 +1,54 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+
+/// What an audited change did to an item, stored as text in
+/// `item_audit.action`, which accepts no other values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Create,
+    Update,
+    /// Moved to the trash, from where it can still be restored
+    Trash,
+    /// Taken back out of the trash
+    Restore,
+    /// Removed for good, from the trash or with its owner's account
+    Purge,
+    Archive,
+    Unarchive,
+    /// Given to another owner
+    Reassign,
+}
+
+impl AuditAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AuditAction::Create => "create",
+            AuditAction::Update => "update",
+            AuditAction::Trash => "trash",
+            AuditAction::Restore => "restore",
+            AuditAction::Purge => "purge",
+            AuditAction::Archive => "archive",
+            AuditAction::Unarchive => "unarchive",
+            AuditAction::Reassign => "reassign",
+        }
+    }
+}
+
+/// One change to an item, recorded with the change itself; see
+/// `DbTransaction::record_audit`
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ItemAudit {
+    pub id: i64,
+    pub item_id: i64,
+    /// Who made the change, unless their account is gone
+    pub user_id: Option<i64>,
+    /// An `AuditAction`
+    pub action: String,
+    /// The item as JSON before the change; none for a create
+    pub before_json: Option<String>,
+    /// The item as JSON after the change; none once it is purged
+    pub after_json: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
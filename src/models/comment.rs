@@ -0,0 +1,67 @@
+use chrono::{DateTime, Utc};
+use rustapi_macros::Validate;
+use rustapi_rs::prelude::*;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Longest comment body accepted, in characters
+pub const MAX_COMMENT_LENGTH: u64 = 2000;
+
+/// Comment model representing the comments table
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Comment {
+    pub id: i64,
+    pub item_id: i64,
+    pub user_id: i64,
+    /// Author's username; joined from `users`
+    pub username: String,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Form data for posting a comment
+#[derive(Debug, Deserialize, Validate, Schema)]
+pub struct CommentForm {
+    #[validate(
+        length(min = 1, message = "Comment is required"),
+        length(max = 2000, message = "Comment must be 2000 characters or less")
+    )]
+    pub body: String,
+}
+
+impl CommentForm {
+    /// The form with surrounding whitespace removed, so a blank comment
+    /// fails validation
+    pub fn trimmed(self) -> Self {
+        Self {
+            body: self.body.trim().to_string(),
+        }
+    }
+}
+
+/// Query parameters accepted by an item's comments page
+#[derive(Debug, Default, Deserialize, Schema)]
+pub struct CommentsQuery {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+    /// Keyword matched against comment bodies
+    pub q: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn comment_form_rejects_blank_and_overlong_bodies() {
+        let form = |body: &str| CommentForm {
+            body: body.to_string(),
+        };
+
+        assert!(form("Looks good").trimmed().validate().is_ok());
+        assert!(form("   \n").trimmed().validate().is_err());
+        let longest = "x".repeat(MAX_COMMENT_LENGTH as usize);
+        assert!(form(&longest).validate().is_ok());
+        assert!(form(&format!("{}x", longest)).validate().is_err());
+    }
+}
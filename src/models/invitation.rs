@@ -0,0 +1,40 @@
+use chrono::{DateTime, Utc};
+use rustapi_macros::Validate;
+use rustapi_rs::prelude::*;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Offer to hand an item over to the user with `email`; the link's token is
+/// only stored as a hash
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ItemInvitation {
+    pub id: i64,
+    pub item_id: i64,
+    pub sender_id: i64,
+    pub email: String,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ItemInvitation {
+    /// Whether the invitation can still be accepted
+    pub fn is_active(&self) -> bool {
+        self.expires_at > Utc::now()
+    }
+}
+
+/// Form data for inviting someone to take over an item
+#[derive(Debug, Deserialize, Validate, Schema)]
+pub struct InvitationForm {
+    /// Email address of the user who should become the owner
+    #[validate(email(message = "Enter a valid email address"))]
+    pub email: String,
+}
+
+/// Query parameters of an invitation link
+#[derive(Debug, Deserialize, Schema)]
+pub struct InvitationQuery {
+    pub token: String,
+}
@@ -0,0 +1,61 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+
+/// Who may create an account, from `REGISTRATION_ENABLED` and
+/// `REGISTRATION_INVITE_ONLY`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistrationMode {
+    /// Anyone may register
+    #[default]
+    Open,
+    /// Registering takes an unused invite code from an admin
+    InviteOnly,
+    /// Nobody may register; existing accounts still log in
+    Closed,
+}
+
+impl RegistrationMode {
+    pub fn new(enabled: bool, invite_only: bool) -> Self {
+        match (enabled, invite_only) {
+            (false, _) => RegistrationMode::Closed,
+            (true, true) => RegistrationMode::InviteOnly,
+            (true, false) => RegistrationMode::Open,
+        }
+    }
+}
+
+/// Single-use code letting someone register while registration is
+/// invite-only; the code itself is only stored as a hash
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Invite {
+    pub id: i64,
+    #[serde(skip_serializing)]
+    pub code_hash: String,
+    /// Admin who created it, unless their account is gone
+    pub created_by: Option<i64>,
+    pub created_at: DateTime<Utc>,
+    /// Account registered with it, once used
+    pub used_by: Option<i64>,
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabling_registration_wins_over_invites() {
+        assert_eq!(RegistrationMode::new(true, false), RegistrationMode::Open);
+        assert_eq!(
+            RegistrationMode::new(true, true),
+            RegistrationMode::InviteOnly
+        );
+        assert_eq!(RegistrationMode::new(false, true), RegistrationMode::Closed);
+        assert_eq!(
+            serde_json::to_value(RegistrationMode::InviteOnly).expect("serialize"),
+            "invite_only"
+        );
+    }
+}
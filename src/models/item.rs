@@ -1,5 +1,14 @@
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{de, Deserialize, Deserializer, Serialize};
 use sqlx::FromRow;
+use std::collections::BTreeSet;
+
+/// Longest item title unless `MAX_TITLE_LENGTH` overrides it, in characters
+pub const DEFAULT_MAX_TITLE_LENGTH: usize = 200;
+
+/// Longest item description unless `MAX_DESCRIPTION_LENGTH` overrides it,
+/// in characters
+pub const DEFAULT_MAX_DESCRIPTION_LENGTH: usize = 10_000;
 
 /// Item model representing the items table
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -8,29 +17,653 @@ pub struct Item {
     pub user_id: i64,
     pub title: String,
     pub description: Option<String>,
-    pub created_at: String,
-    pub updated_at: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Set while the item sits in the trash
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Kept out of the default item list; see `ItemFilter::archived`
+    pub archived: bool,
+    /// Day the item is due, if it has one; see `Database::get_overdue_items`
+    pub due_at: Option<NaiveDate>,
+    /// Tag names, sorted; loaded separately from `item_tags`
+    #[sqlx(skip)]
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl Item {
+    /// Title for a duplicate of this item, "Copy of ..." cut to `max_length`
+    /// characters
+    pub fn copy_title(&self, max_length: usize) -> String {
+        format!("Copy of {}", self.title)
+            .chars()
+            .take(max_length)
+            .collect()
+    }
+}
+
+/// Longest title and description an item may have, in characters once
+/// trimmed
+///
+/// Read from the environment at startup, so unlike the `#[validate(...)]`
+/// rules they are checked at runtime: by `ItemForm::validate_with`, and by
+/// `Database` before any item is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ItemLimits {
+    pub title: usize,
+    pub description: usize,
+}
+
+impl Default for ItemLimits {
+    fn default() -> Self {
+        Self {
+            title: DEFAULT_MAX_TITLE_LENGTH,
+            description: DEFAULT_MAX_DESCRIPTION_LENGTH,
+        }
+    }
+}
+
+impl ItemLimits {
+    /// The fields over the limits, reported like failed `#[validate]` rules
+    pub fn check(&self, title: &str, description: Option<&str>) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if title.trim().chars().count() > self.title {
+            errors.add(
+                "title",
+                RuleError::new("length", "Title must be {max} characters or less")
+                    .param("max", self.title),
+            );
+        }
+        if description.is_some_and(|d| d.trim().chars().count() > self.description) {
+            errors.add(
+                "description",
+                RuleError::new("length", "Description must be {max} characters or less")
+                    .param("max", self.description),
+            );
+        }
+        errors.into_result()
+    }
+}
+
+/// Items a user created on one day, in their timezone
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DayCount {
+    pub day: NaiveDate,
+    pub count: i64,
+}
+
+/// One of a user's items open to anyone at `/share/{share_slug}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, FromRow)]
+pub struct SharedItem {
+    pub id: i64,
+    pub title: String,
+    pub share_slug: String,
 }
 
 use rustapi_macros::Validate;
 use rustapi_rs::prelude::*;
+use rustapi_validate::v2::{RuleError, ValidationErrors};
 
 /// Form data for creating/updating items
-#[derive(Debug, Deserialize, Validate)]
+#[derive(Debug, Deserialize, Validate, Schema)]
 pub struct ItemForm {
-    #[validate(
-        length(min = 1, message = "Title is required"),
-        length(max = 200, message = "Title must be 200 characters or less")
-    )]
+    /// At most `ItemLimits::title` characters, checked by `validate_with`
+    #[validate(length(min = 1, message = "Title is required"))]
     pub title: String,
 
+    /// At most `ItemLimits::description` characters
     pub description: Option<String>,
+
+    /// Comma-separated tag names; may be omitted
+    #[serde(default)]
+    #[validate(length(max = 500, message = "Tags must be 500 characters or less"))]
+    pub tags: String,
+
+    /// Due date as `YYYY-MM-DD`, what a date input submits; blank for none
+    #[serde(default)]
+    pub due_at: Option<String>,
+}
+
+impl ItemForm {
+    /// Run the `#[validate(...)]` rules and check the configured `limits`,
+    /// reporting every failure at once
+    pub fn validate_with(&self, limits: &ItemLimits) -> Result<(), ValidationErrors> {
+        let mut errors = self.validate().err().unwrap_or_default();
+        if let Err(over) = limits.check(&self.title, self.description.as_deref()) {
+            errors.merge(over);
+        }
+        if self.due_date().is_err() {
+            errors.add(
+                "due_at",
+                RuleError::new("date", "Due date must be a date like 2024-12-31"),
+            );
+        }
+        errors.into_result()
+    }
+
+    /// Normalized, deduplicated tags from the comma-separated field
+    pub fn tag_list(&self) -> Vec<String> {
+        normalize_tags(&self.tags)
+    }
+
+    /// The parsed due date, `None` when the field is blank
+    pub fn due_date(&self) -> Result<Option<NaiveDate>, chrono::ParseError> {
+        match self.due_at.as_deref().map(str::trim) {
+            None | Some("") => Ok(None),
+            Some(value) => NaiveDate::parse_from_str(value, "%Y-%m-%d").map(Some),
+        }
+    }
+}
+
+/// Normalize a single tag: trimmed, lowercased, inner whitespace collapsed
+pub fn normalize_tag(tag: &str) -> Option<String> {
+    let tag = tag.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!tag.is_empty()).then(|| tag.to_lowercase())
+}
+
+/// Split a comma-separated tag list into sorted, unique, normalized tags
+pub fn normalize_tags(input: &str) -> Vec<String> {
+    input
+        .split(',')
+        .filter_map(normalize_tag)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// One record of an `/items/import` file, in the shape `/items/export.json`
+/// produces; `id`, `user_id` and timestamps are ignored
+#[derive(Debug, Deserialize)]
+pub struct ImportItem {
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub due_at: Option<NaiveDate>,
+}
+
+impl ImportItem {
+    /// The same form a user would submit, so imports get its validation;
+    /// the title is trimmed first so a blank one fails it
+    pub fn into_form(self) -> ItemForm {
+        ItemForm {
+            title: self.title.trim().to_string(),
+            description: self.description,
+            tags: self.tags.join(", "),
+            due_at: self.due_at.map(|day| day.to_string()),
+        }
+    }
 }
 
 /// Item creation data (includes user_id)
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CreateItem {
     pub user_id: i64,
     pub title: String,
     pub description: Option<String>,
+    pub due_at: Option<NaiveDate>,
+}
+
+impl CreateItem {
+    /// The item every new account starts with
+    pub fn welcome(user_id: i64) -> Self {
+        Self {
+            user_id,
+            title: "Welcome to your list".to_string(),
+            description: Some(
+                "This item was created with your account. Edit or delete it whenever you like."
+                    .to_string(),
+            ),
+            due_at: None,
+        }
+    }
+}
+
+/// Action applied to every selected item by the bulk form
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BulkAction {
+    Delete,
+    Archive,
+}
+
+impl BulkAction {
+    /// Parse the submitted `action` field
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "delete" => Some(BulkAction::Delete),
+            "archive" => Some(BulkAction::Archive),
+            _ => None,
+        }
+    }
+}
+
+/// Form data for `/items/bulk`: a repeated `ids` checkbox field and an action
+#[derive(Debug, Schema)]
+pub struct BulkItemsForm {
+    pub ids: Vec<i64>,
+    pub action: String,
+}
+
+// `serde_urlencoded` cannot collect repeated keys into a `Vec`, so the form is
+// read as raw pairs instead of derived
+impl<'de> Deserialize<'de> for BulkItemsForm {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut form = BulkItemsForm {
+            ids: Vec::new(),
+            action: String::new(),
+        };
+        for (name, value) in Vec::<(String, String)>::deserialize(deserializer)? {
+            match name.as_str() {
+                "ids" => form.ids.push(
+                    value
+                        .parse()
+                        .map_err(|_| de::Error::custom(format!("invalid item id `{}`", value)))?,
+                ),
+                "action" => form.action = value,
+                _ => {}
+            }
+        }
+        Ok(form)
+    }
+}
+
+/// Form data for `/items/reorder`: a repeated `ids` field listing items in
+/// their new order
+#[derive(Debug, Schema)]
+pub struct ReorderItemsForm {
+    pub ids: Vec<i64>,
+}
+
+// Read as raw pairs for the same reason as `BulkItemsForm`
+impl<'de> Deserialize<'de> for ReorderItemsForm {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut ids = Vec::new();
+        for (name, value) in Vec::<(String, String)>::deserialize(deserializer)? {
+            if name == "ids" {
+                ids.push(
+                    value
+                        .parse()
+                        .map_err(|_| de::Error::custom(format!("invalid item id `{}`", value)))?,
+                );
+            }
+        }
+        Ok(ReorderItemsForm { ids })
+    }
+}
+
+/// Form data for `/admin/items/{id}/reassign`
+#[derive(Debug, Deserialize, Schema)]
+pub struct ReassignItemForm {
+    /// Id of the user the item moves to
+    pub user_id: i64,
+}
+
+/// Query parameters accepted by the item list page
+#[derive(Debug, Default, Deserialize, Schema)]
+pub struct ListItemsQuery {
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+    /// Keyword matched against title and description
+    pub q: Option<String>,
+    /// `title`, `created_at`, `updated_at` or `relevance`; searches default
+    /// to `relevance`
+    pub sort: Option<String>,
+    /// `asc` or `desc`
+    pub order: Option<String>,
+    /// Only show items carrying this tag
+    pub tag: Option<String>,
+    /// `1` to list archived items instead of active ones
+    pub archived: Option<String>,
+}
+
+impl ListItemsQuery {
+    /// Whether the archived view was asked for
+    pub fn archived(&self) -> bool {
+        matches!(self.archived.as_deref(), Some("1" | "true"))
+    }
+}
+
+/// Query parameters accepted by the JSON item list
+#[derive(Debug, Default, Deserialize, Schema)]
+pub struct ApiItemsQuery {
+    /// Only return items carrying this tag
+    pub tag: Option<String>,
+    /// `next_cursor` of the previous page, to continue after it
+    pub cursor: Option<String>,
+    /// Items per page; with it or `cursor` the list comes one page at a time
+    pub limit: Option<i64>,
+    /// RFC 3339 time; only items changed after it are returned, along with
+    /// those trashed since
+    pub since: Option<String>,
+}
+
+impl ApiItemsQuery {
+    /// Whether the list was asked for one page at a time
+    pub fn paged(&self) -> bool {
+        self.cursor.is_some() || self.limit.is_some()
+    }
+}
+
+/// Query parameters accepted by the item feed
+#[derive(Debug, Default, Deserialize, Schema)]
+pub struct FeedQuery {
+    pub token: Option<String>,
+}
+
+/// Optional narrowing of a user's item list
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ItemFilter<'a> {
+    /// Keyword matched against title and description
+    pub query: Option<&'a str>,
+    /// Normalized tag the item must carry
+    pub tag: Option<&'a str>,
+    /// Match the keyword word by word against the full-text index instead
+    /// of as a substring; only this can rank by `SortColumn::Relevance`
+    pub full_text: bool,
+    /// Match archived items instead of active ones
+    pub archived: bool,
+}
+
+impl ItemFilter<'_> {
+    /// Whether the filter matches every active item
+    pub fn is_empty(&self) -> bool {
+        self.query.is_none() && self.tag.is_none() && !self.archived
+    }
+}
+
+/// Column the item list can be sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortColumn {
+    /// The user's manual order; see `Database::reorder_items`
+    Position,
+    Title,
+    CreatedAt,
+    UpdatedAt,
+    /// Best full-text match first
+    Relevance,
+}
+
+impl SortColumn {
+    /// Query parameter value for this column
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SortColumn::Position => "position",
+            SortColumn::Title => "title",
+            SortColumn::CreatedAt => "created_at",
+            SortColumn::UpdatedAt => "updated_at",
+            SortColumn::Relevance => "relevance",
+        }
+    }
+}
+
+/// Validated item list ordering; defaults to the manual order, which is
+/// newest first until the user reorders their items
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ItemSort {
+    pub column: SortColumn,
+    pub descending: bool,
+}
+
+impl Default for ItemSort {
+    fn default() -> Self {
+        Self::manual()
+    }
+}
+
+impl ItemSort {
+    /// The user's manual order, with items never placed first
+    pub fn manual() -> Self {
+        Self {
+            column: SortColumn::Position,
+            descending: false,
+        }
+    }
+
+    /// Newest first
+    pub fn newest() -> Self {
+        Self {
+            column: SortColumn::CreatedAt,
+            descending: true,
+        }
+    }
+
+    /// Best full-text match first
+    pub fn relevance() -> Self {
+        Self {
+            column: SortColumn::Relevance,
+            descending: false,
+        }
+    }
+
+    /// Parse `sort`/`order` query params against the allowlist
+    ///
+    /// An unknown column falls back to the default ordering; a known column
+    /// without a valid order uses its natural direction (A-Z, newest first).
+    /// The manual order only goes one way.
+    pub fn from_params(sort: Option<&str>, order: Option<&str>) -> Self {
+        let column = match sort {
+            Some("position") => return Self::manual(),
+            Some("title") => SortColumn::Title,
+            Some("created_at") => SortColumn::CreatedAt,
+            Some("updated_at") => SortColumn::UpdatedAt,
+            Some("relevance") => return Self::relevance(),
+            _ => return Self::default(),
+        };
+
+        let descending = match order {
+            Some("asc") => false,
+            Some("desc") => true,
+            _ => column != SortColumn::Title,
+        };
+
+        Self { column, descending }
+    }
+
+    /// Query parameter value for the direction
+    pub fn order_str(&self) -> &'static str {
+        if self.descending {
+            "desc"
+        } else {
+            "asc"
+        }
+    }
+
+    /// `ORDER BY` clause for this ordering; always one of a fixed set of
+    /// strings, so it is safe to splice into SQL
+    ///
+    /// Relevance needs the full-text match to rank by, so on its own it
+    /// falls back to newest first.
+    pub fn order_by(&self) -> &'static str {
+        match (self.column, self.descending) {
+            (SortColumn::Position, _) => {
+                "position IS NOT NULL, position ASC, created_at DESC, id DESC"
+            }
+            (SortColumn::Relevance, _) => "created_at DESC, id DESC",
+            (SortColumn::Title, false) => "LOWER(title) ASC, id ASC",
+            (SortColumn::Title, true) => "LOWER(title) DESC, id DESC",
+            (SortColumn::CreatedAt, false) => "created_at ASC, id ASC",
+            (SortColumn::CreatedAt, true) => "created_at DESC, id DESC",
+            (SortColumn::UpdatedAt, false) => "updated_at ASC, id ASC",
+            (SortColumn::UpdatedAt, true) => "updated_at DESC, id DESC",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_title_stays_within_the_title_limit() {
+        let mut item = Item {
+            id: 1,
+            user_id: 1,
+            title: "Plan".to_string(),
+            description: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            deleted_at: None,
+            archived: false,
+            due_at: None,
+            tags: vec![],
+        };
+        assert_eq!(item.copy_title(DEFAULT_MAX_TITLE_LENGTH), "Copy of Plan");
+
+        item.title = "é".repeat(DEFAULT_MAX_TITLE_LENGTH);
+        let title = item.copy_title(DEFAULT_MAX_TITLE_LENGTH);
+        assert_eq!(title.chars().count(), DEFAULT_MAX_TITLE_LENGTH);
+        assert!(title.starts_with("Copy of é"));
+        assert_eq!(item.copy_title(10).chars().count(), 10);
+    }
+
+    #[test]
+    fn item_limits_apply_at_the_configured_lengths() {
+        let limits = ItemLimits {
+            title: 5,
+            description: 8,
+        };
+        let form = |title: &str, description: &str| ItemForm {
+            title: title.to_string(),
+            description: Some(description.to_string()),
+            tags: String::new(),
+            due_at: None,
+        };
+
+        // Characters count, not bytes, and surrounding whitespace is trimmed
+        assert!(form("ééééé", " 12345678 ").validate_with(&limits).is_ok());
+        let errors = form("123456", "123456789")
+            .validate_with(&limits)
+            .expect_err("over both limits");
+        let errors = crate::validation::field_errors(&errors);
+        assert_eq!(
+            errors.get("title"),
+            Some(&vec!["Title must be 5 characters or less".to_string()])
+        );
+        assert_eq!(
+            errors.get("description"),
+            Some(&vec!["Description must be 8 characters or less".to_string()])
+        );
+
+        // The compile-time rules still run alongside
+        let errors = form("", "123456789")
+            .validate_with(&limits)
+            .expect_err("invalid");
+        let errors = crate::validation::field_errors(&errors);
+        assert_eq!(
+            errors.get("title"),
+            Some(&vec!["Title is required".to_string()])
+        );
+        assert!(errors.contains_key("description"));
+
+        let defaults = ItemLimits::default();
+        assert!(form(&"x".repeat(200), "").validate_with(&defaults).is_ok());
+        assert!(form(&"x".repeat(201), "").validate_with(&defaults).is_err());
+    }
+
+    #[test]
+    fn due_date_must_be_a_calendar_date() {
+        let form = |due_at: Option<&str>| ItemForm {
+            title: "Taxes".to_string(),
+            description: None,
+            tags: String::new(),
+            due_at: due_at.map(str::to_string),
+        };
+        let limits = ItemLimits::default();
+
+        assert_eq!(form(None).due_date(), Ok(None));
+        assert_eq!(form(Some(" ")).due_date(), Ok(None));
+        assert_eq!(
+            form(Some("2024-02-29")).due_date(),
+            Ok(NaiveDate::from_ymd_opt(2024, 2, 29))
+        );
+        assert!(form(Some("2024-02-29")).validate_with(&limits).is_ok());
+
+        for bad in ["2023-02-29", "31/12/2024", "tomorrow"] {
+            let errors = form(Some(bad))
+                .validate_with(&limits)
+                .expect_err("invalid due date");
+            let errors = crate::validation::field_errors(&errors);
+            assert_eq!(
+                errors.get("due_at"),
+                Some(&vec!["Due date must be a date like 2024-12-31".to_string()])
+            );
+        }
+    }
+
+    #[test]
+    fn sort_defaults_to_the_manual_order() {
+        assert_eq!(ItemSort::from_params(None, None), ItemSort::default());
+        assert_eq!(ItemSort::default(), ItemSort::manual());
+        assert_eq!(
+            ItemSort::from_params(Some("position"), Some("desc")),
+            ItemSort::manual()
+        );
+        assert_eq!(ItemSort::newest().order_by(), "created_at DESC, id DESC");
+    }
+
+    #[test]
+    fn sort_accepts_allowlisted_columns() {
+        let title = ItemSort::from_params(Some("title"), None);
+        assert_eq!(title.column, SortColumn::Title);
+        assert!(!title.descending);
+
+        let updated = ItemSort::from_params(Some("updated_at"), Some("asc"));
+        assert_eq!(updated.order_by(), "updated_at ASC, id ASC");
+
+        let created = ItemSort::from_params(Some("created_at"), Some("sideways"));
+        assert!(created.descending);
+    }
+
+    #[test]
+    fn tags_are_normalized_and_deduplicated() {
+        assert_eq!(
+            normalize_tags(" Rust, web ,rust,,  Web  Dev ,"),
+            ["rust", "web", "web dev"]
+        );
+        assert!(normalize_tags(" , ").is_empty());
+        assert_eq!(normalize_tag("  "), None);
+    }
+
+    #[test]
+    fn bulk_form_collects_repeated_ids() {
+        let form: BulkItemsForm =
+            serde_urlencoded::from_str("_csrf=t&ids=3&ids=7&action=delete").expect("parse");
+        assert_eq!(form.ids, [3, 7]);
+        assert_eq!(BulkAction::parse(&form.action), Some(BulkAction::Delete));
+
+        assert!(serde_urlencoded::from_str::<BulkItemsForm>("ids=abc").is_err());
+        assert_eq!(BulkAction::parse("archive"), Some(BulkAction::Archive));
+        assert_eq!(BulkAction::parse("explode"), None);
+    }
+
+    #[test]
+    fn reorder_form_keeps_the_submitted_order() {
+        let form: ReorderItemsForm =
+            serde_urlencoded::from_str("_csrf=t&ids=7&ids=3&ids=5").expect("parse");
+        assert_eq!(form.ids, [7, 3, 5]);
+        assert!(serde_urlencoded::from_str::<ReorderItemsForm>("ids=first").is_err());
+    }
+
+    #[test]
+    fn import_item_ignores_ownership_fields() {
+        let record: ImportItem = serde_json::from_value(serde_json::json!({
+            "id": 7,
+            "user_id": 99,
+            "title": "Imported",
+            "tags": ["Rust", "web"],
+        }))
+        .expect("parse record");
+        let form = record.into_form();
+        assert_eq!(form.title, "Imported");
+        assert_eq!(form.description, None);
+        assert_eq!(form.tag_list(), ["rust", "web"]);
+    }
+
+    #[test]
+    fn sort_rejects_unknown_column() {
+        let sort = ItemSort::from_params(Some("password_hash; DROP TABLE items"), Some("asc"));
+        assert_eq!(sort, ItemSort::default());
+    }
 }
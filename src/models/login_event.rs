@@ -0,0 +1,24 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+
+/// Days login events are kept unless `LOGIN_HISTORY_RETENTION_DAYS`
+/// overrides it
+pub const DEFAULT_LOGIN_HISTORY_RETENTION_DAYS: i64 = 90;
+
+/// Logins the profile page lists
+pub const RECENT_LOGINS_SHOWN: i64 = 10;
+
+/// Longest `User-Agent` kept with a login, in characters
+pub const MAX_USER_AGENT_LENGTH: usize = 512;
+
+/// A successful login, shown to the account's owner on their profile page
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct LoginEvent {
+    pub id: i64,
+    pub user_id: i64,
+    pub ip_address: String,
+    /// `User-Agent` of the browser or client, when it sent one
+    pub user_agent: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
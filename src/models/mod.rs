@@ -1,5 +1,23 @@
+pub mod attachment;
+pub mod audit;
+pub mod comment;
+pub mod invitation;
+pub mod invite;
 pub mod item;
+pub mod login_event;
+pub mod pagination;
+pub mod token;
 pub mod user;
+pub mod webauthn;
 
+pub use attachment::*;
+pub use audit::*;
+pub use comment::*;
+pub use invitation::*;
+pub use invite::*;
 pub use item::*;
+pub use login_event::*;
+pub use pagination::*;
+pub use token::*;
 pub use user::*;
+pub use webauthn::*;
@@ -0,0 +1,149 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+
+use super::Item;
+
+/// Page size used when the request doesn't specify one
+pub const DEFAULT_PER_PAGE: i64 = 20;
+
+/// Upper bound on the requested page size
+pub const MAX_PER_PAGE: i64 = 100;
+
+/// Resolved page window for a paginated listing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Pagination {
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+    pub total_pages: i64,
+}
+
+impl Pagination {
+    /// Resolve the requested page against the total row count.
+    ///
+    /// `per_page` is clamped to `1..=MAX_PER_PAGE` and `page` to the valid
+    /// range, so an out-of-range page lands on the last page.
+    pub fn new(page: Option<i64>, per_page: Option<i64>, total: i64) -> Self {
+        let per_page = per_page.unwrap_or(DEFAULT_PER_PAGE).clamp(1, MAX_PER_PAGE);
+        let total = total.max(0);
+        let total_pages = ((total + per_page - 1) / per_page).max(1);
+        let page = page.unwrap_or(1).clamp(1, total_pages);
+
+        Self {
+            page,
+            per_page,
+            total,
+            total_pages,
+        }
+    }
+
+    /// Number of rows to skip for the current page
+    pub fn offset(&self) -> i64 {
+        (self.page - 1) * self.per_page
+    }
+
+    /// Number of rows to fetch for the current page
+    pub fn limit(&self) -> i64 {
+        self.per_page
+    }
+}
+
+/// Position in a listing ordered newest first, just after the item it was
+/// taken from
+///
+/// Clients get it as an opaque `next_cursor` token and pass it back as
+/// `?cursor=`. Unlike an offset it stays put when items are added before it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemCursor {
+    pub created_at: DateTime<Utc>,
+    pub id: i64,
+}
+
+impl ItemCursor {
+    /// The cursor continuing after `item`
+    pub fn after(item: &Item) -> Self {
+        Self {
+            created_at: item.created_at,
+            id: item.id,
+        }
+    }
+
+    /// The token handed to clients
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!(
+            "{}|{}",
+            self.created_at.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+            self.id
+        ))
+    }
+
+    /// Read back a token from `encode`; `None` if it is not one
+    pub fn decode(token: &str) -> Option<Self> {
+        let raw = String::from_utf8(URL_SAFE_NO_PAD.decode(token.trim()).ok()?).ok()?;
+        let (created_at, id) = raw.split_once('|')?;
+        Some(Self {
+            created_at: DateTime::parse_from_rfc3339(created_at)
+                .ok()?
+                .with_timezone(&Utc),
+            id: id.parse().ok()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_to_first_page() {
+        let pagination = Pagination::new(None, None, 45);
+        assert_eq!(pagination.page, 1);
+        assert_eq!(pagination.per_page, DEFAULT_PER_PAGE);
+        assert_eq!(pagination.total_pages, 3);
+        assert_eq!(pagination.offset(), 0);
+    }
+
+    #[test]
+    fn computes_offset_for_later_pages() {
+        let pagination = Pagination::new(Some(3), Some(10), 45);
+        assert_eq!(pagination.offset(), 20);
+        assert_eq!(pagination.limit(), 10);
+        assert_eq!(pagination.total_pages, 5);
+    }
+
+    #[test]
+    fn clamps_out_of_range_pages() {
+        assert_eq!(Pagination::new(Some(99), Some(10), 45).page, 5);
+        assert_eq!(Pagination::new(Some(0), Some(10), 45).page, 1);
+        assert_eq!(Pagination::new(Some(-4), Some(10), 45).page, 1);
+    }
+
+    #[test]
+    fn clamps_per_page() {
+        assert_eq!(Pagination::new(None, Some(1000), 0).per_page, MAX_PER_PAGE);
+        assert_eq!(Pagination::new(None, Some(0), 0).per_page, 1);
+    }
+
+    #[test]
+    fn empty_listing_has_one_page() {
+        let pagination = Pagination::new(Some(2), None, 0);
+        assert_eq!(pagination.total_pages, 1);
+        assert_eq!(pagination.page, 1);
+        assert_eq!(pagination.offset(), 0);
+    }
+
+    #[test]
+    fn cursors_round_trip_and_reject_garbage() {
+        let cursor = ItemCursor {
+            created_at: DateTime::parse_from_rfc3339("2024-03-01T12:30:45.123456Z")
+                .expect("timestamp")
+                .with_timezone(&Utc),
+            id: 42,
+        };
+        assert_eq!(ItemCursor::decode(&cursor.encode()), Some(cursor));
+        for token in ["", "not base64!", "bm90IGEgY3Vyc29y", "MjAyNHwxMg"] {
+            assert_eq!(ItemCursor::decode(token), None, "{}", token);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use chrono::{DateTime, Utc};
+use rustapi_rs::prelude::*;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Longest API key name accepted, in characters
+pub const MAX_API_KEY_NAME_LENGTH: usize = 100;
+
+/// Refresh token row; the token itself is only stored as a hash
+#[derive(Debug, Clone, FromRow)]
+pub struct RefreshToken {
+    pub id: i64,
+    pub user_id: i64,
+    pub token_hash: String,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    /// Issued by a "remember me" login; carried over when it is rotated
+    pub remember: bool,
+    /// When the login it belongs to ends, however often it is refreshed;
+    /// `None` for tokens issued before the limit existed
+    pub session_expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Access token handed to API clients by `POST /api/login`
+#[derive(Debug, Serialize)]
+pub struct ApiToken {
+    /// JWT to send as `Authorization: Bearer <token>`
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// API key row; like refresh tokens, the key itself is only stored as a hash
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct ApiKey {
+    pub id: i64,
+    pub user_id: i64,
+    #[serde(skip_serializing)]
+    pub key_hash: String,
+    /// Label chosen by the owner, e.g. the service that uses the key
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// Form data for creating an API key
+#[derive(Debug, Deserialize, Schema)]
+pub struct ApiKeyForm {
+    pub name: String,
+}
+
+impl RefreshToken {
+    /// Whether the token can still be exchanged for a new access token
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at > Utc::now()
+    }
+}
@@ -1,3 +1,6 @@
+use chrono::{DateTime, Utc};
+use rustapi_macros::Validate;
+use rustapi_rs::prelude::Schema;
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
@@ -9,7 +12,42 @@ pub struct User {
     pub email: String,
     #[serde(skip_serializing)]
     pub password_hash: String,
-    pub created_at: String,
+    #[serde(skip_serializing)]
+    pub failed_attempts: i32,
+    #[serde(skip_serializing)]
+    pub locked_until: Option<DateTime<Utc>>,
+    #[serde(skip_serializing)]
+    pub token_version: i32,
+    pub verified: bool,
+    pub role: String,
+    /// IANA zone the user's pages show times in, e.g. `Europe/Berlin`
+    pub timezone: String,
+    pub created_at: DateTime<Utc>,
+    /// `None` until the first login
+    pub last_login_at: Option<DateTime<Utc>>,
+}
+
+/// Access level of a user, stored as text in `users.role`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    User,
+    Admin,
+}
+
+impl Role {
+    /// Parse a stored role; unknown values get the least privilege
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "admin" => Role::Admin,
+            _ => Role::User,
+        }
+    }
+
+    /// Whether this role grants at least the access of `required`
+    pub fn allows(self, required: Role) -> bool {
+        self >= required
+    }
 }
 
 /// User data for templates (without sensitive fields)
@@ -18,32 +56,125 @@ pub struct UserInfo {
     pub id: i64,
     pub username: String,
     pub email: String,
+    pub role: Role,
+    pub timezone: String,
 }
 
 impl From<User> for UserInfo {
     fn from(user: User) -> Self {
         Self {
+            role: Role::parse(&user.role),
             id: user.id,
             username: user.username,
             email: user.email,
+            timezone: user.timezone,
         }
     }
 }
 
+/// Canonical form of an email address, as stored: trimmed and lowercased
+///
+/// Usernames keep the case they were typed in; both are compared without
+/// case.
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
 /// Form data for user registration
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Validate, Schema)]
 pub struct RegisterForm {
     pub username: String,
+    #[validate(email(message = "Enter a valid email address"))]
     pub email: String,
     pub password: String,
     pub confirm_password: String,
+    /// Required while registration is invite-only
+    #[serde(default)]
+    pub invite_code: String,
 }
 
 /// Form data for user login
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Schema)]
 pub struct LoginForm {
     pub username: String,
     pub password: String,
+    /// "Remember me" checkbox; keeps the login across browser restarts
+    #[serde(default)]
+    pub remember: bool,
+}
+
+/// JSON body of `POST /api/login`
+#[derive(Debug, Deserialize, Schema)]
+pub struct ApiLoginForm {
+    pub username: String,
+    pub password: String,
+}
+
+/// Form data for updating the current user's profile
+#[derive(Debug, Deserialize, Schema)]
+pub struct ProfileForm {
+    pub username: String,
+    pub email: String,
+    /// IANA zone name; the current one is kept when the field is missing
+    #[serde(default)]
+    pub timezone: Option<String>,
+}
+
+/// Form data for deleting the current account; the password is confirmed
+/// through sudo mode beforehand
+#[derive(Debug, Deserialize, Schema)]
+pub struct DeleteAccountForm {
+    /// Must repeat the username, to guard against accidental deletion
+    pub confirm: String,
+}
+
+/// Form data for confirming the password before a sensitive action
+#[derive(Debug, Deserialize, Schema)]
+pub struct SudoForm {
+    pub password: String,
+    /// Path to return to afterwards
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+/// Query parameters accepted by the password confirmation page
+#[derive(Debug, Default, Deserialize, Schema)]
+pub struct SudoQuery {
+    pub next: Option<String>,
+}
+
+/// Query parameters accepted by the profile page
+#[derive(Debug, Default, Deserialize, Schema)]
+pub struct ProfilePageQuery {
+    pub updated: Option<bool>,
+}
+
+/// Query parameters accepted by the login page
+#[derive(Debug, Default, Deserialize, Schema)]
+pub struct LoginPageQuery {
+    pub registered: Option<bool>,
+    pub verified: Option<bool>,
+    pub resent: Option<bool>,
+    pub verify_failed: Option<bool>,
+}
+
+/// Query parameters of an email verification link
+#[derive(Debug, Deserialize, Schema)]
+pub struct VerifyQuery {
+    pub token: String,
+}
+
+/// Form data for requesting a new verification link
+#[derive(Debug, Deserialize, Schema)]
+pub struct ResendVerificationForm {
+    pub username: String,
+}
+
+/// Form data for `/admin/maintenance`
+#[derive(Debug, Deserialize, Schema)]
+pub struct MaintenanceForm {
+    /// Whether the app goes down for maintenance (`true`) or back up
+    pub enabled: bool,
 }
 
 /// JWT claims structure
@@ -51,6 +182,12 @@ pub struct LoginForm {
 pub struct Claims {
     pub sub: i64, // user id
     pub username: String,
-    pub exp: i64, // expiration timestamp
-    pub iat: i64, // issued at timestamp
+    pub exp: i64,           // expiration timestamp
+    pub iat: i64,           // issued at timestamp
+    pub token_version: i32, // must match users.token_version
+    pub role: Role,
+    /// End of the login, after which sliding renewal stops; 0 on tokens
+    /// issued before it existed
+    #[serde(default)]
+    pub session_exp: i64,
 }
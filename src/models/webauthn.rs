@@ -0,0 +1,61 @@
+use chrono::{DateTime, Utc};
+use rustapi_rs::prelude::*;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// Longest passkey name accepted, in characters
+pub const MAX_PASSKEY_NAME_LENGTH: usize = 100;
+
+/// Passkey a user can log in with; credential ID and public key are
+/// base64url-encoded
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Passkey {
+    pub id: i64,
+    pub user_id: i64,
+    #[serde(skip_serializing)]
+    pub credential_id: String,
+    #[serde(skip_serializing)]
+    pub public_key: String,
+    /// Highest signature counter the authenticator has reported; 0 for
+    /// authenticators that keep no counter
+    #[serde(skip_serializing)]
+    pub sign_count: i64,
+    /// Label chosen by the owner, e.g. the device it lives on
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+/// JSON body of `POST /webauthn/register/finish`, taken from the
+/// `PublicKeyCredential` the browser created; binary values are base64url
+#[derive(Debug, Clone, Deserialize, Schema)]
+pub struct PasskeyRegistration {
+    pub credential_id: String,
+    /// `response.clientDataJSON`
+    pub client_data: String,
+    /// `response.getAuthenticatorData()`
+    pub authenticator_data: String,
+    /// `response.getPublicKey()`, a DER SubjectPublicKeyInfo
+    pub public_key: String,
+    /// `response.getPublicKeyAlgorithm()`, a COSE algorithm identifier
+    pub public_key_algorithm: i64,
+    /// Label for the passkey; "Passkey" when left empty
+    #[serde(default)]
+    pub name: String,
+}
+
+/// JSON body of `POST /webauthn/login/finish`, taken from the
+/// `PublicKeyCredential` the browser got; binary values are base64url
+#[derive(Debug, Clone, Deserialize, Schema)]
+pub struct PasskeyAssertion {
+    pub credential_id: String,
+    /// `response.clientDataJSON`
+    pub client_data: String,
+    /// `response.authenticatorData`
+    pub authenticator_data: String,
+    /// `response.signature`
+    pub signature: String,
+    /// Keeps the login across browser restarts, like "remember me"
+    #[serde(default)]
+    pub remember: bool,
+}
@@ -0,0 +1,131 @@
+use rustapi_rs::prelude::*;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::errors::ApiErrorResponse;
+
+/// JSON a handler attaches to an HTML response, sent in its place to clients
+/// that prefer JSON
+#[derive(Clone)]
+struct JsonAlternative {
+    status: StatusCode,
+    body: Value,
+}
+
+/// Offer `body` with `status` as the JSON form of `response`, a page or a
+/// redirect
+pub fn with_json<T: Serialize>(mut response: Response, status: StatusCode, body: &T) -> Response {
+    let body = serde_json::to_value(body).unwrap_or(Value::Null);
+    response
+        .extensions_mut()
+        .insert(JsonAlternative { status, body });
+    response
+}
+
+/// Offer the `ApiErrorResponse` for `code` with `status` as the JSON form of
+/// `response`
+pub fn with_json_error(response: Response, status: StatusCode, code: &str) -> Response {
+    with_json(
+        response,
+        status,
+        &ApiErrorResponse::new(status, code).body(),
+    )
+}
+
+/// Whether an `Accept` header ranks `application/json` above `text/html`
+///
+/// Ties go to HTML, so `*/*` and a missing header keep the pages.
+pub fn prefers_json(accept: &str) -> bool {
+    quality(accept, "application/json") > quality(accept, "text/html")
+}
+
+// The `q` of the most specific range in `accept` matching `media_type`, or
+// 0 when none does
+fn quality(accept: &str, media_type: &str) -> f32 {
+    let kind = media_type.split('/').next().unwrap_or_default();
+    accept
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';').map(str::trim);
+            let range = parts.next()?.to_ascii_lowercase();
+            let specificity = if range == media_type {
+                2
+            } else if range.strip_suffix("/*") == Some(kind) {
+                1
+            } else if range == "*/*" {
+                0
+            } else {
+                return None;
+            };
+            let q = parts
+                .find_map(|param| param.strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((specificity, q))
+        })
+        .max_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)))
+        .map_or(0.0, |(_, q)| q)
+}
+
+/// The response for a request with `accept`: the JSON `response` offers when
+/// the client prefers it, otherwise `response` itself
+///
+/// The JSON replaces the page or redirect entirely, flash cookie included;
+/// both forms say `Vary: Accept` so caches keep them apart.
+pub fn negotiate(accept: Option<&str>, mut response: Response) -> Response {
+    let Some(alternative) = response.extensions().get::<JsonAlternative>().cloned() else {
+        return response;
+    };
+    if accept.is_some_and(prefers_json) {
+        response = (alternative.status, Json(alternative.body)).into_response();
+    }
+    if let Ok(value) = "Accept".parse() {
+        response.headers_mut().append("Vary", value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{body_string, header_value};
+    use serde_json::json;
+
+    #[test]
+    fn prefers_json_weighs_quality_and_specificity() {
+        assert!(prefers_json("application/json"));
+        assert!(prefers_json("text/html;q=0.5, application/json"));
+        assert!(prefers_json("application/*, text/*;q=0.9"));
+        assert!(!prefers_json("text/html,application/xhtml+xml,*/*;q=0.8"));
+        assert!(!prefers_json("*/*"));
+        assert!(!prefers_json("application/json;q=0.9, text/html"));
+        // The exact type wins over a wildcard, whatever their order
+        assert!(!prefers_json("application/json;q=0, */*"));
+    }
+
+    #[tokio::test]
+    async fn negotiate_swaps_in_the_offered_json() {
+        let page = || {
+            with_json(
+                Html("<p>page</p>").into_response(),
+                StatusCode::CREATED,
+                &json!({ "id": 1 }),
+            )
+        };
+
+        let response = negotiate(Some("application/json"), page());
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(header_value(&response, "Vary").as_deref(), Some("Accept"));
+        assert_eq!(body_string(response).await, r#"{"id":1}"#);
+
+        let response = negotiate(Some("text/html"), page());
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header_value(&response, "Vary").as_deref(), Some("Accept"));
+        assert_eq!(body_string(response).await, "<p>page</p>");
+
+        // Responses without JSON to offer are left alone
+        let response = negotiate(Some("application/json"), Html("plain").into_response());
+        assert_eq!(header_value(&response, "Vary"), None);
+        assert_eq!(body_string(response).await, "plain");
+    }
+}
@@ -0,0 +1,561 @@
+use rustapi_core::ResponseBody;
+use rustapi_openapi::{
+    schema::{JsonSchema2020, RustApiSchema, SchemaCtx, TypeArray},
+    MediaType, OpenApiSpec, Operation, ResponseSpec, SchemaRef,
+};
+use rustapi_rs::prelude::*;
+use serde_json::json;
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use crate::{
+    models::{
+        ApiKeyForm, ApiLoginForm, ApiToken, BulkItemsForm, Claims, CommentForm, DeleteAccountForm,
+        InvitationForm, Item, ItemForm, LoginForm, MaintenanceForm, PasskeyAssertion,
+        PasskeyRegistration, ProfileForm, ReassignItemForm, RegisterForm, ReorderItemsForm,
+        ResendVerificationForm, Role, SudoForm,
+    },
+    tokens::{ACCESS_COOKIE, API_KEY_HEADER},
+};
+
+/// Where the generated OpenAPI document is served
+pub const SPEC_PATH: &str = "/openapi.json";
+
+/// Where the Swagger UI page is served
+pub const DOCS_PATH: &str = "/docs";
+
+/// Name of the security scheme for the JWT access cookie
+pub const COOKIE_AUTH: &str = "cookieAuth";
+
+/// Name of the security scheme for the same JWT sent as a bearer token
+pub const BEARER_AUTH: &str = "bearerAuth";
+
+/// Name of the security scheme for API keys
+pub const API_KEY_AUTH: &str = "apiKeyAuth";
+
+/// Tag grouping the JSON item endpoints in the docs
+const ITEMS_API_TAG: &str = "Items API";
+
+/// Tag of the JSON login endpoint
+const AUTH_API_TAG: &str = "Auth API";
+
+/// What a JSON item endpoint responds with besides its status
+#[derive(Clone, Copy)]
+enum Body {
+    Item,
+    Items,
+    Token,
+    Error,
+    Empty,
+}
+
+/// Documented responses of the JSON item endpoints: path, method, status,
+/// description and body
+const ITEM_RESPONSES: &[(&str, &str, &str, &str, Body)] = &[
+    ("/api/items", "GET", "200", "The user's items", Body::Items),
+    ("/api/items", "POST", "201", "Created item", Body::Item),
+    ("/api/items", "POST", "422", "Invalid item", Body::Error),
+    ("/api/items/{id}", "GET", "200", "The item", Body::Item),
+    (
+        "/api/items/{id}",
+        "GET",
+        "304",
+        "Unchanged since the ETag in If-None-Match",
+        Body::Empty,
+    ),
+    ("/api/items/{id}", "GET", "404", "No such item", Body::Error),
+    ("/api/items/{id}", "PUT", "200", "Updated item", Body::Item),
+    ("/api/items/{id}", "PUT", "404", "No such item", Body::Error),
+    ("/api/items/{id}", "PUT", "422", "Invalid item", Body::Error),
+    (
+        "/api/items/{id}",
+        "DELETE",
+        "204",
+        "Item deleted",
+        Body::Empty,
+    ),
+    (
+        "/api/items/{id}",
+        "DELETE",
+        "404",
+        "No such item",
+        Body::Error,
+    ),
+];
+
+/// Documented responses of `POST /api/login`: status, description and body
+const LOGIN_RESPONSES: &[(&str, &str, Body)] = &[
+    ("200", "Bearer access token", Body::Token),
+    ("401", "Unknown username or wrong password", Body::Error),
+    ("403", "Account locked or email not verified", Body::Error),
+    ("429", "Too many login attempts", Body::Error),
+];
+
+/// Serve the spec of every route mounted so far at `/openapi.json`, and
+/// Swagger UI for it at `/docs`
+pub fn mount(app: RustApi) -> RustApi {
+    let json = Arc::new(spec_json(&document(app.openapi_spec().clone())));
+
+    app.route(
+        SPEC_PATH,
+        get(move || {
+            let json = json.clone();
+            openapi_json(json)
+        }),
+    )
+    .route(DOCS_PATH, get(swagger_ui))
+}
+
+/// Complete the spec RustAPI collected from the route macros
+///
+/// Registers the schemas our own extractors refer to, the access token
+/// security schemes, and the responses of the JSON item endpoints, which
+/// return a plain `Response` the macros cannot look into.
+pub fn document(mut spec: OpenApiSpec) -> OpenApiSpec {
+    spec.info.title = "Basic CRUD Ops".to_string();
+    spec.info.version = env!("CARGO_PKG_VERSION").to_string();
+    spec.info.description = Some(env!("CARGO_PKG_DESCRIPTION").to_string());
+
+    spec.register_in_place::<Item>();
+    spec.register_in_place::<Claims>();
+    spec.register_in_place::<ItemForm>();
+    spec.register_in_place::<BulkItemsForm>();
+    spec.register_in_place::<ReorderItemsForm>();
+    spec.register_in_place::<ReassignItemForm>();
+    spec.register_in_place::<MaintenanceForm>();
+    spec.register_in_place::<InvitationForm>();
+    spec.register_in_place::<CommentForm>();
+    spec.register_in_place::<LoginForm>();
+    spec.register_in_place::<RegisterForm>();
+    spec.register_in_place::<ProfileForm>();
+    spec.register_in_place::<DeleteAccountForm>();
+    spec.register_in_place::<SudoForm>();
+    spec.register_in_place::<ApiKeyForm>();
+    spec.register_in_place::<ResendVerificationForm>();
+    spec.register_in_place::<ApiLoginForm>();
+    spec.register_in_place::<ApiToken>();
+    spec.register_in_place::<PasskeyRegistration>();
+    spec.register_in_place::<PasskeyAssertion>();
+
+    let components = spec.components.get_or_insert_with(Default::default);
+    components.schemas.insert(
+        "JsonError".to_string(),
+        object(
+            vec![(
+                "error",
+                object(
+                    vec![
+                        ("code", JsonSchema2020::string()),
+                        ("message", JsonSchema2020::string()),
+                        ("details", JsonSchema2020::object()),
+                    ],
+                    &["details"],
+                ),
+            )],
+            &[],
+        ),
+    );
+    // `SecurityScheme` is not re-exported by rustapi-openapi, so these are
+    // built from their JSON form
+    let cookie = serde_json::from_value(json!({
+        "type": "apiKey",
+        "in": "cookie",
+        "name": ACCESS_COOKIE,
+        "description": "JWT access token set by `POST /login`",
+    }))
+    .expect("valid security scheme");
+    let bearer = serde_json::from_value(json!({
+        "type": "http",
+        "scheme": "bearer",
+        "bearerFormat": "JWT",
+        "description": "Access token from `POST /api/login`; wins over the cookie when both are sent",
+    }))
+    .expect("valid security scheme");
+    components
+        .security_schemes
+        .insert(COOKIE_AUTH.to_string(), cookie);
+    let api_key = serde_json::from_value(json!({
+        "type": "apiKey",
+        "in": "header",
+        "name": API_KEY_HEADER,
+        "description": "API key created on the profile page; when sent, the JWT is ignored",
+    }))
+    .expect("valid security scheme");
+    components
+        .security_schemes
+        .insert(BEARER_AUTH.to_string(), bearer);
+    components
+        .security_schemes
+        .insert(API_KEY_AUTH.to_string(), api_key);
+
+    let mut documented = BTreeSet::new();
+    for &(path, method, status, description, body) in ITEM_RESPONSES {
+        let Some(op) = operation_mut(&mut spec, path, method) else {
+            continue;
+        };
+        // Replace the generic `200` the macros add, once per operation
+        if documented.insert((path, method)) {
+            op.responses.clear();
+            op.tags = vec![ITEMS_API_TAG.to_string()];
+            require_api_auth(op);
+            op.responses
+                .insert("401".to_string(), response("Not logged in", Body::Error));
+        }
+        op.responses
+            .insert(status.to_string(), response(description, body));
+    }
+
+    if let Some(op) = operation_mut(&mut spec, "/api/login", "POST") {
+        op.responses.clear();
+        op.tags = vec![AUTH_API_TAG.to_string()];
+        for &(status, description, body) in LOGIN_RESPONSES {
+            op.responses
+                .insert(status.to_string(), response(description, body));
+        }
+    }
+
+    spec
+}
+
+/// Mark an operation as needing the JWT access token, as a cookie or a
+/// bearer header
+pub fn require_auth(op: &mut Operation) {
+    for scheme in [COOKIE_AUTH, BEARER_AUTH] {
+        let requirement = BTreeMap::from([(scheme.to_string(), Vec::new())]);
+        if !op.security.contains(&requirement) {
+            op.security.push(requirement);
+        }
+    }
+}
+
+/// Mark a JSON API operation as accepting an API key besides the JWT
+pub fn require_api_auth(op: &mut Operation) {
+    require_auth(op);
+    let requirement = BTreeMap::from([(API_KEY_AUTH.to_string(), Vec::new())]);
+    if !op.security.contains(&requirement) {
+        op.security.push(requirement);
+    }
+}
+
+/// The spec as pretty-printed JSON
+pub fn spec_json(spec: &OpenApiSpec) -> String {
+    serde_json::to_string_pretty(&spec.to_json()).unwrap_or_else(|e| {
+        tracing::error!(error = %e, "failed to serialize OpenAPI spec");
+        "{}".to_string()
+    })
+}
+
+async fn openapi_json(json: Arc<String>) -> Response {
+    let mut response = Response::new(ResponseBody::from(json.as_str().to_string()));
+    if let Ok(value) = "application/json".parse() {
+        response.headers_mut().insert("Content-Type", value);
+    }
+    response
+}
+
+pub(crate) async fn swagger_ui() -> Response {
+    rustapi_openapi::swagger_ui_html(SPEC_PATH).map(ResponseBody::Full)
+}
+
+fn operation_mut<'a>(
+    spec: &'a mut OpenApiSpec,
+    path: &str,
+    method: &str,
+) -> Option<&'a mut Operation> {
+    let item = spec.paths.get_mut(path)?;
+    match method {
+        "GET" => item.get.as_mut(),
+        "POST" => item.post.as_mut(),
+        "PUT" => item.put.as_mut(),
+        "DELETE" => item.delete.as_mut(),
+        _ => None,
+    }
+}
+
+fn response(description: &str, body: Body) -> ResponseSpec {
+    let schema = match body {
+        Body::Item => reference("Item"),
+        Body::Items => JsonSchema2020::array(reference("Item")),
+        Body::Token => reference("ApiToken"),
+        Body::Error => reference("JsonError"),
+        Body::Empty => {
+            return ResponseSpec {
+                description: description.to_string(),
+                ..Default::default()
+            }
+        }
+    };
+
+    ResponseSpec {
+        description: description.to_string(),
+        content: BTreeMap::from([(
+            "application/json".to_string(),
+            MediaType {
+                schema: Some(SchemaRef::Schema(Box::new(schema))),
+                example: None,
+            },
+        )]),
+        ..Default::default()
+    }
+}
+
+// `Item`, `ApiToken` and `Claims` hold `chrono` and enum fields the `Schema`
+// derive cannot describe, so their schemas are written out here
+
+impl RustApiSchema for Item {
+    fn schema(ctx: &mut SchemaCtx) -> SchemaRef {
+        component(ctx, "Item", |_| {
+            object(
+                vec![
+                    ("id", int64()),
+                    ("user_id", int64()),
+                    ("title", JsonSchema2020::string()),
+                    ("description", nullable(JsonSchema2020::string())),
+                    ("created_at", date_time()),
+                    ("updated_at", date_time()),
+                    ("deleted_at", nullable(date_time())),
+                    ("archived", JsonSchema2020::boolean()),
+                    ("tags", JsonSchema2020::array(JsonSchema2020::string())),
+                ],
+                &["description", "deleted_at"],
+            )
+        })
+    }
+
+    fn component_name() -> Option<&'static str> {
+        Some("Item")
+    }
+
+    fn name() -> Cow<'static, str> {
+        Cow::Borrowed("Item")
+    }
+}
+
+impl RustApiSchema for ApiToken {
+    fn schema(ctx: &mut SchemaCtx) -> SchemaRef {
+        component(ctx, "ApiToken", |_| {
+            object(
+                vec![
+                    ("token", JsonSchema2020::string()),
+                    ("expires_at", date_time()),
+                ],
+                &[],
+            )
+        })
+    }
+
+    fn component_name() -> Option<&'static str> {
+        Some("ApiToken")
+    }
+
+    fn name() -> Cow<'static, str> {
+        Cow::Borrowed("ApiToken")
+    }
+}
+
+impl RustApiSchema for Claims {
+    fn schema(ctx: &mut SchemaCtx) -> SchemaRef {
+        component(ctx, "Claims", |ctx| {
+            Role::schema(ctx);
+            let mut token_version = JsonSchema2020::integer();
+            token_version.format = Some("int32".to_string());
+            object(
+                vec![
+                    ("sub", int64()),
+                    ("username", JsonSchema2020::string()),
+                    ("exp", int64()),
+                    ("iat", int64()),
+                    ("token_version", token_version),
+                    ("role", reference("Role")),
+                    ("session_exp", int64()),
+                ],
+                &[],
+            )
+        })
+    }
+
+    fn component_name() -> Option<&'static str> {
+        Some("Claims")
+    }
+
+    fn name() -> Cow<'static, str> {
+        Cow::Borrowed("Claims")
+    }
+}
+
+impl RustApiSchema for Role {
+    fn schema(ctx: &mut SchemaCtx) -> SchemaRef {
+        component(ctx, "Role", |_| {
+            let mut schema = JsonSchema2020::string();
+            schema.enum_values = Some(vec![json!("user"), json!("admin")]);
+            schema
+        })
+    }
+
+    fn component_name() -> Option<&'static str> {
+        Some("Role")
+    }
+
+    fn name() -> Cow<'static, str> {
+        Cow::Borrowed("Role")
+    }
+}
+
+// Helper function to register a named schema once and refer to it
+fn component(
+    ctx: &mut SchemaCtx,
+    name: &str,
+    build: impl FnOnce(&mut SchemaCtx) -> JsonSchema2020,
+) -> SchemaRef {
+    if !ctx.components.contains_key(name) {
+        let schema = build(ctx);
+        ctx.components.insert(name.to_string(), schema);
+    }
+    SchemaRef::Ref {
+        reference: format!("#/components/schemas/{}", name),
+    }
+}
+
+fn object(properties: Vec<(&str, JsonSchema2020)>, optional: &[&str]) -> JsonSchema2020 {
+    let required = properties
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .filter(|name| !optional.contains(&name.as_str()))
+        .collect::<Vec<_>>();
+
+    let mut schema = JsonSchema2020::object();
+    schema.properties = Some(
+        properties
+            .into_iter()
+            .map(|(name, schema)| (name.to_string(), schema))
+            .collect(),
+    );
+    if !required.is_empty() {
+        schema.required = Some(required);
+    }
+    schema
+}
+
+fn reference(name: &str) -> JsonSchema2020 {
+    let mut schema = JsonSchema2020::new();
+    schema.reference = Some(format!("#/components/schemas/{}", name));
+    schema
+}
+
+fn int64() -> JsonSchema2020 {
+    let mut schema = JsonSchema2020::integer();
+    schema.format = Some("int64".to_string());
+    schema
+}
+
+fn date_time() -> JsonSchema2020 {
+    let mut schema = JsonSchema2020::string();
+    schema.format = Some("date-time".to_string());
+    schema
+}
+
+fn nullable(mut schema: JsonSchema2020) -> JsonSchema2020 {
+    if let Some(TypeArray::Single(ty)) = schema.schema_type.take() {
+        schema.schema_type = Some(TypeArray::nullable(ty));
+    }
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{body_string, header_value};
+    use serde_json::Value;
+
+    fn app_spec() -> OpenApiSpec {
+        document(
+            RustApi::config()
+                .docs_enabled(false)
+                .build()
+                .openapi_spec()
+                .clone(),
+        )
+    }
+
+    #[tokio::test]
+    async fn openapi_json_documents_item_endpoints() {
+        let spec = app_spec();
+        assert_eq!(spec.validate_integrity(), Ok(()));
+
+        let response = openapi_json(Arc::new(spec_json(&spec))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            header_value(&response, "Content-Type"),
+            Some("application/json".to_string())
+        );
+        let spec: Value = serde_json::from_str(&body_string(response).await).expect("valid JSON");
+
+        let items = &spec["paths"]["/api/items"];
+        assert_eq!(
+            items["get"]["responses"]["200"]["content"]["application/json"]["schema"]["items"]
+                ["$ref"],
+            "#/components/schemas/Item"
+        );
+        assert_eq!(
+            items["post"]["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/ItemForm"
+        );
+        assert_eq!(
+            items["post"]["security"],
+            json!([{ "cookieAuth": [] }, { "bearerAuth": [] }, { "apiKeyAuth": [] }])
+        );
+
+        let item = &spec["paths"]["/api/items/{id}"];
+        for method in ["get", "put", "delete"] {
+            assert!(item[method]["responses"]["404"].is_object(), "{}", method);
+        }
+        assert!(item["delete"]["responses"]["204"].is_object());
+        assert!(item["delete"]["responses"]["200"].is_null());
+
+        assert_eq!(
+            spec["paths"]["/login"]["post"]["requestBody"]["content"]
+                ["application/x-www-form-urlencoded"]["schema"]["$ref"],
+            "#/components/schemas/LoginForm"
+        );
+
+        let login = &spec["paths"]["/api/login"]["post"];
+        assert_eq!(
+            login["responses"]["200"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/ApiToken"
+        );
+        assert!(login["responses"]["401"].is_object());
+
+        let schemas = &spec["components"]["schemas"];
+        assert_eq!(
+            schemas["Item"]["properties"]["created_at"]["format"],
+            "date-time"
+        );
+        assert_eq!(
+            schemas["Claims"]["properties"]["role"]["$ref"],
+            "#/components/schemas/Role"
+        );
+        assert_eq!(schemas["Role"]["enum"], json!(["user", "admin"]));
+        assert_eq!(
+            spec["components"]["securitySchemes"]["cookieAuth"]["in"],
+            "cookie"
+        );
+        assert_eq!(
+            spec["components"]["securitySchemes"]["bearerAuth"]["scheme"],
+            "bearer"
+        );
+        assert_eq!(
+            spec["components"]["securitySchemes"]["apiKeyAuth"]["name"],
+            "X-API-Key"
+        );
+    }
+
+    #[tokio::test]
+    async fn swagger_ui_loads_the_spec() {
+        let response = swagger_ui().await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_string(response).await.contains(SPEC_PATH));
+    }
+}
@@ -0,0 +1,181 @@
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Algorithm, Argon2, Params, Version,
+};
+use std::sync::OnceLock;
+
+/// Argon2 memory cost in KiB when `ARGON2_MEMORY_KIB` is unset
+pub const DEFAULT_MEMORY_KIB: u32 = Params::DEFAULT_M_COST;
+/// Passes over memory when `ARGON2_ITERATIONS` is unset
+pub const DEFAULT_ITERATIONS: u32 = Params::DEFAULT_T_COST;
+/// Lanes when `ARGON2_PARALLELISM` is unset
+pub const DEFAULT_PARALLELISM: u32 = Params::DEFAULT_P_COST;
+
+/// Prefixes of the bcrypt hashes users imported from other systems bring
+/// along; they verify, and are replaced by Argon2id at the next login
+const BCRYPT_PREFIXES: &[&str] = &["$2a$", "$2b$", "$2x$", "$2y$"];
+
+fn is_bcrypt(password_hash: &str) -> bool {
+    BCRYPT_PREFIXES
+        .iter()
+        .any(|prefix| password_hash.starts_with(prefix))
+}
+
+/// Whether a stored hash is in a format `PasswordConfig::verify` reads: a
+/// PHC string such as Argon2's, or bcrypt
+pub fn is_known_format(password_hash: &str) -> bool {
+    is_bcrypt(password_hash) || PasswordHash::new(password_hash).is_ok()
+}
+
+/// Argon2id settings for new password hashes
+///
+/// Every stored hash records the parameters it was made with, so changing
+/// these never breaks existing logins; weaker hashes are replaced as their
+/// owners log in (see `needs_rehash`).
+#[derive(Debug)]
+pub struct PasswordConfig {
+    params: Params,
+    // Hashed on first use with the same settings, so unknown usernames take
+    // as long to reject as wrong passwords
+    dummy_hash: OnceLock<String>,
+}
+
+impl PasswordConfig {
+    /// Fails for costs Argon2 does not accept, e.g. memory below 8 KiB per lane
+    pub fn new(memory_kib: u32, iterations: u32, parallelism: u32) -> Result<Self, argon2::Error> {
+        Ok(Self {
+            params: Params::new(memory_kib, iterations, parallelism, None)?,
+            dummy_hash: OnceLock::new(),
+        })
+    }
+
+    fn hasher(&self) -> Argon2<'static> {
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+    }
+
+    /// Hash a password with a fresh salt, as a PHC string
+    pub fn hash(&self, password: &str) -> Result<String, argon2::password_hash::Error> {
+        let salt = SaltString::generate(&mut OsRng);
+        self.hasher()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+    }
+
+    /// Whether `password` matches a stored Argon2 or bcrypt hash, whatever
+    /// parameters it was made with; an unparsable hash never matches
+    pub fn verify(&self, password_hash: &str, password: &str) -> bool {
+        if is_bcrypt(password_hash) {
+            return bcrypt::verify(password, password_hash).unwrap_or(false);
+        }
+        PasswordHash::new(password_hash).is_ok_and(|hash| {
+            self.hasher()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+    }
+
+    /// Check a password against nothing, taking as long as `verify` would
+    pub fn verify_dummy(&self, password: &str) {
+        let hash = self
+            .dummy_hash
+            .get_or_init(|| self.hash("not-a-real-password").unwrap_or_default());
+        self.verify(hash, password);
+    }
+
+    /// Whether a stored hash is weaker than what `hash` produces now: bcrypt,
+    /// another Argon2 algorithm or version, or a lower memory, iteration or
+    /// lane count
+    ///
+    /// Hashes that do not parse are left alone; they never verify anyway.
+    pub fn needs_rehash(&self, password_hash: &str) -> bool {
+        if is_bcrypt(password_hash) {
+            return true;
+        }
+        let Ok(hash) = PasswordHash::new(password_hash) else {
+            return false;
+        };
+        let current = hash.algorithm == Algorithm::Argon2id.ident()
+            && hash.version == Some(Version::V0x13.into());
+        match Params::try_from(&hash) {
+            Ok(params) => {
+                !current
+                    || params.m_cost() < self.params.m_cost()
+                    || params.t_cost() < self.params.t_cost()
+                    || params.p_cost() < self.params.p_cost()
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+impl Default for PasswordConfig {
+    fn default() -> Self {
+        Self {
+            params: Params::DEFAULT,
+            dummy_hash: OnceLock::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Cheap enough for tests, and weaker than the defaults
+    fn weak() -> PasswordConfig {
+        PasswordConfig::new(1024, 1, 1).expect("valid params")
+    }
+
+    #[test]
+    fn hashes_verify_under_any_settings() {
+        let hash = weak().hash("secret").expect("hash");
+        assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+
+        let config = PasswordConfig::default();
+        assert!(config.verify(&hash, "secret"));
+        assert!(!config.verify(&hash, "wrong"));
+        assert!(!config.verify("not a hash", "secret"));
+    }
+
+    #[test]
+    fn needs_rehash_flags_weaker_hashes_only() {
+        let config = PasswordConfig::new(2048, 2, 1).expect("valid params");
+        let hash = config.hash("secret").expect("hash");
+        assert!(!config.needs_rehash(&hash));
+
+        assert!(config.needs_rehash(&weak().hash("secret").expect("hash")));
+        let stronger = PasswordConfig::new(4096, 3, 1).expect("valid params");
+        assert!(!config.needs_rehash(&stronger.hash("secret").expect("hash")));
+
+        let argon2i = Argon2::new(Algorithm::Argon2i, Version::V0x13, Params::DEFAULT)
+            .hash_password(b"secret", &SaltString::generate(&mut OsRng))
+            .expect("hash")
+            .to_string();
+        assert!(config.needs_rehash(&argon2i));
+        assert!(config.verify(&argon2i, "secret"));
+        assert!(!config.needs_rehash("not a hash"));
+    }
+
+    #[test]
+    fn bcrypt_hashes_verify_and_need_rehash() {
+        let config = PasswordConfig::default();
+        let hash = bcrypt::hash("secret", 4).expect("hash");
+        assert!(hash.starts_with("$2b$04$"));
+        assert!(config.verify(&hash, "secret"));
+        assert!(!config.verify(&hash, "wrong"));
+        assert!(config.needs_rehash(&hash));
+
+        // Older libraries wrote `$2a$` and `$2y$` for the same format
+        let legacy = hash.replacen("$2b$", "$2y$", 1);
+        assert!(config.verify(&legacy, "secret"));
+        assert!(!config.verify("$2b$04$truncated", "secret"));
+        assert!(is_known_format(&hash) && is_known_format(&weak().hash("x").expect("hash")));
+        assert!(!is_known_format("not a hash"));
+    }
+
+    #[test]
+    fn new_rejects_invalid_costs() {
+        assert!(PasswordConfig::new(1024, 0, 1).is_err());
+        assert!(PasswordConfig::new(1, 1, 1).is_err());
+    }
+}
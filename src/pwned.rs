@@ -0,0 +1,126 @@
+use async_trait::async_trait;
+use sha1::{Digest, Sha1};
+use std::{sync::Arc, time::Duration};
+
+/// HaveIBeenPwned's range endpoint when `PWNED_PASSWORD_API_URL` is unset;
+/// the hash prefix is appended to it
+pub const DEFAULT_API_URL: &str = "https://api.pwnedpasswords.com/range/";
+/// How long a lookup may take when `PWNED_PASSWORD_TIMEOUT_MS` is unset
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Hex digits of the SHA-1 that leave the server; the API wants five
+const PREFIX_LEN: usize = 5;
+
+/// Answers range lookups: every known hash starting with a prefix, as
+/// `SUFFIX:COUNT` lines
+///
+/// `HttpRangeSource` asks the API; tests answer without a network.
+#[async_trait]
+pub trait RangeSource: Send + Sync {
+    async fn fetch_range(
+        &self,
+        prefix: &str,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>>;
+}
+
+/// Range lookups over HTTP
+pub struct HttpRangeSource {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl HttpRangeSource {
+    pub fn new(base_url: &str, timeout: Duration) -> Result<Self, reqwest::Error> {
+        Ok(Self {
+            client: reqwest::Client::builder().timeout(timeout).build()?,
+            base_url: base_url.to_string(),
+        })
+    }
+}
+
+#[async_trait]
+impl RangeSource for HttpRangeSource {
+    async fn fetch_range(
+        &self,
+        prefix: &str,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        // Padding hides from onlookers how many suffixes came back
+        let response = self
+            .client
+            .get(format!("{}{}", self.base_url, prefix))
+            .header("Add-Padding", "true")
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(response.text().await?)
+    }
+}
+
+/// Rejects passwords known from data breaches, enabled by
+/// `PWNED_PASSWORD_CHECK`
+///
+/// Only the first five hex digits of the password's SHA-1 are sent
+/// (k-anonymity); the rest is compared here against what comes back.
+pub struct PwnedPasswords {
+    source: Arc<dyn RangeSource>,
+}
+
+impl PwnedPasswords {
+    pub fn new(source: impl RangeSource + 'static) -> Self {
+        Self {
+            source: Arc::new(source),
+        }
+    }
+
+    /// Whether `password` appears in a known breach
+    ///
+    /// A failed lookup counts as not found, so an outage of the API never
+    /// keeps anyone from signing up.
+    pub async fn is_pwned(&self, password: &str) -> bool {
+        let digest = hex::encode_upper(Sha1::digest(password.as_bytes()));
+        let (prefix, suffix) = digest.split_at(PREFIX_LEN);
+        match self.source.fetch_range(prefix).await {
+            Ok(body) => body.lines().any(|line| lists(line, suffix)),
+            Err(e) => {
+                tracing::warn!(error = %e, "pwned password lookup failed, allowing the password");
+                false
+            }
+        }
+    }
+}
+
+// Padding lines carry a count of 0 and never match a real password
+fn lists(line: &str, suffix: &str) -> bool {
+    line.trim().split_once(':').is_some_and(|(hash, count)| {
+        hash.eq_ignore_ascii_case(suffix) && count.trim().parse::<u64>().is_ok_and(|n| n > 0)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::FakeRangeSource;
+
+    #[tokio::test]
+    async fn listed_passwords_are_pwned() {
+        // SHA-1 of "password" is 5BAA61E4C9B93F3F0682250B6CF8331B7EE68FD8
+        let range = "0018A45C4D1DEF81644B54AB7F969B88D65:3\r\n\
+                     1e4c9b93f3f0682250b6cf8331b7ee68fd8:9659365\r\n";
+        let pwned = PwnedPasswords::new(FakeRangeSource::answering(range));
+        assert!(pwned.is_pwned("password").await);
+        assert!(!pwned.is_pwned("plum tree lantern").await);
+    }
+
+    #[tokio::test]
+    async fn padding_entries_are_not_breaches() {
+        let range = "1E4C9B93F3F0682250B6CF8331B7EE68FD8:0\r\n";
+        let pwned = PwnedPasswords::new(FakeRangeSource::answering(range));
+        assert!(!pwned.is_pwned("password").await);
+    }
+
+    #[tokio::test]
+    async fn failed_lookups_let_the_password_through() {
+        let pwned = PwnedPasswords::new(FakeRangeSource::failing());
+        assert!(!pwned.is_pwned("password").await);
+    }
+}
@@ -0,0 +1,226 @@
+use futures_util::future::BoxFuture;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// First added delay after a failed login unless `LOGIN_DELAY_STEP_MS`
+/// overrides it
+pub const DEFAULT_DELAY_STEP: Duration = Duration::from_millis(250);
+/// Longest added delay unless `LOGIN_DELAY_MAX_MS` overrides it
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(5);
+
+/// In-memory fixed-window limiter for login attempts
+///
+/// Counts failed attempts per key (the normalized username). Once a key hits
+/// `max_attempts` inside `window`, further attempts are refused until the
+/// window expires.
+pub struct LoginRateLimiter {
+    max_attempts: u32,
+    window: Duration,
+    attempts: Mutex<HashMap<String, Attempts>>,
+}
+
+struct Attempts {
+    window_start: Instant,
+    failures: u32,
+}
+
+impl LoginRateLimiter {
+    /// Create a limiter allowing `max_attempts` failures per `window`
+    pub fn new(max_attempts: u32, window: Duration) -> Self {
+        Self {
+            max_attempts,
+            window,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Check whether another attempt is allowed
+    ///
+    /// Returns the remaining wait when the key is currently limited.
+    pub fn check(&self, key: &str) -> Result<(), Duration> {
+        let mut attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+
+        match attempts.get(&normalize(key)) {
+            Some(entry) if now.duration_since(entry.window_start) >= self.window => {
+                attempts.remove(&normalize(key));
+                Ok(())
+            }
+            Some(entry) if entry.failures >= self.max_attempts => {
+                Err(self.window - now.duration_since(entry.window_start))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Record a failed attempt for a key
+    pub fn record_failure(&self, key: &str) {
+        let mut attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+
+        let entry = attempts.entry(normalize(key)).or_insert(Attempts {
+            window_start: now,
+            failures: 0,
+        });
+        if now.duration_since(entry.window_start) >= self.window {
+            entry.window_start = now;
+            entry.failures = 0;
+        }
+        entry.failures += 1;
+    }
+
+    /// Clear the counter for a key, e.g. after a successful login
+    pub fn reset(&self, key: &str) {
+        let mut attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        attempts.remove(&normalize(key));
+    }
+}
+
+/// Waits before answering a failed login, longer after each recent failure
+///
+/// Keyed like `LoginRateLimiter`, but instead of refusing attempts it slows
+/// them: the n-th failure inside `window` waits `step * 2^(n-1)`, at most
+/// `max_delay`. Successful logins are never delayed.
+pub struct LoginThrottle {
+    step: Duration,
+    max_delay: Duration,
+    window: Duration,
+    attempts: Mutex<HashMap<String, Attempts>>,
+    sleep: Box<dyn Fn(Duration) -> BoxFuture<'static, ()> + Send + Sync>,
+}
+
+impl LoginThrottle {
+    /// Create a throttle that sleeps on the Tokio timer
+    pub fn new(step: Duration, max_delay: Duration, window: Duration) -> Self {
+        Self {
+            step,
+            max_delay,
+            window,
+            attempts: Mutex::new(HashMap::new()),
+            sleep: Box::new(|delay| Box::pin(tokio::time::sleep(delay))),
+        }
+    }
+
+    /// Wait with `sleep` instead of the Tokio timer, e.g. to record delays
+    #[cfg(test)]
+    pub fn with_sleep(
+        mut self,
+        sleep: impl Fn(Duration) -> BoxFuture<'static, ()> + Send + Sync + 'static,
+    ) -> Self {
+        self.sleep = Box::new(sleep);
+        self
+    }
+
+    /// Record a failed attempt for a key and wait out its delay
+    pub async fn fail(&self, key: &str) {
+        let delay = self.record_failure(key);
+        if !delay.is_zero() {
+            (self.sleep)(delay).await;
+        }
+    }
+
+    /// Count a failure for a key, returning the delay it now earns
+    fn record_failure(&self, key: &str) -> Duration {
+        let mut attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+
+        let entry = attempts.entry(normalize(key)).or_insert(Attempts {
+            window_start: now,
+            failures: 0,
+        });
+        if now.duration_since(entry.window_start) >= self.window {
+            entry.window_start = now;
+            entry.failures = 0;
+        }
+        entry.failures += 1;
+
+        let factor = 2u32.saturating_pow(entry.failures - 1);
+        self.step.saturating_mul(factor).min(self.max_delay)
+    }
+
+    /// Forget a key's failures, e.g. after a successful login
+    pub fn reset(&self, key: &str) {
+        let mut attempts = self.attempts.lock().unwrap_or_else(|e| e.into_inner());
+        attempts.remove(&normalize(key));
+    }
+}
+
+fn normalize(key: &str) -> String {
+    key.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_attempts_below_limit() {
+        let limiter = LoginRateLimiter::new(3, Duration::from_secs(60));
+        limiter.record_failure("alice");
+        limiter.record_failure("alice");
+        assert!(limiter.check("alice").is_ok());
+    }
+
+    #[test]
+    fn blocks_after_limit_and_reports_wait() {
+        let limiter = LoginRateLimiter::new(2, Duration::from_secs(60));
+        limiter.record_failure("alice");
+        limiter.record_failure("Alice ");
+
+        let wait = limiter.check("alice").expect_err("limited");
+        assert!(wait <= Duration::from_secs(60));
+        assert!(limiter.check("bob").is_ok());
+    }
+
+    #[test]
+    fn window_expiry_allows_again() {
+        let limiter = LoginRateLimiter::new(1, Duration::from_millis(20));
+        limiter.record_failure("alice");
+        assert!(limiter.check("alice").is_err());
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert!(limiter.check("alice").is_ok());
+    }
+
+    #[test]
+    fn reset_clears_failures() {
+        let limiter = LoginRateLimiter::new(1, Duration::from_secs(60));
+        limiter.record_failure("alice");
+        limiter.reset("alice");
+        assert!(limiter.check("alice").is_ok());
+    }
+
+    #[test]
+    fn throttle_delay_doubles_up_to_the_cap() {
+        let throttle = LoginThrottle::new(
+            Duration::from_millis(100),
+            Duration::from_millis(500),
+            Duration::from_secs(60),
+        );
+        let delays: Vec<_> = (0..5)
+            .map(|_| throttle.record_failure("alice").as_millis())
+            .collect();
+        assert_eq!(delays, [100, 200, 400, 500, 500]);
+        assert_eq!(throttle.record_failure("bob").as_millis(), 100);
+
+        throttle.reset("Alice");
+        assert_eq!(throttle.record_failure("alice").as_millis(), 100);
+    }
+
+    #[test]
+    fn throttle_forgets_failures_after_the_window() {
+        let throttle = LoginThrottle::new(
+            Duration::from_millis(100),
+            Duration::from_secs(5),
+            Duration::from_millis(20),
+        );
+        throttle.record_failure("alice");
+        assert_eq!(throttle.record_failure("alice").as_millis(), 200);
+
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(throttle.record_failure("alice").as_millis(), 100);
+    }
+}
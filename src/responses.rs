@@ -0,0 +1,124 @@
+use rustapi_rs::prelude::*;
+use rustapi_rs::ResponseBody;
+
+use crate::AppState;
+
+/// Redirect to `location` with `status`, adding every `headers` entry
+///
+/// Names may repeat, so several `Set-Cookie` values all reach the client.
+/// Use `303 See Other` to answer a form POST and `302 Found` elsewhere.
+pub fn redirect(status: StatusCode, location: &str, headers: &[(&'static str, &str)]) -> Response {
+    let mut response = Response::new(ResponseBody::empty());
+    *response.status_mut() = status;
+
+    if let Ok(value) = location.parse() {
+        response.headers_mut().insert("Location", value);
+    }
+    for (name, value) in headers {
+        if let Ok(value) = value.parse() {
+            response.headers_mut().append(*name, value);
+        }
+    }
+    response
+}
+
+/// `302 Found` to the app's own `path`, under `Config::base_path`
+pub fn redirect_to(state: &AppState, path: &str) -> Response {
+    redirect(StatusCode::FOUND, &state.config.base_path.url(path), &[])
+}
+
+/// `303 See Other` to the app's own `path` that sets `cookies`, full
+/// `Set-Cookie` values
+pub fn redirect_with_cookies(state: &AppState, path: &str, cookies: &[String]) -> Response {
+    with_cookies(
+        redirect(
+            StatusCode::SEE_OTHER,
+            &state.config.base_path.url(path),
+            &[],
+        ),
+        cookies,
+    )
+}
+
+/// Add a `Set-Cookie` header to `response` for each of `cookies`
+pub fn with_cookies(mut response: Response, cookies: &[String]) -> Response {
+    for cookie in cookies {
+        if let Ok(value) = cookie.parse() {
+            response.headers_mut().append("Set-Cookie", value);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_path::BasePath;
+    use crate::test_utils::{cleanup_db, header_value, setup_test_state};
+    use std::sync::Arc;
+
+    fn all(response: &Response, name: &str) -> Vec<String> {
+        response
+            .headers()
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok().map(str::to_string))
+            .collect()
+    }
+
+    #[test]
+    fn redirect_sets_status_location_and_every_header() {
+        let response = redirect(
+            StatusCode::FOUND,
+            "/items?page=2",
+            &[
+                ("Set-Cookie", "a=1; Path=/"),
+                ("Set-Cookie", "b=2; Path=/"),
+                ("Cache-Control", "no-store"),
+            ],
+        );
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            header_value(&response, "Location").as_deref(),
+            Some("/items?page=2")
+        );
+        assert_eq!(all(&response, "Set-Cookie"), ["a=1; Path=/", "b=2; Path=/"]);
+        assert_eq!(
+            header_value(&response, "Cache-Control").as_deref(),
+            Some("no-store")
+        );
+    }
+
+    #[tokio::test]
+    async fn redirect_with_cookies_is_a_see_other() {
+        let (state, path) = setup_test_state().await;
+        let cookies = [
+            "token=abc; Path=/".to_string(),
+            "refresh=def; Path=/".to_string(),
+        ];
+        let response = redirect_with_cookies(&state, "/", &cookies);
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(header_value(&response, "Location").as_deref(), Some("/"));
+        assert_eq!(all(&response, "Set-Cookie"), cookies);
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn redirects_stay_under_the_base_path() {
+        let (mut state, path) = setup_test_state().await;
+        Arc::make_mut(&mut state.config).base_path = BasePath::parse("/app").expect("base path");
+
+        let response = redirect_to(&state, "/login?registered=true");
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            header_value(&response, "Location").as_deref(),
+            Some("/app/login?registered=true")
+        );
+        let response = redirect_with_cookies(&state, "/", &[]);
+        assert_eq!(
+            header_value(&response, "Location").as_deref(),
+            Some("/app/")
+        );
+        cleanup_db(path);
+    }
+}
@@ -0,0 +1,173 @@
+use std::time::Duration;
+
+use http::Method;
+use rustapi_rs::Request;
+
+use crate::AppState;
+
+/// Largest body `/login`, `/api/login` and `/register` accept; their forms
+/// are a few short fields
+pub const AUTH_MAX_BODY_BYTES: usize = 16 * 1024;
+
+/// Limits one route has in place of the global ones
+#[derive(Debug, Clone)]
+pub struct RouteOverride {
+    pub method: Method,
+    /// The route as its handler declares it; a `{name}` segment matches any
+    /// one segment
+    pub path: &'static str,
+    /// Take `LONG_REQUEST_TIMEOUT_SECS` in place of `REQUEST_TIMEOUT_SECS`
+    pub long_timeout: bool,
+    /// Largest form or JSON body, in place of `MAX_FORM_BYTES`
+    pub max_body_bytes: Option<usize>,
+}
+
+impl RouteOverride {
+    const fn long(method: Method, path: &'static str) -> Self {
+        Self {
+            method,
+            path,
+            long_timeout: true,
+            max_body_bytes: None,
+        }
+    }
+
+    const fn max_body(method: Method, path: &'static str, bytes: usize) -> Self {
+        Self {
+            method,
+            path,
+            long_timeout: false,
+            max_body_bytes: Some(bytes),
+        }
+    }
+
+    fn matches(&self, method: &Method, path: &str) -> bool {
+        let mut pattern = self.path.split('/');
+        let mut segments = path.split('/');
+        self.method == method
+            && loop {
+                match (pattern.next(), segments.next()) {
+                    (None, None) => break true,
+                    (Some(p), Some(s)) if p == s || (p.starts_with('{') && !s.is_empty()) => {}
+                    _ => break false,
+                }
+            }
+    }
+}
+
+/// Routes whose handlers need other limits than the rest; the handlers say
+/// so in their docs
+///
+/// Routes that work through all of a user's items at once get the long
+/// timeout, and the login and registration forms only take small bodies.
+pub const ROUTE_OVERRIDES: &[RouteOverride] = &[
+    RouteOverride::long(Method::GET, "/items/export.json"),
+    RouteOverride::long(Method::POST, "/items/import"),
+    RouteOverride::long(Method::POST, "/api/items/batch"),
+    RouteOverride::max_body(Method::POST, "/login", AUTH_MAX_BODY_BYTES),
+    RouteOverride::max_body(Method::POST, "/api/login", AUTH_MAX_BODY_BYTES),
+    RouteOverride::max_body(Method::POST, "/register", AUTH_MAX_BODY_BYTES),
+];
+
+/// The limits a request is held to
+///
+/// `TimeoutLayer` resolves them once per request and leaves them in its
+/// extensions for the body extractors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouteLimits {
+    pub timeout: Duration,
+    pub max_body_bytes: usize,
+}
+
+impl RouteLimits {
+    /// Limits of a `method` request to `path`: the first matching entry of
+    /// `ROUTE_OVERRIDES`, and the configured ones for whatever it leaves out
+    pub fn for_route(state: &AppState, method: &Method, path: &str) -> Self {
+        let found = ROUTE_OVERRIDES
+            .iter()
+            .find(|route| route.matches(method, path));
+        Self {
+            timeout: if found.is_some_and(|route| route.long_timeout) {
+                state.config.request_timeouts.long
+            } else {
+                state.config.request_timeouts.default
+            },
+            max_body_bytes: found
+                .and_then(|route| route.max_body_bytes)
+                .unwrap_or(state.config.max_form_bytes),
+        }
+    }
+
+    /// The limits `TimeoutLayer` left on `req`, or those of its route
+    pub fn of(state: &AppState, req: &Request) -> Self {
+        req.extensions()
+            .get::<RouteLimits>()
+            .copied()
+            .unwrap_or_else(|| Self::for_route(state, req.method(), req.path()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{cleanup_db, setup_test_state};
+    use crate::timeout::RequestTimeouts;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn bulk_routes_get_the_long_timeout_and_logins_the_short_one() {
+        let (mut state, path) = setup_test_state().await;
+        Arc::make_mut(&mut state.config).request_timeouts = RequestTimeouts {
+            default: Duration::from_secs(1),
+            long: Duration::from_secs(60),
+        };
+        let limits = |method: Method, route: &str| RouteLimits::for_route(&state, &method, route);
+
+        assert_eq!(
+            limits(Method::GET, "/items/export.json").timeout,
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            limits(Method::POST, "/api/items/batch").timeout,
+            Duration::from_secs(60)
+        );
+        for (method, path) in [
+            (Method::GET, "/items"),
+            (Method::POST, "/login"),
+            (Method::POST, "/api/login"),
+            (Method::GET, "/items/import"),
+        ] {
+            assert_eq!(limits(method, path).timeout, Duration::from_secs(1));
+        }
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn body_limits_fall_back_to_the_configured_one() {
+        let (mut state, path) = setup_test_state().await;
+        Arc::make_mut(&mut state.config).max_form_bytes = 1024 * 1024;
+        let limits = |method: Method, route: &str| RouteLimits::for_route(&state, &method, route);
+
+        assert_eq!(
+            limits(Method::POST, "/login").max_body_bytes,
+            AUTH_MAX_BODY_BYTES
+        );
+        assert_eq!(
+            limits(Method::POST, "/register").max_body_bytes,
+            AUTH_MAX_BODY_BYTES
+        );
+        assert_eq!(limits(Method::POST, "/items").max_body_bytes, 1024 * 1024);
+        assert_eq!(limits(Method::GET, "/login").max_body_bytes, 1024 * 1024);
+        cleanup_db(path);
+    }
+
+    #[test]
+    fn placeholders_match_one_segment() {
+        let route = RouteOverride::max_body(Method::POST, "/items/{id}", 10);
+        assert!(route.matches(&Method::POST, "/items/42"));
+        assert!(!route.matches(&Method::GET, "/items/42"));
+        for path in ["/items", "/items/", "/items/42/edit", "/things/42"] {
+            assert!(!route.matches(&Method::POST, path), "{}", path);
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use http::HeaderValue;
+use rustapi_rs::prelude::*;
+
+use crate::openapi;
+
+/// `Content-Security-Policy` unless `CONTENT_SECURITY_POLICY` overrides it
+///
+/// Scripts, styles and images only load from our own origin. Inline ones
+/// stay allowed because the templates use them: the theme script in
+/// `base.html`, `onsubmit` confirmations and `style` attributes.
+pub const DEFAULT_CONTENT_SECURITY_POLICY: &str = "default-src 'self'; \
+     script-src 'self' 'unsafe-inline'; style-src 'self' 'unsafe-inline'; \
+     img-src 'self' data:; object-src 'none'; base-uri 'self'; form-action 'self'";
+
+/// Origin the Swagger UI page at `openapi::DOCS_PATH` loads its scripts and
+/// styles from; the page is generated by `rustapi_openapi`
+pub const DOCS_ASSET_ORIGIN: &str = "https://unpkg.com";
+
+/// `X-Frame-Options` unless `X_FRAME_OPTIONS` overrides it
+pub const DEFAULT_FRAME_OPTIONS: &str = "DENY";
+
+/// `Referrer-Policy` unless `REFERRER_POLICY` overrides it
+pub const DEFAULT_REFERRER_POLICY: &str = "strict-origin-when-cross-origin";
+
+/// `max-age` of `Strict-Transport-Security` unless `HSTS_MAX_AGE_SECS`
+/// overrides it: one year
+pub const DEFAULT_HSTS_MAX_AGE_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// Setting a header's variable to this leaves the header out
+pub const OFF: &str = "off";
+
+/// Hardening headers added to every HTML page
+///
+/// A header set to `None` is not sent, and none are when `enabled` is off.
+/// On the API docs page the policy also allows `DOCS_ASSET_ORIGIN`; see
+/// `allow_docs_assets`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SecurityHeaders {
+    pub enabled: bool,
+    pub content_security_policy: Option<String>,
+    pub frame_options: Option<String>,
+    pub referrer_policy: Option<String>,
+    /// Zero leaves `Strict-Transport-Security` out
+    pub hsts_max_age_secs: u64,
+    /// Whether the site is served over HTTPS, going by `PUBLIC_URL`; browsers
+    /// are only told to insist on HTTPS when it is
+    pub https: bool,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            content_security_policy: Some(DEFAULT_CONTENT_SECURITY_POLICY.to_string()),
+            frame_options: Some(DEFAULT_FRAME_OPTIONS.to_string()),
+            referrer_policy: Some(DEFAULT_REFERRER_POLICY.to_string()),
+            hsts_max_age_secs: DEFAULT_HSTS_MAX_AGE_SECS,
+            https: false,
+        }
+    }
+}
+
+impl SecurityHeaders {
+    /// The headers to send, by name
+    pub fn headers(&self) -> Vec<(&'static str, String)> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        let mut headers = vec![("X-Content-Type-Options", "nosniff".to_string())];
+        let configured = [
+            ("Content-Security-Policy", &self.content_security_policy),
+            ("X-Frame-Options", &self.frame_options),
+            ("Referrer-Policy", &self.referrer_policy),
+        ];
+        headers.extend(
+            configured
+                .into_iter()
+                .filter_map(|(name, value)| Some((name, value.clone()?))),
+        );
+        if self.https && self.hsts_max_age_secs > 0 {
+            headers.push((
+                "Strict-Transport-Security",
+                format!("max-age={}; includeSubDomains", self.hsts_max_age_secs),
+            ));
+        }
+        headers
+    }
+
+    /// Add the headers to `response`, the answer to a request for `path`, if
+    /// it is an HTML page, keeping any it already sets itself
+    pub fn apply(&self, path: &str, mut response: Response) -> Response {
+        let is_html = response
+            .headers()
+            .get("Content-Type")
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("text/html"));
+        if !is_html {
+            return response;
+        }
+        let headers = response.headers_mut();
+        for (name, value) in self.headers() {
+            let value = if name == "Content-Security-Policy" && path == openapi::DOCS_PATH {
+                allow_docs_assets(&value)
+            } else {
+                value
+            };
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.entry(name).or_insert(value);
+            }
+        }
+        response
+    }
+}
+
+/// `policy` with `DOCS_ASSET_ORIGIN` added to the sources of its scripts and
+/// styles, keeping everything else as configured
+///
+/// A missing `script-src` or `style-src` falls back to `default-src`, so it
+/// is added as a copy of that with the origin on top. Without `default-src`
+/// either, scripts and styles load from anywhere already.
+pub fn allow_docs_assets(policy: &str) -> String {
+    let mut directives: Vec<Vec<&str>> = policy
+        .split(';')
+        .map(|directive| directive.split_whitespace().collect::<Vec<_>>())
+        .filter(|directive| !directive.is_empty())
+        .collect();
+    let find = |directives: &[Vec<&str>], name: &str| {
+        directives.iter().position(|directive| directive[0] == name)
+    };
+    let default_src = find(&directives, "default-src").map(|i| directives[i][1..].to_vec());
+    for name in ["script-src", "style-src"] {
+        let i = match (find(&directives, name), &default_src) {
+            (Some(i), _) => i,
+            (None, Some(sources)) => {
+                directives.push([&[name][..], sources].concat());
+                directives.len() - 1
+            }
+            (None, None) => continue,
+        };
+        // `'none'` only holds alone
+        directives[i].retain(|source| *source != "'none'");
+        directives[i].push(DOCS_ASSET_ORIGIN);
+    }
+    directives
+        .iter()
+        .map(|directive| directive.join(" "))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(headers: &SecurityHeaders) -> Vec<&'static str> {
+        headers
+            .headers()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    #[test]
+    fn hsts_is_only_sent_over_https() {
+        let mut headers = SecurityHeaders::default();
+        assert!(!names(&headers).contains(&"Strict-Transport-Security"));
+
+        headers.https = true;
+        assert!(headers.headers().contains(&(
+            "Strict-Transport-Security",
+            "max-age=31536000; includeSubDomains".to_string()
+        )));
+        headers.hsts_max_age_secs = 0;
+        assert!(!names(&headers).contains(&"Strict-Transport-Security"));
+    }
+
+    #[test]
+    fn headers_can_be_left_out() {
+        let headers = SecurityHeaders {
+            content_security_policy: None,
+            frame_options: None,
+            ..SecurityHeaders::default()
+        };
+        assert_eq!(
+            names(&headers),
+            ["X-Content-Type-Options", "Referrer-Policy"]
+        );
+        let headers = SecurityHeaders {
+            enabled: false,
+            https: true,
+            ..SecurityHeaders::default()
+        };
+        assert!(headers.headers().is_empty());
+    }
+
+    #[test]
+    fn docs_assets_extend_the_configured_policy() {
+        assert_eq!(
+            allow_docs_assets("default-src 'none'; script-src 'self'; img-src 'self'"),
+            "default-src 'none'; script-src 'self' https://unpkg.com; img-src 'self'; \
+             style-src https://unpkg.com"
+        );
+        let extended = allow_docs_assets(DEFAULT_CONTENT_SECURITY_POLICY);
+        assert!(extended.contains("script-src 'self' 'unsafe-inline' https://unpkg.com;"));
+        assert!(extended.contains("style-src 'self' 'unsafe-inline' https://unpkg.com;"));
+        assert!(extended.ends_with("form-action 'self'"));
+        assert_eq!(
+            allow_docs_assets("frame-ancestors 'none'"),
+            "frame-ancestors 'none'"
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use crate::{db::Database, models::CreateItem, passwords::PasswordConfig};
+
+/// Items every demo account starts with: title, description and tags
+const DEMO_ITEMS: &[(&str, Option<&str>, &[&str])] = &[
+    (
+        "Read the README",
+        Some("Configuration, migrations and the JSON API are described there."),
+        &["docs"],
+    ),
+    (
+        "Try the JSON API",
+        Some("GET /api/items returns this list; the spec is at /openapi.json."),
+        &["api", "docs"],
+    ),
+    ("Buy groceries", Some("Milk, eggs, coffee"), &["errands"]),
+    ("Book a dentist appointment", None, &["errands", "health"]),
+    (
+        "Clean up the trash",
+        Some("Deleted items wait in /items/trash."),
+        &[],
+    ),
+];
+
+/// Demo account created by `cargo run -- seed`
+#[derive(Debug, Clone)]
+pub struct SeedConfig {
+    pub username: String,
+    pub email: String,
+    pub password: String,
+}
+
+impl Default for SeedConfig {
+    fn default() -> Self {
+        Self {
+            username: "demo".to_string(),
+            email: "demo@example.com".to_string(),
+            password: "demo-password".to_string(),
+        }
+    }
+}
+
+/// What `seed` did
+#[derive(Debug, PartialEq, Eq)]
+pub enum SeedOutcome {
+    /// The demo account was created with this many items
+    Created { items: usize },
+    /// The demo username was already taken, so nothing was written
+    AlreadySeeded,
+}
+
+/// Create a verified demo account with a few items, unless it exists already
+pub async fn seed(
+    db: &Database,
+    config: &SeedConfig,
+    passwords: &PasswordConfig,
+) -> Result<SeedOutcome, Box<dyn std::error::Error + Send + Sync>> {
+    if db.username_exists(&config.username).await? {
+        return Ok(SeedOutcome::AlreadySeeded);
+    }
+
+    let password_hash = passwords
+        .hash(&config.password)
+        .map_err(|e| e.to_string())?;
+
+    let user = db
+        .create_user(&config.username, &config.email, &password_hash)
+        .await?;
+    db.verify_user(user.id).await?;
+
+    for (title, description, tags) in DEMO_ITEMS {
+        let item = CreateItem {
+            user_id: user.id,
+            title: title.to_string(),
+            description: description.map(str::to_string),
+            due_at: None,
+        };
+        let tags = tags.iter().map(|t| t.to_string()).collect();
+        db.create_item_with_tags(item, tags).await?;
+    }
+
+    Ok(SeedOutcome::Created {
+        items: DEMO_ITEMS.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{cleanup_db, setup_test_state};
+
+    #[tokio::test]
+    async fn seeding_twice_does_not_duplicate_the_user() {
+        let (state, path) = setup_test_state().await;
+        let config = SeedConfig::default();
+
+        assert_eq!(
+            seed(&state.db, &config, &state.config.passwords)
+                .await
+                .expect("first seed"),
+            SeedOutcome::Created {
+                items: DEMO_ITEMS.len()
+            }
+        );
+        assert_eq!(
+            seed(&state.db, &config, &state.config.passwords)
+                .await
+                .expect("second seed"),
+            SeedOutcome::AlreadySeeded
+        );
+
+        let users = state.db.list_all_users().await.expect("list users");
+        assert_eq!(users.len(), 1);
+        assert!(users[0].verified);
+        let items = state.db.get_user_items(users[0].id).await.expect("items");
+        assert_eq!(items.len(), DEMO_ITEMS.len());
+        cleanup_db(path);
+    }
+}
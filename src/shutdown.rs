@@ -0,0 +1,152 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
+
+use crate::db::Database;
+
+/// Default wait for in-flight requests after a shutdown signal: 30 seconds
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Counts requests that are still being handled
+#[derive(Debug, Default)]
+pub struct InFlight {
+    count: AtomicUsize,
+    idle: Notify,
+}
+
+/// Marks one request as in flight until dropped
+pub struct InFlightGuard(Arc<InFlight>);
+
+impl InFlight {
+    pub fn start(self: &Arc<Self>) -> InFlightGuard {
+        self.count.fetch_add(1, Ordering::SeqCst);
+        InFlightGuard(self.clone())
+    }
+
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+
+    /// Resolve once no request is in flight
+    pub async fn wait_idle(&self) {
+        loop {
+            // Registered before the check, so a request finishing in between
+            // still wakes us
+            let idle = self.idle.notified();
+            if self.count() == 0 {
+                return;
+            }
+            idle.await;
+        }
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        if self.0.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.0.idle.notify_waiters();
+        }
+    }
+}
+
+/// Resolve on Ctrl+C (SIGINT) or, on Unix, SIGTERM
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(error = %e, "failed to listen for Ctrl+C");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => tracing::info!("received SIGINT, shutting down"),
+        _ = terminate => tracing::info!("received SIGTERM, shutting down"),
+    }
+}
+
+/// Finish shutting down once the server stopped accepting connections: wait
+/// up to `timeout` for in-flight requests, then close the database pool
+pub async fn drain(in_flight: &InFlight, db: &Database, timeout: Duration) {
+    tracing::info!(
+        in_flight = in_flight.count(),
+        timeout_secs = timeout.as_secs(),
+        "draining in-flight requests"
+    );
+    match tokio::time::timeout(timeout, in_flight.wait_idle()).await {
+        Ok(()) => tracing::info!("in-flight requests finished"),
+        Err(_) => tracing::warn!(
+            in_flight = in_flight.count(),
+            "shutdown timeout elapsed with requests still running"
+        ),
+    }
+
+    db.close().await;
+    tracing::info!("database pool closed");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{cleanup_db, setup_test_state};
+
+    #[tokio::test]
+    async fn drain_waits_for_requests_then_closes_pool() {
+        let (state, path) = setup_test_state().await;
+        let in_flight = Arc::new(InFlight::default());
+        let (finish, finished) = tokio::sync::oneshot::channel::<()>();
+
+        let request = {
+            let in_flight = in_flight.clone();
+            tokio::spawn(async move {
+                let _guard = in_flight.start();
+                let _ = finished.await;
+            })
+        };
+        while in_flight.count() == 0 {
+            tokio::task::yield_now().await;
+        }
+
+        let shutdown = {
+            let (in_flight, db) = (in_flight.clone(), state.db.clone());
+            tokio::spawn(async move { drain(&in_flight, &db, Duration::from_secs(5)).await })
+        };
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!shutdown.is_finished());
+        assert!(!state.db.is_closed());
+
+        finish.send(()).expect("finish request");
+        request.await.expect("request task");
+        shutdown.await.expect("shutdown task");
+        assert_eq!(in_flight.count(), 0);
+        assert!(state.db.is_closed());
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn drain_gives_up_after_timeout() {
+        let (state, path) = setup_test_state().await;
+        let in_flight = Arc::new(InFlight::default());
+        let _stuck = in_flight.start();
+
+        drain(&in_flight, &state.db, Duration::from_millis(20)).await;
+        assert_eq!(in_flight.count(), 1);
+        assert!(state.db.is_closed());
+        cleanup_db(path);
+    }
+}
@@ -0,0 +1,175 @@
+use chrono::DateTime;
+use rustapi_rs::prelude::*;
+use rustapi_rs::StaticFileConfig;
+
+use crate::etag;
+
+/// URL prefix static assets are served under
+pub const STATIC_PREFIX: &str = "/static";
+
+/// Directory static assets are served from
+pub const STATIC_ROOT: &str = "static";
+
+/// How long browsers may reuse a static asset without asking again, unless
+/// `STATIC_MAX_AGE_SECS` overrides it
+pub const DEFAULT_STATIC_MAX_AGE_SECS: u64 = 3600;
+
+// What static HTML, and assets when caching is off, say instead
+const REVALIDATE: &str = "no-cache";
+
+/// Serve `STATIC_ROOT` at `STATIC_PREFIX` with `max_age` seconds of caching
+///
+/// Files carry an `ETag` and a `Last-Modified` taken from their mtime and
+/// size, which `conditional` checks.
+pub fn config(max_age: u64) -> StaticFileConfig {
+    StaticFileConfig::new(STATIC_ROOT, STATIC_PREFIX)
+        .etag(true)
+        .last_modified(true)
+        .max_age(max_age)
+}
+
+/// Finish a static file `response` for a request with `if_none_match` and
+/// `if_modified_since` headers
+///
+/// Answers `304 Not Modified` when the client's copy is still current.
+/// HTML is never cached, so pages pick up changes on the next load; neither
+/// is anything when the max-age is 0.
+pub fn conditional(
+    mut response: Response,
+    if_none_match: Option<&str>,
+    if_modified_since: Option<&str>,
+) -> Response {
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+    let headers = response.headers_mut();
+    let is_html = headers
+        .get("Content-Type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html"));
+    if is_html {
+        headers.remove("ETag");
+        headers.remove("Last-Modified");
+    }
+    if is_html || !headers.contains_key("Cache-Control") {
+        if let Ok(value) = REVALIDATE.parse() {
+            headers.insert("Cache-Control", value);
+        }
+    }
+    if is_html {
+        return response;
+    }
+
+    let header = |name: &str| response.headers().get(name)?.to_str().ok();
+    if !not_modified(
+        if_none_match,
+        if_modified_since,
+        header("ETag"),
+        header("Last-Modified"),
+    ) {
+        return response;
+    }
+    let mut not_modified = StatusCode::NOT_MODIFIED.into_response();
+    for name in ["ETag", "Last-Modified", "Cache-Control"] {
+        if let Some(value) = response.headers().get(name).cloned() {
+            not_modified.headers_mut().insert(name, value);
+        }
+    }
+    not_modified
+}
+
+// Whether the client holds the version with `etag` and `last_modified`; as
+// RFC 9110 asks, `If-Modified-Since` only counts without `If-None-Match`
+fn not_modified(
+    if_none_match: Option<&str>,
+    if_modified_since: Option<&str>,
+    etag: Option<&str>,
+    last_modified: Option<&str>,
+) -> bool {
+    if let Some(if_none_match) = if_none_match {
+        return etag.is_some_and(|etag| etag::matches(if_none_match, etag));
+    }
+    let parse = |date: &str| DateTime::parse_from_rfc2822(date).ok();
+    match (
+        if_modified_since.and_then(parse),
+        last_modified.and_then(parse),
+    ) {
+        (Some(since), Some(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::header_value;
+    use rustapi_rs::StaticFile;
+
+    async fn serve(config: &StaticFileConfig, path: &str) -> Response {
+        StaticFile::serve(path, config)
+            .await
+            .expect("serve static file")
+    }
+
+    #[tokio::test]
+    async fn assets_carry_cache_headers_and_validators() {
+        let response = conditional(serve(&config(600), "css/style.css").await, None, None);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            header_value(&response, "Cache-Control").as_deref(),
+            Some("public, max-age=600")
+        );
+        assert!(header_value(&response, "ETag").is_some());
+        assert!(header_value(&response, "Last-Modified").is_some());
+
+        let response = conditional(serve(&config(0), "css/style.css").await, None, None);
+        assert_eq!(
+            header_value(&response, "Cache-Control").as_deref(),
+            Some(REVALIDATE)
+        );
+    }
+
+    #[tokio::test]
+    async fn current_copies_get_not_modified() {
+        let fresh = || async { serve(&config(600), "css/style.css").await };
+        let first = fresh().await;
+        let etag = header_value(&first, "ETag").expect("ETag");
+        let last_modified = header_value(&first, "Last-Modified").expect("Last-Modified");
+
+        let response = conditional(fresh().await, Some(&etag), None);
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(header_value(&response, "ETag"), Some(etag.clone()));
+        assert_eq!(
+            header_value(&response, "Cache-Control").as_deref(),
+            Some("public, max-age=600")
+        );
+
+        let response = conditional(fresh().await, None, Some(&last_modified));
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        // A stale tag wins over a current date
+        let response = conditional(fresh().await, Some("\"old\""), Some(&last_modified));
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = conditional(fresh().await, None, Some("Thu, 01 Jan 1970 00:00:00 GMT"));
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn html_is_not_cached() {
+        let dir = std::env::temp_dir().join(format!("static_cache_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).expect("create dir");
+        std::fs::write(dir.join("page.html"), "<p>page</p>").expect("write page");
+        let config = StaticFileConfig::new(&dir, STATIC_PREFIX).max_age(600);
+
+        let first = serve(&config, "page.html").await;
+        let etag = header_value(&first, "ETag").expect("ETag");
+        let response = conditional(serve(&config, "page.html").await, Some(&etag), None);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            header_value(&response, "Cache-Control").as_deref(),
+            Some(REVALIDATE)
+        );
+        assert_eq!(header_value(&response, "ETag"), None);
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
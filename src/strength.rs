@@ -0,0 +1,309 @@
+/// Shortest password accepted, whatever its score
+pub const MIN_PASSWORD_LENGTH: usize = 6;
+
+/// Lowest `estimate` score new passwords need unless `PASSWORD_MIN_SCORE`
+/// overrides it
+pub const DEFAULT_MIN_SCORE: u8 = 2;
+
+/// Highest score `estimate` gives
+pub const MAX_SCORE: u8 = 4;
+
+// Passwords topping leaked-password lists, lowercase; variants with leetspeak
+// or digits and symbols around them count too
+const COMMON_PASSWORDS: &[&str] = &[
+    "000000",
+    "111111",
+    "121212",
+    "123123",
+    "1234",
+    "12345",
+    "123456",
+    "1234567",
+    "12345678",
+    "123456789",
+    "1234567890",
+    "654321",
+    "666666",
+    "987654321",
+    "abc123",
+    "access",
+    "admin",
+    "adobe",
+    "america",
+    "andrew",
+    "arsenal",
+    "ashley",
+    "asdfgh",
+    "asdfghjkl",
+    "autumn",
+    "azerty",
+    "banana",
+    "baseball",
+    "batman",
+    "blink",
+    "buster",
+    "changeme",
+    "charlie",
+    "cheese",
+    "chelsea",
+    "chocolate",
+    "computer",
+    "cookie",
+    "daniel",
+    "default",
+    "diamond",
+    "donald",
+    "dragon",
+    "flower",
+    "football",
+    "freedom",
+    "golden",
+    "google",
+    "guest",
+    "harley",
+    "hello",
+    "hockey",
+    "hottie",
+    "hunter",
+    "iloveyou",
+    "internet",
+    "jesus",
+    "jordan",
+    "joshua",
+    "killer",
+    "letmein",
+    "liverpool",
+    "login",
+    "london",
+    "lovely",
+    "loveme",
+    "maggie",
+    "master",
+    "matrix",
+    "michael",
+    "monkey",
+    "mustang",
+    "ninja",
+    "orange",
+    "passw",
+    "password",
+    "pepper",
+    "photoshop",
+    "pokemon",
+    "princess",
+    "purple",
+    "qazwsx",
+    "qwe123",
+    "qwerty",
+    "qwertyuiop",
+    "ranger",
+    "robert",
+    "root",
+    "samsung",
+    "secret",
+    "shadow",
+    "silver",
+    "soccer",
+    "solo",
+    "spring",
+    "starwars",
+    "summer",
+    "sunshine",
+    "superman",
+    "test",
+    "thomas",
+    "tigger",
+    "trustno1",
+    "welcome",
+    "whatever",
+    "winter",
+    "yellow",
+    "zaq1zaq1",
+    "zxcvbnm",
+];
+
+// Keys next to each other on a QWERTY keyboard read as a sequence
+const KEYBOARD_ROWS: &[&str] = &["1234567890", "qwertyuiop", "asdfghjkl", "zxcvbnm"];
+
+/// How hard a password is to guess
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Strength {
+    /// 0 (guessed almost at once) to `MAX_SCORE` (very hard to guess)
+    pub score: u8,
+    /// What makes the password weak and how to improve it; `None` when
+    /// nothing stands out
+    pub feedback: Option<&'static str>,
+}
+
+/// Estimate how many guesses `password` would take, on zxcvbn's 0-4 scale
+///
+/// Common passwords score 0. Otherwise every character counts as a guess
+/// among the kinds of character the password uses, except those repeating or
+/// continuing a sequence (`aaa`, `abc`, `123`, `qwe`), which count for a
+/// fifth. `user_inputs` such as the username and email address count for
+/// nothing, since an attacker tries them first.
+pub fn estimate(password: &str, user_inputs: &[&str]) -> Strength {
+    let lower = password.to_lowercase();
+    if is_common(&lower) {
+        return Strength {
+            score: 0,
+            feedback: Some(
+                "This is a very common password. Add another word or two; uncommon words are better.",
+            ),
+        };
+    }
+
+    let mut rest = lower.clone();
+    let mut personal = false;
+    for input in user_inputs {
+        let input = input.to_lowercase();
+        let input = input.split('@').next().unwrap_or_default();
+        if input.chars().count() >= 3 && rest.contains(input) {
+            rest = rest.replace(input, "");
+            personal = true;
+        }
+    }
+
+    let chars: Vec<char> = rest.chars().collect();
+    let (mut repeats, mut sequences) = (0, 0);
+    let mut length = 0.0;
+    for (i, &c) in chars.iter().enumerate() {
+        match i.checked_sub(1).map(|prev| chars[prev]) {
+            Some(prev) if prev == c => {
+                repeats += 1;
+                length += 0.2;
+            }
+            Some(prev) if follows(prev, c) => {
+                sequences += 1;
+                length += 0.2;
+            }
+            _ => length += 1.0,
+        }
+    }
+    // The personal text still takes an attacker a few tries
+    let removed = lower.chars().count() - chars.len();
+    let log10_guesses = length * (charset_size(password) as f64).log10() + removed as f64 * 0.1;
+    let score = match log10_guesses {
+        g if g < 3.0 => 0,
+        g if g < 6.0 => 1,
+        g if g < 8.0 => 2,
+        g if g < 10.0 => 3,
+        _ => MAX_SCORE,
+    };
+
+    let feedback = if personal {
+        Some("Avoid using your username or email address in your password.")
+    } else if score == MAX_SCORE {
+        None
+    } else if repeats >= 2 && repeats >= sequences {
+        Some("Repeats like \"aaa\" are easy to guess. Use a longer mix of words.")
+    } else if sequences >= 2 {
+        Some("Sequences like \"abc\" or \"123\" are easy to guess. Use a longer mix of words.")
+    } else {
+        Some("Add another word or two; uncommon words are better.")
+    };
+    Strength { score, feedback }
+}
+
+// Whether `lower`, undoing leetspeak and ignoring digits and symbols around
+// it, is one of `COMMON_PASSWORDS`
+fn is_common(lower: &str) -> bool {
+    let trimmed = lower.trim_matches(|c: char| !c.is_alphabetic());
+    [lower, trimmed]
+        .into_iter()
+        .filter(|candidate| !candidate.is_empty())
+        .flat_map(|candidate| [candidate.to_string(), unleet(candidate)])
+        .any(|candidate| COMMON_PASSWORDS.contains(&candidate.as_str()))
+}
+
+fn unleet(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '0' => 'o',
+            '1' | '!' => 'i',
+            '3' => 'e',
+            '4' | '@' => 'a',
+            '5' | '$' => 's',
+            '7' => 't',
+            c => c,
+        })
+        .collect()
+}
+
+// Whether `c` is next to `prev` in the alphabet, the digits or a keyboard row
+fn follows(prev: char, c: char) -> bool {
+    if prev.is_ascii_alphanumeric()
+        && c.is_ascii_alphanumeric()
+        && (prev as i32 - c as i32).abs() == 1
+    {
+        return true;
+    }
+    KEYBOARD_ROWS
+        .iter()
+        .any(|row| match (row.find(prev), row.find(c)) {
+            (Some(a), Some(b)) => a.abs_diff(b) == 1,
+            _ => false,
+        })
+}
+
+// How many characters each position could be, from the kinds `password` uses
+fn charset_size(password: &str) -> u32 {
+    let mut size = 0;
+    if password.chars().any(|c| c.is_lowercase()) {
+        size += 26;
+    }
+    if password.chars().any(|c| c.is_uppercase()) {
+        size += 26;
+    }
+    if password.chars().any(|c| c.is_ascii_digit()) {
+        size += 10;
+    }
+    if password.chars().any(|c| !c.is_alphanumeric()) {
+        size += 33;
+    }
+    size.max(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn common_and_patterned_passwords_score_low() {
+        for password in [
+            "password",
+            "P@ssw0rd!",
+            "password123",
+            "123456",
+            "qwerty1",
+            "iloveyou",
+        ] {
+            let strength = estimate(password, &[]);
+            assert_eq!(strength.score, 0, "{}", password);
+            assert!(strength.feedback.unwrap_or_default().contains("common"));
+        }
+
+        let strength = estimate("abcdefgh", &[]);
+        assert!(strength.score < DEFAULT_MIN_SCORE);
+        assert!(strength.feedback.unwrap_or_default().contains("Sequences"));
+        assert!(estimate("zzzzzzzzz", &[]).score < DEFAULT_MIN_SCORE);
+        assert!(estimate("asdfgh1234", &[]).score < DEFAULT_MIN_SCORE);
+    }
+
+    #[test]
+    fn user_inputs_do_not_count() {
+        let inputs = ["margaret", "margaret.h@example.com"];
+        let strength = estimate("Margaret1", &inputs);
+        assert!(strength.score < DEFAULT_MIN_SCORE);
+        assert!(strength.feedback.unwrap_or_default().contains("username"));
+        assert!(estimate("Margaret1", &[]).score >= DEFAULT_MIN_SCORE);
+    }
+
+    #[test]
+    fn passphrases_score_high() {
+        let strength = estimate("correct horse battery staple", &[]);
+        assert_eq!(strength.score, MAX_SCORE);
+        assert_eq!(strength.feedback, None);
+        assert_eq!(estimate("Xk9#mQ2!vR", &[]).score, MAX_SCORE);
+    }
+}
@@ -0,0 +1,128 @@
+use chrono::Utc;
+use rustapi_rs::prelude::*;
+use rustapi_rs::Cookies;
+
+use crate::{cookies::CookieConfig, csrf, models::Claims, responses, AppState};
+
+/// Cookie marking a login whose password was confirmed recently
+pub const SUDO_COOKIE: &str = "sudo";
+
+/// How long a password confirmation lasts unless `SUDO_TTL_SECS` overrides
+/// it (10 minutes)
+pub const DEFAULT_SUDO_TTL_SECS: i64 = 10 * 60;
+
+/// Page asking for the password again
+pub const PROMPT_PATH: &str = "/sudo";
+
+/// Where the prompt leads when it was given no usable `next`
+pub const DEFAULT_NEXT: &str = "/profile";
+
+/// `Set-Cookie` value elevating the login `claims` belong to for `ttl_secs`
+///
+/// The cookie names the user and the login's `session_exp`, so it is worth
+/// nothing to another account or after logging in again.
+pub fn cookie(config: &CookieConfig, secret: &str, claims: &Claims, ttl_secs: i64) -> String {
+    let expires_at = Utc::now().timestamp() + ttl_secs;
+    let value = format!("{}:{}:{}", claims.sub, claims.session_exp, expires_at);
+    config.header(
+        SUDO_COOKIE,
+        &csrf::sign_token(secret, &value),
+        Some(ttl_secs),
+    )
+}
+
+/// Whether the request's sudo cookie was issued to the login `claims`
+/// belong to and has not expired
+pub fn is_elevated(secret: &str, cookies: &Cookies, claims: &Claims) -> bool {
+    let Some(value) = cookies
+        .get(SUDO_COOKIE)
+        .and_then(|cookie| csrf::verify_signed(secret, cookie.value()))
+    else {
+        return false;
+    };
+    let parts: Vec<i64> = value.split(':').filter_map(|p| p.parse().ok()).collect();
+    matches!(
+        parts.as_slice(),
+        [sub, session_exp, expires_at]
+            if *sub == claims.sub
+                && *session_exp == claims.session_exp
+                && *expires_at > Utc::now().timestamp()
+    )
+}
+
+/// Redirect to the password prompt, which comes back to `next` afterwards
+///
+/// `next` is put into the URL as is, so it must be a plain path.
+pub fn prompt(state: &AppState, next: &str) -> Response {
+    responses::redirect_to(state, &format!("{}?next={}", PROMPT_PATH, next))
+}
+
+/// `next` if it is a path on this site, so the prompt cannot be used to
+/// send users elsewhere
+pub fn safe_next(next: Option<&str>) -> &str {
+    match next {
+        Some(next) if next.starts_with('/') && !next.starts_with("//") && !next.contains('\\') => {
+            next
+        }
+        _ => DEFAULT_NEXT,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Role;
+    use crate::test_utils::cookies_from;
+
+    fn claims(sub: i64, session_exp: i64) -> Claims {
+        Claims {
+            sub,
+            username: "alice".to_string(),
+            exp: session_exp,
+            iat: 0,
+            token_version: 0,
+            role: Role::User,
+            session_exp,
+        }
+    }
+
+    fn cookies_with(set_cookie: &str) -> Cookies {
+        let value = set_cookie
+            .split(';')
+            .next()
+            .and_then(|pair| pair.split_once('='))
+            .map(|(_, value)| value)
+            .unwrap_or_default();
+        cookies_from(&[(SUDO_COOKIE, value)]).0
+    }
+
+    #[test]
+    fn elevation_belongs_to_one_login() {
+        let config = CookieConfig::default();
+        let login = claims(7, 1_000);
+        let cookies = cookies_with(&cookie(&config, "secret", &login, 60));
+
+        assert!(is_elevated("secret", &cookies, &login));
+        assert!(!is_elevated("secret", &cookies, &claims(8, 1_000)));
+        assert!(!is_elevated("secret", &cookies, &claims(7, 2_000)));
+        assert!(!is_elevated("other-secret", &cookies, &login));
+        assert!(!is_elevated("secret", &cookies_from(&[]).0, &login));
+    }
+
+    #[test]
+    fn elevation_expires() {
+        let config = CookieConfig::default();
+        let login = claims(7, 1_000);
+        let cookies = cookies_with(&cookie(&config, "secret", &login, -1));
+        assert!(!is_elevated("secret", &cookies, &login));
+    }
+
+    #[test]
+    fn next_stays_on_the_site() {
+        assert_eq!(safe_next(Some("/profile?tab=keys")), "/profile?tab=keys");
+        assert_eq!(safe_next(Some("//evil.example")), DEFAULT_NEXT);
+        assert_eq!(safe_next(Some("https://evil.example")), DEFAULT_NEXT);
+        assert_eq!(safe_next(Some("/\\evil.example")), DEFAULT_NEXT);
+        assert_eq!(safe_next(None), DEFAULT_NEXT);
+    }
+}
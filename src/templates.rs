@@ -0,0 +1,429 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use rustapi_rs::prelude::*;
+use std::{collections::HashMap, sync::RwLock};
+use tera::{Context, Tera, Value};
+
+use crate::{i18n, models::UserInfo, AppState};
+
+/// Templates loaded at startup
+pub const TEMPLATE_GLOB: &str = "templates/**/*.html";
+
+/// Every template the handlers and middleware render, checked by
+/// `check_required` at startup
+///
+/// Add a page here when a handler starts rendering it.
+pub const REQUIRED_TEMPLATES: &[&str] = &[
+    "base.html",
+    "index.html",
+    "404.html",
+    "500.html",
+    "maintenance.html",
+    "timeout.html",
+    "stats.html",
+    "profile.html",
+    "auth/login.html",
+    "auth/register.html",
+    "auth/sudo.html",
+    "items/list.html",
+    "items/form.html",
+    "items/detail.html",
+    "items/attachments.html",
+    "items/comments.html",
+    "items/overdue.html",
+    "items/shared.html",
+    "items/trash.html",
+    "admin/users.html",
+    "admin/items.html",
+    "admin/invites.html",
+    "admin/audit.html",
+];
+
+/// Zone pages show times in for anonymous visitors and new users
+pub const DEFAULT_TIMEZONE: &str = "UTC";
+
+/// How `local_time` formats a timestamp unless given a `format`
+pub const DEFAULT_TIME_FORMAT: &str = "%b %d, %Y %H:%M";
+
+/// Parse the templates matching `glob`, refusing any that Tera would render
+/// without escaping
+///
+/// Usernames, item fields, comments and error messages all reach the
+/// templates, and none of them marks a value `| safe`. The Swagger UI page in
+/// `openapi.rs` is the only HTML served without Tera; it holds no user input.
+pub fn load(glob: &str) -> Result<Tera, Box<dyn std::error::Error + Send + Sync>> {
+    let mut tera = Tera::new(glob)?;
+    check_autoescape(&tera)?;
+    tera.register_filter("local_time", local_time);
+    Ok(tera)
+}
+
+/// Whether `name` is a zone in the IANA database, e.g. `Europe/Berlin`
+pub fn is_valid_timezone(name: &str) -> bool {
+    name.parse::<Tz>().is_ok()
+}
+
+/// Tera filter showing a UTC timestamp in the zone named by `tz`
+///
+/// `{{ item.created_at | local_time(tz=timezone) }}` formats with
+/// `DEFAULT_TIME_FORMAT` unless a `format` is given; a missing or unknown
+/// zone shows UTC.
+fn local_time(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let timestamp = value
+        .as_str()
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .ok_or_else(|| tera::Error::msg(format!("local_time expects a timestamp, got {}", value)))?
+        .with_timezone(&Utc);
+    let tz = args
+        .get("tz")
+        .and_then(Value::as_str)
+        .and_then(|name| name.parse::<Tz>().ok())
+        .unwrap_or(Tz::UTC);
+    let format = args
+        .get("format")
+        .and_then(Value::as_str)
+        .unwrap_or(DEFAULT_TIME_FORMAT);
+
+    Ok(Value::String(
+        timestamp.with_timezone(&tz).format(format).to_string(),
+    ))
+}
+
+/// The parsed templates, optionally re-read from disk before every render
+///
+/// With `autoreload` off, as in production, templates are parsed once at
+/// startup. With it on, template edits show up on the next page load without
+/// a restart, at the cost of re-parsing every template per render.
+pub struct Templates {
+    tera: RwLock<Tera>,
+    autoreload: bool,
+}
+
+impl Templates {
+    pub fn new(tera: Tera, autoreload: bool) -> Self {
+        Self {
+            tera: RwLock::new(tera),
+            autoreload,
+        }
+    }
+
+    /// Re-read every template from the glob they were loaded from
+    ///
+    /// The current templates are kept if any of the new ones fail to parse or
+    /// would render unescaped.
+    pub fn reload(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut fresh = self.tera.read().unwrap_or_else(|e| e.into_inner()).clone();
+        fresh.full_reload()?;
+        check_autoescape(&fresh)?;
+        *self.tera.write().unwrap_or_else(|e| e.into_inner()) = fresh;
+        Ok(())
+    }
+
+    pub fn render(
+        &self,
+        template: &str,
+        context: &Context,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        if self.autoreload {
+            self.reload()?;
+        }
+        let tera = self.tera.read().unwrap_or_else(|e| e.into_inner());
+        Ok(tera.render(template, context)?)
+    }
+}
+
+/// Render a page, seeding the context with `user`: the logged-in user, or
+/// `None` on anonymous pages
+///
+/// The layout's navigation reads `user` on every page, so handlers pass it
+/// here instead of inserting it themselves. `timezone` is the user's zone,
+/// for the `local_time` filter, and `registration` the configured
+/// `RegistrationMode`, so closed registration leaves no links to it;
+/// `base_path` prefixes the site's own links, `item_limits` sets the
+/// item form's `maxlength`s, and `locale` is the request's, as `t` translates
+/// to. A page that fails to render is answered with `error_page`.
+pub fn render(
+    state: &AppState,
+    template: &str,
+    user: Option<&UserInfo>,
+    context: Context,
+) -> Response {
+    match state
+        .templates
+        .render(template, &page_context(state, user, context))
+    {
+        Ok(html) => Html(html).into_response(),
+        Err(e) => {
+            tracing::error!(error = %e, template, "template error");
+            error_page(state, StatusCode::INTERNAL_SERVER_ERROR, user)
+        }
+    }
+}
+
+/// The `404.html` page for a 404, and `500.html` for any other status,
+/// answered with `status`
+///
+/// When the error page itself fails to render, the status text is sent as
+/// plain text instead; nothing here goes back through `render`, so a broken
+/// layout cannot loop.
+pub fn error_page(state: &AppState, status: StatusCode, user: Option<&UserInfo>) -> Response {
+    let template = if status == StatusCode::NOT_FOUND {
+        "404.html"
+    } else {
+        "500.html"
+    };
+    let context = page_context(state, user, Context::new());
+    match state.templates.render(template, &context) {
+        Ok(html) => (status, Html(html)).into_response(),
+        Err(e) => {
+            tracing::error!(error = %e, template, "error page failed to render");
+            (status, status.canonical_reason().unwrap_or("Error")).into_response()
+        }
+    }
+}
+
+// What every page finds in its context next to the handler's own values
+fn page_context(state: &AppState, user: Option<&UserInfo>, mut context: Context) -> Context {
+    context.insert("user", &user);
+    context.insert("locale", &i18n::current_locale());
+    context.insert(
+        "timezone",
+        user.map_or(DEFAULT_TIMEZONE, |user| user.timezone.as_str()),
+    );
+    context.insert("registration", &state.config.registration);
+    context.insert("base_path", state.config.base_path.as_str());
+    context.insert("item_limits", &state.config.item_limits);
+    context
+}
+
+/// Fail unless every registered template is autoescaped
+pub fn check_autoescape(tera: &Tera) -> Result<(), String> {
+    let mut unescaped: Vec<&str> = tera
+        .get_template_names()
+        .filter(|name| {
+            !tera
+                .autoescape_suffixes
+                .iter()
+                .any(|suffix| name.ends_with(suffix))
+        })
+        .collect();
+
+    if unescaped.is_empty() {
+        return Ok(());
+    }
+    unescaped.sort_unstable();
+    Err(format!(
+        "autoescaping is disabled for templates: {}",
+        unescaped.join(", ")
+    ))
+}
+
+/// Fail unless every one of `required` was loaded, naming all that are
+/// missing
+///
+/// Run at startup, so a template left out of a deployment stops the server
+/// instead of turning its pages into 500s.
+pub fn check_required(tera: &Tera, required: &[&str]) -> Result<(), String> {
+    let loaded: Vec<&str> = tera.get_template_names().collect();
+    let missing: Vec<&str> = required
+        .iter()
+        .copied()
+        .filter(|name| !loaded.contains(name))
+        .collect();
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+    Err(format!("missing templates: {}", missing.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base_path::BasePath;
+    use crate::test_utils::{body_string, cleanup_db, setup_page_state, setup_test_state};
+    use std::{fs, path::Path, sync::Arc};
+
+    fn write_page(dir: &Path, body: &str) {
+        fs::write(dir.join("page.html"), body).expect("write template");
+    }
+
+    #[test]
+    fn page_templates_load_with_autoescape() {
+        let tera = load(TEMPLATE_GLOB).expect("load templates");
+        assert!(tera.get_template_names().any(|name| name == "base.html"));
+        assert_eq!(check_required(&tera, REQUIRED_TEMPLATES), Ok(()));
+    }
+
+    #[test]
+    fn check_required_names_every_missing_template() {
+        let mut tera = Tera::default();
+        for name in REQUIRED_TEMPLATES {
+            if !["auth/login.html", "500.html"].contains(name) {
+                tera.add_raw_template(name, "").expect("add template");
+            }
+        }
+        assert_eq!(
+            check_required(&tera, REQUIRED_TEMPLATES),
+            Err("missing templates: 500.html, auth/login.html".to_string())
+        );
+
+        tera.add_raw_template("500.html", "").expect("add template");
+        tera.add_raw_template("auth/login.html", "")
+            .expect("add template");
+        assert_eq!(check_required(&tera, REQUIRED_TEMPLATES), Ok(()));
+    }
+
+    #[test]
+    fn check_autoescape_reports_unescaped_templates() {
+        let mut tera = Tera::default();
+        tera.add_raw_template("page.html", "{{ name }}")
+            .expect("add template");
+        assert_eq!(check_autoescape(&tera), Ok(()));
+
+        tera.autoescape_on(vec![]);
+        assert_eq!(
+            check_autoescape(&tera),
+            Err("autoescaping is disabled for templates: page.html".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn render_seeds_the_user() {
+        let (state, path) = setup_test_state().await;
+        let user = state
+            .db
+            .create_user("nav", "nav@example.com", "hash")
+            .await
+            .expect("create user");
+        let user = UserInfo::from(user);
+
+        let response = render(&state, "index.html", Some(&user), Context::new());
+        assert_eq!(body_string(response).await, "HOME nav");
+        let response = render(&state, "index.html", None, Context::new());
+        assert_eq!(body_string(response).await, "HOME anonymous");
+
+        let response = render(&state, "missing.html", None, Context::new());
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body_string(response).await, "ERROR 500 anonymous");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn error_pages_fall_back_to_plain_text() {
+        let (mut state, path) = setup_test_state().await;
+        let response = error_page(&state, StatusCode::NOT_FOUND, None);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body_string(response).await, "NOT FOUND anonymous");
+
+        // Without error pages to fall back on, a failed render still answers
+        state.templates = Arc::new(Templates::new(Tera::default(), false));
+        let response = render(&state, "index.html", None, Context::new());
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body_string(response).await, "Internal Server Error");
+        let response = error_page(&state, StatusCode::NOT_FOUND, None);
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(body_string(response).await, "Not Found");
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn page_links_carry_the_base_path() {
+        let (mut state, path) = setup_page_state().await;
+        Arc::make_mut(&mut state.config).base_path = BasePath::parse("/app").expect("base path");
+
+        let mut context = Context::new();
+        context.insert("csrf_token", "token");
+        let response = render(&state, "auth/login.html", None, context);
+        let body = body_string(response).await;
+        assert!(body.contains(r#"data-base-path="&#x2F;app""#));
+        assert!(body.contains(r#"action="&#x2F;app/login""#));
+        assert!(body.contains(r#"href="&#x2F;app/static/css/style.css"#));
+        assert!(!body.contains(r#"href="/"#) && !body.contains(r#"action="/"#));
+        cleanup_db(path);
+    }
+
+    #[tokio::test]
+    async fn pages_are_rendered_in_the_request_locale() {
+        let (state, path) = setup_page_state().await;
+        let page = || {
+            let mut context = Context::new();
+            context.insert("csrf_token", "token");
+            render(&state, "auth/login.html", None, context)
+        };
+
+        let body = body_string(page()).await;
+        assert!(body.contains(r#"<html lang="en">"#));
+        assert!(body.contains(r#"class="nav-link">Login</a>"#));
+        let body = body_string(crate::i18n::scope("fr".to_string(), async { page() }).await).await;
+        assert!(body.contains(r#"<html lang="fr">"#));
+        assert!(body.contains(r#"class="nav-link">Connexion</a>"#));
+        cleanup_db(path);
+    }
+
+    #[test]
+    fn local_time_shows_the_instant_in_the_given_zone() {
+        let mut tera = Tera::default();
+        tera.register_filter("local_time", local_time);
+        tera.add_raw_template("at.html", "{{ at | local_time(tz=tz) }}")
+            .expect("add template");
+        tera.add_raw_template(
+            "day.html",
+            "{{ at | local_time(tz=tz, format='%Y-%m-%d') }}",
+        )
+        .expect("add template");
+        let show = |template: &str, at: &str, tz: &str| {
+            let mut context = Context::new();
+            context.insert("at", &at.parse::<DateTime<Utc>>().expect("timestamp"));
+            context.insert("tz", tz);
+            tera.render(template, &context).expect("render")
+        };
+
+        let winter = "2024-01-15T12:00:00Z";
+        assert_eq!(show("at.html", winter, "UTC"), "Jan 15, 2024 12:00");
+        assert_eq!(
+            show("at.html", winter, "America/New_York"),
+            "Jan 15, 2024 07:00"
+        );
+        assert_eq!(
+            show("at.html", winter, "Asia/Kolkata"),
+            "Jan 15, 2024 17:30"
+        );
+        // Daylight saving time applies in summer
+        let summer = "2024-07-01T12:00:00Z";
+        assert_eq!(
+            show("at.html", summer, "Europe/Berlin"),
+            "Jul 01, 2024 14:00"
+        );
+        assert_eq!(
+            show("day.html", "2024-07-01T23:30:00Z", "Asia/Tokyo"),
+            "2024-07-02"
+        );
+        assert_eq!(show("at.html", winter, "Not/A_Zone"), "Jan 15, 2024 12:00");
+        assert!(is_valid_timezone("Europe/Berlin"));
+        assert!(!is_valid_timezone("Not/A_Zone"));
+    }
+
+    #[test]
+    fn autoreload_picks_up_edited_templates() {
+        let dir = std::env::temp_dir().join(format!("templates_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("create template dir");
+        write_page(&dir, "before");
+        let glob = format!("{}/*.html", dir.display());
+        let reloading = Templates::new(load(&glob).expect("load"), true);
+        let fixed = Templates::new(load(&glob).expect("load"), false);
+        let context = Context::new();
+
+        write_page(&dir, "after {{ missing");
+        assert!(reloading.render("page.html", &context).is_err());
+        // A broken edit does not replace the templates that last loaded
+        reloading.reload().expect_err("parse error");
+        write_page(&dir, "after");
+        assert_eq!(reloading.render("page.html", &context).unwrap(), "after");
+        assert_eq!(fixed.render("page.html", &context).unwrap(), "before");
+
+        fixed.reload().expect("reload");
+        assert_eq!(fixed.render("page.html", &context).unwrap(), "after");
+        fs::remove_dir_all(dir).ok();
+    }
+}
@@ -2,20 +2,54 @@
 use std::{
     path::PathBuf,
     sync::Arc,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+#[cfg(test)]
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
 #[cfg(test)]
 use cookie::{Cookie, CookieJar};
 #[cfg(test)]
+use http_body_util::BodyExt;
+#[cfg(test)]
 use jsonwebtoken::{encode, EncodingKey, Header};
 #[cfg(test)]
-use rustapi_rs::{Cookies, Response};
+use ring::{
+    rand::{SecureRandom, SystemRandom},
+    signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+};
+#[cfg(test)]
+use rustapi_rs::{path_params::PathParams, BodyVariant, Cookies, Request, Response, StatusCode};
+#[cfg(test)]
+use sha2::{Digest, Sha256};
 #[cfg(test)]
 use tera::Tera;
 
 #[cfg(test)]
-use crate::{db::Database, extractors::AppCookies, models::Claims, AppState};
+use crate::{
+    config::Config,
+    cookies::CookieConfig,
+    db::Database,
+    events::ItemEvents,
+    extractors::{AccessToken, AppCookies, CsrfProtected, CsrfToken, RealIp},
+    flash::{Flash, FLASH_COOKIE},
+    i18n::{self, Messages},
+    jwt::{JwtKeys, DEFAULT_ALGORITHM, DEFAULT_KEY_ID},
+    maintenance::Maintenance,
+    metrics::Metrics,
+    models::{Claims, PasskeyAssertion, PasskeyRegistration, Role},
+    pwned::RangeSource,
+    rate_limit::{LoginRateLimiter, LoginThrottle},
+    shutdown::InFlight,
+    templates::{self, Templates},
+    uploads::{UploadConfig, DEFAULT_ALLOWED_TYPES},
+    webauthn::{self, WebAuthn},
+    AppState,
+};
+
+/// Per-file upload limit in test states, small enough to exceed cheaply
+#[cfg(test)]
+pub const TEST_MAX_UPLOAD_BYTES: usize = 1024;
 
 #[cfg(test)]
 pub async fn setup_test_state() -> (AppState, PathBuf) {
@@ -29,51 +63,446 @@ pub async fn setup_test_state() -> (AppState, PathBuf) {
     let url = format!("sqlite:{}?mode=rwc", path.display());
     let db = Database::new(&url).await.expect("create test db");
 
+    let messages = Arc::new(Messages::load(i18n::LOCALE_DIR).expect("load message catalogs"));
     let mut tera = Tera::default();
+    i18n::register(&mut tera, messages.clone());
     add_test_templates(&mut tera);
 
     let state = AppState {
         db,
-        tera: Arc::new(tera),
-        jwt_secret: "test-secret".to_string(),
+        templates: Arc::new(Templates::new(tera, false)),
+        messages,
+        jwt_keys: Arc::new(test_jwt_keys("test-secret")),
+        login_limiter: Arc::new(LoginRateLimiter::new(5, Duration::from_secs(900))),
+        login_throttle: Arc::new(LoginThrottle::new(
+            Duration::ZERO,
+            Duration::ZERO,
+            Duration::from_secs(900),
+        )),
+        metrics: Arc::new(Metrics::new()),
+        in_flight: Arc::new(InFlight::default()),
+        maintenance: Arc::new(Maintenance::default()),
+        pwned_passwords: None,
+        item_events: Arc::new(ItemEvents::default()),
+        webauthn: Arc::new(WebAuthn::new(
+            webauthn::DEFAULT_RP_ID,
+            webauthn::DEFAULT_ORIGIN,
+        )),
+        config: Arc::new(Config {
+            jwt_secret: "test-secret".to_string(),
+            uploads: UploadConfig::new(
+                upload_dir_for(&path),
+                TEST_MAX_UPLOAD_BYTES,
+                DEFAULT_ALLOWED_TYPES,
+            ),
+            cookies: CookieConfig::default(),
+            ..Config::from_lookup(|_| None).unwrap_or_else(|e| panic!("{}", e))
+        }),
     };
 
     (state, path)
 }
 
+/// Access token keys signing with `secret` alone, under the default `kid`
+#[cfg(test)]
+pub fn test_jwt_keys(secret: &str) -> JwtKeys {
+    JwtKeys::with_rotation(DEFAULT_ALGORITHM, DEFAULT_KEY_ID, secret, &[]).expect("JWT keys")
+}
+
+/// Range lookups answered from memory, failing the test if anything longer
+/// than a five-digit hash prefix is sent
+#[cfg(test)]
+pub struct FakeRangeSource(Option<String>);
+
+#[cfg(test)]
+impl FakeRangeSource {
+    pub fn answering(range: &str) -> Self {
+        Self(Some(range.to_string()))
+    }
+
+    pub fn failing() -> Self {
+        Self(None)
+    }
+}
+
+#[cfg(test)]
+#[async_trait::async_trait]
+impl RangeSource for FakeRangeSource {
+    async fn fetch_range(
+        &self,
+        prefix: &str,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        assert!(
+            prefix.len() == 5 && prefix.bytes().all(|b| b.is_ascii_hexdigit()),
+            "range lookup sent {:?}",
+            prefix
+        );
+        self.0.clone().ok_or_else(|| "lookup failed".into())
+    }
+}
+
+/// Stand-in for a platform authenticator holding one ES256 passkey for
+/// `rp_id`, answering challenges the way a browser passes them on
+#[cfg(test)]
+pub struct TestAuthenticator {
+    rp_id: String,
+    key: EcdsaKeyPair,
+    credential_id: Vec<u8>,
+    /// Reported with the next login, then incremented; 0 for authenticators
+    /// that keep no counter
+    pub sign_count: u32,
+}
+
+#[cfg(test)]
+impl TestAuthenticator {
+    pub fn new(rp_id: &str) -> Self {
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+            .expect("generate key");
+        let key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+            .expect("parse key");
+        let mut credential_id = vec![0u8; 16];
+        rng.fill(&mut credential_id).expect("credential id");
+        Self {
+            rp_id: rp_id.to_string(),
+            key,
+            credential_id,
+            sign_count: 1,
+        }
+    }
+
+    pub fn credential_id(&self) -> String {
+        URL_SAFE_NO_PAD.encode(&self.credential_id)
+    }
+
+    /// The uncompressed public point, as stored
+    pub fn public_key(&self) -> String {
+        URL_SAFE_NO_PAD.encode(self.key.public_key().as_ref())
+    }
+
+    /// Create the passkey in answer to a registration `challenge`, as if on
+    /// a page of `origin`; new passkeys report a counter of 0
+    pub fn register(&self, challenge: &str, origin: &str) -> PasskeyRegistration {
+        let mut authenticator_data = self.authenticator_data(0x41, 0);
+        authenticator_data.extend_from_slice(&[0u8; 16]);
+        authenticator_data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+        authenticator_data.extend_from_slice(&self.credential_id);
+
+        // SubjectPublicKeyInfo of a P-256 key: a fixed header, then the point
+        let mut public_key = hex::decode("3059301306072a8648ce3d020106082a8648ce3d030107034200")
+            .expect("SPKI header");
+        public_key.extend_from_slice(self.key.public_key().as_ref());
+
+        PasskeyRegistration {
+            credential_id: self.credential_id(),
+            client_data: client_data("webauthn.create", challenge, origin),
+            authenticator_data: URL_SAFE_NO_PAD.encode(authenticator_data),
+            public_key: URL_SAFE_NO_PAD.encode(public_key),
+            public_key_algorithm: webauthn::ES256,
+            name: String::new(),
+        }
+    }
+
+    /// Sign a login `challenge`, as if on a page of `origin`
+    pub fn login(&mut self, challenge: &str, origin: &str) -> PasskeyAssertion {
+        let authenticator_data = self.authenticator_data(0x01, self.sign_count);
+        self.sign_count += 1;
+        let client_data = client_data("webauthn.get", challenge, origin);
+
+        let mut signed = authenticator_data.clone();
+        signed.extend_from_slice(&Sha256::digest(
+            URL_SAFE_NO_PAD.decode(&client_data).expect("client data"),
+        ));
+        let signature = self.key.sign(&SystemRandom::new(), &signed).expect("sign");
+
+        PasskeyAssertion {
+            credential_id: self.credential_id(),
+            client_data,
+            authenticator_data: URL_SAFE_NO_PAD.encode(authenticator_data),
+            signature: URL_SAFE_NO_PAD.encode(signature.as_ref()),
+            remember: false,
+        }
+    }
+
+    fn authenticator_data(&self, flags: u8, sign_count: u32) -> Vec<u8> {
+        let mut data = Sha256::digest(self.rp_id.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&sign_count.to_be_bytes());
+        data
+    }
+}
+
+#[cfg(test)]
+fn client_data(kind: &str, challenge: &str, origin: &str) -> String {
+    let data = serde_json::json!({
+        "type": kind,
+        "challenge": challenge,
+        "origin": origin,
+        "crossOrigin": false,
+    });
+    URL_SAFE_NO_PAD.encode(data.to_string())
+}
+
+/// Markup no page may render unescaped
+#[cfg(test)]
+pub const XSS_PAYLOAD: &str = "<script>alert(\"xss\")</script>";
+
+/// Like `setup_test_state`, but rendering the real page templates
+#[cfg(test)]
+pub async fn setup_page_state() -> (AppState, PathBuf) {
+    let (mut state, path) = setup_test_state().await;
+    let mut tera = templates::load(templates::TEMPLATE_GLOB).expect("load templates");
+    i18n::register(&mut tera, state.messages.clone());
+    state.templates = Arc::new(Templates::new(tera, false));
+    (state, path)
+}
+
+/// Render a response body and fail if `XSS_PAYLOAD` survived unescaped;
+/// returns the body for further checks
+#[cfg(test)]
+pub async fn assert_escaped(response: Response) -> String {
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = body_string(response).await;
+    assert!(
+        !body.contains(XSS_PAYLOAD),
+        "page renders user input unescaped:\n{}",
+        body
+    );
+    assert!(body.contains("&lt;script&gt;"), "payload missing from page");
+    body
+}
+
+/// Panic unless `xml` is well-formed: a single root, nested and closed
+/// tags, quoted attributes and only known or numeric entities
+#[cfg(test)]
+pub fn assert_well_formed(xml: &str) {
+    let body = xml
+        .strip_prefix("<?xml version=\"1.0\" encoding=\"UTF-8\"?>")
+        .expect("XML declaration");
+    let mut open: Vec<&str> = Vec::new();
+    let mut roots = 0;
+    let mut rest = body;
+    while let Some(start) = rest.find('<') {
+        check_text(&rest[..start]);
+        let end = rest[start..].find('>').expect("unclosed tag") + start;
+        let tag = &rest[start + 1..end];
+        if let Some(name) = tag.strip_prefix('/') {
+            assert_eq!(open.pop(), Some(name), "mismatched </{}>", name);
+        } else {
+            let (name, attributes) = tag.split_once(' ').unwrap_or((tag, ""));
+            assert!(
+                !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric()),
+                "bad tag <{}>",
+                tag
+            );
+            for attribute in attributes.split_whitespace() {
+                let (_, value) = attribute.split_once('=').expect("attribute value");
+                assert!(value.len() >= 2 && value.starts_with('"') && value.ends_with('"'));
+                check_text(&value[1..value.len() - 1]);
+            }
+            if open.is_empty() {
+                roots += 1;
+            }
+            open.push(name);
+        }
+        rest = &rest[end + 1..];
+    }
+    check_text(rest);
+    assert!(rest.trim().is_empty(), "text after the root");
+    assert!(open.is_empty(), "unclosed {:?}", open);
+    assert_eq!(roots, 1);
+}
+
+#[cfg(test)]
+fn check_text(text: &str) {
+    assert!(!text.contains('<') && !text.contains('>'));
+    let mut parts = text.split('&');
+    parts.next();
+    for part in parts {
+        let (entity, _) = part.split_once(';').expect("unterminated entity");
+        assert!(
+            matches!(entity, "amp" | "lt" | "gt" | "quot" | "apos")
+                || entity
+                    .strip_prefix('#')
+                    .is_some_and(|n| n.parse::<u32>().is_ok()),
+            "unknown entity &{};",
+            entity
+        );
+    }
+    assert!(!text
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r')));
+}
+
+/// Removes the test database and the state's upload directory
 #[cfg(test)]
 pub fn cleanup_db(path: PathBuf) {
+    let _ = std::fs::remove_dir_all(upload_dir_for(&path));
+    for suffix in ["-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+    }
     let _ = std::fs::remove_file(path);
 }
 
+// Each test state stores uploads next to its database file
+#[cfg(test)]
+fn upload_dir_for(db_path: &std::path::Path) -> PathBuf {
+    db_path.with_extension("uploads")
+}
+
 #[cfg(test)]
 pub fn empty_cookies() -> AppCookies {
     AppCookies(Cookies(CookieJar::new()))
 }
 
+/// CSRF token as issued for a rendered form
 #[cfg(test)]
-pub fn cookies_for_user(secret: &str, user_id: i64, username: &str) -> AppCookies {
+pub fn test_csrf_token() -> CsrfToken {
+    CsrfToken("test-csrf".to_string())
+}
+
+/// CSRF check that already passed, for calling POST handlers directly
+#[cfg(test)]
+pub fn test_csrf() -> CsrfProtected {
+    CsrfProtected("test-csrf".to_string())
+}
+
+/// Client address handlers see for requests that came straight from
+/// loopback
+#[cfg(test)]
+pub fn test_client_ip() -> RealIp {
+    RealIp(std::net::Ipv4Addr::LOCALHOST.into())
+}
+
+#[cfg(test)]
+pub fn token_for_user(secret: &str, user_id: i64, username: &str, ttl_secs: i64) -> String {
     let now = chrono::Utc::now().timestamp();
     let claims = Claims {
         sub: user_id,
         username: username.to_string(),
-        exp: now + 3600,
+        exp: now + ttl_secs,
         iat: now,
+        token_version: 0,
+        role: Role::User,
+        session_exp: now + ttl_secs,
     };
 
-    let token = encode(
+    encode(
         &Header::default(),
         &claims,
         &EncodingKey::from_secret(secret.as_bytes()),
     )
-    .expect("encode token");
+    .expect("encode token")
+}
 
+/// Access token a request from the given user would carry
+#[cfg(test)]
+pub fn access_token_for_user(secret: &str, user_id: i64, username: &str) -> AccessToken {
+    AccessToken(Some(token_for_user(secret, user_id, username, 3600)))
+}
+
+/// Cookie jar holding the given `(name, value)` pairs
+#[cfg(test)]
+pub fn cookies_from(pairs: &[(&str, &str)]) -> AppCookies {
     let mut jar = CookieJar::new();
-    jar.add(Cookie::new("token", token));
+    for (name, value) in pairs {
+        jar.add(Cookie::new(name.to_string(), value.to_string()));
+    }
 
     AppCookies(Cookies(jar))
 }
 
+/// Value of the named cookie among a response's `Set-Cookie` headers
+#[cfg(test)]
+pub fn set_cookie_value(response: &Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get_all("Set-Cookie")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .filter_map(|v| v.split(';').next())
+        .find_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            (key == name).then(|| value.to_string())
+        })
+}
+
+/// Flash message a response leaves for the next page
+#[cfg(test)]
+pub fn flash_from(state: &AppState, response: &Response) -> Option<Flash> {
+    Flash::decode(
+        &state.config.jwt_secret,
+        &set_cookie_value(response, FLASH_COOKIE)?,
+    )
+}
+
+/// Build a framework request carrying `state`, as the router would
+#[cfg(test)]
+pub fn request_with_state(
+    state: &AppState,
+    builder: http::request::Builder,
+    body: &str,
+) -> Request {
+    let (parts, _) = builder.body(()).expect("build request").into_parts();
+    let mut extensions = http::Extensions::new();
+    extensions.insert(state.clone());
+
+    Request::new(
+        parts,
+        BodyVariant::Buffered(bytes::Bytes::from(body.to_string())),
+        Arc::new(extensions),
+        PathParams::new(),
+    )
+}
+
+/// Framework request with a `multipart/form-data` body holding the text
+/// `fields` and one `file` part of `(field, filename, content_type, bytes)`
+#[cfg(test)]
+pub fn multipart_request(
+    state: &AppState,
+    builder: http::request::Builder,
+    fields: &[(&str, &str)],
+    file: (&str, &str, &str, &[u8]),
+) -> Request {
+    const BOUNDARY: &str = "test-boundary-7MA4YWxkTrZu0gW";
+
+    let mut body = Vec::new();
+    for (name, value) in fields {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                BOUNDARY, name, value
+            )
+            .as_bytes(),
+        );
+    }
+    let (field, filename, content_type, bytes) = file;
+    body.extend_from_slice(
+        format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+            BOUNDARY, field, filename, content_type
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(bytes);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", BOUNDARY).as_bytes());
+
+    let builder = builder.header(
+        "Content-Type",
+        format!("multipart/form-data; boundary={}", BOUNDARY),
+    );
+    let (parts, _) = builder.body(()).expect("build request").into_parts();
+    let mut extensions = http::Extensions::new();
+    extensions.insert(state.clone());
+
+    Request::new(
+        parts,
+        BodyVariant::Buffered(bytes::Bytes::from(body)),
+        Arc::new(extensions),
+        PathParams::new(),
+    )
+}
+
 #[cfg(test)]
 pub fn header_value(response: &Response, name: &str) -> Option<String> {
     response
@@ -83,16 +512,112 @@ pub fn header_value(response: &Response, name: &str) -> Option<String> {
         .map(|s| s.to_string())
 }
 
+#[cfg(test)]
+pub async fn body_string(response: Response) -> String {
+    let bytes = response
+        .into_body()
+        .collect()
+        .await
+        .expect("collect body")
+        .to_bytes();
+    String::from_utf8(bytes.to_vec()).expect("utf-8 body")
+}
+
 #[cfg(test)]
 fn add_test_templates(tera: &mut Tera) {
-    tera.add_raw_template("index.html", "HOME")
-        .expect("add index template");
-    tera.add_raw_template("auth/login.html", "LOGIN")
+    tera.add_raw_template(
+        "index.html",
+        "HOME {% if user %}{{ user.username }}{% else %}anonymous{% endif %}{% if item_count is defined %} items={{ item_count }}{% endif %}{% if latest_item %} latest={{ latest_item.title }}{% endif %}",
+    )
+    .expect("add index template");
+    tera.add_raw_template("auth/login.html", "LOGIN {{ error | default(value='') }}")
         .expect("add login template");
-    tera.add_raw_template("auth/register.html", "REGISTER")
+    tera.add_raw_template(
+        "auth/sudo.html",
+        "SUDO {{ error | default(value='') }} next={{ next }}",
+    )
+    .expect("add sudo template");
+    tera.add_raw_template(
+        "auth/register.html",
+        "REGISTER {{ error | default(value='') }} closed={{ closed | default(value=false) }} invite_required={{ invite_required | default(value=false) }} errors.username={{ errors.username | default(value=[]) | join(sep=',') }} errors.email={{ errors.email | default(value=[]) | join(sep=',') }}",
+    )
         .expect("add register template");
-    tera.add_raw_template("items/list.html", "ITEMS LIST")
-        .expect("add items list template");
-    tera.add_raw_template("items/form.html", "ITEMS FORM")
-        .expect("add items form template");
+    tera.add_raw_template(
+        "items/list.html",
+        "ITEMS LIST success={{ success | default(value='') }}; page {{ pagination.page }} of {{ total_pages }} tag={{ tag }} q={{ q }} items={{ items | length }} overdue={{ overdue_ids | length }} sort={{ sort }} order={{ order }}",
+    )
+    .expect("add items list template");
+    tera.add_raw_template(
+        "items/form.html",
+        "ITEMS FORM errors.title={{ errors.title | default(value=[]) | join(sep=',') }} error={{ error | default(value='') }}",
+    )
+    .expect("add items form template");
+    tera.add_raw_template(
+        "items/detail.html",
+        "ITEM DETAIL {{ item.title }} tags={{ item.tags | length }} attachments={{ attachments | length }} comments={{ comments | length }} of {{ comment_count }}",
+    )
+    .expect("add item detail template");
+    tera.add_raw_template(
+        "items/shared.html",
+        "SHARED ITEM {{ title }} tags={% for tag in tags %}{{ tag }},{% endfor %}",
+    )
+    .expect("add shared item template");
+    tera.add_raw_template("items/trash.html", "ITEMS TRASH {{ items | length }}")
+        .expect("add items trash template");
+    tera.add_raw_template(
+        "items/overdue.html",
+        "ITEMS OVERDUE {% for item in items %}{{ item.title }},{% endfor %}",
+    )
+    .expect("add overdue items template");
+    tera.add_raw_template(
+        "items/attachments.html",
+        "ITEM ATTACHMENTS {{ attachments | length }}",
+    )
+    .expect("add item attachments template");
+    tera.add_raw_template(
+        "items/comments.html",
+        "ITEM COMMENTS page {{ pagination.page }} of {{ total_pages }} q={{ q }} comments={{ comments | length }} error={{ error | default(value='') }} errors.body={{ errors.body | default(value=[]) | join(sep=',') }}",
+    )
+    .expect("add item comments template");
+    tera.add_raw_template(
+        "profile.html",
+        "PROFILE {{ user.username }} {{ error | default(value='') }} new_api_key={{ new_api_key | default(value='') }} api_keys={% for key in api_keys | default(value=[]) %}{{ key.name }},{% endfor %} passkeys={% for passkey in passkeys | default(value=[]) %}{{ passkey.name }},{% endfor %} pending_email={{ pending_email | default(value='') }} logins={% for login in recent_logins | default(value=[]) %}{{ login.ip_address }},{% endfor %} shared={% for shared in shared_items | default(value=[]) %}{{ share_base_url }}{{ shared.share_slug }},{% endfor %} feed={% if feed_token_created_at %}on{% else %}off{% endif %} new_feed_url={{ new_feed_url | default(value='') }}",
+    )
+    .expect("add profile template");
+    tera.add_raw_template(
+        "admin/users.html",
+        "ADMIN USERS {{ users | length }} maintenance={{ maintenance }}",
+    )
+    .expect("add admin users template");
+    tera.add_raw_template("admin/items.html", "ADMIN ITEMS {{ items | length }}")
+        .expect("add admin items template");
+    tera.add_raw_template(
+        "admin/audit.html",
+        "ADMIN AUDIT {{ item_id }} {% for entry in entries %}{{ entry.action }},{% endfor %}",
+    )
+    .expect("add admin audit template");
+    tera.add_raw_template(
+        "admin/invites.html",
+        "ADMIN INVITES {{ invites | length }} new_invite_code={{ new_invite_code | default(value='') }}",
+    )
+    .expect("add admin invites template");
+    tera.add_raw_template("maintenance.html", "MAINTENANCE")
+        .expect("add maintenance template");
+    tera.add_raw_template("timeout.html", "TIMEOUT")
+        .expect("add timeout template");
+    tera.add_raw_template(
+        "404.html",
+        "NOT FOUND {% if user %}{{ user.username }}{% else %}anonymous{% endif %}",
+    )
+    .expect("add 404 template");
+    tera.add_raw_template(
+        "500.html",
+        "ERROR 500 {% if user %}{{ user.username }}{% else %}anonymous{% endif %}",
+    )
+    .expect("add 500 template");
+    tera.add_raw_template(
+        "stats.html",
+        "STATS total={{ total | default(value=0) }} days={{ days | default(value=[]) | length }}",
+    )
+    .expect("add stats template");
 }
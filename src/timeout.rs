@@ -0,0 +1,24 @@
+use std::time::Duration;
+
+/// How long a request may take unless `REQUEST_TIMEOUT_SECS` overrides it
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long routes marked `long_timeout` in `route_limits::ROUTE_OVERRIDES`
+/// may take unless `LONG_REQUEST_TIMEOUT_SECS` overrides it (5 minutes)
+pub const DEFAULT_LONG_REQUEST_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Deadlines requests are answered by, in place of hanging on a slow query
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestTimeouts {
+    pub default: Duration,
+    pub long: Duration,
+}
+
+impl Default for RequestTimeouts {
+    fn default() -> Self {
+        Self {
+            default: DEFAULT_REQUEST_TIMEOUT,
+            long: DEFAULT_LONG_REQUEST_TIMEOUT,
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{DateTime, Utc};
+use rustapi_rs::Cookies;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    cookies::CookieConfig,
+    jwt::JwtKeys,
+    models::{Claims, Role},
+};
+
+/// Cookie holding the short-lived JWT access token
+pub const ACCESS_COOKIE: &str = "token";
+
+/// Cookie holding the opaque refresh token
+pub const REFRESH_COOKIE: &str = "refresh_token";
+
+/// Header services send an API key in
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+/// Access token lifetime (15 minutes)
+pub const ACCESS_TOKEN_TTL_SECS: i64 = 15 * 60;
+
+/// Refresh token lifetime of a login without "remember me" (24 hours)
+pub const SESSION_TTL_SECS: i64 = 24 * 60 * 60;
+
+/// Refresh token lifetime of a login with "remember me" (30 days)
+pub const REMEMBER_ME_TTL_SECS: i64 = 30 * 24 * 60 * 60;
+
+/// Remaining access token lifetime below which an authenticated request
+/// renews the cookie (5 minutes)
+pub const SLIDING_WINDOW_SECS: i64 = 5 * 60;
+
+/// Longest a login lasts, however active, before the password is needed
+/// again (30 days)
+pub const MAX_SESSION_LIFETIME_SECS: i64 = 30 * 24 * 60 * 60;
+
+/// Email verification link lifetime (24 hours)
+pub const VERIFICATION_TOKEN_TTL_SECS: i64 = 24 * 60 * 60;
+
+/// How long the link confirming a new email address works (24 hours)
+pub const EMAIL_CHANGE_TTL_SECS: i64 = 24 * 60 * 60;
+
+/// How long an item invitation can be accepted unless
+/// `INVITATION_TTL_SECS` overrides it (7 days)
+pub const INVITATION_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+
+/// How long a login lasts before the user must enter their password again
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// Without "remember me"; the refresh cookie is also dropped when the
+    /// browser closes
+    pub session_ttl_secs: i64,
+    /// With "remember me"; the refresh cookie persists for as long
+    pub remember_ttl_secs: i64,
+    /// Requests made while the access token has less than this left get a
+    /// renewed one, so active users are not logged out mid-session
+    pub sliding_window_secs: i64,
+    /// Absolute limit on a login, from the moment the password was entered;
+    /// neither sliding renewal nor `/refresh` extends a session past it
+    pub max_lifetime_secs: i64,
+}
+
+impl SessionConfig {
+    pub fn new(
+        session_ttl_secs: i64,
+        remember_ttl_secs: i64,
+        sliding_window_secs: i64,
+        max_lifetime_secs: i64,
+    ) -> Self {
+        Self {
+            session_ttl_secs,
+            remember_ttl_secs,
+            sliding_window_secs,
+            max_lifetime_secs,
+        }
+    }
+
+    /// Lifetime of a refresh token issued with or without "remember me"
+    pub fn refresh_ttl_secs(&self, remember: bool) -> i64 {
+        if remember {
+            self.remember_ttl_secs
+        } else {
+            self.session_ttl_secs
+        }
+    }
+
+    /// When a login made now must end, however active it stays
+    pub fn session_end(&self) -> DateTime<Utc> {
+        Utc::now() + chrono::Duration::seconds(self.max_lifetime_secs)
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self::new(
+            SESSION_TTL_SECS,
+            REMEMBER_ME_TTL_SECS,
+            SLIDING_WINDOW_SECS,
+            MAX_SESSION_LIFETIME_SECS,
+        )
+    }
+}
+
+/// Sign a JWT access token for a user, for a login ending at `session_exp`
+///
+/// The token lives `ACCESS_TOKEN_TTL_SECS`, or less when the login ends first.
+pub fn create_access_token(
+    keys: &JwtKeys,
+    user_id: i64,
+    username: &str,
+    token_version: i32,
+    role: Role,
+    session_exp: i64,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let now = chrono::Utc::now().timestamp();
+    let claims = Claims {
+        sub: user_id,
+        username: username.to_string(),
+        exp: (now + ACCESS_TOKEN_TTL_SECS).min(session_exp),
+        iat: now,
+        token_version,
+        role,
+        session_exp,
+    };
+
+    keys.sign(&claims)
+}
+
+/// Generate a fresh random opaque token (refresh tokens, verification links)
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Hash an opaque token for storage; only the hash is kept in the database
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Read the refresh token from the request cookies
+pub fn refresh_token_from_cookies(cookies: &Cookies) -> Option<String> {
+    cookies.get(REFRESH_COOKIE).map(|c| c.value().to_string())
+}
+
+/// `Set-Cookie` value for an access token
+pub fn access_cookie(config: &CookieConfig, token: &str) -> String {
+    config.header(ACCESS_COOKIE, token, Some(ACCESS_TOKEN_TTL_SECS))
+}
+
+/// `Set-Cookie` value for a refresh token; `max_age` must match the stored
+/// token's expiry, or be `None` for a cookie that ends with the browser session
+pub fn refresh_cookie(config: &CookieConfig, token: &str, max_age: Option<i64>) -> String {
+    config.header(REFRESH_COOKIE, token, max_age)
+}
+
+/// `Set-Cookie` values removing both session cookies
+pub fn clear_session_cookies(config: &CookieConfig) -> [String; 2] {
+    [
+        config.removal(ACCESS_COOKIE),
+        config.removal(REFRESH_COOKIE),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::test_jwt_keys;
+    use jsonwebtoken::{decode, DecodingKey, Validation};
+
+    fn claims_of(token: &str) -> Claims {
+        decode::<Claims>(
+            token,
+            &DecodingKey::from_secret(b"secret"),
+            &Validation::default(),
+        )
+        .expect("decode")
+        .claims
+    }
+
+    #[test]
+    fn access_token_expires_after_ttl() {
+        let session_exp = chrono::Utc::now().timestamp() + 3600;
+        let token = create_access_token(
+            &test_jwt_keys("secret"),
+            7,
+            "alice",
+            3,
+            Role::Admin,
+            session_exp,
+        )
+        .expect("encode");
+        let claims = claims_of(&token);
+
+        assert_eq!(claims.sub, 7);
+        assert_eq!(claims.token_version, 3);
+        assert_eq!(claims.role, Role::Admin);
+        assert_eq!(claims.exp - claims.iat, ACCESS_TOKEN_TTL_SECS);
+        assert_eq!(claims.session_exp, session_exp);
+    }
+
+    #[test]
+    fn access_token_never_outlives_the_session() {
+        let session_exp = chrono::Utc::now().timestamp() + 60;
+        let token = create_access_token(
+            &test_jwt_keys("secret"),
+            7,
+            "alice",
+            0,
+            Role::User,
+            session_exp,
+        )
+        .expect("encode");
+        assert_eq!(claims_of(&token).exp, session_exp);
+    }
+
+    #[test]
+    fn remember_me_selects_the_longer_lifetime() {
+        let config = SessionConfig::new(60, 3600, 30, 7200);
+        assert_eq!(config.refresh_ttl_secs(false), 60);
+        assert_eq!(config.refresh_ttl_secs(true), 3600);
+    }
+
+    #[test]
+    fn tokens_are_unique_and_hashed() {
+        let first = generate_token();
+        let second = generate_token();
+        assert_ne!(first, second);
+
+        let hash = hash_token(&first);
+        assert_ne!(hash, first);
+        assert_eq!(hash, hash_token(&first));
+    }
+}
@@ -0,0 +1,116 @@
+use std::path::PathBuf;
+
+/// Default per-file upload limit: 5 MiB
+pub const DEFAULT_MAX_UPLOAD_BYTES: usize = 5 * 1024 * 1024;
+
+/// Content types accepted when `UPLOAD_ALLOWED_TYPES` is not set
+pub const DEFAULT_ALLOWED_TYPES: &[&str] = &[
+    "image/png",
+    "image/jpeg",
+    "image/gif",
+    "image/webp",
+    "application/pdf",
+    "text/plain",
+];
+
+/// Where item attachments are stored and what may be uploaded
+#[derive(Debug, Clone)]
+pub struct UploadConfig {
+    /// Directory holding the stored files
+    pub dir: PathBuf,
+    /// Largest accepted file, in bytes
+    pub max_bytes: usize,
+    /// Lowercase MIME types without parameters, e.g. `image/png`
+    pub allowed_types: Vec<String>,
+}
+
+impl UploadConfig {
+    pub fn new(dir: impl Into<PathBuf>, max_bytes: usize, allowed_types: &[&str]) -> Self {
+        Self {
+            dir: dir.into(),
+            max_bytes,
+            allowed_types: allowed_types
+                .iter()
+                .map(|t| t.trim().to_ascii_lowercase())
+                .filter(|t| !t.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Whether a submitted `Content-Type` is on the allowlist
+    pub fn allows(&self, content_type: &str) -> bool {
+        self.allowed_types.contains(&mime_essence(content_type))
+    }
+
+    /// Largest multipart body accepted: one file plus room for the other
+    /// form fields and part headers
+    pub fn max_body_bytes(&self) -> usize {
+        self.max_bytes.saturating_add(64 * 1024)
+    }
+
+    /// Write `bytes` under a fresh random name and return that name
+    pub async fn store(&self, bytes: &[u8]) -> std::io::Result<String> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let stored_name = uuid::Uuid::new_v4().to_string();
+        tokio::fs::write(self.path_for(&stored_name), bytes).await?;
+        Ok(stored_name)
+    }
+
+    /// Read a stored file back
+    pub async fn read(&self, stored_name: &str) -> std::io::Result<Vec<u8>> {
+        tokio::fs::read(self.path_for(stored_name)).await
+    }
+
+    /// Delete a stored file; a file that is already gone is not an error
+    pub async fn remove(&self, stored_name: &str) -> std::io::Result<()> {
+        match tokio::fs::remove_file(self.path_for(stored_name)).await {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    // Stored names are always generated UUIDs, never user input
+    fn path_for(&self, stored_name: &str) -> PathBuf {
+        self.dir.join(stored_name)
+    }
+}
+
+/// Lowercase MIME type without parameters such as `; charset=utf-8`
+pub fn mime_essence(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allowlist_ignores_case_and_parameters() {
+        let config = UploadConfig::new("uploads", 1024, &["image/png", " Text/Plain "]);
+        assert!(config.allows("image/png"));
+        assert!(config.allows("text/plain; charset=utf-8"));
+        assert!(config.allows("IMAGE/PNG"));
+        assert!(!config.allows("text/html"));
+        assert!(!config.allows(""));
+    }
+
+    #[tokio::test]
+    async fn store_writes_under_a_generated_name() {
+        let dir =
+            std::env::temp_dir().join(format!("basic_crud_ops_uploads_{}", uuid::Uuid::new_v4()));
+        let config = UploadConfig::new(&dir, 1024, DEFAULT_ALLOWED_TYPES);
+
+        let name = config.store(b"hello").await.expect("store");
+        assert!(uuid::Uuid::parse_str(&name).is_ok());
+        assert_eq!(config.read(&name).await.expect("read"), b"hello");
+
+        config.remove(&name).await.expect("remove");
+        config.remove(&name).await.expect("remove twice");
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
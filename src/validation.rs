@@ -0,0 +1,70 @@
+use rustapi_validate::v2::ValidationErrors;
+use std::collections::HashMap;
+
+use crate::{models::ItemForm, AppState};
+
+/// Summary shown above a form whose fields carry their own messages
+pub const INVALID_FORM_MESSAGE: &str = "Please correct the highlighted fields";
+
+/// Validation messages keyed by field name; templates read them as
+/// `errors.<field>`
+pub type FieldErrors = HashMap<String, Vec<String>>;
+
+/// Collect the message of every failed rule, per field
+///
+/// Messages are the ones given in the `#[validate(...)]` attributes, with
+/// rule parameters such as `{max}` filled in.
+pub fn field_errors(errors: &ValidationErrors) -> FieldErrors {
+    errors
+        .fields
+        .iter()
+        .map(|(field, rules)| {
+            let messages = rules
+                .iter()
+                .map(|rule| rule.interpolate_message())
+                .collect();
+            (field.clone(), messages)
+        })
+        .collect()
+}
+
+/// Validation that also depends on the app's runtime configuration, such as
+/// the item length limits
+///
+/// `ValidatedForm` runs it in place of plain `Validate::validate`.
+pub trait ValidateConfigured {
+    fn validate_configured(&self, state: &AppState) -> Result<(), ValidationErrors>;
+}
+
+impl ValidateConfigured for ItemForm {
+    fn validate_configured(&self, state: &AppState) -> Result<(), ValidationErrors> {
+        self.validate_with(&state.config.item_limits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustapi_rs::prelude::*;
+
+    #[test]
+    fn empty_title_maps_to_its_message() {
+        let form = ItemForm {
+            title: String::new(),
+            description: None,
+            tags: "x".repeat(501),
+            due_at: None,
+        };
+        let errors = field_errors(&form.validate().expect_err("invalid form"));
+
+        assert_eq!(
+            errors.get("title"),
+            Some(&vec!["Title is required".to_string()])
+        );
+        assert_eq!(
+            errors.get("tags"),
+            Some(&vec!["Tags must be 500 characters or less".to_string()])
+        );
+        assert!(!errors.contains_key("description"));
+    }
+}
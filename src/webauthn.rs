@@ -0,0 +1,447 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::{
+    rand::{SecureRandom, SystemRandom},
+    signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1},
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::models::{PasskeyAssertion, PasskeyRegistration};
+
+/// Relying party ID when `WEBAUTHN_RP_ID` is unset; passkeys only work on
+/// this domain and its subdomains
+pub const DEFAULT_RP_ID: &str = "localhost";
+/// Origin pages run on when `WEBAUTHN_ORIGIN` is unset
+pub const DEFAULT_ORIGIN: &str = "http://localhost:8080";
+/// Name authenticators show next to the account
+pub const RP_NAME: &str = "CRUD App";
+/// COSE identifier of ES256, the only algorithm accepted
+pub const ES256: i64 = -7;
+/// How long a ceremony may take from start to finish
+pub const CHALLENGE_TTL: Duration = Duration::from_secs(300);
+
+// Ceremonies in flight at once; login challenges are handed to anyone, so
+// their number is bounded
+const MAX_PENDING: usize = 10_000;
+const CHALLENGE_BYTES: usize = 32;
+// DER header of a P-256 SubjectPublicKeyInfo; the uncompressed point follows
+const P256_SPKI_PREFIX: [u8; 26] = [
+    0x30, 0x59, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01, 0x06, 0x08, 0x2a,
+    0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x03, 0x42, 0x00,
+];
+// Authenticator data flags: user present, attested credential data included
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_ATTESTED: u8 = 0x40;
+
+/// What a challenge was issued for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ceremony {
+    /// Adding a passkey to the account of `user_id`
+    Register { user_id: i64 },
+    /// Logging in with any passkey
+    Login,
+}
+
+/// Why a passkey response was not accepted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebAuthnError {
+    /// Not valid base64url, client data or authenticator data
+    Malformed,
+    /// Unknown, expired, already used or issued for another ceremony
+    Challenge,
+    /// Signed on a page of another origin
+    Origin,
+    /// Created for another relying party ID
+    RelyingParty,
+    /// The authenticator did not confirm someone was present
+    UserPresence,
+    /// A key other than P-256 with ES256
+    Algorithm,
+    Signature,
+}
+
+impl WebAuthnError {
+    /// Error code of the JSON response
+    pub fn code(self) -> &'static str {
+        match self {
+            WebAuthnError::Malformed => "malformed_passkey",
+            WebAuthnError::Challenge => "invalid_challenge",
+            WebAuthnError::Origin => "invalid_origin",
+            WebAuthnError::RelyingParty => "invalid_rp_id",
+            WebAuthnError::UserPresence => "user_not_present",
+            WebAuthnError::Algorithm => "unsupported_algorithm",
+            WebAuthnError::Signature => "invalid_signature",
+        }
+    }
+}
+
+/// A verified new passkey, ready to be stored
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewPasskey {
+    pub credential_id: String,
+    /// Uncompressed P-256 point, base64url-encoded
+    pub public_key: String,
+    pub sign_count: i64,
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+}
+
+struct AuthenticatorData<'a> {
+    flags: u8,
+    sign_count: i64,
+    // Attested credential data and extensions, when present
+    rest: &'a [u8],
+}
+
+struct Pending {
+    ceremony: Ceremony,
+    issued_at: Instant,
+}
+
+/// Passkey ceremonies: hands out challenges and checks what authenticators
+/// answer them with
+///
+/// Challenges live in memory until they are answered or `CHALLENGE_TTL`
+/// has passed, and each is accepted once. The browser only lets pages of
+/// `origin` use passkeys of `rp_id`, and the origin it reports is signed
+/// along with the challenge, so a response cannot be obtained by a page of
+/// another site.
+pub struct WebAuthn {
+    rp_id: String,
+    origin: String,
+    ttl: Duration,
+    rng: SystemRandom,
+    pending: Mutex<HashMap<String, Pending>>,
+}
+
+impl WebAuthn {
+    pub fn new(rp_id: &str, origin: &str) -> Self {
+        Self {
+            rp_id: rp_id.to_string(),
+            origin: origin.trim_end_matches('/').to_string(),
+            ttl: CHALLENGE_TTL,
+            rng: SystemRandom::new(),
+            pending: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn rp_id(&self) -> &str {
+        &self.rp_id
+    }
+
+    /// A new base64url challenge for `ceremony`, or `None` while too many
+    /// are waiting for an answer
+    pub fn challenge(&self, ceremony: Ceremony) -> Option<String> {
+        let mut bytes = [0u8; CHALLENGE_BYTES];
+        self.rng.fill(&mut bytes).ok()?;
+        let challenge = URL_SAFE_NO_PAD.encode(bytes);
+
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        pending.retain(|_, entry| now.duration_since(entry.issued_at) < self.ttl);
+        if pending.len() >= MAX_PENDING {
+            return None;
+        }
+        pending.insert(
+            challenge.clone(),
+            Pending {
+                ceremony,
+                issued_at: now,
+            },
+        );
+        Some(challenge)
+    }
+
+    /// Check the response to a registration challenge issued for `user_id`
+    ///
+    /// Attestation is not asked for, so only the key and the credential ID
+    /// are taken from the response.
+    pub fn verify_registration(
+        &self,
+        user_id: i64,
+        registration: &PasskeyRegistration,
+    ) -> Result<NewPasskey, WebAuthnError> {
+        let client_data = decode(&registration.client_data)?;
+        self.check_client_data(
+            &client_data,
+            "webauthn.create",
+            Ceremony::Register { user_id },
+        )?;
+
+        let authenticator_data = decode(&registration.authenticator_data)?;
+        let data = self.check_authenticator_data(&authenticator_data)?;
+        if data.flags & FLAG_ATTESTED == 0 {
+            return Err(WebAuthnError::Malformed);
+        }
+        // AAGUID, then the length of the credential ID and the ID itself
+        let id_len = data
+            .rest
+            .get(16..18)
+            .map(|len| usize::from(u16::from_be_bytes([len[0], len[1]])))
+            .ok_or(WebAuthnError::Malformed)?;
+        let credential_id = data
+            .rest
+            .get(18..18 + id_len)
+            .ok_or(WebAuthnError::Malformed)?;
+        if decode(&registration.credential_id)? != credential_id {
+            return Err(WebAuthnError::Malformed);
+        }
+
+        if registration.public_key_algorithm != ES256 {
+            return Err(WebAuthnError::Algorithm);
+        }
+        let public_key = decode(&registration.public_key)?;
+        let point = public_key
+            .strip_prefix(P256_SPKI_PREFIX.as_slice())
+            .filter(|point| point.len() == 65 && point[0] == 0x04)
+            .ok_or(WebAuthnError::Algorithm)?;
+
+        Ok(NewPasskey {
+            credential_id: URL_SAFE_NO_PAD.encode(credential_id),
+            public_key: URL_SAFE_NO_PAD.encode(point),
+            sign_count: data.sign_count,
+        })
+    }
+
+    /// Check the response to a login challenge against the stored
+    /// `public_key` of the passkey it names, returning its new signature
+    /// counter
+    ///
+    /// Whether the counter went up is for the caller to compare with the
+    /// stored one.
+    pub fn verify_login(
+        &self,
+        assertion: &PasskeyAssertion,
+        public_key: &str,
+    ) -> Result<i64, WebAuthnError> {
+        let client_data = decode(&assertion.client_data)?;
+        self.check_client_data(&client_data, "webauthn.get", Ceremony::Login)?;
+
+        let authenticator_data = decode(&assertion.authenticator_data)?;
+        let data = self.check_authenticator_data(&authenticator_data)?;
+
+        // The authenticator signs its data followed by the client data hash
+        let mut signed = authenticator_data.clone();
+        signed.extend_from_slice(&Sha256::digest(&client_data));
+        let point = decode(public_key)?;
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, point)
+            .verify(&signed, &decode(&assertion.signature)?)
+            .map_err(|_| WebAuthnError::Signature)?;
+
+        Ok(data.sign_count)
+    }
+
+    // The challenge is used up by the first response naming it, whether or
+    // not the rest of that response holds up
+    fn check_client_data(
+        &self,
+        client_data: &[u8],
+        kind: &str,
+        ceremony: Ceremony,
+    ) -> Result<(), WebAuthnError> {
+        let client_data: ClientData =
+            serde_json::from_slice(client_data).map_err(|_| WebAuthnError::Malformed)?;
+        if client_data.kind != kind {
+            return Err(WebAuthnError::Malformed);
+        }
+        if self.take(&client_data.challenge) != Some(ceremony) {
+            return Err(WebAuthnError::Challenge);
+        }
+        if client_data.origin != self.origin {
+            return Err(WebAuthnError::Origin);
+        }
+        Ok(())
+    }
+
+    fn check_authenticator_data<'a>(
+        &self,
+        data: &'a [u8],
+    ) -> Result<AuthenticatorData<'a>, WebAuthnError> {
+        if data.len() < 37 {
+            return Err(WebAuthnError::Malformed);
+        }
+        if data[..32] != Sha256::digest(self.rp_id.as_bytes())[..] {
+            return Err(WebAuthnError::RelyingParty);
+        }
+        let flags = data[32];
+        if flags & FLAG_USER_PRESENT == 0 {
+            return Err(WebAuthnError::UserPresence);
+        }
+        Ok(AuthenticatorData {
+            flags,
+            sign_count: i64::from(u32::from_be_bytes([data[33], data[34], data[35], data[36]])),
+            rest: &data[37..],
+        })
+    }
+
+    fn take(&self, challenge: &str) -> Option<Ceremony> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        pending
+            .remove(challenge)
+            .filter(|entry| entry.issued_at.elapsed() < self.ttl)
+            .map(|entry| entry.ceremony)
+    }
+}
+
+/// `user.id` of the creation options: the account's ID, base64url-encoded
+///
+/// Authenticators store it with the passkey; it must not carry anything
+/// personal, such as the username.
+pub fn user_handle(user_id: i64) -> String {
+    URL_SAFE_NO_PAD.encode(user_id.to_string())
+}
+
+fn decode(value: &str) -> Result<Vec<u8>, WebAuthnError> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| WebAuthnError::Malformed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TestAuthenticator;
+
+    const ORIGIN: &str = "https://app.example";
+
+    fn webauthn() -> WebAuthn {
+        WebAuthn::new("app.example", ORIGIN)
+    }
+
+    #[test]
+    fn registration_takes_the_key_and_id_from_the_authenticator() {
+        let webauthn = webauthn();
+        let authenticator = TestAuthenticator::new("app.example");
+        let challenge = webauthn
+            .challenge(Ceremony::Register { user_id: 1 })
+            .expect("challenge");
+
+        let passkey = webauthn
+            .verify_registration(1, &authenticator.register(&challenge, ORIGIN))
+            .expect("registered");
+        assert_eq!(passkey.credential_id, authenticator.credential_id());
+        assert_eq!(passkey.public_key, authenticator.public_key());
+        assert_eq!(passkey.sign_count, 0);
+
+        // Each challenge is answered once
+        assert_eq!(
+            webauthn.verify_registration(1, &authenticator.register(&challenge, ORIGIN)),
+            Err(WebAuthnError::Challenge)
+        );
+    }
+
+    #[test]
+    fn registration_challenges_belong_to_one_user() {
+        let webauthn = webauthn();
+        let authenticator = TestAuthenticator::new("app.example");
+
+        let challenge = webauthn
+            .challenge(Ceremony::Register { user_id: 1 })
+            .expect("challenge");
+        assert_eq!(
+            webauthn.verify_registration(2, &authenticator.register(&challenge, ORIGIN)),
+            Err(WebAuthnError::Challenge)
+        );
+
+        // Nor do login challenges register anything
+        let challenge = webauthn.challenge(Ceremony::Login).expect("challenge");
+        assert_eq!(
+            webauthn.verify_registration(1, &authenticator.register(&challenge, ORIGIN)),
+            Err(WebAuthnError::Challenge)
+        );
+    }
+
+    #[test]
+    fn responses_from_elsewhere_are_refused() {
+        let webauthn = webauthn();
+        let register = || {
+            webauthn
+                .challenge(Ceremony::Register { user_id: 1 })
+                .expect("challenge")
+        };
+
+        let authenticator = TestAuthenticator::new("app.example");
+        assert_eq!(
+            webauthn.verify_registration(
+                1,
+                &authenticator.register(&register(), "https://evil.example")
+            ),
+            Err(WebAuthnError::Origin)
+        );
+        let other_rp = TestAuthenticator::new("evil.example");
+        assert_eq!(
+            webauthn.verify_registration(1, &other_rp.register(&register(), ORIGIN)),
+            Err(WebAuthnError::RelyingParty)
+        );
+
+        let mut registration = authenticator.register(&register(), ORIGIN);
+        registration.public_key_algorithm = -257;
+        assert_eq!(
+            webauthn.verify_registration(1, &registration),
+            Err(WebAuthnError::Algorithm)
+        );
+    }
+
+    #[test]
+    fn login_checks_the_signature_against_the_stored_key() {
+        let webauthn = webauthn();
+        let mut authenticator = TestAuthenticator::new("app.example");
+        let public_key = authenticator.public_key();
+
+        let challenge = webauthn.challenge(Ceremony::Login).expect("challenge");
+        let assertion = authenticator.login(&challenge, ORIGIN);
+        assert_eq!(webauthn.verify_login(&assertion, &public_key), Ok(1));
+
+        let challenge = webauthn.challenge(Ceremony::Login).expect("challenge");
+        let assertion = authenticator.login(&challenge, ORIGIN);
+        let other_key = TestAuthenticator::new("app.example").public_key();
+        assert_eq!(
+            webauthn.verify_login(&assertion, &other_key),
+            Err(WebAuthnError::Signature)
+        );
+
+        // Tampering with the signed data breaks the signature too
+        let challenge = webauthn.challenge(Ceremony::Login).expect("challenge");
+        let mut assertion = authenticator.login(&challenge, ORIGIN);
+        let mut data = decode(&assertion.authenticator_data).expect("data");
+        data[36] = data[36].wrapping_add(1);
+        assertion.authenticator_data = URL_SAFE_NO_PAD.encode(data);
+        assert_eq!(
+            webauthn.verify_login(&assertion, &public_key),
+            Err(WebAuthnError::Signature)
+        );
+    }
+
+    #[test]
+    fn challenges_expire() {
+        let mut webauthn = webauthn();
+        webauthn.ttl = Duration::ZERO;
+        let mut authenticator = TestAuthenticator::new("app.example");
+
+        let challenge = webauthn.challenge(Ceremony::Login).expect("challenge");
+        assert_eq!(
+            webauthn.verify_login(
+                &authenticator.login(&challenge, ORIGIN),
+                &authenticator.public_key()
+            ),
+            Err(WebAuthnError::Challenge)
+        );
+        assert!(webauthn
+            .pending
+            .lock()
+            .expect("lock")
+            .get(&challenge)
+            .is_none());
+    }
+}
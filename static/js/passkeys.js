@@ -0,0 +1,90 @@
+// Passkey registration and login through the /webauthn endpoints; binary
+// values travel as base64url
+(function () {
+    function toBytes(value) {
+        var base64 = value.replace(/-/g, "+").replace(/_/g, "/");
+        var binary = atob(base64 + "===".slice((base64.length + 3) % 4));
+        return Uint8Array.from(binary, function (c) { return c.charCodeAt(0); });
+    }
+
+    function toBase64url(buffer) {
+        var binary = String.fromCharCode.apply(null, new Uint8Array(buffer));
+        return btoa(binary).replace(/\+/g, "-").replace(/\//g, "_").replace(/=+$/, "");
+    }
+
+    // Set on <body> when the app is served under a path prefix
+    var basePath = document.body.dataset.basePath || "";
+
+    function post(path, body) {
+        return fetch(basePath + path, {
+            method: "POST",
+            credentials: "same-origin",
+            headers: { "Accept": "application/json", "Content-Type": "application/json" },
+            body: JSON.stringify(body || {})
+        }).then(function (response) {
+            return response.json().then(function (json) {
+                if (!response.ok) {
+                    throw new Error(json.error ? json.error.message : "Request failed");
+                }
+                return json;
+            });
+        });
+    }
+
+    function showError(status, error) {
+        status.textContent = error.message || "The passkey could not be used.";
+        status.hidden = false;
+    }
+
+    var register = document.getElementById("passkey-register");
+    if (register) {
+        register.addEventListener("submit", function (event) {
+            event.preventDefault();
+            var status = document.getElementById("passkey-status");
+            var name = register.elements.name.value;
+            post("/webauthn/register/start").then(function (options) {
+                options.challenge = toBytes(options.challenge);
+                options.user.id = toBytes(options.user.id);
+                options.excludeCredentials.forEach(function (credential) {
+                    credential.id = toBytes(credential.id);
+                });
+                return navigator.credentials.create({ publicKey: options });
+            }).then(function (credential) {
+                var response = credential.response;
+                return post("/webauthn/register/finish", {
+                    credential_id: toBase64url(credential.rawId),
+                    client_data: toBase64url(response.clientDataJSON),
+                    authenticator_data: toBase64url(response.getAuthenticatorData()),
+                    public_key: toBase64url(response.getPublicKey()),
+                    public_key_algorithm: response.getPublicKeyAlgorithm(),
+                    name: name
+                });
+            }).then(function () {
+                window.location.reload();
+            }).catch(function (error) { showError(status, error); });
+        });
+    }
+
+    var login = document.getElementById("passkey-login");
+    if (login) {
+        login.addEventListener("click", function () {
+            var status = document.getElementById("passkey-status");
+            var remember = document.querySelector("input[name=remember]");
+            post("/webauthn/login/start").then(function (options) {
+                options.challenge = toBytes(options.challenge);
+                return navigator.credentials.get({ publicKey: options });
+            }).then(function (credential) {
+                var response = credential.response;
+                return post("/webauthn/login/finish", {
+                    credential_id: toBase64url(credential.rawId),
+                    client_data: toBase64url(response.clientDataJSON),
+                    authenticator_data: toBase64url(response.authenticatorData),
+                    signature: toBase64url(response.signature),
+                    remember: Boolean(remember && remember.checked)
+                });
+            }).then(function (result) {
+                window.location.href = result.redirect;
+            }).catch(function (error) { showError(status, error); });
+        });
+    }
+})();
@@ -0,0 +1,57 @@
+// Drag-and-drop ordering of the item list; dropping a card saves the new
+// order through the hidden reorder form
+(function () {
+    var grid = document.querySelector(".items-grid[data-reorder]");
+    var form = document.getElementById("reorder-form");
+    if (!grid || !form) {
+        return;
+    }
+    var dragged = null;
+    var before = "";
+
+    function order() {
+        return Array.prototype.map.call(grid.querySelectorAll(".item-card[data-id]"), function (card) {
+            return card.dataset.id;
+        });
+    }
+
+    grid.addEventListener("dragstart", function (event) {
+        dragged = event.target.closest(".item-card");
+        if (dragged) {
+            before = order().join(",");
+            dragged.classList.add("dragging");
+            event.dataTransfer.effectAllowed = "move";
+        }
+    });
+
+    grid.addEventListener("dragover", function (event) {
+        var target = event.target.closest(".item-card");
+        if (!dragged || !target || target === dragged) {
+            return;
+        }
+        event.preventDefault();
+        var box = target.getBoundingClientRect();
+        var after = event.clientY > box.top + box.height / 2;
+        grid.insertBefore(dragged, after ? target.nextSibling : target);
+    });
+
+    grid.addEventListener("dragend", function () {
+        if (!dragged) {
+            return;
+        }
+        dragged.classList.remove("dragging");
+        dragged = null;
+        var ids = order();
+        if (ids.join(",") === before) {
+            return;
+        }
+        ids.forEach(function (id) {
+            var input = document.createElement("input");
+            input.type = "hidden";
+            input.name = "ids";
+            input.value = id;
+            form.appendChild(input);
+        });
+        form.submit();
+    });
+})();